@@ -468,7 +468,7 @@ export function showShareWebhookModal(webhookId: string, webhookName: string): P
           const response = await fetch(`/api/webhooks/${webhookId}/share`, {
             method: 'POST',
             headers: { 'Content-Type': 'application/json' },
-            body: JSON.stringify({ email: trimmedEmail, role: 'collaborator' })
+            body: JSON.stringify({ email: trimmedEmail, role: 'editor' })
           })
 
           if (!response.ok) {
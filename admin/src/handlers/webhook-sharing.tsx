@@ -27,14 +27,14 @@ export async function shareWebhook(c: AppContext) {
     }
 
     const body = await c.req.json()
-    const { email, role = 'collaborator' } = body
+    const { email, role = 'viewer' } = body
 
     if (!email || email.trim().length === 0) {
       return c.json({ error: 'Email is required' }, 400)
     }
 
-    if (role !== 'owner' && role !== 'collaborator') {
-      return c.json({ error: 'Role must be either "owner" or "collaborator"' }, 400)
+    if (!['viewer', 'editor', 'owner'].includes(role)) {
+      return c.json({ error: 'Role must be "viewer", "editor" or "owner"' }, 400)
     }
 
     const db = drizzle(c.env.DB)
@@ -30,7 +30,7 @@ export interface Collaborator {
   id: string
   sharedWithEmail: string
   sharedWithUserId?: string | null
-  role: 'owner' | 'collaborator'
+  role: 'viewer' | 'editor' | 'owner'
   invitedAt: Date | string
   acceptedAt?: Date | string | null
 }
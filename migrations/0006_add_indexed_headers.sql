@@ -0,0 +1,15 @@
+-- Migration: Add indexed header columns to webhook_data
+-- High-value headers are stored in dedicated columns next to the headers JSON blob
+-- so filtered queries don't need JSON parsing in SQL
+
+ALTER TABLE webhook_data ADD COLUMN content_type TEXT;
+ALTER TABLE webhook_data ADD COLUMN user_agent TEXT;
+ALTER TABLE webhook_data ADD COLUMN signature TEXT;
+ALTER TABLE webhook_data ADD COLUMN idempotency_key TEXT;
+ALTER TABLE webhook_data ADD COLUMN event_type TEXT;
+
+CREATE INDEX webhook_data_content_type_idx ON webhook_data(webhook_id, content_type);
+CREATE INDEX webhook_data_user_agent_idx ON webhook_data(webhook_id, user_agent);
+CREATE INDEX webhook_data_signature_idx ON webhook_data(webhook_id, signature);
+CREATE INDEX webhook_data_idempotency_key_idx ON webhook_data(webhook_id, idempotency_key);
+CREATE INDEX webhook_data_event_type_idx ON webhook_data(webhook_id, event_type);
@@ -0,0 +1,7 @@
+-- Migration: Add per-webhook sequence numbers to webhook_data
+-- Assigned by the webhook worker's WebhookSequence Durable Object so bursts
+-- within the same second can be ordered and gaps detected
+
+ALTER TABLE webhook_data ADD COLUMN sequence INTEGER;
+
+CREATE INDEX webhook_data_sequence_idx ON webhook_data(webhook_id, sequence);
@@ -0,0 +1,9 @@
+-- Migration: Add millisecond receive time and client-supplied event time
+-- received_at stays in Unix seconds for existing readers; received_at_ms orders bursts
+-- event_time comes from provider timestamp headers or the Date header (Unix ms)
+
+ALTER TABLE webhook_data ADD COLUMN received_at_ms INTEGER;
+ALTER TABLE webhook_data ADD COLUMN event_time INTEGER;
+
+CREATE INDEX webhook_data_received_at_ms_idx ON webhook_data(webhook_id, received_at_ms);
+CREATE INDEX webhook_data_event_time_idx ON webhook_data(webhook_id, event_time);
@@ -0,0 +1,8 @@
+-- Migration: Add replication heartbeat table
+-- Single row refreshed on the primary by the webhook worker's /health route;
+-- comparing it with a read replica's copy estimates replication lag
+
+CREATE TABLE replication_heartbeat (
+  id INTEGER PRIMARY KEY,
+  written_at_ms INTEGER NOT NULL
+);
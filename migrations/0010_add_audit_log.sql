@@ -0,0 +1,18 @@
+-- Migration: Add audit log of management actions
+-- Written by the webhook worker's management API (actor, IP, before/after snapshots)
+
+CREATE TABLE audit_log (
+  id TEXT PRIMARY KEY,
+  created_at_ms INTEGER NOT NULL,
+  actor TEXT NOT NULL,
+  ip TEXT,
+  action TEXT NOT NULL,
+  target TEXT,
+  before TEXT,
+  after TEXT
+);
+
+CREATE INDEX audit_log_created_idx ON audit_log(created_at_ms);
+CREATE INDEX audit_log_action_idx ON audit_log(action, created_at_ms);
+CREATE INDEX audit_log_actor_idx ON audit_log(actor, created_at_ms);
+CREATE INDEX audit_log_target_idx ON audit_log(target, created_at_ms);
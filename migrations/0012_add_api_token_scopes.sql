@@ -0,0 +1,11 @@
+-- Migration: Add scopes, expiry, usage tracking and rotation to API tokens
+-- scopes: space-separated (ingest:read, webhooks:write, admin); NULL allows everything the role does
+-- previous_token_hash stays valid until previous_expires_at_ms after a rotation
+
+ALTER TABLE api_tokens ADD COLUMN scopes TEXT;
+ALTER TABLE api_tokens ADD COLUMN expires_at_ms INTEGER;
+ALTER TABLE api_tokens ADD COLUMN last_used_at_ms INTEGER;
+ALTER TABLE api_tokens ADD COLUMN previous_token_hash TEXT;
+ALTER TABLE api_tokens ADD COLUMN previous_expires_at_ms INTEGER;
+
+CREATE INDEX api_tokens_previous_hash_idx ON api_tokens(previous_token_hash);
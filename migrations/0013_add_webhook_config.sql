@@ -0,0 +1,8 @@
+-- Migration: Add per-webhook configuration
+-- config: JSON document managed by the webhook worker API (NULL = defaults)
+-- config_version: incremented on every config write (ETag / If-Match)
+-- secret: webhook secret for signed capture URLs (kept out of the config document)
+
+ALTER TABLE webhooks ADD COLUMN config TEXT;
+ALTER TABLE webhooks ADD COLUMN config_version INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE webhooks ADD COLUMN secret TEXT;
@@ -0,0 +1,26 @@
+-- Migration: Add UUID enumeration detection
+-- enumeration_misses: requests to unknown UUIDs per client IP (pruned after 7 days)
+-- abuse_scanners: IPs flagged for scanning or for hitting a decoy UUID
+
+CREATE TABLE enumeration_misses (
+  ip TEXT NOT NULL,
+  uuid TEXT NOT NULL,
+  seen_at_ms INTEGER NOT NULL,
+  PRIMARY KEY (ip, uuid)
+);
+
+CREATE INDEX enumeration_misses_ip_seen_idx ON enumeration_misses(ip, seen_at_ms);
+CREATE INDEX enumeration_misses_seen_idx ON enumeration_misses(seen_at_ms);
+
+CREATE TABLE abuse_scanners (
+  ip TEXT PRIMARY KEY,
+  first_seen_ms INTEGER NOT NULL,
+  last_seen_ms INTEGER NOT NULL,
+  distinct_uuids INTEGER NOT NULL,
+  decoy_hits INTEGER NOT NULL DEFAULT 0,
+  last_uuid TEXT,
+  user_agent TEXT,
+  flagged_at_ms INTEGER NOT NULL
+);
+
+CREATE INDEX abuse_scanners_last_seen_idx ON abuse_scanners(last_seen_ms);
@@ -0,0 +1,6 @@
+-- Migration: Add signature verification outcome to captured requests
+-- valid, missing, invalid or replay_suspected (NULL when the webhook does not verify signatures)
+
+ALTER TABLE webhook_data ADD COLUMN verification TEXT;
+
+CREATE INDEX webhook_data_verification_idx ON webhook_data(webhook_id, verification);
@@ -0,0 +1,10 @@
+-- Migration: Add inbox consumption state to captured requests
+-- read_at_ms: first fetched by an inbox consumer (NULL = unread)
+-- acked_at_ms: acknowledged, never handed out again
+-- lease_until_ms: hidden from other fetches until this time
+
+ALTER TABLE webhook_data ADD COLUMN read_at_ms INTEGER;
+ALTER TABLE webhook_data ADD COLUMN acked_at_ms INTEGER;
+ALTER TABLE webhook_data ADD COLUMN lease_until_ms INTEGER;
+
+CREATE INDEX webhook_data_inbox_idx ON webhook_data(webhook_id, acked_at_ms, lease_until_ms);
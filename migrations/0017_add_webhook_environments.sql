@@ -0,0 +1,21 @@
+-- Migration: Add named environments per webhook (dev/staging/prod)
+-- Each environment has its own capture UUID and optional forwarding target;
+-- captures are stored under the parent webhook so config and stats are shared.
+
+CREATE TABLE webhook_environments (
+  id TEXT PRIMARY KEY,
+  webhook_id TEXT NOT NULL,
+  name TEXT NOT NULL,
+  uuid TEXT NOT NULL UNIQUE,
+  forward_url TEXT,
+  created_at_ms INTEGER NOT NULL,
+  FOREIGN KEY (webhook_id) REFERENCES webhooks(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX webhook_environment_name_idx ON webhook_environments(webhook_id, name);
+CREATE INDEX webhook_environment_uuid_idx ON webhook_environments(uuid);
+
+-- Environment a capture arrived through (NULL = the webhook's own UUID)
+ALTER TABLE webhook_data ADD COLUMN environment TEXT;
+
+CREATE INDEX webhook_data_environment_idx ON webhook_data(webhook_id, environment);
@@ -0,0 +1,5 @@
+-- Migration: Store trailing metadata separately from headers
+-- JSON object of trailer fields recovered from the delivery (e.g. the gRPC-Web
+-- trailer frame); NULL when the delivery carried none.
+
+ALTER TABLE webhook_data ADD COLUMN trailers TEXT;
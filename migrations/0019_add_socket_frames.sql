@@ -0,0 +1,8 @@
+-- Migration: WebSocket ingestion
+-- Messages captured on /w/{uuid}/ws record the connection they arrived on and
+-- their frame type (text or binary); both are NULL for HTTP captures.
+
+ALTER TABLE webhook_data ADD COLUMN connection_id TEXT;
+ALTER TABLE webhook_data ADD COLUMN frame_type TEXT;
+
+CREATE INDEX webhook_data_connection_idx ON webhook_data(webhook_id, connection_id);
@@ -0,0 +1,11 @@
+-- Migration: Delivery SLA alerts
+-- One row per webhook expectation (config `expectations`) that is currently
+-- violated, keyed by `Expectation::key`; removed when the expectation is met again.
+
+CREATE TABLE expectation_alerts (
+  webhook_id TEXT NOT NULL,
+  expectation TEXT NOT NULL,
+  violated_since_ms INTEGER NOT NULL,
+  observed_count INTEGER NOT NULL,
+  PRIMARY KEY (webhook_id, expectation)
+);
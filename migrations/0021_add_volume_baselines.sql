@@ -0,0 +1,13 @@
+-- Migration: Ingestion volume baselines
+-- Per-webhook EWMA of hourly capture counts (webhook config `anomaly`), the last
+-- hour folded in (Unix seconds, start of hour) and the current anomaly
+-- (`spike`, `drought` or NULL) with when it began.
+
+CREATE TABLE volume_baselines (
+  webhook_id TEXT PRIMARY KEY,
+  ewma REAL NOT NULL,
+  hours INTEGER NOT NULL,
+  last_hour INTEGER NOT NULL,
+  anomaly TEXT,
+  anomaly_since_ms INTEGER
+);
@@ -0,0 +1,17 @@
+-- Migration: Forward target latency histograms
+-- Daily per-target histogram of forwarding attempt durations: one row per
+-- (webhook, target, day, bucket) with the attempt count and how many failed.
+-- `day` is the start of the UTC day (Unix seconds); `bucket` indexes
+-- `latency::BUCKET_BOUNDS_MS`.
+
+CREATE TABLE forward_latency (
+  webhook_id TEXT NOT NULL,
+  target TEXT NOT NULL,
+  day INTEGER NOT NULL,
+  bucket INTEGER NOT NULL,
+  count INTEGER NOT NULL DEFAULT 0,
+  failures INTEGER NOT NULL DEFAULT 0,
+  PRIMARY KEY (webhook_id, target, day, bucket)
+);
+
+CREATE INDEX forward_latency_day_idx ON forward_latency(day);
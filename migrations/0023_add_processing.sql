@@ -0,0 +1,6 @@
+-- Migration: Per-capture processing trail
+-- JSON record of the pipeline stages that ran for a capture (signed URL and
+-- signature checks, extraction sources, hook script effects, matched route,
+-- forwarding targets, response); NULL for captures stored before this.
+
+ALTER TABLE webhook_data ADD COLUMN processing TEXT;
@@ -0,0 +1,21 @@
+-- Migration: Downstream responses to forwarded captures
+-- One row per forwarding attempt: what the target answered (status, headers
+-- as JSON, body truncated to 8 KiB) and how long it took, or the error when
+-- there was no answer. Rows older than 30 days are pruned by the scheduled handler.
+
+CREATE TABLE forward_responses (
+  id TEXT PRIMARY KEY,
+  webhook_id TEXT NOT NULL,
+  capture_id TEXT NOT NULL,
+  target TEXT NOT NULL,
+  status INTEGER,
+  headers TEXT,
+  body TEXT,
+  body_truncated INTEGER NOT NULL DEFAULT 0,
+  duration_ms INTEGER NOT NULL,
+  error TEXT,
+  created_at_ms INTEGER NOT NULL
+);
+
+CREATE INDEX forward_responses_capture_idx ON forward_responses(webhook_id, capture_id);
+CREATE INDEX forward_responses_created_idx ON forward_responses(created_at_ms);
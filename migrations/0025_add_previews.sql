@@ -0,0 +1,6 @@
+-- Migration: Previews of binary payloads
+-- JSON summary of non-text captures (sniffed MIME type, image dimensions, PDF
+-- version, SHA-256, hexdump of the first bytes) so they can be shown without
+-- downloading the blob; NULL for text payloads and captures stored before this.
+
+ALTER TABLE webhook_data ADD COLUMN preview TEXT;
@@ -0,0 +1,7 @@
+-- Migration: Body charset detection
+-- Detected charset of the body (from a byte order mark, the Content-Type
+-- charset parameter or the bytes) and, when transcoding to UTF-8 changed the
+-- bytes, the original body as base64; NULL for captures stored before this.
+
+ALTER TABLE webhook_data ADD COLUMN charset TEXT;
+ALTER TABLE webhook_data ADD COLUMN original_body TEXT;
@@ -0,0 +1,6 @@
+-- Migration: Canonical JSON bodies
+-- JSON object/array bodies minified with recursively sorted keys, so diffs,
+-- dedup hashes and schema inference ignore key order and whitespace; NULL for
+-- non-JSON bodies and captures stored before this.
+
+ALTER TABLE webhook_data ADD COLUMN canonical_data TEXT;
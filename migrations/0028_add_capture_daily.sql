@@ -0,0 +1,13 @@
+-- Migration: Daily capture aggregates for tiered retention
+-- Captures rolled up per (webhook, UTC day, event type) before tiered
+-- retention deletes them; kept forever. `day` is the start of the UTC day
+-- (Unix seconds); `event_type` is '' for captures without one.
+
+CREATE TABLE capture_daily (
+  webhook_id TEXT NOT NULL,
+  day INTEGER NOT NULL,
+  event_type TEXT NOT NULL DEFAULT '',
+  count INTEGER NOT NULL DEFAULT 0,
+  bytes INTEGER NOT NULL DEFAULT 0,
+  PRIMARY KEY (webhook_id, day, event_type)
+);
@@ -0,0 +1,17 @@
+-- Migration: Legal holds
+-- A hold on a webhook (capture_id NULL) or on one capture exempts it from
+-- every cleanup path until released. Rows are never deleted: released holds
+-- keep who placed and released them as the audit trail.
+
+CREATE TABLE legal_holds (
+  id TEXT PRIMARY KEY,
+  webhook_id TEXT NOT NULL,
+  capture_id TEXT,
+  reason TEXT NOT NULL,
+  created_by TEXT NOT NULL,
+  created_at_ms INTEGER NOT NULL,
+  released_by TEXT,
+  released_at_ms INTEGER
+);
+
+CREATE INDEX idx_legal_holds_webhook ON legal_holds(webhook_id, released_at_ms);
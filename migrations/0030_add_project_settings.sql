@@ -0,0 +1,9 @@
+-- Migration: Per-project settings
+-- One row per project (owning user). `jurisdiction` pins the project's
+-- captures to region-specific bindings ('eu', 'us'); NULL keeps the default.
+
+CREATE TABLE project_settings (
+  user_id TEXT PRIMARY KEY,
+  jurisdiction TEXT,
+  updated_at_ms INTEGER NOT NULL
+);
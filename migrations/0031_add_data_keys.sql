@@ -0,0 +1,16 @@
+-- Migration: Envelope encryption data keys
+-- Capture payloads are encrypted with a per-webhook data key; the key is kept
+-- here wrapped (AES-GCM) by master key `master_version`. Rewrapping under a
+-- new master key rewrites `wrapped_key` only. Rows are never deleted.
+
+CREATE TABLE data_keys (
+  id TEXT PRIMARY KEY,
+  webhook_id TEXT NOT NULL,
+  master_version INTEGER NOT NULL,
+  wrapped_key TEXT NOT NULL,
+  created_at_ms INTEGER NOT NULL,
+  rewrapped_at_ms INTEGER
+);
+
+CREATE INDEX idx_data_keys_webhook ON data_keys(webhook_id, created_at_ms);
+CREATE INDEX idx_data_keys_master_version ON data_keys(master_version);
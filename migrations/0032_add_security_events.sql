@@ -0,0 +1,21 @@
+-- Migration: Security events
+-- Abuse of the public ingestion URLs and the management API (signature
+-- failures, scanner blocks, rejected tokens, enumeration) as a stream for
+-- security teams. Rows are pruned by the scheduled handler; `detail` is JSON.
+
+CREATE TABLE security_events (
+  id TEXT PRIMARY KEY,
+  created_at_ms INTEGER NOT NULL,
+  kind TEXT NOT NULL,
+  severity TEXT NOT NULL,
+  ip TEXT,
+  uuid TEXT,
+  webhook_id TEXT,
+  actor TEXT,
+  user_agent TEXT,
+  detail TEXT
+);
+
+CREATE INDEX idx_security_events_created ON security_events(created_at_ms);
+CREATE INDEX idx_security_events_kind ON security_events(kind, created_at_ms);
+CREATE INDEX idx_security_events_ip ON security_events(ip, created_at_ms);
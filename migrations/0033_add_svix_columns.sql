@@ -0,0 +1,9 @@
+-- Migration: Svix delivery columns
+-- Deliveries following the Svix webhook standard carry a message ID that stays
+-- the same across retries and the timestamp the attempt was signed at; both
+-- are NULL for other captures. The message ID index backs redelivery detection.
+
+ALTER TABLE webhook_data ADD COLUMN svix_id TEXT;
+ALTER TABLE webhook_data ADD COLUMN svix_timestamp INTEGER;
+
+CREATE INDEX webhook_data_svix_id_idx ON webhook_data(webhook_id, svix_id);
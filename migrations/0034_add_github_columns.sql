@@ -0,0 +1,14 @@
+-- Migration: GitHub delivery columns
+-- Deliveries with X-GitHub-Event keep the event, delivery GUID, App
+-- installation ID, repository (owner/name) and the installation's account
+-- login (looked up through the GitHub API when GITHUB_APP_TOKEN is set) in
+-- their own columns; all NULL for other captures.
+
+ALTER TABLE webhook_data ADD COLUMN github_event TEXT;
+ALTER TABLE webhook_data ADD COLUMN github_delivery TEXT;
+ALTER TABLE webhook_data ADD COLUMN github_installation_id TEXT;
+ALTER TABLE webhook_data ADD COLUMN github_repository TEXT;
+ALTER TABLE webhook_data ADD COLUMN github_installation TEXT;
+
+CREATE INDEX webhook_data_github_repository_idx ON webhook_data(webhook_id, github_repository);
+CREATE INDEX webhook_data_github_installation_idx ON webhook_data(webhook_id, github_installation_id);
@@ -0,0 +1,6 @@
+-- Migration: Stripe cross-check column
+-- With stripe_cross_check on in a webhook's config, verified Stripe events
+-- store the fields that differ between the event's object and the object as
+-- the Stripe API returns it at capture time (JSON); NULL otherwise.
+
+ALTER TABLE webhook_data ADD COLUMN stripe_cross_check TEXT;
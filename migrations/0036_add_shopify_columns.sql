@@ -0,0 +1,9 @@
+-- Migration: Shopify delivery columns
+-- Deliveries with X-Shopify-Topic keep the topic (orders/create) and the
+-- sending shop's domain (X-Shopify-Shop-Domain) in their own columns for
+-- per-shop, per-topic filters and stats; both NULL for other captures.
+
+ALTER TABLE webhook_data ADD COLUMN shopify_topic TEXT;
+ALTER TABLE webhook_data ADD COLUMN shopify_shop_domain TEXT;
+
+CREATE INDEX webhook_data_shopify_idx ON webhook_data(webhook_id, shopify_shop_domain, shopify_topic);
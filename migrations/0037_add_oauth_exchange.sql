@@ -0,0 +1,6 @@
+-- Migration: OAuth token exchange column
+-- Captures of /w/{uuid}/oauth/callback on webhooks with OAuth client
+-- credentials keep what the token endpoint answered (token type, scope,
+-- issued tokens, redacted claims; never the tokens) as JSON; NULL otherwise.
+
+ALTER TABLE webhook_data ADD COLUMN oauth_exchange TEXT;
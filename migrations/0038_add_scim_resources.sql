@@ -0,0 +1,18 @@
+-- Migration: SCIM resources
+-- Users and groups written through a webhook's SCIM sink (/w/{uuid}/scim/v2),
+-- so provisioning calls read back what earlier ones created. `attributes` is
+-- the resource as last written, as JSON, without `id` and `meta`.
+
+CREATE TABLE scim_resources (
+  webhook_id TEXT NOT NULL,
+  id TEXT NOT NULL,
+  resource_type TEXT NOT NULL,
+  attributes TEXT NOT NULL,
+  version INTEGER NOT NULL,
+  created_at_ms INTEGER NOT NULL,
+  updated_at_ms INTEGER NOT NULL,
+  PRIMARY KEY (webhook_id, id),
+  FOREIGN KEY (webhook_id) REFERENCES webhooks(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_scim_resources_type ON scim_resources(webhook_id, resource_type, created_at_ms);
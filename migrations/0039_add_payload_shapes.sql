@@ -0,0 +1,22 @@
+-- Migration: Payload shapes
+-- Field paths and JSON types of each event type's deliveries, fingerprinted
+-- and counted per webhook when `shapes` tracking is on. A new shape stores its
+-- diff against the dominant one (`previous_fingerprint`); `event_type` is ''
+-- for captures without one. `shape` and `diff` are JSON.
+
+CREATE TABLE payload_shapes (
+  webhook_id TEXT NOT NULL,
+  event_type TEXT NOT NULL,
+  fingerprint TEXT NOT NULL,
+  shape TEXT NOT NULL,
+  count INTEGER NOT NULL,
+  first_seen_ms INTEGER NOT NULL,
+  last_seen_ms INTEGER NOT NULL,
+  capture_id TEXT NOT NULL,
+  previous_fingerprint TEXT,
+  diff TEXT,
+  PRIMARY KEY (webhook_id, event_type, fingerprint),
+  FOREIGN KEY (webhook_id) REFERENCES webhooks(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_payload_shapes_seen ON payload_shapes(webhook_id, first_seen_ms);
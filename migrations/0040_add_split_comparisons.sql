@@ -0,0 +1,20 @@
+-- Migration: A/B forwarding comparisons
+-- Deliveries a traffic split forwarded to both targets, with how the two
+-- answers compared. `body_diff` is a JSON array of the differing JSON paths.
+
+CREATE TABLE split_comparisons (
+  webhook_id TEXT NOT NULL,
+  capture_id TEXT NOT NULL,
+  status_a INTEGER,
+  status_b INTEGER,
+  duration_a_ms INTEGER NOT NULL,
+  duration_b_ms INTEGER NOT NULL,
+  status_match INTEGER NOT NULL,
+  body_match INTEGER NOT NULL,
+  body_diff TEXT,
+  created_at_ms INTEGER NOT NULL,
+  PRIMARY KEY (webhook_id, capture_id),
+  FOREIGN KEY (webhook_id) REFERENCES webhooks(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_split_comparisons_created ON split_comparisons(webhook_id, created_at_ms);
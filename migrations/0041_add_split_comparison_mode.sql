@@ -0,0 +1,7 @@
+-- Migration: Shadow forwarding comparisons
+-- Shadow forwarding stores its comparisons next to the traffic split's;
+-- `mode` tells them apart ('split' or 'shadow', where `a` is the primary).
+
+ALTER TABLE split_comparisons ADD COLUMN mode TEXT NOT NULL DEFAULT 'split';
+
+CREATE INDEX idx_split_comparisons_mode ON split_comparisons(webhook_id, mode, created_at_ms);
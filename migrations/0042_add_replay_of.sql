@@ -0,0 +1,8 @@
+-- Migration: Replay parent column
+-- Captures made by POST /api/webhooks/{uuid}/requests/{id}/replay (an edited
+-- resend of a stored capture) name the capture they were derived from, so an
+-- original's variants can be listed with `replay_of={id}`; NULL otherwise.
+
+ALTER TABLE webhook_data ADD COLUMN replay_of TEXT;
+
+CREATE INDEX webhook_data_replay_of_idx ON webhook_data(webhook_id, replay_of);
@@ -0,0 +1,27 @@
+-- Migration: Background jobs
+-- Long-running operations (bulk delete, bulk replay, CSV export, schema
+-- migrations) are queued here and run step by step by the JobRunner Durable
+-- Object, which records its progress after every step. `params` is the JSON
+-- request the job was created with, `state` the runner's position between
+-- steps and `result` the JSON summary of a finished job.
+
+CREATE TABLE jobs (
+  id TEXT PRIMARY KEY,
+  kind TEXT NOT NULL,
+  webhook_id TEXT,
+  created_by TEXT NOT NULL,
+  status TEXT NOT NULL DEFAULT 'queued',
+  params TEXT NOT NULL,
+  state TEXT,
+  done INTEGER NOT NULL DEFAULT 0,
+  total INTEGER,
+  result TEXT,
+  error TEXT,
+  created_at_ms INTEGER NOT NULL,
+  updated_at_ms INTEGER NOT NULL,
+  finished_at_ms INTEGER,
+  FOREIGN KEY (webhook_id) REFERENCES webhooks(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_jobs_webhook ON jobs(webhook_id, created_at_ms);
+CREATE INDEX idx_jobs_finished ON jobs(finished_at_ms);
@@ -0,0 +1,15 @@
+-- Migration: Counters
+-- Flushed copies of the Counter Durable Objects' values (quotas, rate limits),
+-- one row per scope and key, written in batches. The objects hold the live
+-- values; rows whose `expires_at_ms` has passed are pruned daily.
+
+CREATE TABLE counters (
+  scope TEXT NOT NULL,
+  key TEXT NOT NULL,
+  value INTEGER NOT NULL,
+  expires_at_ms INTEGER,
+  updated_at_ms INTEGER NOT NULL,
+  PRIMARY KEY (scope, key)
+);
+
+CREATE INDEX idx_counters_expires ON counters(expires_at_ms);
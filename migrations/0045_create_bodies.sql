@@ -0,0 +1,16 @@
+-- Migration: Content-addressed bodies
+-- Capture bodies stored once per webhook and SHA-256, referenced from the
+-- captures' `data` as `body:sha256:{hash}` (`BODY_DEDUP_MIN_BYTES`). `refs`
+-- counts the referencing captures; bodies left at 0 are deleted daily.
+
+CREATE TABLE bodies (
+  webhook_id TEXT NOT NULL,
+  hash TEXT NOT NULL,
+  data TEXT NOT NULL,
+  size_bytes INTEGER NOT NULL,
+  refs INTEGER NOT NULL,
+  created_at_ms INTEGER NOT NULL,
+  PRIMARY KEY (webhook_id, hash)
+);
+
+CREATE INDEX idx_bodies_refs ON bodies(refs);
@@ -0,0 +1,8 @@
+-- Migration: Heartbeat repeat columns
+-- Webhooks whose `heartbeat` config collapses repeated identical deliveries
+-- fold each heartbeat into the capture before it instead of storing another:
+-- repeat_count is how many deliveries that capture stands for and
+-- last_seen_at_ms when the latest arrived; both NULL for a single delivery.
+
+ALTER TABLE webhook_data ADD COLUMN repeat_count INTEGER;
+ALTER TABLE webhook_data ADD COLUMN last_seen_at_ms INTEGER;
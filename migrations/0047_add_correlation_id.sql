@@ -0,0 +1,8 @@
+-- Migration: Correlation ID column
+-- The value a webhook's `correlation_id` config extracts (a header or a JSON
+-- body path) at ingest, so GET /api/trace/{correlation_id} can find every
+-- capture of a project carrying it; NULL when not configured or absent.
+
+ALTER TABLE webhook_data ADD COLUMN correlation_id TEXT;
+
+CREATE INDEX webhook_data_correlation_id_idx ON webhook_data(webhook_id, correlation_id);
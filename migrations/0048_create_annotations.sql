@@ -0,0 +1,20 @@
+-- Migration: Capture annotations
+-- Notes external systems (CI, monitoring, the consumer itself) attach to a
+-- capture through POST /api/webhooks/{uuid}/requests/{id}/annotations: what
+-- processed it and how that went. Kept 30 days, like forward responses.
+
+CREATE TABLE annotations (
+  id TEXT PRIMARY KEY,
+  webhook_id TEXT NOT NULL,
+  capture_id TEXT NOT NULL,
+  source TEXT NOT NULL,
+  status TEXT NOT NULL,
+  message TEXT NOT NULL,
+  url TEXT,
+  data TEXT,
+  actor TEXT NOT NULL,
+  created_at_ms INTEGER NOT NULL
+);
+
+CREATE INDEX idx_annotations_capture ON annotations(webhook_id, capture_id, created_at_ms);
+CREATE INDEX idx_annotations_created ON annotations(created_at_ms);
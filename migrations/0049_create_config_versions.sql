@@ -0,0 +1,15 @@
+-- Migration: Config history
+-- Every webhook config write keeps the version it produced, with who wrote it
+-- and when, so GET /api/webhooks/{uuid}/config/history can show what changed
+-- and POST /api/webhooks/{uuid}/config/rollback/{version} can restore it.
+-- The version a write replaced is kept too when it wasn't yet (actor and time
+-- unknown). The latest 50 versions of each webhook are kept.
+
+CREATE TABLE config_versions (
+  webhook_id TEXT NOT NULL,
+  version INTEGER NOT NULL,
+  config TEXT NOT NULL,
+  actor TEXT,
+  created_at_ms INTEGER,
+  PRIMARY KEY (webhook_id, version)
+);
@@ -0,0 +1,16 @@
+-- Migration: Pipeline fixtures
+-- Named sample deliveries, typed in or saved from a capture, with the outcome
+-- they should have (stored, forwarded, status, event type, route), run
+-- through the webhook's current pipeline by
+-- POST /api/webhooks/{uuid}/fixtures/{name}/run. At most 50 per webhook.
+
+CREATE TABLE fixtures (
+  webhook_id TEXT NOT NULL,
+  name TEXT NOT NULL,
+  request TEXT NOT NULL,
+  expect TEXT NOT NULL,
+  capture_id TEXT,
+  actor TEXT NOT NULL,
+  created_at_ms INTEGER NOT NULL,
+  PRIMARY KEY (webhook_id, name)
+);
@@ -0,0 +1,6 @@
+-- Migration: Explicit share roles
+-- The worker maps share roles one to one (viewer, editor, owner) and an
+-- unknown role grants nothing. Shares created as 'collaborator' had editor
+-- rights, so they keep them.
+
+UPDATE webhook_shares SET role = 'editor' WHERE role = 'collaborator';
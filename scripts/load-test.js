@@ -25,6 +25,9 @@
  *   # Stress test (find breaking point)
  *   k6 run --env LOAD_PROFILE=stress load-test.js
  *
+ *   # Soak test (steady 200 RPS for 30 minutes, SOAK_DURATION to override)
+ *   k6 run --env LOAD_PROFILE=soak load-test.js
+ *
  *   # Custom webhook endpoint
  *   k6 run --env WEBHOOK_URL=https://your-worker.workers.dev/w/your-uuid load-test.js
  */
@@ -96,6 +99,14 @@ const profiles = {
       { duration: '2m', target: 0 },      // Ramp down
     ],
   },
+  soak: {
+    executor: 'constant-arrival-rate',
+    rate: 200,
+    timeUnit: '1s',
+    duration: __ENV.SOAK_DURATION || '30m',
+    preAllocatedVUs: 50,
+    maxVUs: 200,
+  },
 };
 
 // Test configuration
@@ -115,9 +115,12 @@ case "$PROFILE" in
     stress)
         echo -e "${BLUE}📊 Stress Test${NC} - Ramp up to find breaking point (24 minutes)"
         ;;
+    soak)
+        echo -e "${BLUE}📊 Soak Test${NC} - 200 RPS for ${SOAK_DURATION:-30m}"
+        ;;
     *)
         echo -e "${RED}❌ Unknown profile: $PROFILE${NC}"
-        echo -e "${YELLOW}Available profiles: light, medium, heavy, extreme, stress${NC}"
+        echo -e "${YELLOW}Available profiles: light, medium, heavy, extreme, stress, soak${NC}"
         exit 1
         ;;
 esac
@@ -134,6 +137,7 @@ RESULT_FILE="$OUTPUT_DIR/load-test_${PROFILE}_${TIMESTAMP}.json"
 k6 run \
     --env WEBHOOK_URL="$WEBHOOK_URL" \
     --env LOAD_PROFILE="$PROFILE" \
+    --env SOAK_DURATION="${SOAK_DURATION:-30m}" \
     --out "json=$RESULT_FILE" \
     load-test.js
 
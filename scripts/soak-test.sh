@@ -0,0 +1,70 @@
+#!/bin/bash
+
+# Local Soak Test Harness
+# Runs the hot path micro-benchmarks, then soaks the webhook worker running
+# locally under wrangler dev (miniflare, built with worker-build) and checks it
+# is still healthy afterwards.
+#
+# Usage: ./soak-test.sh                      # 200 RPS for 30 minutes
+#        SOAK_DURATION=5m ./soak-test.sh     # shorter soak
+#        BENCH_BASELINE=../webhook-worker/bench-baseline.json ./soak-test.sh
+#        SKIP_BENCH=true ./soak-test.sh
+
+set -e
+
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+YELLOW='\033[1;33m'
+BLUE='\033[0;34m'
+NC='\033[0m' # No Color
+
+SCRIPT_DIR="$( cd "$( dirname "${BASH_SOURCE[0]}" )" && pwd )"
+ROOT_DIR="$SCRIPT_DIR/.."
+BASE_URL="${BASE_URL:-http://localhost:5174}"
+STARTED_DEV_PID=""
+
+cleanup() {
+    if [ -n "$STARTED_DEV_PID" ]; then
+        echo -e "${BLUE}🛑 Stopping local dev server${NC}"
+        kill "$STARTED_DEV_PID" 2>/dev/null || true
+    fi
+}
+trap cleanup EXIT
+
+# Step 1: micro-benchmarks (fails on regressions when BENCH_BASELINE is set)
+if [ "$SKIP_BENCH" != "true" ]; then
+    echo -e "${BLUE}⏱️  Running hot path benchmarks...${NC}"
+    (cd "$ROOT_DIR/webhook-worker" && cargo bench --features bench)
+fi
+
+# Step 2: local worker (reuse a running `npm run dev`)
+if ! curl -sf "$BASE_URL/health" > /dev/null; then
+    echo -e "${BLUE}🏠 Starting local dev server...${NC}"
+    (cd "$ROOT_DIR" && node scripts/dev.js > "$SCRIPT_DIR/soak-dev.log" 2>&1) &
+    STARTED_DEV_PID=$!
+
+    for _ in $(seq 1 180); do
+        if curl -sf "$BASE_URL/health" > /dev/null; then
+            break
+        fi
+        sleep 1
+    done
+    if ! curl -sf "$BASE_URL/health" > /dev/null; then
+        echo -e "${RED}❌ Local worker did not become healthy (see scripts/soak-dev.log)${NC}"
+        exit 1
+    fi
+fi
+echo -e "${GREEN}✅ Local worker is up at $BASE_URL${NC}"
+
+# Step 3: soak
+export USE_LOCAL_DB=true
+export BASE_URL
+"$SCRIPT_DIR/run-load-test.sh" soak
+
+# Step 4: the worker must survive the soak
+if curl -sf "$BASE_URL/health" > /dev/null; then
+    echo -e "${GREEN}✅ Worker still healthy after soak${NC}"
+else
+    echo -e "${RED}❌ Worker unhealthy after soak${NC}"
+    exit 1
+fi
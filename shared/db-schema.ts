@@ -4,7 +4,7 @@
  */
 
 // @ts-ignore - Module resolution works at runtime from parent projects
-import { sqliteTable, text, integer, index } from 'drizzle-orm/sqlite-core'
+import { sqliteTable, text, integer, real, index, uniqueIndex, primaryKey } from 'drizzle-orm/sqlite-core'
 
 // Better Auth: Users table
 export const user = sqliteTable('user', {
@@ -81,6 +81,9 @@ export const webhooks = sqliteTable('webhooks', {
   uuid: text('uuid').notNull().unique(),
   name: text('name').notNull(),
   tags: text('tags'), // JSON array
+  config: text('config'), // JSON document managed by the webhook worker API
+  configVersion: integer('config_version').notNull().default(0),
+  secret: text('secret'), // Signed capture URL secret
   createdAt: integer('created_at', { mode: 'timestamp' }).notNull(),
 }, (table: ReturnType<typeof sqliteTable>) => ({
   userIdIdx: index('webhook_user_id_idx').on(table.userId),
@@ -96,9 +99,79 @@ export const webhookData = sqliteTable('webhook_data', {
   data: text('data').notNull(), // JSON string of body/query params
   sizeBytes: integer('size_bytes').notNull(),
   receivedAt: integer('received_at', { mode: 'timestamp' }).notNull(),
+  receivedAtMs: integer('received_at_ms'), // Unix milliseconds
+  eventTime: integer('event_time'), // Sender-supplied event time (Unix milliseconds)
+  sequence: integer('sequence'), // Strictly increasing per webhook (assigned by the webhook worker)
+  // Indexed header columns (extracted at ingest by the webhook worker)
+  contentType: text('content_type'),
+  userAgent: text('user_agent'),
+  signature: text('signature'), // Provider signature header value
+  idempotencyKey: text('idempotency_key'),
+  eventType: text('event_type'), // Provider event header (e.g. X-GitHub-Event)
+  verification: text('verification'), // valid, missing, invalid or replay_suspected
+  environment: text('environment'), // Environment name when captured through an environment UUID
+  trailers: text('trailers'), // Trailing metadata as JSON (gRPC-Web trailer frame)
+  connectionId: text('connection_id'), // Capture socket connection (WebSocket ingestion)
+  frameType: text('frame_type'), // text or binary (WebSocket ingestion)
+  processing: text('processing'), // Pipeline stages that ran, as JSON
+  preview: text('preview'), // Sniffed type, dimensions and hexdump of binary payloads, as JSON
+  charset: text('charset'), // Detected body charset (utf-8, utf-16le, utf-16be, windows-1252)
+  originalBody: text('original_body'), // Base64 body bytes before UTF-8 normalization, when they differ
+  canonicalData: text('canonical_data'), // JSON body minified with sorted keys
+  svixId: text('svix_id'), // svix-id message ID, the same across retries (Svix-style deliveries)
+  svixTimestamp: integer('svix_timestamp'), // svix-timestamp the attempt was signed at (Unix seconds)
+  // GitHub deliveries (X-GitHub-Event present)
+  githubEvent: text('github_event'),
+  githubDelivery: text('github_delivery'), // X-GitHub-Delivery GUID
+  githubInstallationId: text('github_installation_id'), // App installation ID
+  githubRepository: text('github_repository'), // owner/name
+  githubInstallation: text('github_installation'), // Installation account login (GitHub API lookup)
+  stripeCrossCheck: text('stripe_cross_check'), // JSON: event vs. current Stripe object
+  shopifyTopic: text('shopify_topic'), // X-Shopify-Topic (orders/create)
+  shopifyShopDomain: text('shopify_shop_domain'), // X-Shopify-Shop-Domain
+  oauthExchange: text('oauth_exchange'), // JSON: token endpoint answer to an OAuth callback, tokens dropped
+  replayOf: text('replay_of'), // Capture this one is an edited resend of
+  correlationId: text('correlation_id'), // Where the webhook's correlation_id config points (trace API)
+  repeatCount: integer('repeat_count'), // Deliveries folded into this capture as heartbeats
+  lastSeenAtMs: integer('last_seen_at_ms'), // When the latest folded heartbeat arrived
+  // Inbox consumption state (webhook worker inbox API)
+  readAtMs: integer('read_at_ms'),
+  ackedAtMs: integer('acked_at_ms'),
+  leaseUntilMs: integer('lease_until_ms'),
 }, (table: ReturnType<typeof sqliteTable>) => ({
   webhookIdIdx: index('webhook_data_webhook_id_idx').on(table.webhookId),
   receivedAtIdx: index('webhook_data_received_at_idx').on(table.receivedAt),
+  receivedAtMsIdx: index('webhook_data_received_at_ms_idx').on(table.webhookId, table.receivedAtMs),
+  eventTimeIdx: index('webhook_data_event_time_idx').on(table.webhookId, table.eventTime),
+  sequenceIdx: index('webhook_data_sequence_idx').on(table.webhookId, table.sequence),
+  contentTypeIdx: index('webhook_data_content_type_idx').on(table.webhookId, table.contentType),
+  userAgentIdx: index('webhook_data_user_agent_idx').on(table.webhookId, table.userAgent),
+  signatureIdx: index('webhook_data_signature_idx').on(table.webhookId, table.signature),
+  idempotencyKeyIdx: index('webhook_data_idempotency_key_idx').on(table.webhookId, table.idempotencyKey),
+  eventTypeIdx: index('webhook_data_event_type_idx').on(table.webhookId, table.eventType),
+  verificationIdx: index('webhook_data_verification_idx').on(table.webhookId, table.verification),
+  inboxIdx: index('webhook_data_inbox_idx').on(table.webhookId, table.ackedAtMs, table.leaseUntilMs),
+  environmentIdx: index('webhook_data_environment_idx').on(table.webhookId, table.environment),
+  connectionIdx: index('webhook_data_connection_idx').on(table.webhookId, table.connectionId),
+  svixIdIdx: index('webhook_data_svix_id_idx').on(table.webhookId, table.svixId),
+  githubRepositoryIdx: index('webhook_data_github_repository_idx').on(table.webhookId, table.githubRepository),
+  githubInstallationIdx: index('webhook_data_github_installation_idx').on(table.webhookId, table.githubInstallationId),
+  shopifyIdx: index('webhook_data_shopify_idx').on(table.webhookId, table.shopifyShopDomain, table.shopifyTopic),
+  replayOfIdx: index('webhook_data_replay_of_idx').on(table.webhookId, table.replayOf),
+  correlationIdIdx: index('webhook_data_correlation_id_idx').on(table.webhookId, table.correlationId),
+}))
+
+// Named environments per webhook (own capture UUID and forwarding target, shared config)
+export const webhookEnvironments = sqliteTable('webhook_environments', {
+  id: text('id').primaryKey(),
+  webhookId: text('webhook_id').notNull().references(() => webhooks.id, { onDelete: 'cascade' }),
+  name: text('name').notNull(), // dev, staging, prod, ...
+  uuid: text('uuid').notNull().unique(),
+  forwardUrl: text('forward_url'),
+  createdAtMs: integer('created_at_ms').notNull(),
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  nameIdx: uniqueIndex('webhook_environment_name_idx').on(table.webhookId, table.name),
+  uuidIdx: index('webhook_environment_uuid_idx').on(table.uuid),
 }))
 
 // Webhook shares table (collaboration)
@@ -108,7 +181,7 @@ export const webhookShares = sqliteTable('webhook_shares', {
   sharedWithEmail: text('shared_with_email').notNull(),
   sharedWithUserId: text('shared_with_user_id').references(() => users.id, { onDelete: 'cascade' }),
   invitedByUserId: text('invited_by_user_id').notNull().references(() => users.id, { onDelete: 'cascade' }),
-  role: text('role').notNull(), // 'owner' or 'collaborator'
+  role: text('role').notNull(), // 'viewer', 'editor' or 'owner'
   invitedAt: integer('invited_at', { mode: 'timestamp' }).notNull(),
   acceptedAt: integer('accepted_at', { mode: 'timestamp' }),
 }, (table: ReturnType<typeof sqliteTable>) => ({
@@ -117,6 +190,314 @@ export const webhookShares = sqliteTable('webhook_shares', {
   sharedWithUserIdx: index('webhook_share_user_id_idx').on(table.sharedWithUserId),
 }))
 
+// Replication heartbeat (webhook worker /health replication lag estimate)
+export const replicationHeartbeat = sqliteTable('replication_heartbeat', {
+  id: integer('id').primaryKey(),
+  writtenAtMs: integer('written_at_ms').notNull(),
+})
+
+// Project API tokens for the webhook worker management API (SHA-256 hashed secrets)
+export const apiTokens = sqliteTable('api_tokens', {
+  id: text('id').primaryKey(),
+  userId: text('user_id').notNull().references(() => users.id, { onDelete: 'cascade' }),
+  name: text('name').notNull(),
+  role: text('role').notNull(), // 'viewer', 'editor' or 'owner'
+  scopes: text('scopes'), // Space-separated: 'ingest:read', 'webhooks:write', 'inbox:consume', 'admin' (NULL = all)
+  tokenHash: text('token_hash').notNull().unique(),
+  createdAtMs: integer('created_at_ms').notNull(),
+  expiresAtMs: integer('expires_at_ms'),
+  lastUsedAtMs: integer('last_used_at_ms'),
+  revokedAtMs: integer('revoked_at_ms'),
+  previousTokenHash: text('previous_token_hash'), // Rotated-out secret, valid during the grace period
+  previousExpiresAtMs: integer('previous_expires_at_ms'),
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  userIdIdx: index('api_tokens_user_id_idx').on(table.userId),
+  previousHashIdx: index('api_tokens_previous_hash_idx').on(table.previousTokenHash),
+}))
+
+// Audit log of management actions (written by the webhook worker API)
+export const auditLog = sqliteTable('audit_log', {
+  id: text('id').primaryKey(),
+  createdAtMs: integer('created_at_ms').notNull(),
+  actor: text('actor').notNull(),
+  ip: text('ip'),
+  action: text('action').notNull(),
+  target: text('target'),
+  before: text('before'),
+  after: text('after'),
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  createdIdx: index('audit_log_created_idx').on(table.createdAtMs),
+  actionIdx: index('audit_log_action_idx').on(table.action, table.createdAtMs),
+  actorIdx: index('audit_log_actor_idx').on(table.actor, table.createdAtMs),
+  targetIdx: index('audit_log_target_idx').on(table.target, table.createdAtMs),
+}))
+
+// UUID enumeration detection (webhook worker)
+export const enumerationMisses = sqliteTable('enumeration_misses', {
+  ip: text('ip').notNull(),
+  uuid: text('uuid').notNull(),
+  seenAtMs: integer('seen_at_ms').notNull(),
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  pk: primaryKey({ columns: [table.ip, table.uuid] }),
+  ipSeenIdx: index('enumeration_misses_ip_seen_idx').on(table.ip, table.seenAtMs),
+  seenIdx: index('enumeration_misses_seen_idx').on(table.seenAtMs),
+}))
+
+export const abuseScanners = sqliteTable('abuse_scanners', {
+  ip: text('ip').primaryKey(),
+  firstSeenMs: integer('first_seen_ms').notNull(),
+  lastSeenMs: integer('last_seen_ms').notNull(),
+  distinctUuids: integer('distinct_uuids').notNull(),
+  decoyHits: integer('decoy_hits').notNull().default(0),
+  lastUuid: text('last_uuid'),
+  userAgent: text('user_agent'),
+  flaggedAtMs: integer('flagged_at_ms').notNull(),
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  lastSeenIdx: index('abuse_scanners_last_seen_idx').on(table.lastSeenMs),
+}))
+
+// Violated delivery expectations (webhook worker SLA checks)
+export const expectationAlerts = sqliteTable('expectation_alerts', {
+  webhookId: text('webhook_id').notNull(),
+  expectation: text('expectation').notNull(),
+  violatedSinceMs: integer('violated_since_ms').notNull(),
+  observedCount: integer('observed_count').notNull(),
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  pk: primaryKey({ columns: [table.webhookId, table.expectation] }),
+}))
+
+// Hourly volume baselines (webhook worker anomaly detection)
+export const volumeBaselines = sqliteTable('volume_baselines', {
+  webhookId: text('webhook_id').primaryKey(),
+  ewma: real('ewma').notNull(),
+  hours: integer('hours').notNull(),
+  lastHour: integer('last_hour').notNull(),
+  anomaly: text('anomaly'),
+  anomalySinceMs: integer('anomaly_since_ms'),
+})
+
+// Forward target latency histograms (webhook worker stats API)
+export const forwardLatency = sqliteTable('forward_latency', {
+  webhookId: text('webhook_id').notNull(),
+  target: text('target').notNull(),
+  day: integer('day').notNull(),
+  bucket: integer('bucket').notNull(),
+  count: integer('count').notNull().default(0),
+  failures: integer('failures').notNull().default(0),
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  pk: primaryKey({ columns: [table.webhookId, table.target, table.day, table.bucket] }),
+  dayIdx: index('forward_latency_day_idx').on(table.day),
+}))
+
+// Downstream responses to forwarded captures (webhook worker request detail API)
+export const forwardResponses = sqliteTable('forward_responses', {
+  id: text('id').primaryKey(),
+  webhookId: text('webhook_id').notNull(),
+  captureId: text('capture_id').notNull(),
+  target: text('target').notNull(),
+  status: integer('status'),
+  headers: text('headers'), // JSON object
+  body: text('body'), // Truncated to 8 KiB
+  bodyTruncated: integer('body_truncated', { mode: 'boolean' }).notNull().default(false),
+  durationMs: integer('duration_ms').notNull(),
+  error: text('error'),
+  createdAtMs: integer('created_at_ms').notNull(),
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  captureIdx: index('forward_responses_capture_idx').on(table.webhookId, table.captureId),
+  createdIdx: index('forward_responses_created_idx').on(table.createdAtMs),
+}))
+
+// Daily capture aggregates kept by tiered retention (webhook worker)
+export const captureDaily = sqliteTable('capture_daily', {
+  webhookId: text('webhook_id').notNull(),
+  day: integer('day').notNull(), // Start of the UTC day (Unix seconds)
+  eventType: text('event_type').notNull().default(''),
+  count: integer('count').notNull().default(0),
+  bytes: integer('bytes').notNull().default(0),
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  pk: primaryKey({ columns: [table.webhookId, table.day, table.eventType] }),
+}))
+
+export const legalHolds = sqliteTable('legal_holds', {
+  id: text('id').primaryKey(),
+  webhookId: text('webhook_id').notNull(),
+  captureId: text('capture_id'), // NULL holds the whole webhook
+  reason: text('reason').notNull(),
+  createdBy: text('created_by').notNull(),
+  createdAtMs: integer('created_at_ms').notNull(),
+  releasedBy: text('released_by'),
+  releasedAtMs: integer('released_at_ms'),
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  webhookIdx: index('idx_legal_holds_webhook').on(table.webhookId, table.releasedAtMs),
+}))
+
+export const projectSettings = sqliteTable('project_settings', {
+  userId: text('user_id').primaryKey(),
+  jurisdiction: text('jurisdiction'), // 'eu' | 'us'; NULL keeps captures in the default region
+  updatedAtMs: integer('updated_at_ms').notNull(),
+})
+
+export const dataKeys = sqliteTable('data_keys', {
+  id: text('id').primaryKey(),
+  webhookId: text('webhook_id').notNull(),
+  masterVersion: integer('master_version').notNull(), // MASTER_KEY_{n} the data key is wrapped with
+  wrappedKey: text('wrapped_key').notNull(), // base64 IV + AES-GCM ciphertext
+  createdAtMs: integer('created_at_ms').notNull(),
+  rewrappedAtMs: integer('rewrapped_at_ms'),
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  webhookIdx: index('idx_data_keys_webhook').on(table.webhookId, table.createdAtMs),
+  masterVersionIdx: index('idx_data_keys_master_version').on(table.masterVersion),
+}))
+
+export const securityEvents = sqliteTable('security_events', {
+  id: text('id').primaryKey(),
+  createdAtMs: integer('created_at_ms').notNull(),
+  kind: text('kind').notNull(), // signature_failure | ip_blocked | token_misuse | enumeration
+  severity: text('severity').notNull(), // low | medium | high
+  ip: text('ip'),
+  uuid: text('uuid'),
+  webhookId: text('webhook_id'),
+  actor: text('actor'),
+  userAgent: text('user_agent'),
+  detail: text('detail'), // JSON
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  createdIdx: index('idx_security_events_created').on(table.createdAtMs),
+  kindIdx: index('idx_security_events_kind').on(table.kind, table.createdAtMs),
+  ipIdx: index('idx_security_events_ip').on(table.ip, table.createdAtMs),
+}))
+
+// Users and groups written through a webhook's SCIM sink (webhook worker)
+export const scimResources = sqliteTable('scim_resources', {
+  webhookId: text('webhook_id').notNull().references(() => webhooks.id, { onDelete: 'cascade' }),
+  id: text('id').notNull(),
+  resourceType: text('resource_type').notNull(), // User | Group
+  attributes: text('attributes').notNull(), // JSON, without id and meta
+  version: integer('version').notNull(),
+  createdAtMs: integer('created_at_ms').notNull(),
+  updatedAtMs: integer('updated_at_ms').notNull(),
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  pk: primaryKey({ columns: [table.webhookId, table.id] }),
+  typeIdx: index('idx_scim_resources_type').on(table.webhookId, table.resourceType, table.createdAtMs),
+}))
+
+// Payload shapes per event type and the diff of each new one (webhook worker shape tracking)
+export const payloadShapes = sqliteTable('payload_shapes', {
+  webhookId: text('webhook_id').notNull().references(() => webhooks.id, { onDelete: 'cascade' }),
+  eventType: text('event_type').notNull(), // '' without an event type
+  fingerprint: text('fingerprint').notNull(),
+  shape: text('shape').notNull(), // JSON: field path -> JSON types
+  count: integer('count').notNull(),
+  firstSeenMs: integer('first_seen_ms').notNull(),
+  lastSeenMs: integer('last_seen_ms').notNull(),
+  captureId: text('capture_id').notNull(),
+  previousFingerprint: text('previous_fingerprint'),
+  diff: text('diff'), // JSON: added, removed, type_changed
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  pk: primaryKey({ columns: [table.webhookId, table.eventType, table.fingerprint] }),
+  seenIdx: index('idx_payload_shapes_seen').on(table.webhookId, table.firstSeenMs),
+}))
+
+export const splitComparisons = sqliteTable('split_comparisons', {
+  webhookId: text('webhook_id').notNull().references(() => webhooks.id, { onDelete: 'cascade' }),
+  captureId: text('capture_id').notNull(),
+  statusA: integer('status_a'),
+  statusB: integer('status_b'),
+  durationAMs: integer('duration_a_ms').notNull(),
+  durationBMs: integer('duration_b_ms').notNull(),
+  statusMatch: integer('status_match', { mode: 'boolean' }).notNull(),
+  bodyMatch: integer('body_match', { mode: 'boolean' }).notNull(),
+  bodyDiff: text('body_diff'), // JSON array of differing paths
+  createdAtMs: integer('created_at_ms').notNull(),
+  mode: text('mode').notNull().default('split'), // 'split' or 'shadow' (a = primary)
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  pk: primaryKey({ columns: [table.webhookId, table.captureId] }),
+  createdIdx: index('idx_split_comparisons_created').on(table.webhookId, table.createdAtMs),
+  modeIdx: index('idx_split_comparisons_mode').on(table.webhookId, table.mode, table.createdAtMs),
+}))
+
+export const jobs = sqliteTable('jobs', {
+  id: text('id').primaryKey(),
+  kind: text('kind').notNull(), // 'delete', 'replay', 'export' or 'migrate'
+  webhookId: text('webhook_id').references(() => webhooks.id, { onDelete: 'cascade' }), // null for migrations
+  createdBy: text('created_by').notNull(),
+  status: text('status').notNull().default('queued'), // queued, running, succeeded, failed, cancelled
+  params: text('params').notNull(), // JSON
+  state: text('state'), // JSON runner position between steps
+  done: integer('done').notNull().default(0),
+  total: integer('total'),
+  result: text('result'), // JSON
+  error: text('error'),
+  createdAtMs: integer('created_at_ms').notNull(),
+  updatedAtMs: integer('updated_at_ms').notNull(),
+  finishedAtMs: integer('finished_at_ms'),
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  webhookIdx: index('idx_jobs_webhook').on(table.webhookId, table.createdAtMs),
+  finishedIdx: index('idx_jobs_finished').on(table.finishedAtMs),
+}))
+
+export const counters = sqliteTable('counters', {
+  scope: text('scope').notNull(), // e.g. 'quota:{webhook_id}'
+  key: text('key').notNull(),
+  value: integer('value').notNull(),
+  expiresAtMs: integer('expires_at_ms'),
+  updatedAtMs: integer('updated_at_ms').notNull(),
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  pk: primaryKey({ columns: [table.scope, table.key] }),
+  expiresIdx: index('idx_counters_expires').on(table.expiresAtMs),
+}))
+
+export const bodies = sqliteTable('bodies', {
+  webhookId: text('webhook_id').notNull(),
+  hash: text('hash').notNull(), // SHA-256 hex; captures reference it as 'body:sha256:{hash}'
+  data: text('data').notNull(),
+  sizeBytes: integer('size_bytes').notNull(),
+  refs: integer('refs').notNull(),
+  createdAtMs: integer('created_at_ms').notNull(),
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  pk: primaryKey({ columns: [table.webhookId, table.hash] }),
+  refsIdx: index('idx_bodies_refs').on(table.refs),
+}))
+
+// Notes external systems attach to captures (webhook worker annotations API)
+export const annotations = sqliteTable('annotations', {
+  id: text('id').primaryKey(),
+  webhookId: text('webhook_id').notNull(),
+  captureId: text('capture_id').notNull(),
+  source: text('source').notNull(), // e.g. 'ci', 'billing-worker'
+  status: text('status').notNull(), // info | success | warning | failure
+  message: text('message').notNull(),
+  url: text('url'), // Link to the job / run
+  data: text('data'), // JSON object
+  actor: text('actor').notNull(),
+  createdAtMs: integer('created_at_ms').notNull(),
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  captureIdx: index('idx_annotations_capture').on(table.webhookId, table.captureId, table.createdAtMs),
+  createdIdx: index('idx_annotations_created').on(table.createdAtMs),
+}))
+
+// Kept webhook config versions (webhook worker config history and rollback)
+export const configVersions = sqliteTable('config_versions', {
+  webhookId: text('webhook_id').notNull(),
+  version: integer('version').notNull(),
+  config: text('config').notNull(), // JSON, secrets included
+  actor: text('actor'), // Null for versions written before history was kept
+  createdAtMs: integer('created_at_ms'),
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  pk: primaryKey({ columns: [table.webhookId, table.version] }),
+}))
+
+export const fixtures = sqliteTable('fixtures', {
+  webhookId: text('webhook_id').notNull(),
+  name: text('name').notNull(),
+  request: text('request').notNull(), // JSON sample delivery
+  expect: text('expect').notNull(), // JSON expected outcome
+  captureId: text('capture_id'), // Capture the sample was saved from
+  actor: text('actor').notNull(),
+  createdAtMs: integer('created_at_ms').notNull(),
+}, (table: ReturnType<typeof sqliteTable>) => ({
+  pk: primaryKey({ columns: [table.webhookId, table.name] }),
+}))
+
 // Types for TypeScript
 export type User = typeof users.$inferSelect
 export type NewUser = typeof users.$inferInsert
@@ -0,0 +1,97 @@
+//! Hot path benchmark runner
+//!
+//! cargo bench --features bench                       run every case
+//! cargo bench --features bench -- body/              run cases whose name contains `body/`
+//! BENCH_SAVE=baseline.json cargo bench ...           record results as a baseline
+//! BENCH_BASELINE=baseline.json cargo bench ...       fail if a case got slower than the baseline
+//!                                                    by more than BENCH_TOLERANCE (default 0.25)
+
+use std::time::{Duration, Instant};
+use webhook_ingestion::bench::{self, BenchCase, BenchStats};
+
+const WARM_UP: Duration = Duration::from_millis(200);
+const SAMPLES: usize = 50;
+const SAMPLE_TARGET: Duration = Duration::from_millis(20);
+
+/// Iterations per sample so one sample takes about `SAMPLE_TARGET`
+fn calibrate(case: &BenchCase) -> u64 {
+    let started = Instant::now();
+    let mut iterations = 0u64;
+    while started.elapsed() < WARM_UP {
+        (case.run)();
+        iterations += 1;
+    }
+    let per_iteration = started.elapsed().as_nanos() as f64 / iterations as f64;
+    ((SAMPLE_TARGET.as_nanos() as f64 / per_iteration) as u64).max(1)
+}
+
+fn measure(case: &BenchCase) -> BenchStats {
+    let iterations = calibrate(case);
+    let mut samples: Vec<f64> = (0..SAMPLES)
+        .map(|_| {
+            let started = Instant::now();
+            for _ in 0..iterations {
+                (case.run)();
+            }
+            started.elapsed().as_nanos() as f64 / iterations as f64
+        })
+        .collect();
+    BenchStats::from_samples(case.name, iterations * SAMPLES as u64, &mut samples)
+}
+
+fn throughput(case: &BenchCase, stats: &BenchStats) -> String {
+    if case.bytes == 0 {
+        return String::new();
+    }
+    let mib_per_second = case.bytes as f64 / stats.median_ns * 1e9 / (1024.0 * 1024.0);
+    format!("  {:>9.1} MiB/s", mib_per_second)
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; any other argument filters cases by name
+    let filter = std::env::args().skip(1).find(|arg| !arg.starts_with("--"));
+    let baseline: Option<Vec<BenchStats>> = std::env::var("BENCH_BASELINE").ok().map(|path| {
+        let contents = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("reading {}: {}", path, e));
+        serde_json::from_str(&contents).unwrap_or_else(|e| panic!("parsing {}: {}", path, e))
+    });
+    let tolerance: f64 = std::env::var("BENCH_TOLERANCE")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0.25);
+
+    let mut results = Vec::new();
+    let mut regressions = Vec::new();
+    for case in bench::cases() {
+        if filter.as_deref().is_some_and(|filter| !case.name.contains(filter)) {
+            continue;
+        }
+        let stats = measure(&case);
+        let mut line = format!(
+            "{:<28} {:>10.1} ns/iter (mean {:>10.1}, min {:>10.1}){}",
+            stats.name,
+            stats.median_ns,
+            stats.mean_ns,
+            stats.min_ns,
+            throughput(&case, &stats)
+        );
+        if let Some(before) = baseline.iter().flatten().find(|before| before.name == stats.name) {
+            let change = stats.median_ns / before.median_ns - 1.0;
+            line.push_str(&format!("  {:+.1}%", change * 100.0));
+            if change > tolerance {
+                regressions.push(format!("{} ({:+.1}%)", stats.name, change * 100.0));
+            }
+        }
+        println!("{}", line);
+        results.push(stats);
+    }
+
+    if let Ok(path) = std::env::var("BENCH_SAVE") {
+        let contents = serde_json::to_string_pretty(&results).expect("stats serialize");
+        std::fs::write(&path, contents).unwrap_or_else(|e| panic!("writing {}: {}", path, e));
+        println!("Saved baseline to {}", path);
+    }
+    if !regressions.is_empty() {
+        eprintln!("Regressed beyond {:.0}%: {}", tolerance * 100.0, regressions.join(", "));
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,36 @@
+//! Embeds the numbered SQL files from ../migrations so the worker can apply
+//! pending schema migrations itself (see src/migrations.rs)
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn main() {
+    let migrations_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../migrations");
+    println!("cargo:rerun-if-changed={}", migrations_dir.display());
+
+    let mut files: Vec<PathBuf> = fs::read_dir(&migrations_dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "sql"))
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+
+    let mut generated = String::from("pub const MIGRATIONS: &[Migration] = &[\n");
+    for path in &files {
+        let name = path.file_name().unwrap().to_string_lossy();
+        let absolute = path.canonicalize().unwrap();
+        generated.push_str(&format!(
+            "    Migration {{ name: {:?}, sql: include_str!({:?}) }},\n",
+            name,
+            absolute.display().to_string()
+        ));
+    }
+    generated.push_str("];\n");
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("migrations.rs");
+    fs::write(out, generated).unwrap();
+}
@@ -0,0 +1,271 @@
+//! Webhook ingestion API client
+//! Typed calls to the versioned management API (`/api/v1`), for tools and
+//! tests that would otherwise hand-roll JSON. The client builds requests and
+//! decodes answers into `webhook-types`; sending them is up to a `Transport`,
+//! so it runs on any HTTP stack (a blocking reqwest or ureq client, a Workers
+//! fetch, a test double) without pulling one in. No transport is bundled; one
+//! over a blocking reqwest client is a few lines:
+//!
+//! ```ignore
+//! struct Reqwest(reqwest::blocking::Client);
+//!
+//! impl Transport for Reqwest {
+//!     fn send(&self, request: HttpRequest) -> Result<HttpResponse, String> {
+//!         let method = reqwest::Method::from_bytes(request.method.as_bytes()).map_err(|e| e.to_string())?;
+//!         let mut builder = self.0.request(method, &request.url);
+//!         for (name, value) in request.headers {
+//!             builder = builder.header(name, value);
+//!         }
+//!         if let Some(body) = request.body {
+//!             builder = builder.body(body);
+//!         }
+//!         let response = builder.send().map_err(|e| e.to_string())?;
+//!         let status = response.status().as_u16();
+//!         Ok(HttpResponse { status, body: response.text().map_err(|e| e.to_string())? })
+//!     }
+//! }
+//! ```
+//!
+//! The client covers part of the API: captured requests, webhook config
+//! (read, patch, dry run), simulation and fixtures. Other `/api/v1` routes
+//! (inbox, replays, exports, tokens, ...) still need a hand-built request.
+//! Path segments (UUIDs, fixture names) are percent-encoded.
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use webhook_types::captures::RequestList;
+use webhook_types::config::ConfigView;
+use webhook_types::fixtures::{Fixture, FixtureReport, FixtureResult, NewFixture};
+use webhook_types::simulate::{Sample, Simulation};
+use webhook_types::{API_VERSION, API_VERSION_HEADER};
+
+pub use webhook_types as types;
+
+/// Characters escaped in a path segment: the URL standard's path set, plus `/` and `%`
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}')
+    .add(b'/')
+    .add(b'%');
+
+/// Path to a webhook route, with `segments` (UUID, names) percent-encoded
+fn webhook_path(segments: &[&str], suffix: &str) -> String {
+    let mut path = "/webhooks".to_string();
+    for segment in segments {
+        path.push('/');
+        path.extend(utf8_percent_encode(segment, SEGMENT));
+    }
+    path + suffix
+}
+
+/// A request for the transport to send
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpRequest {
+    pub method: &'static str,
+    pub url: String,
+    pub headers: Vec<(&'static str, String)>,
+    /// JSON body
+    pub body: Option<String>,
+}
+
+/// What the transport got back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+/// Sends requests; an `Err` is a request that got no answer at all
+pub trait Transport {
+    fn send(&self, request: HttpRequest) -> std::result::Result<HttpResponse, String>;
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The request got no answer
+    Transport(String),
+    /// The API refused the request; `message` is the answer's body
+    Api { status: u16, message: String },
+    /// The answer wasn't what the call expects
+    Decode(serde_json::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transport(message) => write!(f, "Request failed: {}", message),
+            Self::Api { status, message } => write!(f, "API error {}: {}", status, message),
+            Self::Decode(e) => write!(f, "Unexpected response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Filters and paging for `list_requests`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestQuery {
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+    /// Unix seconds range
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    /// Equality filters on indexed columns, e.g. `("event_type", "invoice.paid")`
+    pub filters: Vec<(String, String)>,
+}
+
+impl RequestQuery {
+    fn to_query(&self) -> String {
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        let numbers = [
+            ("limit", self.limit.map(i64::from)),
+            ("offset", self.offset.map(i64::from)),
+            ("since", self.since),
+            ("until", self.until),
+        ];
+        for (name, value) in numbers {
+            if let Some(value) = value {
+                query.append_pair(name, &value.to_string());
+            }
+        }
+        query.extend_pairs(&self.filters);
+        query.finish()
+    }
+}
+
+#[derive(Serialize)]
+struct SimulateBody<'a> {
+    request: &'a Sample,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    config: Option<&'a Value>,
+}
+
+#[derive(Deserialize)]
+struct FixtureList {
+    fixtures: Vec<Fixture>,
+}
+
+#[derive(Deserialize)]
+struct SavedFixture {
+    fixture: Fixture,
+}
+
+/// Client for one worker, authenticated with one token
+pub struct Client<T> {
+    base_url: String,
+    token: String,
+    transport: T,
+}
+
+impl<T: Transport> Client<T> {
+    /// `base_url` is the worker's origin, e.g. `https://hooks.example.com`
+    pub fn new(base_url: &str, token: &str, transport: T) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            transport,
+        }
+    }
+
+    fn call<R: DeserializeOwned>(&self, method: &'static str, path: &str, body: Option<String>) -> Result<R> {
+        let mut headers = vec![
+            ("Authorization", format!("Bearer {}", self.token)),
+            (API_VERSION_HEADER, API_VERSION.to_string()),
+        ];
+        if body.is_some() {
+            headers.push(("Content-Type", "application/json".to_string()));
+        }
+        let request = HttpRequest {
+            method,
+            url: format!("{}/api/v{}{}", self.base_url, API_VERSION, path),
+            headers,
+            body,
+        };
+        let response = self.transport.send(request).map_err(Error::Transport)?;
+        if !(200..300).contains(&response.status) {
+            return Err(Error::Api {
+                status: response.status,
+                message: response.body,
+            });
+        }
+        serde_json::from_str(&response.body).map_err(Error::Decode)
+    }
+
+    fn send_json<B: Serialize, R: DeserializeOwned>(&self, method: &'static str, path: &str, body: &B) -> Result<R> {
+        let body = serde_json::to_string(body).map_err(Error::Decode)?;
+        self.call(method, path, Some(body))
+    }
+
+    /// Captured requests, newest first
+    pub fn list_requests(&self, uuid: &str, query: &RequestQuery) -> Result<RequestList> {
+        let query = query.to_query();
+        let path = if query.is_empty() {
+            webhook_path(&[uuid], "/requests")
+        } else {
+            webhook_path(&[uuid], &format!("/requests?{}", query))
+        };
+        self.call("GET", &path, None)
+    }
+
+    /// The webhook's config, secrets redacted
+    pub fn config(&self, uuid: &str) -> Result<ConfigView> {
+        self.call("GET", &webhook_path(&[uuid], "/config"), None)
+    }
+
+    /// Merge `patch` into the config and save it
+    pub fn update_config(&self, uuid: &str, patch: &Value) -> Result<ConfigView> {
+        self.send_json("PATCH", &webhook_path(&[uuid], "/config"), patch)
+    }
+
+    /// The config `patch` would produce and its diff, without saving it
+    pub fn preview_config(&self, uuid: &str, patch: &Value) -> Result<ConfigView> {
+        self.send_json("PATCH", &webhook_path(&[uuid], "/config?dry_run=true"), patch)
+    }
+
+    /// Run a sample through the pipeline, optionally with a config patch applied first
+    pub fn simulate(&self, uuid: &str, sample: &Sample, patch: Option<&Value>) -> Result<Simulation> {
+        let body = SimulateBody {
+            request: sample,
+            config: patch,
+        };
+        self.send_json("POST", &webhook_path(&[uuid], "/simulate"), &body)
+    }
+
+    pub fn fixtures(&self, uuid: &str) -> Result<Vec<Fixture>> {
+        let list: FixtureList = self.call("GET", &webhook_path(&[uuid], "/fixtures"), None)?;
+        Ok(list.fixtures)
+    }
+
+    /// Save a fixture, replacing the one with its name
+    pub fn save_fixture(&self, uuid: &str, fixture: &NewFixture) -> Result<Fixture> {
+        let saved: SavedFixture = self.send_json("POST", &webhook_path(&[uuid], "/fixtures"), fixture)?;
+        Ok(saved.fixture)
+    }
+
+    pub fn delete_fixture(&self, uuid: &str, name: &str) -> Result<()> {
+        let _: Value = self.call("DELETE", &webhook_path(&[uuid, "fixtures", name], ""), None)?;
+        Ok(())
+    }
+
+    /// Run a fixture against the current config
+    pub fn run_fixture(&self, uuid: &str, name: &str) -> Result<FixtureResult> {
+        self.call("POST", &webhook_path(&[uuid, "fixtures", name], "/run"), None)
+    }
+
+    /// Run all of the webhook's fixtures
+    pub fn run_fixtures(&self, uuid: &str) -> Result<FixtureReport> {
+        self.call("POST", &webhook_path(&[uuid], "/fixtures/run"), None)
+    }
+}
@@ -0,0 +1,86 @@
+//! Typed calls against a scripted transport
+
+use std::cell::RefCell;
+use webhook_client::types::fixtures::NewFixture;
+use webhook_client::{Client, Error, HttpRequest, HttpResponse, RequestQuery, Transport};
+
+/// Answers each request with the next scripted response, keeping what was sent
+struct Scripted {
+    responses: RefCell<Vec<HttpResponse>>,
+    sent: RefCell<Vec<HttpRequest>>,
+}
+
+impl Scripted {
+    fn new(responses: &[(u16, &str)]) -> Self {
+        Self {
+            responses: RefCell::new(
+                responses
+                    .iter()
+                    .rev()
+                    .map(|(status, body)| HttpResponse { status: *status, body: body.to_string() })
+                    .collect(),
+            ),
+            sent: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl Transport for &Scripted {
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse, String> {
+        self.sent.borrow_mut().push(request);
+        self.responses.borrow_mut().pop().ok_or_else(|| "no response scripted".to_string())
+    }
+}
+
+#[test]
+fn calls_the_versioned_api_and_decodes_typed_answers() {
+    let transport = Scripted::new(&[
+        (
+            200,
+            r#"{"webhook_id": "abc", "limit": 1, "offset": 0, "requests": [{"id": "req_1", "webhook_id": "wh_1",
+                "method": "POST", "headers": "{}", "data": "{}", "size_bytes": 2, "received_at": 1760000000,
+                "received_at_iso": "2025-10-09T08:53:20Z", "event_type": "invoice.paid"}]}"#,
+        ),
+        (
+            200,
+            r#"{"name": "paid", "passed": false, "webhook_id": "abc", "version": 3,
+                "failures": [{"field": "status", "expected": 202, "actual": 200}]}"#,
+        ),
+        (400, "Fixture names are 1-64 letters, digits, '-' or '_'"),
+    ]);
+    let client = Client::new("https://hooks.example.com/", "tok", &transport);
+
+    let query = RequestQuery {
+        limit: Some(1),
+        filters: vec![("event_type".to_string(), "invoice.paid".to_string())],
+        ..RequestQuery::default()
+    };
+    let list = client.list_requests("abc", &query).unwrap();
+    assert_eq!(list.requests[0].event_type.as_deref(), Some("invoice.paid"));
+    let result = client.run_fixture("abc", "paid").unwrap();
+    assert!(!result.passed);
+    assert_eq!(result.failures[0].field, "status");
+    let refused = client.save_fixture("abc", &NewFixture { name: "no spaces".to_string(), ..NewFixture::default() });
+    assert!(matches!(refused, Err(Error::Api { status: 400, .. })));
+
+    let sent = transport.sent.borrow();
+    assert_eq!(sent[0].url, "https://hooks.example.com/api/v1/webhooks/abc/requests?limit=1&event_type=invoice.paid");
+    assert_eq!(sent[1].method, "POST");
+    assert_eq!(sent[1].url, "https://hooks.example.com/api/v1/webhooks/abc/fixtures/paid/run");
+    assert!(sent[0].headers.contains(&("Authorization", "Bearer tok".to_string())));
+    assert!(sent[0].headers.contains(&("API-Version", "1".to_string())));
+    assert_eq!(sent[2].body.as_deref(), Some(r#"{"name":"no spaces","expect":{}}"#));
+}
+
+#[test]
+fn path_segments_are_percent_encoded() {
+    let transport = Scripted::new(&[(404, "Fixture not found"), (404, "Webhook not found")]);
+    let client = Client::new("https://hooks.example.com", "tok", &transport);
+
+    assert!(client.delete_fixture("abc", "../config?x=1#y").is_err());
+    assert!(client.config("a/b c").is_err());
+
+    let sent = transport.sent.borrow();
+    assert_eq!(sent[0].url, "https://hooks.example.com/api/v1/webhooks/abc/fixtures/..%2Fconfig%3Fx=1%23y");
+    assert_eq!(sent[1].url, "https://hooks.example.com/api/v1/webhooks/a%2Fb%20c/config");
+}
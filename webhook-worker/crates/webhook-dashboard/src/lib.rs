@@ -0,0 +1,37 @@
+//! Dashboard wasm exports
+//! `wasm-pack build --target web crates/webhook-dashboard` packages the
+//! worker's preview functions (see `src/dashboard.rs`) for the browser. Each
+//! takes and returns JSON strings; errors are thrown as strings.
+
+use wasm_bindgen::prelude::*;
+use webhook_ingestion::dashboard;
+
+/// `valid`, `missing`, `invalid` or `replay_suspected` for a pasted delivery
+#[wasm_bindgen(js_name = verifySignature)]
+pub fn verify_signature(preview: &str) -> Result<String, String> {
+    dashboard::verify_signature(preview)
+}
+
+/// Field paths and JSON types of a body
+#[wasm_bindgen(js_name = inferShape)]
+pub fn infer_shape(body: &str) -> Result<String, String> {
+    dashboard::infer_shape(body)
+}
+
+/// Fields added, removed and changed type between two shapes
+#[wasm_bindgen(js_name = diffShapes)]
+pub fn diff_shapes(previous: &str, current: &str) -> Result<String, String> {
+    dashboard::diff_shapes(previous, current)
+}
+
+/// The docs page for capture rows
+#[wasm_bindgen(js_name = inferDocs)]
+pub fn infer_docs(webhook_id: &str, captures: &str) -> Result<String, String> {
+    dashboard::infer_docs(webhook_id, captures)
+}
+
+/// Changed values between two JSON documents, by dot path
+#[wasm_bindgen(js_name = diffJson)]
+pub fn diff_json(before: &str, after: &str) -> Result<String, String> {
+    dashboard::diff_json(before, after)
+}
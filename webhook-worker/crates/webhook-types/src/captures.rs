@@ -0,0 +1,63 @@
+//! Captured requests
+
+use serde::{Deserialize, Serialize};
+
+/// A captured request as returned by the management API
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StoredRequest {
+    pub id: String,
+    pub webhook_id: String,
+    pub method: String,
+    pub headers: String,
+    pub data: String,
+    pub size_bytes: i64,
+    pub received_at: i64,
+    pub received_at_ms: Option<i64>,
+    pub event_time: Option<i64>,
+    pub sequence: Option<i64>,
+    pub content_type: Option<String>,
+    pub user_agent: Option<String>,
+    pub signature: Option<String>,
+    pub idempotency_key: Option<String>,
+    pub event_type: Option<String>,
+    pub verification: Option<String>,
+    pub environment: Option<String>,
+    pub trailers: Option<String>,
+    pub connection_id: Option<String>,
+    pub frame_type: Option<String>,
+    pub processing: Option<String>,
+    pub preview: Option<String>,
+    pub charset: Option<String>,
+    pub original_body: Option<String>,
+    pub canonical_data: Option<String>,
+    pub svix_id: Option<String>,
+    pub svix_timestamp: Option<i64>,
+    pub shopify_topic: Option<String>,
+    pub shopify_shop_domain: Option<String>,
+    pub oauth_exchange: Option<String>,
+    pub replay_of: Option<String>,
+    pub correlation_id: Option<String>,
+    pub github_event: Option<String>,
+    pub github_delivery: Option<String>,
+    pub github_installation_id: Option<String>,
+    pub github_repository: Option<String>,
+    pub github_installation: Option<String>,
+    pub stripe_cross_check: Option<String>,
+    /// Deliveries this capture stands for once heartbeats are folded into it, and when the
+    /// last arrived; None for a single delivery
+    pub repeat_count: Option<i64>,
+    pub last_seen_at_ms: Option<i64>,
+    /// Inbox state: first fetched by a consumer / acknowledged
+    pub read_at_ms: Option<i64>,
+    pub acked_at_ms: Option<i64>,
+}
+
+/// `GET /api/v1/webhooks/{uuid}/requests`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RequestList {
+    /// Webhook UUID
+    pub webhook_id: String,
+    pub requests: Vec<StoredRequest>,
+    pub limit: u32,
+    pub offset: u32,
+}
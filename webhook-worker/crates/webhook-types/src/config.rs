@@ -0,0 +1,30 @@
+//! Webhook configs
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// One changed config value; None where the value is unset
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Change {
+    /// Dot path of the value, e.g. `signature.tolerance_seconds`
+    pub path: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+/// `GET` and `PATCH /api/v1/webhooks/{uuid}/config`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ConfigView {
+    /// Webhook UUID
+    pub webhook_id: String,
+    /// The config with secrets redacted
+    pub config: Value,
+    pub version: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_secret: Option<bool>,
+    /// Set on a `?dry_run=true` patch, which answers with its diff instead of saving
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub diff: Vec<Change>,
+}
@@ -0,0 +1,86 @@
+//! Pipeline fixtures
+
+use crate::simulate::Sample;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// What a fixture run should produce; unset fields aren't checked
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Expect {
+    /// Stored as a capture (false: refused, e.g. by the signed URL check)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stored: Option<bool>,
+    /// Forwarded to at least one target
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub forwarded: Option<bool>,
+    /// Exactly these forwarding targets, in order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub forwards: Option<Vec<String>>,
+    /// Status the sender gets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_type: Option<String>,
+    /// Event type pattern of the matching route
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route: Option<String>,
+}
+
+/// An expectation the run didn't meet
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Failure {
+    pub field: String,
+    pub expected: Value,
+    pub actual: Value,
+}
+
+/// A saved fixture
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Fixture {
+    pub name: String,
+    pub request: Sample,
+    pub expect: Expect,
+    /// Capture the sample was saved from
+    pub capture_id: Option<String>,
+    pub actor: String,
+    pub created_at_ms: i64,
+}
+
+/// `POST /api/v1/webhooks/{uuid}/fixtures`: a capture (`capture_id`) or a sample (`request`) to save
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewFixture {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capture_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<Sample>,
+    #[serde(default)]
+    pub expect: Expect,
+}
+
+/// One fixture run; `error` instead of an outcome when the sample couldn't be simulated
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FixtureResult {
+    pub name: String,
+    pub passed: bool,
+    #[serde(default)]
+    pub failures: Vec<Failure>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// `POST /api/v1/webhooks/{uuid}/fixtures/run`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FixtureReport {
+    /// Webhook UUID
+    pub webhook_id: String,
+    /// Config version the fixtures ran against
+    pub version: i64,
+    pub passed: usize,
+    pub failed: usize,
+    pub results: Vec<FixtureResult>,
+}
@@ -0,0 +1,16 @@
+//! Webhook ingestion API types
+//! The request and response bodies of the management API, shared by the
+//! worker that serves them and the clients that call it (`webhook-client`).
+//! Responses may carry fields these types don't name (readable `_iso`
+//! timestamps, fields added later); they are ignored when deserializing.
+
+pub mod captures;
+pub mod config;
+pub mod fixtures;
+pub mod simulate;
+
+/// Header naming the requested or served API version
+pub const API_VERSION_HEADER: &str = "API-Version";
+
+/// API version these types describe
+pub const API_VERSION: u32 = 1;
@@ -0,0 +1,64 @@
+//! Pipeline simulation
+
+use crate::config::Change;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+fn default_method() -> String {
+    "POST".to_string()
+}
+
+/// A delivery to simulate: `{"method"?, "headers"?, "body"?, "query"?, "environment"?}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Sample {
+    #[serde(default = "default_method")]
+    pub method: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// A string is sent as is, anything else as JSON
+    #[serde(default)]
+    pub body: Option<Value>,
+    /// Query string of the capture URL, e.g. `exp=...&sig=...`
+    #[serde(default)]
+    pub query: Option<String>,
+    /// Name of the environment whose capture URL the delivery arrives on
+    #[serde(default)]
+    pub environment: Option<String>,
+}
+
+impl Default for Sample {
+    fn default() -> Self {
+        Self {
+            method: default_method(),
+            headers: HashMap::new(),
+            body: None,
+            query: None,
+            environment: None,
+        }
+    }
+}
+
+/// The answer the sender would get
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Answer {
+    pub status: u16,
+    pub content_type: String,
+    pub body: String,
+    /// Extra headers a configured response sets
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+/// `POST /api/v1/webhooks/{uuid}/simulate`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Simulation {
+    /// Webhook UUID
+    pub webhook_id: String,
+    /// Config version the patch was applied to
+    pub version: i64,
+    pub diff: Vec<Change>,
+    /// `accepted`, `processing`, `capture` and `response`
+    pub outcome: Value,
+}
@@ -0,0 +1,68 @@
+-- Postgres schema for the Hyperdrive storage backend
+-- Mirrors the D1 webhook_data table; webhook definitions stay in D1
+
+CREATE TABLE IF NOT EXISTS webhook_data (
+  id TEXT PRIMARY KEY,
+  webhook_id TEXT NOT NULL,
+  method TEXT NOT NULL,
+  headers TEXT NOT NULL,
+  data TEXT NOT NULL,
+  size_bytes BIGINT NOT NULL,
+  received_at BIGINT NOT NULL,
+  received_at_ms BIGINT,
+  event_time BIGINT,
+  sequence BIGINT,
+  content_type TEXT,
+  user_agent TEXT,
+  signature TEXT,
+  idempotency_key TEXT,
+  event_type TEXT,
+  verification TEXT,
+  environment TEXT,
+  trailers TEXT,
+  connection_id TEXT,
+  frame_type TEXT,
+  processing TEXT,
+  preview TEXT,
+  charset TEXT,
+  original_body TEXT,
+  canonical_data TEXT,
+  svix_id TEXT,
+  svix_timestamp BIGINT,
+  github_event TEXT,
+  github_delivery TEXT,
+  github_installation_id TEXT,
+  github_repository TEXT,
+  github_installation TEXT,
+  stripe_cross_check TEXT,
+  shopify_topic TEXT,
+  shopify_shop_domain TEXT,
+  oauth_exchange TEXT,
+  replay_of TEXT,
+  correlation_id TEXT,
+  repeat_count BIGINT,
+  last_seen_at_ms BIGINT,
+  read_at_ms BIGINT,
+  acked_at_ms BIGINT,
+  lease_until_ms BIGINT
+);
+
+CREATE INDEX IF NOT EXISTS webhook_data_webhook_received_idx ON webhook_data(webhook_id, received_at DESC);
+CREATE INDEX IF NOT EXISTS webhook_data_received_at_ms_idx ON webhook_data(webhook_id, received_at_ms);
+CREATE INDEX IF NOT EXISTS webhook_data_event_time_idx ON webhook_data(webhook_id, event_time);
+CREATE INDEX IF NOT EXISTS webhook_data_sequence_idx ON webhook_data(webhook_id, sequence);
+CREATE INDEX IF NOT EXISTS webhook_data_content_type_idx ON webhook_data(webhook_id, content_type);
+CREATE INDEX IF NOT EXISTS webhook_data_user_agent_idx ON webhook_data(webhook_id, user_agent);
+CREATE INDEX IF NOT EXISTS webhook_data_signature_idx ON webhook_data(webhook_id, signature);
+CREATE INDEX IF NOT EXISTS webhook_data_idempotency_key_idx ON webhook_data(webhook_id, idempotency_key);
+CREATE INDEX IF NOT EXISTS webhook_data_event_type_idx ON webhook_data(webhook_id, event_type);
+CREATE INDEX IF NOT EXISTS webhook_data_verification_idx ON webhook_data(webhook_id, verification);
+CREATE INDEX IF NOT EXISTS webhook_data_inbox_idx ON webhook_data(webhook_id, acked_at_ms, lease_until_ms);
+CREATE INDEX IF NOT EXISTS webhook_data_environment_idx ON webhook_data(webhook_id, environment);
+CREATE INDEX IF NOT EXISTS webhook_data_connection_idx ON webhook_data(webhook_id, connection_id);
+CREATE INDEX IF NOT EXISTS webhook_data_svix_id_idx ON webhook_data(webhook_id, svix_id);
+CREATE INDEX IF NOT EXISTS webhook_data_github_repository_idx ON webhook_data(webhook_id, github_repository);
+CREATE INDEX IF NOT EXISTS webhook_data_github_installation_idx ON webhook_data(webhook_id, github_installation_id);
+CREATE INDEX IF NOT EXISTS webhook_data_shopify_idx ON webhook_data(webhook_id, shopify_shop_domain, shopify_topic);
+CREATE INDEX IF NOT EXISTS webhook_data_replay_of_idx ON webhook_data(webhook_id, replay_of);
+CREATE INDEX IF NOT EXISTS webhook_data_correlation_id_idx ON webhook_data(webhook_id, correlation_id);
@@ -0,0 +1,257 @@
+//! UUID enumeration and honeypot detection
+//! Every capture to an unknown UUID is recorded per client IP. An IP that misses
+//! `ENUMERATION_THRESHOLD` distinct UUIDs within `ENUMERATION_WINDOW_SECONDS`, or
+//! that hits one of the `DECOY_UUIDS` honeypots, is flagged in `abuse_scanners`
+//! and gets the `DECOY_MODE` response from then on.
+
+use crate::ids;
+use crate::storage::optional_str;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use wasm_bindgen::JsValue;
+use worker::*;
+
+const DEFAULT_THRESHOLD: i64 = 20;
+const DEFAULT_WINDOW_SECONDS: i64 = 3600;
+
+/// How long the tarpit holds a flagged scanner's connection
+const TARPIT_DELAY: Duration = Duration::from_secs(5);
+
+/// Misses older than this are pruned by the scheduled handler
+const MISS_RETENTION_MS: i64 = 7 * 86_400_000;
+
+/// Response given to flagged scanners
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecoyMode {
+    /// Plain 404, same as any unknown UUID
+    NotFound,
+    /// Fake capture success, so found endpoints can't be told apart from real ones
+    Accept,
+    /// Slow 404 to waste the scanner's time
+    Tarpit,
+}
+
+impl DecoyMode {
+    /// `DECOY_MODE` value selecting this mode
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::NotFound => "404",
+            Self::Accept => "accept",
+            Self::Tarpit => "tarpit",
+        }
+    }
+}
+
+/// Detection settings from the environment
+pub struct AbuseConfig {
+    pub threshold: i64,
+    pub window_ms: i64,
+    pub mode: DecoyMode,
+    decoys: Vec<String>,
+}
+
+impl AbuseConfig {
+    pub fn from_env(env: &Env) -> Self {
+        let var = |name: &str| env.var(name).ok().map(|value| value.to_string());
+        Self {
+            threshold: var("ENUMERATION_THRESHOLD")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(DEFAULT_THRESHOLD),
+            window_ms: var("ENUMERATION_WINDOW_SECONDS")
+                .and_then(|value| value.parse::<i64>().ok())
+                .unwrap_or(DEFAULT_WINDOW_SECONDS)
+                * 1000,
+            mode: match var("DECOY_MODE").as_deref() {
+                Some("accept") => DecoyMode::Accept,
+                Some("tarpit") => DecoyMode::Tarpit,
+                _ => DecoyMode::NotFound,
+            },
+            decoys: var("DECOY_UUIDS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|uuid| uuid.trim().to_string())
+                .filter(|uuid| !uuid.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Whether a UUID is a honeypot that no legitimate sender knows
+    pub fn is_decoy(&self, uuid: &str) -> bool {
+        self.decoys.iter().any(|decoy| decoy == uuid)
+    }
+}
+
+/// A flagged scanner, as listed by the admin report
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Scanner {
+    pub ip: String,
+    pub first_seen_ms: i64,
+    pub last_seen_ms: i64,
+    pub distinct_uuids: i64,
+    pub decoy_hits: i64,
+    pub last_uuid: Option<String>,
+    pub user_agent: Option<String>,
+    pub flagged_at_ms: i64,
+}
+
+#[derive(Deserialize)]
+struct CountRow {
+    count: i64,
+}
+
+/// Client IP as seen by Cloudflare
+pub fn client_ip(req: &Request) -> Option<String> {
+    req.headers().get("CF-Connecting-IP").ok().flatten()
+}
+
+/// What a miss means for the IP that sent it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Miss {
+    /// Below the threshold; answered like any unknown UUID
+    Counted,
+    /// This miss got the IP flagged
+    Flagged { distinct_uuids: i64, decoy: bool },
+    /// Already flagged earlier
+    Blocked,
+}
+
+/// Record a request to an unknown (or decoy) UUID and whether it flags the IP
+pub async fn record_miss(
+    db: &D1Database,
+    config: &AbuseConfig,
+    ip: &str,
+    uuid: &str,
+    user_agent: Option<&String>,
+    now_ms: i64,
+) -> Result<Miss> {
+    let decoy = config.is_decoy(uuid);
+
+    db.prepare(
+        "INSERT INTO enumeration_misses (ip, uuid, seen_at_ms) VALUES (?1, ?2, ?3) \
+         ON CONFLICT (ip, uuid) DO UPDATE SET seen_at_ms = excluded.seen_at_ms",
+    )
+    .bind(&[
+        JsValue::from_str(ip),
+        JsValue::from_str(uuid),
+        JsValue::from_f64(now_ms as f64),
+    ])?
+    .run()
+    .await?;
+
+    let distinct = db
+        .prepare("SELECT COUNT(*) AS count FROM enumeration_misses WHERE ip = ?1 AND seen_at_ms >= ?2")
+        .bind(&[
+            JsValue::from_str(ip),
+            JsValue::from_f64((now_ms - config.window_ms) as f64),
+        ])?
+        .first::<CountRow>(None)
+        .await?
+        .map(|row| row.count)
+        .unwrap_or(0);
+
+    let already_flagged = db
+        .prepare("SELECT COUNT(*) AS count FROM abuse_scanners WHERE ip = ?1")
+        .bind(&[JsValue::from_str(ip)])?
+        .first::<CountRow>(None)
+        .await?
+        .is_some_and(|row| row.count > 0);
+
+    if !(decoy || already_flagged || distinct >= config.threshold) {
+        return Ok(Miss::Counted);
+    }
+
+    if !already_flagged {
+        log_info!(
+            "🚨 Flagging {} as a UUID scanner ({} distinct misses, decoy: {})",
+            ip,
+            distinct,
+            decoy
+        );
+    }
+
+    db.prepare(
+        "INSERT INTO abuse_scanners \
+         (ip, first_seen_ms, last_seen_ms, distinct_uuids, decoy_hits, last_uuid, user_agent, flagged_at_ms) \
+         VALUES (?1, ?2, ?2, ?3, ?4, ?5, ?6, ?2) \
+         ON CONFLICT (ip) DO UPDATE SET last_seen_ms = excluded.last_seen_ms, \
+         distinct_uuids = MAX(distinct_uuids, excluded.distinct_uuids), \
+         decoy_hits = decoy_hits + excluded.decoy_hits, \
+         last_uuid = excluded.last_uuid, user_agent = excluded.user_agent",
+    )
+    .bind(&[
+        JsValue::from_str(ip),
+        JsValue::from_f64(now_ms as f64),
+        JsValue::from_f64(distinct as f64),
+        JsValue::from_f64(if decoy { 1.0 } else { 0.0 }),
+        JsValue::from_str(uuid),
+        optional_str(&user_agent.cloned()),
+    ])?
+    .run()
+    .await?;
+
+    Ok(if already_flagged {
+        Miss::Blocked
+    } else {
+        Miss::Flagged {
+            distinct_uuids: distinct,
+            decoy,
+        }
+    })
+}
+
+/// Response for a flagged scanner
+pub async fn decoy_response(mode: DecoyMode, uuid: &str, method: &str, received_at_ms: i64) -> Result<Response> {
+    match mode {
+        DecoyMode::NotFound => Response::error("Webhook not found", 404),
+        DecoyMode::Tarpit => {
+            Delay::from(TARPIT_DELAY).await;
+            Response::error("Webhook not found", 404)
+        }
+        DecoyMode::Accept => {
+            let mut response = Response::from_json(&serde_json::json!({
+                "success": true,
+                "message": "Webhook received",
+                "webhook_id": uuid,
+                "data_id": ids::ulid(received_at_ms),
+                "method": method,
+                "received_at": received_at_ms / 1000,
+                "received_at_ms": received_at_ms,
+            }))?;
+            crate::set_cors_headers(response.headers_mut())?;
+            Ok(response)
+        }
+    }
+}
+
+/// Flagged scanners, most recently active first
+pub async fn list_scanners(db: &D1Database, limit: u32, offset: u32) -> Result<Vec<Scanner>> {
+    db.prepare(
+        "SELECT ip, first_seen_ms, last_seen_ms, distinct_uuids, decoy_hits, last_uuid, user_agent, flagged_at_ms \
+         FROM abuse_scanners ORDER BY last_seen_ms DESC LIMIT ?1 OFFSET ?2",
+    )
+    .bind(&[JsValue::from_f64(limit as f64), JsValue::from_f64(offset as f64)])?
+    .all()
+    .await?
+    .results::<Scanner>()
+}
+
+/// Remove a scanner flag (e.g. a false positive)
+pub async fn clear_scanner(db: &D1Database, ip: &str) -> Result<()> {
+    db.batch(vec![
+        db.prepare("DELETE FROM abuse_scanners WHERE ip = ?1")
+            .bind(&[JsValue::from_str(ip)])?,
+        db.prepare("DELETE FROM enumeration_misses WHERE ip = ?1")
+            .bind(&[JsValue::from_str(ip)])?,
+    ])
+    .await?;
+    Ok(())
+}
+
+/// Drop old miss records (scheduled)
+pub async fn prune(db: &D1Database, now_ms: i64) -> Result<()> {
+    db.prepare("DELETE FROM enumeration_misses WHERE seen_at_ms < ?1")
+        .bind(&[JsValue::from_f64((now_ms - MISS_RETENTION_MS) as f64)])?
+        .run()
+        .await?;
+    Ok(())
+}
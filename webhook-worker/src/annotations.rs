@@ -0,0 +1,224 @@
+//! Capture annotations
+//! External systems that consume deliveries (a CI job, a queue worker,
+//! monitoring) report back what they did with one by annotating its capture:
+//! `POST /api/webhooks/{uuid}/requests/{id}/annotations` with a `source`, a
+//! `status`, a message and optionally a link and structured `data`.
+//! Annotations are stored in the `annotations` table and returned, oldest
+//! first, with the capture's detail next to its forward responses, so the
+//! timeline shows both what arrived and what became of it. Like responses,
+//! they are kept `RETENTION_DAYS`.
+
+use crate::ids;
+use crate::storage::optional_str;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Days of annotations kept
+pub const RETENTION_DAYS: i64 = 30;
+
+/// Most annotations one capture takes
+pub const MAX_PER_CAPTURE: usize = 100;
+
+const MAX_SOURCE_CHARS: usize = 64;
+const MAX_MESSAGE_CHARS: usize = 2000;
+const MAX_URL_CHARS: usize = 2048;
+/// Largest `data` object, serialized
+const MAX_DATA_BYTES: usize = 8 * 1024;
+
+/// How the annotated processing went
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    #[default]
+    Info,
+    Success,
+    Warning,
+    Failure,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Success => "success",
+            Self::Warning => "warning",
+            Self::Failure => "failure",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "info" => Some(Self::Info),
+            "success" => Some(Self::Success),
+            "warning" => Some(Self::Warning),
+            "failure" => Some(Self::Failure),
+            _ => None,
+        }
+    }
+}
+
+/// An annotation as posted: `{"source": "ci", "status": "failure", "message": "job #123 failed: ...",
+/// "url"?, "data"?}`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct NewAnnotation {
+    pub source: String,
+    #[serde(default)]
+    pub status: Status,
+    pub message: String,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub data: Option<serde_json::Value>,
+}
+
+impl NewAnnotation {
+    /// What is wrong with the annotation; None when it can be stored
+    pub fn validate(&self) -> Option<String> {
+        let source = self.source.trim();
+        if source.is_empty() || source.chars().count() > MAX_SOURCE_CHARS {
+            return Some(format!("source must be 1 to {} characters", MAX_SOURCE_CHARS));
+        }
+        if self.message.trim().is_empty() || self.message.chars().count() > MAX_MESSAGE_CHARS {
+            return Some(format!("message must be 1 to {} characters", MAX_MESSAGE_CHARS));
+        }
+        if let Some(url) = &self.url {
+            let web = url.starts_with("https://") || url.starts_with("http://");
+            if !web || url.len() > MAX_URL_CHARS {
+                return Some(format!("url must be an http(s) URL of at most {} characters", MAX_URL_CHARS));
+            }
+        }
+        if self.data.as_ref().is_some_and(|data| !data.is_object()) {
+            return Some("data must be an object".to_string());
+        }
+        if self.data_json().is_some_and(|data| data.len() > MAX_DATA_BYTES) {
+            return Some(format!("data must be at most {} bytes", MAX_DATA_BYTES));
+        }
+        None
+    }
+
+    fn data_json(&self) -> Option<String> {
+        self.data.as_ref().map(|data| data.to_string())
+    }
+}
+
+/// A stored annotation
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Annotation {
+    pub id: String,
+    pub source: String,
+    pub status: Status,
+    pub message: String,
+    pub url: Option<String>,
+    pub data: Option<serde_json::Value>,
+    /// Token or user that posted it (see `auth.rs`)
+    pub actor: String,
+    pub created_at_ms: i64,
+}
+
+#[derive(Deserialize)]
+struct AnnotationRow {
+    id: String,
+    source: String,
+    status: String,
+    message: String,
+    url: Option<String>,
+    data: Option<String>,
+    actor: String,
+    created_at_ms: f64,
+}
+
+impl From<AnnotationRow> for Annotation {
+    fn from(row: AnnotationRow) -> Self {
+        Self {
+            id: row.id,
+            source: row.source,
+            status: Status::parse(&row.status).unwrap_or_default(),
+            message: row.message,
+            url: row.url,
+            data: row.data.and_then(|data| serde_json::from_str(&data).ok()),
+            actor: row.actor,
+            created_at_ms: row.created_at_ms as i64,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CountRow {
+    count: f64,
+}
+
+/// Store a validated annotation on capture `capture_id`; None when the capture has `MAX_PER_CAPTURE` already
+pub async fn add(
+    db: &D1Database,
+    webhook_id: &str,
+    capture_id: &str,
+    annotation: &NewAnnotation,
+    actor: &str,
+    now_ms: i64,
+) -> Result<Option<Annotation>> {
+    let count = db
+        .prepare("SELECT COUNT(*) AS count FROM annotations WHERE webhook_id = ?1 AND capture_id = ?2")
+        .bind(&[JsValue::from_str(webhook_id), JsValue::from_str(capture_id)])?
+        .first::<CountRow>(None)
+        .await?
+        .map_or(0, |row| row.count as usize);
+    if count >= MAX_PER_CAPTURE {
+        return Ok(None);
+    }
+
+    let stored = Annotation {
+        id: ids::ulid(now_ms),
+        source: annotation.source.trim().to_string(),
+        status: annotation.status,
+        message: annotation.message.trim().to_string(),
+        url: annotation.url.clone(),
+        data: annotation.data.clone(),
+        actor: actor.to_string(),
+        created_at_ms: now_ms,
+    };
+    db.prepare(
+        "INSERT INTO annotations (id, webhook_id, capture_id, source, status, message, url, data, actor, \
+         created_at_ms) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+    )
+    .bind(&[
+        JsValue::from_str(&stored.id),
+        JsValue::from_str(webhook_id),
+        JsValue::from_str(capture_id),
+        JsValue::from_str(&stored.source),
+        JsValue::from_str(stored.status.as_str()),
+        JsValue::from_str(&stored.message),
+        optional_str(&stored.url),
+        optional_str(&annotation.data_json()),
+        JsValue::from_str(actor),
+        JsValue::from_f64(now_ms as f64),
+    ])?
+    .run()
+    .await?;
+    Ok(Some(stored))
+}
+
+/// Annotations of one capture, oldest first
+pub async fn for_capture(db: &D1Database, webhook_id: &str, capture_id: &str) -> Result<Vec<Annotation>> {
+    Ok(db
+        .prepare(
+            "SELECT id, source, status, message, url, data, actor, created_at_ms FROM annotations \
+             WHERE webhook_id = ?1 AND capture_id = ?2 ORDER BY created_at_ms, id",
+        )
+        .bind(&[JsValue::from_str(webhook_id), JsValue::from_str(capture_id)])?
+        .all()
+        .await?
+        .results::<AnnotationRow>()?
+        .into_iter()
+        .map(Annotation::from)
+        .collect())
+}
+
+/// Drop annotations older than `RETENTION_DAYS`
+pub async fn prune(db: &D1Database, now_ms: i64) -> Result<()> {
+    db.prepare("DELETE FROM annotations WHERE created_at_ms < ?1")
+        .bind(&[JsValue::from_f64((now_ms - RETENTION_DAYS * 86_400_000) as f64)])?
+        .run()
+        .await?;
+    Ok(())
+}
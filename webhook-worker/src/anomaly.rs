@@ -0,0 +1,225 @@
+//! Ingestion volume anomaly detection
+//! Webhooks with an `anomaly` config keep a rolling baseline in
+//! `volume_baselines`: an EWMA of hourly capture counts. The scheduled handler
+//! feeds it every completed hour and compares each hour with the baseline of
+//! the hours before it; an hour `factor` times above is a spike (runaway retry
+//! loops), `factor` times below a drought (a provider that stopped sending).
+//! The current anomaly is stored with the baseline, and `notify_url` receives a
+//! POST when it starts, changes kind or ends.
+
+use crate::config::{self, AnomalyRule};
+use crate::forward;
+use crate::storage::{self, Consistency, Storage};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Hours observed before anomalies are reported
+pub const WARMUP_HOURS: u32 = 24;
+
+/// Hours replayed per check: history for a new baseline, or a gap after missed triggers
+const MAX_CATCH_UP_HOURS: i64 = 48;
+
+/// Baselines thinner than this per hour never report droughts
+const MIN_DROUGHT_BASELINE: f64 = 1.0;
+
+const HOUR: i64 = 3600;
+
+/// Rolling hourly volume
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Baseline {
+    /// Exponentially weighted mean of hourly counts
+    pub ewma: f64,
+    /// Hours observed
+    pub hours: u32,
+}
+
+impl Baseline {
+    /// Fold in one more hour
+    pub fn observe(self, count: u64, alpha: f64) -> Self {
+        let count = count as f64;
+        Self {
+            ewma: if self.hours == 0 { count } else { alpha * count + (1.0 - alpha) * self.ewma },
+            hours: self.hours.saturating_add(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Anomaly {
+    Spike,
+    Drought,
+}
+
+impl Anomaly {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Spike => "spike",
+            Self::Drought => "drought",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "spike" => Some(Self::Spike),
+            "drought" => Some(Self::Drought),
+            _ => None,
+        }
+    }
+}
+
+/// Classify an hour's count against the baseline of the hours before it
+pub fn classify(baseline: &Baseline, count: u64, factor: f64) -> Option<Anomaly> {
+    if baseline.hours < WARMUP_HOURS {
+        return None;
+    }
+    let count = count as f64;
+    if count >= factor * baseline.ewma.max(1.0) {
+        Some(Anomaly::Spike)
+    } else if baseline.ewma >= MIN_DROUGHT_BASELINE && count * factor <= baseline.ewma {
+        Some(Anomaly::Drought)
+    } else {
+        None
+    }
+}
+
+/// JSON body POSTed to `notify_url` when the anomaly state changes
+pub fn notification(
+    uuid: &str,
+    previous: Option<Anomaly>,
+    current: Option<Anomaly>,
+    hour: i64,
+    count: u64,
+    baseline: &Baseline,
+    now_ms: i64,
+) -> serde_json::Value {
+    serde_json::json!({
+        "type": format!("volume.{}", current.map_or("normal", Anomaly::as_str)),
+        "webhook_id": uuid,
+        "previous": previous,
+        "hour": hour,
+        "count": count,
+        "baseline": baseline.ewma,
+        "checked_at_ms": now_ms,
+    })
+}
+
+#[derive(Deserialize)]
+struct BaselineRow {
+    webhook_id: String,
+    ewma: f64,
+    hours: f64,
+    last_hour: f64,
+    anomaly: Option<String>,
+    anomaly_since_ms: Option<f64>,
+}
+
+/// Feed completed hours into every enabled webhook's baseline; `now` is Unix seconds
+pub async fn check(env: &Env, now: i64) -> Result<()> {
+    let db = env.d1("DB")?;
+    let rules = config::anomaly_rules(&db).await?;
+    if rules.is_empty() {
+        return Ok(());
+    }
+
+    let rows: HashMap<String, BaselineRow> = db
+        .prepare("SELECT webhook_id, ewma, hours, last_hour, anomaly, anomaly_since_ms FROM volume_baselines")
+        .all()
+        .await?
+        .results::<BaselineRow>()?
+        .into_iter()
+        .map(|row| (row.webhook_id.clone(), row))
+        .collect();
+
+    let current_hour = now - now.rem_euclid(HOUR);
+    for rule in rules {
+        let storage = storage::for_webhook(env, &rule.webhook_id, Consistency::Primary).await?;
+        if let Err(e) = observe(&db, storage.as_ref(), &rule, rows.get(&rule.webhook_id), current_hour, now * 1000).await {
+            log_warn!("⚠️  Volume baseline update for webhook {} failed: {:?}", rule.uuid, e);
+        }
+    }
+    Ok(())
+}
+
+/// Replay the webhook's unobserved hours; only the state after the last one is notified
+async fn observe(
+    db: &D1Database,
+    storage: &dyn Storage,
+    rule: &AnomalyRule,
+    row: Option<&BaselineRow>,
+    current_hour: i64,
+    now_ms: i64,
+) -> Result<()> {
+    let mut baseline = row.map_or(Baseline::default(), |row| Baseline {
+        ewma: row.ewma,
+        hours: row.hours as u32,
+    });
+    let previous = row.and_then(|row| row.anomaly.as_deref()).and_then(Anomaly::parse);
+    let mut since_ms = row.and_then(|row| row.anomaly_since_ms).map(|since| since as i64);
+
+    let first = row.map_or(i64::MIN, |row| row.last_hour as i64 + HOUR);
+    let mut hour = first.max(current_hour - MAX_CATCH_UP_HOURS * HOUR);
+    if hour >= current_hour {
+        return Ok(());
+    }
+    let (mut state, mut last_count) = (previous, 0);
+    while hour < current_hour {
+        let count = storage.count_received(&rule.webhook_id, None, hour, Some(hour + HOUR)).await?;
+        state = classify(&baseline, count, rule.anomaly.factor);
+        baseline = baseline.observe(count, rule.anomaly.alpha);
+        last_count = count;
+        hour += HOUR;
+    }
+    let last_hour = hour - HOUR;
+
+    if state != previous {
+        since_ms = state.map(|_| now_ms);
+        log_info!(
+            "📈 Webhook {} volume {} ({} in hour {}, baseline {:.1})",
+            rule.uuid,
+            state.map_or("normal", Anomaly::as_str),
+            last_count,
+            last_hour,
+            baseline.ewma
+        );
+        let body = notification(&rule.uuid, previous, state, last_hour, last_count, &baseline, now_ms);
+        forward::notify(&rule.anomaly.notify_url, &body).await;
+    }
+
+    db.prepare(
+        "INSERT INTO volume_baselines (webhook_id, ewma, hours, last_hour, anomaly, anomaly_since_ms) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6) ON CONFLICT(webhook_id) DO UPDATE SET ewma = ?2, hours = ?3, \
+         last_hour = ?4, anomaly = ?5, anomaly_since_ms = ?6",
+    )
+    .bind(&[
+        JsValue::from_str(&rule.webhook_id),
+        JsValue::from_f64(baseline.ewma),
+        JsValue::from_f64(baseline.hours as f64),
+        JsValue::from_f64(last_hour as f64),
+        state.map_or(JsValue::NULL, |state| JsValue::from_str(state.as_str())),
+        since_ms.map_or(JsValue::NULL, |since| JsValue::from_f64(since as f64)),
+    ])?
+    .run()
+    .await?;
+    Ok(())
+}
+
+/// Baseline and current anomaly of a webhook, for the management API
+pub async fn status(db: &D1Database, webhook_id: &str) -> Result<Option<serde_json::Value>> {
+    let row = db
+        .prepare("SELECT webhook_id, ewma, hours, last_hour, anomaly, anomaly_since_ms FROM volume_baselines WHERE webhook_id = ?1")
+        .bind(&[JsValue::from_str(webhook_id)])?
+        .first::<BaselineRow>(None)
+        .await?;
+    Ok(row.map(|row| {
+        serde_json::json!({
+            "baseline": row.ewma,
+            "hours": row.hours as u32,
+            "last_hour": row.last_hour as i64,
+            "anomaly": row.anomaly,
+            "anomaly_since_ms": row.anomaly_since_ms.map(|since| since as i64),
+        })
+    }))
+}
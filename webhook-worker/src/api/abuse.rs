@@ -0,0 +1,45 @@
+//! Enumeration report routes
+//!
+//! - GET    /api/admin/abuse        flagged UUID scanners (`limit`, `offset`)
+//! - DELETE /api/admin/abuse/{ip}   clear a scanner flag
+
+use crate::abuse;
+use crate::api::{json, query_param};
+use crate::audit::{self, AuditEntry};
+use crate::auth::{self, RouteData};
+use worker::*;
+
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+/// List flagged scanners
+pub async fn list(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let url = req.url()?;
+    let limit = query_param(&url, "limit")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_LIMIT)
+        .clamp(1, MAX_LIMIT);
+    let offset = query_param(&url, "offset")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0);
+
+    let db = ctx.env.d1("DB")?;
+    let scanners = abuse::list_scanners(&db, limit, offset).await?;
+    json(&serde_json::json!({
+        "scanners": scanners,
+        "limit": limit,
+        "offset": offset,
+    }))
+}
+
+/// Clear a scanner flag
+pub async fn clear(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let ip = ctx.param("ip").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+    abuse::clear_scanner(&db, &ip).await?;
+
+    let entry = AuditEntry::from_request(&req, auth::principal(&ctx)?, "abuse.clear").target(ip);
+    audit::record(&db, entry).await;
+
+    json(&serde_json::json!({ "cleared": true }))
+}
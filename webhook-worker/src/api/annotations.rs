@@ -0,0 +1,57 @@
+//! Capture annotation routes
+//!
+//! - GET  /api/webhooks/{uuid}/requests/{id}/annotations   a capture's annotations, oldest first
+//! - POST /api/webhooks/{uuid}/requests/{id}/annotations   attach one (editors):
+//!   `{"source": "ci", "status": "info" | "success" | "warning" | "failure", "message": "...", "url"?, "data"?}`
+
+use crate::annotations::{self, NewAnnotation, MAX_PER_CAPTURE};
+use crate::api::requests::find_request;
+use crate::api::{authorized_webhook, json};
+use crate::auth::{self, RouteData, Role};
+use crate::storage::{self, Consistency};
+use worker::*;
+
+pub async fn list(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let id = ctx.param("id").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let annotations = annotations::for_capture(&db, &webhook_id, &id).await?;
+    json(&serde_json::json!({ "webhook_id": uuid, "request_id": id, "annotations": annotations }))
+}
+
+pub async fn create(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let id = ctx.param("id").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let annotation: NewAnnotation = match req.json().await {
+        Ok(annotation) => annotation,
+        Err(_) => return Response::error("Expected {\"source\": \"...\", \"message\": \"...\"}", 400),
+    };
+    if let Some(problem) = annotation.validate() {
+        return Response::error(problem, 400);
+    }
+    let storage = storage::for_webhook(&ctx.env, &webhook_id, Consistency::Primary).await?;
+    if find_request(storage.as_ref(), &webhook_id, id.clone()).await?.is_none() {
+        return Response::error("Request not found", 404);
+    }
+
+    let now_ms = Date::now().as_millis() as i64;
+    match annotations::add(&db, &webhook_id, &id, &annotation, &principal.actor, now_ms).await? {
+        Some(stored) => Ok(json(&stored)?.with_status(201)),
+        None => Response::error(format!("A capture takes at most {} annotations", MAX_PER_CAPTURE), 409),
+    }
+}
@@ -0,0 +1,53 @@
+//! Audit log route
+//!
+//! - GET /api/audit  list management actions, newest first
+//!   (`action`, `actor`, `target`, `since`, `until`, `limit`, `offset`)
+
+use crate::api::{json, query_param};
+use crate::audit::{self, AuditQuery};
+use crate::auth::RouteData;
+use worker::*;
+
+const DEFAULT_LIMIT: u32 = 50;
+const MAX_LIMIT: u32 = 500;
+
+/// List audit records
+pub async fn list(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let url = req.url()?;
+    let bounds = |name: &str| match query_param(&url, name) {
+        None => Ok(None),
+        Some(value) => value
+            .parse()
+            .ok()
+            .and_then(audit::second_bounds)
+            .map(Some)
+            .ok_or_else(|| format!("{} must be Unix seconds", name)),
+    };
+    let (since, until) = match (bounds("since"), bounds("until")) {
+        (Ok(since), Ok(until)) => (since, until),
+        (Err(message), _) | (_, Err(message)) => return Response::error(message, 400),
+    };
+    let query = AuditQuery {
+        action: query_param(&url, "action"),
+        actor: query_param(&url, "actor"),
+        target: query_param(&url, "target"),
+        since_ms: since.map(|(start, _)| start),
+        until_ms: until.map(|(_, end)| end),
+        limit: query_param(&url, "limit")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_LIMIT)
+            .clamp(1, MAX_LIMIT),
+        offset: query_param(&url, "offset")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0),
+    };
+
+    let db = ctx.env.d1("DB")?;
+    let entries = audit::list(&db, &query).await?;
+
+    json(&serde_json::json!({
+        "entries": entries,
+        "limit": query.limit,
+        "offset": query.offset,
+    }))
+}
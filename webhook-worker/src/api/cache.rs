@@ -0,0 +1,183 @@
+//! KV cache inspection and management
+//!
+//! - GET    /api/admin/cache          list cached UUID entries with TTLs (`cursor`, `limit`)
+//! - GET    /api/admin/cache/{uuid}   show one entry
+//! - POST   /api/admin/cache/warm     cache the given webhooks: `{"uuids": [...]}`
+//! - DELETE /api/admin/cache/{uuid}   flush one entry
+//! - DELETE /api/admin/cache          flush every cached UUID entry
+
+use crate::api::{json, query_param};
+use crate::audit::{self, AuditEntry};
+use crate::auth::{self, RouteData};
+use crate::cache;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+const DEFAULT_LIST_LIMIT: u64 = 100;
+const MAX_LIST_LIMIT: u64 = 1000;
+
+#[derive(Serialize)]
+struct CacheEntry {
+    uuid: String,
+    key: String,
+    webhook_id: Option<String>,
+    /// Absolute expiration (Unix seconds)
+    expiration: Option<u64>,
+    /// Seconds until expiration
+    ttl_seconds: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct WarmRequest {
+    uuids: Vec<String>,
+}
+
+fn now_seconds() -> i64 {
+    (Date::now().as_millis() / 1000) as i64
+}
+
+fn entry(key: String, expiration: Option<u64>, webhook_id: Option<String>) -> CacheEntry {
+    CacheEntry {
+        uuid: key.strip_prefix(cache::KEY_PREFIX).unwrap_or(&key).to_string(),
+        ttl_seconds: expiration.map(|exp| exp as i64 - now_seconds()),
+        key,
+        webhook_id,
+        expiration,
+    }
+}
+
+/// List cached UUID entries
+pub async fn list(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let url = req.url()?;
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+    let limit = query_param(&url, "limit")
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+
+    let mut builder = kv
+        .list()
+        .prefix(cache::KEY_PREFIX.to_string())
+        .limit(limit);
+    if let Some(cursor) = query_param(&url, "cursor") {
+        builder = builder.cursor(cursor);
+    }
+    let page = builder.execute().await?;
+
+    let entries: Vec<CacheEntry> = page
+        .keys
+        .into_iter()
+        .map(|key| entry(key.name, key.expiration, None))
+        .collect();
+
+    json(&serde_json::json!({
+        "entries": entries,
+        "cursor": page.cursor,
+        "list_complete": page.list_complete,
+    }))
+}
+
+/// Show a single cache entry
+pub async fn show(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+    let key = cache::key(&uuid);
+
+    let webhook_id = match kv.get(&key).text().await? {
+        Some(id) => id,
+        None => return Response::error("Not cached", 404),
+    };
+
+    // KV only reports expirations through list()
+    let expiration = kv
+        .list()
+        .prefix(key.clone())
+        .limit(1)
+        .execute()
+        .await?
+        .keys
+        .into_iter()
+        .find(|candidate| candidate.name == key)
+        .and_then(|candidate| candidate.expiration);
+
+    json(&entry(key, expiration, Some(webhook_id)))
+}
+
+/// Resolve webhooks in D1 and populate their cache entries
+pub async fn warm(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let body: WarmRequest = match req.json().await {
+        Ok(body) => body,
+        Err(_) => return Response::error("Expected {\"uuids\": [...]}", 400),
+    };
+
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+    let db = ctx.env.d1("DB")?;
+    let mut warmed = Vec::new();
+    let mut not_found = Vec::new();
+
+    for uuid in body.uuids {
+        match cache::find_in_d1(&db, &uuid).await? {
+            Some(webhook_id) => {
+                cache::put(&kv, &uuid, &webhook_id).await?;
+                warmed.push(uuid);
+            }
+            None => not_found.push(uuid),
+        }
+    }
+
+    log_info!("🔥 Warmed {} cache entries", warmed.len());
+    let result = serde_json::json!({
+        "warmed": warmed,
+        "not_found": not_found,
+    });
+    let entry = AuditEntry::from_request(&req, auth::principal(&ctx)?, "cache.warm").after(&result);
+    audit::record(&db, entry).await;
+    json(&result)
+}
+
+/// Flush a single cache entry
+pub async fn flush_one(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+    let key = cache::key(&uuid);
+    let previous = kv.get(&key).text().await?;
+    kv.delete(&key).await?;
+
+    let entry = AuditEntry::from_request(&req, auth::principal(&ctx)?, "cache.flush")
+        .target(uuid)
+        .before(&serde_json::json!({ "webhook_id": previous }));
+    audit::record(&ctx.env.d1("DB")?, entry).await;
+
+    json(&serde_json::json!({ "flushed": 1 }))
+}
+
+/// Flush every cached UUID entry
+pub async fn flush_all(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+    let mut flushed = 0;
+    let mut cursor: Option<String> = None;
+
+    loop {
+        let mut builder = kv.list().prefix(cache::KEY_PREFIX.to_string());
+        if let Some(cursor) = cursor.take() {
+            builder = builder.cursor(cursor);
+        }
+        let page = builder.execute().await?;
+
+        for key in &page.keys {
+            kv.delete(&key.name).await?;
+            flushed += 1;
+        }
+
+        if page.list_complete || page.cursor.is_none() {
+            break;
+        }
+        cursor = page.cursor;
+    }
+
+    log_info!("🧹 Flushed {} cache entries", flushed);
+    let entry = AuditEntry::from_request(&req, auth::principal(&ctx)?, "cache.flush_all")
+        .after(&serde_json::json!({ "flushed": flushed }));
+    audit::record(&ctx.env.d1("DB")?, entry).await;
+    json(&serde_json::json!({ "flushed": flushed }))
+}
@@ -0,0 +1,47 @@
+//! Standard Webhooks capability document
+//! GET /.well-known/standard-webhooks (public). Tells senders and tooling how
+//! this worker takes part in the Standard Webhooks spec: the headers and
+//! signature versions it verifies on capture URLs, how redeliveries are
+//! recognised, and how forwards are signed and retried. Which webhooks verify
+//! or sign is their own config (`signature.provider = standard_webhooks`,
+//! `forward_signing`).
+
+use crate::api::json;
+use crate::auth::RouteData;
+use crate::config::MAX_FORWARD_RETRIES;
+use crate::forward::SIGNED_RETRY_DELAYS;
+use crate::signature::{self, standard};
+use worker::*;
+
+/// Describe the worker's Standard Webhooks support
+pub async fn standard_webhooks(_req: Request, _ctx: RouteContext<RouteData>) -> Result<Response> {
+    let headers = serde_json::json!({
+        "id": standard::STANDARD_HEADERS.id,
+        "timestamp": standard::STANDARD_HEADERS.timestamp,
+        "signature": standard::STANDARD_HEADERS.signature,
+    });
+    let delays: Vec<u64> = SIGNED_RETRY_DELAYS.iter().map(|delay| delay.as_secs()).collect();
+    json(&serde_json::json!({
+        "specification": "https://www.standardwebhooks.com",
+        "signature_versions": [standard::VERSION],
+        "secret_format": "whsec_ followed by a base64 key",
+        "verification": {
+            "provider": "standard_webhooks",
+            "headers": headers,
+            "default_tolerance_seconds": 300,
+            "deduplication": "webhook-id, across retries",
+        },
+        "signing": {
+            "config": "forward_signing",
+            "headers": headers,
+            "message_id": "capture ID",
+            "retries": {
+                "default": 2,
+                "max": MAX_FORWARD_RETRIES,
+                "delays_seconds": delays,
+                "on": ["network error", "timeout", "429", "5xx"],
+            },
+        },
+        "signature_providers": signature::providers(),
+    }))
+}
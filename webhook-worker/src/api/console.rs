@@ -0,0 +1,35 @@
+//! Debug console route
+//! GET /api/webhooks/{uuid}/console upgrades to a WebSocket handed to the
+//! webhook's `WebhookConsole` Durable Object (see `console.rs` for the
+//! protocol). Consoles steer forwarding and answers, so they need an editor.
+
+use crate::api::authorized_webhook;
+use crate::auth::{self, RouteData, Role};
+use crate::durable::console::{self, Attendee};
+use crate::replay;
+use worker::*;
+
+/// Upgrade a console connection
+pub async fn connect(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let upgrade = req.headers().get("Upgrade")?.unwrap_or_default();
+    if !upgrade.eq_ignore_ascii_case("websocket") {
+        return Response::error("Expected a WebSocket upgrade", 426);
+    }
+
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let attendee = Attendee {
+        webhook_id,
+        actor: principal.actor.clone(),
+        capture_url: replay::capture_url(&req.url()?, &uuid).to_string(),
+        uuid,
+    };
+    console::connect(&ctx.env, &attendee).await
+}
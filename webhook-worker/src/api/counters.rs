@@ -0,0 +1,55 @@
+//! Counter routes (global owners only, see `counters.rs`)
+//!
+//! - GET    /api/admin/counters/{scope}         live counters of a scope with their expiry
+//! - POST   /api/admin/counters/{scope}/{key}   adjust a counter: `{"by": -10}` (e.g. to give quota back)
+//! - DELETE /api/admin/counters/{scope}/{key}   reset a counter to zero
+
+use crate::api::json;
+use crate::audit::{self, AuditEntry};
+use crate::auth::{self, RouteData};
+use crate::durable::counter;
+use serde::Deserialize;
+use worker::*;
+
+#[derive(Deserialize)]
+struct Adjustment {
+    by: i64,
+}
+
+/// Live counters of a scope
+pub async fn show(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let scope = ctx.param("scope").cloned().unwrap_or_default();
+    let counters = counter::snapshot(&ctx.env, &scope).await?;
+    json(&serde_json::json!({ "scope": scope, "counters": counters }))
+}
+
+/// Add to (or take from) one counter
+pub async fn adjust(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let scope = ctx.param("scope").cloned().unwrap_or_default();
+    let key = ctx.param("key").cloned().unwrap_or_default();
+    let adjustment: Adjustment = match req.json().await {
+        Ok(adjustment) => adjustment,
+        Err(_) => return Response::error("Expected {\"by\": n}", 400),
+    };
+
+    let value = counter::increment(&ctx.env, &scope, &key, adjustment.by, None).await?;
+    let entry = AuditEntry::from_request(&req, auth::principal(&ctx)?, "counter.adjust")
+        .target(format!("{}/{}", scope, key))
+        .after(&serde_json::json!({ "by": adjustment.by, "value": value }));
+    audit::record(&ctx.env.d1("DB")?, entry).await;
+    json(&serde_json::json!({ "scope": scope, "key": key, "value": value }))
+}
+
+/// Reset one counter
+pub async fn reset(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let scope = ctx.param("scope").cloned().unwrap_or_default();
+    let key = ctx.param("key").cloned().unwrap_or_default();
+    if !counter::reset(&ctx.env, &scope, &key).await? {
+        return Response::error("Counter not found", 404);
+    }
+
+    let entry = AuditEntry::from_request(&req, auth::principal(&ctx)?, "counter.reset")
+        .target(format!("{}/{}", scope, key));
+    audit::record(&ctx.env.d1("DB")?, entry).await;
+    json(&serde_json::json!({ "scope": scope, "key": key, "value": 0 }))
+}
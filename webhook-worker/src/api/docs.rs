@@ -0,0 +1,72 @@
+//! Traffic documentation routes
+//!
+//! - GET /api/webhooks/{uuid}/docs  what the provider actually sends, inferred from the `limit`
+//!   most recent captures (default 200, max 1000); Markdown, or `format=json` for the structure
+//! - GET /api/webhooks/{uuid}/shapes  tracked payload shapes and their diffs (`event_type=` narrows)
+
+use crate::api::{authorized_webhook, json, query_param};
+use crate::auth::{self, RouteData, Role};
+use crate::docs;
+use crate::shapes;
+use crate::storage::{self, Consistency, RequestQuery, SortColumn};
+use worker::*;
+
+/// Event types, fields, field frequency and redacted examples of a webhook's captures
+pub async fn show(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let url = req.url()?;
+    let limit = query_param(&url, "limit")
+        .and_then(|value| value.parse::<u32>().ok())
+        .unwrap_or(docs::DEFAULT_SAMPLE)
+        .clamp(1, docs::MAX_SAMPLE);
+    let storage = storage::for_webhook(&ctx.env, &webhook_id, Consistency::Replica { bookmark: None }).await?;
+    let captures = storage
+        .list_requests(&RequestQuery {
+            webhook_id: webhook_id.clone(),
+            limit,
+            offset: 0,
+            since: None,
+            until: None,
+            sort: SortColumn::ReceivedAt,
+            ascending: false,
+            filters: Vec::new(),
+        })
+        .await?;
+    let document = docs::infer(&uuid, &captures);
+
+    if query_param(&url, "format").as_deref() == Some("json") {
+        return json(&document);
+    }
+    let mut response = Response::ok(docs::markdown(&document, &uuid))?;
+    let headers = response.headers_mut();
+    headers.set("Content-Type", "text/markdown; charset=utf-8")?;
+    crate::set_cors_headers(headers)?;
+    Ok(response)
+}
+
+/// Payload shapes seen per event type, newest first, with the diff of each against the dominant one
+pub async fn shapes(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let event_type = query_param(&req.url()?, "event_type");
+    let shapes = shapes::list(&db, &webhook_id, event_type.as_deref()).await?;
+    json(&serde_json::json!({
+        "webhook_id": uuid,
+        "shapes": shapes,
+    }))
+}
@@ -0,0 +1,51 @@
+//! Envelope encryption job routes (see `encryption.rs`)
+//!
+//! - GET  /api/admin/encryption         current master key version and data keys per version
+//! - POST /api/admin/encryption/rewrap  rewrap data keys under the current master key: `{"limit"?}`
+
+use crate::api::json;
+use crate::audit::{self, AuditEntry};
+use crate::auth::{self, RouteData};
+use crate::encryption;
+use serde::Deserialize;
+use worker::*;
+
+#[derive(Deserialize, Default)]
+struct RewrapRequest {
+    limit: Option<u32>,
+}
+
+/// Show which master key versions still wrap data keys
+pub async fn status(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let db = ctx.env.d1("DB")?;
+    json(&serde_json::json!({
+        "enabled": encryption::current_version(&ctx.env).is_some(),
+        "master_version": encryption::current_version(&ctx.env),
+        "versions": encryption::status(&db).await?,
+    }))
+}
+
+/// Rewrap data keys left on older master keys; payloads are not touched
+pub async fn rewrap(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    if encryption::current_version(&ctx.env).is_none() {
+        return Response::error("Encryption is not enabled (MASTER_KEY_VERSION)", 409);
+    }
+    let body: RewrapRequest = req.json().await.unwrap_or_default();
+    let limit = body.limit.unwrap_or(encryption::REWRAP_BATCH).clamp(1, encryption::REWRAP_BATCH);
+
+    let db = ctx.env.d1("DB")?;
+    let before = encryption::status(&db).await?;
+    let report = encryption::rewrap(&ctx.env, &db, limit).await?;
+    if !report.rewrapped.is_empty() {
+        let entry = AuditEntry::from_request(&req, auth::principal(&ctx)?, "encryption.rewrap")
+            .before(&before)
+            .after(&serde_json::json!({
+                "master_version": report.master_version,
+                "rewrapped": report.rewrapped.len(),
+                "failed": report.failed.len(),
+                "remaining": report.remaining,
+            }));
+        audit::record(&db, entry).await;
+    }
+    json(&report)
+}
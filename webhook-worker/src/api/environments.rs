@@ -0,0 +1,170 @@
+//! Webhook environment routes
+//!
+//! - GET    /api/webhooks/{uuid}/environments          list environments with their capture URLs
+//! - POST   /api/webhooks/{uuid}/environments          create: `{"name": "staging", "forward_url"?}`
+//! - PATCH  /api/webhooks/{uuid}/environments/{name}   change the target: `{"forward_url": "..." | null}`
+//! - DELETE /api/webhooks/{uuid}/environments/{name}   delete an environment (its captures are kept)
+
+use crate::api::{authorized_webhook, json};
+use crate::audit::{self, AuditEntry};
+use crate::auth::{self, RouteData, Role};
+use crate::config;
+use crate::environments::{self, Environment};
+use serde::Deserialize;
+use worker::*;
+
+#[derive(Deserialize)]
+struct CreateRequest {
+    name: String,
+    forward_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct UpdateRequest {
+    forward_url: Option<String>,
+}
+
+/// Environment as returned by the API, with its capture URL
+pub fn with_capture_url(req: &Request, environment: &Environment) -> Result<serde_json::Value> {
+    let mut url = req.url()?;
+    url.set_path(&format!("/w/{}", environment.uuid));
+    url.set_query(None);
+
+    let mut value = serde_json::to_value(environment)?;
+    value["capture_url"] = serde_json::Value::String(url.to_string());
+    Ok(value)
+}
+
+fn invalid_forward_url(forward_url: &Option<String>) -> bool {
+    forward_url
+        .as_deref()
+        .is_some_and(|url| !environments::is_valid_forward_target(url))
+}
+
+/// List a webhook's environments
+pub async fn list(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let environments = environments::list(&db, &webhook_id)
+        .await?
+        .iter()
+        .map(|environment| with_capture_url(&req, environment))
+        .collect::<Result<Vec<_>>>()?;
+    json(&serde_json::json!({ "environments": environments }))
+}
+
+/// Create an environment with its own capture UUID
+pub async fn create(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let body: CreateRequest = match req.json().await {
+        Ok(body) => body,
+        Err(_) => return Response::error("Expected {\"name\": \"...\"}", 400),
+    };
+    if !environments::is_valid_name(&body.name) {
+        return Response::error("Invalid name (lowercase letters, digits and dashes, max 32)", 400);
+    }
+    if invalid_forward_url(&body.forward_url) {
+        return Response::error("forward_url must be an http(s) URL or webhook:{uuid}", 400);
+    }
+
+    let environment = match environments::create(&db, &webhook_id, &body.name, body.forward_url).await? {
+        Some(environment) => environment,
+        None => return Response::error("Environment already exists", 409),
+    };
+    config::invalidate(&kv, &webhook_id).await;
+
+    let entry = AuditEntry::from_request(&req, &principal, "environment.create")
+        .target(format!("{}/{}", uuid, environment.name))
+        .after(&environment);
+    audit::record(&db, entry).await;
+
+    Ok(json(&with_capture_url(&req, &environment)?)?.with_status(201))
+}
+
+/// Change or clear an environment's forwarding target
+pub async fn update(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let name = ctx.param("name").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+    let before = match environments::get(&db, &webhook_id, &name).await? {
+        Some(environment) => environment,
+        None => return Response::error("Environment not found", 404),
+    };
+
+    let body: UpdateRequest = match req.json().await {
+        Ok(body) => body,
+        Err(_) => return Response::error("Expected {\"forward_url\": \"...\"}", 400),
+    };
+    if invalid_forward_url(&body.forward_url) {
+        return Response::error("forward_url must be an http(s) URL or webhook:{uuid}", 400);
+    }
+
+    environments::set_forward_url(&db, &before.id, body.forward_url.clone()).await?;
+    config::invalidate(&kv, &webhook_id).await;
+    let after = Environment {
+        forward_url: body.forward_url,
+        ..before.clone()
+    };
+
+    let entry = AuditEntry::from_request(&req, &principal, "environment.update")
+        .target(format!("{}/{}", uuid, name))
+        .before(&before)
+        .after(&after);
+    audit::record(&db, entry).await;
+
+    json(&with_capture_url(&req, &after)?)
+}
+
+/// Delete an environment; its capture UUID stops resolving
+pub async fn delete(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let name = ctx.param("name").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+    let environment = match environments::get(&db, &webhook_id, &name).await? {
+        Some(environment) => environment,
+        None => return Response::error("Environment not found", 404),
+    };
+
+    environments::delete(&db, &environment.id).await?;
+    config::invalidate(&kv, &webhook_id).await;
+    if let Err(e) = crate::cache::delete(&kv, &environment.uuid).await {
+        log_warn!("⚠️  Failed to drop cached environment UUID: {:?}", e);
+    }
+
+    let entry = AuditEntry::from_request(&req, &principal, "environment.delete")
+        .target(format!("{}/{}", uuid, name))
+        .before(&environment);
+    audit::record(&db, entry).await;
+
+    json(&serde_json::json!({ "deleted": name }))
+}
@@ -0,0 +1,61 @@
+//! Erasure routes
+//!
+//! - POST /api/erasure   delete or redact every capture in the project containing an identifier:
+//!   `{"identifier": "jane@example.com", "mode"?: "delete" | "redact", "dry_run"?, "user_id"?}`
+//!   (`user_id` picks the project for global callers, who otherwise search every webhook)
+
+use crate::api::json;
+use crate::audit::{self, AuditEntry};
+use crate::auth::{self, RouteData, Role};
+use crate::erasure::{self, Mode};
+use crate::webhooks;
+use serde::Deserialize;
+use worker::*;
+
+#[derive(Deserialize)]
+struct EraseRequest {
+    identifier: String,
+    #[serde(default)]
+    mode: Mode,
+    #[serde(default)]
+    dry_run: bool,
+    user_id: Option<String>,
+}
+
+/// Find and erase a data subject's captures, reporting what was (or would be) removed
+pub async fn erase(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let db = ctx.env.d1("DB")?;
+
+    let body: EraseRequest = match req.json().await {
+        Ok(body) => body,
+        Err(_) => return Response::error("Expected {\"identifier\": \"...\", \"mode\": \"delete\" | \"redact\"}", 400),
+    };
+    let needle = match erasure::needle(&body.identifier) {
+        Ok(needle) => needle,
+        Err(message) => return Response::error(message, 400),
+    };
+    let project = match (&principal.user_id, &body.user_id) {
+        (Some(own), Some(other)) if own != other => return Response::error("Forbidden", 403),
+        (Some(own), _) => Some(own.clone()),
+        (None, requested) => requested.clone(),
+    };
+
+    let mut writable = Vec::new();
+    for webhook in webhooks::in_project(&db, project.as_deref()).await? {
+        if auth::can_access_webhook(&db, &principal, &webhook.id, Role::Editor).await? {
+            writable.push(webhook);
+        }
+    }
+
+    let report = erasure::erase(&ctx.env, &db, &writable, &needle, body.mode, body.dry_run).await?;
+
+    if !report.dry_run {
+        let entry = AuditEntry::from_request(&req, &principal, "erasure.run")
+            .target(report.identifier_sha256.clone())
+            .after(&report);
+        audit::record(&db, entry).await;
+    }
+
+    json(&report)
+}
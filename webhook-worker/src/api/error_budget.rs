@@ -0,0 +1,25 @@
+//! Error budget route (global owners only, see `error_budget.rs`)
+//!
+//! - GET /api/admin/error-budget   the budget, the current window's counts and the alert, if any
+
+use crate::api::json;
+use crate::auth::RouteData;
+use crate::error_budget::{self, Budget};
+use worker::*;
+
+/// The error budget as the next check will see it
+pub async fn show(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let Some(budget) = Budget::from_env(&ctx.env) else {
+        return json(&serde_json::json!({ "enabled": false }));
+    };
+    let (window, alert) = error_budget::status(&ctx.env, &budget, Date::now().as_millis() as i64).await?;
+    json(&serde_json::json!({
+        "enabled": true,
+        "threshold": budget.threshold,
+        "window_minutes": budget.window_minutes,
+        "min_requests": budget.min_requests,
+        "window": window,
+        "failure_rate": window.failure_rate(),
+        "exhausted_since_ms": alert.map(|alert| alert.since_ms),
+    }))
+}
@@ -0,0 +1,189 @@
+//! Fixture routes
+//! Save sample deliveries with the outcome they should have and run them
+//! through the webhook's current pipeline (see `fixtures.rs`).
+
+use crate::api::requests::find_request;
+use crate::api::{authorized_webhook, json};
+use crate::audit::{self, AuditEntry};
+use crate::auth::{self, RouteData, Role};
+use crate::config::{self, WebhookSettings};
+use crate::fixtures::{self, Fixture};
+use crate::replay;
+use crate::simulate;
+use crate::storage::{self, Consistency};
+use webhook_types::fixtures::{FixtureReport, FixtureResult, NewFixture};
+use worker::*;
+
+/// List a webhook's fixtures
+pub async fn list(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let fixtures = fixtures::list(&db, &webhook_id).await?;
+    json(&serde_json::json!({ "webhook_id": uuid, "fixtures": fixtures }))
+}
+
+/// Save a capture or a typed-in sample as a fixture, replacing one with the same name
+pub async fn save(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let body: NewFixture = match req.json().await {
+        Ok(body) => body,
+        Err(e) => return Response::error(format!("Invalid fixture: {}", e), 400),
+    };
+    if !fixtures::is_valid_name(&body.name) {
+        return Response::error("Fixture names are 1-64 letters, digits, '-' or '_'", 400);
+    }
+    let request = match (body.capture_id.clone(), body.request) {
+        (Some(id), None) => {
+            let storage = storage::for_webhook(&ctx.env, &webhook_id, Consistency::Primary).await?;
+            match find_request(storage.as_ref(), &webhook_id, id).await? {
+                Some(capture) => fixtures::sample_from_capture(&capture),
+                None => return Response::error("Request not found", 404),
+            }
+        }
+        (None, Some(request)) => request,
+        _ => return Response::error("Give either capture_id or request", 400),
+    };
+
+    let fixture = Fixture {
+        name: body.name,
+        request,
+        expect: body.expect,
+        capture_id: body.capture_id,
+        actor: principal.actor.clone(),
+        created_at_ms: Date::now().as_millis() as i64,
+    };
+    if !fixtures::save(&db, &webhook_id, &fixture).await? {
+        let message = format!("A webhook keeps at most {} fixtures", fixtures::MAX_PER_WEBHOOK);
+        return Response::error(message, 409);
+    }
+
+    let entry = AuditEntry::from_request(&req, principal, "fixture.save")
+        .target(uuid.clone())
+        .after(&serde_json::json!({
+            "name": fixture.name,
+            "capture_id": fixture.capture_id,
+            "expect": fixture.expect,
+        }));
+    audit::record(&db, entry).await;
+    Ok(json(&serde_json::json!({ "webhook_id": uuid, "fixture": fixture }))?.with_status(201))
+}
+
+/// Delete a fixture
+pub async fn delete(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let name = ctx.param("name").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    if !fixtures::delete(&db, &webhook_id, &name).await? {
+        return Response::error("Fixture not found", 404);
+    }
+    let entry = AuditEntry::from_request(&req, principal, "fixture.delete")
+        .target(uuid.clone())
+        .before(&serde_json::json!({ "name": name }));
+    audit::record(&db, entry).await;
+    json(&serde_json::json!({ "webhook_id": uuid, "deleted": name }))
+}
+
+/// Run one fixture through the current pipeline; `passed` with no `failures` when every expectation holds
+fn run_one(
+    fixture: &Fixture,
+    capture_url: &Url,
+    webhook_id: &str,
+    settings: &WebhookSettings,
+    now_ms: i64,
+) -> FixtureResult {
+    match simulate::run(&fixture.request, capture_url, webhook_id, settings, now_ms) {
+        Ok(outcome) => {
+            let failures = fixtures::check(&fixture.expect, &outcome);
+            FixtureResult {
+                name: fixture.name.clone(),
+                passed: failures.is_empty(),
+                failures,
+                outcome: serde_json::to_value(&outcome).ok(),
+                error: None,
+            }
+        }
+        Err(problem) => FixtureResult {
+            name: fixture.name.clone(),
+            passed: false,
+            failures: Vec::new(),
+            outcome: None,
+            error: Some(problem),
+        },
+    }
+}
+
+/// Run a fixture
+pub async fn run(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let name = ctx.param("name").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let fixtures = fixtures::list(&db, &webhook_id).await?;
+    let Some(fixture) = fixtures.iter().find(|fixture| fixture.name == name) else {
+        return Response::error("Fixture not found", 404);
+    };
+    let settings = config::load_from_d1(&db, &webhook_id).await?;
+    let capture_url = replay::capture_url(&req.url()?, &uuid);
+    let result = run_one(fixture, &capture_url, &webhook_id, &settings, Date::now().as_millis() as i64);
+    let mut result = serde_json::to_value(result)?;
+    result["webhook_id"] = serde_json::json!(uuid);
+    result["version"] = serde_json::json!(settings.version);
+    json(&result)
+}
+
+/// Run all of a webhook's fixtures
+pub async fn run_all(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let fixtures = fixtures::list(&db, &webhook_id).await?;
+    let settings = config::load_from_d1(&db, &webhook_id).await?;
+    let capture_url = replay::capture_url(&req.url()?, &uuid);
+    let now_ms = Date::now().as_millis() as i64;
+    let results: Vec<FixtureResult> = fixtures
+        .iter()
+        .map(|fixture| run_one(fixture, &capture_url, &webhook_id, &settings, now_ms))
+        .collect();
+    let failed = results.iter().filter(|result| !result.passed).count();
+    json(&FixtureReport {
+        webhook_id: uuid,
+        version: settings.version,
+        passed: results.len() - failed,
+        failed,
+        results,
+    })
+}
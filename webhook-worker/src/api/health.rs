@@ -0,0 +1,79 @@
+//! Health check with replication lag
+//! GET /health (public, read-only). Replication lag is estimated from a heartbeat
+//! row the cron triggers refresh on the primary (`db::write_heartbeat`): the
+//! nearest replica's copy shows how many refreshes behind that replica is, so
+//! the estimate moves in steps of the cron interval. KV is probed with one read;
+//! when it fails the status is `degraded` but the check still answers 200, since
+//! deliveries fall back to D1. `kv_health` holds this isolate's counters.
+
+use crate::api::json;
+use crate::db;
+use crate::kv::{self, KvBackend};
+use serde::Deserialize;
+use crate::auth::RouteData;
+use worker::*;
+
+/// Key read by the KV probe; it never exists, so a working KV answers with a miss
+const KV_PROBE_KEY: &str = "health:probe";
+
+#[derive(Deserialize)]
+struct HeartbeatRow {
+    written_at_ms: i64,
+}
+
+async fn read_heartbeat(db: &D1Database) -> Result<Option<i64>> {
+    Ok(db
+        .prepare("SELECT written_at_ms FROM replication_heartbeat WHERE id = 1")
+        .first::<HeartbeatRow>(None)
+        .await?
+        .map(|row| row.written_at_ms))
+}
+
+/// Report worker health and D1 replication lag
+pub async fn check(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let now_ms = Date::now().as_millis() as i64;
+    let primary = db::primary(&ctx.env)?;
+    let replica = db::replica(&ctx.env, None)?;
+
+    let primary_heartbeat = read_heartbeat(&primary).await;
+    let replica_heartbeat = read_heartbeat(&replica).await;
+
+    let (primary_heartbeat, replica_heartbeat) = match (primary_heartbeat, replica_heartbeat) {
+        (Ok(primary), Ok(replica)) => (primary, replica),
+        (Err(e), _) | (_, Err(e)) => {
+            log_error!("❌ Health check database error: {:?}", e);
+            let mut response = json(&serde_json::json!({
+                "status": "degraded",
+                "timestamp": now_ms,
+                "database": "unavailable",
+            }))?
+            .with_status(503);
+            response.headers_mut().set("Cache-Control", "no-store")?;
+            return Ok(response);
+        }
+    };
+
+    let kv_ok = match ctx.env.kv("WEBHOOK_CACHE") {
+        Ok(store) => store.get_text(KV_PROBE_KEY).await.is_ok(),
+        Err(_) => false,
+    };
+    if !kv_ok {
+        log_warn!("⚠️  Health check KV probe failed");
+    }
+
+    let replication_lag_ms = match (primary_heartbeat, replica_heartbeat) {
+        (Some(primary), Some(replica)) => Some((primary - replica).max(0)),
+        _ => None,
+    };
+
+    let mut response = json(&serde_json::json!({
+        "status": if kv_ok { "ok" } else { "degraded" },
+        "timestamp": now_ms,
+        "database": "ok",
+        "replication_lag_ms": replication_lag_ms,
+        "kv": if kv_ok { "ok" } else { "unavailable" },
+        "kv_health": kv::health(),
+    }))?;
+    response.headers_mut().set("Cache-Control", "no-store")?;
+    Ok(response)
+}
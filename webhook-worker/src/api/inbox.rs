@@ -0,0 +1,113 @@
+//! Inbox consumption routes
+//! A polling consumer treats the webhook as a durable inbox: a lease hands out
+//! the oldest unacknowledged requests and marks them read; anything not acked
+//! before its lease expires is handed out again. Leasing and acking change
+//! what other consumers get, so they need an editor (or a token limited to
+//! `inbox:consume`); viewers can only look at what a lease would return.
+//!
+//! - GET  /api/webhooks/{uuid}/inbox      requests a lease would get, unchanged (`limit`, `unread=true`)
+//! - POST /api/webhooks/{uuid}/inbox      lease requests (`limit`, `visibility_timeout` seconds, `unread=true`)
+//! - POST /api/webhooks/{uuid}/inbox/ack  acknowledge: `{"ids": ["..."]}`
+
+use crate::api::{authorized_webhook, json, query_param};
+use crate::auth::{self, RouteData, Role};
+use crate::storage::{self, Consistency, InboxQuery};
+use serde::Deserialize;
+use worker::*;
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 100;
+const DEFAULT_VISIBILITY_TIMEOUT_SECONDS: i64 = 30;
+const MAX_VISIBILITY_TIMEOUT_SECONDS: i64 = 12 * 3600;
+
+/// D1 allows 100 bound parameters per statement (two are taken by the webhook and time)
+const MAX_ACK_IDS: usize = 98;
+
+#[derive(Deserialize)]
+struct AckRequest {
+    ids: Vec<String>,
+}
+
+fn now_ms() -> i64 {
+    Date::now().as_millis() as i64
+}
+
+/// Requests the next lease would get, without leasing them
+pub async fn peek(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    fetch(req, ctx, false).await
+}
+
+/// Lease the next batch of unacknowledged requests
+pub async fn lease(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    fetch(req, ctx, true).await
+}
+
+async fn fetch(req: Request, ctx: RouteContext<RouteData>, lease: bool) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let role = if lease { Role::Editor } else { Role::Viewer };
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, role).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let url = req.url()?;
+    let limit = query_param(&url, "limit")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_LIMIT)
+        .clamp(1, MAX_LIMIT);
+    let visibility_timeout = query_param(&url, "visibility_timeout")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_VISIBILITY_TIMEOUT_SECONDS)
+        .clamp(1, MAX_VISIBILITY_TIMEOUT_SECONDS);
+    let now_ms = now_ms();
+
+    let consistency = if lease { Consistency::Primary } else { Consistency::Replica { bookmark: None } };
+    let storage = storage::for_webhook(&ctx.env, &webhook_id, consistency).await?;
+    let requests = storage
+        .inbox_fetch(&InboxQuery {
+            webhook_id,
+            limit,
+            lease_ms: visibility_timeout * 1000,
+            now_ms,
+            unread_only: query_param(&url, "unread").as_deref() == Some("true"),
+            lease,
+        })
+        .await?;
+
+    if !lease {
+        return json(&serde_json::json!({ "webhook_id": uuid, "requests": requests }));
+    }
+    json(&serde_json::json!({
+        "webhook_id": uuid,
+        "requests": requests,
+        "lease_expires_at_ms": now_ms + visibility_timeout * 1000,
+    }))
+}
+
+/// Acknowledge processed requests
+pub async fn ack(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let body: AckRequest = match req.json().await {
+        Ok(body) => body,
+        Err(_) => return Response::error("Expected {\"ids\": [...]}", 400),
+    };
+    if body.ids.len() > MAX_ACK_IDS {
+        return Response::error(format!("At most {} ids per ack", MAX_ACK_IDS), 400);
+    }
+
+    let storage = storage::for_webhook(&ctx.env, &webhook_id, Consistency::Primary).await?;
+    let acked = storage.inbox_ack(&webhook_id, &body.ids, now_ms()).await?;
+
+    json(&serde_json::json!({ "acked": acked }))
+}
@@ -0,0 +1,208 @@
+//! Background job routes (see `jobs.rs`)
+//!
+//! - POST   /api/webhooks/{uuid}/jobs                  queue a job: `{"kind": "delete", "until": 1700000000}`,
+//!   `{"kind": "replay", "ids": [...], "recipe": "staging"}` or `{"kind": "export", "columns": "..."}`; 202
+//! - GET    /api/webhooks/{uuid}/jobs                  the webhook's recent jobs, newest first
+//! - GET    /api/webhooks/{uuid}/jobs/{id}             one job with its progress and result
+//! - DELETE /api/webhooks/{uuid}/jobs/{id}             cancel it before its next step
+//! - GET    /api/webhooks/{uuid}/jobs/{id}/download    a finished export job's CSV file
+//! - GET    /api/admin/jobs/{id}, DELETE /api/admin/jobs/{id}   any job, including migration runs
+
+use crate::api::{authorized_webhook, json};
+use crate::audit::{self, AuditEntry};
+use crate::auth::{self, RouteData, Role};
+use crate::durable::jobs as runner;
+use crate::jobs::{self, Job, Kind, Params, Status};
+use crate::replay;
+use serde_json::Value;
+use worker::*;
+
+/// A job as the API shows it, with the webhook's UUID
+fn view(job: &Job, uuid: Option<&str>) -> Value {
+    let mut value = serde_json::to_value(job).unwrap_or_default();
+    if let (Some(uuid), Value::Object(fields)) = (uuid, &mut value) {
+        fields.insert("webhook_id".to_string(), Value::String(uuid.to_string()));
+    }
+    value
+}
+
+fn now_ms() -> i64 {
+    Date::now().as_millis() as i64
+}
+
+/// Store a job, start its runner and answer 202
+pub(crate) async fn queue(
+    req: &Request,
+    ctx: &RouteContext<RouteData>,
+    job: Job,
+    uuid: Option<&str>,
+) -> Result<Response> {
+    let principal = auth::principal(ctx)?;
+    let db = ctx.env.d1("DB")?;
+    jobs::create(&db, &job).await?;
+    runner::start(&ctx.env, &job.id).await?;
+
+    let mut entry = AuditEntry::from_request(req, principal, "job.create")
+        .after(&serde_json::json!({ "id": job.id, "kind": job.kind, "params": job.params }));
+    if let Some(uuid) = uuid {
+        entry = entry.target(uuid.to_string());
+    }
+    audit::record(&db, entry).await;
+    Ok(json(&view(&job, uuid))?.with_status(202))
+}
+
+/// Queue a bulk delete, bulk replay or export job
+pub async fn create(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let mut params: Params = match req.json().await {
+        Ok(params) => params,
+        Err(e) => return Response::error(format!("Invalid job: {}", e), 400),
+    };
+    if let Some(problem) = params.validate() {
+        return Response::error(problem, 400);
+    }
+    match &mut params {
+        Params::Migrate => {
+            let message = "Migration jobs are queued with POST /api/admin/migrations/apply?async=true";
+            return Response::error(message, 400);
+        }
+        Params::Replay(replay_params) => {
+            if let Some(name) = &replay_params.recipe {
+                let settings = crate::config::load_from_d1(&db, &webhook_id).await?;
+                if !settings.config.replay_recipes.contains_key(name) {
+                    return Response::error("Replay recipe not found", 404);
+                }
+            }
+            replay_params.capture_url = Some(replay::capture_url(&req.url()?, &uuid).to_string());
+        }
+        _ => {}
+    }
+
+    let job = Job::new(Some(webhook_id), &principal.actor, params, now_ms());
+    queue(&req, &ctx, job, Some(&uuid)).await
+}
+
+/// The webhook's recent jobs
+pub async fn list(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let listed: Vec<Value> = jobs::list(&db, &webhook_id).await?.iter().map(|job| view(job, None)).collect();
+    json(&serde_json::json!({ "webhook_id": uuid, "jobs": listed }))
+}
+
+/// The job `{id}` of the webhook in the route, if the caller holds `role` on it, else the response to send
+async fn webhook_job(
+    ctx: &RouteContext<RouteData>,
+    role: Role,
+) -> Result<std::result::Result<(String, Job), Response>> {
+    let principal = auth::principal(ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let id = ctx.param("id").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, role).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(Err(denied)),
+    };
+    let job = jobs::get(&db, &id).await?.filter(|job| job.webhook_id.as_deref() == Some(webhook_id.as_str()));
+    match job {
+        Some(job) => Ok(Ok((uuid, job))),
+        None => Ok(Err(Response::error("Job not found", 404)?)),
+    }
+}
+
+/// One job of a webhook
+pub async fn show(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    match webhook_job(&ctx, Role::Viewer).await? {
+        Ok((uuid, job)) => json(&view(&job, Some(&uuid))),
+        Err(denied) => Ok(denied),
+    }
+}
+
+/// Cancel one of a webhook's jobs
+pub async fn cancel(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let (uuid, job) = match webhook_job(&ctx, Role::Editor).await? {
+        Ok(found) => found,
+        Err(denied) => return Ok(denied),
+    };
+    cancel_job(&req, &ctx, job, Some(&uuid)).await
+}
+
+async fn cancel_job(req: &Request, ctx: &RouteContext<RouteData>, job: Job, uuid: Option<&str>) -> Result<Response> {
+    let db = ctx.env.d1("DB")?;
+    if !jobs::cancel(&db, &job.id, now_ms()).await? {
+        return Response::error("The job has already finished", 409);
+    }
+    let mut entry = AuditEntry::from_request(req, auth::principal(ctx)?, "job.cancel")
+        .before(&serde_json::json!({ "id": job.id, "kind": job.kind, "done": job.done }));
+    if let Some(uuid) = uuid {
+        entry = entry.target(uuid.to_string());
+    }
+    audit::record(&db, entry).await;
+
+    let job = jobs::get(&db, &job.id).await?.unwrap_or(job);
+    json(&view(&job, uuid))
+}
+
+/// The CSV file of a finished export job
+pub async fn download(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let (uuid, job) = match webhook_job(&ctx, Role::Viewer).await? {
+        Ok(found) => found,
+        Err(denied) => return Ok(denied),
+    };
+    if job.kind != Kind::Export {
+        return Response::error("Only export jobs have a file", 400);
+    }
+    if job.status != Status::Succeeded {
+        return Response::error("The export has not finished", 409);
+    }
+
+    let chunks = futures_util::stream::try_unfold(0u32, move |index| {
+        let (env, id) = (ctx.env.clone(), job.id.clone());
+        async move {
+            let Some(chunk) = runner::chunk(&env, &id, index).await? else {
+                return Ok(None);
+            };
+            Ok::<_, Error>(Some((chunk.into_bytes(), index + 1)))
+        }
+    });
+    let mut response = Response::from_stream(chunks)?;
+    let headers = response.headers_mut();
+    headers.set("Content-Type", "text/csv; charset=utf-8")?;
+    headers.set("Content-Disposition", &format!("attachment; filename=\"{}.csv\"", uuid))?;
+    crate::set_cors_headers(headers)?;
+    Ok(response)
+}
+
+/// Any job (global callers)
+pub async fn admin_show(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let id = ctx.param("id").cloned().unwrap_or_default();
+    match jobs::get(&ctx.env.d1("DB")?, &id).await? {
+        Some(job) => json(&view(&job, None)),
+        None => Response::error("Job not found", 404),
+    }
+}
+
+/// Cancel any job (global callers)
+pub async fn admin_cancel(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let id = ctx.param("id").cloned().unwrap_or_default();
+    let Some(job) = jobs::get(&ctx.env.d1("DB")?, &id).await? else {
+        return Response::error("Job not found", 404);
+    };
+    cancel_job(&req, &ctx, job, None).await
+}
@@ -0,0 +1,118 @@
+//! Legal hold routes
+//!
+//! - GET    /api/webhooks/{uuid}/legal-holds        active holds (`include_released=true` for the full trail)
+//! - POST   /api/webhooks/{uuid}/legal-holds        place a hold: `{"reason": "...", "request_id"?}`, on one
+//!   capture when `request_id` is given, else on the whole webhook
+//! - DELETE /api/webhooks/{uuid}/legal-holds/{id}   release a hold (owners only)
+
+use crate::api::{authorized_webhook, json, query_param};
+use crate::audit::{self, AuditEntry};
+use crate::auth::{self, RouteData, Role};
+use crate::legal_hold;
+use crate::storage::{self, Consistency, RequestQuery, SortColumn};
+use serde::Deserialize;
+use worker::*;
+
+#[derive(Deserialize)]
+struct PlaceRequest {
+    reason: String,
+    request_id: Option<String>,
+}
+
+/// List a webhook's legal holds, newest first
+pub async fn list(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let include_released = query_param(&req.url()?, "include_released").as_deref() == Some("true");
+    let holds = legal_hold::list(&db, &webhook_id, include_released).await?;
+    json(&serde_json::json!({ "webhook_id": uuid, "legal_holds": holds }))
+}
+
+/// Place a hold on a webhook or one of its captures
+pub async fn place(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let body: PlaceRequest = match req.json().await {
+        Ok(body) => body,
+        Err(_) => return Response::error("Expected {\"reason\": \"...\"}", 400),
+    };
+    let reason = body.reason.trim();
+    if reason.is_empty() {
+        return Response::error("A reason is required", 400);
+    }
+    if let Some(request_id) = &body.request_id {
+        let storage = storage::for_webhook(&ctx.env, &webhook_id, Consistency::Primary).await?;
+        let rows = storage
+            .list_requests(&RequestQuery {
+                webhook_id: webhook_id.clone(),
+                limit: 1,
+                offset: 0,
+                since: None,
+                until: None,
+                sort: SortColumn::default(),
+                ascending: false,
+                filters: vec![("id", request_id.clone())],
+            })
+            .await?;
+        if rows.is_empty() {
+            return Response::error("Request not found", 404);
+        }
+    }
+
+    let now_ms = Date::now().as_millis() as i64;
+    let hold = legal_hold::place(
+        &db,
+        &webhook_id,
+        body.request_id.as_deref(),
+        reason,
+        &principal.actor,
+        now_ms,
+    )
+    .await?;
+
+    let entry = AuditEntry::from_request(&req, &principal, "webhook.legal_hold")
+        .target(uuid)
+        .after(&hold);
+    audit::record(&db, entry).await;
+
+    Ok(json(&hold)?.with_status(201))
+}
+
+/// Release an active hold; the record stays for the audit trail
+pub async fn release(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let id = ctx.param("id").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Owner).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let now_ms = Date::now().as_millis() as i64;
+    if !legal_hold::release(&db, &webhook_id, &id, &principal.actor, now_ms).await? {
+        return Response::error("Legal hold not found", 404);
+    }
+
+    let entry = AuditEntry::from_request(&req, &principal, "webhook.legal_hold_release")
+        .target(format!("{}/{}", uuid, id))
+        .after(&serde_json::json!({ "id": id, "released_by": principal.actor, "released_at_ms": now_ms }));
+    audit::record(&db, entry).await;
+
+    json(&serde_json::json!({ "released": id }))
+}
@@ -0,0 +1,91 @@
+//! Synthetic load routes (global owners only)
+//!
+//! - GET    /api/admin/webhooks/{uuid}/load   current or last run with its counters
+//! - POST   /api/admin/webhooks/{uuid}/load   start a run: `{"rate_per_second": 20, "duration_seconds": 60}`
+//! - DELETE /api/admin/webhooks/{uuid}/load   stop the current run
+//!
+//! Deliveries go to the capture URL of `{uuid}`, which may be an environment UUID.
+
+use crate::api::{authorized_webhook, json};
+use crate::audit::{self, AuditEntry};
+use crate::auth::{self, RouteData, Role};
+use crate::durable::load::{self, LoadPlan};
+use worker::*;
+
+/// Current or last load run
+pub async fn status(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Owner).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    match load::status(&ctx.env, &webhook_id).await? {
+        Some(run) => json(&run),
+        None => Response::error("No load run", 404),
+    }
+}
+
+/// Start generating synthetic deliveries
+pub async fn start(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Owner).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let mut plan: LoadPlan = match req.json().await {
+        Ok(plan) => plan,
+        Err(_) => return Response::error("Expected {\"rate_per_second\": n, \"duration_seconds\": n}", 400),
+    };
+    plan.method = plan.method.to_ascii_uppercase();
+    if let Some(problem) = plan.validate() {
+        return Response::error(problem, 400);
+    }
+    let mut url = req.url()?;
+    url.set_path(&format!("/w/{}", uuid));
+    url.set_query(None);
+    plan.target_url = url.to_string();
+
+    let run = match load::start(&ctx.env, &webhook_id, &plan).await? {
+        Some(run) => run,
+        None => return Response::error("A load run is already in progress", 409),
+    };
+
+    let entry = AuditEntry::from_request(&req, &principal, "load.start")
+        .target(uuid)
+        .after(&run.plan);
+    audit::record(&db, entry).await;
+
+    Ok(json(&run)?.with_status(201))
+}
+
+/// Stop the current run
+pub async fn stop(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Owner).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let run = match load::stop(&ctx.env, &webhook_id).await? {
+        Some(run) => run,
+        None => return Response::error("No load run", 404),
+    };
+
+    let entry = AuditEntry::from_request(&req, &principal, "load.stop")
+        .target(uuid)
+        .before(&run.plan);
+    audit::record(&db, entry).await;
+
+    json(&run)
+}
@@ -0,0 +1,36 @@
+//! Schema migration routes
+//!
+//! - GET  /api/admin/migrations        applied and pending migrations
+//! - POST /api/admin/migrations/apply  apply pending migrations (`async=true` queues a job instead)
+
+use crate::api::{self, json, query_param};
+use crate::audit::{self, AuditEntry};
+use crate::auth::{self, RouteData};
+use crate::jobs::{Job, Params};
+use crate::migrations;
+use worker::*;
+
+/// Show migration status
+pub async fn status(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let db = ctx.env.d1("DB")?;
+    json(&migrations::status(&db).await?)
+}
+
+/// Apply pending migrations
+pub async fn apply(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    if query_param(&req.url()?, "async").as_deref() == Some("true") {
+        let actor = auth::principal(&ctx)?.actor.clone();
+        let job = Job::new(None, &actor, Params::Migrate, Date::now().as_millis() as i64);
+        return api::jobs::queue(&req, &ctx, job, None).await;
+    }
+    let db = ctx.env.d1("DB")?;
+    let before = migrations::status(&db).await?;
+    let applied = migrations::apply_pending(&db).await?;
+    if !applied.is_empty() {
+        let entry = AuditEntry::from_request(&req, auth::principal(&ctx)?, "migrations.apply")
+            .before(&before)
+            .after(&serde_json::json!({ "applied": applied }));
+        audit::record(&db, entry).await;
+    }
+    json(&serde_json::json!({ "applied": applied }))
+}
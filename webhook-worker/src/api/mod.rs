@@ -0,0 +1,102 @@
+//! Management API
+//! Authenticated JSON routes for captured data and operator tooling
+
+pub mod abuse;
+pub mod annotations;
+pub mod audit;
+pub mod cache;
+pub mod capabilities;
+pub mod console;
+pub mod counters;
+pub mod docs;
+pub mod encryption;
+pub mod environments;
+pub mod erasure;
+pub mod error_budget;
+pub mod fixtures;
+pub mod health;
+pub mod inbox;
+pub mod jobs;
+pub mod legal_holds;
+pub mod load;
+pub mod migrations;
+pub mod overview;
+pub mod projects;
+pub mod relay;
+pub mod requests;
+pub mod security_events;
+pub mod sessions;
+pub mod stats;
+pub mod status;
+pub mod tail;
+pub mod tokens;
+pub mod trace;
+pub mod transfer;
+pub mod webhooks;
+
+use crate::auth::{self, Principal, Role};
+use crate::timestamps::{self, TimeOptions};
+use worker::*;
+
+/// JSON response with CORS headers
+pub fn json<T: serde::Serialize>(value: &T) -> Result<Response> {
+    let mut response = Response::from_json(value)?;
+    crate::set_cors_headers(response.headers_mut())?;
+    Ok(response)
+}
+
+/// Re-render a JSON response with readable timestamps; other responses pass through
+pub async fn with_timestamps(mut response: Response, options: &TimeOptions) -> Result<Response> {
+    let is_json = response
+        .headers()
+        .get("content-type")?
+        .is_some_and(|content_type| content_type.starts_with("application/json"));
+    if !is_json {
+        return Ok(response);
+    }
+    let status = response.status_code();
+    let headers = response.headers().clone();
+    let mut value: serde_json::Value = response.json().await?;
+    timestamps::annotate(&mut value, options, Date::now().as_millis() as i64);
+    Ok(Response::from_json(&value)?.with_status(status).with_headers(headers))
+}
+
+/// The same request on another path, its body passed along unread
+pub fn with_path(req: Request, path: &str) -> Result<Request> {
+    if req.path() == path {
+        return Ok(req);
+    }
+    let mut url = req.url()?;
+    url.set_path(path);
+    let mut init = RequestInit::new();
+    init.with_method(req.method())
+        .with_headers(req.headers().clone())
+        .with_body(req.inner().body().map(wasm_bindgen::JsValue::from));
+    Request::new_with_init(url.as_str(), &init)
+}
+
+/// Read a query parameter by name
+pub fn query_param(url: &Url, name: &str) -> Option<String> {
+    url.query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+/// Resolve a webhook UUID to its ID if the caller holds at least `role` on it, else the 404 or 403
+/// to send. Webhooks outside the caller's project look the same as missing ones.
+pub async fn authorized_webhook(
+    db: &D1Database,
+    principal: &Principal,
+    uuid: &str,
+    role: Role,
+) -> Result<std::result::Result<String, Response>> {
+    let Some(id) = crate::cache::find_in_d1(db, uuid).await? else {
+        return Ok(Err(Response::error("Webhook not found", 404)?));
+    };
+    let granted = auth::webhook_role(db, principal, &id).await?;
+    match auth::webhook_access(granted, role) {
+        Ok(()) => Ok(Ok(id)),
+        Err(404) => Ok(Err(Response::error("Webhook not found", 404)?)),
+        Err(status) => Ok(Err(Response::error("Forbidden", status)?)),
+    }
+}
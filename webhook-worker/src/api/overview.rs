@@ -0,0 +1,13 @@
+//! Deployment overview route (global owners only, see `overview.rs`)
+//!
+//! - GET /api/admin/overview   webhooks, today's captures and busiest webhooks, error rates, stored captures
+
+use crate::api::json;
+use crate::auth::RouteData;
+use crate::overview;
+use worker::*;
+
+/// Totals across every webhook of the deployment
+pub async fn show(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    json(&overview::load(&ctx.env, Date::now().as_millis() as i64).await?)
+}
@@ -0,0 +1,142 @@
+//! Project settings routes
+//!
+//! - GET   /api/project   the caller's project settings (global callers pass `user_id`)
+//! - PATCH /api/project   pin the project's captures to a jurisdiction (owners only):
+//!   `{"jurisdiction": "eu" | "us" | null, "user_id"?}`
+//! - GET   /api/project/storage   bytes stored by each of the project's webhooks and in total
+//!   (as last flushed, up to a few seconds behind; global callers pass `user_id`)
+
+use crate::api::{json, query_param};
+use crate::audit::{self, AuditEntry};
+use crate::auth::{self, Principal, RouteData, Role};
+use crate::config;
+use crate::residency::{self, Jurisdiction};
+use crate::storage::{self, Consistency};
+use crate::usage::{self, Usage};
+use crate::webhooks;
+use serde::Deserialize;
+use worker::*;
+
+#[derive(Deserialize)]
+struct UpdateRequest {
+    jurisdiction: Option<String>,
+    /// Project to change (global callers only)
+    user_id: Option<String>,
+}
+
+/// The project a request is about: the caller's own, or `requested` for global callers
+pub(crate) fn project(principal: &Principal, requested: Option<String>) -> Option<String> {
+    principal.user_id.clone().or(requested)
+}
+
+/// Show a project's jurisdiction
+pub async fn show(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let Some(user_id) = project(principal, query_param(&req.url()?, "user_id")) else {
+        return Response::error("user_id is required for global callers", 400);
+    };
+    let db = ctx.env.d1("DB")?;
+    let jurisdiction = residency::of_project(&db, &user_id).await?;
+    json(&serde_json::json!({ "user_id": user_id, "jurisdiction": jurisdiction }))
+}
+
+/// Change a project's jurisdiction; refused while its webhooks still hold captures
+/// in the current region, since they would no longer be found
+pub async fn update(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    if principal.role < Role::Owner {
+        return Response::error("Forbidden", 403);
+    }
+    let body: UpdateRequest = match req.json().await {
+        Ok(body) => body,
+        Err(_) => return Response::error("Expected {\"jurisdiction\": \"eu\" | \"us\" | null}", 400),
+    };
+    let after = match &body.jurisdiction {
+        Some(value) => match Jurisdiction::parse(value) {
+            Some(jurisdiction) => Some(jurisdiction),
+            None => return Response::error("jurisdiction must be \"eu\", \"us\" or null", 400),
+        },
+        None => None,
+    };
+    let Some(user_id) = project(&principal, body.user_id) else {
+        return Response::error("user_id is required for global callers", 400);
+    };
+
+    let db = ctx.env.d1("DB")?;
+    let before = residency::of_project(&db, &user_id).await?;
+    if before == after {
+        return json(&serde_json::json!({ "user_id": user_id, "jurisdiction": after }));
+    }
+    if let Err(message) = residency::check(&ctx.env, after) {
+        return Response::error(message, 409);
+    }
+
+    let owned: Vec<_> = webhooks::in_project(&db, Some(&user_id))
+        .await?
+        .into_iter()
+        .filter(|webhook| webhook.user_id == user_id)
+        .collect();
+    let current = storage::open_in(&ctx.env, Consistency::Primary, before).await?;
+    for webhook in &owned {
+        if current.count_received(&webhook.id, None, 0, None).await? > 0 {
+            return Response::error(
+                format!("Webhook {} still has captures in the current region; erase or migrate them first", webhook.uuid),
+                409,
+            );
+        }
+    }
+
+    residency::set(&db, &user_id, after, Date::now().as_millis() as i64).await?;
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+    for webhook in &owned {
+        config::invalidate(&kv, &webhook.id).await;
+    }
+
+    let entry = AuditEntry::from_request(&req, &principal, "project.jurisdiction")
+        .target(user_id.clone())
+        .before(&serde_json::json!({ "jurisdiction": before }))
+        .after(&serde_json::json!({ "jurisdiction": after }));
+    audit::record(&db, entry).await;
+
+    json(&serde_json::json!({ "user_id": user_id, "jurisdiction": after }))
+}
+
+/// Storage usage of the webhooks a project owns
+pub async fn storage(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let Some(user_id) = project(principal, query_param(&req.url()?, "user_id")) else {
+        return Response::error("user_id is required for global callers", 400);
+    };
+    let db = ctx.env.d1("DB")?;
+    let owned: Vec<_> = webhooks::in_project(&db, Some(&user_id))
+        .await?
+        .into_iter()
+        .filter(|webhook| webhook.user_id == user_id)
+        .collect();
+    let ids: Vec<String> = owned.iter().map(|webhook| webhook.id.clone()).collect();
+    let usage = usage::flushed(&db, &ids).await?;
+
+    let mut total = Usage::default();
+    let webhooks: Vec<serde_json::Value> = owned
+        .iter()
+        .map(|webhook| {
+            let usage = usage.get(&webhook.id).copied().unwrap_or_default();
+            total.captures += usage.captures;
+            total.capture_bytes += usage.capture_bytes;
+            total.objects += usage.objects;
+            total.object_bytes += usage.object_bytes;
+            serde_json::json!({
+                "uuid": webhook.uuid,
+                "name": webhook.name,
+                "usage": usage,
+                "bytes": usage.bytes(),
+            })
+        })
+        .collect();
+    json(&serde_json::json!({
+        "user_id": user_id,
+        "webhooks": webhooks,
+        "total": total,
+        "bytes": total.bytes(),
+    }))
+}
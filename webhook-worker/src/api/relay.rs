@@ -0,0 +1,119 @@
+//! Local development relay routes
+//!
+//! - GET    /api/webhooks/{uuid}/relay              connected agents, queue depth and tokens
+//! - POST   /api/webhooks/{uuid}/relay/tokens       create a relay token: `{"name"}`
+//! - DELETE /api/webhooks/{uuid}/relay/tokens/{id}  revoke a token and disconnect its agents
+//! - GET    /relay/{uuid}                           agent WebSocket; authenticates with a relay
+//!   token (`Authorization: Bearer rly_...` or `?token=`), not an API token
+
+use crate::api::{authorized_webhook, json, query_param};
+use crate::audit::{self, AuditEntry};
+use crate::auth::{self, RouteData, Role};
+use crate::durable::relay;
+use serde::Deserialize;
+use worker::*;
+
+#[derive(Deserialize, Default)]
+struct CreateRequest {
+    name: Option<String>,
+}
+
+/// Relay status for a webhook
+pub async fn status(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    json(&relay::status(&ctx.env, &webhook_id).await?)
+}
+
+/// Create a relay token; the plain secret is only returned here
+pub async fn create_token(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let body: CreateRequest = req.json().await.unwrap_or_default();
+    let name = body
+        .name
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "relay".to_string());
+    let (token, secret) = relay::create_token(&ctx.env, &webhook_id, &name).await?;
+
+    let mut url = req.url()?;
+    url.set_scheme(if url.scheme() == "http" { "ws" } else { "wss" })
+        .ok();
+    url.set_path(&format!("/relay/{}", uuid));
+    url.set_query(None);
+
+    let entry = AuditEntry::from_request(&req, &principal, "relay.token.create")
+        .target(uuid)
+        .after(&token);
+    audit::record(&db, entry).await;
+
+    let mut response = json(&serde_json::json!({
+        "token": token,
+        "secret": secret,
+        "connect_url": url.to_string(),
+    }))?
+    .with_status(201);
+    response.headers_mut().set("Cache-Control", "no-store")?;
+    Ok(response)
+}
+
+/// Revoke a relay token
+pub async fn revoke_token(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let id = ctx.param("id").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    if !relay::revoke_token(&ctx.env, &webhook_id, &id).await? {
+        return Response::error("Relay token not found", 404);
+    }
+
+    let entry = AuditEntry::from_request(&req, &principal, "relay.token.revoke").target(format!("{}/{}", uuid, id));
+    audit::record(&db, entry).await;
+
+    json(&serde_json::json!({ "revoked": id }))
+}
+
+/// Upgrade an agent connection and hand it to the webhook's relay
+pub async fn connect(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let upgrade = req.headers().get("Upgrade")?.unwrap_or_default();
+    if !upgrade.eq_ignore_ascii_case("websocket") {
+        return Response::error("Expected a WebSocket upgrade", 426);
+    }
+
+    let token = match auth::bearer_token(&req).or_else(|| query_param(&req.url().ok()?, "token")) {
+        Some(token) => token,
+        None => return Response::error("Missing relay token", 401),
+    };
+
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let kv_store = ctx.env.kv("WEBHOOK_CACHE")?;
+    let kv = crate::kv::TolerantKv::new(&kv_store);
+    let db = ctx.env.d1("DB")?;
+    let webhook_id = match crate::cache::resolve_webhook_id(&kv, &db, &uuid).await? {
+        Some(id) => id,
+        None => return Response::error("Webhook not found", 404),
+    };
+
+    relay::connect(&ctx.env, &webhook_id, &token).await
+}
@@ -0,0 +1,555 @@
+//! Captured request listing
+//! GET /api/webhooks/{uuid}/requests with pagination, time range, sorting
+//! (`sort=received_at|event_time|sequence`, `order=asc|desc`) and indexed column filters.
+//! GET /api/webhooks/{uuid}/requests/wait long-polls for the next delivery.
+//! GET /api/webhooks/{uuid}/requests/{id} returns one capture with its processing trail,
+//! the downstream responses to its forwards and its annotations (see `annotations.rs`).
+//! GET /api/webhooks/{uuid}/requests/{id}/export downloads it as a freeze-frame
+//! snapshot, and POST /api/webhooks/{uuid}/requests/import loads one (see `snapshot.rs`).
+//! POST /api/webhooks/{uuid}/requests/{id}/replay resends it with overrides as a
+//! child capture (see `replay.rs`); POST /api/webhooks/{uuid}/replay/{recipe}
+//! does the same for a list of captures with a recipe saved in the config.
+//! GET /api/webhooks/{uuid}/export.csv streams request metadata as CSV (same
+//! time range and filters, `columns=` picks the columns, oldest first).
+
+use crate::annotations;
+use crate::api::{authorized_webhook, json, query_param};
+use crate::audit::{self, AuditEntry};
+use crate::auth::{self, RouteData, Role};
+use crate::canonical;
+use crate::config;
+use crate::db;
+use crate::durable::events;
+use crate::export::{self, Column};
+use crate::ids;
+use crate::replay::{self, Overrides};
+use crate::responses;
+use crate::snapshot::{self, Snapshot};
+use crate::storage::{self, Consistency, RequestQuery, SortColumn, Storage, StoredRequest};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use std::time::Duration;
+use webhook_types::captures::RequestList;
+use worker::*;
+
+const DEFAULT_LIMIT: u32 = 50;
+const MAX_LIMIT: u32 = 500;
+
+/// Rows fetched per storage page while exporting
+const EXPORT_PAGE: u32 = 500;
+/// Most rows one export returns unless `limit` is lower
+const MAX_EXPORT_ROWS: u32 = 100_000;
+
+const DEFAULT_WAIT_SECONDS: u64 = 30;
+const MAX_WAIT_SECONDS: u64 = 120;
+
+/// Optional equality filters on indexed columns, as (query param, column)
+pub const COLUMN_FILTERS: &[(&str, &str)] = &[
+    ("method", "method"),
+    ("content_type", "content_type"),
+    ("event_type", "event_type"),
+    ("idempotency_key", "idempotency_key"),
+    ("verification", "verification"),
+    ("environment", "environment"),
+    ("connection_id", "connection_id"),
+    ("svix_id", "svix_id"),
+    ("shopify_topic", "shopify_topic"),
+    ("shopify_shop_domain", "shopify_shop_domain"),
+    ("github_event", "github_event"),
+    ("github_installation_id", "github_installation_id"),
+    ("github_repository", "github_repository"),
+    ("replay_of", "replay_of"),
+    ("correlation_id", "correlation_id"),
+];
+
+/// List captured requests for a webhook (newest first by default)
+pub async fn list(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let webhooks_db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&webhooks_db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let url = req.url()?;
+    let limit = query_param(&url, "limit")
+        .and_then(|value| value.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_LIMIT)
+        .clamp(1, MAX_LIMIT);
+    let offset = query_param(&url, "offset")
+        .and_then(|value| value.parse::<u32>().ok())
+        .unwrap_or(0);
+    let since = query_param(&url, "since").and_then(|value| value.parse::<i64>().ok());
+    let until = query_param(&url, "until").and_then(|value| value.parse::<i64>().ok());
+
+    let sort = match query_param(&url, "sort") {
+        Some(value) => match SortColumn::parse(&value) {
+            Some(sort) => sort,
+            None => return Response::error("Invalid sort column", 400),
+        },
+        None => SortColumn::default(),
+    };
+    let ascending = query_param(&url, "order").as_deref() == Some("asc");
+
+    let filters = COLUMN_FILTERS
+        .iter()
+        .filter_map(|(param, column)| query_param(&url, param).map(|value| (*column, value)))
+        .collect();
+
+    // Reads may be served by a replica; clients pass the bookmark back for read-your-writes
+    let bookmark = req.headers().get(db::BOOKMARK_HEADER)?;
+    let storage = storage::for_webhook(&ctx.env, &webhook_id, Consistency::Replica { bookmark }).await?;
+    let rows = storage
+        .list_requests(&RequestQuery {
+            webhook_id,
+            limit,
+            offset,
+            since,
+            until,
+            sort,
+            ascending,
+            filters,
+        })
+        .await?;
+
+    let mut response = json(&RequestList {
+        webhook_id: uuid,
+        requests: rows,
+        limit,
+        offset,
+    })?;
+    if let Some(bookmark) = storage.bookmark() {
+        response.headers_mut().set(db::BOOKMARK_HEADER, &bookmark)?;
+    }
+
+    Ok(response)
+}
+
+/// One captured request, with its `processing` trail and binary `preview` as
+/// JSON objects and what every forwarding target answered
+pub async fn show(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let id = ctx.param("id").cloned().unwrap_or_default();
+    let webhooks_db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&webhooks_db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let bookmark = req.headers().get(db::BOOKMARK_HEADER)?;
+    let storage = storage::for_webhook(&ctx.env, &webhook_id, Consistency::Replica { bookmark }).await?;
+    let Some(row) = find_request(storage.as_ref(), &webhook_id, id).await? else {
+        return Response::error("Request not found", 404);
+    };
+
+    let as_object = |column: &Option<String>| {
+        column
+            .as_deref()
+            .and_then(|value| serde_json::from_str(value).ok())
+            .unwrap_or(serde_json::Value::Null)
+    };
+    let processing = as_object(&row.processing);
+    let preview = as_object(&row.preview);
+    let responses = responses::for_capture(&webhooks_db, &webhook_id, &row.id).await?;
+    let annotations = annotations::for_capture(&webhooks_db, &webhook_id, &row.id).await?;
+    let mut request = serde_json::to_value(&row)?;
+    request["processing"] = processing;
+    request["preview"] = preview;
+    if query_param(&req.url()?, "pretty").as_deref() == Some("true") {
+        if let Some(pretty) = row.canonical_data.as_deref().and_then(canonical::pretty) {
+            request["canonical_data"] = serde_json::Value::String(pretty);
+        }
+    }
+
+    let mut response = json(&serde_json::json!({
+        "webhook_id": uuid,
+        "request": request,
+        "responses": responses,
+        "annotations": annotations,
+    }))?;
+    if let Some(bookmark) = storage.bookmark() {
+        response.headers_mut().set(db::BOOKMARK_HEADER, &bookmark)?;
+    }
+    Ok(response)
+}
+
+/// One capture by ID
+pub(crate) async fn find_request(
+    storage: &dyn Storage,
+    webhook_id: &str,
+    id: String,
+) -> Result<Option<StoredRequest>> {
+    let rows = storage
+        .list_requests(&RequestQuery {
+            webhook_id: webhook_id.to_string(),
+            limit: 1,
+            offset: 0,
+            since: None,
+            until: None,
+            sort: SortColumn::default(),
+            ascending: false,
+            filters: vec![("id", id)],
+        })
+        .await?;
+    Ok(rows.into_iter().next())
+}
+
+/// Download one capture with its responses as a self-contained snapshot
+pub async fn export_one(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let id = ctx.param("id").cloned().unwrap_or_default();
+    let webhooks_db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&webhooks_db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let storage = storage::for_webhook(&ctx.env, &webhook_id, Consistency::Primary).await?;
+    let Some(row) = find_request(storage.as_ref(), &webhook_id, id).await? else {
+        return Response::error("Request not found", 404);
+    };
+    let responses = responses::for_capture(&webhooks_db, &webhook_id, &row.id).await?;
+    let source = snapshot::Source {
+        webhook_uuid: uuid,
+        capture_id: row.id.clone(),
+        host: req.url()?.host_str().map(str::to_string),
+    };
+    let filename = format!("capture-{}.json", row.id);
+    let snapshot = snapshot::build(source, row, responses, Date::now().as_millis() as i64);
+
+    let mut response = json(&snapshot)?;
+    response
+        .headers_mut()
+        .set("Content-Disposition", &format!("attachment; filename=\"{}\"", filename))?;
+    Ok(response)
+}
+
+/// Load a snapshot (from this or another instance) as a new capture of the webhook
+pub async fn import(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let webhooks_db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&webhooks_db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let snapshot: Snapshot = match req.json().await {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Response::error(format!("Invalid snapshot: {}", e), 400),
+    };
+    let received_at_ms = snapshot.request.received_at_ms.unwrap_or(snapshot.request.received_at * 1000);
+    let id = ids::new_capture_id(&ctx.env, received_at_ms);
+    let record = match snapshot::into_record(&snapshot, &webhook_id, &id) {
+        Ok(record) => record,
+        Err(message) => return Response::error(format!("Invalid snapshot: {}", message), 400),
+    };
+
+    let storage = storage::for_webhook(&ctx.env, &webhook_id, Consistency::Primary).await?;
+    storage.insert_capture(&record).await?;
+    for response in &snapshot.responses {
+        responses::restore(&webhooks_db, &webhook_id, &id, response).await?;
+    }
+
+    let entry = AuditEntry::from_request(&req, &principal, "request.import")
+        .target(uuid.clone())
+        .after(&serde_json::json!({ "id": id, "source": snapshot.source }));
+    audit::record(&webhooks_db, entry).await;
+
+    json(&serde_json::json!({
+        "webhook_id": uuid,
+        "id": id,
+        "source": snapshot.source,
+        "responses": snapshot.responses.len(),
+    }))
+}
+
+/// Resend a capture with overrides; the variant is stored as a capture with `replay_of` set
+pub async fn replay(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let id = ctx.param("id").cloned().unwrap_or_default();
+    let webhooks_db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&webhooks_db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let overrides: Overrides = match req.text().await {
+        Ok(text) if text.trim().is_empty() => Overrides::default(),
+        Ok(text) => match serde_json::from_str(&text) {
+            Ok(overrides) => overrides,
+            Err(e) => return Response::error(format!("Invalid overrides: {}", e), 400),
+        },
+        Err(_) => return Response::error("Expected a JSON body", 400),
+    };
+    let storage = storage::for_webhook(&ctx.env, &webhook_id, Consistency::Primary).await?;
+    let Some(original) = find_request(storage.as_ref(), &webhook_id, id).await? else {
+        return Response::error("Request not found", 404);
+    };
+    let variant = match replay::apply(&original, &overrides) {
+        Ok(variant) => variant,
+        Err(message) => return Response::error(message, 400),
+    };
+
+    let settings = config::load_from_d1(&webhooks_db, &webhook_id).await?;
+    let resend = replay::Resend {
+        env: &ctx.env,
+        db: &webhooks_db,
+        storage: storage.as_ref(),
+        webhook_id: &webhook_id,
+        capture_url: replay::capture_url(&req.url()?, &uuid),
+        signing: settings.config.forward_signing.as_ref(),
+    };
+    let mut replayed = resend.send(&original, &variant).await?;
+    replayed["webhook_id"] = serde_json::json!(uuid);
+
+    let entry = AuditEntry::from_request(&req, &principal, "request.replay")
+        .target(uuid.clone())
+        .after(&serde_json::json!({ "id": replayed["id"], "replay_of": original.id, "target": variant.target }));
+    audit::record(&webhooks_db, entry).await;
+    Ok(json(&replayed)?.with_status(201))
+}
+
+#[derive(Deserialize)]
+struct RecipeRun {
+    ids: Vec<String>,
+}
+
+/// Run a saved replay recipe on one or more captures, `pacing_ms` apart
+pub async fn replay_recipe(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let name = ctx.param("recipe").cloned().unwrap_or_default();
+    let webhooks_db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&webhooks_db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+    let settings = config::load_from_d1(&webhooks_db, &webhook_id).await?;
+    let Some(recipe) = settings.config.replay_recipes.get(&name) else {
+        return Response::error("Replay recipe not found", 404);
+    };
+    let ids = match req.json::<RecipeRun>().await {
+        Ok(run) if (1..=replay::MAX_RUN_CAPTURES).contains(&run.ids.len()) => run.ids,
+        _ => {
+            let message = format!("Expected {{\"ids\": [...]}} with 1 to {} capture IDs", replay::MAX_RUN_CAPTURES);
+            return Response::error(message, 400);
+        }
+    };
+
+    // Every capture is looked up and edited before the first one is resent
+    let storage = storage::for_webhook(&ctx.env, &webhook_id, Consistency::Primary).await?;
+    let mut variants = Vec::with_capacity(ids.len());
+    for id in ids {
+        let Some(original) = find_request(storage.as_ref(), &webhook_id, id.clone()).await? else {
+            return Response::error(format!("Request {} not found", id), 404);
+        };
+        match replay::apply(&original, &recipe.overrides) {
+            Ok(variant) => variants.push((original, variant)),
+            Err(message) => return Response::error(format!("Request {}: {}", id, message), 400),
+        }
+    }
+
+    let resend = replay::Resend {
+        env: &ctx.env,
+        db: &webhooks_db,
+        storage: storage.as_ref(),
+        webhook_id: &webhook_id,
+        capture_url: replay::capture_url(&req.url()?, &uuid),
+        signing: settings.config.forward_signing.as_ref().filter(|_| recipe.sign),
+    };
+    let mut replays = Vec::with_capacity(variants.len());
+    for (index, (original, variant)) in variants.iter().enumerate() {
+        if index > 0 && recipe.pacing_ms > 0 {
+            Delay::from(Duration::from_millis(recipe.pacing_ms as u64)).await;
+        }
+        replays.push(resend.send(original, variant).await?);
+    }
+
+    let entry = AuditEntry::from_request(&req, &principal, "request.replay")
+        .target(uuid.clone())
+        .after(&serde_json::json!({
+            "recipe": name,
+            "ids": replays.iter().map(|replayed| replayed["id"].clone()).collect::<Vec<_>>(),
+            "replay_of": variants.iter().map(|(original, _)| original.id.as_str()).collect::<Vec<_>>(),
+        }));
+    audit::record(&webhooks_db, entry).await;
+    let response = json(&serde_json::json!({
+        "webhook_id": uuid,
+        "recipe": name,
+        "replays": replays,
+    }))?;
+    Ok(response.with_status(201))
+}
+
+/// Export position; each step renders the next storage page
+struct ExportCursor {
+    storage: Box<dyn Storage>,
+    query: RequestQuery,
+    max_rows: u32,
+    columns: Vec<Column>,
+    provider: Option<String>,
+    done: bool,
+}
+
+impl ExportCursor {
+    async fn next_page(&mut self) -> Result<Option<String>> {
+        if self.done || self.query.offset >= self.max_rows {
+            return Ok(None);
+        }
+        self.query.limit = EXPORT_PAGE.min(self.max_rows - self.query.offset);
+        let rows = self.storage.list_requests(&self.query).await?;
+        self.done = (rows.len() as u32) < self.query.limit;
+        self.query.offset += rows.len() as u32;
+        Ok(Some(
+            rows.iter()
+                .map(|request| export::row(&self.columns, request, self.provider.as_deref()))
+                .collect(),
+        ))
+    }
+}
+
+/// Stream request metadata as CSV, page by page from storage
+pub async fn export(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let webhooks_db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&webhooks_db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let url = req.url()?;
+    let columns = query_param(&url, "columns").unwrap_or_else(|| export::DEFAULT_COLUMNS.to_string());
+    let columns: Vec<Column> = match export::parse_columns(&columns) {
+        Ok(columns) => columns,
+        Err(column) => return Response::error(format!("Unknown export column: {}", column), 400),
+    };
+    let max_rows = query_param(&url, "limit")
+        .and_then(|value| value.parse::<u32>().ok())
+        .unwrap_or(MAX_EXPORT_ROWS)
+        .clamp(1, MAX_EXPORT_ROWS);
+    let since = query_param(&url, "since").and_then(|value| value.parse::<i64>().ok());
+    let until = query_param(&url, "until").and_then(|value| value.parse::<i64>().ok());
+    let filters: Vec<(&'static str, String)> = COLUMN_FILTERS
+        .iter()
+        .filter_map(|(param, column)| query_param(&url, param).map(|value| (*column, value)))
+        .collect();
+    // Excel only detects UTF-8 with a byte order mark
+    let bom = query_param(&url, "bom").as_deref() == Some("true");
+
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+    let settings = config::load(&kv, &webhooks_db, &webhook_id).await?;
+    let provider = settings
+        .config
+        .signature
+        .and_then(|signature| serde_json::to_value(signature.provider).ok())
+        .and_then(|provider| provider.as_str().map(str::to_string));
+
+    let storage = storage::for_webhook(&ctx.env, &webhook_id, Consistency::Replica { bookmark: None }).await?;
+    let mut head = if bom { "\u{feff}".to_string() } else { String::new() };
+    head.push_str(&export::header_row(&columns));
+
+    let cursor = ExportCursor {
+        storage,
+        query: RequestQuery {
+            webhook_id,
+            limit: EXPORT_PAGE,
+            offset: 0,
+            since,
+            until,
+            sort: SortColumn::ReceivedAt,
+            ascending: true,
+            filters,
+        },
+        max_rows,
+        columns,
+        provider,
+        done: false,
+    };
+    let pages = futures_util::stream::try_unfold(cursor, |mut cursor| async move {
+        let Some(chunk) = cursor.next_page().await? else {
+            return Ok(None);
+        };
+        Ok::<_, Error>(Some((chunk.into_bytes(), cursor)))
+    });
+    let body = futures_util::stream::once(async move { Ok::<_, Error>(head.into_bytes()) }).chain(pages);
+
+    let mut response = Response::from_stream(body)?;
+    let headers = response.headers_mut();
+    headers.set("Content-Type", "text/csv; charset=utf-8")?;
+    headers.set("Content-Disposition", &format!("attachment; filename=\"{}.csv\"", uuid))?;
+    crate::set_cors_headers(headers)?;
+    Ok(response)
+}
+
+/// Parse a wait timeout such as `30s`, `2m` or `45` (seconds)
+fn parse_timeout(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if let Some(minutes) = value.strip_suffix('m') {
+        return minutes.parse::<u64>().ok().map(|minutes| minutes * 60);
+    }
+    value.strip_suffix('s').unwrap_or(value).parse().ok()
+}
+
+/// Block until the next request arrives or the timeout elapses.
+/// With `since_ms`, a request received after that time returns immediately.
+pub async fn wait(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let webhooks_db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&webhooks_db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+    if !events::is_enabled(&ctx.env) {
+        return Response::error("Live events are disabled", 503);
+    }
+
+    let url = req.url()?;
+    let timeout = query_param(&url, "timeout")
+        .and_then(|value| parse_timeout(&value))
+        .unwrap_or(DEFAULT_WAIT_SECONDS)
+        .clamp(1, MAX_WAIT_SECONDS);
+
+    // Catch deliveries that landed between the client's last read and this call
+    if let Some(since_ms) = query_param(&url, "since_ms").and_then(|value| value.parse::<i64>().ok()) {
+        let storage = storage::for_webhook(&ctx.env, &webhook_id, Consistency::Replica { bookmark: None }).await?;
+        let missed = storage
+            .list_requests(&RequestQuery {
+                webhook_id: webhook_id.clone(),
+                limit: MAX_LIMIT,
+                offset: 0,
+                since: Some(since_ms / 1000),
+                until: None,
+                sort: SortColumn::ReceivedAt,
+                ascending: true,
+                filters: Vec::new(),
+            })
+            .await?
+            .into_iter()
+            .find(|request| request.received_at_ms.unwrap_or(request.received_at * 1000) > since_ms);
+        if let Some(request) = missed {
+            return json(&serde_json::json!({ "webhook_id": uuid, "request": request, "timed_out": false }));
+        }
+    }
+
+    let request = events::wait(&ctx.env, &webhook_id, Duration::from_secs(timeout)).await?;
+    json(&serde_json::json!({
+        "webhook_id": uuid,
+        "timed_out": request.is_none(),
+        "request": request,
+    }))
+}
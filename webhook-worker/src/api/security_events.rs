@@ -0,0 +1,46 @@
+//! Security event routes
+//!
+//! - GET /api/admin/security-events  signature failures, scanner blocks, token
+//!   misuse and enumeration, newest first (`kind`, `ip`, `webhook`, `since`,
+//!   `limit`, `offset`)
+
+use crate::api::{json, query_param};
+use crate::auth::RouteData;
+use crate::security_events::{self, EventQuery, Kind};
+use worker::*;
+
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+/// List security events
+pub async fn list(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let url = req.url()?;
+    let kind = match query_param(&url, "kind") {
+        Some(value) => match Kind::parse(&value) {
+            Some(kind) => Some(kind),
+            None => return Response::error(format!("Unknown security event kind: {}", value), 400),
+        },
+        None => None,
+    };
+    let query = EventQuery {
+        kind,
+        ip: query_param(&url, "ip"),
+        webhook_id: query_param(&url, "webhook"),
+        since: query_param(&url, "since").and_then(|value| value.parse().ok()),
+        limit: query_param(&url, "limit")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_LIMIT)
+            .clamp(1, MAX_LIMIT),
+        offset: query_param(&url, "offset")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0),
+    };
+
+    let db = ctx.env.d1("DB")?;
+    let events = security_events::list(&db, &query).await?;
+    json(&serde_json::json!({
+        "events": events,
+        "limit": query.limit,
+        "offset": query.offset,
+    }))
+}
@@ -0,0 +1,61 @@
+//! Session listing
+//! GET /api/webhooks/{uuid}/sessions groups the webhook's most recent captures
+//! (`since`/`until` narrow the time range) into sessions of related deliveries
+//! (see `sessions.rs`), newest first. Webhooks without a `sessions` config are
+//! grouped by time alone, five minutes apart at most.
+
+use crate::api::{authorized_webhook, json, query_param};
+use crate::auth::{self, RouteData, Role};
+use crate::config;
+use crate::sessions::{self, MAX_CAPTURES};
+use crate::storage::{self, Consistency, RequestQuery, SortColumn};
+use worker::*;
+
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 100;
+
+pub async fn list(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let url = req.url()?;
+    let limit = query_param(&url, "limit")
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_LIMIT)
+        .clamp(1, MAX_LIMIT);
+    let since = query_param(&url, "since").and_then(|value| value.parse::<i64>().ok());
+    let until = query_param(&url, "until").and_then(|value| value.parse::<i64>().ok());
+
+    let grouping = config::load_from_d1(&db, &webhook_id).await?.config.sessions.unwrap_or_default();
+    let storage = storage::for_webhook(&ctx.env, &webhook_id, Consistency::Replica { bookmark: None }).await?;
+    let captures = storage
+        .list_requests(&RequestQuery {
+            webhook_id,
+            limit: MAX_CAPTURES,
+            offset: 0,
+            since,
+            until,
+            sort: SortColumn::ReceivedAt,
+            ascending: false,
+            filters: Vec::new(),
+        })
+        .await?;
+
+    let mut sessions = sessions::group(&captures, &grouping);
+    let total = sessions.len();
+    sessions.truncate(limit);
+    json(&serde_json::json!({
+        "webhook_id": uuid,
+        "sessions": sessions,
+        "total": total,
+        "captures_scanned": captures.len(),
+        "truncated": captures.len() == MAX_CAPTURES as usize,
+        "gap_seconds": grouping.gap_seconds,
+    }))
+}
@@ -0,0 +1,225 @@
+//! Webhook statistics routes
+//!
+//! - GET /api/webhooks/{uuid}/stats/forwarding  per forward target latency percentiles and
+//!   histograms over the last `days` (default 7, max 30)
+//! - GET /api/webhooks/{uuid}/stats/daily  daily capture aggregates kept by tiered retention
+//! - GET /api/webhooks/{uuid}/stats/shopify  Shopify captures per shop and topic over the last `days`
+//! - GET /api/webhooks/{uuid}/stats/split  A/B forwarding: each arm's status codes and latency, and the
+//!   comparisons of deliveries sent to both (`days`, `limit` of comparisons listed)
+//! - GET /api/webhooks/{uuid}/stats/shadow  shadow forwarding mismatch report over the last `days`
+//!   (`limit` of mismatching captures listed)
+//! - GET /api/webhooks/{uuid}/stats/storage  bytes stored by captures and R2 objects, and the storage quota
+
+use crate::api::{authorized_webhook, json, query_param};
+use crate::auth::{self, RouteData, Role};
+use crate::config;
+use crate::latency;
+use crate::retention;
+use crate::split;
+use crate::storage::{self, Consistency};
+use crate::usage;
+use worker::*;
+
+const DEFAULT_DAYS: u32 = 7;
+
+/// Comparisons listed by the split and shadow stats unless `limit` is given
+const DEFAULT_COMPARISONS: u32 = 20;
+const MAX_COMPARISONS: u32 = 200;
+
+/// Most recent shadow comparisons a mismatch report sums up
+const MAX_REPORTED: u32 = 10_000;
+
+/// Longest window of the Shopify stats (captures past the retention are gone anyway)
+const MAX_SHOPIFY_DAYS: u32 = 90;
+
+/// Forward target response times: p50/p95/p99, failures and buckets per target
+pub async fn forwarding(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let days = query_param(&req.url()?, "days")
+        .and_then(|value| value.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_DAYS)
+        .clamp(1, latency::RETENTION_DAYS);
+    let now = (Date::now().as_millis() / 1000) as i64;
+    let targets: serde_json::Map<String, serde_json::Value> = latency::histograms(&db, &webhook_id, days, now)
+        .await?
+        .into_iter()
+        .map(|(target, histogram)| (target, histogram.summary()))
+        .collect();
+
+    json(&serde_json::json!({
+        "webhook_id": uuid,
+        "days": days,
+        "targets": targets,
+    }))
+}
+
+/// Daily counts and bytes per event type rolled up by tiered retention, in `[since, until)`
+pub async fn daily(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let url = req.url()?;
+    let since = query_param(&url, "since").and_then(|value| value.parse::<i64>().ok()).unwrap_or(0);
+    let until = query_param(&url, "until")
+        .and_then(|value| value.parse::<i64>().ok())
+        .unwrap_or(i64::MAX);
+    let days = retention::daily(&db, &webhook_id, since, until).await?;
+
+    json(&serde_json::json!({
+        "webhook_id": uuid,
+        "days": days,
+    }))
+}
+
+/// Shopify captures per shop domain and topic: count, bytes, unverified deliveries and the latest one
+pub async fn shopify(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let days = query_param(&req.url()?, "days")
+        .and_then(|value| value.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_DAYS)
+        .clamp(1, MAX_SHOPIFY_DAYS);
+    let now = (Date::now().as_millis() / 1000) as i64;
+    let storage = storage::for_webhook(&ctx.env, &webhook_id, Consistency::Replica { bookmark: None }).await?;
+    let topics = storage.shopify_counts(&webhook_id, now - days as i64 * 86_400, now + 1).await?;
+
+    json(&serde_json::json!({
+        "webhook_id": uuid,
+        "days": days,
+        "topics": topics,
+    }))
+}
+
+/// A/B forwarding outcome per arm, and how the answers to deliveries sent to both compared
+pub async fn split(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+    let Some(traffic) = config::load_from_d1(&db, &webhook_id).await?.config.split else {
+        return Response::error("Webhook does not split traffic", 404);
+    };
+
+    let url = req.url()?;
+    let days = query_param(&url, "days")
+        .and_then(|value| value.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_DAYS)
+        .clamp(1, latency::RETENTION_DAYS);
+    let limit = query_param(&url, "limit")
+        .and_then(|value| value.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_COMPARISONS)
+        .clamp(1, MAX_COMPARISONS);
+    let now = (Date::now().as_millis() / 1000) as i64;
+    let since_ms = (now - days as i64 * 86_400) * 1000;
+    let mut histograms = latency::histograms(&db, &webhook_id, days, now).await?;
+    let mut statuses = split::statuses(&db, &webhook_id, &traffic, since_ms).await?;
+    let mut arm = |target: &str, percent: u8| {
+        serde_json::json!({
+            "target": target,
+            "percent": percent,
+            "statuses": statuses.remove(target).unwrap_or_default(),
+            "latency": histograms.remove(target).unwrap_or_default().summary(),
+        })
+    };
+    let (a, b) = (arm(&traffic.a, 100 - traffic.b_percent), arm(&traffic.b, traffic.b_percent));
+
+    let comparisons = split::comparisons(&db, &webhook_id, split::Mode::Split, since_ms, MAX_COMPARISONS).await?;
+    let mismatched = |field: fn(&split::Comparison) -> bool| comparisons.iter().filter(|c| !field(c)).count();
+    json(&serde_json::json!({
+        "webhook_id": uuid,
+        "days": days,
+        "compare_percent": traffic.compare_percent,
+        "a": a,
+        "b": b,
+        "comparisons": {
+            "count": comparisons.len(),
+            "status_mismatches": mismatched(|comparison| comparison.status_match),
+            "body_mismatches": mismatched(|comparison| comparison.body_match),
+            "recent": &comparisons[..comparisons.len().min(limit as usize)],
+        },
+    }))
+}
+
+/// Shadow forwarding mismatch report: status pairs, the body paths differing most and the latest mismatches
+pub async fn shadow(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+    let shadow = config::load_from_d1(&db, &webhook_id).await?.config.shadow;
+
+    let url = req.url()?;
+    let days = query_param(&url, "days")
+        .and_then(|value| value.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_DAYS)
+        .clamp(1, split::RETENTION_DAYS as u32);
+    let limit = query_param(&url, "limit")
+        .and_then(|value| value.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_COMPARISONS)
+        .clamp(1, MAX_COMPARISONS);
+    let since_ms = Date::now().as_millis() as i64 - days as i64 * 86_400_000;
+    let comparisons = split::comparisons(&db, &webhook_id, split::Mode::Shadow, since_ms, MAX_REPORTED).await?;
+
+    json(&serde_json::json!({
+        "webhook_id": uuid,
+        "days": days,
+        "primary": shadow.as_ref().map(|shadow| &shadow.primary),
+        "shadow": shadow.as_ref().map(|shadow| &shadow.shadow),
+        "report": split::report(&comparisons, limit as usize),
+    }))
+}
+
+/// Live storage usage against the webhook's `storage_quota`
+pub async fn storage(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let usage = usage::current(&ctx.env, &webhook_id).await?;
+    let max_bytes = config::load_from_d1(&db, &webhook_id)
+        .await?
+        .config
+        .storage_quota
+        .map(|quota| quota.max_bytes);
+    json(&serde_json::json!({
+        "webhook_id": uuid,
+        "usage": usage,
+        "bytes": usage.bytes(),
+        "max_bytes": max_bytes,
+        "used_share": max_bytes.map(|max_bytes| usage.bytes() as f64 / max_bytes as f64),
+    }))
+}
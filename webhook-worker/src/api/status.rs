@@ -0,0 +1,148 @@
+//! Public status page routes
+//!
+//! - POST /api/webhooks/{uuid}/status-url  mint a shareable status link: `{"ttl_seconds": 604800}`
+//! - GET  /status/{uuid}                   status summary; authenticates with the link's `exp` / `sig`,
+//!   not an API token (`format=html` or `Accept: text/html` for the page)
+
+use crate::api::{authorized_webhook, json, query_param};
+use crate::audit::{self, AuditEntry};
+use crate::auth::{self, RouteData, Role};
+use crate::config;
+use crate::latency;
+use crate::signed_url::{self, SignedUrlError};
+use crate::status_page::{Forwarding, Summary, Volume};
+use crate::storage::{self, Consistency, RequestQuery, SortColumn};
+use crate::webhooks;
+use serde::Deserialize;
+use worker::*;
+
+const DEFAULT_STATUS_TTL_SECONDS: i64 = 7 * 86_400;
+const MAX_STATUS_TTL_SECONDS: i64 = 90 * 86_400;
+
+/// Edge cache lifetime of a rendered summary
+const STATUS_MAX_AGE_SECONDS: u32 = 60;
+
+const FORWARDING_DAYS: u32 = 7;
+
+#[derive(Deserialize, Default)]
+struct StatusUrlRequest {
+    ttl_seconds: Option<i64>,
+}
+
+/// Mint a status link that stops working after `ttl_seconds`
+pub async fn create_url(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let body: StatusUrlRequest = req.json().await.unwrap_or_default();
+    let ttl_seconds = body
+        .ttl_seconds
+        .unwrap_or(DEFAULT_STATUS_TTL_SECONDS)
+        .clamp(1, MAX_STATUS_TTL_SECONDS);
+
+    let secret = config::ensure_secret(&kv, &db, &webhook_id).await?;
+    let expires_at = (Date::now().as_millis() / 1000) as i64 + ttl_seconds;
+    let signature = signed_url::sign_status(&secret, &uuid, expires_at);
+
+    let mut url = req.url()?;
+    url.set_path(&format!("/status/{}", uuid));
+    url.set_query(None);
+    url.query_pairs_mut()
+        .append_pair(signed_url::EXP_PARAM, &expires_at.to_string())
+        .append_pair(signed_url::SIG_PARAM, &signature);
+
+    let entry = AuditEntry::from_request(&req, &principal, "webhook.status_url")
+        .target(uuid)
+        .after(&serde_json::json!({ "expires_at": expires_at }));
+    audit::record(&db, entry).await;
+
+    json(&serde_json::json!({
+        "url": url.to_string(),
+        "expires_at": expires_at,
+    }))
+}
+
+/// Public status summary of a webhook, for holders of a status link
+pub async fn show(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let url = req.url()?;
+    let db = ctx.env.d1("DB")?;
+    let now = (Date::now().as_millis() / 1000) as i64;
+
+    // Unknown webhooks and bad signatures look the same
+    let Some(webhook) = webhooks::find_by_uuid(&db, &uuid).await? else {
+        return Response::error("Invalid status link", 403);
+    };
+    let secret = config::load_from_d1(&db, &webhook.id).await?.secret;
+    let exp = query_param(&url, signed_url::EXP_PARAM);
+    let sig = query_param(&url, signed_url::SIG_PARAM);
+    let verified = match &secret {
+        Some(secret) => signed_url::verify_status(secret, &uuid, exp.as_deref(), sig.as_deref(), now),
+        None => Err(SignedUrlError::BadSignature),
+    };
+    match verified {
+        Ok(()) => {}
+        Err(SignedUrlError::Expired) => return Response::error("Status link expired", 410),
+        Err(SignedUrlError::Malformed | SignedUrlError::BadSignature) => {
+            return Response::error("Invalid status link", 403)
+        }
+    }
+
+    let storage = storage::for_webhook(&ctx.env, &webhook.id, Consistency::Replica { bookmark: None }).await?;
+    let volume = Volume {
+        last_hour: storage.count_received(&webhook.id, None, now - 3600, None).await?,
+        last_24h: storage.count_received(&webhook.id, None, now - 86_400, None).await?,
+        last_7d: storage.count_received(&webhook.id, None, now - 7 * 86_400, None).await?,
+    };
+    let histograms = latency::histograms(&db, &webhook.id, FORWARDING_DAYS, now).await?;
+    let latest = storage
+        .list_requests(&RequestQuery {
+            webhook_id: webhook.id.clone(),
+            limit: 1,
+            offset: 0,
+            since: None,
+            until: None,
+            sort: SortColumn::ReceivedAt,
+            ascending: false,
+            filters: Vec::new(),
+        })
+        .await?;
+    let last_delivery_ms = latest
+        .first()
+        .map(|request| request.received_at_ms.unwrap_or(request.received_at * 1000));
+
+    let summary = Summary::new(
+        uuid,
+        webhook.name,
+        volume,
+        Forwarding::from_histograms(histograms.values()),
+        last_delivery_ms,
+        now * 1000,
+    );
+
+    let wants_html = match query_param(&url, "format") {
+        Some(format) => format == "html",
+        None => req
+            .headers()
+            .get("Accept")?
+            .is_some_and(|accept| accept.contains("text/html")),
+    };
+    let mut response = if wants_html {
+        let mut response = Response::ok(summary.html())?;
+        response.headers_mut().set("Content-Type", "text/html; charset=utf-8")?;
+        response
+    } else {
+        json(&summary)?
+    };
+    response
+        .headers_mut()
+        .set("Cache-Control", &format!("public, max-age={}", STATUS_MAX_AGE_SECONDS))?;
+    Ok(response)
+}
@@ -0,0 +1,124 @@
+//! NDJSON tail stream
+//! GET /api/webhooks/{uuid}/tail keeps the response open and writes one JSON
+//! object per line for every new capture, for `curl -N ... | jq` workflows.
+//! `backlog=N` first replays the N most recent requests (oldest first); the
+//! indexed column filters of the list endpoint are applied server-side. The
+//! stream subscribes before reading the backlog, so a capture arriving in
+//! between is in both; its live line is dropped.
+
+use crate::api::requests::COLUMN_FILTERS;
+use crate::api::{authorized_webhook, query_param};
+use crate::auth::{self, RouteData, Role};
+use crate::durable::events;
+use crate::storage::{self, Consistency, RequestQuery, SortColumn};
+use futures_util::future::ready;
+use futures_util::{stream, StreamExt};
+use serde::Deserialize;
+use std::collections::HashSet;
+use worker::*;
+
+const MAX_BACKLOG: u32 = 100;
+
+#[derive(Deserialize)]
+struct LineId {
+    id: String,
+}
+
+/// Live NDJSON lines minus the captures the backlog already sent
+pub struct BacklogDedup {
+    /// IDs sent in the backlog and not seen live yet
+    sent: HashSet<String>,
+    /// Start of a line the last chunk ended in
+    partial: Vec<u8>,
+}
+
+impl BacklogDedup {
+    pub fn new(sent: HashSet<String>) -> Self {
+        Self { sent, partial: Vec::new() }
+    }
+
+    /// The complete lines `chunk` ends (a partial one is held back for the next)
+    /// whose capture wasn't in the backlog
+    pub fn filter(&mut self, chunk: &[u8]) -> Vec<u8> {
+        if self.sent.is_empty() && self.partial.is_empty() {
+            return chunk.to_vec();
+        }
+        self.partial.extend_from_slice(chunk);
+        let Some(end) = self.partial.iter().rposition(|byte| *byte == b'\n') else {
+            return Vec::new();
+        };
+        let rest = self.partial.split_off(end + 1);
+        let complete = std::mem::replace(&mut self.partial, rest);
+        let mut lines = Vec::with_capacity(complete.len());
+        for line in complete.split_inclusive(|byte| *byte == b'\n') {
+            let id = serde_json::from_slice::<LineId>(line).ok().map(|line| line.id);
+            if !id.is_some_and(|id| self.sent.remove(&id)) {
+                lines.extend_from_slice(line);
+            }
+        }
+        lines
+    }
+}
+
+/// Stream new captures as NDJSON
+pub async fn stream(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+    if !events::is_enabled(&ctx.env) {
+        return Response::error("Live events are disabled", 503);
+    }
+
+    let url = req.url()?;
+    let filters: Vec<(&'static str, String)> = COLUMN_FILTERS
+        .iter()
+        .filter_map(|(param, column)| query_param(&url, param).map(|value| (*column, value)))
+        .collect();
+    let backlog = query_param(&url, "backlog")
+        .and_then(|value| value.parse::<u32>().ok())
+        .unwrap_or(0)
+        .min(MAX_BACKLOG);
+
+    // Subscribe before reading the backlog so nothing falls in between
+    let live = events::subscribe(&ctx.env, &webhook_id, &filters).await?;
+
+    let mut lines = Vec::new();
+    let mut sent = HashSet::new();
+    if backlog > 0 {
+        let storage = storage::for_webhook(&ctx.env, &webhook_id, Consistency::Replica { bookmark: None }).await?;
+        let mut recent = storage
+            .list_requests(&RequestQuery {
+                webhook_id,
+                limit: backlog,
+                offset: 0,
+                since: None,
+                until: None,
+                sort: SortColumn::ReceivedAt,
+                ascending: false,
+                filters,
+            })
+            .await?;
+        recent.reverse();
+        for request in recent {
+            sent.insert(request.id.clone());
+            lines.push(Ok::<Vec<u8>, Error>(format!("{}\n", serde_json::to_string(&request)?).into_bytes()));
+        }
+    }
+
+    let mut dedup = BacklogDedup::new(sent);
+    let live = live
+        .map(move |chunk| chunk.map(|chunk| dedup.filter(&chunk)))
+        .filter(|chunk| ready(!chunk.as_ref().is_ok_and(Vec::is_empty)));
+    let body = stream::iter(lines).chain(live);
+    let mut response = Response::from_stream(body)?;
+    let headers = response.headers_mut();
+    headers.set("Content-Type", "application/x-ndjson")?;
+    headers.set("Cache-Control", "no-store")?;
+    crate::set_cors_headers(headers)?;
+    Ok(response)
+}
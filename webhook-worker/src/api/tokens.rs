@@ -0,0 +1,191 @@
+//! Project API token management (owner role)
+//!
+//! - GET    /api/tokens       list tokens (global callers may filter with `user_id`)
+//! - POST   /api/tokens              create: `{"name", "role", "user_id"?, "scopes"?, "expires_in_seconds"?}`
+//! - POST   /api/tokens/{id}/rotate  new secret; the old one works for `grace_seconds` (default 300)
+//! - DELETE /api/tokens/{id}         revoke a token
+
+use crate::api::{json, query_param};
+use crate::audit::{self, AuditEntry};
+use crate::auth::{self, Principal, RouteData, Role, Scope};
+use crate::tokens::{self, ApiToken, NewToken};
+use serde::Deserialize;
+use worker::*;
+
+#[derive(Deserialize)]
+struct CreateRequest {
+    name: String,
+    role: String,
+    /// Project to create the token in (global callers only)
+    user_id: Option<String>,
+    /// Omitted: inherit the caller's scopes
+    scopes: Option<Vec<String>>,
+    expires_in_seconds: Option<i64>,
+}
+
+#[derive(Deserialize, Default)]
+struct RotateRequest {
+    grace_seconds: Option<i64>,
+}
+
+/// Token in the caller's project, if any
+async fn find_own(db: &D1Database, principal: &Principal, id: &str) -> Result<Option<ApiToken>> {
+    Ok(tokens::get(db, id)
+        .await?
+        .filter(|token| principal.is_global() || principal.user_id.as_ref() == Some(&token.user_id)))
+}
+
+/// List tokens in the caller's project
+pub async fn list(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let url = req.url()?;
+    let user_id = match &principal.user_id {
+        Some(user_id) => Some(user_id.clone()),
+        None => query_param(&url, "user_id"),
+    };
+
+    let db = ctx.env.d1("DB")?;
+    let tokens = tokens::list(&db, user_id.as_deref()).await?;
+    json(&serde_json::json!({ "tokens": tokens }))
+}
+
+/// Create a token; the plain secret is only returned here
+pub async fn create(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let body: CreateRequest = match req.json().await {
+        Ok(body) => body,
+        Err(_) => return Response::error("Expected {\"name\": \"...\", \"role\": \"...\"}", 400),
+    };
+
+    let role = match Role::parse(&body.role) {
+        Some(role) => role,
+        None => return Response::error("Invalid role (viewer, editor or owner)", 400),
+    };
+    if role > principal.role {
+        return Response::error("Cannot create a token with a higher role than your own", 403);
+    }
+
+    let user_id = match (&principal.user_id, body.user_id) {
+        (Some(own), _) => own.clone(),
+        (None, Some(user_id)) => user_id,
+        (None, None) => return Response::error("user_id is required", 400),
+    };
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Response::error("name is required", 400);
+    }
+
+    let scopes = match body.scopes {
+        Some(values) => {
+            let mut scopes = Vec::new();
+            for value in values {
+                match Scope::parse(&value) {
+                    Some(scope) => scopes.push(scope),
+                    None => return Response::error(format!("Invalid scope: {}", value), 400),
+                }
+            }
+            Some(scopes)
+        }
+        None => principal.scopes.clone(),
+    };
+    if scopes
+        .as_ref()
+        .is_some_and(|scopes| !scopes.iter().all(|scope| principal.has_scope(*scope)))
+    {
+        return Response::error("Cannot grant scopes you do not have", 403);
+    }
+
+    let expires_at_ms = match body.expires_in_seconds {
+        Some(seconds) => match tokens::expires_at_ms(Date::now().as_millis() as i64, seconds) {
+            Some(expires_at_ms) => Some(expires_at_ms),
+            None => {
+                let message = format!("expires_in_seconds must be between 1 and {}", tokens::MAX_EXPIRES_IN_SECONDS);
+                return Response::error(message, 400);
+            }
+        },
+        None => None,
+    };
+
+    let db = ctx.env.d1("DB")?;
+    let (token, secret) = tokens::create(
+        &db,
+        NewToken {
+            user_id: &user_id,
+            name,
+            role,
+            scopes,
+            expires_at_ms,
+        },
+    )
+    .await?;
+
+    let entry = AuditEntry::from_request(&req, &principal, "token.create")
+        .target(token.id.clone())
+        .after(&token);
+    audit::record(&db, entry).await;
+
+    let mut response = json(&serde_json::json!({
+        "token": token,
+        "secret": secret,
+    }))?
+    .with_status(201);
+    response.headers_mut().set("Cache-Control", "no-store")?;
+    Ok(response)
+}
+
+/// Revoke a token in the caller's project
+pub async fn revoke(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let id = ctx.param("id").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let token = match find_own(&db, principal, &id).await? {
+        Some(token) => token,
+        None => return Response::error("Token not found", 404),
+    };
+
+    tokens::revoke(&db, &id).await?;
+
+    let entry = AuditEntry::from_request(&req, principal, "token.revoke")
+        .target(id)
+        .before(&token);
+    audit::record(&db, entry).await;
+
+    json(&serde_json::json!({ "revoked": true }))
+}
+
+/// Rotate a token's secret with a grace period for the old one
+pub async fn rotate(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let id = ctx.param("id").cloned().unwrap_or_default();
+    let body: RotateRequest = req.json().await.unwrap_or_default();
+    let grace_seconds = body
+        .grace_seconds
+        .unwrap_or(tokens::DEFAULT_GRACE_SECONDS)
+        .clamp(0, tokens::MAX_GRACE_SECONDS);
+
+    let db = ctx.env.d1("DB")?;
+    let before = match find_own(&db, &principal, &id).await? {
+        Some(token) if token.revoked_at_ms.is_none() => token,
+        _ => return Response::error("Token not found", 404),
+    };
+
+    let (token, secret) = match tokens::rotate(&db, &id, grace_seconds).await? {
+        Some(rotated) => rotated,
+        None => return Response::error("Token not found", 404),
+    };
+
+    let entry = AuditEntry::from_request(&req, &principal, "token.rotate")
+        .target(id)
+        .before(&before)
+        .after(&token);
+    audit::record(&db, entry).await;
+
+    let mut response = json(&serde_json::json!({
+        "token": token,
+        "secret": secret,
+        "grace_seconds": grace_seconds,
+    }))?;
+    response.headers_mut().set("Cache-Control", "no-store")?;
+    Ok(response)
+}
@@ -0,0 +1,92 @@
+//! Cross-webhook trace
+//! GET /api/trace/{correlation_id} returns the captures of every webhook in the
+//! caller's project, shared ones included (global callers pass `user_id`),
+//! whose `correlation_id` config extracted that value at ingest, oldest first,
+//! each with the UUID and name of the webhook it arrived at, so one request can
+//! be followed through the services that passed it on. Webhooks the caller
+//! cannot view are skipped; each is read from its owner's capture store.
+
+use crate::api::projects::project;
+use crate::api::{json, query_param};
+use crate::auth::{self, RouteData, Role};
+use crate::residency::{self, Jurisdiction};
+use crate::storage::{self, Consistency, RequestQuery, SortColumn, Storage};
+use crate::webhooks;
+use std::collections::HashMap;
+use worker::*;
+
+const DEFAULT_LIMIT: usize = 200;
+const MAX_LIMIT: usize = 1000;
+
+pub async fn show(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let url = req.url()?;
+    let Some(user_id) = project(principal, query_param(&url, "user_id")) else {
+        return Response::error("user_id is required for global callers", 400);
+    };
+    let correlation_id = ctx.param("correlation_id").cloned().unwrap_or_default();
+    let limit = query_param(&url, "limit")
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_LIMIT)
+        .clamp(1, MAX_LIMIT);
+    let db = ctx.env.d1("DB")?;
+
+    // Shared webhooks live in their owners' capture stores, which may be pinned elsewhere
+    let mut jurisdictions: HashMap<String, Option<Jurisdiction>> = HashMap::new();
+    let mut stores: Vec<(Option<Jurisdiction>, Box<dyn Storage>)> = Vec::new();
+    let mut captures = Vec::new();
+    for webhook in webhooks::in_project(&db, Some(&user_id)).await? {
+        if !auth::can_access_webhook(&db, principal, &webhook.id, Role::Viewer).await? {
+            continue;
+        }
+        let jurisdiction = match jurisdictions.get(&webhook.user_id) {
+            Some(jurisdiction) => *jurisdiction,
+            None => {
+                let jurisdiction = residency::of_project(&db, &webhook.user_id).await?;
+                jurisdictions.insert(webhook.user_id.clone(), jurisdiction);
+                jurisdiction
+            }
+        };
+        let index = match stores.iter().position(|(store, _)| *store == jurisdiction) {
+            Some(index) => index,
+            None => {
+                let consistency = Consistency::Replica { bookmark: None };
+                stores.push((jurisdiction, storage::open_in(&ctx.env, consistency, jurisdiction).await?));
+                stores.len() - 1
+            }
+        };
+        let requests = stores[index]
+            .1
+            .list_requests(&RequestQuery {
+                webhook_id: webhook.id.clone(),
+                limit: limit as u32,
+                offset: 0,
+                since: None,
+                until: None,
+                sort: SortColumn::ReceivedAt,
+                ascending: true,
+                filters: vec![("correlation_id", correlation_id.clone())],
+            })
+            .await?;
+        captures.extend(requests.into_iter().map(|request| (webhook.clone(), request)));
+    }
+    captures.sort_by_key(|(_, request)| request.received_at_ms.unwrap_or(request.received_at * 1000));
+    let total = captures.len();
+    captures.truncate(limit);
+
+    let captures: Vec<serde_json::Value> = captures
+        .into_iter()
+        .map(|(webhook, request)| {
+            serde_json::json!({
+                "webhook": { "uuid": webhook.uuid, "name": webhook.name },
+                "request": request,
+            })
+        })
+        .collect();
+    json(&serde_json::json!({
+        "correlation_id": correlation_id,
+        "captures": captures,
+        "total": total,
+        "limit": limit,
+    }))
+}
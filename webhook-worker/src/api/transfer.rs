@@ -0,0 +1,117 @@
+//! Cross-instance transfer routes (global owners only)
+//!
+//! - GET  /api/admin/webhooks/{uuid}/transfer  one export page (`data=true` for captures,
+//!   `secrets=true` to keep literal signing secrets, `cursor`, `limit`)
+//! - POST /api/admin/webhooks/{uuid}/transfer  import a page on the target deployment;
+//!   the first page creates the webhook under `{uuid}` (`user_id=` names its project)
+//!
+//! See `transfer.rs` for the page format.
+
+use crate::api::{json, query_param};
+use crate::audit::{self, AuditEntry};
+use crate::auth::{self, RouteData};
+use crate::config;
+use crate::transfer::{self, ExportOptions, Page};
+use crate::webhooks;
+use worker::*;
+
+/// Read one export page of a webhook
+pub async fn export(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+    let Some(webhook) = webhooks::find_by_uuid(&db, &uuid).await? else {
+        return Response::error("Webhook not found", 404);
+    };
+
+    let url = req.url()?;
+    let flag = |name: &str| query_param(&url, name).as_deref() == Some("true");
+    let options = ExportOptions {
+        data: flag("data"),
+        secrets: flag("secrets"),
+        cursor: query_param(&url, "cursor")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0),
+        limit: query_param(&url, "limit")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(transfer::DEFAULT_PAGE)
+            .clamp(1, transfer::MAX_PAGE),
+        host: url.host_str().map(str::to_string),
+    };
+    json(&transfer::export_page(&ctx.env, &db, &webhook, &options).await?)
+}
+
+/// Import a page into `{uuid}`, creating the webhook from the first page if needed
+pub async fn import(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+
+    if uuid::Uuid::parse_str(&uuid).is_err() {
+        return Response::error("Webhook UUID must be a UUID", 400);
+    }
+    let page: Page = match req.json().await {
+        Ok(page) => page,
+        Err(e) => return Response::error(format!("Invalid transfer page: {}", e), 400),
+    };
+    if let Some(problem) = transfer::validate(&page) {
+        return Response::error(format!("Invalid transfer page: {}", problem), 400);
+    }
+
+    let (webhook, created) = match (webhooks::find_by_uuid(&db, &uuid).await?, &page.webhook) {
+        (Some(webhook), _) => (webhook, false),
+        (None, None) => return Response::error("Webhook not found; import the first page first", 404),
+        (None, Some(spec)) => {
+            if crate::cache::find_in_d1(&db, &uuid).await?.is_some() {
+                return Response::error("Webhook UUID is already in use", 409);
+            }
+            let Some(user_id) = query_param(&req.url()?, "user_id") else {
+                return Response::error("user_id is required to create the webhook", 400);
+            };
+            if spec
+                .document
+                .config
+                .signature
+                .as_ref()
+                .is_some_and(|signature| signature.secret == config::REDACTED)
+            {
+                return Response::error("A new webhook needs its signature secret (export with secrets=true)", 400);
+            }
+            match webhooks::create(&db, &user_id, &uuid, &spec.name, &spec.tags).await? {
+                Some(webhook) => (webhook, true),
+                None => return Response::error("Webhook UUID is already in use", 409),
+            }
+        }
+    };
+
+    let mut environments = None;
+    if let Some(spec) = &page.webhook {
+        if !created && (webhook.name != spec.name || webhook.tag_list() != spec.tags) {
+            webhooks::update(&db, &webhook.id, &spec.name, &spec.tags).await?;
+        }
+        let outcome = spec.document.apply(&kv, &db, &webhook.id, &principal.actor, None, true).await?;
+        environments = Some(serde_json::json!({
+            "created": outcome.created,
+            "updated": outcome.updated,
+            "deleted": outcome.deleted,
+        }));
+    }
+    let outcome = transfer::import_captures(&ctx.env, &db, &webhook.id, &page).await?;
+
+    let entry = AuditEntry::from_request(&req, &principal, "webhook.transfer_import")
+        .target(uuid.clone())
+        .after(&serde_json::json!({
+            "created": created,
+            "config": page.webhook.is_some(),
+            "imported": outcome.imported,
+            "skipped": outcome.skipped,
+        }));
+    audit::record(&db, entry).await;
+
+    json(&serde_json::json!({
+        "webhook_id": uuid,
+        "created": created,
+        "environments": environments,
+        "captures": outcome,
+    }))
+}
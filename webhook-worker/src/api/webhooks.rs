@@ -0,0 +1,924 @@
+//! Webhook configuration routes
+//!
+//! - POST  /api/webhooks                    create a webhook, optionally from a template (`template=stripe`)
+//! - GET   /api/templates                   built-in provider templates and signature providers
+//! - GET   /api/webhooks/{uuid}             webhook with its config and environments (`ETag`)
+//! - PUT   /api/webhooks/{uuid}             idempotent full upsert (`If-Match`, `If-None-Match: *`)
+//! - GET   /api/webhooks/{uuid}/config      current config and version (`ETag`)
+//! - PATCH /api/webhooks/{uuid}/config      merge fields into the config (`If-Match` optional)
+//! - GET   /api/webhooks/{uuid}/config/export  declarative document (`format=json|yaml`)
+//! - POST  /api/webhooks/{uuid}/config/import  apply a JSON or YAML document (`prune=true`, `If-Match`)
+//! - PATCH /api/webhooks/{uuid}/config?dry_run=true  validate a patch and show its diff without saving it
+//! - POST  /api/webhooks/{uuid}/simulate    run a sample delivery through the pipeline, optionally with a
+//!   config patch: `{"request": {"method"?, "headers"?, "body"?, "query"?, "environment"?}, "config"?}`
+//! - GET   /api/webhooks/{uuid}/config/history  kept config versions with who/when and the changed paths
+//! - GET   /api/webhooks/{uuid}/config/history/{version}  one version's config and diff against the one before
+//! - POST  /api/webhooks/{uuid}/config/rollback/{version}  save a kept version as the current config (`If-Match`)
+//! - POST  /api/webhooks/{uuid}/signed-url  mint a time-limited capture URL: `{"ttl_seconds": 3600}`
+//! - GET   /api/webhooks/{uuid}/volume      hourly volume baseline and current anomaly (`anomaly` config)
+//! - POST  /api/webhooks/{uuid}/upload-url  mint a time-limited PUT URL for one file: `{"filename": "orders.csv"}`
+//! - POST  /api/webhooks/{uuid}/signature/rotate  new signing secret: `{"secret", "grace_seconds"?}`;
+//!   the previous one keeps verifying for `grace_seconds` (default 86400)
+//! - POST  /api/webhooks/{uuid}/latency-profile  simulate answer latency from forward timings:
+//!   `{"target"?, "days"?}`, or explicit `{"buckets": [{"le_ms", "count"}]}`
+
+use crate::anomaly;
+use crate::api::{authorized_webhook, json, query_param};
+use crate::audit::{self, AuditEntry};
+use crate::auth::{self, RouteData, Role};
+use crate::config::{self, WebhookConfig};
+use crate::config_document::ConfigDocument;
+use crate::config_history;
+use crate::latency;
+use crate::pipeline;
+use crate::replay;
+use crate::signature;
+use crate::simulate::{self, Sample};
+use crate::templates;
+use crate::webhooks::{self, Webhook};
+use crate::signed_url;
+use serde::Deserialize;
+use serde_json::Value;
+use worker::*;
+
+const DEFAULT_SIGNED_URL_TTL_SECONDS: i64 = 3600;
+const MAX_SIGNED_URL_TTL_SECONDS: i64 = 30 * 86_400;
+
+/// Days of forward timings a latency profile is imported from unless `days` is given
+const DEFAULT_PROFILE_DAYS: u32 = 7;
+
+#[derive(Deserialize, Default)]
+struct SignedUrlRequest {
+    ttl_seconds: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct RotateSecretRequest {
+    /// New signing secret, literal or `env:NAME`
+    secret: String,
+    grace_seconds: Option<i64>,
+}
+
+#[derive(Deserialize, Default)]
+struct LatencyProfileRequest {
+    /// Forward target whose timings to import (default: all targets of the webhook)
+    target: Option<String>,
+    days: Option<u32>,
+    /// Explicit distribution instead of measured timings
+    buckets: Option<Vec<config::LatencyBucket>>,
+}
+
+#[derive(Deserialize)]
+struct UploadUrlRequest {
+    filename: String,
+    ttl_seconds: Option<i64>,
+}
+
+/// Full webhook definition for `PUT /api/webhooks/{uuid}`
+#[derive(Deserialize)]
+struct UpsertRequest {
+    name: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Project to create the webhook in (global callers only)
+    user_id: Option<String>,
+    #[serde(flatten)]
+    document: ConfigDocument,
+}
+
+/// Body for `POST /api/webhooks`; everything is optional
+#[derive(Deserialize, Default)]
+struct CreateRequest {
+    name: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Project to create the webhook in (global callers only)
+    user_id: Option<String>,
+    /// Signature secret (literal or `env:NAME`) replacing the template default
+    secret: Option<String>,
+}
+
+/// Config version from an `If-Match` header (`"3"` or `W/"3"`)
+pub fn if_match_version(req: &Request) -> Result<Option<i64>> {
+    Ok(req.headers().get("If-Match")?.and_then(|value| {
+        value
+            .trim()
+            .trim_start_matches("W/")
+            .trim_matches('"')
+            .parse()
+            .ok()
+    }))
+}
+
+/// Response carrying a config version as its `ETag`
+fn with_etag(mut response: Response, version: i64) -> Result<Response> {
+    response.headers_mut().set("ETag", &format!("\"{}\"", version))?;
+    Ok(response)
+}
+
+/// Show a webhook's config
+pub async fn config_show(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let settings = config::load_from_d1(&db, &webhook_id).await?;
+    let response = json(&serde_json::json!({
+        "webhook_id": uuid,
+        "config": settings.config.redacted(),
+        "version": settings.version,
+        "has_secret": settings.secret.is_some(),
+    }))?;
+    with_etag(response, settings.version)
+}
+
+/// Merge fields into a webhook's config
+pub async fn config_update(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let patch: Value = match req.json().await {
+        Ok(Value::Object(patch)) => Value::Object(patch),
+        _ => return Response::error("Expected a JSON object", 400),
+    };
+
+    let current = config::load_from_d1(&db, &webhook_id).await?;
+    let updated = match patched(&current.config, patch) {
+        Ok(config) => config,
+        Err(problem) => return Response::error(problem, 400),
+    };
+    // A dry run shows what the patch would change and stops there
+    if query_param(&req.url()?, "dry_run").is_some_and(|value| value == "true") {
+        return json(&serde_json::json!({
+            "webhook_id": uuid,
+            "config": updated.redacted(),
+            "version": current.version,
+            "dry_run": true,
+            "diff": config_history::config_changes(&current.config, &updated),
+        }));
+    }
+
+    let expected = if_match_version(&req)?.or(Some(current.version));
+    let version = match config::save(&kv, &db, &webhook_id, &updated, &principal.actor, expected).await? {
+        Some(version) => version,
+        None => return Response::error("Config was modified concurrently", 412),
+    };
+
+    let entry = AuditEntry::from_request(&req, &principal, "webhook.config_update")
+        .target(uuid.clone())
+        .before(&current.config.redacted())
+        .after(&updated.redacted());
+    audit::record(&db, entry).await;
+
+    let response = json(&serde_json::json!({
+        "webhook_id": uuid,
+        "config": updated.redacted(),
+        "version": version,
+    }))?;
+    with_etag(response, version)
+}
+
+/// A config with a merge patch applied, validated
+fn patched(current: &WebhookConfig, patch: Value) -> std::result::Result<WebhookConfig, String> {
+    let mut merged = serde_json::to_value(current).map_err(|e| e.to_string())?;
+    merge(&mut merged, patch);
+    let updated: WebhookConfig = serde_json::from_value(merged).map_err(|e| format!("Invalid config: {}", e))?;
+    match updated.validate() {
+        Some(problem) => Err(problem),
+        None => Ok(updated),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SimulateRequest {
+    request: Sample,
+    /// Merge patch tried on top of the stored config
+    #[serde(default)]
+    config: Option<Value>,
+}
+
+/// Run a sample delivery through the webhook's pipeline, optionally with a config patch, storing nothing
+pub async fn simulate(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let body: SimulateRequest = match req.json().await {
+        Ok(body) => body,
+        Err(e) => return Response::error(format!("Invalid simulation: {}", e), 400),
+    };
+    let mut settings = config::load_from_d1(&db, &webhook_id).await?;
+    let diff = match body.config {
+        Some(Value::Object(patch)) => match patched(&settings.config, Value::Object(patch)) {
+            Ok(config) => {
+                let diff = config_history::config_changes(&settings.config, &config);
+                settings.config = config;
+                diff
+            }
+            Err(problem) => return Response::error(problem, 400),
+        },
+        Some(_) => return Response::error("config must be a JSON object", 400),
+        None => Vec::new(),
+    };
+
+    let capture_url = replay::capture_url(&req.url()?, &uuid);
+    let now_ms = Date::now().as_millis() as i64;
+    let outcome = match simulate::run(&body.request, &capture_url, &webhook_id, &settings, now_ms) {
+        Ok(outcome) => outcome,
+        Err(problem) => return Response::error(problem, 400),
+    };
+    json(&serde_json::json!({
+        "webhook_id": uuid,
+        "version": settings.version,
+        "diff": diff,
+        "outcome": outcome,
+    }))
+}
+
+/// List a webhook's kept config versions, newest first, with the paths each changed
+pub async fn config_history(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let snapshots = config_history::list(&db, &webhook_id).await?;
+    let versions: Vec<Value> = snapshots
+        .iter()
+        .enumerate()
+        .map(|(index, snapshot)| {
+            let changed: Vec<String> = snapshot
+                .changes(snapshots.get(index + 1))
+                .into_iter()
+                .map(|change| change.path)
+                .collect();
+            serde_json::json!({
+                "version": snapshot.version,
+                "actor": snapshot.actor,
+                "created_at_ms": snapshot.created_at_ms,
+                "changed": changed,
+            })
+        })
+        .collect();
+    json(&serde_json::json!({ "webhook_id": uuid, "versions": versions }))
+}
+
+/// One kept config version and its diff against the version before
+pub async fn config_version(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let requested = ctx.param("version").and_then(|version| version.parse::<i64>().ok());
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let snapshots = config_history::list(&db, &webhook_id).await?;
+    let Some(index) = snapshots.iter().position(|snapshot| Some(snapshot.version) == requested) else {
+        return Response::error("Config version not found", 404);
+    };
+    let snapshot = &snapshots[index];
+    json(&serde_json::json!({
+        "webhook_id": uuid,
+        "version": snapshot.version,
+        "actor": snapshot.actor,
+        "created_at_ms": snapshot.created_at_ms,
+        "config": snapshot.config.redacted(),
+        "previous_version": snapshots.get(index + 1).map(|previous| previous.version),
+        "diff": snapshot.changes(snapshots.get(index + 1)),
+    }))
+}
+
+/// Save a kept config version as the current config
+pub async fn config_rollback(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let requested = ctx.param("version").and_then(|version| version.parse::<i64>().ok());
+    let db = ctx.env.d1("DB")?;
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let snapshots = config_history::list(&db, &webhook_id).await?;
+    let Some(snapshot) = snapshots.into_iter().find(|snapshot| Some(snapshot.version) == requested) else {
+        return Response::error("Config version not found", 404);
+    };
+    // Rules may have tightened since the version was written
+    if let Some(problem) = snapshot.config.validate() {
+        return Response::error(format!("Config version {} is no longer valid: {}", snapshot.version, problem), 409);
+    }
+
+    let current = config::load_from_d1(&db, &webhook_id).await?;
+    let expected = if_match_version(&req)?.or(Some(current.version));
+    let version = match config::save(&kv, &db, &webhook_id, &snapshot.config, &principal.actor, expected).await? {
+        Some(version) => version,
+        None => return Response::error("Config was modified concurrently", 412),
+    };
+
+    let entry = AuditEntry::from_request(&req, &principal, "webhook.config_rollback")
+        .target(uuid.clone())
+        .before(&current.config.redacted())
+        .after(&serde_json::json!({ "rolled_back_to": snapshot.version, "config": snapshot.config.redacted() }));
+    audit::record(&db, entry).await;
+
+    let response = json(&serde_json::json!({
+        "webhook_id": uuid,
+        "config": snapshot.config.redacted(),
+        "version": version,
+        "rolled_back_to": snapshot.version,
+    }))?;
+    with_etag(response, version)
+}
+
+/// Webhook as returned by the API, with its config version
+async fn webhook_body(req: &Request, db: &D1Database, webhook: &Webhook) -> Result<(Value, i64)> {
+    let settings = config::load_from_d1(db, &webhook.id).await?;
+    let environments = settings
+        .environments
+        .iter()
+        .map(|environment| crate::api::environments::with_capture_url(req, environment))
+        .collect::<Result<Vec<_>>>()?;
+    let body = serde_json::json!({
+        "uuid": webhook.uuid,
+        "user_id": webhook.user_id,
+        "name": webhook.name,
+        "tags": webhook.tag_list(),
+        "created_at": webhook.created_at,
+        "version": settings.version,
+        "config": settings.config.redacted(),
+        "environments": environments,
+    });
+    Ok((body, settings.version))
+}
+
+/// List the built-in templates
+pub async fn templates(_req: Request, _ctx: RouteContext<RouteData>) -> Result<Response> {
+    json(&serde_json::json!({
+        "templates": templates::all(),
+        "signature_providers": signature::providers(),
+    }))
+}
+
+/// Create a webhook with a fresh UUID, pre-configured from `template` if given
+pub async fn create(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let db = ctx.env.d1("DB")?;
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+
+    let template = match query_param(&req.url()?, "template") {
+        Some(id) => match templates::find(&id) {
+            Some(template) => Some(template),
+            None => {
+                let known = templates::TEMPLATE_IDS.join(", ");
+                return Response::error(format!("Unknown template (available: {})", known), 400);
+            }
+        },
+        None => None,
+    };
+    let body: CreateRequest = req.json().await.unwrap_or_default();
+    let user_id = match (&principal.user_id, &body.user_id) {
+        (Some(own), _) => own.clone(),
+        (None, Some(user_id)) => user_id.clone(),
+        (None, None) => return Response::error("user_id is required", 400),
+    };
+
+    let mut webhook_config = template
+        .as_ref()
+        .map(|template| template.config.clone())
+        .unwrap_or_default();
+    if let (Some(signature), Some(secret)) = (&mut webhook_config.signature, body.secret) {
+        signature.secret = secret;
+    }
+    let name = body
+        .name
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .or_else(|| template.as_ref().map(|template| format!("{} webhook", template.id)))
+        .unwrap_or_else(|| "New webhook".to_string());
+
+    let uuid = uuid::Uuid::new_v4().to_string();
+    let webhook = match webhooks::create(&db, &user_id, &uuid, &name, &body.tags).await? {
+        Some(webhook) => webhook,
+        None => return Response::error("Failed to create webhook", 500),
+    };
+    if webhook_config != WebhookConfig::default() {
+        config::save(&kv, &db, &webhook.id, &webhook_config, &principal.actor, None).await?;
+    }
+
+    let (after, version) = webhook_body(&req, &db, &webhook).await?;
+    let entry = AuditEntry::from_request(&req, &principal, "webhook.create")
+        .target(uuid)
+        .after(&after);
+    audit::record(&db, entry).await;
+
+    with_etag(json(&after)?.with_status(201), version)
+}
+
+/// Show a webhook with its config and environments
+pub async fn show(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook = match webhooks::find_by_uuid(&db, &uuid).await? {
+        Some(webhook) if auth::can_access_webhook(&db, principal, &webhook.id, Role::Viewer).await? => webhook,
+        _ => return Response::error("Webhook not found", 404),
+    };
+
+    let (body, version) = webhook_body(&req, &db, &webhook).await?;
+    with_etag(json(&body)?, version)
+}
+
+/// Create or fully replace a webhook. Repeating the same request changes nothing;
+/// environments missing from the body are deleted.
+pub async fn upsert(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+
+    if uuid::Uuid::parse_str(&uuid).is_err() {
+        return Response::error("Webhook UUID must be a UUID", 400);
+    }
+    let body: UpsertRequest = match req.json().await {
+        Ok(body) => body,
+        Err(e) => return Response::error(format!("Invalid webhook definition: {}", e), 400),
+    };
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Response::error("name is required", 400);
+    }
+    if let Some(problem) = body.document.validate() {
+        return Response::error(format!("Invalid webhook definition: {}", problem), 400);
+    }
+
+    let if_match = req.headers().get("If-Match")?;
+    let create_only = req.headers().get("If-None-Match")?.is_some_and(|value| value.trim() == "*");
+
+    let (webhook, before, created) = match webhooks::find_by_uuid(&db, &uuid).await? {
+        Some(webhook) => {
+            match auth::webhook_access(auth::webhook_role(&db, &principal, &webhook.id).await?, Role::Editor) {
+                Ok(()) => {}
+                Err(404) => return Response::error("Webhook UUID is already in use", 409),
+                Err(status) => return Response::error("Forbidden", status),
+            }
+            if create_only {
+                return Response::error("Webhook already exists", 412);
+            }
+            let before = webhook_body(&req, &db, &webhook).await?.0;
+            (webhook, Some(before), false)
+        }
+        None => {
+            // Environment UUIDs can't be claimed for a new webhook
+            if crate::cache::find_in_d1(&db, &uuid).await?.is_some() {
+                return Response::error("Webhook UUID is already in use", 409);
+            }
+            if if_match.is_some() {
+                return Response::error("Webhook does not exist", 412);
+            }
+            let user_id = match (&principal.user_id, &body.user_id) {
+                (Some(own), _) => own.clone(),
+                (None, Some(user_id)) => user_id.clone(),
+                (None, None) => return Response::error("user_id is required", 400),
+            };
+            if body
+                .document
+                .config
+                .signature
+                .as_ref()
+                .is_some_and(|signature| signature.secret == config::REDACTED)
+            {
+                return Response::error("A new webhook needs its signature secret (or an env: reference)", 400);
+            }
+            match webhooks::create(&db, &user_id, &uuid, name, &body.tags).await? {
+                Some(webhook) => (webhook, None, true),
+                None => return Response::error("Webhook UUID is already in use", 409),
+            }
+        }
+    };
+
+    if !created && (webhook.name != name || webhook.tag_list() != body.tags) {
+        webhooks::update(&db, &webhook.id, name, &body.tags).await?;
+    }
+    let outcome = body
+        .document
+        .apply(&kv, &db, &webhook.id, &principal.actor, if_match_version(&req)?, true)
+        .await?;
+    if outcome.version.is_none() {
+        return Response::error("Config was modified concurrently", 412);
+    }
+
+    let webhook = webhooks::find_by_uuid(&db, &uuid).await?.unwrap_or(webhook);
+    let (after, version) = webhook_body(&req, &db, &webhook).await?;
+    if before.as_ref() != Some(&after) {
+        let action = if created { "webhook.create" } else { "webhook.update" };
+        let mut entry = AuditEntry::from_request(&req, &principal, action)
+            .target(uuid.clone())
+            .after(&after);
+        if let Some(before) = &before {
+            entry = entry.before(before);
+        }
+        audit::record(&db, entry).await;
+    }
+
+    let response = json(&after)?.with_status(if created { 201 } else { 200 });
+    with_etag(response, version)
+}
+
+/// Whether the caller asked for YAML (`format=yaml` or an `Accept` header naming yaml)
+fn wants_yaml(req: &Request) -> Result<bool> {
+    if let Some(format) = query_param(&req.url()?, "format") {
+        return Ok(format == "yaml");
+    }
+    Ok(req
+        .headers()
+        .get("Accept")?
+        .is_some_and(|accept| accept.contains("yaml")))
+}
+
+/// Render a config document as JSON or YAML
+fn document_response(document: &ConfigDocument, yaml: bool) -> Result<Response> {
+    if !yaml {
+        return json(document);
+    }
+    let Some(body) = to_yaml(document)? else {
+        return Response::error("This build does not support YAML documents (`yaml` feature)", 406);
+    };
+    let mut response = Response::ok(body)?;
+    response.headers_mut().set("Content-Type", "application/yaml")?;
+    crate::set_cors_headers(response.headers_mut())?;
+    Ok(response)
+}
+
+/// Parse a request body as a config document (YAML when the `Content-Type` says so)
+async fn read_document(req: &mut Request) -> Result<std::result::Result<ConfigDocument, String>> {
+    let yaml = req
+        .headers()
+        .get("Content-Type")?
+        .is_some_and(|content_type| content_type.contains("yaml"));
+    let body = req.text().await?;
+    let parsed = if yaml {
+        from_yaml(&body)
+    } else {
+        serde_json::from_str(&body).map_err(|e| e.to_string())
+    };
+    Ok(parsed.and_then(|document: ConfigDocument| match document.validate() {
+        Some(problem) => Err(problem),
+        None => Ok(document),
+    }))
+}
+
+/// YAML rendering of a document; None when the worker is built without the `yaml` feature
+#[cfg(feature = "yaml")]
+fn to_yaml(document: &ConfigDocument) -> Result<Option<String>> {
+    serde_yaml::to_string(document)
+        .map(Some)
+        .map_err(|e| Error::RustError(e.to_string()))
+}
+
+#[cfg(not(feature = "yaml"))]
+fn to_yaml(_document: &ConfigDocument) -> Result<Option<String>> {
+    Ok(None)
+}
+
+#[cfg(feature = "yaml")]
+fn from_yaml(body: &str) -> std::result::Result<ConfigDocument, String> {
+    serde_yaml::from_str(body).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "yaml"))]
+fn from_yaml(_body: &str) -> std::result::Result<ConfigDocument, String> {
+    Err("This build does not support YAML documents (`yaml` feature)".to_string())
+}
+
+/// Export a webhook's config and environments as a declarative document
+pub async fn config_export(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let version = config::load_from_d1(&db, &webhook_id).await?.version;
+    let document = ConfigDocument::export(&db, &webhook_id).await?;
+    with_etag(document_response(&document, wants_yaml(&req)?)?, version)
+}
+
+/// Apply a declarative document to a webhook
+pub async fn config_import(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let document = match read_document(&mut req).await? {
+        Ok(document) => document,
+        Err(problem) => return Response::error(format!("Invalid config document: {}", problem), 400),
+    };
+    let prune = query_param(&req.url()?, "prune").is_some_and(|value| value == "true");
+
+    let before = ConfigDocument::export(&db, &webhook_id).await?;
+    let outcome = document
+        .apply(&kv, &db, &webhook_id, &principal.actor, if_match_version(&req)?, prune)
+        .await?;
+    let Some(version) = outcome.version else {
+        return Response::error("Config was modified concurrently", 412);
+    };
+
+    let entry = AuditEntry::from_request(&req, &principal, "webhook.config_import")
+        .target(uuid.clone())
+        .before(&before)
+        .after(&ConfigDocument::export(&db, &webhook_id).await?);
+    audit::record(&db, entry).await;
+
+    let response = json(&serde_json::json!({
+        "webhook_id": uuid,
+        "version": version,
+        "environments": {
+            "created": outcome.created,
+            "updated": outcome.updated,
+            "deleted": outcome.deleted,
+        },
+    }))?;
+    with_etag(response, version)
+}
+
+/// Mint a signed capture URL that stops working after `ttl_seconds`
+pub async fn signed_url(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let body: SignedUrlRequest = req.json().await.unwrap_or_default();
+    let ttl_seconds = body
+        .ttl_seconds
+        .unwrap_or(DEFAULT_SIGNED_URL_TTL_SECONDS)
+        .clamp(1, MAX_SIGNED_URL_TTL_SECONDS);
+
+    let secret = config::ensure_secret(&kv, &db, &webhook_id).await?;
+    let expires_at = (Date::now().as_millis() / 1000) as i64 + ttl_seconds;
+    let signature = signed_url::sign(&secret, &uuid, expires_at);
+
+    let mut url = req.url()?;
+    url.set_path(&format!("/w/{}", uuid));
+    url.set_query(None);
+    url.query_pairs_mut()
+        .append_pair(signed_url::EXP_PARAM, &expires_at.to_string())
+        .append_pair(signed_url::SIG_PARAM, &signature);
+
+    let entry = AuditEntry::from_request(&req, &principal, "webhook.signed_url")
+        .target(uuid)
+        .after(&serde_json::json!({ "expires_at": expires_at }));
+    audit::record(&db, entry).await;
+
+    json(&serde_json::json!({
+        "url": url.to_string(),
+        "expires_at": expires_at,
+    }))
+}
+
+/// Rotate the signature secret, accepting the previous one for a grace period
+pub async fn rotate_secret(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let body: RotateSecretRequest = match req.json().await {
+        Ok(body) => body,
+        Err(_) => return Response::error("Expected {\"secret\": \"...\"}", 400),
+    };
+    if body.secret.is_empty() || body.secret == config::REDACTED {
+        return Response::error("secret must be a new signing secret or an env: reference", 400);
+    }
+    let grace_seconds = body
+        .grace_seconds
+        .unwrap_or(config::DEFAULT_ROTATION_GRACE_SECONDS)
+        .clamp(0, config::MAX_ROTATION_GRACE_SECONDS);
+
+    let current = config::load_from_d1(&db, &webhook_id).await?;
+    let mut updated = current.config.clone();
+    let Some(signature) = &mut updated.signature else {
+        return Response::error("Webhook does not verify signatures", 400);
+    };
+    signature.rotate(body.secret, grace_seconds, Date::now().as_millis() as i64);
+    let previous_expires_at_ms = signature.previous_expires_at_ms;
+
+    let version = match config::save(&kv, &db, &webhook_id, &updated, &principal.actor, Some(current.version)).await? {
+        Some(version) => version,
+        None => return Response::error("Config was modified concurrently", 412),
+    };
+
+    let entry = AuditEntry::from_request(&req, &principal, "webhook.secret_rotate")
+        .target(uuid.clone())
+        .before(&current.config.redacted())
+        .after(&updated.redacted());
+    audit::record(&db, entry).await;
+
+    let response = json(&serde_json::json!({
+        "webhook_id": uuid,
+        "config": updated.redacted(),
+        "version": version,
+        "grace_seconds": grace_seconds,
+        "previous_expires_at_ms": previous_expires_at_ms,
+    }))?;
+    with_etag(response, version)
+}
+
+/// Set the latency profile answers are delayed by, from measured forward timings or given buckets
+pub async fn latency_profile(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let body: LatencyProfileRequest = match req.text().await {
+        Ok(text) if text.trim().is_empty() => LatencyProfileRequest::default(),
+        Ok(text) => match serde_json::from_str(&text) {
+            Ok(body) => body,
+            Err(_) => return Response::error("Expected {\"target\"?, \"days\"?} or {\"buckets\": [...]}", 400),
+        },
+        Err(_) => return Response::error("Expected a JSON body", 400),
+    };
+    let profile = match body.buckets {
+        Some(buckets) => config::LatencyProfile {
+            buckets,
+            source: Some("import".to_string()),
+        },
+        None => {
+            let days = body.days.unwrap_or(DEFAULT_PROFILE_DAYS).clamp(1, latency::RETENTION_DAYS);
+            let now = (Date::now().as_millis() / 1000) as i64;
+            let histograms = latency::histograms(&db, &webhook_id, days, now).await?;
+            let mut measured = latency::Histogram::default();
+            for (target, histogram) in &histograms {
+                if body.target.as_ref().is_none_or(|wanted| wanted == target) {
+                    measured.merge(histogram);
+                }
+            }
+            let source = format!("forwarding:{}", body.target.as_deref().unwrap_or("*"));
+            match measured.profile(&source) {
+                Some(profile) => profile,
+                None => return Response::error("No forward timings to import", 404),
+            }
+        }
+    };
+    if let Some(problem) = profile.validate() {
+        return Response::error(format!("Invalid latency_profile: {}", problem), 400);
+    }
+
+    let current = config::load_from_d1(&db, &webhook_id).await?;
+    let mut updated = current.config.clone();
+    updated.latency_profile = Some(profile);
+    let version = match config::save(&kv, &db, &webhook_id, &updated, &principal.actor, Some(current.version)).await? {
+        Some(version) => version,
+        None => return Response::error("Config was modified concurrently", 412),
+    };
+
+    let entry = AuditEntry::from_request(&req, &principal, "webhook.latency_profile")
+        .target(uuid.clone())
+        .before(&current.config.redacted())
+        .after(&updated.redacted());
+    audit::record(&db, entry).await;
+
+    let response = json(&serde_json::json!({
+        "webhook_id": uuid,
+        "latency_profile": updated.latency_profile,
+        "version": version,
+    }))?;
+    with_etag(response, version)
+}
+
+/// Mint a signed URL that accepts one file PUT (stored in R2 and captured) until `ttl_seconds`
+pub async fn upload_url(mut req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?.clone();
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+    let kv = ctx.env.kv("WEBHOOK_CACHE")?;
+
+    let webhook_id = match authorized_webhook(&db, &principal, &uuid, Role::Editor).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    let body: UploadUrlRequest = match req.json().await {
+        Ok(body) => body,
+        Err(_) => return Response::error("Expected {\"filename\": ...}", 400),
+    };
+    if !pipeline::valid_upload_filename(&body.filename) {
+        return Response::error("Invalid file name (letters, digits, '.', '_' and '-')", 400);
+    }
+    let ttl_seconds = body
+        .ttl_seconds
+        .unwrap_or(DEFAULT_SIGNED_URL_TTL_SECONDS)
+        .clamp(1, MAX_SIGNED_URL_TTL_SECONDS);
+
+    let secret = config::ensure_secret(&kv, &db, &webhook_id).await?;
+    let expires_at = (Date::now().as_millis() / 1000) as i64 + ttl_seconds;
+    let signature = signed_url::sign_upload(&secret, &uuid, &body.filename, expires_at);
+
+    let mut url = req.url()?;
+    url.set_path(&format!("/w/{}/upload/{}", uuid, body.filename));
+    url.set_query(None);
+    url.query_pairs_mut()
+        .append_pair(signed_url::EXP_PARAM, &expires_at.to_string())
+        .append_pair(signed_url::SIG_PARAM, &signature);
+
+    let entry = AuditEntry::from_request(&req, &principal, "webhook.upload_url")
+        .target(uuid)
+        .after(&serde_json::json!({ "filename": body.filename, "expires_at": expires_at }));
+    audit::record(&db, entry).await;
+
+    json(&serde_json::json!({
+        "url": url.to_string(),
+        "method": "PUT",
+        "expires_at": expires_at,
+    }))
+}
+
+/// Volume baseline of a webhook with anomaly detection (null before the first completed hour)
+pub async fn volume(_req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let principal = auth::principal(&ctx)?;
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let db = ctx.env.d1("DB")?;
+
+    let webhook_id = match authorized_webhook(&db, principal, &uuid, Role::Viewer).await? {
+        Ok(id) => id,
+        Err(denied) => return Ok(denied),
+    };
+
+    json(&serde_json::json!({
+        "webhook_id": uuid,
+        "volume": anomaly::status(&db, &webhook_id).await?,
+    }))
+}
+
+/// RFC 7386 JSON merge patch
+pub fn merge(target: &mut Value, patch: Value) {
+    match patch {
+        Value::Object(fields) => {
+            if !target.is_object() {
+                *target = Value::Object(Default::default());
+            }
+            let object = target.as_object_mut().expect("target is an object");
+            for (key, value) in fields {
+                if value.is_null() {
+                    object.remove(&key);
+                } else {
+                    merge(object.entry(key).or_insert(Value::Null), value);
+                }
+            }
+        }
+        patch => *target = patch,
+    }
+}
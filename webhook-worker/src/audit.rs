@@ -0,0 +1,193 @@
+//! Audit log of management actions
+//! Every mutating management API call records who did what (actor, IP, action,
+//! target) with before/after snapshots in the `audit_log` D1 table.
+
+use crate::auth::Principal;
+use crate::ids;
+use crate::storage::optional_str;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Actor recorded for actions taken by the worker itself (cron, auto-migrate)
+pub const SYSTEM_ACTOR: &str = "system";
+
+/// A single audit record, built up by the handler and written once the action succeeds
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub action: &'static str,
+    pub actor: String,
+    pub ip: Option<String>,
+    pub target: Option<String>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+impl AuditEntry {
+    /// Start an entry for an API request made by `principal`
+    pub fn from_request(req: &Request, principal: &Principal, action: &'static str) -> Self {
+        Self {
+            action,
+            actor: principal.actor.clone(),
+            ip: req.headers().get("CF-Connecting-IP").ok().flatten(),
+            target: None,
+            before: None,
+            after: None,
+        }
+    }
+
+    /// Start an entry for an action taken by the worker itself
+    pub fn system(action: &'static str) -> Self {
+        Self {
+            action,
+            actor: SYSTEM_ACTOR.to_string(),
+            ip: None,
+            target: None,
+            before: None,
+            after: None,
+        }
+    }
+
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    pub fn before<T: Serialize>(mut self, snapshot: &T) -> Self {
+        self.before = serde_json::to_value(snapshot).ok();
+        self
+    }
+
+    pub fn after<T: Serialize>(mut self, snapshot: &T) -> Self {
+        self.after = serde_json::to_value(snapshot).ok();
+        self
+    }
+}
+
+/// Stored audit record as returned by `/api/audit`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AuditRecord {
+    pub id: String,
+    pub created_at_ms: i64,
+    pub actor: String,
+    pub ip: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    #[serde(with = "json_text")]
+    pub before: Option<Value>,
+    #[serde(with = "json_text")]
+    pub after: Option<Value>,
+}
+
+/// Filters for listing audit records
+#[derive(Debug, Default)]
+pub struct AuditQuery {
+    pub action: Option<String>,
+    pub actor: Option<String>,
+    pub target: Option<String>,
+    /// Unix milliseconds (inclusive), see `second_bounds`
+    pub since_ms: Option<i64>,
+    /// Unix milliseconds (inclusive)
+    pub until_ms: Option<i64>,
+    pub limit: u32,
+    pub offset: u32,
+}
+
+/// First and last millisecond of Unix second `seconds`; None when they don't fit an i64
+pub fn second_bounds(seconds: i64) -> Option<(i64, i64)> {
+    let start = seconds.checked_mul(1000)?;
+    Some((start, start.checked_add(999)?))
+}
+
+/// Write an audit entry. Failures are logged rather than failing the action
+/// that already completed.
+pub async fn record(db: &D1Database, entry: AuditEntry) {
+    if let Err(e) = insert(db, &entry).await {
+        log_error!("❌ Failed to write audit log ({}): {:?}", entry.action, e);
+    }
+}
+
+async fn insert(db: &D1Database, entry: &AuditEntry) -> Result<()> {
+    let now_ms = Date::now().as_millis() as i64;
+    let before = entry.before.as_ref().map(Value::to_string);
+    let after = entry.after.as_ref().map(Value::to_string);
+
+    db.prepare(
+        "INSERT INTO audit_log (id, created_at_ms, actor, ip, action, target, before, after) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    )
+    .bind(&[
+        JsValue::from_str(&ids::ulid(now_ms)),
+        JsValue::from_f64(now_ms as f64),
+        JsValue::from_str(&entry.actor),
+        optional_str(&entry.ip),
+        JsValue::from_str(entry.action),
+        optional_str(&entry.target),
+        optional_str(&before),
+        optional_str(&after),
+    ])?
+    .run()
+    .await?;
+
+    Ok(())
+}
+
+/// List audit records, newest first
+pub async fn list(db: &D1Database, query: &AuditQuery) -> Result<Vec<AuditRecord>> {
+    let mut conditions = Vec::new();
+    let mut params = Vec::new();
+
+    let text_filters = [
+        ("action", &query.action),
+        ("actor", &query.actor),
+        ("target", &query.target),
+    ];
+    for (column, value) in text_filters {
+        if let Some(value) = value {
+            params.push(JsValue::from_str(value));
+            conditions.push(format!("{} = ?{}", column, params.len()));
+        }
+    }
+    if let Some(since_ms) = query.since_ms {
+        params.push(JsValue::from_f64(since_ms as f64));
+        conditions.push(format!("created_at_ms >= ?{}", params.len()));
+    }
+    if let Some(until_ms) = query.until_ms {
+        params.push(JsValue::from_f64(until_ms as f64));
+        conditions.push(format!("created_at_ms <= ?{}", params.len()));
+    }
+
+    let where_clause = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", conditions.join(" AND "))
+    };
+
+    params.push(JsValue::from_f64(query.limit as f64));
+    params.push(JsValue::from_f64(query.offset as f64));
+    let sql = format!(
+        "SELECT id, created_at_ms, actor, ip, action, target, before, after FROM audit_log {} \
+         ORDER BY created_at_ms DESC, id DESC LIMIT ?{} OFFSET ?{}",
+        where_clause,
+        params.len() - 1,
+        params.len()
+    );
+
+    db.prepare(&sql).bind(&params)?.all().await?.results::<AuditRecord>()
+}
+
+/// Snapshots are stored as JSON text and returned as JSON values
+mod json_text {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde_json::Value;
+
+    pub fn serialize<S: Serializer>(value: &Option<Value>, serializer: S) -> Result<S::Ok, S::Error> {
+        value.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+        let text = Option::<String>::deserialize(deserializer)?;
+        Ok(text.and_then(|text| serde_json::from_str(&text).ok()))
+    }
+}
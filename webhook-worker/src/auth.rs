@@ -0,0 +1,317 @@
+//! Management API authentication and role-based access control
+//! Callers authenticate with the global `API_TOKEN` secret, a project API token
+//! (see `tokens`) or a JWT from the configured OIDC issuer (see `oidc`). A project is the owning user's workspace: its webhooks plus the
+//! ones shared with that user. Roles are ordered viewer < editor < owner.
+//! Rejected tokens and under-privileged callers are recorded as security events.
+
+use crate::security_events::{self, Kind, SecurityEvent};
+use crate::{oidc, tokens};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Actor recorded for callers authenticated with the static `API_TOKEN`
+pub const API_TOKEN_ACTOR: &str = "api_token";
+
+/// Router data handed to every route
+#[derive(Debug)]
+pub struct RouteData {
+    /// The authenticated caller on /api routes, None on public routes
+    pub principal: Option<Principal>,
+    /// The fetch event, for work that finishes after the response (`wait_until`)
+    pub context: Context,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// List and read captured requests
+    Viewer,
+    /// Viewer plus changing configuration and deleting data
+    Editor,
+    /// Editor plus managing tokens
+    Owner,
+}
+
+impl Role {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "viewer" => Some(Self::Viewer),
+            "editor" => Some(Self::Editor),
+            "owner" => Some(Self::Owner),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Viewer => "viewer",
+            Self::Editor => "editor",
+            Self::Owner => "owner",
+        }
+    }
+
+    /// Role granted through a `webhook_shares` row; an unknown role grants nothing
+    pub fn from_share(value: &str) -> Option<Self> {
+        Self::parse(value)
+    }
+}
+
+/// Token scopes, an optional second restriction on top of the role
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    /// Read captured requests
+    #[serde(rename = "ingest:read")]
+    IngestRead,
+    /// Change webhook configuration and delete data
+    #[serde(rename = "webhooks:write")]
+    WebhooksWrite,
+    /// Token management
+    #[serde(rename = "admin")]
+    Admin,
+    /// Lease and acknowledge inbox requests (also granted by `webhooks:write`)
+    #[serde(rename = "inbox:consume")]
+    InboxConsume,
+}
+
+impl Scope {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ingest:read" => Some(Self::IngestRead),
+            "webhooks:write" => Some(Self::WebhooksWrite),
+            "admin" => Some(Self::Admin),
+            "inbox:consume" => Some(Self::InboxConsume),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::IngestRead => "ingest:read",
+            Self::WebhooksWrite => "webhooks:write",
+            Self::Admin => "admin",
+            Self::InboxConsume => "inbox:consume",
+        }
+    }
+}
+
+/// What an API route needs from the caller
+pub struct Requirement {
+    pub role: Role,
+    pub scope: Scope,
+    /// Needs a global (unscoped) caller
+    pub global: bool,
+}
+
+/// An authenticated management API caller
+#[derive(Debug, Clone)]
+pub struct Principal {
+    /// Audit actor (`api_token`, `token:{id}` or `oidc:{sub}`)
+    pub actor: String,
+    /// Project (user) the token is scoped to; None for the global `API_TOKEN`
+    pub user_id: Option<String>,
+    pub role: Role,
+    /// Scopes granted to the token; None allows everything the role does
+    pub scopes: Option<Vec<Scope>>,
+}
+
+impl Principal {
+    /// Whether the caller is not limited to one project
+    pub fn is_global(&self) -> bool {
+        self.user_id.is_none()
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.as_ref().is_none_or(|scopes| {
+            scopes.contains(&scope) || (scope == Scope::InboxConsume && scopes.contains(&Scope::WebhooksWrite))
+        })
+    }
+}
+
+/// Principal attached to the route by `guard`
+pub fn principal(ctx: &RouteContext<RouteData>) -> Result<&Principal> {
+    ctx.data
+        .principal
+        .as_ref()
+        .ok_or_else(|| Error::RustError("Route is not behind the API guard".to_string()))
+}
+
+/// Middleware for /api routes: authenticate the caller and check the route's
+/// role and scope requirement. Returns the principal, or the 401/403 response to send.
+pub async fn guard(req: &Request, env: &Env) -> Result<std::result::Result<Principal, Response>> {
+    let path = req.path();
+    let principal = match authenticate(req, env).await? {
+        Some(principal) => principal,
+        None => {
+            // A request without any token is just unauthenticated; a wrong one is misuse
+            if bearer_token(req).is_some() {
+                let event = SecurityEvent::from_request(req, Kind::TokenMisuse)
+                    .detail(serde_json::json!({
+                        "reason": "invalid_token",
+                        "method": req.method().to_string(),
+                        "path": path,
+                    }));
+                security_events::record(env, event).await;
+            }
+            return Ok(Err(Response::error("Unauthorized", 401)?));
+        }
+    };
+
+    let required = requirement(&req.method(), &path);
+    if principal.role < required.role
+        || !principal.has_scope(required.scope)
+        || (required.global && !principal.is_global())
+    {
+        let event = SecurityEvent::from_request(req, Kind::TokenMisuse)
+            .actor(principal.actor.as_str())
+            .detail(serde_json::json!({
+                "reason": "forbidden",
+                "method": req.method().to_string(),
+                "path": path,
+                "role": principal.role,
+                "required_role": required.role,
+                "required_scope": required.scope,
+            }));
+        security_events::record(env, event).await;
+        return Ok(Err(Response::error("Forbidden", 403)?));
+    }
+
+    Ok(Ok(principal))
+}
+
+/// Minimum role and scope for an API route
+pub fn requirement(method: &Method, path: &str) -> Requirement {
+    let (role, scope, global) = if path.starts_with("/api/admin") || path.starts_with("/api/audit") {
+        (Role::Owner, Scope::Admin, true)
+    } else if path.starts_with("/api/tokens") {
+        (Role::Owner, Scope::Admin, false)
+    } else if path.ends_with("/console") {
+        // An upgrade, but the console pauses forwarding, overrides answers and replays
+        (Role::Editor, Scope::WebhooksWrite, false)
+    } else if *method == Method::Post && (path.ends_with("/inbox") || path.ends_with("/inbox/ack")) {
+        // Leasing and acking change what other consumers get, but not the configuration
+        (Role::Editor, Scope::InboxConsume, false)
+    } else if matches!(method, Method::Get | Method::Head) {
+        (Role::Viewer, Scope::IngestRead, false)
+    } else {
+        (Role::Editor, Scope::WebhooksWrite, false)
+    };
+    Requirement { role, scope, global }
+}
+
+/// Resolve the caller from the Authorization header
+pub async fn authenticate(req: &Request, env: &Env) -> Result<Option<Principal>> {
+    let token = match bearer_token(req) {
+        Some(token) => token,
+        None => return Ok(None),
+    };
+
+    if let Ok(secret) = env.secret("API_TOKEN") {
+        let expected = secret.to_string();
+        if !expected.is_empty() && constant_time_eq(token.as_bytes(), expected.as_bytes()) {
+            return Ok(Some(Principal {
+                actor: API_TOKEN_ACTOR.to_string(),
+                user_id: None,
+                role: Role::Owner,
+                scopes: None,
+            }));
+        }
+    }
+
+    if oidc::looks_like_jwt(&token) && oidc::is_enabled(env) {
+        return oidc::authenticate(env, &token).await;
+    }
+
+    let db = env.d1("DB")?;
+    let token = match tokens::find_active(&db, &token).await? {
+        Some(token) => token,
+        None => return Ok(None),
+    };
+    tokens::touch(&db, &token).await;
+
+    Ok(Role::parse(&token.role).map(|role| Principal {
+        actor: format!("token:{}", token.id),
+        scopes: token.scope_list(),
+        user_id: Some(token.user_id),
+        role,
+    }))
+}
+
+#[derive(Deserialize)]
+struct OwnerRow {
+    user_id: String,
+}
+
+#[derive(Deserialize)]
+struct ShareRow {
+    role: String,
+}
+
+/// The caller's effective role on a webhook, if it is part of their project
+pub async fn webhook_role(db: &D1Database, principal: &Principal, webhook_id: &str) -> Result<Option<Role>> {
+    let user_id = match &principal.user_id {
+        Some(user_id) => user_id,
+        None => return Ok(Some(principal.role)),
+    };
+
+    let owner = db
+        .prepare("SELECT user_id FROM webhooks WHERE id = ?1")
+        .bind(&[JsValue::from_str(webhook_id)])?
+        .first::<OwnerRow>(None)
+        .await?;
+    if owner.is_some_and(|owner| &owner.user_id == user_id) {
+        return Ok(Some(principal.role));
+    }
+
+    let share = db
+        .prepare(
+            "SELECT role FROM webhook_shares \
+             WHERE webhook_id = ?1 AND shared_with_user_id = ?2 AND accepted_at IS NOT NULL",
+        )
+        .bind(&[JsValue::from_str(webhook_id), JsValue::from_str(user_id)])?
+        .first::<ShareRow>(None)
+        .await?;
+    Ok(share
+        .and_then(|share| Role::from_share(&share.role))
+        .map(|role| role.min(principal.role)))
+}
+
+/// Whether a caller holding `granted` on a webhook (None: not in their project) may use a route
+/// needing `required`; the error is the status to answer with, 404 so outsiders can't tell the
+/// webhook exists and 403 for project members without the role
+pub fn webhook_access(granted: Option<Role>, required: Role) -> std::result::Result<(), u16> {
+    match granted {
+        Some(granted) if granted >= required => Ok(()),
+        Some(_) => Err(403),
+        None => Err(404),
+    }
+}
+
+/// Whether the caller holds at least `role` on a webhook
+pub async fn can_access_webhook(
+    db: &D1Database,
+    principal: &Principal,
+    webhook_id: &str,
+    role: Role,
+) -> Result<bool> {
+    Ok(webhook_role(db, principal, webhook_id)
+        .await?
+        .is_some_and(|granted| granted >= role))
+}
+
+/// Extract the bearer token from the Authorization header
+pub fn bearer_token(req: &Request) -> Option<String> {
+    let header = req.headers().get("Authorization").ok()??;
+    header
+        .strip_prefix("Bearer ")
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+}
+
+/// Compare secrets without leaking the mismatch position through timing
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,88 @@
+//! OpenID Connect back-channel logout sink
+//! `POST /w/{uuid}/oidc/backchannel-logout` can be registered as a relying
+//! party's `backchannel_logout_uri`. The delivery is stored like any capture
+//! and answered as OpenID Connect Back-Channel Logout 1.0 requires: 200 when
+//! the `logout_token` is a well-formed logout token (issuer, audience, issue
+//! and expiry times, `jti`, the back-channel logout event, `sub` or `sid`, no
+//! `nonce`), else 400 with an `invalid_request` error saying what is wrong,
+//! so an identity provider's retry and error handling can be exercised. The
+//! token's signature is not verified; there is no key set to verify it with.
+
+use crate::config::CustomResponse;
+use crate::oauth;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Path suffix of the logout route
+pub const PATH: &str = "/oidc/backchannel-logout";
+
+/// Event type of every logout delivery
+pub const EVENT_TYPE: &str = "oidc.backchannel_logout";
+
+/// Member of the `events` claim that makes a JWT a logout token
+pub const LOGOUT_EVENT: &str = "http://schemas.openid.net/event/backchannel-logout";
+
+/// Claims of the delivery's logout token, or why it is not one (checked at `now`, Unix seconds)
+pub fn check(content_type: Option<&str>, body: &str, now: i64) -> Result<Value, String> {
+    if !content_type.is_some_and(|content_type| content_type.starts_with("application/x-www-form-urlencoded")) {
+        return Err("the body must be application/x-www-form-urlencoded".to_string());
+    }
+    let token = form_urlencoded::parse(body.as_bytes())
+        .find(|(name, _)| name == "logout_token")
+        .map(|(_, token)| token.into_owned())
+        .ok_or("logout_token is missing")?;
+    let claims = oauth::jwt_claims(&token).ok_or("logout_token is not a JWT")?;
+
+    let text = |name: &str| claims.get(name).and_then(Value::as_str).filter(|value| !value.is_empty());
+    let time = |name: &str| claims.get(name).and_then(Value::as_i64);
+    if text("iss").is_none() {
+        return Err("iss is missing".to_string());
+    }
+    let audience = match claims.get("aud") {
+        Some(Value::String(audience)) => !audience.is_empty(),
+        Some(Value::Array(audiences)) => audiences.iter().any(Value::is_string),
+        _ => false,
+    };
+    if !audience {
+        return Err("aud is missing".to_string());
+    }
+    if time("iat").is_none() {
+        return Err("iat is missing".to_string());
+    }
+    match time("exp") {
+        None => return Err("exp is missing".to_string()),
+        Some(exp) if exp < now => return Err("the logout token has expired".to_string()),
+        Some(_) => {}
+    }
+    if text("jti").is_none() {
+        return Err("jti is missing".to_string());
+    }
+    if !claims.get("events").and_then(|events| events.get(LOGOUT_EVENT)).is_some_and(Value::is_object) {
+        return Err(format!("events must contain {}", LOGOUT_EVENT));
+    }
+    if text("sub").is_none() && text("sid").is_none() {
+        return Err("sub or sid is required".to_string());
+    }
+    if claims.get("nonce").is_some() {
+        return Err("a logout token must not contain a nonce".to_string());
+    }
+    Ok(claims)
+}
+
+/// The answer the identity provider expects (sent with `Cache-Control: no-store`)
+pub fn respond(content_type: Option<&str>, body: &str, now: i64) -> CustomResponse {
+    match check(content_type, body, now) {
+        Ok(_) => CustomResponse {
+            status: 200,
+            body: String::new(),
+            content_type: None,
+            headers: BTreeMap::new(),
+        },
+        Err(problem) => CustomResponse {
+            status: 400,
+            body: serde_json::json!({ "error": "invalid_request", "error_description": problem }).to_string(),
+            content_type: Some("application/json".to_string()),
+            headers: BTreeMap::new(),
+        },
+    }
+}
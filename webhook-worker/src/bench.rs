@@ -0,0 +1,219 @@
+//! Hot path micro-benchmarks (`bench` feature)
+//! The cases exercise the per-capture work that runs before storage: header
+//! serialization and indexing, body hashing and signature checks, and config
+//! routing. They only touch pure code, so they build for wasm32 as well as
+//! natively; the runner supplies the clock (`benches/hot_path.rs` uses
+//! `std::time::Instant`), since the Workers runtime freezes timers between I/O.
+
+use crate::config::{EventRoute, FieldSource, WebhookConfig};
+use crate::event_time;
+use crate::headers::IndexedHeaders;
+use crate::partition;
+use crate::signature;
+use crate::signed_url;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::hint::black_box;
+
+/// A named benchmark body; `bytes` is the input size for throughput reporting
+pub struct BenchCase {
+    pub name: &'static str,
+    pub bytes: usize,
+    pub run: Box<dyn Fn()>,
+}
+
+/// Timing summary of one case, in nanoseconds per iteration
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct BenchStats {
+    pub name: String,
+    pub iterations: u64,
+    pub mean_ns: f64,
+    pub median_ns: f64,
+    pub min_ns: f64,
+}
+
+impl BenchStats {
+    /// Summarize per-iteration sample times
+    pub fn from_samples(name: &str, iterations: u64, samples: &mut [f64]) -> Self {
+        samples.sort_by(|a, b| a.total_cmp(b));
+        let mean_ns = samples.iter().sum::<f64>() / samples.len().max(1) as f64;
+        Self {
+            name: name.to_string(),
+            iterations,
+            mean_ns,
+            median_ns: samples.get(samples.len() / 2).copied().unwrap_or(0.0),
+            min_ns: samples.first().copied().unwrap_or(0.0),
+        }
+    }
+}
+
+/// Representative delivery headers (GitHub push through Cloudflare)
+fn sample_headers() -> HashMap<String, String> {
+    [
+        ("content-type", "application/json; charset=utf-8"),
+        ("user-agent", "GitHub-Hookshot/8f2c1a4"),
+        ("x-github-event", "push"),
+        ("x-github-delivery", "72d3162e-cc78-11e3-81ab-4c9367dc0958"),
+        ("x-github-hook-id", "292430182"),
+        ("x-hub-signature-256", "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17"),
+        ("accept", "*/*"),
+        ("cf-connecting-ip", "140.82.115.1"),
+        ("cf-ray", "8a1b2c3d4e5f6a7b-IAD"),
+        ("x-forwarded-proto", "https"),
+        ("content-length", "7342"),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect()
+}
+
+/// A GitHub-push-sized JSON body (~7 KB)
+fn sample_body() -> String {
+    let commits: Vec<_> = (0..20)
+        .map(|i| {
+            serde_json::json!({
+                "id": format!("{:040x}", i),
+                "message": "Fix race condition in file watcher initialization",
+                "author": { "name": "octocat", "email": "octocat@github.com" },
+                "added": [], "removed": [], "modified": ["src/watcher.rs"],
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "ref": "refs/heads/main",
+        "repository": { "id": 1296269, "full_name": "octocat/Hello-World" },
+        "pusher": { "name": "octocat" },
+        "commits": commits,
+    })
+    .to_string()
+}
+
+fn routed_config() -> WebhookConfig {
+    let route = |event_type: &str| EventRoute {
+        event_type: event_type.to_string(),
+        forward_url: Some("https://example.com/hooks".to_string()),
+        response: None,
+        retention_days: None,
+    };
+    WebhookConfig {
+        event_type: Some(FieldSource::Header("x-github-event".to_string())),
+        idempotency_key: Some(FieldSource::Body("repository.id".to_string())),
+        routes: vec![
+            route("issues"),
+            route("pull_request*"),
+            route("release"),
+            route("push"),
+            route("*"),
+        ],
+        ..WebhookConfig::default()
+    }
+}
+
+/// Every hot path case
+pub fn cases() -> Vec<BenchCase> {
+    let headers = sample_headers();
+    let body = sample_body();
+    let body_bytes = body.len();
+    let config = routed_config();
+    let secret = "whsec_benchmark_secret";
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key");
+    mac.update(body.as_bytes());
+    let signature_hex: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let url_signature = signed_url::sign(secret, "0b9c9a4e-5c55-4d3a-8f5d-3f6b1b2c7d8e", 1_900_000_000);
+
+    let serialize_headers = headers.clone();
+    let index_headers = headers.clone();
+    let time_headers = headers.clone();
+    let extract_headers = headers.clone();
+    let hash_body = body.clone();
+    let hmac_body = body.clone();
+    let extract_body = body.clone();
+    let sources: Vec<FieldSource> = [config.event_type.clone(), config.idempotency_key.clone()]
+        .into_iter()
+        .flatten()
+        .collect();
+
+    vec![
+        BenchCase {
+            name: "headers/serialize_json",
+            bytes: 0,
+            run: Box::new(move || {
+                black_box(serde_json::to_string(black_box(&serialize_headers)).ok());
+            }),
+        },
+        BenchCase {
+            name: "headers/index",
+            bytes: 0,
+            run: Box::new(move || {
+                black_box(IndexedHeaders::extract(black_box(&index_headers)));
+            }),
+        },
+        BenchCase {
+            name: "headers/event_time",
+            bytes: 0,
+            run: Box::new(move || {
+                black_box(event_time::extract(black_box(&time_headers)));
+            }),
+        },
+        BenchCase {
+            name: "body/sha256",
+            bytes: body_bytes,
+            run: Box::new(move || {
+                black_box(Sha256::digest(black_box(hash_body.as_bytes())));
+            }),
+        },
+        BenchCase {
+            name: "body/hmac_verify",
+            bytes: body_bytes,
+            run: Box::new(move || {
+                black_box(signature::hmac_matches(secret, black_box(hmac_body.as_bytes()), &signature_hex));
+            }),
+        },
+        BenchCase {
+            name: "body/field_extract",
+            bytes: body_bytes,
+            run: Box::new(move || {
+                for source in &sources {
+                    black_box(source.extract(black_box(&extract_headers), black_box(&extract_body)));
+                }
+            }),
+        },
+        BenchCase {
+            name: "routing/event_route",
+            bytes: 0,
+            run: Box::new(move || {
+                for event_type in ["push", "pull_request_review", "workflow_run"] {
+                    black_box(config.route_for(black_box(Some(event_type))));
+                }
+            }),
+        },
+        BenchCase {
+            name: "routing/signed_url_verify",
+            bytes: 0,
+            run: Box::new(move || {
+                black_box(signed_url::verify(
+                    secret,
+                    "0b9c9a4e-5c55-4d3a-8f5d-3f6b1b2c7d8e",
+                    Some("1900000000"),
+                    Some(black_box(&url_signature)),
+                    1_800_000_000,
+                ))
+                .ok();
+            }),
+        },
+        BenchCase {
+            name: "routing/partition_table",
+            bytes: 0,
+            run: Box::new(|| {
+                black_box(partition::write_table(true, black_box(1_760_000_000)));
+            }),
+        },
+    ]
+}
@@ -0,0 +1,198 @@
+//! Content-addressed bodies
+//! With `BODY_DEDUP_MIN_BYTES` set, the D1 capture store keeps bodies of at
+//! least that many bytes once per webhook in the `bodies` table, keyed by their
+//! SHA-256, and the capture's `data` column holds `body:sha256:{hash}` instead.
+//! Providers retrying identical payloads, or sending the same heartbeat every
+//! minute, then add a row reference rather than another copy. Each body counts
+//! the captures referencing it (`refs`): inserting one adds a reference, and
+//! deleting or rewriting captures (purges, retention, erasure, dropped
+//! partitions) gives theirs back in the same batch. Bodies nothing references
+//! any more are deleted by the storage maintenance. Inline bodies that start
+//! like a reference (or like an escaped body) are stored behind
+//! `ESCAPE_PREFIX`, so a sender posting `body:sha256:{hash}` gets a capture of
+//! that text rather than another capture's body.
+//!
+//! Bodies are deduplicated as they reach the store, after compression (see
+//! `compression.rs`) and encryption (see `encryption.rs`); encrypted bodies
+//! never repeat, so encryption leaves nothing to deduplicate.
+
+use crate::legal_hold;
+use crate::storage::{CaptureRecord, StoredRequest};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Marks a `data` value referencing a stored body
+pub const REF_PREFIX: &str = "body:sha256:";
+
+/// Marks an inline `data` value that would otherwise read as a reference
+pub const ESCAPE_PREFIX: &str = "body:inline:";
+
+/// Smallest body deduplicated (`BODY_DEDUP_MIN_BYTES`); None when deduplication is off
+pub fn min_bytes(env: &Env) -> Option<usize> {
+    env.var("BODY_DEDUP_MIN_BYTES").ok()?.to_string().trim().parse().ok()
+}
+
+/// Hex SHA-256 of a body
+pub fn hash(data: &str) -> String {
+    Sha256::digest(data.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn reference(hash: &str) -> String {
+    format!("{}{}", REF_PREFIX, hash)
+}
+
+/// Hash a `data` value references; None for an inline body
+pub fn parse_reference(data: &str) -> Option<&str> {
+    data.strip_prefix(REF_PREFIX).filter(|hash| hash.len() == 64)
+}
+
+/// `data` as stored inline, escaped when it starts like a reference or an escaped body
+pub fn escape(data: &str) -> Cow<'_, str> {
+    if data.starts_with(REF_PREFIX) || data.starts_with(ESCAPE_PREFIX) {
+        Cow::Owned(format!("{}{}", ESCAPE_PREFIX, data))
+    } else {
+        Cow::Borrowed(data)
+    }
+}
+
+/// The record as stored without splitting its body off (see `escape`)
+pub fn inline(record: &CaptureRecord) -> Cow<'_, CaptureRecord> {
+    match escape(&record.data) {
+        Cow::Borrowed(_) => Cow::Borrowed(record),
+        Cow::Owned(data) => Cow::Owned(CaptureRecord { data, ..record.clone() }),
+    }
+}
+
+/// The body of a record worth storing apart, and the record referencing it instead
+pub fn split(record: &CaptureRecord, min_bytes: usize) -> Option<(String, CaptureRecord)> {
+    if record.data.len() < min_bytes.max(1) {
+        return None;
+    }
+    let mut referencing = record.clone();
+    referencing.data = reference(&hash(&record.data));
+    Some((record.data.clone(), referencing))
+}
+
+/// Store a body, or add a reference to the stored one
+pub fn add_reference(db: &D1Database, webhook_id: &str, body: &str, now_ms: i64) -> Result<D1PreparedStatement> {
+    db.prepare(
+        "INSERT INTO bodies (webhook_id, hash, data, size_bytes, refs, created_at_ms) VALUES (?1, ?2, ?3, ?4, 1, ?5) \
+         ON CONFLICT (webhook_id, hash) DO UPDATE SET refs = refs + 1",
+    )
+    .bind(&[
+        JsValue::from_str(webhook_id),
+        JsValue::from_str(&hash(body)),
+        JsValue::from_str(body),
+        JsValue::from_f64(body.len() as f64),
+        JsValue::from_f64(now_ms as f64),
+    ])
+}
+
+/// Give back one reference (of a capture that turned out to be stored already)
+pub fn drop_reference(db: &D1Database, webhook_id: &str, hash: &str) -> Result<D1PreparedStatement> {
+    db.prepare("UPDATE bodies SET refs = refs - 1 WHERE webhook_id = ?1 AND hash = ?2")
+        .bind(&[JsValue::from_str(webhook_id), JsValue::from_str(hash)])
+}
+
+/// Give back the references of the rows of `table` matching `condition` (over the
+/// caller's numbered `params`, `?1` being the webhook ID), before they are deleted
+/// or their payloads rewritten
+pub fn release(db: &D1Database, table: &str, condition: &str, params: &[JsValue]) -> Result<D1PreparedStatement> {
+    let referenced = format!(
+        "FROM {table} WHERE {condition} AND data = '{prefix}' || bodies.hash",
+        table = table,
+        condition = condition,
+        prefix = REF_PREFIX
+    );
+    db.prepare(format!(
+        "UPDATE bodies SET refs = refs - (SELECT COUNT(*) {referenced}) \
+         WHERE webhook_id = ?1 AND EXISTS (SELECT 1 {referenced})",
+        referenced = referenced
+    ))
+    .bind(params)
+}
+
+/// Give back the references of a partition about to be dropped, except those of
+/// legally held rows, which are kept in the legacy table
+pub async fn release_table(db: &D1Database, table: &str) -> Result<()> {
+    let referenced = format!(
+        "FROM {table} WHERE webhook_id = bodies.webhook_id AND data = '{prefix}' || bodies.hash AND NOT ({held})",
+        table = table,
+        prefix = REF_PREFIX,
+        held = legal_hold::HELD_ROWS_CLAUSE
+    );
+    db.prepare(format!(
+        "UPDATE bodies SET refs = refs - (SELECT COUNT(*) {referenced}) WHERE EXISTS (SELECT 1 {referenced})",
+        referenced = referenced
+    ))
+    .run()
+    .await?;
+    Ok(())
+}
+
+/// SQL matching rows of `table` whose referenced body contains `needle` (bound as `needle_param`)
+pub fn body_contains(table: &str, needle_param: &str) -> String {
+    format!(
+        "({table}.data LIKE '{prefix}%' AND EXISTS (SELECT 1 FROM bodies WHERE bodies.webhook_id = {table}.webhook_id \
+         AND {table}.data = '{prefix}' || bodies.hash AND instr(lower(bodies.data), {needle}) > 0))",
+        table = table,
+        prefix = REF_PREFIX,
+        needle = needle_param
+    )
+}
+
+#[derive(Deserialize)]
+struct BodyRow {
+    hash: String,
+    data: String,
+}
+
+/// Put the referenced bodies back into requests read from the store
+pub async fn resolve(db: &D1Database, requests: &mut [StoredRequest]) -> Result<()> {
+    let mut references: HashMap<String, Vec<String>> = HashMap::new();
+    for request in requests.iter() {
+        if let Some(hash) = parse_reference(&request.data) {
+            references.entry(request.webhook_id.clone()).or_default().push(hash.to_string());
+        }
+    }
+    let mut bodies: HashMap<(String, String), String> = HashMap::new();
+    for (webhook_id, hashes) in references {
+        let rows = db
+            .prepare(
+                "SELECT hash, data FROM bodies WHERE webhook_id = ?1 AND hash IN (SELECT value FROM json_each(?2))",
+            )
+            .bind(&[JsValue::from_str(&webhook_id), JsValue::from_str(&serde_json::to_string(&hashes)?)])?
+            .all()
+            .await?
+            .results::<BodyRow>()?;
+        bodies.extend(rows.into_iter().map(|row| ((webhook_id.clone(), row.hash), row.data)));
+    }
+
+    for request in requests.iter_mut() {
+        if let Some(data) = request.data.strip_prefix(ESCAPE_PREFIX) {
+            request.data = data.to_string();
+            continue;
+        }
+        let Some(hash) = parse_reference(&request.data) else {
+            continue;
+        };
+        match bodies.get(&(request.webhook_id.clone(), hash.to_string())) {
+            Some(body) => request.data = body.clone(),
+            None => {
+                log_warn!("⚠️  Body {} of capture {} is missing", hash, request.id);
+                request.data = String::new();
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Delete bodies no capture references; returns how many
+pub async fn prune(db: &D1Database) -> Result<u64> {
+    let result = db.prepare("DELETE FROM bodies WHERE refs <= 0").run().await?;
+    Ok(result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) as u64)
+}
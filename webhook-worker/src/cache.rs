@@ -0,0 +1,78 @@
+//! Webhook UUID → ID cache
+//! KV entries `webhook:uuid:{uuid}` hold the internal webhook ID (shared with the admin worker)
+
+use crate::directory::Directory;
+use crate::kv::KvBackend;
+use serde::Deserialize;
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Key prefix shared with the admin worker
+pub const KEY_PREFIX: &str = "webhook:uuid:";
+
+/// Cache lifetime for resolved webhook IDs (1 hour)
+pub const TTL_SECONDS: u64 = 3600;
+
+#[derive(Deserialize)]
+struct WebhookRow {
+    id: String,
+}
+
+/// KV key for a webhook UUID
+pub fn key(uuid: &str) -> String {
+    format!("{}{}", KEY_PREFIX, uuid)
+}
+
+/// Resolve a webhook UUID to its ID (KV first, D1 fallback), caching D1 hits
+pub async fn resolve_webhook_id(
+    kv: &(impl KvBackend + ?Sized),
+    db: &(impl Directory + ?Sized),
+    uuid: &str,
+) -> Result<Option<String>> {
+    let cache_key = key(uuid);
+
+    // Try KV cache first
+    if let Some(cached_id) = kv.get_text(&cache_key).await? {
+        // Cache hit! Use cached webhook ID
+        log_debug!("✅ KV cache hit for UUID: {}", uuid);
+        return Ok(Some(cached_id));
+    }
+
+    // Cache miss - query D1
+    log_info!("❌ KV cache miss for UUID: {}, querying D1", uuid);
+    let webhook_id = match db.find_webhook_id(uuid).await? {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+
+    // Cache the result for future requests
+    match put(kv, uuid, &webhook_id).await {
+        Ok(_) => log_info!("📝 Cached webhook ID in KV: {}", webhook_id),
+        Err(e) => log_error!("⚠️  Failed to cache webhook ID: {:?}", e),
+    }
+
+    Ok(Some(webhook_id))
+}
+
+/// Look up a webhook ID by UUID directly in D1; environment UUIDs resolve to their webhook
+pub async fn find_in_d1(db: &D1Database, uuid: &str) -> Result<Option<String>> {
+    let row = db
+        .prepare(
+            "SELECT id FROM webhooks WHERE uuid = ?1 \
+             UNION ALL SELECT webhook_id AS id FROM webhook_environments WHERE uuid = ?1 LIMIT 1",
+        )
+        .bind(&[JsValue::from_str(uuid)])?
+        .first::<WebhookRow>(None)
+        .await?;
+    Ok(row.map(|row| row.id))
+}
+
+/// Drop a cache entry
+pub async fn delete(kv: &(impl KvBackend + ?Sized), uuid: &str) -> Result<()> {
+    kv.delete(&key(uuid)).await
+}
+
+/// Write a cache entry with the standard TTL
+pub async fn put(kv: &(impl KvBackend + ?Sized), uuid: &str, webhook_id: &str) -> Result<()> {
+    kv.put_text(&key(uuid), webhook_id, Some(TTL_SECONDS)).await
+}
@@ -0,0 +1,41 @@
+//! Canonical JSON bodies
+//! JSON object and array bodies are also stored minified with object keys
+//! sorted (recursively) in `canonical_data`, so two deliveries that differ
+//! only in key order or whitespace compare, hash and infer schemas the same.
+//! The raw body stays in `data` exactly as sent; the request detail API can
+//! pretty-print the canonical copy for reading.
+
+use serde_json::{Map, Value};
+
+/// Canonical form of a JSON object or array body, None for anything else
+pub fn canonicalize(data: &str) -> Option<String> {
+    let trimmed = data.trim_start();
+    if !trimmed.starts_with(['{', '[']) {
+        return None;
+    }
+    let value: Value = serde_json::from_str(trimmed).ok()?;
+    serde_json::to_string(&sorted(value)).ok()
+}
+
+/// Canonical form, indented for reading
+pub fn pretty(canonical: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(canonical).ok()?;
+    serde_json::to_string_pretty(&value).ok()
+}
+
+/// Rebuild objects in key order, independent of how `serde_json::Map` orders entries
+fn sorted(value: Value) -> Value {
+    match value {
+        Value::Object(object) => {
+            let mut entries: Vec<(String, Value)> = object.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            let mut object = Map::new();
+            for (key, value) in entries {
+                object.insert(key, sorted(value));
+            }
+            Value::Object(object)
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(sorted).collect()),
+        other => other,
+    }
+}
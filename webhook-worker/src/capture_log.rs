@@ -0,0 +1,108 @@
+//! Structured capture log events
+//! One JSON line per ingestion request, written with console.log so it lands in
+//! Workers Logs / Trace Events and can be shipped with Logpush without extra queries.
+//! The line belongs to the `capture` module of `LOG_LEVEL`: failures are logged
+//! as errors, rejections as warnings and captures as info, which `LOG_SAMPLE`
+//! can thin out (see `logging.rs`); sampled lines carry their `sample_rate`.
+
+use crate::logging::{self, Level};
+use serde::Serialize;
+use worker::*;
+
+/// `event` field value used to pick capture lines out of the log stream
+const EVENT_NAME: &str = "webhook.capture";
+
+/// `LOG_LEVEL` / `LOG_SAMPLE` module name of the capture line
+const MODULE: &str = "capture";
+
+/// Outcome of a single ingestion request
+#[derive(Debug, Default, Serialize)]
+pub struct CaptureEvent {
+    pub event: &'static str,
+    pub webhook_uuid: String,
+    pub webhook_id: Option<String>,
+    /// Environment name when captured through an environment UUID
+    pub environment: Option<String>,
+    /// Upstream hops of a chained delivery (`x-webhook-chain`)
+    pub chain: Option<String>,
+    pub data_id: Option<String>,
+    pub method: String,
+    pub status: u16,
+    pub outcome: &'static str,
+    pub error: Option<String>,
+    pub content_type: Option<String>,
+    pub event_type: Option<String>,
+    /// Event type pattern of the config route that handled the delivery
+    pub route: Option<String>,
+    pub request_bytes: Option<i64>,
+    pub response_bytes: Option<i64>,
+    pub sequence: Option<i64>,
+    /// Signature verification outcome, when the webhook verifies signatures
+    pub verification: Option<&'static str>,
+    pub hot: bool,
+    /// Sender was a flagged UUID scanner and got the decoy response
+    pub decoy: bool,
+    /// Redelivery of a capture already stored under the same derived ID (see `dedup.rs`)
+    pub duplicate: bool,
+    pub received_at_ms: i64,
+    pub lookup_ms: Option<i64>,
+    /// A KV read failed and D1 served the lookup
+    pub kv_fallback: bool,
+    pub store_ms: Option<i64>,
+    /// Forwarding response status (last target) and total latency, when the environment or route forwards
+    pub forward_status: Option<u16>,
+    pub forward_ms: Option<i64>,
+    pub duration_ms: i64,
+    /// Share of lines like this one that are written, when `LOG_SAMPLE` thins them out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<f64>,
+}
+
+impl CaptureEvent {
+    /// Start an event for a request that arrived at `received_at_ms`
+    pub fn start(webhook_uuid: &str, method: &str, received_at_ms: i64) -> Self {
+        Self {
+            event: EVENT_NAME,
+            webhook_uuid: webhook_uuid.to_string(),
+            method: method.to_string(),
+            received_at_ms,
+            ..Self::default()
+        }
+    }
+
+    /// Fill in the final status and total duration, then write the log line
+    pub fn finish(mut self, status: u16, error: Option<String>) {
+        self.status = status;
+        self.outcome = match status {
+            _ if self.decoy => "decoy",
+            200..=299 if self.duplicate => "duplicate",
+            200..=299 => "captured",
+            404 => "not_found",
+            400..=499 => "rejected",
+            _ => "error",
+        };
+        self.error = error;
+        self.duration_ms = now_ms() - self.received_at_ms;
+
+        let level = match self.outcome {
+            "error" => Level::Error,
+            "captured" | "duplicate" => Level::Info,
+            _ => Level::Warn,
+        };
+        if !logging::enabled(MODULE, level) {
+            return;
+        }
+        if level == Level::Info {
+            self.sample_rate = Some(logging::rate(MODULE)).filter(|rate| *rate < 1.0);
+        }
+        match serde_json::to_string(&self) {
+            Ok(line) => console_log!("{}", line),
+            Err(e) => log_error!("❌ Failed to serialize capture log event: {:?}", e),
+        }
+    }
+}
+
+/// Current time in Unix milliseconds
+pub fn now_ms() -> i64 {
+    Date::now().as_millis() as i64
+}
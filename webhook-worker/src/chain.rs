@@ -0,0 +1,67 @@
+//! Webhook chaining
+//! A forwarding target of `webhook:{uuid}` hands the delivery straight to
+//! another webhook's capture pipeline inside the worker, with no public HTTP
+//! hop, so multi-stage pipelines (capture → route → capture) stay internal.
+//! Every chained capture carries `x-webhook-chain`: the upstream hops as
+//! `{uuid}/{capture id}`, oldest first. It is stored with the capture and
+//! logged, so any stage can be traced back to the original delivery, and it
+//! bounds the chain: a hop back to a webhook already on it, or past
+//! `MAX_DEPTH` hops, is dropped.
+
+/// Scheme of chained forwarding targets
+pub const TARGET_PREFIX: &str = "webhook:";
+
+/// Upstream hops of a chained capture
+pub const CHAIN_HEADER: &str = "x-webhook-chain";
+
+/// Longest chain followed (hops before the capture being forwarded included)
+pub const MAX_DEPTH: usize = 5;
+
+/// Webhook UUID of a `webhook:{uuid}` target
+pub fn target_uuid(target: &str) -> Option<&str> {
+    target.strip_prefix(TARGET_PREFIX).filter(|uuid| {
+        !uuid.is_empty()
+            && uuid.len() <= 64
+            && uuid.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'-')
+    })
+}
+
+/// One upstream hop
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hop {
+    pub uuid: String,
+    pub capture_id: String,
+}
+
+/// Parse a chain header value; malformed entries are skipped
+pub fn parse(value: &str) -> Vec<Hop> {
+    value
+        .split(',')
+        .filter_map(|hop| {
+            let (uuid, capture_id) = hop.trim().split_once('/')?;
+            (!uuid.is_empty() && !capture_id.is_empty()).then(|| Hop {
+                uuid: uuid.to_string(),
+                capture_id: capture_id.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Chain header for forwarding capture `capture_id` of `uuid` to `target`,
+/// or None when the hop would loop or exceed `MAX_DEPTH`
+pub fn next(upstream: Option<&str>, uuid: &str, capture_id: &str, target: &str) -> Option<String> {
+    let mut hops = upstream.map(parse).unwrap_or_default();
+    hops.push(Hop {
+        uuid: uuid.to_string(),
+        capture_id: capture_id.to_string(),
+    });
+    if hops.len() > MAX_DEPTH || hops.iter().any(|hop| hop.uuid == target) {
+        return None;
+    }
+    Some(
+        hops.iter()
+            .map(|hop| format!("{}/{}", hop.uuid, hop.capture_id))
+            .collect::<Vec<_>>()
+            .join(","),
+    )
+}
@@ -0,0 +1,142 @@
+//! Charset detection and UTF-8 normalization
+//! Bodies are stored as UTF-8 text, so anything else is transcoded before it
+//! is stored: the charset comes from a byte order mark, else the
+//! `Content-Type` `charset` parameter, else the bytes themselves (valid UTF-8
+//! stays UTF-8; other bytes under a textual media type are read as
+//! Windows-1252). Labels follow the WHATWG Encoding Standard, so `latin1`,
+//! `iso-8859-1` and `us-ascii` all decode as Windows-1252. The detected charset
+//! is recorded with the capture and, when transcoding changed the bytes, the
+//! original body is kept next to the searchable copy.
+
+use crate::preview;
+
+/// Supported body charsets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Windows1252,
+}
+
+/// Windows-1252 code points for bytes 0x80-0x9F (undefined bytes map to C1 controls)
+const WINDOWS_1252_HIGH: [u16; 32] = [
+    0x20ac, 0x0081, 0x201a, 0x0192, 0x201e, 0x2026, 0x2020, 0x2021, 0x02c6, 0x2030, 0x0160, 0x2039, 0x0152, 0x008d,
+    0x017d, 0x008f, 0x0090, 0x2018, 0x2019, 0x201c, 0x201d, 0x2022, 0x2013, 0x2014, 0x02dc, 0x2122, 0x0161, 0x203a,
+    0x0153, 0x009d, 0x017e, 0x0178,
+];
+
+impl Charset {
+    /// Charset for an encoding label (`UTF-8`, `utf-16le`, `ISO-8859-1`, ...)
+    pub fn from_label(label: &str) -> Option<Self> {
+        match label.trim().trim_matches('"').to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" | "unicode-1-1-utf-8" => Some(Self::Utf8),
+            "utf-16" | "utf-16le" | "unicode" | "ucs-2" => Some(Self::Utf16Le),
+            "utf-16be" | "unicodefffe" => Some(Self::Utf16Be),
+            "windows-1252" | "cp1252" | "x-cp1252" | "iso-8859-1" | "iso8859-1" | "iso_8859-1" | "latin1" | "l1"
+            | "cp819" | "ibm819" | "us-ascii" | "ascii" | "ansi_x3.4-1968" => Some(Self::Windows1252),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Utf8 => "utf-8",
+            Self::Utf16Le => "utf-16le",
+            Self::Utf16Be => "utf-16be",
+            Self::Windows1252 => "windows-1252",
+        }
+    }
+
+    /// Decode to UTF-8; invalid sequences become U+FFFD
+    pub fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Self::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Self::Utf16Le | Self::Utf16Be => {
+                let units = bytes.chunks(2).map(|pair| match (self, pair) {
+                    (Self::Utf16Le, [low, high]) => u16::from_le_bytes([*low, *high]),
+                    (_, [high, low]) => u16::from_be_bytes([*high, *low]),
+                    // Odd trailing byte
+                    _ => 0xfffd,
+                });
+                char::decode_utf16(units)
+                    .map(|unit| unit.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect()
+            }
+            Self::Windows1252 => bytes
+                .iter()
+                .map(|byte| match byte {
+                    0x80..=0x9f => char::from_u32(WINDOWS_1252_HIGH[(byte - 0x80) as usize] as u32)
+                        .unwrap_or(char::REPLACEMENT_CHARACTER),
+                    _ => *byte as char,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Charset and length of a leading byte order mark
+pub fn bom(bytes: &[u8]) -> Option<(Charset, usize)> {
+    if bytes.starts_with(b"\xef\xbb\xbf") {
+        Some((Charset::Utf8, 3))
+    } else if bytes.starts_with(b"\xff\xfe") {
+        Some((Charset::Utf16Le, 2))
+    } else if bytes.starts_with(b"\xfe\xff") {
+        Some((Charset::Utf16Be, 2))
+    } else {
+        None
+    }
+}
+
+/// The `charset` parameter of a `Content-Type` value, when it names a supported charset
+pub fn declared(content_type: &str) -> Option<Charset> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim().eq_ignore_ascii_case("charset").then(|| Charset::from_label(value)).flatten()
+    })
+}
+
+/// Media types whose bodies are text even without a declared charset
+fn is_textual(content_type: &str) -> bool {
+    let media_type = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    media_type.starts_with("text/")
+        || media_type.ends_with("/json")
+        || media_type.ends_with("+json")
+        || media_type.ends_with("/xml")
+        || media_type.ends_with("+xml")
+        || media_type == "application/x-www-form-urlencoded"
+        || media_type == "application/javascript"
+}
+
+/// A body normalized to UTF-8
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoded {
+    pub text: String,
+    pub charset: Charset,
+    /// The text differs from the original bytes (other charset, BOM or invalid sequences)
+    pub transcoded: bool,
+}
+
+/// Decode a text body, or None when it looks binary (nothing says it's text,
+/// it isn't UTF-8, or it starts with a binary file signature)
+pub fn decode(bytes: &[u8], content_type: Option<&str>) -> Option<Decoded> {
+    let (charset, skip) = match bom(bytes) {
+        Some(found) => found,
+        None => {
+            let charset = match content_type.and_then(declared) {
+                Some(charset) => charset,
+                None if preview::sniff(bytes).is_some() => return None,
+                None if std::str::from_utf8(bytes).is_ok() => Charset::Utf8,
+                None if content_type.is_some_and(is_textual) => Charset::Windows1252,
+                None => return None,
+            };
+            (charset, 0)
+        }
+    };
+    let text = charset.decode(&bytes[skip..]);
+    Some(Decoded {
+        transcoded: text.as_bytes() != bytes,
+        text,
+        charset,
+    })
+}
@@ -0,0 +1,339 @@
+//! Body compression at rest
+//! A webhook whose config sets `compression` has bodies (`COMPRESSED_COLUMNS`)
+//! of at least `min_bytes` compressed with the runtime's `CompressionStream`
+//! before they are stored; verbose JSON shrinks several times over, even after
+//! the base64 the text columns need. Compressed values read
+//! `cmp:v1:{algorithm}:{base64}` and every capture store is wrapped to
+//! decompress them when read, so captures stored before (or after) compression
+//! was switched on stay readable as they are, and turning it off needs nothing.
+//! A body that would not get smaller is stored as it is, behind `ESCAPE_PREFIX`
+//! when it starts like a compressed (or escaped) value, so a sender can't have
+//! a body of theirs inflated on read; a value that fails to inflate, or would
+//! inflate past `MAX_INFLATED_BYTES`, is returned as stored. Compression runs
+//! before encryption (see `encryption.rs`), whose ciphertext would not shrink.
+//!
+//! `size_bytes`, and with it storage quotas (see `usage.rs`), stays the size
+//! of the body as received. Compressed bodies can't be matched in SQL, so
+//! erasure searches decompress while scanning, as they do for encryption.
+
+use crate::config::{Compression, CompressionAlgorithm};
+use crate::erasure;
+use crate::storage::{
+    CaptureRecord, DailyCount, InboxQuery, RequestQuery, ShopifyCount, SortColumn, Storage, StoredRequest,
+    WebhookVolume,
+};
+use crate::webcrypto;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use js_sys::{Array, Function, Reflect, Uint8Array};
+use wasm_bindgen::{JsCast, JsValue};
+use worker::*;
+
+/// Marks a compressed column value
+pub const PREFIX: &str = "cmp:v1:";
+
+/// Marks an uncompressed column value that would otherwise read as compressed
+pub const ESCAPE_PREFIX: &str = "cmp:raw:";
+
+/// Most bytes a compressed value inflates to; stored bodies are far smaller
+pub const MAX_INFLATED_BYTES: usize = 8 * 1024 * 1024;
+
+/// Capture columns stored compressed
+pub const COMPRESSED_COLUMNS: [&str; 3] = ["data", "canonical_data", "original_body"];
+
+/// Captures decompressed per page while searching compressed bodies
+const SCAN_PAGE: u32 = 500;
+
+/// Column value for compressed bytes
+pub fn envelope(algorithm: CompressionAlgorithm, compressed: &[u8]) -> String {
+    format!("{}{}:{}", PREFIX, algorithm.as_str(), BASE64.encode(compressed))
+}
+
+/// Algorithm and compressed bytes of a compressed value; None for anything else
+pub fn parse_envelope(value: &str) -> Option<(CompressionAlgorithm, Vec<u8>)> {
+    let (algorithm, compressed) = value.strip_prefix(PREFIX)?.split_once(':')?;
+    Some((CompressionAlgorithm::parse(algorithm)?, BASE64.decode(compressed).ok()?))
+}
+
+/// Whether a value is worth compressing
+pub fn eligible(config: &Compression, value: &str) -> bool {
+    value.len() >= config.min_bytes.max(1)
+}
+
+/// An uncompressed value as stored, escaped when it starts like a compressed or escaped one
+pub fn escape(value: &str) -> Option<String> {
+    (value.starts_with(PREFIX) || value.starts_with(ESCAPE_PREFIX)).then(|| format!("{}{}", ESCAPE_PREFIX, value))
+}
+
+fn escape_in_place(value: &mut String) {
+    if let Some(escaped) = escape(value) {
+        *value = escaped;
+    }
+}
+
+fn escape_optional(value: &mut Option<String>) {
+    if let Some(value) = value {
+        escape_in_place(value);
+    }
+}
+
+/// A record as stored without compression
+pub fn escape_record(record: &CaptureRecord) -> CaptureRecord {
+    let mut stored = record.clone();
+    escape_in_place(&mut stored.data);
+    escape_optional(&mut stored.canonical_data);
+    escape_optional(&mut stored.original_body);
+    stored.storage_form = true;
+    stored
+}
+
+fn constructor(name: &str) -> Result<Function> {
+    Reflect::get(&js_sys::global(), &JsValue::from_str(name))?
+        .dyn_into()
+        .map_err(|_| Error::RustError(format!("{} is not available", name)))
+}
+
+/// Run bytes through a `CompressionStream` or `DecompressionStream`, giving up past `limit` output bytes
+async fn transform(stream: &str, algorithm: CompressionAlgorithm, input: &[u8], limit: usize) -> Result<Vec<u8>> {
+    let format = Array::of1(&JsValue::from_str(algorithm.as_str()));
+    let transformer = Reflect::construct(&constructor(stream)?, &format)?;
+    let parts = Array::of1(&Uint8Array::from(input).into());
+    let blob = Reflect::construct(&constructor("Blob")?, &Array::of1(&parts))?;
+    let readable = call(&blob, "stream", &[])?;
+    let output = call(&readable, "pipeThrough", &[transformer])?;
+    let reader = call(&output, "getReader", &[])?;
+    let mut bytes = Vec::new();
+    loop {
+        let chunk = webcrypto::call_async(&reader, "read", &[]).await?;
+        if Reflect::get(&chunk, &JsValue::from_str("done"))?.is_truthy() {
+            return Ok(bytes);
+        }
+        let value = Uint8Array::new(&Reflect::get(&chunk, &JsValue::from_str("value"))?);
+        if bytes.len() + value.length() as usize > limit {
+            // Stop the stream rather than inflate the rest
+            let _ = call(&reader, "cancel", &[]);
+            return Err(Error::RustError(format!("{} output exceeds {} bytes", stream, limit)));
+        }
+        bytes.extend(value.to_vec());
+    }
+}
+
+fn call(target: &JsValue, method: &str, args: &[JsValue]) -> Result<JsValue> {
+    let function: Function = Reflect::get(target, &JsValue::from_str(method))?
+        .dyn_into()
+        .map_err(|_| Error::RustError(format!("{} is not a function", method)))?;
+    let args: Array = args.iter().collect();
+    Ok(function.apply(target, &args)?)
+}
+
+pub async fn compress(algorithm: CompressionAlgorithm, input: &[u8]) -> Result<Vec<u8>> {
+    transform("CompressionStream", algorithm, input, usize::MAX).await
+}
+
+/// Inflate at most `MAX_INFLATED_BYTES`
+pub async fn decompress(algorithm: CompressionAlgorithm, input: &[u8]) -> Result<Vec<u8>> {
+    transform("DecompressionStream", algorithm, input, MAX_INFLATED_BYTES).await
+}
+
+async fn compress_value(config: &Compression, value: &mut String) -> Result<()> {
+    if eligible(config, value) {
+        let compressed = envelope(config.algorithm, &compress(config.algorithm, value.as_bytes()).await?);
+        if compressed.len() < value.len() {
+            *value = compressed;
+            return Ok(());
+        }
+    }
+    escape_in_place(value);
+    Ok(())
+}
+
+async fn compress_optional(config: &Compression, value: &mut Option<String>) -> Result<()> {
+    if let Some(value) = value {
+        compress_value(config, value).await?;
+    }
+    Ok(())
+}
+
+/// The record as it should be stored under a webhook's `compression`; None when the
+/// store should escape it as it is (no compression configured, or it failed)
+pub async fn for_storage(config: Option<&Compression>, record: &CaptureRecord) -> Option<CaptureRecord> {
+    let config = config?;
+    let mut stored = record.clone();
+    let compressed = async {
+        compress_value(config, &mut stored.data).await?;
+        compress_optional(config, &mut stored.canonical_data).await?;
+        compress_optional(config, &mut stored.original_body).await
+    };
+    match compressed.await {
+        Ok(()) => Some(CaptureRecord { storage_form: true, ..stored }),
+        Err(e) => {
+            log_warn!("⚠️  Failed to compress capture {}, storing it uncompressed: {:?}", record.id, e);
+            None
+        }
+    }
+}
+
+/// A stored value as it was received; one that doesn't inflate is left as stored
+async fn inflate(id: &str, value: &mut String) {
+    if let Some(raw) = value.strip_prefix(ESCAPE_PREFIX) {
+        *value = raw.to_string();
+        return;
+    }
+    let Some((algorithm, compressed)) = parse_envelope(value) else {
+        return;
+    };
+    let inflated = decompress(algorithm, &compressed)
+        .await
+        .and_then(|bytes| String::from_utf8(bytes).map_err(|_| Error::RustError("not UTF-8".to_string())));
+    match inflated {
+        Ok(inflated) => *value = inflated,
+        Err(e) => log_warn!("⚠️  Failed to decompress a value of capture {}, returning it as stored: {:?}", id, e),
+    }
+}
+
+async fn inflate_optional(id: &str, value: &mut Option<String>) {
+    if let Some(value) = value {
+        inflate(id, value).await;
+    }
+}
+
+/// `storage`, decompressing what it reads
+pub fn wrap(storage: Box<dyn Storage>) -> Box<dyn Storage> {
+    Box::new(CompressedStorage { inner: storage })
+}
+
+/// Capture store that decompresses bodies on read; writes not yet in storage form
+/// (see `for_storage`) are escaped
+pub struct CompressedStorage {
+    inner: Box<dyn Storage>,
+}
+
+impl CompressedStorage {
+    async fn decompress(&self, mut request: StoredRequest) -> StoredRequest {
+        inflate(&request.id, &mut request.data).await;
+        inflate_optional(&request.id, &mut request.canonical_data).await;
+        inflate_optional(&request.id, &mut request.original_body).await;
+        request
+    }
+
+    async fn decompress_all(&self, requests: Vec<StoredRequest>) -> Result<Vec<StoredRequest>> {
+        let mut decompressed = Vec::with_capacity(requests.len());
+        for request in requests {
+            decompressed.push(self.decompress(request).await);
+        }
+        Ok(decompressed)
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Storage for CompressedStorage {
+    async fn insert_capture(&self, record: &CaptureRecord) -> Result<bool> {
+        if record.storage_form {
+            return self.inner.insert_capture(record).await;
+        }
+        self.inner.insert_capture(&escape_record(record)).await
+    }
+
+    async fn insert_captures(&self, records: &[CaptureRecord]) -> Result<()> {
+        let stored: Vec<CaptureRecord> = records
+            .iter()
+            .map(|record| if record.storage_form { record.clone() } else { escape_record(record) })
+            .collect();
+        self.inner.insert_captures(&stored).await
+    }
+
+    async fn list_requests(&self, query: &RequestQuery) -> Result<Vec<StoredRequest>> {
+        self.decompress_all(self.inner.list_requests(query).await?).await
+    }
+
+    async fn inbox_fetch(&self, query: &InboxQuery) -> Result<Vec<StoredRequest>> {
+        self.decompress_all(self.inner.inbox_fetch(query).await?).await
+    }
+
+    async fn inbox_ack(&self, webhook_id: &str, ids: &[String], now_ms: i64) -> Result<u64> {
+        self.inner.inbox_ack(webhook_id, ids, now_ms).await
+    }
+
+    async fn record_repeat(&self, webhook_id: &str, id: &str, seen_at_ms: i64) -> Result<bool> {
+        self.inner.record_repeat(webhook_id, id, seen_at_ms).await
+    }
+
+    async fn purge(&self, webhook_id: &str, event_type: Option<&str>, before: i64, keep: &[String]) -> Result<u64> {
+        self.inner.purge(webhook_id, event_type, before, keep).await
+    }
+
+    async fn strip_payloads(&self, webhook_id: &str, before: i64, keep: &[String]) -> Result<u64> {
+        self.inner.strip_payloads(webhook_id, before, keep).await
+    }
+
+    /// Compressed bodies cannot be searched in SQL, so every capture is decompressed and matched here
+    async fn find_containing(&self, webhook_id: &str, needle: &str, limit: u32) -> Result<Vec<StoredRequest>> {
+        let mut found = Vec::new();
+        let mut offset = 0;
+        loop {
+            let query = RequestQuery {
+                webhook_id: webhook_id.to_string(),
+                limit: SCAN_PAGE,
+                offset,
+                since: None,
+                until: None,
+                sort: SortColumn::ReceivedAt,
+                ascending: true,
+                filters: Vec::new(),
+            };
+            let page = self.list_requests(&query).await?;
+            let full = page.len() == SCAN_PAGE as usize;
+            for request in page {
+                if !erasure::matched_columns(&request, needle).is_empty() {
+                    found.push(request);
+                    if found.len() >= limit as usize {
+                        return Ok(found);
+                    }
+                }
+            }
+            if !full {
+                return Ok(found);
+            }
+            offset += SCAN_PAGE;
+        }
+    }
+
+    async fn delete_captures(&self, webhook_id: &str, ids: &[String]) -> Result<u64> {
+        self.inner.delete_captures(webhook_id, ids).await
+    }
+
+    /// Rewritten (redacted) payloads are stored uncompressed
+    async fn replace_payloads(&self, requests: &[StoredRequest]) -> Result<u64> {
+        let mut stored = requests.to_vec();
+        for request in &mut stored {
+            escape_in_place(&mut request.data);
+            escape_optional(&mut request.canonical_data);
+            escape_optional(&mut request.original_body);
+        }
+        self.inner.replace_payloads(&stored).await
+    }
+
+    async fn daily_counts(&self, webhook_id: &str, before: i64) -> Result<Vec<DailyCount>> {
+        self.inner.daily_counts(webhook_id, before).await
+    }
+
+    async fn shopify_counts(&self, webhook_id: &str, since: i64, until: i64) -> Result<Vec<ShopifyCount>> {
+        self.inner.shopify_counts(webhook_id, since, until).await
+    }
+
+    async fn webhook_volumes(&self, since: i64) -> Result<Vec<WebhookVolume>> {
+        self.inner.webhook_volumes(since).await
+    }
+
+    async fn count_received(&self, webhook_id: &str, event_type: Option<&str>, since: i64, until: Option<i64>) -> Result<u64> {
+        self.inner.count_received(webhook_id, event_type, since, until).await
+    }
+
+    async fn maintain(&self, now: i64) -> Result<()> {
+        self.inner.maintain(now).await
+    }
+
+    fn bookmark(&self) -> Option<String> {
+        self.inner.bookmark()
+    }
+}
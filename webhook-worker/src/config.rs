@@ -0,0 +1,1235 @@
+//! Per-webhook configuration
+//! Settings live as a JSON document in `webhooks.config` with a `config_version`
+//! counter for optimistic concurrency; the webhook secret is kept in its own
+//! column so configs can be exported without it. Ingestion reads settings through
+//! a KV cache (`webhook:config:{id}`) that every write invalidates.
+
+use crate::config_history;
+use crate::directory::Directory;
+use crate::environments::{self, Environment};
+use crate::kv::KvBackend;
+use crate::replay::{self, Overrides};
+use crate::residency::{self, Jurisdiction};
+use crate::script::Script;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// KV key prefix for cached settings
+const CACHE_PREFIX: &str = "webhook:config:";
+
+/// Declarative per-webhook configuration; unknown fields are ignored and
+/// missing ones take their defaults
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// Reject captures that do not carry a valid signed URL (`exp` + `sig`)
+    pub require_signed_urls: bool,
+    /// Provider signature verification (None disables it)
+    pub signature: Option<SignatureConfig>,
+    /// Queue captures for local relay agents (`/relay/{uuid}` WebSocket)
+    pub relay: bool,
+    /// Where to read the event type (default: well-known event headers)
+    pub event_type: Option<FieldSource>,
+    /// Where to read the dedup key (default: well-known idempotency headers)
+    pub idempotency_key: Option<FieldSource>,
+    /// Also deduplicate deliveries without a dedup key, by method and body (see `dedup.rs`)
+    pub dedup_bodies: bool,
+    /// Delete captures older than this many days (the global retention still applies)
+    pub retention_days: Option<u32>,
+    /// Downsample old captures instead of a single cutoff (see `retention.rs`)
+    pub retention_tiers: Option<RetentionTiers>,
+    /// Per-event-type handling; the first matching rule applies
+    pub routes: Vec<EventRoute>,
+    /// Delivery SLAs checked by the scheduled handler
+    pub expectations: Vec<Expectation>,
+    /// Alert on hourly volume far from the rolling baseline (None disables it)
+    pub anomaly: Option<AnomalyConfig>,
+    /// Hook script run on every delivery (see `script.rs`)
+    pub script: Option<String>,
+    /// Sign external forwards per the Standard Webhooks spec (None forwards them as received)
+    pub forward_signing: Option<ForwardSigning>,
+    /// Compare verified Stripe events with the object's current state (see `stripe.rs`)
+    pub stripe_cross_check: bool,
+    /// Answer Twilio voice and messaging webhooks with TwiML (see `twiml.rs`)
+    pub twiml: Option<TwimlConfig>,
+    /// Answer Slack slash commands and interactions (see `slack.rs`)
+    pub slack: Option<SlackConfig>,
+    /// Complete the token exchange for OAuth callbacks (see `oauth.rs`)
+    pub oauth: Option<OauthClient>,
+    /// Track payload shapes per event type and report new ones (see `shapes.rs`)
+    pub shapes: Option<ShapeTracking>,
+    /// Delay every answer by a duration drawn from this distribution (see `latency.rs`)
+    pub latency_profile: Option<LatencyProfile>,
+    /// Split forwarded deliveries between two targets and compare them (see `split.rs`)
+    pub split: Option<TrafficSplit>,
+    /// Mirror deliveries to a shadow target and diff its answers with the primary's (see `split.rs`)
+    pub shadow: Option<ShadowForwarding>,
+    /// Named edit-and-resend settings, invoked by name (see `replay.rs`)
+    pub replay_recipes: BTreeMap<String, ReplayRecipe>,
+    /// Refuse captures and uploads once the webhook stores this much (see `usage.rs`)
+    pub storage_quota: Option<StorageQuota>,
+    /// Store large bodies compressed (see `compression.rs`)
+    pub compression: Option<Compression>,
+    /// Recognise repeated identical deliveries as heartbeats, optionally folded into one capture
+    /// (see `heartbeat.rs`)
+    pub heartbeat: Option<Heartbeat>,
+    /// Where deliveries carry the correlation ID traced across the project's webhooks (see `api/trace.rs`)
+    pub correlation_id: Option<FieldSource>,
+    /// How related captures are grouped into sessions (see `sessions.rs`)
+    pub sessions: Option<Sessions>,
+    /// Answers picked by the delivery's method, event type, headers and body fields
+    pub response_rules: Option<ResponseRules>,
+}
+
+/// Handling for deliveries of one event type
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventRoute {
+    /// Event type to match: exact (`push`), prefix (`pull_request*`) or `*` for any
+    pub event_type: String,
+    /// Forward matching deliveries here (in addition to the environment's target)
+    #[serde(default)]
+    pub forward_url: Option<String>,
+    /// Answer matching deliveries with this instead of the default JSON
+    #[serde(default)]
+    pub response: Option<CustomResponse>,
+    /// Delete matching captures sooner than the webhook's retention
+    #[serde(default)]
+    pub retention_days: Option<u32>,
+}
+
+/// A delivery SLA: at least `min_count` captures of an event type in every
+/// trailing `window_hours`, with a notification when that stops (and starts
+/// again) being true
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Expectation {
+    /// Event type pattern, as in `EventRoute::event_type`
+    pub event_type: String,
+    #[serde(default = "default_min_count")]
+    pub min_count: u32,
+    pub window_hours: u32,
+    /// Receives a JSON POST on violation and on recovery
+    pub notify_url: String,
+}
+
+fn default_min_count() -> u32 {
+    1
+}
+
+/// Longest expectation window (the global retention bounds what can be counted)
+pub const MAX_EXPECTATION_WINDOW_HOURS: u32 = 30 * 24;
+
+/// Volume anomaly detection: an hour's capture count compared with an EWMA
+/// baseline of previous hours
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnomalyConfig {
+    /// Alert when an hour is this many times above (spike) or below (drought) the baseline
+    #[serde(default = "default_anomaly_factor")]
+    pub factor: f64,
+    /// Weight of the newest hour in the baseline (0 < alpha <= 1)
+    #[serde(default = "default_anomaly_alpha")]
+    pub alpha: f64,
+    /// Receives a JSON POST when an anomaly starts, changes kind and ends
+    pub notify_url: String,
+}
+
+fn default_anomaly_factor() -> f64 {
+    3.0
+}
+
+fn default_anomaly_alpha() -> f64 {
+    0.2
+}
+
+/// Tiered retention: full captures, then metadata only, then daily aggregates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionTiers {
+    /// Keep bodies and headers this many days
+    #[serde(default = "default_full_days")]
+    pub full_days: u32,
+    /// Keep the capture rows (indexed metadata) this many days
+    #[serde(default = "default_metadata_days")]
+    pub metadata_days: u32,
+    /// Roll deleted rows into `capture_daily` counts, kept forever
+    #[serde(default = "default_true")]
+    pub aggregates: bool,
+}
+
+fn default_full_days() -> u32 {
+    7
+}
+
+fn default_metadata_days() -> u32 {
+    30
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Expectation {
+    /// Stable identity of an expectation across config edits that keep it unchanged
+    pub fn key(&self) -> String {
+        format!("{}:{}/{}h", self.event_type, self.min_count, self.window_hours)
+    }
+}
+
+/// Event type pattern match: exact (`push`), prefix (`pull_request*`) or `*` for any
+pub fn event_type_matches(pattern: &str, event_type: Option<&str>) -> bool {
+    match (pattern.strip_suffix('*'), event_type) {
+        (Some(""), _) => true,
+        (Some(prefix), Some(event_type)) => event_type.starts_with(prefix),
+        (None, Some(event_type)) => event_type == pattern,
+        (_, None) => false,
+    }
+}
+
+impl EventRoute {
+    pub fn matches(&self, event_type: Option<&str>) -> bool {
+        event_type_matches(&self.event_type, event_type)
+    }
+}
+
+/// A fixed response returned to the sender
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CustomResponse {
+    #[serde(default = "default_response_status")]
+    pub status: u16,
+    #[serde(default)]
+    pub body: String,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    /// Extra response headers
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+fn default_response_status() -> u16 {
+    200
+}
+
+impl CustomResponse {
+    pub fn to_response(&self) -> Result<Response> {
+        // 204 and friends must not carry a body, not even an empty one
+        let mut response = if self.body.is_empty() && matches!(self.status, 204 | 205 | 304) {
+            Response::empty()?.with_status(self.status)
+        } else {
+            Response::ok(self.body.clone())?.with_status(self.status)
+        };
+        for (name, value) in &self.headers {
+            response.headers_mut().set(name, value)?;
+        }
+        let content_type = self.content_type.as_deref().unwrap_or("text/plain");
+        response.headers_mut().set("Content-Type", content_type)?;
+        Ok(response)
+    }
+
+    /// Why the response can't be sent: a status outside 100-599 or a malformed header
+    pub fn problem(&self) -> Option<String> {
+        if !(100..=599).contains(&self.status) {
+            return Some(format!("status {} is not between 100 and 599", self.status));
+        }
+        let malformed = |name: &String, value: &String| {
+            name.is_empty()
+                || !name.bytes().all(|byte| byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte))
+                || value.contains(['\r', '\n'])
+        };
+        self.headers
+            .iter()
+            .find(|(name, value)| malformed(name, value))
+            .map(|(name, _)| format!("header {:?} is malformed", name))
+    }
+}
+
+/// Most response rules one webhook may have
+pub const MAX_RESPONSE_RULES: usize = 50;
+
+/// Conditional answers for mocking: the first matching rule's response, else the default
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponseRules {
+    /// Checked in order
+    #[serde(default)]
+    pub rules: Vec<ResponseRule>,
+    /// Answer when no rule matches, unless a route, a provider answer (TwiML, Slack, SCIM) or the
+    /// capture summary applies
+    #[serde(default)]
+    pub default: Option<CustomResponse>,
+}
+
+/// A response used when every condition given holds; a rule without conditions matches everything
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponseRule {
+    /// HTTP method (case-insensitive)
+    #[serde(default)]
+    pub method: Option<String>,
+    /// Event type pattern, as in `EventRoute::event_type`
+    #[serde(default)]
+    pub event_type: Option<String>,
+    /// Header and body field conditions
+    #[serde(default)]
+    pub when: Vec<Condition>,
+    pub response: CustomResponse,
+}
+
+/// A header or body field that must be present, or equal `equals`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Condition {
+    pub field: FieldSource,
+    #[serde(default)]
+    pub equals: Option<String>,
+}
+
+impl ResponseRule {
+    /// Whether a delivery (lowercase-keyed headers, raw body or query parameters as JSON) matches
+    pub fn matches(
+        &self,
+        method: &str,
+        event_type: Option<&str>,
+        headers: &HashMap<String, String>,
+        body: &str,
+    ) -> bool {
+        self.method.as_deref().is_none_or(|expected| expected.eq_ignore_ascii_case(method))
+            && self.event_type.as_deref().is_none_or(|pattern| event_type_matches(pattern, event_type))
+            && self.when.iter().all(|condition| match condition.field.extract(headers, body) {
+                Some(value) => condition.equals.as_ref().is_none_or(|expected| *expected == value),
+                None => false,
+            })
+    }
+}
+
+/// A value taken from a delivery: `{"header": "x-github-event"}` or `{"body": "data.object.id"}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldSource {
+    /// Header name (case-insensitive)
+    Header(String),
+    /// Dot-separated path into a JSON body
+    Body(String),
+}
+
+impl FieldSource {
+    /// Extract the value from a lowercase-keyed header map and the raw body
+    pub fn extract(&self, headers: &HashMap<String, String>, body: &str) -> Option<String> {
+        let value = match self {
+            Self::Header(name) => headers.get(&name.to_ascii_lowercase())?.trim().to_string(),
+            Self::Body(path) => {
+                let document: serde_json::Value = serde_json::from_str(body).ok()?;
+                match path.split('.').try_fold(&document, |value, key| value.get(key))? {
+                    serde_json::Value::String(text) => text.clone(),
+                    serde_json::Value::Number(number) => number.to_string(),
+                    _ => return None,
+                }
+            }
+        };
+        Some(value).filter(|value| !value.is_empty())
+    }
+}
+
+/// Placeholder shown instead of literal secrets
+pub const REDACTED: &str = "********";
+
+impl WebhookConfig {
+    /// Copy with literal secrets masked (`env:` references are kept)
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        if let Some(signature) = &mut config.signature {
+            if !signature.secret.starts_with(SECRET_ENV_PREFIX) {
+                signature.secret = REDACTED.to_string();
+            }
+            if let Some(previous) = &mut signature.previous_secret {
+                if !previous.starts_with(SECRET_ENV_PREFIX) {
+                    *previous = REDACTED.to_string();
+                }
+            }
+        }
+        if let Some(signing) = &mut config.forward_signing {
+            if !signing.secret.starts_with(SECRET_ENV_PREFIX) {
+                signing.secret = REDACTED.to_string();
+            }
+        }
+        if let Some(client) = &mut config.oauth {
+            if !client.client_secret.starts_with(SECRET_ENV_PREFIX) {
+                client.client_secret = REDACTED.to_string();
+            }
+        }
+        config
+    }
+
+    /// First problem that makes the config unusable
+    pub fn validate(&self) -> Option<String> {
+        for route in &self.routes {
+            if route.event_type.is_empty() {
+                return Some("Route event_type must not be empty".to_string());
+            }
+            if route
+                .forward_url
+                .as_deref()
+                .is_some_and(|url| !environments::is_valid_forward_target(url))
+            {
+                return Some(format!("Invalid forward_url for route {}", route.event_type));
+            }
+            if route
+                .response
+                .as_ref()
+                .is_some_and(|response| !(100..=599).contains(&response.status))
+            {
+                return Some(format!("Invalid response status for route {}", route.event_type));
+            }
+            if let Some(problem) = route.response.as_ref().and_then(CustomResponse::problem) {
+                return Some(format!("Invalid response for route {}: {}", route.event_type, problem));
+            }
+        }
+        for expectation in &self.expectations {
+            if expectation.event_type.is_empty() {
+                return Some("Expectation event_type must not be empty".to_string());
+            }
+            if expectation.min_count == 0 || !(1..=MAX_EXPECTATION_WINDOW_HOURS).contains(&expectation.window_hours) {
+                return Some(format!(
+                    "Expectation for {} needs min_count >= 1 and window_hours between 1 and {}",
+                    expectation.event_type, MAX_EXPECTATION_WINDOW_HOURS
+                ));
+            }
+            if !environments::is_valid_forward_url(&expectation.notify_url) {
+                return Some(format!("Invalid notify_url for expectation {}", expectation.event_type));
+            }
+        }
+        if let Some(anomaly) = &self.anomaly {
+            let valid = anomaly.factor > 1.0 && anomaly.alpha > 0.0 && anomaly.alpha <= 1.0;
+            if !valid {
+                return Some("Anomaly detection needs factor > 1 and alpha in (0, 1]".to_string());
+            }
+            if !environments::is_valid_forward_url(&anomaly.notify_url) {
+                return Some("Invalid notify_url for anomaly detection".to_string());
+            }
+        }
+        if let Some(notify_url) = self.shapes.as_ref().and_then(|shapes| shapes.notify_url.as_ref()) {
+            if !environments::is_valid_forward_url(notify_url) {
+                return Some("Invalid notify_url for shape tracking".to_string());
+            }
+        }
+        if let Some(split) = &self.split {
+            if !environments::is_valid_forward_target(&split.a) || !environments::is_valid_forward_target(&split.b) {
+                return Some("Traffic split needs valid a and b targets".to_string());
+            }
+            if split.a == split.b || split.b_percent > 100 || split.compare_percent > 100 {
+                return Some("Traffic split needs two different targets and percentages up to 100".to_string());
+            }
+        }
+        if let Some(shadow) = &self.shadow {
+            let valid = environments::is_valid_forward_target(&shadow.primary)
+                && environments::is_valid_forward_url(&shadow.shadow)
+                && shadow.primary != shadow.shadow;
+            if !valid {
+                return Some("Shadow forwarding needs a primary target and a different shadow URL".to_string());
+            }
+        }
+        if self.replay_recipes.len() > replay::MAX_RECIPES {
+            return Some(format!("At most {} replay recipes", replay::MAX_RECIPES));
+        }
+        for (name, recipe) in &self.replay_recipes {
+            if let Some(problem) = replay::validate_recipe(name, recipe) {
+                return Some(format!("Invalid replay recipe {:?}: {}", name, problem));
+            }
+        }
+        if self.storage_quota.as_ref().is_some_and(|quota| quota.max_bytes == 0) {
+            return Some("storage_quota.max_bytes must be at least 1".to_string());
+        }
+        if let Some(sessions) = &self.sessions {
+            if sessions.gap_seconds == 0 {
+                return Some("sessions.gap_seconds must be at least 1".to_string());
+            }
+            if sessions.keys.len() > MAX_SESSION_KEYS {
+                return Some(format!("At most {} session keys", MAX_SESSION_KEYS));
+            }
+        }
+        if let Some(rules) = &self.response_rules {
+            if rules.rules.len() > MAX_RESPONSE_RULES {
+                return Some(format!("At most {} response rules", MAX_RESPONSE_RULES));
+            }
+            for (index, rule) in rules.rules.iter().enumerate() {
+                let empty = |field: &FieldSource| match field {
+                    FieldSource::Header(name) | FieldSource::Body(name) => name.is_empty(),
+                };
+                if rule.event_type.as_deref() == Some("") || rule.when.iter().any(|condition| empty(&condition.field)) {
+                    return Some(format!("Response rule {} has an empty condition", index));
+                }
+                if let Some(problem) = rule.response.problem() {
+                    return Some(format!("Invalid response for response rule {}: {}", index, problem));
+                }
+            }
+            if let Some(problem) = rules.default.as_ref().and_then(CustomResponse::problem) {
+                return Some(format!("Invalid default response: {}", problem));
+            }
+        }
+        if let Some(heartbeat) = &self.heartbeat {
+            if heartbeat.min_repeats < 2 {
+                return Some("heartbeat.min_repeats must be at least 2".to_string());
+            }
+            if !(0.0..1.0).contains(&heartbeat.tolerance) {
+                return Some("heartbeat.tolerance must be at least 0 and below 1".to_string());
+            }
+        }
+        if let Some(profile) = &self.latency_profile {
+            if let Some(problem) = profile.validate() {
+                return Some(format!("Invalid latency_profile: {}", problem));
+            }
+        }
+        if let Some(tiers) = &self.retention_tiers {
+            if tiers.full_days == 0 || tiers.metadata_days < tiers.full_days {
+                return Some("Retention tiers need full_days >= 1 and metadata_days >= full_days".to_string());
+            }
+        }
+        if let Some(signature) = &self.signature {
+            let hmac_header = signature.hmac.as_ref().map(|scheme| scheme.header.trim());
+            if signature.provider == SignatureProvider::Hmac && hmac_header.is_none_or(str::is_empty) {
+                return Some("The hmac signature provider needs hmac.header".to_string());
+            }
+            let paypal_app = signature.paypal.as_ref();
+            let paypal_complete = paypal_app.is_some_and(|app| !app.client_id.is_empty() && !app.webhook_id.is_empty());
+            if signature.provider == SignatureProvider::Paypal && !paypal_complete {
+                return Some("The paypal signature provider needs paypal.client_id and paypal.webhook_id".to_string());
+            }
+        }
+        if let Some(signing) = &self.forward_signing {
+            if signing.secret.is_empty() || signing.retries > MAX_FORWARD_RETRIES {
+                return Some(format!(
+                    "Forward signing needs a secret and at most {} retries",
+                    MAX_FORWARD_RETRIES
+                ));
+            }
+        }
+        if let Some(client) = &self.oauth {
+            if !client.token_url.starts_with("https://") || client.client_id.is_empty() {
+                return Some("OAuth token exchange needs an https token_url and a client_id".to_string());
+            }
+        }
+        if let Some(twiml) = &self.twiml {
+            let templates = [&twiml.voice, &twiml.messaging];
+            if templates.into_iter().flatten().any(|template| !template.trim_start().starts_with('<')) {
+                return Some("TwiML templates must be XML (<Response>...</Response>)".to_string());
+            }
+        }
+        if let Some(slack) = &self.slack {
+            let templates = [&slack.response, &slack.follow_up];
+            let empty = HashMap::new();
+            let is_message = |template: &String| {
+                serde_json::from_str::<serde_json::Value>(&crate::slack::render(template, &empty)).is_ok()
+            };
+            if !templates.into_iter().flatten().all(is_message) {
+                return Some("Slack templates must be JSON messages".to_string());
+            }
+        }
+        if let Some(Err(e)) = self.script.as_deref().map(Script::parse) {
+            return Some(format!("Invalid script: {}", e));
+        }
+        None
+    }
+
+    /// First route matching an event type
+    pub fn route_for(&self, event_type: Option<&str>) -> Option<&EventRoute> {
+        self.routes.iter().find(|route| route.matches(event_type))
+    }
+
+    /// Put back secrets that arrive masked (from an earlier `redacted()` export)
+    pub fn restore_secrets(&mut self, current: &WebhookConfig) {
+        if let (Some(signature), Some(existing)) = (&mut self.signature, &current.signature) {
+            if signature.secret == REDACTED {
+                signature.secret = existing.secret.clone();
+            }
+            if signature.previous_secret.as_deref() == Some(REDACTED) {
+                signature.previous_secret = existing.previous_secret.clone();
+            }
+        }
+        if let (Some(signing), Some(existing)) = (&mut self.forward_signing, &current.forward_signing) {
+            if signing.secret == REDACTED {
+                signing.secret = existing.secret.clone();
+            }
+        }
+        if let (Some(client), Some(existing)) = (&mut self.oauth, &current.oauth) {
+            if client.client_secret == REDACTED {
+                client.client_secret = existing.client_secret.clone();
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureProvider {
+    Stripe,
+    Slack,
+    Github,
+    Shopify,
+    Twilio,
+    Svix,
+    /// The Standard Webhooks spec (`webhook-id`, `webhook-timestamp`, `webhook-signature`)
+    #[serde(rename = "standard_webhooks")]
+    StandardWebhooks,
+    /// Checked through PayPal's verification API with the app in `SignatureConfig::paypal`
+    Paypal,
+    Adyen,
+    Square,
+    /// Any HMAC scheme described by `SignatureConfig::hmac`
+    Hmac,
+}
+
+/// Hash of a generic HMAC signature
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HmacAlgorithm {
+    #[default]
+    Sha256,
+    Sha1,
+    Sha512,
+}
+
+/// How a generic HMAC signature is written in its header
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureEncoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+/// Where and how a provider without a dedicated verifier signs deliveries
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HmacScheme {
+    /// Header carrying the signature
+    pub header: String,
+    #[serde(default)]
+    pub algorithm: HmacAlgorithm,
+    #[serde(default)]
+    pub encoding: SignatureEncoding,
+    /// Stripped from the header value before decoding (e.g. `sha256=`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    /// Header with the Unix timestamp signed as `{timestamp}.{body}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp_header: Option<String>,
+}
+
+/// The PayPal REST app that asks PayPal to verify deliveries
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaypalApp {
+    pub client_id: String,
+    /// ID of the webhook as registered with PayPal
+    pub webhook_id: String,
+    /// Use the sandbox API
+    #[serde(default)]
+    pub sandbox: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignatureConfig {
+    pub provider: SignatureProvider,
+    /// Signing secret, or `env:NAME` to read a worker secret
+    pub secret: String,
+    /// Allowed clock skew for the signed timestamp
+    #[serde(default = "default_tolerance_seconds")]
+    pub tolerance_seconds: i64,
+    /// Reject invalid and stale deliveries (they are stored and flagged either way)
+    #[serde(default = "default_enforce")]
+    pub enforce: bool,
+    /// Secret being rotated out, still accepted until `previous_expires_at_ms`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_secret: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_expires_at_ms: Option<i64>,
+    /// Scheme for the `hmac` provider
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hmac: Option<HmacScheme>,
+    /// PayPal app and webhook for the `paypal` provider; `secret` is the app's client secret
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paypal: Option<PaypalApp>,
+}
+
+/// Standard Webhooks signing of external forwards: every attempt carries the
+/// capture ID as `webhook-id`, its own `webhook-timestamp` and a `v1` signature
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForwardSigning {
+    /// Signing secret (`whsec_` plus a base64 key, any other literal, or `env:NAME`)
+    pub secret: String,
+    /// Further attempts after a failure, timeout or 429/5xx answer
+    #[serde(default = "default_forward_retries")]
+    pub retries: u32,
+}
+
+fn default_forward_retries() -> u32 {
+    2
+}
+
+/// Most further attempts a signed forward may make
+pub const MAX_FORWARD_RETRIES: u32 = 3;
+
+/// TwiML templates for Twilio webhooks; `{{Param}}` is replaced with the XML-escaped
+/// request parameter (`{{From}}`, `{{Body}}`, ...). A missing template answers with an
+/// empty `<Response/>`: no SMS reply, and the call ends.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TwimlConfig {
+    /// Answer to incoming calls (`CallSid` present)
+    #[serde(default)]
+    pub voice: Option<String>,
+    /// Answer to incoming SMS/MMS/WhatsApp messages (`MessageSid` present)
+    #[serde(default)]
+    pub messaging: Option<String>,
+}
+
+/// OAuth2 client credentials for exchanging callback codes at the token endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OauthClient {
+    /// Token endpoint (`https://oauth2.googleapis.com/token`)
+    pub token_url: String,
+    pub client_id: String,
+    /// Client secret (literal or `env:NAME`), sent as `client_secret` in the form
+    pub client_secret: String,
+    /// Redirect URI of the authorization request (default: the callback URL itself)
+    #[serde(default)]
+    pub redirect_uri: Option<String>,
+}
+
+/// Slack message templates (JSON); `{{field}}` is replaced with the JSON-escaped
+/// command field (`{{text}}`, `{{user_name}}`) or interaction payload value (`{{user.name}}`)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlackConfig {
+    /// Immediate answer; None acknowledges with an empty 200
+    #[serde(default)]
+    pub response: Option<String>,
+    /// Posted to the request's `response_url` once the capture is stored
+    #[serde(default)]
+    pub follow_up: Option<String>,
+}
+
+/// Payload shape tracking: a new shape of an event type is diffed against its dominant shape
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShapeTracking {
+    /// Receives a JSON POST with the diff when a new shape appears (None only stores it)
+    #[serde(default)]
+    pub notify_url: Option<String>,
+    /// Notify only when fields were removed or changed type
+    #[serde(default)]
+    pub breaking_only: bool,
+}
+
+/// A/B forwarding between a control and a candidate target
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrafficSplit {
+    /// Control target (URL or chained webhook UUID)
+    pub a: String,
+    /// Candidate target
+    pub b: String,
+    /// Share of deliveries forwarded to `b` instead of `a`
+    #[serde(default)]
+    pub b_percent: u8,
+    /// Share of deliveries forwarded to both, with their answers compared
+    #[serde(default)]
+    pub compare_percent: u8,
+}
+
+/// Shadow forwarding: the primary's answer counts, the shadow's is only compared with it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShadowForwarding {
+    /// Forwarded to like any target, its answer is stored with the capture
+    pub primary: String,
+    /// Gets the same delivery after the sender has been answered
+    pub shadow: String,
+}
+
+/// A saved replay: what to change, whether to sign, and the gap between resends
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayRecipe {
+    #[serde(default)]
+    pub overrides: Overrides,
+    /// Sign with `forward_signing` when it is configured; false sends as edited
+    #[serde(default = "default_true")]
+    pub sign: bool,
+    /// Wait between the resends of one run
+    #[serde(default)]
+    pub pacing_ms: u32,
+}
+
+/// Most storage a webhook may use
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageQuota {
+    /// Captured payloads plus R2 objects (uploads, email attachments), in bytes
+    pub max_bytes: u64,
+}
+
+/// Compression of stored bodies
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Compression {
+    #[serde(default)]
+    pub algorithm: CompressionAlgorithm,
+    /// Bodies smaller than this are stored as they are
+    #[serde(default = "default_compression_min_bytes")]
+    pub min_bytes: usize,
+}
+
+fn default_compression_min_bytes() -> usize {
+    1024
+}
+
+/// Recognition of heartbeat (ping) deliveries
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Heartbeat {
+    /// Identical deliveries at a steady interval before they count as heartbeats
+    #[serde(default = "default_heartbeat_min_repeats")]
+    pub min_repeats: u32,
+    /// How far an interval may stray from the usual one, as a share of it
+    #[serde(default = "default_heartbeat_tolerance")]
+    pub tolerance: f64,
+    /// Fold heartbeats into the capture before them (a count and last-seen time) instead of storing each
+    #[serde(default)]
+    pub collapse: bool,
+}
+
+fn default_heartbeat_min_repeats() -> u32 {
+    3
+}
+
+fn default_heartbeat_tolerance() -> f64 {
+    0.2
+}
+
+/// Most correlation keys one webhook's sessions may use
+pub const MAX_SESSION_KEYS: usize = 8;
+
+/// Grouping of captures into sessions
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sessions {
+    /// Longest pause between two captures of one session
+    #[serde(default = "default_session_gap_seconds")]
+    pub gap_seconds: u32,
+    /// Values tying captures together, e.g. `[{"header": "x-request-id"}, {"body": "data.order_id"}]`
+    #[serde(default)]
+    pub keys: Vec<FieldSource>,
+}
+
+impl Default for Sessions {
+    fn default() -> Self {
+        Self {
+            gap_seconds: default_session_gap_seconds(),
+            keys: Vec::new(),
+        }
+    }
+}
+
+fn default_session_gap_seconds() -> u32 {
+    300
+}
+
+/// Formats of the runtime's `CompressionStream`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionAlgorithm {
+    #[default]
+    Gzip,
+    Deflate,
+}
+
+impl CompressionAlgorithm {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "gzip" => Some(Self::Gzip),
+            "deflate" => Some(Self::Deflate),
+            _ => None,
+        }
+    }
+}
+
+/// Simulated response latency, in the bucket layout the forwarding stats report
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencyProfile {
+    /// Ascending upper bounds; only the last bucket may be open (`le_ms: null`)
+    pub buckets: Vec<LatencyBucket>,
+    /// Where the distribution came from (`forwarding:https://api.example.com`, `import`)
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+/// Share of the simulated answers taking up to `le_ms` (above the previous bound)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencyBucket {
+    pub le_ms: Option<i64>,
+    pub count: u64,
+}
+
+impl LatencyProfile {
+    /// What makes the profile unusable: no answers to draw from, or bounds out of order
+    pub fn validate(&self) -> Option<String> {
+        if self.buckets.iter().all(|bucket| bucket.count == 0) {
+            return Some("no bucket has a count".to_string());
+        }
+        let mut previous = 0;
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            match bucket.le_ms {
+                None if index + 1 < self.buckets.len() => return Some("only the last bucket may be open".to_string()),
+                None => {}
+                Some(bound) if bound <= previous || bound > crate::latency::MAX_SIMULATED_MS => {
+                    return Some(format!(
+                        "bucket bounds must ascend from above 0 to at most {} ms",
+                        crate::latency::MAX_SIMULATED_MS
+                    ));
+                }
+                Some(bound) => previous = bound,
+            }
+        }
+        None
+    }
+}
+
+/// How long a rotated-out signing secret keeps verifying when no grace period is given
+pub const DEFAULT_ROTATION_GRACE_SECONDS: i64 = 86_400;
+
+/// Longest grace period for a rotated-out signing secret
+pub const MAX_ROTATION_GRACE_SECONDS: i64 = 7 * 86_400;
+
+impl SignatureConfig {
+    /// Secrets that verify deliveries at `now_ms`: the current one, then the
+    /// previous one until its grace period ends
+    pub fn active_secrets(&self, now_ms: i64) -> Vec<&str> {
+        let mut secrets = vec![self.secret.as_str()];
+        if let (Some(previous), Some(expires_at_ms)) = (&self.previous_secret, self.previous_expires_at_ms) {
+            if now_ms < expires_at_ms {
+                secrets.push(previous);
+            }
+        }
+        secrets
+    }
+
+    /// Make `secret` current, keeping the old one valid for `grace_seconds`
+    /// (immediately dropped with 0)
+    pub fn rotate(&mut self, secret: String, grace_seconds: i64, now_ms: i64) {
+        let previous = std::mem::replace(&mut self.secret, secret);
+        if grace_seconds > 0 {
+            self.previous_secret = Some(previous);
+            self.previous_expires_at_ms = Some(now_ms + grace_seconds * 1000);
+        } else {
+            self.previous_secret = None;
+            self.previous_expires_at_ms = None;
+        }
+    }
+}
+
+fn default_tolerance_seconds() -> i64 {
+    300
+}
+
+fn default_enforce() -> bool {
+    true
+}
+
+/// Prefix for secrets read from the worker environment instead of the config
+pub(crate) const SECRET_ENV_PREFIX: &str = "env:";
+
+/// Resolve a config secret: `env:NAME` reads the worker secret (or var) NAME
+pub fn resolve_secret(env: &Env, value: &str) -> Option<String> {
+    match value.strip_prefix(SECRET_ENV_PREFIX) {
+        Some(name) => env
+            .secret(name)
+            .map(|secret| secret.to_string())
+            .or_else(|_| env.var(name).map(|var| var.to_string()))
+            .ok(),
+        None if value.is_empty() || value == REDACTED => None,
+        None => Some(value.to_string()),
+    }
+}
+
+/// Everything ingestion needs to know about a webhook beyond its ID
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebhookSettings {
+    pub config: WebhookConfig,
+    /// Secret for signed URLs, generated on first use
+    pub secret: Option<String>,
+    pub version: i64,
+    /// Named environments sharing this config
+    #[serde(default)]
+    pub environments: Vec<Environment>,
+    /// Jurisdiction of the owning project, when pinned (see `residency.rs`)
+    #[serde(default)]
+    pub jurisdiction: Option<Jurisdiction>,
+}
+
+#[derive(Deserialize)]
+struct SettingsRow {
+    config: Option<String>,
+    secret: Option<String>,
+    config_version: i64,
+}
+
+fn cache_key(webhook_id: &str) -> String {
+    format!("{}{}", CACHE_PREFIX, webhook_id)
+}
+
+/// Settings for a webhook (KV first, D1 fallback)
+pub async fn load(
+    kv: &(impl KvBackend + ?Sized),
+    db: &(impl Directory + ?Sized),
+    webhook_id: &str,
+) -> Result<WebhookSettings> {
+    let cached = kv
+        .get_text(&cache_key(webhook_id))
+        .await?
+        .and_then(|cached| serde_json::from_str::<WebhookSettings>(&cached).ok());
+    if let Some(cached) = cached {
+        return Ok(cached);
+    }
+
+    let settings = db.load_settings(webhook_id).await?;
+    let written = match serde_json::to_string(&settings) {
+        Ok(json) => kv.put_text(&cache_key(webhook_id), &json, Some(crate::cache::TTL_SECONDS)).await,
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = written {
+        log_error!("⚠️  Failed to cache webhook settings: {:?}", e);
+    }
+
+    Ok(settings)
+}
+
+/// Settings straight from D1 (management API reads)
+pub async fn load_from_d1(db: &D1Database, webhook_id: &str) -> Result<WebhookSettings> {
+    let row = db
+        .prepare("SELECT config, secret, config_version FROM webhooks WHERE id = ?1")
+        .bind(&[JsValue::from_str(webhook_id)])?
+        .first::<SettingsRow>(None)
+        .await?;
+
+    Ok(match row {
+        Some(row) => WebhookSettings {
+            config: row
+                .config
+                .and_then(|config| serde_json::from_str(&config).ok())
+                .unwrap_or_default(),
+            secret: row.secret,
+            version: row.config_version,
+            environments: environments::list(db, webhook_id).await?,
+            jurisdiction: residency::of_webhook(db, webhook_id).await?,
+        },
+        None => WebhookSettings::default(),
+    })
+}
+
+/// Store a new config written by `actor`, keeping it in the history (see
+/// `config_history.rs`). With `expected_version`, the write only succeeds if the
+/// stored version still matches. Returns the new version, or None on a conflict.
+pub async fn save(
+    kv: &KvStore,
+    db: &D1Database,
+    webhook_id: &str,
+    config: &WebhookConfig,
+    actor: &str,
+    expected_version: Option<i64>,
+) -> Result<Option<i64>> {
+    let config_json = serde_json::to_string(config)?;
+    let statement = match expected_version {
+        Some(version) => db
+            .prepare(
+                "UPDATE webhooks SET config = ?2, config_version = config_version + 1 \
+                 WHERE id = ?1 AND config_version = ?3",
+            )
+            .bind(&[
+                JsValue::from_str(webhook_id),
+                JsValue::from_str(&config_json),
+                JsValue::from_f64(version as f64),
+            ])?,
+        None => db
+            .prepare("UPDATE webhooks SET config = ?2, config_version = config_version + 1 WHERE id = ?1")
+            .bind(&[JsValue::from_str(webhook_id), JsValue::from_str(&config_json)])?,
+    };
+
+    let now_ms = Date::now().as_millis() as i64;
+    let results = db
+        .batch(vec![
+            config_history::keep_current(db, webhook_id)?,
+            statement,
+            config_history::keep_written(db, webhook_id, actor, now_ms)?,
+            config_history::trim(db, webhook_id)?,
+        ])
+        .await?;
+    let changed = match results.get(1) {
+        Some(result) => result.meta()?.and_then(|meta| meta.changes).unwrap_or(0),
+        None => 0,
+    };
+    invalidate(kv, webhook_id).await;
+
+    if changed == 0 {
+        return Ok(None);
+    }
+    Ok(Some(load_from_d1(db, webhook_id).await?.version))
+}
+
+/// The webhook secret, generating and storing one if it does not exist yet
+pub async fn ensure_secret(kv: &KvStore, db: &D1Database, webhook_id: &str) -> Result<String> {
+    if let Some(secret) = load_from_d1(db, webhook_id).await?.secret {
+        return Ok(secret);
+    }
+
+    let secret = format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    );
+    // Only the first concurrent writer wins; re-read to return the stored value
+    db.prepare("UPDATE webhooks SET secret = ?2 WHERE id = ?1 AND secret IS NULL")
+        .bind(&[JsValue::from_str(webhook_id), JsValue::from_str(&secret)])?
+        .run()
+        .await?;
+    invalidate(kv, webhook_id).await;
+
+    Ok(load_from_d1(db, webhook_id).await?.secret.unwrap_or(secret))
+}
+
+#[derive(Deserialize)]
+struct ConfigRow {
+    id: String,
+    config: String,
+}
+
+/// A retention rule: delete a webhook's captures (optionally only one event type
+/// pattern, as in `EventRoute::event_type`) older than `days`
+pub struct RetentionRule {
+    pub webhook_id: String,
+    pub event_type: Option<String>,
+    pub days: u32,
+}
+
+/// Retention rules of every webhook whose config sets `retention_days`
+pub async fn retention_rules(db: &D1Database) -> Result<Vec<RetentionRule>> {
+    let rows = db
+        .prepare("SELECT id, config FROM webhooks WHERE config LIKE '%\"retention_days\":%'")
+        .all()
+        .await?
+        .results::<ConfigRow>()?;
+
+    let mut rules = Vec::new();
+    for row in rows {
+        let Ok(config) = serde_json::from_str::<WebhookConfig>(&row.config) else {
+            continue;
+        };
+        if let Some(days) = config.retention_days {
+            rules.push(RetentionRule {
+                webhook_id: row.id.clone(),
+                event_type: None,
+                days,
+            });
+        }
+        for route in config.routes {
+            if let Some(days) = route.retention_days {
+                rules.push(RetentionRule {
+                    webhook_id: row.id.clone(),
+                    event_type: Some(route.event_type),
+                    days,
+                });
+            }
+        }
+    }
+    Ok(rules)
+}
+
+/// A webhook's retention tiers, for the scheduled downsampling
+pub struct TierRule {
+    pub webhook_id: String,
+    pub tiers: RetentionTiers,
+}
+
+/// Retention tiers of every webhook whose config sets them
+pub async fn tier_rules(db: &D1Database) -> Result<Vec<TierRule>> {
+    let rows = db
+        .prepare("SELECT id, config FROM webhooks WHERE config LIKE '%\"retention_tiers\":{%'")
+        .all()
+        .await?
+        .results::<ConfigRow>()?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            let config = serde_json::from_str::<WebhookConfig>(&row.config).ok()?;
+            Some(TierRule {
+                webhook_id: row.id,
+                tiers: config.retention_tiers?,
+            })
+        })
+        .collect())
+}
+
+/// A webhook's delivery expectation, for the scheduled SLA check
+pub struct ExpectationRule {
+    pub webhook_id: String,
+    pub uuid: String,
+    pub expectation: Expectation,
+}
+
+#[derive(Deserialize)]
+struct RuleRow {
+    id: String,
+    uuid: String,
+    config: String,
+}
+
+/// Expectations of every webhook whose config sets any
+pub async fn expectation_rules(db: &D1Database) -> Result<Vec<ExpectationRule>> {
+    let rows = db
+        .prepare("SELECT id, uuid, config FROM webhooks WHERE config LIKE '%\"expectations\":[{%'")
+        .all()
+        .await?
+        .results::<RuleRow>()?;
+
+    let mut rules = Vec::new();
+    for row in rows {
+        let Ok(config) = serde_json::from_str::<WebhookConfig>(&row.config) else {
+            continue;
+        };
+        for expectation in config.expectations {
+            rules.push(ExpectationRule {
+                webhook_id: row.id.clone(),
+                uuid: row.uuid.clone(),
+                expectation,
+            });
+        }
+    }
+    Ok(rules)
+}
+
+/// A webhook with volume anomaly detection, for the scheduled check
+pub struct AnomalyRule {
+    pub webhook_id: String,
+    pub uuid: String,
+    pub anomaly: AnomalyConfig,
+}
+
+/// Every webhook whose config enables anomaly detection
+pub async fn anomaly_rules(db: &D1Database) -> Result<Vec<AnomalyRule>> {
+    let rows = db
+        .prepare("SELECT id, uuid, config FROM webhooks WHERE config LIKE '%\"anomaly\":{%'")
+        .all()
+        .await?
+        .results::<RuleRow>()?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            let config = serde_json::from_str::<WebhookConfig>(&row.config).ok()?;
+            Some(AnomalyRule {
+                webhook_id: row.id,
+                uuid: row.uuid,
+                anomaly: config.anomaly?,
+            })
+        })
+        .collect())
+}
+
+/// Drop cached settings after a write
+pub async fn invalidate(kv: &(impl KvBackend + ?Sized), webhook_id: &str) {
+    if let Err(e) = kv.delete(&cache_key(webhook_id)).await {
+        log_error!("⚠️  Failed to invalidate webhook settings cache: {:?}", e);
+    }
+}
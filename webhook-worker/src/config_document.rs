@@ -0,0 +1,152 @@
+//! Declarative webhook configuration documents
+//! A document holds everything about a webhook that can be promoted between
+//! deployments: its config (secrets as `env:NAME` references, literal ones
+//! masked) and its environments by name. Environment UUIDs are deployment
+//! specific and never part of a document.
+
+use crate::config::{self, WebhookConfig};
+use crate::environments;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+/// Document format version written on export
+pub const DOCUMENT_VERSION: u32 = 1;
+
+fn document_version() -> u32 {
+    DOCUMENT_VERSION
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigDocument {
+    #[serde(default = "document_version")]
+    pub version: u32,
+    #[serde(default)]
+    pub config: WebhookConfig,
+    #[serde(default)]
+    pub environments: Vec<EnvironmentSpec>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvironmentSpec {
+    pub name: String,
+    #[serde(default)]
+    pub forward_url: Option<String>,
+}
+
+/// What applying a document changed
+#[derive(Debug, Default, Serialize)]
+pub struct ApplyOutcome {
+    /// New config version (None if the `expected_version` no longer matched)
+    pub version: Option<i64>,
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub deleted: Vec<String>,
+}
+
+impl ConfigDocument {
+    /// Export a webhook's current state
+    pub async fn export(db: &D1Database, webhook_id: &str) -> Result<Self> {
+        let settings = config::load_from_d1(db, webhook_id).await?;
+        Ok(Self {
+            version: DOCUMENT_VERSION,
+            config: settings.config.redacted(),
+            environments: settings
+                .environments
+                .into_iter()
+                .map(|environment| EnvironmentSpec {
+                    name: environment.name,
+                    forward_url: environment.forward_url,
+                })
+                .collect(),
+        })
+    }
+
+    /// First problem that would stop the document from applying
+    pub fn validate(&self) -> Option<String> {
+        if self.version != DOCUMENT_VERSION {
+            return Some(format!("Unsupported document version {}", self.version));
+        }
+        if let Some(problem) = self.config.validate() {
+            return Some(problem);
+        }
+        for (index, environment) in self.environments.iter().enumerate() {
+            if !environments::is_valid_name(&environment.name) {
+                return Some(format!("Invalid environment name: {}", environment.name));
+            }
+            if self.environments[..index].iter().any(|other| other.name == environment.name) {
+                return Some(format!("Duplicate environment: {}", environment.name));
+            }
+            if environment
+                .forward_url
+                .as_deref()
+                .is_some_and(|url| !environments::is_valid_forward_target(url))
+            {
+                return Some(format!("Invalid forward_url for environment {}", environment.name));
+            }
+        }
+        None
+    }
+
+    /// Apply a validated document: replace the config, create or update the listed
+    /// environments and, with `prune`, delete environments the document omits
+    pub async fn apply(
+        &self,
+        kv: &KvStore,
+        db: &D1Database,
+        webhook_id: &str,
+        actor: &str,
+        expected_version: Option<i64>,
+        prune: bool,
+    ) -> Result<ApplyOutcome> {
+        let current = config::load_from_d1(db, webhook_id).await?;
+        let mut updated = self.config.clone();
+        updated.restore_secrets(&current.config);
+
+        let version = if updated == current.config {
+            // Unchanged configs keep their version so re-applying is a no-op
+            match expected_version {
+                Some(expected) if expected != current.version => None,
+                _ => Some(current.version),
+            }
+        } else {
+            config::save(kv, db, webhook_id, &updated, actor, expected_version.or(Some(current.version))).await?
+        };
+        let mut outcome = ApplyOutcome {
+            version,
+            ..ApplyOutcome::default()
+        };
+        if version.is_none() {
+            return Ok(outcome);
+        }
+
+        for spec in &self.environments {
+            match current.environments.iter().find(|existing| existing.name == spec.name) {
+                Some(existing) if existing.forward_url != spec.forward_url => {
+                    environments::set_forward_url(db, &existing.id, spec.forward_url.clone()).await?;
+                    outcome.updated.push(spec.name.clone());
+                }
+                Some(_) => {}
+                None => {
+                    environments::create(db, webhook_id, &spec.name, spec.forward_url.clone()).await?;
+                    outcome.created.push(spec.name.clone());
+                }
+            }
+        }
+        if prune {
+            for existing in &current.environments {
+                if !self.environments.iter().any(|spec| spec.name == existing.name) {
+                    environments::delete(db, &existing.id).await?;
+                    if let Err(e) = crate::cache::delete(kv, &existing.uuid).await {
+                        log_warn!("⚠️  Failed to drop cached environment UUID: {:?}", e);
+                    }
+                    outcome.deleted.push(existing.name.clone());
+                }
+            }
+        }
+
+        if !(outcome.created.is_empty() && outcome.updated.is_empty() && outcome.deleted.is_empty()) {
+            config::invalidate(kv, webhook_id).await;
+        }
+        Ok(outcome)
+    }
+}
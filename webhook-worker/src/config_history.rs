@@ -0,0 +1,148 @@
+//! Config history
+//! Every config write (see `config::save`) keeps the version it produced in the
+//! `config_versions` table, with who wrote it and when, and the version it
+//! replaced if that one wasn't kept yet (configs written before history was
+//! kept are recorded without an actor). The history lists versions newest
+//! first with the paths each one changed; a version's detail has its config
+//! and the diff against the version before it. Rolling back saves an old
+//! config as a new version, so a rollback can be rolled back too. The latest
+//! `MAX_VERSIONS` versions of each webhook are kept.
+
+use crate::config::WebhookConfig;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Versions kept per webhook
+pub const MAX_VERSIONS: i64 = 50;
+
+/// One changed config value; None where the value is unset
+pub use webhook_types::config::Change;
+
+/// Values that differ between two configs (as JSON), objects compared key by key
+pub fn diff(before: &Value, after: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_at(Some(before), Some(after), String::new(), &mut changes);
+    changes
+}
+
+fn diff_at(before: Option<&Value>, after: Option<&Value>, path: String, changes: &mut Vec<Change>) {
+    // Nulls are unset values, like missing keys
+    let before = before.filter(|value| !value.is_null());
+    let after = after.filter(|value| !value.is_null());
+    if let (Some(Value::Object(old)), Some(Value::Object(new))) = (before, after) {
+        let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+        for key in keys {
+            let nested = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+            diff_at(old.get(key), new.get(key), nested, changes);
+        }
+    } else if before != after {
+        changes.push(Change {
+            path,
+            before: before.cloned(),
+            after: after.cloned(),
+        });
+    }
+}
+
+/// A kept config version
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub version: i64,
+    pub config: WebhookConfig,
+    /// None for a version written before history was kept
+    pub actor: Option<String>,
+    pub created_at_ms: Option<i64>,
+}
+
+/// What changed between two configs, secrets redacted
+pub fn config_changes(before: &WebhookConfig, after: &WebhookConfig) -> Vec<Change> {
+    let to_json = |config: &WebhookConfig| serde_json::to_value(config.redacted()).unwrap_or_default();
+    diff(&to_json(before), &to_json(after))
+}
+
+impl Snapshot {
+    /// What this version changed from `previous` (the default config for the first one)
+    pub fn changes(&self, previous: Option<&Snapshot>) -> Vec<Change> {
+        match previous {
+            Some(previous) => config_changes(&previous.config, &self.config),
+            None => config_changes(&WebhookConfig::default(), &self.config),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SnapshotRow {
+    version: f64,
+    config: String,
+    actor: Option<String>,
+    created_at_ms: Option<f64>,
+}
+
+impl SnapshotRow {
+    /// None for a config this worker can no longer read, which must not be rolled back to as a default one
+    fn into_snapshot(self) -> Option<Snapshot> {
+        let config = match serde_json::from_str(&self.config) {
+            Ok(config) => config,
+            Err(e) => {
+                log_warn!("⚠️  Skipping unreadable config version {}: {}", self.version, e);
+                return None;
+            }
+        };
+        Some(Snapshot {
+            version: self.version as i64,
+            config,
+            actor: self.actor,
+            created_at_ms: self.created_at_ms.map(|ms| ms as i64),
+        })
+    }
+}
+
+/// Keep the webhook's current version, unless it is kept already; run before the config is written
+pub fn keep_current(db: &D1Database, webhook_id: &str) -> Result<D1PreparedStatement> {
+    db.prepare(
+        "INSERT OR IGNORE INTO config_versions (webhook_id, version, config, actor, created_at_ms) \
+         SELECT id, config_version, COALESCE(config, '{}'), NULL, NULL FROM webhooks WHERE id = ?1",
+    )
+    .bind(&[JsValue::from_str(webhook_id)])
+}
+
+/// Keep the version just written by `actor`; a write that lost a version conflict adds nothing
+pub fn keep_written(db: &D1Database, webhook_id: &str, actor: &str, now_ms: i64) -> Result<D1PreparedStatement> {
+    db.prepare(
+        "INSERT OR IGNORE INTO config_versions (webhook_id, version, config, actor, created_at_ms) \
+         SELECT id, config_version, config, ?2, ?3 FROM webhooks WHERE id = ?1",
+    )
+    .bind(&[
+        JsValue::from_str(webhook_id),
+        JsValue::from_str(actor),
+        JsValue::from_f64(now_ms as f64),
+    ])
+}
+
+/// Drop the webhook's versions beyond the latest `MAX_VERSIONS`
+pub fn trim(db: &D1Database, webhook_id: &str) -> Result<D1PreparedStatement> {
+    db.prepare(
+        "DELETE FROM config_versions WHERE webhook_id = ?1 AND version <= \
+         (SELECT MAX(version) FROM config_versions WHERE webhook_id = ?1) - ?2",
+    )
+    .bind(&[JsValue::from_str(webhook_id), JsValue::from_f64(MAX_VERSIONS as f64)])
+}
+
+/// The webhook's kept versions, newest first
+pub async fn list(db: &D1Database, webhook_id: &str) -> Result<Vec<Snapshot>> {
+    Ok(db
+        .prepare(
+            "SELECT version, config, actor, created_at_ms FROM config_versions WHERE webhook_id = ?1 \
+             ORDER BY version DESC LIMIT ?2",
+        )
+        .bind(&[JsValue::from_str(webhook_id), JsValue::from_f64(MAX_VERSIONS as f64)])?
+        .all()
+        .await?
+        .results::<SnapshotRow>()?
+        .into_iter()
+        .filter_map(SnapshotRow::into_snapshot)
+        .collect())
+}
@@ -0,0 +1,118 @@
+//! Debug consoles
+//! An editor opens a WebSocket to `/api/webhooks/{uuid}/console`, which the
+//! webhook's `WebhookConsole` Durable Object accepts. Every new capture is
+//! pushed to the open consoles, and any of them can steer the webhook while
+//! the debugging session lasts: hold forwarding (captures are still stored
+//! and shown, and can be replayed once the consumer is ready), answer senders
+//! with a response of its own instead of the configured one, or replay a
+//! capture with overrides (see `replay.rs`). The session's state is shared by
+//! all consoles of the webhook and mirrored to KV under `console:{webhook_id}`,
+//! which ingestion reads; it is cleared when the last console disconnects, so
+//! a forgotten pause can't outlive the session. The KV copy expires an hour
+//! after the last console message, so idle consoles should ping.
+//!
+//! Console protocol (JSON text frames):
+//! - server → console: `{"type":"hello","state":{...},"consoles":n}`, `{"type":"capture","request":{...}}`,
+//!   `{"type":"state","state":{...},"by":"..."}`, `{"type":"replayed","result":{...}}`,
+//!   `{"type":"error","message":"..."}`, `{"type":"pong"}`
+//! - console → server: `{"type":"pause"}`, `{"type":"resume"}`, `{"type":"respond","response":{...}}`,
+//!   `{"type":"clear_response"}`, `{"type":"replay","id":"...","overrides"?:{...}}`, `{"type":"ping"}`
+
+use crate::config::CustomResponse;
+use crate::kv::KvBackend;
+use crate::replay::Overrides;
+use serde::{Deserialize, Serialize};
+
+/// The KV copy of a session's state expires this long after the last console message
+pub const STATE_TTL_SECONDS: u64 = 3600;
+
+/// Largest body of a console response
+const MAX_RESPONSE_BODY_BYTES: usize = 64 * 1024;
+
+/// What the open consoles of a webhook have set
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConsoleState {
+    /// Captures are stored and pushed but not forwarded
+    pub paused: bool,
+    /// Answer given to senders instead of the configured one
+    pub response: Option<CustomResponse>,
+}
+
+impl ConsoleState {
+    /// Apply a command; false for commands that leave the state alone
+    pub fn apply(&mut self, command: &Command) -> bool {
+        match command {
+            Command::Pause => self.paused = true,
+            Command::Resume => self.paused = false,
+            Command::Respond { response } => self.response = Some(response.clone()),
+            Command::ClearResponse => self.response = None,
+            Command::Replay { .. } | Command::Ping => return false,
+        }
+        true
+    }
+}
+
+/// A message from a console
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Command {
+    Pause,
+    Resume,
+    Respond {
+        response: CustomResponse,
+    },
+    ClearResponse,
+    Replay {
+        id: String,
+        #[serde(default)]
+        overrides: Overrides,
+    },
+    Ping,
+}
+
+impl Command {
+    /// Parse and check a console message
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let command: Self = serde_json::from_str(text).map_err(|e| format!("Invalid command: {}", e))?;
+        if let Self::Respond { response } = &command {
+            if !(100..=599).contains(&response.status) {
+                return Err("response status must be between 100 and 599".to_string());
+            }
+            if response.body.len() > MAX_RESPONSE_BODY_BYTES {
+                return Err(format!("response body must be at most {} bytes", MAX_RESPONSE_BODY_BYTES));
+            }
+        }
+        Ok(command)
+    }
+}
+
+pub fn key(webhook_id: &str) -> String {
+    format!("console:{}", webhook_id)
+}
+
+/// State of the webhook's debugging session; None when no console is open
+pub async fn load(kv: &(impl KvBackend + ?Sized), webhook_id: &str) -> Option<ConsoleState> {
+    match kv.get_text(&key(webhook_id)).await {
+        Ok(value) => value.and_then(|value| serde_json::from_str(&value).ok()),
+        Err(e) => {
+            log_warn!("⚠️  Failed to read console state of webhook {}: {:?}", webhook_id, e);
+            None
+        }
+    }
+}
+
+/// Publish a session's state to ingestion
+pub async fn save(kv: &(impl KvBackend + ?Sized), webhook_id: &str, state: &ConsoleState) {
+    let value = serde_json::to_string(state).unwrap_or_default();
+    if let Err(e) = kv.put_text(&key(webhook_id), &value, Some(STATE_TTL_SECONDS)).await {
+        log_warn!("⚠️  Failed to save console state of webhook {}: {:?}", webhook_id, e);
+    }
+}
+
+/// End a session: ingestion goes back to the configured behavior
+pub async fn close(kv: &(impl KvBackend + ?Sized), webhook_id: &str) {
+    if let Err(e) = kv.delete(&key(webhook_id)).await {
+        log_warn!("⚠️  Failed to clear console state of webhook {}: {:?}", webhook_id, e);
+    }
+}
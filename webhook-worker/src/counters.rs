@@ -0,0 +1,165 @@
+//! Concurrency-safe counters
+//! Quotas, rate limits and similar tallies must not lose increments when many
+//! deliveries land at once, which a read-modify-write on KV (or a D1 row read
+//! before it is written) does. Counters therefore live in a `Counter` Durable
+//! Object per scope (`quota:{webhook_id}`, `rate:{ip}`; see `durable/counter.rs`),
+//! whose requests are serialized, and every increment is answered from there.
+//! Changed counters are flushed to the `counters` table in one batch at most
+//! every `FLUSH_INTERVAL_MS`, so D1 sees one write per scope and interval
+//! however busy the counter is, and reports and dashboards can read them there.
+//!
+//! A counter can expire (`ttl_ms` when it is first incremented), which makes
+//! fixed-window limits a matter of putting the window in the key
+//! (`captures:2026-10-14`); expired counters restart at zero.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Longest a changed counter waits before it is written to D1
+pub const FLUSH_INTERVAL_MS: i64 = 10_000;
+
+/// One counter's value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub value: i64,
+    /// When the counter restarts at zero; None keeps it forever
+    pub expires_at_ms: Option<i64>,
+}
+
+impl Entry {
+    fn is_expired(&self, now_ms: i64) -> bool {
+        self.expires_at_ms.is_some_and(|expires_at_ms| expires_at_ms <= now_ms)
+    }
+}
+
+/// The counters of one scope, and which of them D1 has not seen yet
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Counters {
+    entries: BTreeMap<String, Entry>,
+    dirty: BTreeSet<String>,
+}
+
+impl Counters {
+    /// Add `by` (which may be negative) and return the new value; a counter that
+    /// is new or expired starts at zero and lives for `ttl_ms`
+    pub fn increment(&mut self, key: &str, by: i64, ttl_ms: Option<i64>, now_ms: i64) -> i64 {
+        let fresh = Entry {
+            value: 0,
+            expires_at_ms: ttl_ms.map(|ttl_ms| now_ms + ttl_ms),
+        };
+        let entry = self.entries.entry(key.to_string()).or_insert(fresh);
+        if entry.is_expired(now_ms) {
+            *entry = fresh;
+        }
+        entry.value += by;
+        self.dirty.insert(key.to_string());
+        entry.value
+    }
+
+    /// Current value; zero for unknown and expired counters
+    pub fn get(&self, key: &str, now_ms: i64) -> i64 {
+        self.entries
+            .get(key)
+            .filter(|entry| !entry.is_expired(now_ms))
+            .map_or(0, |entry| entry.value)
+    }
+
+    /// Every live counter
+    pub fn snapshot(&self, now_ms: i64) -> BTreeMap<String, Entry> {
+        self.entries
+            .iter()
+            .filter(|(_, entry)| !entry.is_expired(now_ms))
+            .map(|(key, entry)| (key.clone(), *entry))
+            .collect()
+    }
+
+    /// Overwrite a counter (e.g. with a recount); it no longer expires
+    pub fn set(&mut self, key: &str, value: i64) {
+        let entry = Entry {
+            value,
+            expires_at_ms: None,
+        };
+        self.entries.insert(key.to_string(), entry);
+        self.dirty.insert(key.to_string());
+    }
+
+    /// Forget a counter; true if it existed
+    pub fn reset(&mut self, key: &str) -> bool {
+        self.dirty.insert(key.to_string());
+        self.entries.remove(key).is_some()
+    }
+
+    /// Whether anything is waiting to be flushed
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    /// Changes since the last flush: the counter's value, or None for a reset one.
+    /// Put them back with `restore` if the flush fails.
+    pub fn take_dirty(&mut self) -> Vec<(String, Option<Entry>)> {
+        std::mem::take(&mut self.dirty)
+            .into_iter()
+            .map(|key| {
+                let entry = self.entries.get(&key).copied();
+                (key, entry)
+            })
+            .collect()
+    }
+
+    /// Mark changes whose flush failed as unflushed again
+    pub fn restore(&mut self, changes: &[(String, Option<Entry>)]) {
+        self.dirty.extend(changes.iter().map(|(key, _)| key.clone()));
+    }
+
+    /// Drop expired counters (D1 prunes its copies on its own); returns how many
+    pub fn expire(&mut self, now_ms: i64) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|_, entry| !entry.is_expired(now_ms));
+        before - self.entries.len()
+    }
+}
+
+/// Write a scope's changed counters to D1 in one batch
+pub async fn flush(db: &D1Database, scope: &str, changes: &[(String, Option<Entry>)], now_ms: i64) -> Result<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let mut batch = Vec::with_capacity(changes.len());
+    for (key, entry) in changes {
+        let statement = match entry {
+            Some(entry) => db
+                .prepare(
+                    "INSERT INTO counters (scope, key, value, expires_at_ms, updated_at_ms) \
+                     VALUES (?1, ?2, ?3, ?4, ?5) \
+                     ON CONFLICT(scope, key) DO UPDATE SET value = excluded.value, \
+                     expires_at_ms = excluded.expires_at_ms, updated_at_ms = excluded.updated_at_ms",
+                )
+                .bind(&[
+                    JsValue::from_str(scope),
+                    JsValue::from_str(key),
+                    JsValue::from_f64(entry.value as f64),
+                    entry.expires_at_ms.map_or(JsValue::NULL, |ms| JsValue::from_f64(ms as f64)),
+                    JsValue::from_f64(now_ms as f64),
+                ])?,
+            None => db
+                .prepare("DELETE FROM counters WHERE scope = ?1 AND key = ?2")
+                .bind(&[JsValue::from_str(scope), JsValue::from_str(key)])?,
+        };
+        batch.push(statement);
+    }
+    db.batch(batch).await?;
+    Ok(())
+}
+
+/// Forget flushed counters that have expired
+pub async fn prune(db: &D1Database, now_ms: i64) -> Result<u64> {
+    let pruned = db
+        .prepare("DELETE FROM counters WHERE expires_at_ms <= ?1")
+        .bind(&[JsValue::from_f64(now_ms as f64)])?
+        .run()
+        .await?;
+    Ok(pruned.meta()?.and_then(|meta| meta.changes).unwrap_or(0) as u64)
+}
@@ -0,0 +1,110 @@
+//! Dashboard previews
+//! The pure parts of ingestion the dashboard shows before anything is saved,
+//! over JSON in and JSON out: checking a pasted delivery against a signature
+//! config, inferring a payload's shape or a docs page from capture rows, and
+//! diffing two shapes or two JSON documents. `crates/webhook-dashboard`
+//! exports them with `wasm_bindgen`, so the browser runs the same logic the
+//! worker does. Worker secrets (`env:NAME`) can't be read there; a preview
+//! uses the secret it is given or the config's literal ones.
+
+use crate::config::{self, SignatureConfig, SignatureProvider};
+use crate::config_history;
+use crate::docs;
+use crate::shapes::{self, Shape};
+use crate::signature;
+use crate::storage::StoredRequest;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use worker::Url;
+
+fn default_url() -> String {
+    "https://example.com/w/preview".to_string()
+}
+
+/// A delivery to check: `{"signature", "secret"?, "url"?, "headers", "body", "now"}`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SignaturePreview {
+    pub signature: SignatureConfig,
+    /// Secret to check with, instead of the config's
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// Capture URL the delivery was sent to (Twilio signs it)
+    #[serde(default = "default_url")]
+    pub url: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub body: String,
+    /// Unix seconds the timestamp tolerance is measured from
+    pub now: i64,
+}
+
+fn parse<T: serde::de::DeserializeOwned>(json: &str, what: &str) -> Result<T, String> {
+    serde_json::from_str(json).map_err(|e| format!("Invalid {}: {}", what, e))
+}
+
+fn render<T: serde::Serialize>(value: &T) -> Result<String, String> {
+    serde_json::to_string(value).map_err(|e| e.to_string())
+}
+
+/// `valid`, `missing`, `invalid` or `replay_suspected` for a `SignaturePreview`
+pub fn verify_signature(preview: &str) -> Result<String, String> {
+    let preview: SignaturePreview = parse(preview, "signature preview")?;
+    if preview.signature.provider == SignatureProvider::Paypal {
+        return Err("PayPal signatures are checked by PayPal's API and can't be previewed".to_string());
+    }
+    let url = Url::parse(&preview.url).map_err(|e| format!("Invalid url: {}", e))?;
+    let secrets: Vec<String> = match preview.secret {
+        Some(secret) => vec![secret],
+        None => preview
+            .signature
+            .active_secrets(preview.now * 1000)
+            .into_iter()
+            .filter(|secret| !secret.starts_with(config::SECRET_ENV_PREFIX))
+            .filter(|secret| !secret.is_empty() && *secret != config::REDACTED)
+            .map(str::to_string)
+            .collect(),
+    };
+    if secrets.is_empty() {
+        return Err("No secret to check with: the config's secrets are worker secrets".to_string());
+    }
+    let headers: HashMap<String, String> = preview
+        .headers
+        .into_iter()
+        .map(|(name, value)| (name.to_ascii_lowercase(), value))
+        .collect();
+    let verification =
+        signature::verify_with_secrets(&secrets, &preview.signature, &url, &headers, &preview.body, preview.now);
+    Ok(verification.as_str().to_string())
+}
+
+/// Shape of a JSON body (`{"field.path": ["type", ...]}`), as schema tracking fingerprints it
+pub fn infer_shape(body: &str) -> Result<String, String> {
+    let body: Value = parse(body, "JSON body")?;
+    match Shape::of(&body) {
+        Some(shape) => render(&shape),
+        None => Err("Only JSON objects and arrays have a shape".to_string()),
+    }
+}
+
+/// Fields added, removed and changed type from one shape to another
+pub fn diff_shapes(previous: &str, current: &str) -> Result<String, String> {
+    let previous: Shape = parse(previous, "previous shape")?;
+    let current: Shape = parse(current, "current shape")?;
+    render(&shapes::diff(&previous, &current))
+}
+
+/// The docs page `GET /api/webhooks/{uuid}/docs` would infer from capture rows
+pub fn infer_docs(webhook_id: &str, captures: &str) -> Result<String, String> {
+    let captures: Vec<StoredRequest> = parse(captures, "captures")?;
+    render(&docs::infer(webhook_id, &captures))
+}
+
+/// Values that differ between two JSON documents, by dot path, as config history diffs them
+pub fn diff_json(before: &str, after: &str) -> Result<String, String> {
+    let before: Value = parse(before, "before")?;
+    let after: Value = parse(after, "after")?;
+    render(&config_history::diff(&before, &after))
+}
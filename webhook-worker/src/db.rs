@@ -0,0 +1,65 @@
+//! D1 session routing
+//! With D1 read replication, writes go through a `first-primary` session and
+//! reads through a `first-unconstrained` session (nearest replica), optionally
+//! resuming from a client bookmark for read-your-writes consistency.
+//! Falls back to the plain binding where the Sessions API is unavailable.
+
+use wasm_bindgen::{JsCast, JsValue};
+use worker::worker_sys::types::D1Database as D1DatabaseSys;
+use worker::*;
+
+/// Request/response header carrying a D1 session bookmark
+pub const BOOKMARK_HEADER: &str = "x-d1-bookmark";
+
+/// D1 handle for writes (always routed to the primary)
+pub fn primary(env: &Env) -> Result<D1Database> {
+    primary_in(env, "DB")
+}
+
+/// D1 handle for reads, resuming from `bookmark` when the client has one
+pub fn replica(env: &Env, bookmark: Option<&str>) -> Result<D1Database> {
+    replica_in(env, "DB", bookmark)
+}
+
+/// Write handle for another D1 binding (region-pinned capture databases, see `residency`)
+pub fn primary_in(env: &Env, binding: &str) -> Result<D1Database> {
+    session(env, binding, "first-primary")
+}
+
+/// Read handle for another D1 binding
+pub fn replica_in(env: &Env, binding: &str, bookmark: Option<&str>) -> Result<D1Database> {
+    session(env, binding, bookmark.unwrap_or("first-unconstrained"))
+}
+
+/// Refresh the replication heartbeat row on the primary; `/health` compares it
+/// with the nearest replica's copy
+pub async fn write_heartbeat(env: &Env, now_ms: i64) -> Result<()> {
+    primary(env)?
+        .prepare("INSERT OR REPLACE INTO replication_heartbeat (id, written_at_ms) VALUES (1, ?1)")
+        .bind(&[JsValue::from_f64(now_ms as f64)])?
+        .run()
+        .await?;
+    Ok(())
+}
+
+/// Bookmark of a session handle, to return to the client
+pub fn bookmark(db: &D1Database) -> Option<String> {
+    let get_bookmark = js_sys::Reflect::get(db.as_ref(), &JsValue::from_str("getBookmark")).ok()?;
+    let function = get_bookmark.dyn_ref::<js_sys::Function>()?;
+    function.call0(db.as_ref()).ok()?.as_string()
+}
+
+fn session(env: &Env, binding: &str, constraint: &str) -> Result<D1Database> {
+    let db = env.d1(binding)?;
+    let with_session = js_sys::Reflect::get(db.as_ref(), &JsValue::from_str("withSession"))
+        .ok()
+        .and_then(|value| value.dyn_into::<js_sys::Function>().ok());
+
+    match with_session {
+        Some(function) => {
+            let session = function.call1(db.as_ref(), &JsValue::from_str(constraint))?;
+            Ok(D1Database::from(session.unchecked_into::<D1DatabaseSys>()))
+        }
+        None => Ok(db),
+    }
+}
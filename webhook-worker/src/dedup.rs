@@ -0,0 +1,128 @@
+//! Idempotent capture inserts
+//! A provider that retries after a 500 whose insert actually went through would
+//! otherwise be stored twice. Capture IDs are therefore derived from the
+//! webhook, the delivery's dedup key and the `DEDUP_WINDOW_SECONDS` bucket it
+//! arrived in (default 300, `0` restores random IDs), and inserts skip IDs that
+//! already exist: the redelivery is answered with the stored capture and not
+//! fanned out or forwarded again. A retry that straddles a bucket boundary is
+//! still stored twice. Deliveries without a dedup key keep random IDs unless the
+//! webhook sets `dedup_bodies`, which derives them from a SHA-256 of method and
+//! body instead, so identical keyless bodies within one window are stored once.
+//! Derived IDs keep the `ID_FORMAT`: ULIDs are timestamped with the bucket start.
+//! Uploads and socket frames keep random IDs.
+//! Svix and Standard Webhooks deliveries keep their message ID (`svix-id`,
+//! `webhook-id`) across a retry schedule spanning a day or more, so one whose
+//! message ID was stored for the webhook within `SVIX_RETRY_WINDOW_SECONDS` is a
+//! redelivery of that capture whichever bucket it lands in, and is not inserted
+//! again. The window bounds the lookup to the partitions a retry can reach.
+
+use crate::ids;
+use crate::storage::{RequestQuery, SortColumn, Storage};
+use sha2::{Digest, Sha256};
+use worker::*;
+
+/// How far back a Svix or Standard Webhooks retry can name its original message
+/// (their retry schedules end within about a day and a half)
+pub const SVIX_RETRY_WINDOW_SECONDS: i64 = 3 * 86_400;
+
+/// Bucket width when `DEDUP_WINDOW_SECONDS` is not set
+pub const DEFAULT_WINDOW_SECONDS: i64 = 300;
+
+/// Dedup bucket width in milliseconds; None when disabled
+pub fn window_ms(env: &Env) -> Option<i64> {
+    let seconds = env
+        .var("DEDUP_WINDOW_SECONDS")
+        .ok()
+        .and_then(|value| value.to_string().parse::<i64>().ok())
+        .unwrap_or(DEFAULT_WINDOW_SECONDS);
+    (seconds > 0).then_some(seconds * 1000)
+}
+
+/// What identifies one delivery of a webhook: its dedup key, else method and body
+pub fn fingerprint(webhook_id: &str, idempotency_key: Option<&str>, method: &str, body: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(webhook_id.as_bytes());
+    match idempotency_key {
+        Some(key) => {
+            hasher.update(b"\0key\0");
+            hasher.update(key.as_bytes());
+        }
+        None => {
+            hasher.update(b"\0body\0");
+            hasher.update(method.as_bytes());
+            hasher.update(b"\0");
+            hasher.update(body);
+        }
+    }
+    hasher.finalize().into()
+}
+
+/// Capture ID for a fingerprint received at `received_at_ms`, the same for every
+/// delivery in its `window_ms` bucket
+pub fn capture_id(fingerprint: &[u8; 32], received_at_ms: i64, window_ms: i64, uuid_format: bool) -> String {
+    let bucket = received_at_ms - received_at_ms.rem_euclid(window_ms);
+    let mut hasher = Sha256::new();
+    hasher.update(fingerprint);
+    hasher.update(bucket.to_be_bytes());
+    let digest = hasher.finalize();
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    if uuid_format {
+        uuid::Builder::from_random_bytes(bytes).into_uuid().to_string()
+    } else {
+        ids::ulid_from(bucket, u128::from_be_bytes(bytes))
+    }
+}
+
+/// Fingerprint a delivery is deduplicated by; None when it has no dedup key
+/// and the webhook doesn't set `dedup_bodies`
+pub fn delivery_fingerprint(
+    webhook_id: &str,
+    idempotency_key: Option<&str>,
+    dedup_bodies: bool,
+    method: &str,
+    body: &[u8],
+) -> Option<[u8; 32]> {
+    (idempotency_key.is_some() || dedup_bodies).then(|| fingerprint(webhook_id, idempotency_key, method, body))
+}
+
+/// Capture ID for a delivery: derived while dedup is enabled and the delivery
+/// has a fingerprint, random otherwise
+pub fn new_capture_id(
+    env: &Env,
+    webhook_id: &str,
+    idempotency_key: Option<&str>,
+    dedup_bodies: bool,
+    method: &str,
+    body: &[u8],
+    received_at_ms: i64,
+) -> String {
+    let fingerprint = delivery_fingerprint(webhook_id, idempotency_key, dedup_bodies, method, body);
+    match (window_ms(env), fingerprint) {
+        (Some(window_ms), Some(fingerprint)) => {
+            capture_id(&fingerprint, received_at_ms, window_ms, ids::uses_uuid(env))
+        }
+        _ => ids::new_capture_id(env, received_at_ms),
+    }
+}
+
+/// ID of the capture stored for a Svix or Standard Webhooks message ID within the
+/// retry window before `received_at` (Unix seconds), if any
+pub async fn svix_original(
+    storage: &dyn Storage,
+    webhook_id: &str,
+    svix_id: &str,
+    received_at: i64,
+) -> Result<Option<String>> {
+    let query = RequestQuery {
+        webhook_id: webhook_id.to_string(),
+        limit: 1,
+        offset: 0,
+        since: Some(received_at.saturating_sub(SVIX_RETRY_WINDOW_SECONDS)),
+        until: None,
+        sort: SortColumn::ReceivedAt,
+        ascending: true,
+        filters: vec![("svix_id", svix_id.to_string())],
+    };
+    Ok(storage.list_requests(&query).await?.into_iter().next().map(|original| original.id))
+}
@@ -0,0 +1,27 @@
+//! Webhook directory lookups
+//! Ingestion only needs two reads from the webhook database: which webhook a
+//! capture UUID belongs to and that webhook's settings. `Directory` is
+//! implemented for D1 and, under the `local` feature, for in-memory fixtures.
+
+use crate::config::{self, WebhookSettings};
+use worker::*;
+
+#[async_trait::async_trait(?Send)]
+pub trait Directory {
+    /// Webhook ID for a capture UUID (environment UUIDs resolve to their webhook)
+    async fn find_webhook_id(&self, uuid: &str) -> Result<Option<String>>;
+
+    /// Settings for a webhook (defaults when it has none)
+    async fn load_settings(&self, webhook_id: &str) -> Result<WebhookSettings>;
+}
+
+#[async_trait::async_trait(?Send)]
+impl Directory for D1Database {
+    async fn find_webhook_id(&self, uuid: &str) -> Result<Option<String>> {
+        crate::cache::find_in_d1(self, uuid).await
+    }
+
+    async fn load_settings(&self, webhook_id: &str) -> Result<WebhookSettings> {
+        config::load_from_d1(self, webhook_id).await
+    }
+}
@@ -0,0 +1,311 @@
+//! Documentation from observed traffic
+//! `GET /api/webhooks/{uuid}/docs` answers "what does this provider actually
+//! send": the most recent captures are grouped by event type, and each event
+//! type's JSON bodies are walked to infer its fields (path, JSON types, the
+//! share of deliveries carrying it and, for fields that keep repeating a few
+//! short values, those values). Each event type comes with its latest body as
+//! an example, with every string replaced by a placeholder naming its format,
+//! so the document can be shared without the payloads' contents.
+
+use crate::storage::StoredRequest;
+use chrono::{DateTime, SecondsFormat};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+/// Captures sampled unless `limit` is given
+pub const DEFAULT_SAMPLE: u32 = 200;
+pub const MAX_SAMPLE: u32 = 1000;
+
+/// Listed for a field only when it has at most this many distinct values
+const MAX_VALUES: usize = 5;
+
+/// Longest value still considered an enum member
+const MAX_VALUE_LENGTH: usize = 40;
+
+/// Fields appear with this path component for array elements
+const ARRAY: &str = "[]";
+
+/// Heading of captures without an event type
+const UNTYPED: &str = "(no event type)";
+
+/// One inferred field of an event type
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldDoc {
+    /// Dotted path, `[]` for array elements: `data.object.items[].price`
+    pub path: String,
+    /// JSON types seen: `string`, `integer`, `number`, `boolean`, `null`, `object`, `array`
+    pub types: Vec<&'static str>,
+    /// Deliveries carrying the field
+    pub seen: u64,
+    /// `seen` as a share of the event type's deliveries
+    pub frequency: f64,
+    /// The few short values the field keeps repeating (an enum), empty otherwise
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<String>,
+}
+
+/// Everything observed about one event type
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EventDoc {
+    /// None for captures without an event type
+    pub event_type: Option<String>,
+    pub count: u64,
+    pub first_seen_ms: i64,
+    pub last_seen_ms: i64,
+    pub content_types: Vec<String>,
+    /// Deliveries whose body was not JSON
+    pub non_json: u64,
+    pub fields: Vec<FieldDoc>,
+    /// Latest JSON body, strings redacted
+    pub example: Option<Value>,
+}
+
+/// The whole document
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WebhookDocs {
+    pub webhook_id: String,
+    /// Captures the document was inferred from
+    pub sampled: u64,
+    /// Most frequent event type first
+    pub events: Vec<EventDoc>,
+}
+
+#[derive(Default)]
+struct FieldStats {
+    types: BTreeSet<&'static str>,
+    seen: u64,
+    /// Distinct enum-like values, None once the field stopped looking like an enum
+    values: Option<BTreeSet<String>>,
+}
+
+#[derive(Default)]
+struct EventStats {
+    count: u64,
+    first_seen_ms: i64,
+    last_seen_ms: i64,
+    content_types: BTreeSet<String>,
+    non_json: u64,
+    fields: BTreeMap<String, FieldStats>,
+    example: Option<(i64, Value)>,
+}
+
+/// Infer the document from sampled captures (any order)
+pub fn infer(webhook_id: &str, captures: &[StoredRequest]) -> WebhookDocs {
+    let mut events: BTreeMap<Option<String>, EventStats> = BTreeMap::new();
+    for capture in captures {
+        let received_at_ms = capture.received_at_ms.unwrap_or(capture.received_at * 1000);
+        let stats = events.entry(capture.event_type.clone()).or_default();
+        if stats.count == 0 {
+            stats.first_seen_ms = received_at_ms;
+            stats.last_seen_ms = received_at_ms;
+        }
+        stats.count += 1;
+        stats.first_seen_ms = stats.first_seen_ms.min(received_at_ms);
+        stats.last_seen_ms = stats.last_seen_ms.max(received_at_ms);
+        if let Some(content_type) = &capture.content_type {
+            let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+            stats.content_types.insert(essence);
+        }
+        // Form-encoded providers (Slack, Twilio) have their decoded form in `canonical_data`
+        let body = capture.canonical_data.as_deref().unwrap_or(&capture.data);
+        let Some(body) = serde_json::from_str::<Value>(body).ok().filter(|body| body.is_object() || body.is_array())
+        else {
+            stats.non_json += 1;
+            continue;
+        };
+        let mut seen = HashSet::new();
+        walk(&body, String::new(), &mut stats.fields, &mut seen);
+        for path in seen {
+            if let Some(field) = stats.fields.get_mut(&path) {
+                field.seen += 1;
+            }
+        }
+        if stats.example.as_ref().is_none_or(|(latest, _)| received_at_ms >= *latest) {
+            stats.example = Some((received_at_ms, body));
+        }
+    }
+
+    let mut events: Vec<EventDoc> = events
+        .into_iter()
+        .map(|(event_type, stats)| {
+            let parsed = (stats.count - stats.non_json).max(1) as f64;
+            let fields = stats
+                .fields
+                .into_iter()
+                .map(|(path, field)| {
+                    let values = field
+                        .values
+                        .filter(|values| !values.is_empty() && values.len() as u64 * 2 <= field.seen)
+                        .map(|values| values.into_iter().collect())
+                        .unwrap_or_default();
+                    FieldDoc {
+                        path,
+                        types: field.types.into_iter().collect(),
+                        seen: field.seen,
+                        frequency: field.seen as f64 / parsed,
+                        values,
+                    }
+                })
+                .collect();
+            EventDoc {
+                event_type,
+                count: stats.count,
+                first_seen_ms: stats.first_seen_ms,
+                last_seen_ms: stats.last_seen_ms,
+                content_types: stats.content_types.into_iter().collect(),
+                non_json: stats.non_json,
+                fields,
+                example: stats.example.map(|(_, body)| redact(&body)),
+            }
+        })
+        .collect();
+    events.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.event_type.cmp(&b.event_type)));
+    WebhookDocs {
+        webhook_id: webhook_id.to_string(),
+        sampled: captures.len() as u64,
+        events,
+    }
+}
+
+fn walk(value: &Value, path: String, fields: &mut BTreeMap<String, FieldStats>, seen: &mut HashSet<String>) {
+    if !path.is_empty() {
+        let field = fields.entry(path.clone()).or_insert_with(|| FieldStats {
+            values: Some(BTreeSet::new()),
+            ..FieldStats::default()
+        });
+        field.types.insert(type_name(value));
+        match value {
+            Value::String(text) if is_enum_like(text) => {
+                if let Some(values) = &mut field.values {
+                    values.insert(text.clone());
+                }
+            }
+            _ => field.values = None,
+        }
+        if field.values.as_ref().is_some_and(|values| values.len() > MAX_VALUES) {
+            field.values = None;
+        }
+        seen.insert(path.clone());
+    }
+    match value {
+        Value::Object(members) => {
+            for (name, member) in members {
+                let child = if path.is_empty() { name.clone() } else { format!("{}.{}", path, name) };
+                walk(member, child, fields, seen);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                walk(item, format!("{}{}", path, ARRAY), fields, seen);
+            }
+        }
+        _ => {}
+    }
+}
+
+pub(crate) fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_i64() || number.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Short identifier-like text (`customer.created`, `active`), not prose or data
+fn is_enum_like(text: &str) -> bool {
+    !text.is_empty()
+        && text.len() <= MAX_VALUE_LENGTH
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || "_-.:/".contains(c))
+}
+
+/// `value` with every string replaced by a placeholder naming its format
+pub fn redact(value: &Value) -> Value {
+    match value {
+        Value::String(text) => Value::String(format!("<{}>", string_format(text))),
+        Value::Array(items) => Value::Array(items.iter().map(redact).collect()),
+        Value::Object(members) => {
+            let members: Map<String, Value> =
+                members.iter().map(|(name, value)| (name.clone(), redact(value))).collect();
+            Value::Object(members)
+        }
+        value => value.clone(),
+    }
+}
+
+fn string_format(text: &str) -> &'static str {
+    if DateTime::parse_from_rfc3339(text).is_ok() {
+        "date-time"
+    } else if text.starts_with("https://") || text.starts_with("http://") {
+        "uri"
+    } else if text.contains('@') && !text.contains(char::is_whitespace) {
+        "email"
+    } else if uuid::Uuid::parse_str(text).is_ok() {
+        "uuid"
+    } else {
+        "string"
+    }
+}
+
+/// The document as Markdown
+pub fn markdown(docs: &WebhookDocs, uuid: &str) -> String {
+    let mut out = format!("# What webhook `{}` receives\n\n", uuid);
+    if docs.events.is_empty() {
+        out.push_str("No captures to learn from yet.\n");
+        return out;
+    }
+    out.push_str(&format!(
+        "Inferred from the {} most recent captures: {} event type{}.\n",
+        docs.sampled,
+        docs.events.len(),
+        if docs.events.len() == 1 { "" } else { "s" },
+    ));
+    for event in &docs.events {
+        let name = event.event_type.as_deref().map_or(UNTYPED.to_string(), |name| format!("`{}`", name));
+        out.push_str(&format!("\n## {}\n\n", name));
+        out.push_str(&format!(
+            "{} of {} captures ({:.0}%), first seen {}, last seen {}",
+            event.count,
+            docs.sampled,
+            event.count as f64 * 100.0 / docs.sampled.max(1) as f64,
+            rfc3339(event.first_seen_ms),
+            rfc3339(event.last_seen_ms),
+        ));
+        if !event.content_types.is_empty() {
+            let content_types: Vec<String> = event.content_types.iter().map(|name| format!("`{}`", name)).collect();
+            out.push_str(&format!("; sent as {}", content_types.join(", ")));
+        }
+        out.push_str(".\n");
+        if event.non_json > 0 {
+            out.push_str(&format!("\n{} of them carried a body that is not JSON.\n", event.non_json));
+        }
+        if !event.fields.is_empty() {
+            out.push_str("\n| Field | Type | Present | Values |\n|---|---|---|---|\n");
+            for field in &event.fields {
+                let values: Vec<String> = field.values.iter().map(|value| format!("`{}`", value)).collect();
+                out.push_str(&format!(
+                    "| `{}` | {} | {:.0}% | {} |\n",
+                    field.path,
+                    field.types.join(" \\| "),
+                    field.frequency * 100.0,
+                    values.join(", "),
+                ));
+            }
+        }
+        if let Some(example) = &event.example {
+            let example = serde_json::to_string_pretty(example).unwrap_or_default();
+            out.push_str(&format!("\nLatest payload, strings redacted:\n\n```json\n{}\n```\n", example));
+        }
+    }
+    out
+}
+
+fn rfc3339(ms: i64) -> String {
+    DateTime::from_timestamp_millis(ms)
+        .map(|time| time.to_rfc3339_opts(SecondsFormat::Secs, true))
+        .unwrap_or_default()
+}
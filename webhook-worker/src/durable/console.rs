@@ -0,0 +1,238 @@
+//! Debug console sessions
+//! The webhook's `WebhookConsole` Durable Object holds its open debug
+//! consoles (hibernating WebSockets, each tagged with a connection ID and
+//! carrying who opened it) and the session state they share (see
+//! `console.rs`). Ingestion pushes captures here only while the session's
+//! KV copy says a console is open.
+
+use crate::api::requests::find_request;
+use crate::audit::{self, AuditEntry};
+use crate::capture_log;
+use crate::config;
+use crate::console::{self, Command, ConsoleState};
+use crate::ids;
+use crate::replay;
+use crate::storage::{self, CaptureRecord, Consistency, StoredRequest};
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+/// Header carrying the attendee (JSON) from the worker to the DO
+const ATTENDEE_HEADER: &str = "X-Console-Attendee";
+
+/// DO storage key of the session state
+const STATE_KEY: &str = "state";
+
+/// Who opened a console, kept as its socket's attachment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attendee {
+    pub webhook_id: String,
+    pub uuid: String,
+    /// Token or user that connected (see `auth.rs`)
+    pub actor: String,
+    /// Capture URL (`/w/{uuid}`) replays are parsed as received on
+    pub capture_url: String,
+}
+
+fn stub(env: &Env, webhook_id: &str) -> Result<Stub> {
+    env.durable_object("WEBHOOK_CONSOLE")?
+        .id_from_name(webhook_id)?
+        .get_stub()
+}
+
+/// Push a capture to the webhook's open consoles
+pub async fn publish(env: &Env, record: &CaptureRecord) -> Result<()> {
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_body(Some(serde_json::to_string(&StoredRequest::from(record))?.into()));
+    let request = Request::new_with_init("https://webhook-console/publish", &init)?;
+    stub(env, &record.webhook_id)?.fetch_with_request(request).await?;
+    Ok(())
+}
+
+/// Hand a console's WebSocket upgrade to the webhook's DO
+pub async fn connect(env: &Env, attendee: &Attendee) -> Result<Response> {
+    let headers = Headers::new();
+    headers.set("Upgrade", "websocket")?;
+    headers.set(ATTENDEE_HEADER, &serde_json::to_string(attendee)?)?;
+    let mut init = RequestInit::new();
+    init.with_method(Method::Get).with_headers(headers);
+    let request = Request::new_with_init("https://webhook-console/connect", &init)?;
+    stub(env, &attendee.webhook_id)?.fetch_with_request(request).await
+}
+
+#[cfg_attr(feature = "entrypoints", durable_object)]
+pub struct WebhookConsole {
+    state: State,
+    env: Env,
+}
+
+impl WebhookConsole {
+    async fn session(&self) -> ConsoleState {
+        self.state.storage().get(STATE_KEY).await.ok().flatten().unwrap_or_default()
+    }
+
+    /// Store the session state and publish it to ingestion
+    async fn save(&self, webhook_id: &str, session: &ConsoleState) -> Result<()> {
+        self.state.storage().put(STATE_KEY, session).await?;
+        console::save(&self.env.kv("WEBHOOK_CACHE")?, webhook_id, session).await;
+        Ok(())
+    }
+
+    fn broadcast(&self, message: &str) {
+        for socket in self.state.get_websockets() {
+            let _ = socket.send_with_str(message);
+        }
+    }
+
+    async fn accept(&self, attendee: Attendee) -> Result<Response> {
+        let pair = WebSocketPair::new()?;
+        let connection_id = ids::ulid(capture_log::now_ms());
+        self.state.accept_websocket_with_tags(&pair.server, &[&connection_id]);
+        pair.server.serialize_attachment(&attendee)?;
+
+        let session = self.session().await;
+        self.save(&attendee.webhook_id, &session).await?;
+        let hello = serde_json::json!({
+            "type": "hello",
+            "state": session,
+            "consoles": self.state.get_websockets().len(),
+        });
+        pair.server.send_with_str(hello.to_string())?;
+        log_info!("🖥️  Console opened for webhook {} by {}", attendee.webhook_id, attendee.actor);
+        Response::from_websocket(pair.client)
+    }
+
+    /// Resend a capture as `POST /api/webhooks/{uuid}/requests/{id}/replay` does
+    async fn replay(
+        &self,
+        attendee: &Attendee,
+        id: String,
+        overrides: &replay::Overrides,
+    ) -> Result<serde_json::Value> {
+        let db = self.env.d1("DB")?;
+        let storage = storage::for_webhook(&self.env, &attendee.webhook_id, Consistency::Primary).await?;
+        let Some(original) = find_request(storage.as_ref(), &attendee.webhook_id, id).await? else {
+            return Ok(serde_json::json!({ "type": "error", "message": "Request not found" }));
+        };
+        let variant = match replay::apply(&original, overrides) {
+            Ok(variant) => variant,
+            Err(message) => return Ok(serde_json::json!({ "type": "error", "message": message })),
+        };
+        let settings = config::load_from_d1(&db, &attendee.webhook_id).await?;
+        let resend = replay::Resend {
+            env: &self.env,
+            db: &db,
+            storage: storage.as_ref(),
+            webhook_id: &attendee.webhook_id,
+            capture_url: Url::parse(&attendee.capture_url)?,
+            signing: settings.config.forward_signing.as_ref(),
+        };
+        let mut replayed = resend.send(&original, &variant).await?;
+        replayed["webhook_id"] = serde_json::json!(attendee.uuid);
+
+        let entry = AuditEntry {
+            actor: attendee.actor.clone(),
+            ..AuditEntry::system("request.replay")
+        };
+        let entry = entry.target(attendee.uuid.clone()).after(&serde_json::json!({
+            "id": replayed["id"],
+            "replay_of": original.id,
+            "target": variant.target,
+            "console": true,
+        }));
+        audit::record(&db, entry).await;
+        Ok(serde_json::json!({ "type": "replayed", "result": replayed }))
+    }
+}
+
+#[cfg(feature = "entrypoints")]
+impl DurableObject for WebhookConsole {
+    fn new(state: State, env: Env) -> Self {
+        crate::logging::init(&env);
+        Self { state, env }
+    }
+
+    async fn fetch(&self, mut req: Request) -> Result<Response> {
+        match (req.method(), req.path().as_str()) {
+            (Method::Post, "/publish") => {
+                let body = req.text().await?;
+                self.broadcast(&format!(r#"{{"type":"capture","request":{}}}"#, body));
+                Response::from_json(&serde_json::json!({ "sent": self.state.get_websockets().len() }))
+            }
+            (Method::Get, "/connect") => {
+                let attendee = req.headers().get(ATTENDEE_HEADER)?.unwrap_or_default();
+                match serde_json::from_str::<Attendee>(&attendee) {
+                    Ok(attendee) => self.accept(attendee).await,
+                    Err(_) => Response::error("Missing console attendee", 400),
+                }
+            }
+            _ => Response::error("Not Found", 404),
+        }
+    }
+
+    async fn websocket_message(&self, ws: WebSocket, message: WebSocketIncomingMessage) -> Result<()> {
+        let WebSocketIncomingMessage::String(text) = message else {
+            return Ok(());
+        };
+        let Some(attendee) = ws.deserialize_attachment::<Attendee>()? else {
+            let _ = ws.close(Some(1011), Some("Console session lost"));
+            return Ok(());
+        };
+        let command = match Command::parse(&text) {
+            Ok(command) => command,
+            Err(message) => {
+                ws.send_with_str(serde_json::json!({ "type": "error", "message": message }).to_string())?;
+                return Ok(());
+            }
+        };
+
+        let mut session = self.session().await;
+        if session.apply(&command) {
+            self.save(&attendee.webhook_id, &session).await?;
+            let update = serde_json::json!({ "type": "state", "state": session, "by": attendee.actor });
+            self.broadcast(&update.to_string());
+            return Ok(());
+        }
+        // Every message keeps the session's KV copy from expiring
+        self.save(&attendee.webhook_id, &session).await?;
+        match command {
+            Command::Replay { id, overrides } => {
+                let reply = match self.replay(&attendee, id, &overrides).await {
+                    Ok(reply) => reply,
+                    Err(e) => {
+                        log_warn!("⚠️  Console replay failed for webhook {}: {:?}", attendee.webhook_id, e);
+                        serde_json::json!({ "type": "error", "message": "Replay failed" })
+                    }
+                };
+                ws.send_with_str(reply.to_string())?;
+            }
+            _ => ws.send_with_str(r#"{"type":"pong"}"#)?,
+        }
+        Ok(())
+    }
+
+    async fn websocket_close(&self, ws: WebSocket, _code: usize, _reason: String, _was_clean: bool) -> Result<()> {
+        let closing = self.state.get_tags(&ws);
+        let others = self
+            .state
+            .get_websockets()
+            .iter()
+            .filter(|socket| self.state.get_tags(socket) != closing)
+            .count();
+        if others > 0 {
+            return Ok(());
+        }
+        // The last console ends the session
+        self.state.storage().delete(STATE_KEY).await?;
+        if let Some(attendee) = ws.deserialize_attachment::<Attendee>()? {
+            console::close(&self.env.kv("WEBHOOK_CACHE")?, &attendee.webhook_id).await;
+            log_info!("🖥️  Console session ended for webhook {}", attendee.webhook_id);
+        }
+        Ok(())
+    }
+
+    async fn websocket_error(&self, _ws: WebSocket, error: Error) -> Result<()> {
+        log_warn!("⚠️  Console socket error: {:?}", error);
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+//! Counter Durable Object
+//! One object per counter scope (see `counters.rs`). Its requests run one at a
+//! time, so every increment sees the previous one; changes are flushed to D1
+//! by an alarm `FLUSH_INTERVAL_MS` after the first unflushed change.
+
+use crate::counters::{self, Counters, Entry, FLUSH_INTERVAL_MS};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use worker::*;
+
+const STATE_KEY: &str = "counters";
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Stored {
+    /// Scope the object was named after, for the D1 rows
+    scope: String,
+    counters: Counters,
+    flush_scheduled: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Increment {
+    scope: String,
+    key: String,
+    by: i64,
+    ttl_ms: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Add {
+    scope: String,
+    changes: Vec<(String, i64)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Set {
+    scope: String,
+    values: BTreeMap<String, i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Reset {
+    scope: String,
+    key: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Value {
+    value: i64,
+}
+
+fn stub(env: &Env, scope: &str) -> Result<Stub> {
+    env.durable_object("COUNTERS")?.id_from_name(scope)?.get_stub()
+}
+
+fn now_ms() -> i64 {
+    Date::now().as_millis() as i64
+}
+
+async fn post<T: Serialize>(env: &Env, scope: &str, path: &str, body: &T) -> Result<Response> {
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_body(Some(serde_json::to_string(body)?.into()));
+    let request = Request::new_with_init(&format!("https://counter{}", path), &init)?;
+    stub(env, scope)?.fetch_with_request(request).await
+}
+
+/// Add `by` to a counter and return its new value; `ttl_ms` applies when the counter (re)starts
+pub async fn increment(env: &Env, scope: &str, key: &str, by: i64, ttl_ms: Option<i64>) -> Result<i64> {
+    let body = Increment {
+        scope: scope.to_string(),
+        key: key.to_string(),
+        by,
+        ttl_ms,
+    };
+    let mut response = post(env, scope, "/increment", &body).await?;
+    Ok(response.json::<Value>().await?.value)
+}
+
+/// Add to several counters of a scope at once (none of them expire); returns their new values
+pub async fn add(env: &Env, scope: &str, changes: &[(&str, i64)]) -> Result<BTreeMap<String, i64>> {
+    let body = Add {
+        scope: scope.to_string(),
+        changes: changes.iter().map(|(key, by)| (key.to_string(), *by)).collect(),
+    };
+    post(env, scope, "/add", &body).await?.json().await
+}
+
+/// Overwrite counters of a scope, e.g. with a recount
+pub async fn set(env: &Env, scope: &str, values: &BTreeMap<String, i64>) -> Result<()> {
+    let body = Set {
+        scope: scope.to_string(),
+        values: values.clone(),
+    };
+    post(env, scope, "/set", &body).await?;
+    Ok(())
+}
+
+/// Live counters of a scope
+pub async fn snapshot(env: &Env, scope: &str) -> Result<BTreeMap<String, Entry>> {
+    let request = Request::new("https://counter/snapshot", Method::Get)?;
+    stub(env, scope)?.fetch_with_request(request).await?.json().await
+}
+
+/// Forget a counter; false if it did not exist
+pub async fn reset(env: &Env, scope: &str, key: &str) -> Result<bool> {
+    let body = Reset {
+        scope: scope.to_string(),
+        key: key.to_string(),
+    };
+    Ok(post(env, scope, "/reset", &body).await?.status_code() == 200)
+}
+
+#[cfg_attr(feature = "entrypoints", durable_object)]
+pub struct Counter {
+    state: State,
+    env: Env,
+}
+
+impl Counter {
+    async fn load(&self) -> Result<Stored> {
+        Ok(self.state.storage().get(STATE_KEY).await?.unwrap_or_default())
+    }
+
+    /// Save the state, scheduling a flush for the first unflushed change
+    async fn save(&self, mut stored: Stored) -> Result<()> {
+        if stored.counters.is_dirty() && !stored.flush_scheduled {
+            self.state.storage().set_alarm(FLUSH_INTERVAL_MS).await?;
+            stored.flush_scheduled = true;
+        }
+        self.state.storage().put(STATE_KEY, &stored).await
+    }
+}
+
+#[cfg(feature = "entrypoints")]
+impl DurableObject for Counter {
+    fn new(state: State, env: Env) -> Self {
+        crate::logging::init(&env);
+        Self { state, env }
+    }
+
+    async fn fetch(&self, mut req: Request) -> Result<Response> {
+        match (req.method(), req.path().as_str()) {
+            (Method::Post, "/increment") => {
+                let increment: Increment = req.json().await?;
+                let mut stored = self.load().await?;
+                stored.scope = increment.scope;
+                let value = stored.counters.increment(&increment.key, increment.by, increment.ttl_ms, now_ms());
+                self.save(stored).await?;
+                Response::from_json(&Value { value })
+            }
+            (Method::Post, "/add") => {
+                let add: Add = req.json().await?;
+                let mut stored = self.load().await?;
+                stored.scope = add.scope;
+                let now = now_ms();
+                let values: BTreeMap<String, i64> = add
+                    .changes
+                    .iter()
+                    .map(|(key, by)| (key.clone(), stored.counters.increment(key, *by, None, now)))
+                    .collect();
+                self.save(stored).await?;
+                Response::from_json(&values)
+            }
+            (Method::Post, "/set") => {
+                let set: Set = req.json().await?;
+                let mut stored = self.load().await?;
+                stored.scope = set.scope;
+                for (key, value) in &set.values {
+                    stored.counters.set(key, *value);
+                }
+                self.save(stored).await?;
+                Response::ok("set")
+            }
+            (Method::Post, "/reset") => {
+                let reset: Reset = req.json().await?;
+                let mut stored = self.load().await?;
+                stored.scope = reset.scope;
+                let existed = stored.counters.reset(&reset.key);
+                self.save(stored).await?;
+                if existed {
+                    Response::ok("reset")
+                } else {
+                    Response::error("No such counter", 404)
+                }
+            }
+            (Method::Get, "/snapshot") => Response::from_json(&self.load().await?.counters.snapshot(now_ms())),
+            _ => Response::error("Not Found", 404),
+        }
+    }
+
+    async fn alarm(&self) -> Result<Response> {
+        let mut stored = self.load().await?;
+        stored.flush_scheduled = false;
+        let now = now_ms();
+        let changes = stored.counters.take_dirty();
+        let flushed = match self.env.d1("DB") {
+            Ok(db) => counters::flush(&db, &stored.scope, &changes, now).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = flushed {
+            // Keep the changes for the next flush; the live values are unaffected
+            log_warn!("⚠️  Failed to flush counters of {}: {:?}", stored.scope, e);
+            stored.counters.restore(&changes);
+        }
+        stored.counters.expire(now);
+        self.save(stored).await?;
+        Response::ok("flushed")
+    }
+}
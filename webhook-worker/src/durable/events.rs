@@ -0,0 +1,163 @@
+//! Per-webhook live events
+//! Ingestion publishes every capture to the webhook's `WebhookEvents` Durable
+//! Object, which hands it to long-poll waiters and streams it to tail
+//! subscribers as NDJSON. Nothing is stored here: captured data stays in the
+//! storage backend. Ingestion publishes after answering the sender; a DO with
+//! no waiters or subscribers drops the capture without parsing it.
+
+use crate::storage::{CaptureRecord, StoredRequest};
+use futures_channel::{mpsc, oneshot};
+use futures_util::StreamExt;
+use futures_util::future::{select, Either};
+use std::cell::RefCell;
+use std::time::Duration;
+use worker::*;
+
+fn stub(env: &Env, webhook_id: &str) -> Result<Stub> {
+    env.durable_object("WEBHOOK_EVENTS")?
+        .id_from_name(webhook_id)?
+        .get_stub()
+}
+
+/// Notify waiters of a new capture
+pub async fn publish(env: &Env, record: &CaptureRecord) -> Result<()> {
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_body(Some(serde_json::to_string(&StoredRequest::from(record))?.into()));
+    let request = Request::new_with_init("https://webhook-events/publish", &init)?;
+    stub(env, &record.webhook_id)?.fetch_with_request(request).await?;
+    Ok(())
+}
+
+/// Wait up to `timeout` for the next capture of a webhook
+pub async fn wait(env: &Env, webhook_id: &str, timeout: Duration) -> Result<Option<StoredRequest>> {
+    let url = format!("https://webhook-events/wait?timeout_ms={}", timeout.as_millis());
+    let mut response = stub(env, webhook_id)?
+        .fetch_with_request(Request::new(&url, Method::Get)?)
+        .await?;
+    match response.status_code() {
+        200 => Ok(Some(response.json::<StoredRequest>().await?)),
+        _ => Ok(None),
+    }
+}
+
+/// Open an NDJSON stream of new captures matching `filters` (column, value)
+pub async fn subscribe(env: &Env, webhook_id: &str, filters: &[(&str, String)]) -> Result<ByteStream> {
+    let mut url = Url::parse("https://webhook-events/subscribe")?;
+    for (column, value) in filters {
+        url.query_pairs_mut().append_pair(column, value);
+    }
+    let mut response = stub(env, webhook_id)?
+        .fetch_with_request(Request::new(url.as_str(), Method::Get)?)
+        .await?;
+    response.stream()
+}
+
+/// Whether live events are enabled (`LIVE_EVENTS` var, on unless "false")
+pub fn is_enabled(env: &Env) -> bool {
+    env.var("LIVE_EVENTS")
+        .map(|value| value.to_string() != "false")
+        .unwrap_or(true)
+}
+
+/// An open tail stream and its server-side filters
+struct Subscriber {
+    sender: mpsc::UnboundedSender<String>,
+    filters: Vec<(String, String)>,
+}
+
+impl Subscriber {
+    fn matches(&self, request: &serde_json::Value) -> bool {
+        self.filters
+            .iter()
+            .all(|(column, value)| request.get(column).and_then(|field| field.as_str()) == Some(value.as_str()))
+    }
+}
+
+#[cfg_attr(feature = "entrypoints", durable_object)]
+pub struct WebhookEvents {
+    /// Pending long-poll waiters, each expecting one serialized capture
+    waiters: RefCell<Vec<oneshot::Sender<String>>>,
+    /// Open NDJSON tail streams
+    subscribers: RefCell<Vec<Subscriber>>,
+}
+
+#[cfg(feature = "entrypoints")]
+impl DurableObject for WebhookEvents {
+    fn new(_state: State, env: Env) -> Self {
+        crate::logging::init(&env);
+        Self {
+            waiters: RefCell::new(Vec::new()),
+            subscribers: RefCell::new(Vec::new()),
+        }
+    }
+
+    async fn fetch(&self, mut req: Request) -> Result<Response> {
+        match (req.method(), req.path().as_str()) {
+            (Method::Post, "/publish") => {
+                let body = req.text().await?;
+                if self.waiters.borrow().is_empty() && self.subscribers.borrow().is_empty() {
+                    return Response::from_json(&serde_json::json!({ "delivered": 0 }));
+                }
+                let waiters: Vec<_> = self.waiters.borrow_mut().drain(..).collect();
+                let mut delivered = waiters
+                    .into_iter()
+                    .filter_map(|waiter| waiter.send(body.clone()).ok())
+                    .count();
+
+                // Stream to matching subscribers, dropping ones whose client went away
+                let request: serde_json::Value = serde_json::from_str(&body)?;
+                self.subscribers.borrow_mut().retain(|subscriber| {
+                    if !subscriber.matches(&request) {
+                        return !subscriber.sender.is_closed();
+                    }
+                    let sent = subscriber.sender.unbounded_send(format!("{}\n", body)).is_ok();
+                    delivered += sent as usize;
+                    sent
+                });
+
+                Response::from_json(&serde_json::json!({ "delivered": delivered }))
+            }
+            (Method::Get, "/wait") => {
+                let timeout_ms = req
+                    .url()?
+                    .query_pairs()
+                    .find(|(key, _)| key == "timeout_ms")
+                    .and_then(|(_, value)| value.parse::<u64>().ok())
+                    .unwrap_or(30_000);
+
+                let (sender, receiver) = oneshot::channel();
+                self.waiters.borrow_mut().push(sender);
+
+                let timeout = Delay::from(Duration::from_millis(timeout_ms));
+                match select(receiver, timeout).await {
+                    Either::Left((Ok(body), _)) => {
+                        let mut response = Response::ok(body)?;
+                        response.headers_mut().set("Content-Type", "application/json")?;
+                        Ok(response)
+                    }
+                    _ => {
+                        // Drop our sender (and any other closed ones) on timeout
+                        self.waiters.borrow_mut().retain(|waiter| !waiter.is_canceled());
+                        Response::empty().map(|response| response.with_status(204))
+                    }
+                }
+            }
+            (Method::Get, "/subscribe") => {
+                let filters = req
+                    .url()?
+                    .query_pairs()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect();
+                let (sender, receiver) = mpsc::unbounded::<String>();
+                self.subscribers.borrow_mut().push(Subscriber { sender, filters });
+
+                let stream = receiver.map(|line| Ok::<Vec<u8>, Error>(line.into_bytes()));
+                let mut response = Response::from_stream(stream)?;
+                response.headers_mut().set("Content-Type", "application/x-ndjson")?;
+                Ok(response)
+            }
+            _ => Response::error("Not Found", 404),
+        }
+    }
+}
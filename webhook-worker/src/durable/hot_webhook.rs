@@ -0,0 +1,133 @@
+//! Hot webhook store
+//! Per-webhook Durable Object with SQLite storage for webhooks that receive
+//! hundreds of requests per second. Captures are appended to the DO's local
+//! SQLite (strictly ordered, single-writer) and flushed to the configured
+//! storage backend in batches by an alarm.
+
+use crate::storage::{self, CaptureRecord};
+use serde::Deserialize;
+use worker::*;
+
+/// Delay between the first buffered capture and the flush alarm
+const FLUSH_DELAY_MS: i64 = 2_000;
+
+/// Maximum captures written to the backend per alarm run
+const FLUSH_BATCH_SIZE: usize = 200;
+
+#[derive(Deserialize)]
+struct BufferedRow {
+    seq: i64,
+    record: String,
+}
+
+/// Whether captures for `uuid` should go through the hot store (`HOT_WEBHOOKS` var)
+pub fn is_hot(env: &Env, uuid: &str) -> bool {
+    env.var("HOT_WEBHOOKS")
+        .map(|value| value.to_string().split(',').any(|hot| hot.trim() == uuid))
+        .unwrap_or(false)
+}
+
+/// Append a capture to the webhook's hot store
+pub async fn enqueue(env: &Env, record: &CaptureRecord) -> Result<()> {
+    let stub = env
+        .durable_object("HOT_WEBHOOK")?
+        .id_from_name(&record.webhook_id)?
+        .get_stub()?;
+
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_body(Some(serde_json::to_string(record)?.into()));
+    let request = Request::new_with_init("https://hot-webhook/capture", &init)?;
+
+    let response = stub.fetch_with_request(request).await?;
+    if response.status_code() != 200 {
+        return Err(Error::RustError(format!(
+            "Hot store rejected capture with status {}",
+            response.status_code()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg_attr(feature = "entrypoints", durable_object)]
+pub struct HotWebhook {
+    state: State,
+    env: Env,
+}
+
+impl HotWebhook {
+    fn sql(&self) -> SqlStorage {
+        self.state.storage().sql()
+    }
+
+    fn ensure_schema(&self) -> Result<()> {
+        self.sql().exec(
+            "CREATE TABLE IF NOT EXISTS captures (seq INTEGER PRIMARY KEY AUTOINCREMENT, record TEXT NOT NULL)",
+            None,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "entrypoints")]
+impl DurableObject for HotWebhook {
+    fn new(state: State, env: Env) -> Self {
+        crate::logging::init(&env);
+        Self { state, env }
+    }
+
+    async fn fetch(&self, mut req: Request) -> Result<Response> {
+        if req.method() != Method::Post || req.path() != "/capture" {
+            return Response::error("Not Found", 404);
+        }
+
+        let record = req.text().await?;
+        self.ensure_schema()?;
+        self.sql()
+            .exec("INSERT INTO captures (record) VALUES (?)", vec![record.into()])?;
+
+        // Schedule a flush unless one is already pending
+        let storage = self.state.storage();
+        if storage.get_alarm().await?.is_none() {
+            storage.set_alarm(FLUSH_DELAY_MS).await?;
+        }
+
+        Response::ok("buffered")
+    }
+
+    async fn alarm(&self) -> Result<Response> {
+        self.ensure_schema()?;
+
+        let rows: Vec<BufferedRow> = self
+            .sql()
+            .exec(
+                "SELECT seq, record FROM captures ORDER BY seq LIMIT ?",
+                vec![(FLUSH_BATCH_SIZE as i64).into()],
+            )?
+            .to_array()?;
+
+        let Some(last_seq) = rows.last().map(|row| row.seq) else {
+            return Response::ok("empty");
+        };
+
+        let records = rows
+            .iter()
+            .map(|row| serde_json::from_str::<CaptureRecord>(&row.record))
+            .collect::<serde_json::Result<Vec<_>>>()?;
+
+        // On failure the rows stay buffered and the alarm is retried by the runtime
+        let backend = storage::from_env(&self.env).await?;
+        backend.insert_captures(&records).await?;
+
+        self.sql()
+            .exec("DELETE FROM captures WHERE seq <= ?", vec![last_seq.into()])?;
+        log_info!("🔥 Flushed {} hot captures", records.len());
+
+        // Keep draining if the buffer still holds a backlog
+        if rows.len() == FLUSH_BATCH_SIZE {
+            self.state.storage().set_alarm(0).await?;
+        }
+
+        Response::ok("flushed")
+    }
+}
@@ -0,0 +1,132 @@
+//! Background job runner
+//! One Durable Object per job (see `jobs.rs`). Starting it sets an alarm; each
+//! alarm reloads the job from D1, runs one step, records the progress and
+//! sets the next alarm until the job is done, failed or cancelled. Export
+//! output is kept in the object's storage as numbered chunks, served by
+//! `GET /api/webhooks/{uuid}/jobs/{id}/download`, and wiped with everything
+//! else once the job is past `RETENTION_DAYS`.
+
+use crate::jobs::{self, Status, RETENTION_DAYS};
+use worker::*;
+
+const JOB_KEY: &str = "job";
+
+fn stub(env: &Env, job_id: &str) -> Result<Stub> {
+    env.durable_object("JOB_RUNNER")?.id_from_name(job_id)?.get_stub()
+}
+
+fn now_ms() -> i64 {
+    Date::now().as_millis() as i64
+}
+
+fn chunk_key(index: u32) -> String {
+    format!("chunk:{:06}", index)
+}
+
+/// Start running a stored job
+pub async fn start(env: &Env, job_id: &str) -> Result<()> {
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post).with_body(Some(job_id.into()));
+    let request = Request::new_with_init("https://job-runner/start", &init)?;
+    stub(env, job_id)?.fetch_with_request(request).await?;
+    Ok(())
+}
+
+/// One chunk of an export job's file; None past the last one
+pub async fn chunk(env: &Env, job_id: &str, index: u32) -> Result<Option<String>> {
+    let request = Request::new(&format!("https://job-runner/chunks/{}", index), Method::Get)?;
+    let mut response = stub(env, job_id)?.fetch_with_request(request).await?;
+    match response.status_code() {
+        200 => Ok(Some(response.text().await?)),
+        _ => Ok(None),
+    }
+}
+
+#[cfg_attr(feature = "entrypoints", durable_object)]
+pub struct JobRunner {
+    state: State,
+    env: Env,
+}
+
+#[cfg(feature = "entrypoints")]
+impl DurableObject for JobRunner {
+    fn new(state: State, env: Env) -> Self {
+        crate::logging::init(&env);
+        Self { state, env }
+    }
+
+    async fn fetch(&self, mut req: Request) -> Result<Response> {
+        let path = req.path();
+        match (req.method(), path.strip_prefix("/chunks/")) {
+            (Method::Post, None) if path == "/start" => {
+                self.state.storage().put(JOB_KEY, req.text().await?).await?;
+                self.state.storage().set_alarm(0).await?;
+                Response::ok("started")
+            }
+            (Method::Get, Some(index)) => {
+                let Ok(index) = index.parse::<u32>() else {
+                    return Response::error("Not Found", 404);
+                };
+                match self.state.storage().get::<String>(&chunk_key(index)).await? {
+                    Some(chunk) => Response::ok(chunk),
+                    None => Response::error("Not Found", 404),
+                }
+            }
+            _ => Response::error("Not Found", 404),
+        }
+    }
+
+    async fn alarm(&self) -> Result<Response> {
+        let db = self.env.d1("DB")?;
+        let Some(job_id) = self.state.storage().get::<String>(JOB_KEY).await? else {
+            return Response::ok("idle");
+        };
+        let Some(mut job) = jobs::get(&db, &job_id).await? else {
+            self.state.storage().delete_all().await?;
+            return Response::ok("gone");
+        };
+        if let Some(finished_at_ms) = job.finished_at_ms {
+            // Woken up for cleanup, or cancelled since the last step
+            let expires_at_ms = finished_at_ms + RETENTION_DAYS * 86_400_000;
+            if now_ms() >= expires_at_ms {
+                self.state.storage().delete_all().await?;
+            } else {
+                self.state.storage().set_alarm(expires_at_ms - now_ms()).await?;
+            }
+            return Response::ok("finished");
+        }
+
+        job.status = Status::Running;
+        let next_in_ms = match jobs::step(&self.env, &mut job).await {
+            Ok(step) => {
+                if let Some(chunk) = step.chunk {
+                    self.state.storage().put(&chunk_key(job.cursor.chunks - 1), chunk).await?;
+                }
+                if step.next_in_ms.is_none() {
+                    job.finish(Status::Succeeded, now_ms());
+                }
+                step.next_in_ms
+            }
+            Err(e) => {
+                log_error!("❌ Job {} failed: {:?}", job.id, e);
+                job.error = Some(e.to_string());
+                job.finish(Status::Failed, now_ms());
+                None
+            }
+        };
+        job.updated_at_ms = now_ms();
+        if !jobs::save(&db, &job).await? {
+            // Cancelled while the step ran; stay around until the retention runs out
+            self.state.storage().set_alarm(RETENTION_DAYS * 86_400_000).await?;
+            return Response::ok("cancelled");
+        }
+        match next_in_ms {
+            Some(delay) => self.state.storage().set_alarm(delay as i64).await?,
+            None => {
+                log_info!("🧰 Job {} {:?} after {} items", job.id, job.status, job.done);
+                self.state.storage().set_alarm(RETENTION_DAYS * 86_400_000).await?;
+            }
+        }
+        Response::ok("stepped")
+    }
+}
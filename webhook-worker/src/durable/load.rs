@@ -0,0 +1,290 @@
+//! Synthetic load generator
+//! Per-webhook Durable Object that sends synthetic deliveries to a capture URL
+//! at a fixed rate, one batch per one-second alarm tick, so downstream
+//! consumers and the forwarding pipeline can be exercised under load. Runs
+//! are started and stopped by admins and end on their own after their duration.
+//!
+//! Deliveries carry `X-Synthetic-Load: {run_id}` and, unless the plan sets a
+//! body, `{"synthetic":true,"run_id":...,"seq":n,"sent_at_ms":...}`.
+
+use crate::ids;
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+/// Highest supported rate (deliveries per second)
+pub const MAX_RATE_PER_SECOND: u32 = 100;
+
+/// Longest supported run
+pub const MAX_DURATION_SECONDS: u32 = 3_600;
+
+/// Header identifying synthetic deliveries
+pub const SYNTHETIC_HEADER: &str = "X-Synthetic-Load";
+
+const TICK_MS: i64 = 1_000;
+
+const RUN_KEY: &str = "run";
+
+fn default_method() -> String {
+    "POST".to_string()
+}
+
+/// What to send, and how fast
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LoadPlan {
+    /// Capture URL deliveries are sent to
+    #[serde(default)]
+    pub target_url: String,
+    pub rate_per_second: u32,
+    pub duration_seconds: u32,
+    #[serde(default = "default_method")]
+    pub method: String,
+    /// Fixed body for every delivery (the default is a small JSON document)
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub content_type: Option<String>,
+}
+
+impl LoadPlan {
+    /// First problem that would stop the plan from running
+    pub fn validate(&self) -> Option<String> {
+        if !(1..=MAX_RATE_PER_SECOND).contains(&self.rate_per_second) {
+            return Some(format!("rate_per_second must be between 1 and {}", MAX_RATE_PER_SECOND));
+        }
+        if !(1..=MAX_DURATION_SECONDS).contains(&self.duration_seconds) {
+            return Some(format!("duration_seconds must be between 1 and {}", MAX_DURATION_SECONDS));
+        }
+        if !matches!(self.method.as_str(), "POST" | "PUT" | "PATCH" | "GET") {
+            return Some("method must be POST, PUT, PATCH or GET".to_string());
+        }
+        None
+    }
+}
+
+/// A load run and its counters so far
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LoadRun {
+    pub run_id: String,
+    pub plan: LoadPlan,
+    pub started_at_ms: i64,
+    pub ends_at_ms: i64,
+    /// Set once the run completed or was stopped
+    pub finished_at_ms: Option<i64>,
+    pub stopped: bool,
+    pub sent: u64,
+    /// Deliveries answered with a 2xx status
+    pub accepted: u64,
+    pub failed: u64,
+    pub last_status: Option<u16>,
+    pub last_error: Option<String>,
+}
+
+impl LoadRun {
+    pub fn is_running(&self) -> bool {
+        self.finished_at_ms.is_none()
+    }
+}
+
+fn stub(env: &Env, webhook_id: &str) -> Result<Stub> {
+    env.durable_object("LOAD_GENERATOR")?
+        .id_from_name(webhook_id)?
+        .get_stub()
+}
+
+fn now_ms() -> i64 {
+    Date::now().as_millis() as i64
+}
+
+async fn run_from(mut response: Response) -> Result<Option<LoadRun>> {
+    match response.status_code() {
+        200 => Ok(Some(response.json().await?)),
+        _ => Ok(None),
+    }
+}
+
+/// Start a run; None if one is already in progress
+pub async fn start(env: &Env, webhook_id: &str, plan: &LoadPlan) -> Result<Option<LoadRun>> {
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_body(Some(serde_json::to_string(plan)?.into()));
+    let request = Request::new_with_init("https://load-generator/start", &init)?;
+    run_from(stub(env, webhook_id)?.fetch_with_request(request).await?).await
+}
+
+/// Stop the current run; None if the webhook never had one
+pub async fn stop(env: &Env, webhook_id: &str) -> Result<Option<LoadRun>> {
+    let request = Request::new("https://load-generator/stop", Method::Post)?;
+    run_from(stub(env, webhook_id)?.fetch_with_request(request).await?).await
+}
+
+/// The current (or last) run
+pub async fn status(env: &Env, webhook_id: &str) -> Result<Option<LoadRun>> {
+    let request = Request::new("https://load-generator/status", Method::Get)?;
+    run_from(stub(env, webhook_id)?.fetch_with_request(request).await?).await
+}
+
+/// Outcome of one synthetic delivery
+async fn send_one(run: &LoadRun, seq: u64) -> std::result::Result<u16, String> {
+    let plan = &run.plan;
+    let headers = Headers::new();
+    let body = match &plan.body {
+        Some(body) => body.clone(),
+        None => serde_json::json!({
+            "synthetic": true,
+            "run_id": run.run_id,
+            "seq": seq,
+            "sent_at_ms": now_ms(),
+        })
+        .to_string(),
+    };
+    let content_type = plan.content_type.as_deref().unwrap_or("application/json");
+    headers.set(SYNTHETIC_HEADER, &run.run_id).map_err(|e| e.to_string())?;
+
+    let mut init = RequestInit::new();
+    init.with_method(Method::from(plan.method.clone()));
+    if plan.method != "GET" {
+        headers.set("Content-Type", content_type).map_err(|e| e.to_string())?;
+        init.with_body(Some(body.into()));
+    }
+    init.with_headers(headers);
+
+    let request = Request::new_with_init(&plan.target_url, &init).map_err(|e| e.to_string())?;
+    let response = Fetch::Request(request).send().await.map_err(|e| e.to_string())?;
+    Ok(response.status_code())
+}
+
+#[cfg_attr(feature = "entrypoints", durable_object)]
+pub struct LoadGenerator {
+    state: State,
+}
+
+impl LoadGenerator {
+    async fn run(&self) -> Result<Option<LoadRun>> {
+        self.state.storage().get(RUN_KEY).await
+    }
+
+    async fn save(&self, run: &LoadRun) -> Result<()> {
+        self.state.storage().put(RUN_KEY, run).await
+    }
+
+    async fn start(&self, mut req: Request) -> Result<Response> {
+        if let Some(run) = self.run().await? {
+            if run.is_running() {
+                return Response::error("A load run is already in progress", 409);
+            }
+        }
+
+        let plan: LoadPlan = req.json().await?;
+        let started_at_ms = now_ms();
+        let run = LoadRun {
+            run_id: ids::ulid(started_at_ms),
+            ends_at_ms: started_at_ms + plan.duration_seconds as i64 * 1000,
+            plan,
+            started_at_ms,
+            finished_at_ms: None,
+            stopped: false,
+            sent: 0,
+            accepted: 0,
+            failed: 0,
+            last_status: None,
+            last_error: None,
+        };
+        self.save(&run).await?;
+        self.state.storage().set_alarm(0).await?;
+        Response::from_json(&run)
+    }
+
+    async fn stop(&self) -> Result<Response> {
+        let Some(mut run) = self.run().await? else {
+            return Response::error("No load run", 404);
+        };
+        if run.is_running() {
+            run.stopped = true;
+            run.finished_at_ms = Some(now_ms());
+            self.save(&run).await?;
+            self.state.storage().delete_alarm().await?;
+        }
+        Response::from_json(&run)
+    }
+}
+
+#[cfg(feature = "entrypoints")]
+impl DurableObject for LoadGenerator {
+    fn new(state: State, env: Env) -> Self {
+        crate::logging::init(&env);
+        Self { state }
+    }
+
+    async fn fetch(&self, req: Request) -> Result<Response> {
+        match (req.method(), req.path().as_str()) {
+            (Method::Post, "/start") => self.start(req).await,
+            (Method::Post, "/stop") => self.stop().await,
+            (Method::Get, "/status") => match self.run().await? {
+                Some(run) => Response::from_json(&run),
+                None => Response::error("No load run", 404),
+            },
+            _ => Response::error("Not Found", 404),
+        }
+    }
+
+    async fn alarm(&self) -> Result<Response> {
+        let Some(mut run) = self.run().await? else {
+            return Response::ok("idle");
+        };
+        if !run.is_running() {
+            return Response::ok("finished");
+        }
+
+        let tick_started = now_ms();
+        let batch = (run.sent..run.sent + run.plan.rate_per_second as u64).map(|seq| send_one(&run, seq));
+        let outcomes = join_all(batch).await;
+
+        for outcome in outcomes {
+            run.sent += 1;
+            match outcome {
+                Ok(status) if (200..300).contains(&status) => {
+                    run.accepted += 1;
+                    run.last_status = Some(status);
+                }
+                Ok(status) => {
+                    run.failed += 1;
+                    run.last_status = Some(status);
+                }
+                Err(error) => {
+                    run.failed += 1;
+                    run.last_error = Some(error);
+                }
+            }
+        }
+
+        // The run may have been stopped while the batch was in flight
+        if let Some(current) = self.run().await?.filter(|current| current.run_id == run.run_id) {
+            if !current.is_running() {
+                run.stopped = current.stopped;
+                run.finished_at_ms = current.finished_at_ms;
+                self.save(&run).await?;
+                return Response::ok("stopped");
+            }
+        }
+
+        let now = now_ms();
+        if now + TICK_MS > run.ends_at_ms {
+            run.finished_at_ms = Some(now);
+            log_info!(
+                "📈 Load run {} finished: {} sent, {} accepted, {} failed",
+                run.run_id,
+                run.sent,
+                run.accepted,
+                run.failed
+            );
+        } else {
+            // Keep to a one-second cadence however long the batch took
+            let elapsed = now - tick_started;
+            self.state.storage().set_alarm((TICK_MS - elapsed).max(0)).await?;
+        }
+        self.save(&run).await?;
+
+        Response::ok("sent")
+    }
+}
@@ -0,0 +1,12 @@
+//! Durable Objects
+//! Exported DO classes; bindings are declared in wrangler.toml
+
+pub mod console;
+pub mod counter;
+pub mod events;
+pub mod hot_webhook;
+pub mod jobs;
+pub mod load;
+pub mod relay;
+pub mod sequence;
+pub mod socket;
@@ -0,0 +1,356 @@
+//! Local development relay
+//! A locally-running agent opens a WebSocket to the webhook's `WebhookRelay`
+//! Durable Object and receives every capture as it arrives, ngrok style.
+//! Deliveries are kept in the DO's SQLite queue until an agent acks them, so
+//! nothing is lost while the agent is disconnected. Relay tokens are scoped to
+//! one webhook and only their SHA-256 hash is stored (in the DO itself).
+//!
+//! Agent protocol (JSON text frames):
+//! - server → agent: `{"type":"hello","queued":n}`, `{"type":"delivery","request":{...}}`, `{"type":"pong"}`
+//! - agent → server: `{"type":"ack","id":"..."}`, `{"type":"ping"}`
+
+use crate::ids;
+use crate::storage::{CaptureRecord, StoredRequest};
+use crate::tokens;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+/// Prefix that makes leaked relay tokens easy to recognize
+const TOKEN_PREFIX: &str = "rly_";
+
+/// Header carrying the relay token from the worker to the DO
+const TOKEN_HEADER: &str = "X-Relay-Token";
+
+/// Oldest deliveries are dropped beyond this many queued ones
+const MAX_QUEUED: i64 = 1_000;
+
+/// Queued deliveries older than this are dropped
+const QUEUE_TTL_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// A relay token as listed by the management API (never includes the secret)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RelayToken {
+    pub id: String,
+    pub name: String,
+    pub created_at_ms: i64,
+    pub last_connected_at_ms: Option<i64>,
+}
+
+/// Connected agents, queue depth and tokens for a webhook
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RelayStatus {
+    pub connected: usize,
+    pub queued: i64,
+    pub oldest_queued_at_ms: Option<i64>,
+    pub tokens: Vec<RelayToken>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct NewTokenRequest {
+    name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct NewTokenResponse {
+    token: RelayToken,
+    secret: String,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum AgentMessage {
+    Ack { id: String },
+    Ping,
+}
+
+#[derive(Deserialize)]
+struct QueuedRow {
+    body: String,
+}
+
+#[derive(Deserialize)]
+struct QueueStats {
+    queued: i64,
+    oldest_queued_at_ms: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct TokenIdRow {
+    id: String,
+}
+
+fn stub(env: &Env, webhook_id: &str) -> Result<Stub> {
+    env.durable_object("WEBHOOK_RELAY")?
+        .id_from_name(webhook_id)?
+        .get_stub()
+}
+
+fn now_ms() -> i64 {
+    Date::now().as_millis() as i64
+}
+
+/// Queue a capture for the webhook's relay agents (sent immediately if one is connected)
+pub async fn deliver(env: &Env, record: &CaptureRecord) -> Result<()> {
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_body(Some(serde_json::to_string(&StoredRequest::from(record))?.into()));
+    let request = Request::new_with_init("https://webhook-relay/deliver", &init)?;
+    stub(env, &record.webhook_id)?.fetch_with_request(request).await?;
+    Ok(())
+}
+
+/// Hand an agent's WebSocket upgrade to the relay DO, which checks the token
+pub async fn connect(env: &Env, webhook_id: &str, token: &str) -> Result<Response> {
+    let headers = Headers::new();
+    headers.set("Upgrade", "websocket")?;
+    headers.set(TOKEN_HEADER, token)?;
+    let mut init = RequestInit::new();
+    init.with_method(Method::Get).with_headers(headers);
+    let request = Request::new_with_init("https://webhook-relay/connect", &init)?;
+    stub(env, webhook_id)?.fetch_with_request(request).await
+}
+
+/// Relay status for a webhook
+pub async fn status(env: &Env, webhook_id: &str) -> Result<RelayStatus> {
+    stub(env, webhook_id)?
+        .fetch_with_request(Request::new("https://webhook-relay/status", Method::Get)?)
+        .await?
+        .json()
+        .await
+}
+
+/// Create a relay token; the plain secret is only returned here
+pub async fn create_token(env: &Env, webhook_id: &str, name: &str) -> Result<(RelayToken, String)> {
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post).with_body(Some(
+        serde_json::to_string(&NewTokenRequest { name: name.to_string() })?.into(),
+    ));
+    let request = Request::new_with_init("https://webhook-relay/tokens", &init)?;
+    let created: NewTokenResponse = stub(env, webhook_id)?
+        .fetch_with_request(request)
+        .await?
+        .json()
+        .await?;
+    Ok((created.token, created.secret))
+}
+
+/// Revoke a relay token and disconnect agents using it; false if it doesn't exist
+pub async fn revoke_token(env: &Env, webhook_id: &str, id: &str) -> Result<bool> {
+    let mut init = RequestInit::new();
+    init.with_method(Method::Delete);
+    let url = format!("https://webhook-relay/tokens/{}", id);
+    let response = stub(env, webhook_id)?
+        .fetch_with_request(Request::new_with_init(&url, &init)?)
+        .await?;
+    Ok(response.status_code() == 200)
+}
+
+#[cfg_attr(feature = "entrypoints", durable_object)]
+pub struct WebhookRelay {
+    state: State,
+}
+
+impl WebhookRelay {
+    fn sql(&self) -> SqlStorage {
+        self.state.storage().sql()
+    }
+
+    fn ensure_schema(&self) -> Result<()> {
+        self.sql().exec(
+            "CREATE TABLE IF NOT EXISTS relay_tokens (id TEXT PRIMARY KEY, name TEXT NOT NULL, \
+             token_hash TEXT NOT NULL UNIQUE, created_at_ms INTEGER NOT NULL, last_connected_at_ms INTEGER)",
+            None,
+        )?;
+        self.sql().exec(
+            "CREATE TABLE IF NOT EXISTS relay_queue (id TEXT PRIMARY KEY, body TEXT NOT NULL, \
+             queued_at_ms INTEGER NOT NULL)",
+            None,
+        )?;
+        Ok(())
+    }
+
+    /// Store a delivery, enforcing the queue's age and size limits
+    fn enqueue(&self, id: &str, body: &str, now: i64) -> Result<()> {
+        self.sql().exec(
+            "DELETE FROM relay_queue WHERE queued_at_ms < ?",
+            vec![(now - QUEUE_TTL_MS).into()],
+        )?;
+        self.sql().exec(
+            "INSERT OR REPLACE INTO relay_queue (id, body, queued_at_ms) VALUES (?, ?, ?)",
+            vec![id.into(), body.into(), now.into()],
+        )?;
+        self.sql().exec(
+            "DELETE FROM relay_queue WHERE id NOT IN \
+             (SELECT id FROM relay_queue ORDER BY queued_at_ms DESC, id DESC LIMIT ?)",
+            vec![MAX_QUEUED.into()],
+        )?;
+        Ok(())
+    }
+
+    fn queue_stats(&self) -> Result<QueueStats> {
+        self.sql()
+            .exec(
+                "SELECT COUNT(*) AS queued, MIN(queued_at_ms) AS oldest_queued_at_ms FROM relay_queue",
+                None,
+            )?
+            .one()
+    }
+
+    fn tokens(&self) -> Result<Vec<RelayToken>> {
+        self.sql()
+            .exec(
+                "SELECT id, name, created_at_ms, last_connected_at_ms FROM relay_tokens ORDER BY created_at_ms",
+                None,
+            )?
+            .to_array()
+    }
+
+    /// Accept an agent if its token is valid, then replay the queue to it
+    fn accept_agent(&self, token: &str) -> Result<Response> {
+        let rows: Vec<TokenIdRow> = self
+            .sql()
+            .exec(
+                "SELECT id FROM relay_tokens WHERE token_hash = ?",
+                vec![tokens::hash(token).into()],
+            )?
+            .to_array()?;
+        let Some(token_id) = rows.into_iter().next().map(|row| row.id) else {
+            return Response::error("Invalid relay token", 401);
+        };
+        self.sql().exec(
+            "UPDATE relay_tokens SET last_connected_at_ms = ? WHERE id = ?",
+            vec![now_ms().into(), token_id.as_str().into()],
+        )?;
+
+        let pair = WebSocketPair::new()?;
+        // Tag sockets with their token so revoking it can disconnect them
+        self.state.accept_websocket_with_tags(&pair.server, &[&token_id]);
+
+        let queued: Vec<QueuedRow> = self
+            .sql()
+            .exec("SELECT body FROM relay_queue ORDER BY queued_at_ms, id", None)?
+            .to_array()?;
+        pair.server.send_with_str(
+            serde_json::json!({ "type": "hello", "queued": queued.len() }).to_string(),
+        )?;
+        for row in queued {
+            pair.server.send_with_str(delivery_message(&row.body))?;
+        }
+
+        Response::from_websocket(pair.client)
+    }
+}
+
+/// Delivery frame for a serialized `StoredRequest`
+fn delivery_message(body: &str) -> String {
+    format!(r#"{{"type":"delivery","request":{}}}"#, body)
+}
+
+#[cfg(feature = "entrypoints")]
+impl DurableObject for WebhookRelay {
+    fn new(state: State, env: Env) -> Self {
+        crate::logging::init(&env);
+        Self { state }
+    }
+
+    async fn fetch(&self, mut req: Request) -> Result<Response> {
+        self.ensure_schema()?;
+        let path = req.path();
+
+        match (req.method(), path.as_str()) {
+            (Method::Post, "/deliver") => {
+                let body = req.text().await?;
+                let request: StoredRequest = serde_json::from_str(&body)?;
+                self.enqueue(&request.id, &body, now_ms())?;
+
+                let message = delivery_message(&body);
+                let sent = self
+                    .state
+                    .get_websockets()
+                    .iter()
+                    .filter(|socket| socket.send_with_str(&message).is_ok())
+                    .count();
+                Response::from_json(&serde_json::json!({ "sent": sent }))
+            }
+            (Method::Get, "/connect") => match req.headers().get(TOKEN_HEADER)? {
+                Some(token) => self.accept_agent(&token),
+                None => Response::error("Missing relay token", 401),
+            },
+            (Method::Get, "/status") => {
+                let stats = self.queue_stats()?;
+                Response::from_json(&RelayStatus {
+                    connected: self.state.get_websockets().len(),
+                    queued: stats.queued,
+                    oldest_queued_at_ms: stats.oldest_queued_at_ms,
+                    tokens: self.tokens()?,
+                })
+            }
+            (Method::Post, "/tokens") => {
+                let new: NewTokenRequest = req.json().await?;
+                let now = now_ms();
+                let secret = format!(
+                    "{}{}{}",
+                    TOKEN_PREFIX,
+                    uuid::Uuid::new_v4().simple(),
+                    uuid::Uuid::new_v4().simple()
+                );
+                let token = RelayToken {
+                    id: ids::ulid(now),
+                    name: new.name,
+                    created_at_ms: now,
+                    last_connected_at_ms: None,
+                };
+                self.sql().exec(
+                    "INSERT INTO relay_tokens (id, name, token_hash, created_at_ms) VALUES (?, ?, ?, ?)",
+                    vec![
+                        token.id.as_str().into(),
+                        token.name.as_str().into(),
+                        tokens::hash(&secret).into(),
+                        now.into(),
+                    ],
+                )?;
+                Response::from_json(&NewTokenResponse { token, secret })
+            }
+            (Method::Delete, _) if path.starts_with("/tokens/") => {
+                let id = path.trim_start_matches("/tokens/");
+                let deleted = self
+                    .sql()
+                    .exec("DELETE FROM relay_tokens WHERE id = ?", vec![id.into()])?
+                    .rows_written();
+                if deleted == 0 {
+                    return Response::error("Relay token not found", 404);
+                }
+                for socket in self.state.get_websockets_with_tag(id) {
+                    let _ = socket.close(Some(4001), Some("Relay token revoked"));
+                }
+                Response::ok("revoked")
+            }
+            _ => Response::error("Not Found", 404),
+        }
+    }
+
+    async fn websocket_message(&self, ws: WebSocket, message: WebSocketIncomingMessage) -> Result<()> {
+        let WebSocketIncomingMessage::String(text) = message else {
+            return Ok(());
+        };
+        match serde_json::from_str::<AgentMessage>(&text) {
+            Ok(AgentMessage::Ack { id }) => {
+                self.ensure_schema()?;
+                self.sql()
+                    .exec("DELETE FROM relay_queue WHERE id = ?", vec![id.into()])?;
+            }
+            Ok(AgentMessage::Ping) => ws.send_with_str(r#"{"type":"pong"}"#)?,
+            Err(_) => log_info!("⚠️  Ignoring unknown relay message"),
+        }
+        Ok(())
+    }
+
+    async fn websocket_close(&self, _ws: WebSocket, _code: usize, _reason: String, _was_clean: bool) -> Result<()> {
+        Ok(())
+    }
+
+    async fn websocket_error(&self, _ws: WebSocket, error: Error) -> Result<()> {
+        log_warn!("⚠️  Relay socket error: {:?}", error);
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+//! Per-webhook sequence numbers
+//! A Durable Object per webhook hands out strictly increasing sequence numbers,
+//! so consumers can detect gaps and reordering within bursts that share the
+//! same `received_at` second.
+
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+const SEQUENCE_KEY: &str = "sequence";
+
+#[derive(Serialize, Deserialize)]
+struct SequenceResponse {
+    sequence: i64,
+}
+
+/// Reserve the next sequence number for a webhook
+pub async fn next(env: &Env, webhook_id: &str) -> Result<i64> {
+    let stub = env
+        .durable_object("WEBHOOK_SEQUENCE")?
+        .id_from_name(webhook_id)?
+        .get_stub()?;
+
+    let request = Request::new("https://webhook-sequence/next", Method::Post)?;
+    let mut response = stub.fetch_with_request(request).await?;
+    Ok(response.json::<SequenceResponse>().await?.sequence)
+}
+
+#[cfg_attr(feature = "entrypoints", durable_object)]
+pub struct WebhookSequence {
+    state: State,
+}
+
+#[cfg(feature = "entrypoints")]
+impl DurableObject for WebhookSequence {
+    fn new(state: State, env: Env) -> Self {
+        crate::logging::init(&env);
+        Self { state }
+    }
+
+    async fn fetch(&self, req: Request) -> Result<Response> {
+        if req.method() != Method::Post || req.path() != "/next" {
+            return Response::error("Not Found", 404);
+        }
+
+        // Requests to a single DO are serialized by its input gate, so this
+        // read-increment-write cannot interleave with another delivery
+        let storage = self.state.storage();
+        let sequence = storage.get::<i64>(SEQUENCE_KEY).await?.unwrap_or(0) + 1;
+        storage.put(SEQUENCE_KEY, sequence).await?;
+
+        Response::from_json(&SequenceResponse { sequence })
+    }
+}
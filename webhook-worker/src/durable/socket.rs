@@ -0,0 +1,404 @@
+//! WebSocket ingestion
+//! Providers that push events over a persistent socket connect to
+//! `/w/{uuid}/ws`; the upgrade is handed to the webhook's `WebhookSocket`
+//! Durable Object, which accepts it with the hibernation API and captures every
+//! incoming message as its own record (method `WS`, with the frame type and the
+//! connection ID). The handshake headers are kept per connection in the DO's
+//! SQLite and stored with each message. Text frames are stored verbatim,
+//! binary frames base64-encoded.
+//!
+//! `/w/{uuid}/mqtt` (experimental) speaks MQTT 3.1.1 over the same socket as a
+//! write-only broker: CONNECT is acknowledged, every PUBLISH is captured
+//! (method `MQTT`, the topic as event type, `mqtt-*` pseudo headers for topic,
+//! QoS, retain and client ID) and acknowledged per its QoS, subscriptions are
+//! refused.
+
+use crate::capture_log::{self, CaptureEvent};
+use crate::config;
+use crate::durable::sequence;
+use crate::ids;
+use crate::kv::TolerantKv;
+use crate::ingest;
+#[cfg(feature = "mqtt")]
+use crate::mqtt::{self, Packet};
+use crate::pipeline::{self, CaptureMeta, FrameType, IncomingFrame};
+use crate::preview;
+use crate::processing::{Processing, SignedUrl};
+use crate::storage::{self, Consistency};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::Deserialize;
+use std::collections::HashMap;
+use worker::*;
+
+/// Header carrying the capture UUID from the worker to the DO
+const UUID_HEADER: &str = "X-Socket-Uuid";
+
+/// Header carrying the sanitized handshake headers (JSON) from the worker to the DO
+const HEADERS_HEADER: &str = "X-Socket-Headers";
+
+/// WebSocket subprotocol MQTT clients offer and expect echoed
+pub const MQTT_SUBPROTOCOL: &str = "mqtt";
+
+/// Close codes (RFC 6455)
+#[cfg(feature = "mqtt")]
+const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+const CLOSE_UNSUPPORTED_DATA: u16 = 1003;
+
+/// What a capture socket speaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// Every message is a capture
+    WebSocket,
+    /// MQTT packets; PUBLISH messages are captures
+    Mqtt,
+}
+
+impl Protocol {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::WebSocket => "ws",
+            Self::Mqtt => "mqtt",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ws" => Some(Self::WebSocket),
+            "mqtt" => Some(Self::Mqtt),
+            _ => None,
+        }
+    }
+}
+
+/// Handshake of an open connection
+#[derive(Deserialize)]
+struct ConnectionRow {
+    id: String,
+    webhook_id: String,
+    uuid: String,
+    protocol: String,
+    headers: String,
+    /// MQTT client identifier, set once CONNECT was accepted
+    #[cfg(feature = "mqtt")]
+    client_id: Option<String>,
+}
+
+fn stub(env: &Env, webhook_id: &str) -> Result<Stub> {
+    env.durable_object("WEBHOOK_SOCKETS")?
+        .id_from_name(webhook_id)?
+        .get_stub()
+}
+
+/// Hand a capture socket upgrade to the webhook's DO
+pub async fn connect(
+    env: &Env,
+    webhook_id: &str,
+    uuid: &str,
+    protocol: Protocol,
+    headers: &HashMap<String, String>,
+) -> Result<Response> {
+    let forwarded = Headers::new();
+    forwarded.set("Upgrade", "websocket")?;
+    forwarded.set(UUID_HEADER, uuid)?;
+    forwarded.set(HEADERS_HEADER, &serde_json::to_string(headers)?)?;
+    let mut init = RequestInit::new();
+    init.with_method(Method::Get).with_headers(forwarded);
+    let url = format!(
+        "https://webhook-socket/connect?webhook_id={}&protocol={}",
+        webhook_id,
+        protocol.as_str()
+    );
+    stub(env, webhook_id)?
+        .fetch_with_request(Request::new_with_init(&url, &init)?)
+        .await
+}
+
+#[cfg_attr(feature = "entrypoints", durable_object)]
+pub struct WebhookSocket {
+    state: State,
+    env: Env,
+}
+
+impl WebhookSocket {
+    fn sql(&self) -> SqlStorage {
+        self.state.storage().sql()
+    }
+
+    fn ensure_schema(&self) -> Result<()> {
+        self.sql().exec(
+            "CREATE TABLE IF NOT EXISTS socket_connections (id TEXT PRIMARY KEY, webhook_id TEXT NOT NULL, \
+             uuid TEXT NOT NULL, protocol TEXT NOT NULL DEFAULT 'ws', headers TEXT NOT NULL, client_id TEXT, \
+             opened_at_ms INTEGER NOT NULL)",
+            None,
+        )?;
+        Ok(())
+    }
+
+    /// Accept a connection, remembering its handshake for the messages that follow
+    fn accept(&self, webhook_id: &str, uuid: &str, protocol: Protocol, headers: &str) -> Result<Response> {
+        let now = capture_log::now_ms();
+        let connection_id = ids::ulid(now);
+        self.sql().exec(
+            "INSERT INTO socket_connections (id, webhook_id, uuid, protocol, headers, opened_at_ms) \
+             VALUES (?, ?, ?, ?, ?, ?)",
+            vec![
+                connection_id.as_str().into(),
+                webhook_id.into(),
+                uuid.into(),
+                protocol.as_str().into(),
+                headers.into(),
+                now.into(),
+            ],
+        )?;
+
+        let pair = WebSocketPair::new()?;
+        self.state.accept_websocket_with_tags(&pair.server, &[&connection_id]);
+        pair.server.serialize_attachment(&connection_id)?;
+        let mut response = Response::from_websocket(pair.client)?;
+        if protocol == Protocol::Mqtt {
+            response.headers_mut().set("Sec-WebSocket-Protocol", MQTT_SUBPROTOCOL)?;
+        }
+        Ok(response)
+    }
+
+    fn connection(&self, ws: &WebSocket) -> Result<Option<ConnectionRow>> {
+        let Some(connection_id) = ws.deserialize_attachment::<String>()? else {
+            return Ok(None);
+        };
+        self.ensure_schema()?;
+        let rows: Vec<ConnectionRow> = self
+            .sql()
+            .exec(
+                "SELECT id, webhook_id, uuid, protocol, headers, client_id FROM socket_connections WHERE id = ?",
+                vec![connection_id.into()],
+            )?
+            .to_array()?;
+        Ok(rows.into_iter().next())
+    }
+
+    /// Capture one message; mirrors the HTTP path minus forwarding and signatures
+    async fn capture(&self, connection: &ConnectionRow, frame: IncomingFrame) -> Result<()> {
+        let env = &self.env;
+        let webhook_id = &connection.webhook_id;
+        let mut event = CaptureEvent::start(&connection.uuid, &frame.method, frame.received_at_ms);
+        event.webhook_id = Some(webhook_id.clone());
+
+        let result = async {
+            let kv_store = env.kv("WEBHOOK_CACHE")?;
+            let kv = TolerantKv::new(&kv_store);
+            let db = env.d1("DB")?;
+            let settings = config::load(&kv, &db, webhook_id).await?;
+            event.kv_fallback = kv.fell_back();
+            let mut parsed = pipeline::parse_frame(&frame)?;
+            let applied = pipeline::apply(&mut parsed, &connection.uuid, &settings);
+            event.environment = applied.environment.map(|environment| environment.name.clone());
+            event.event_type = parsed.indexed_headers.event_type.clone();
+            event.request_bytes = Some(parsed.size_bytes as i64);
+
+            let sequence = match sequence::next(env, webhook_id).await {
+                Ok(sequence) => Some(sequence),
+                Err(e) => {
+                    log_warn!("⚠️  Failed to assign sequence number: {:?}", e);
+                    None
+                }
+            };
+            let processing = Processing::new(&applied, &settings, Some(SignedUrl::Connection), None);
+            let mut record = pipeline::into_record(
+                parsed,
+                CaptureMeta {
+                    id: ids::new_capture_id(env, frame.received_at_ms),
+                    webhook_id: webhook_id.clone(),
+                    sequence,
+                    verification: None,
+                    environment: applied.environment,
+                },
+            );
+            record.processing = Some(processing.to_json());
+            // Binary frames are stored base64-encoded; preview the decoded payload
+            if frame.frame_type == FrameType::Binary {
+                record.preview = BASE64
+                    .decode(&frame.data)
+                    .ok()
+                    .and_then(|bytes| preview::generate(&bytes, None))
+                    .map(|preview| preview.to_json());
+            }
+
+            let store_started = capture_log::now_ms();
+            storage::open_in(env, Consistency::Primary, settings.jurisdiction)
+                .await?
+                .insert_capture(&record)
+                .await?;
+            event.store_ms = Some(capture_log::now_ms() - store_started);
+            event.data_id = Some(record.id.clone());
+            event.sequence = sequence;
+
+            ingest::fan_out(env, None, &record, &settings).await;
+            Ok::<(), Error>(())
+        }
+        .await;
+
+        match &result {
+            Ok(()) => event.finish(200, None),
+            Err(e) => event.finish(500, Some(e.to_string())),
+        }
+        result
+    }
+
+    /// Handle the packets of one MQTT message, capturing PUBLISHes
+    #[cfg(feature = "mqtt")]
+    async fn handle_mqtt(&self, ws: &WebSocket, connection: &mut ConnectionRow, bytes: &[u8]) -> Result<()> {
+        let Ok(packets) = mqtt::decode_all(bytes) else {
+            return ws.close(Some(CLOSE_PROTOCOL_ERROR), Some("Malformed MQTT packet"));
+        };
+
+        for packet in packets {
+            match &packet {
+                Packet::Connect { protocol_level, client_id } => {
+                    if !mqtt::SUPPORTED_LEVELS.contains(protocol_level) {
+                        ws.send_with_bytes(mqtt::connack(mqtt::UNACCEPTABLE_PROTOCOL))?;
+                        return ws.close(Some(CLOSE_PROTOCOL_ERROR), Some("Unsupported MQTT protocol level"));
+                    }
+                    self.sql().exec(
+                        "UPDATE socket_connections SET client_id = ? WHERE id = ?",
+                        vec![client_id.as_str().into(), connection.id.as_str().into()],
+                    )?;
+                    connection.client_id = Some(client_id.clone());
+                    ws.send_with_bytes(mqtt::connack(mqtt::CONNECT_ACCEPTED))?;
+                }
+                _ if connection.client_id.is_none() => {
+                    return ws.close(Some(CLOSE_PROTOCOL_ERROR), Some("Expected MQTT CONNECT"));
+                }
+                Packet::Publish(publish) => {
+                    let frame = mqtt_frame(connection, publish)?;
+                    if let Err(e) = self.capture(connection, frame).await {
+                        // Unacknowledged QoS 1/2 messages are redelivered by the client
+                        log_warn!("⚠️  Failed to capture MQTT publish: {:?}", e);
+                        continue;
+                    }
+                }
+                Packet::Disconnect => return ws.close(Some(1000), Some("Disconnected")),
+                Packet::Unsupported(kind) => {
+                    log_warn!("⚠️  Unsupported MQTT packet type {}", kind);
+                    return ws.close(Some(CLOSE_PROTOCOL_ERROR), Some("Unsupported MQTT packet"));
+                }
+                _ => {}
+            }
+            if let Some(response) = mqtt::response(&packet) {
+                ws.send_with_bytes(response)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn close_connection(&self, ws: &WebSocket) -> Result<()> {
+        if let Some(connection_id) = ws.deserialize_attachment::<String>()? {
+            self.ensure_schema()?;
+            self.sql()
+                .exec("DELETE FROM socket_connections WHERE id = ?", vec![connection_id.into()])?;
+        }
+        Ok(())
+    }
+}
+
+/// Capture frame for a PUBLISH: handshake headers plus `mqtt-*` pseudo headers,
+/// the payload verbatim when it is UTF-8 and base64-encoded otherwise
+#[cfg(feature = "mqtt")]
+fn mqtt_frame(connection: &ConnectionRow, publish: &mqtt::Publish) -> Result<IncomingFrame> {
+    let mut headers: HashMap<String, String> = serde_json::from_str(&connection.headers)?;
+    headers.insert("mqtt-topic".to_string(), publish.topic.clone());
+    headers.insert("mqtt-qos".to_string(), publish.qos.to_string());
+    headers.insert("mqtt-retain".to_string(), publish.retain.to_string());
+    if let Some(client_id) = &connection.client_id {
+        headers.insert("mqtt-client-id".to_string(), client_id.clone());
+    }
+    let (frame_type, data) = match std::str::from_utf8(&publish.payload) {
+        Ok(text) => (FrameType::Text, text.to_string()),
+        Err(_) => (FrameType::Binary, BASE64.encode(&publish.payload)),
+    };
+    Ok(IncomingFrame {
+        connection_id: connection.id.clone(),
+        method: pipeline::MQTT_METHOD.to_string(),
+        headers,
+        frame_type,
+        data,
+        received_at_ms: capture_log::now_ms(),
+    })
+}
+
+#[cfg(feature = "entrypoints")]
+impl DurableObject for WebhookSocket {
+    fn new(state: State, env: Env) -> Self {
+        crate::logging::init(&env);
+        Self { state, env }
+    }
+
+    async fn fetch(&self, req: Request) -> Result<Response> {
+        self.ensure_schema()?;
+        match (req.method(), req.path().as_str()) {
+            (Method::Get, "/connect") => {
+                let url = req.url()?;
+                let param = |name: &str| {
+                    url.query_pairs()
+                        .find(|(key, _)| key == name)
+                        .map(|(_, value)| value.to_string())
+                };
+                let protocol = param("protocol").as_deref().and_then(Protocol::parse);
+                let uuid = req.headers().get(UUID_HEADER)?;
+                let headers = req.headers().get(HEADERS_HEADER)?;
+                match (param("webhook_id"), protocol, uuid, headers) {
+                    (Some(webhook_id), Some(protocol), Some(uuid), Some(headers)) => {
+                        self.accept(&webhook_id, &uuid, protocol, &headers)
+                    }
+                    _ => Response::error("Malformed socket handshake", 400),
+                }
+            }
+            _ => Response::error("Not Found", 404),
+        }
+    }
+
+    async fn websocket_message(&self, ws: WebSocket, message: WebSocketIncomingMessage) -> Result<()> {
+        #[cfg_attr(not(feature = "mqtt"), allow(unused_mut))]
+        let Some(mut connection) = self.connection(&ws)? else {
+            return Ok(());
+        };
+
+        let protocol = Protocol::parse(&connection.protocol).unwrap_or(Protocol::WebSocket);
+        let (frame_type, data) = match (protocol, message) {
+            #[cfg(feature = "mqtt")]
+            (Protocol::Mqtt, WebSocketIncomingMessage::Binary(bytes)) => {
+                return self.handle_mqtt(&ws, &mut connection, &bytes).await;
+            }
+            #[cfg(not(feature = "mqtt"))]
+            (Protocol::Mqtt, WebSocketIncomingMessage::Binary(_)) => {
+                return ws.close(Some(CLOSE_UNSUPPORTED_DATA), Some("MQTT is not built into this worker"));
+            }
+            (Protocol::Mqtt, WebSocketIncomingMessage::String(_)) => {
+                return ws.close(Some(CLOSE_UNSUPPORTED_DATA), Some("MQTT requires binary frames"));
+            }
+            (Protocol::WebSocket, WebSocketIncomingMessage::String(text)) => (FrameType::Text, text),
+            (Protocol::WebSocket, WebSocketIncomingMessage::Binary(bytes)) => (FrameType::Binary, BASE64.encode(bytes)),
+        };
+        let frame = IncomingFrame {
+            connection_id: connection.id.clone(),
+            method: pipeline::SOCKET_METHOD.to_string(),
+            headers: serde_json::from_str(&connection.headers)?,
+            frame_type,
+            data,
+            received_at_ms: capture_log::now_ms(),
+        };
+        if let Err(e) = self.capture(&connection, frame).await {
+            log_warn!("⚠️  Failed to capture socket message: {:?}", e);
+        }
+        Ok(())
+    }
+
+    async fn websocket_close(&self, ws: WebSocket, _code: usize, _reason: String, _was_clean: bool) -> Result<()> {
+        self.close_connection(&ws)
+    }
+
+    async fn websocket_error(&self, ws: WebSocket, error: Error) -> Result<()> {
+        log_warn!("⚠️  Capture socket error: {:?}", error);
+        self.close_connection(&ws)
+    }
+}
@@ -0,0 +1,205 @@
+//! Email-in capture
+//! Cloudflare Email Routing delivers mail for `{uuid}@your-domain` (or
+//! `anything+{uuid}@your-domain`) to the worker's email handler, which stores
+//! the message as a capture of that webhook: method `EMAIL`, the message
+//! headers as headers, and a JSON body with the envelope, subject, text and
+//! HTML bodies and attachment metadata. Attachments go to the optional
+//! `EMAIL_ATTACHMENTS` R2 bucket. Mail for unknown addresses is rejected.
+//!
+//! worker-rs has no email event macro, so the handler is a wasm-bindgen export
+//! with the same `(message, env, ctx)` shape as the generated `fetch` export.
+
+use crate::cache;
+use crate::capture_log::{self, CaptureEvent};
+use crate::compression;
+use crate::config;
+use crate::dedup;
+use crate::durable::sequence;
+use crate::kv::TolerantKv;
+use crate::ingest;
+use crate::mime;
+use crate::pipeline::{self, CaptureMeta, IncomingRequest};
+use crate::processing::Processing;
+use crate::residency;
+use crate::storage::{self, Consistency};
+use crate::usage;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use worker::*;
+
+/// R2 bucket for attachments (optional; without it only their metadata is kept)
+const ATTACHMENT_BUCKET: &str = "EMAIL_ATTACHMENTS";
+
+#[wasm_bindgen]
+extern "C" {
+    /// `ForwardableEmailMessage` from the Email Workers runtime
+    pub type EmailMessage;
+
+    #[wasm_bindgen(method, getter)]
+    fn from(this: &EmailMessage) -> String;
+
+    #[wasm_bindgen(method, getter)]
+    fn to(this: &EmailMessage) -> String;
+
+    #[wasm_bindgen(method, getter)]
+    fn raw(this: &EmailMessage) -> web_sys::ReadableStream;
+
+    #[wasm_bindgen(method, js_name = setReject)]
+    fn set_reject(this: &EmailMessage, reason: &str);
+}
+
+/// Email event entry point
+#[cfg_attr(feature = "entrypoints", wasm_bindgen)]
+pub async fn email(message: EmailMessage, env: Env, _ctx: worker::worker_sys::Context) -> std::result::Result<(), JsValue> {
+    crate::logging::init(&env);
+    let to = message.to();
+    let uuid = mime::mailbox_uuid(&to).unwrap_or_default();
+    let mut event = CaptureEvent::start(&uuid, pipeline::EMAIL_METHOD, capture_log::now_ms());
+
+    match capture(&message, &env, &uuid, &to, &mut event).await {
+        Ok(true) => {
+            event.finish(200, None);
+            Ok(())
+        }
+        Ok(false) => {
+            event.finish(404, None);
+            message.set_reject("Unknown webhook address");
+            Ok(())
+        }
+        Err(e) => {
+            event.finish(500, Some(e.to_string()));
+            Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+}
+
+async fn read_raw(message: &EmailMessage) -> Result<Vec<u8>> {
+    let response = web_sys::Response::new_with_opt_readable_stream(Some(&message.raw()))?;
+    let buffer = JsFuture::from(response.array_buffer()?).await?;
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
+
+/// Store the message; false when the address resolves to no webhook
+async fn capture(message: &EmailMessage, env: &Env, uuid: &str, to: &str, event: &mut CaptureEvent) -> Result<bool> {
+    let kv_store = env.kv("WEBHOOK_CACHE")?;
+    let kv = TolerantKv::new(&kv_store);
+    let db = env.d1("DB")?;
+    let Some(webhook_id) = cache::resolve_webhook_id(&kv, &db, uuid).await? else {
+        return Ok(false);
+    };
+    event.webhook_id = Some(webhook_id.clone());
+
+    let raw = read_raw(message).await?;
+    let parsed_email = mime::parse(&raw);
+
+    let settings = config::load(&kv, &db, &webhook_id).await?;
+    event.kv_fallback = kv.fell_back();
+    let data_id = dedup::new_capture_id(
+        env,
+        &webhook_id,
+        None,
+        settings.config.dedup_bodies,
+        pipeline::EMAIL_METHOD,
+        &raw,
+        event.received_at_ms,
+    );
+    residency::check(env, settings.jurisdiction).map_err(Error::RustError)?;
+    let bucket = residency::bucket(env, ATTACHMENT_BUCKET, settings.jurisdiction)?;
+    let mut attachments = Vec::new();
+    let mut usage_changes = Vec::new();
+    for (index, part) in parsed_email.attachments().enumerate() {
+        let filename = part.filename.clone().unwrap_or_else(|| format!("attachment-{}", index + 1));
+        let key = format!("email/{}/{}/{}-{}", webhook_id, data_id, index, filename);
+        let stored = match &bucket {
+            Some(bucket) => {
+                let metadata = HttpMetadata {
+                    content_type: Some(part.content_type.clone()),
+                    ..HttpMetadata::default()
+                };
+                match bucket.put(&key, part.body.clone()).http_metadata(metadata).execute().await {
+                    Ok(_) => {
+                        usage_changes.extend(usage::object_changes(part.body.len() as u64));
+                        Some(key)
+                    }
+                    Err(e) => {
+                        log_warn!("⚠️  Failed to store email attachment: {:?}", e);
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+        attachments.push(serde_json::json!({
+            "filename": filename,
+            "content_type": part.content_type,
+            "size": part.body.len(),
+            "key": stored,
+        }));
+    }
+
+    let body = serde_json::json!({
+        "from": message.from(),
+        "to": to,
+        "subject": parsed_email.header("subject"),
+        "text": parsed_email.body_text("text/plain"),
+        "html": parsed_email.body_text("text/html"),
+        "attachments": attachments,
+    });
+    let mut headers: HashMap<String, String> = HashMap::new();
+    for (name, value) in &parsed_email.headers {
+        headers
+            .entry(name.clone())
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(value);
+            })
+            .or_insert_with(|| value.clone());
+    }
+
+    let incoming = IncomingRequest {
+        method: pipeline::EMAIL_METHOD.to_string(),
+        url: Url::parse(&format!("mailto:{}", to))?,
+        headers,
+        body: Some(body.to_string()),
+        received_at_ms: event.received_at_ms,
+    };
+    let mut parsed = pipeline::parse(&incoming)?;
+    let applied = pipeline::apply(&mut parsed, uuid, &settings);
+    event.content_type = parsed.indexed_headers.content_type.clone();
+    event.event_type = parsed.indexed_headers.event_type.clone();
+    event.environment = applied.environment.map(|environment| environment.name.clone());
+    event.request_bytes = Some(raw.len() as i64);
+
+    let sequence = sequence::next(env, &webhook_id).await.ok();
+    let processing = Processing::new(&applied, &settings, None, None);
+    let mut record = pipeline::into_record(
+        parsed,
+        CaptureMeta {
+            id: data_id.clone(),
+            webhook_id,
+            sequence,
+            verification: None,
+            environment: applied.environment,
+        },
+    );
+    record.processing = Some(processing.to_json());
+
+    let store_started = capture_log::now_ms();
+    let compressed = compression::for_storage(settings.config.compression.as_ref(), &record).await;
+    event.duplicate = !storage::open_in(env, Consistency::Primary, settings.jurisdiction)
+        .await?
+        .insert_capture(compressed.as_ref().unwrap_or(&record))
+        .await?;
+    event.store_ms = Some(capture_log::now_ms() - store_started);
+    event.data_id = Some(data_id);
+    event.sequence = sequence;
+
+    // A resent message is already stored, and its attachments were rewritten in place
+    if !event.duplicate {
+        usage_changes.extend(usage::capture_changes(record.size_bytes as i64));
+        usage::add(env.clone(), record.webhook_id.clone(), usage_changes).await;
+        ingest::fan_out(env, None, &record, &settings).await;
+    }
+    Ok(true)
+}
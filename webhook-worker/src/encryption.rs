@@ -0,0 +1,579 @@
+//! Envelope encryption at rest
+//! With `MASTER_KEY_VERSION` set, capture payloads (`ENCRYPTED_COLUMNS`) are
+//! encrypted with AES-256-GCM before they reach the capture store and decrypted
+//! when read back. Each webhook has a data key, kept in `data_keys` wrapped by
+//! the master key version that was current when it was created; master keys are
+//! worker secrets `MASTER_KEY_{n}` (32 bytes, base64). Encrypted values read
+//! `enc:v1:{data key id}:{base64 nonce + ciphertext}`, so captures stored before
+//! encryption was enabled stay readable as they are. Every value is sealed,
+//! whatever it looks like; without encryption, plaintext that starts like an
+//! envelope is stored behind `ESCAPE_PREFIX`, so a sender's body never reads as
+//! one. A value that fails to decrypt is returned as stored, not failing its page.
+//!
+//! Rotating the master key means adding `MASTER_KEY_{n+1}`, pointing
+//! `MASTER_KEY_VERSION` at it and running the rewrap job
+//! (`POST /api/admin/encryption/rewrap`): each data key is unwrapped with its
+//! old master key and wrapped with the new one, and no payload is re-encrypted.
+//! Data keys are never deleted, and one that fails to unwrap is left as it was
+//! and reported, so a rotation cannot make any capture unreadable, legally held
+//! or not. Retire an old master key only once the status shows no data keys
+//! left on it. Indexed metadata (event type, idempotency key, ...) stays in
+//! plaintext; erasure searches decrypt while scanning.
+
+use crate::erasure;
+use crate::ids;
+use crate::storage::{
+    CaptureRecord, DailyCount, InboxQuery, RequestQuery, ShopifyCount, SortColumn, Storage, StoredRequest,
+    WebhookVolume,
+};
+use crate::webcrypto;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Marks an encrypted column value
+pub const ENVELOPE_PREFIX: &str = "enc:v1:";
+
+/// Marks a plaintext column value that would otherwise read as encrypted
+pub const ESCAPE_PREFIX: &str = "enc:raw:";
+
+/// Capture columns stored encrypted
+pub const ENCRYPTED_COLUMNS: [&str; 8] = [
+    "data",
+    "headers",
+    "trailers",
+    "canonical_data",
+    "original_body",
+    "preview",
+    "stripe_cross_check",
+    "oauth_exchange",
+];
+
+/// Master and data keys are AES-256 keys
+pub const KEY_LEN: usize = 32;
+
+/// Most data keys rewrapped per job run, and the default
+pub const REWRAP_BATCH: u32 = 500;
+
+/// Captures decrypted per page while searching encrypted payloads
+const SCAN_PAGE: u32 = 500;
+
+thread_local! {
+    /// Unwrapped data keys by ID; a rewrap changes only their wrapping, so entries never go stale
+    static DATA_KEYS: RefCell<HashMap<String, Vec<u8>>> = RefCell::new(HashMap::new());
+    /// Data key ID used to encrypt each webhook's captures
+    static WEBHOOK_KEYS: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+}
+
+/// Column value for sealed bytes under a data key
+pub fn envelope(key_id: &str, sealed: &[u8]) -> String {
+    format!("{}{}:{}", ENVELOPE_PREFIX, key_id, BASE64.encode(sealed))
+}
+
+/// Data key ID and sealed bytes of an encrypted value; None for plaintext
+pub fn parse_envelope(value: &str) -> Option<(&str, Vec<u8>)> {
+    let (key_id, sealed) = value.strip_prefix(ENVELOPE_PREFIX)?.split_once(':')?;
+    Some((key_id, BASE64.decode(sealed).ok()?))
+}
+
+/// Secret holding master key `version`
+pub fn master_key_name(version: u32) -> String {
+    format!("MASTER_KEY_{}", version)
+}
+
+/// Raw bytes of a base64 master key
+pub fn decode_master_key(value: &str) -> std::result::Result<Vec<u8>, String> {
+    let key = BASE64
+        .decode(value.trim())
+        .map_err(|e| format!("master key is not base64: {}", e))?;
+    if key.len() != KEY_LEN {
+        return Err(format!("master key must be {} bytes, got {}", KEY_LEN, key.len()));
+    }
+    Ok(key)
+}
+
+/// Master key version new data keys are wrapped with; None when encryption is off
+pub fn current_version(env: &Env) -> Option<u32> {
+    env.var("MASTER_KEY_VERSION").ok()?.to_string().trim().parse().ok()
+}
+
+fn master_key(env: &Env, version: u32) -> Result<Vec<u8>> {
+    let name = master_key_name(version);
+    let value = env
+        .secret(&name)
+        .map_err(|_| Error::RustError(format!("Master key {} is not configured", name)))?;
+    decode_master_key(&value.to_string()).map_err(|e| Error::RustError(format!("{}: {}", name, e)))
+}
+
+/// A data key's metadata
+#[derive(Debug, Clone, Serialize)]
+pub struct DataKey {
+    pub id: String,
+    pub webhook_id: String,
+    pub master_version: u32,
+    pub created_at_ms: i64,
+    pub rewrapped_at_ms: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct DataKeyRow {
+    id: String,
+    webhook_id: String,
+    master_version: f64,
+    wrapped_key: String,
+    created_at_ms: f64,
+    rewrapped_at_ms: Option<f64>,
+}
+
+impl DataKeyRow {
+    fn metadata(&self) -> DataKey {
+        DataKey {
+            id: self.id.clone(),
+            webhook_id: self.webhook_id.clone(),
+            master_version: self.master_version as u32,
+            created_at_ms: self.created_at_ms as i64,
+            rewrapped_at_ms: self.rewrapped_at_ms.map(|ms| ms as i64),
+        }
+    }
+
+    async fn unwrap(&self, env: &Env) -> Result<Vec<u8>> {
+        let wrapped = BASE64
+            .decode(&self.wrapped_key)
+            .map_err(|e| Error::RustError(format!("Data key {} is not base64: {}", self.id, e)))?;
+        webcrypto::open(&master_key(env, self.master_version as u32)?, &wrapped).await
+    }
+}
+
+const DATA_KEY_COLUMNS: &str = "id, webhook_id, master_version, wrapped_key, created_at_ms, rewrapped_at_ms";
+
+async fn oldest_key(db: &D1Database, webhook_id: &str) -> Result<Option<DataKeyRow>> {
+    db.prepare(format!(
+        "SELECT {} FROM data_keys WHERE webhook_id = ?1 ORDER BY created_at_ms, id LIMIT 1",
+        DATA_KEY_COLUMNS
+    ))
+    .bind(&[JsValue::from_str(webhook_id)])?
+    .first::<DataKeyRow>(None)
+    .await
+}
+
+/// The data key encrypting a webhook's captures, created on first use
+async fn webhook_key(env: &Env, db: &D1Database, webhook_id: &str) -> Result<(String, Vec<u8>)> {
+    if let Some(id) = WEBHOOK_KEYS.with(|keys| keys.borrow().get(webhook_id).cloned()) {
+        if let Some(key) = DATA_KEYS.with(|keys| keys.borrow().get(&id).cloned()) {
+            return Ok((id, key));
+        }
+    }
+
+    let row = match oldest_key(db, webhook_id).await? {
+        Some(row) => row,
+        None => {
+            let version = current_version(env)
+                .ok_or_else(|| Error::RustError("MASTER_KEY_VERSION is not set".to_string()))?;
+            let key = webcrypto::random_bytes(KEY_LEN)?;
+            let wrapped = webcrypto::seal(&master_key(env, version)?, &key).await?;
+            let now_ms = Date::now().as_millis() as i64;
+            db.prepare(
+                "INSERT INTO data_keys (id, webhook_id, master_version, wrapped_key, created_at_ms) \
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )
+            .bind(&[
+                JsValue::from_str(&ids::ulid(now_ms)),
+                JsValue::from_str(webhook_id),
+                JsValue::from_f64(version as f64),
+                JsValue::from_str(&BASE64.encode(wrapped)),
+                JsValue::from_f64(now_ms as f64),
+            ])?
+            .run()
+            .await?;
+            // A concurrent first capture may have created one too; everyone settles on the oldest
+            oldest_key(db, webhook_id)
+                .await?
+                .ok_or_else(|| Error::RustError(format!("Data key for webhook {} vanished", webhook_id)))?
+        }
+    };
+
+    let key = row.unwrap(env).await?;
+    DATA_KEYS.with(|keys| keys.borrow_mut().insert(row.id.clone(), key.clone()));
+    WEBHOOK_KEYS.with(|keys| keys.borrow_mut().insert(webhook_id.to_string(), row.id.clone()));
+    Ok((row.id, key))
+}
+
+/// A data key by ID, for decryption
+async fn key_by_id(env: &Env, db: &D1Database, id: &str) -> Result<Vec<u8>> {
+    if let Some(key) = DATA_KEYS.with(|keys| keys.borrow().get(id).cloned()) {
+        return Ok(key);
+    }
+    let row = db
+        .prepare(format!("SELECT {} FROM data_keys WHERE id = ?1", DATA_KEY_COLUMNS))
+        .bind(&[JsValue::from_str(id)])?
+        .first::<DataKeyRow>(None)
+        .await?
+        .ok_or_else(|| Error::RustError(format!("Data key {} does not exist", id)))?;
+    let key = row.unwrap(env).await?;
+    DATA_KEYS.with(|keys| keys.borrow_mut().insert(id.to_string(), key.clone()));
+    Ok(key)
+}
+
+/// Data keys per master key version
+#[derive(Debug, Clone, Serialize)]
+pub struct VersionCount {
+    pub master_version: u32,
+    pub data_keys: u64,
+}
+
+#[derive(Deserialize)]
+struct VersionRow {
+    master_version: f64,
+    count: f64,
+}
+
+/// How many data keys each master key version still wraps
+pub async fn status(db: &D1Database) -> Result<Vec<VersionCount>> {
+    Ok(db
+        .prepare("SELECT master_version, COUNT(*) AS count FROM data_keys GROUP BY master_version ORDER BY master_version")
+        .all()
+        .await?
+        .results::<VersionRow>()?
+        .into_iter()
+        .map(|row| VersionCount {
+            master_version: row.master_version as u32,
+            data_keys: row.count as u64,
+        })
+        .collect())
+}
+
+/// Outcome of a rewrap run
+#[derive(Debug, Clone, Serialize)]
+pub struct RewrapReport {
+    pub master_version: u32,
+    pub rewrapped: Vec<DataKey>,
+    /// Keys left on their old master key, with why they could not be unwrapped
+    pub failed: Vec<RewrapFailure>,
+    /// Data keys still wrapped by another version after this run
+    pub remaining: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RewrapFailure {
+    pub id: String,
+    pub master_version: u32,
+    pub error: String,
+}
+
+/// Rewrap up to `limit` data keys under the current master key
+pub async fn rewrap(env: &Env, db: &D1Database, limit: u32) -> Result<RewrapReport> {
+    let version = current_version(env).ok_or_else(|| Error::RustError("MASTER_KEY_VERSION is not set".to_string()))?;
+    let master = master_key(env, version)?;
+    let rows = db
+        .prepare(format!(
+            "SELECT {} FROM data_keys WHERE master_version != ?1 ORDER BY created_at_ms LIMIT ?2",
+            DATA_KEY_COLUMNS
+        ))
+        .bind(&[JsValue::from_f64(version as f64), JsValue::from_f64(limit as f64)])?
+        .all()
+        .await?
+        .results::<DataKeyRow>()?;
+
+    let mut rewrapped = Vec::new();
+    let mut failed = Vec::new();
+    for row in rows {
+        let key = match row.unwrap(env).await {
+            Ok(key) => key,
+            Err(e) => {
+                log_warn!("⚠️  Data key {} could not be unwrapped: {:?}", row.id, e);
+                failed.push(RewrapFailure {
+                    id: row.id.clone(),
+                    master_version: row.master_version as u32,
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let wrapped = webcrypto::seal(&master, &key).await?;
+        let now_ms = Date::now().as_millis() as i64;
+        // Matching the old version keeps a concurrent run from rewrapping twice
+        let changed = db
+            .prepare(
+                "UPDATE data_keys SET master_version = ?3, wrapped_key = ?4, rewrapped_at_ms = ?5 \
+                 WHERE id = ?1 AND master_version = ?2",
+            )
+            .bind(&[
+                JsValue::from_str(&row.id),
+                JsValue::from_f64(row.master_version),
+                JsValue::from_f64(version as f64),
+                JsValue::from_str(&BASE64.encode(wrapped)),
+                JsValue::from_f64(now_ms as f64),
+            ])?
+            .run()
+            .await?
+            .meta()?
+            .and_then(|meta| meta.changes)
+            .unwrap_or(0);
+        if changed > 0 {
+            let mut key = row.metadata();
+            key.master_version = version;
+            key.rewrapped_at_ms = Some(now_ms);
+            rewrapped.push(key);
+        }
+    }
+
+    let remaining = status(db)
+        .await?
+        .iter()
+        .filter(|count| count.master_version != version)
+        .map(|count| count.data_keys)
+        .sum();
+    Ok(RewrapReport {
+        master_version: version,
+        rewrapped,
+        failed,
+        remaining,
+    })
+}
+
+/// `storage` with payload encryption when `MASTER_KEY_VERSION` is set; without it,
+/// plaintext that reads like an envelope is escaped instead (see `escape`)
+pub fn wrap(env: &Env, storage: Box<dyn Storage>) -> Result<Box<dyn Storage>> {
+    Ok(Box::new(EncryptedStorage {
+        inner: storage,
+        env: env.clone(),
+        keys: env.d1("DB")?,
+        sealing: current_version(env).is_some(),
+    }))
+}
+
+/// A plaintext value as stored, escaped when it starts like an envelope or an escaped value
+pub fn escape(value: &str) -> Option<String> {
+    (value.starts_with(ENVELOPE_PREFIX) || value.starts_with(ESCAPE_PREFIX))
+        .then(|| format!("{}{}", ESCAPE_PREFIX, value))
+}
+
+/// Capture store that encrypts payload columns on write and decrypts them on read
+pub struct EncryptedStorage {
+    inner: Box<dyn Storage>,
+    env: Env,
+    /// Data keys live with the other metadata, whatever the captures' jurisdiction
+    keys: D1Database,
+    /// Whether writes are encrypted (`MASTER_KEY_VERSION` set); reads decrypt either way
+    sealing: bool,
+}
+
+impl EncryptedStorage {
+    /// Encrypt a value under the webhook's data key, or escape it when encryption is off
+    async fn seal(&self, key: Option<&(String, Vec<u8>)>, value: &mut String) -> Result<()> {
+        match key {
+            Some((key_id, key)) if !value.is_empty() => {
+                *value = envelope(key_id, &webcrypto::seal(key, value.as_bytes()).await?);
+            }
+            Some(_) => {}
+            None => {
+                if let Some(escaped) = escape(value) {
+                    *value = escaped;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn seal_optional(&self, key: Option<&(String, Vec<u8>)>, value: &mut Option<String>) -> Result<()> {
+        if let Some(value) = value {
+            self.seal(key, value).await?;
+        }
+        Ok(())
+    }
+
+    async fn data_key(&self, webhook_id: &str) -> Result<Option<(String, Vec<u8>)>> {
+        if !self.sealing {
+            return Ok(None);
+        }
+        Ok(Some(webhook_key(&self.env, &self.keys, webhook_id).await?))
+    }
+
+    async fn encrypt_record(&self, record: &CaptureRecord) -> Result<CaptureRecord> {
+        let key = self.data_key(&record.webhook_id).await?;
+        let key = key.as_ref();
+        let mut sealed = record.clone();
+        self.seal(key, &mut sealed.data).await?;
+        self.seal(key, &mut sealed.headers_json).await?;
+        self.seal_optional(key, &mut sealed.trailers).await?;
+        self.seal_optional(key, &mut sealed.canonical_data).await?;
+        self.seal_optional(key, &mut sealed.original_body).await?;
+        self.seal_optional(key, &mut sealed.preview).await?;
+        self.seal_optional(key, &mut sealed.stripe_cross_check).await?;
+        self.seal_optional(key, &mut sealed.oauth_exchange).await?;
+        Ok(sealed)
+    }
+
+    async fn encrypt_request(&self, request: &StoredRequest) -> Result<StoredRequest> {
+        let key = self.data_key(&request.webhook_id).await?;
+        let key = key.as_ref();
+        let mut sealed = request.clone();
+        self.seal(key, &mut sealed.data).await?;
+        self.seal(key, &mut sealed.headers).await?;
+        self.seal_optional(key, &mut sealed.trailers).await?;
+        self.seal_optional(key, &mut sealed.canonical_data).await?;
+        self.seal_optional(key, &mut sealed.original_body).await?;
+        self.seal_optional(key, &mut sealed.preview).await?;
+        self.seal_optional(key, &mut sealed.stripe_cross_check).await?;
+        self.seal_optional(key, &mut sealed.oauth_exchange).await?;
+        Ok(sealed)
+    }
+
+    /// A stored value as written; one that doesn't decrypt is left as stored
+    async fn open(&self, id: &str, value: &mut String) {
+        if let Some(plaintext) = value.strip_prefix(ESCAPE_PREFIX) {
+            *value = plaintext.to_string();
+            return;
+        }
+        let Some((key_id, sealed)) = parse_envelope(value) else {
+            return;
+        };
+        let opened = async {
+            let key = key_by_id(&self.env, &self.keys, key_id).await?;
+            String::from_utf8(webcrypto::open(&key, &sealed).await?)
+                .map_err(|_| Error::RustError("Decrypted value is not UTF-8".to_string()))
+        };
+        match opened.await {
+            Ok(plaintext) => *value = plaintext,
+            Err(e) => log_warn!("⚠️  Failed to decrypt a value of capture {}, returning it as stored: {:?}", id, e),
+        }
+    }
+
+    async fn open_optional(&self, id: &str, value: &mut Option<String>) {
+        if let Some(value) = value {
+            self.open(id, value).await;
+        }
+    }
+
+    async fn decrypt(&self, mut request: StoredRequest) -> StoredRequest {
+        let id = request.id.clone();
+        self.open(&id, &mut request.data).await;
+        self.open(&id, &mut request.headers).await;
+        self.open_optional(&id, &mut request.trailers).await;
+        self.open_optional(&id, &mut request.canonical_data).await;
+        self.open_optional(&id, &mut request.original_body).await;
+        self.open_optional(&id, &mut request.preview).await;
+        self.open_optional(&id, &mut request.stripe_cross_check).await;
+        self.open_optional(&id, &mut request.oauth_exchange).await;
+        request
+    }
+
+    async fn decrypt_all(&self, requests: Vec<StoredRequest>) -> Result<Vec<StoredRequest>> {
+        let mut decrypted = Vec::with_capacity(requests.len());
+        for request in requests {
+            decrypted.push(self.decrypt(request).await);
+        }
+        Ok(decrypted)
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Storage for EncryptedStorage {
+    async fn insert_capture(&self, record: &CaptureRecord) -> Result<bool> {
+        self.inner.insert_capture(&self.encrypt_record(record).await?).await
+    }
+
+    async fn insert_captures(&self, records: &[CaptureRecord]) -> Result<()> {
+        let mut sealed = Vec::with_capacity(records.len());
+        for record in records {
+            sealed.push(self.encrypt_record(record).await?);
+        }
+        self.inner.insert_captures(&sealed).await
+    }
+
+    async fn list_requests(&self, query: &RequestQuery) -> Result<Vec<StoredRequest>> {
+        self.decrypt_all(self.inner.list_requests(query).await?).await
+    }
+
+    async fn inbox_fetch(&self, query: &InboxQuery) -> Result<Vec<StoredRequest>> {
+        self.decrypt_all(self.inner.inbox_fetch(query).await?).await
+    }
+
+    async fn inbox_ack(&self, webhook_id: &str, ids: &[String], now_ms: i64) -> Result<u64> {
+        self.inner.inbox_ack(webhook_id, ids, now_ms).await
+    }
+
+    async fn record_repeat(&self, webhook_id: &str, id: &str, seen_at_ms: i64) -> Result<bool> {
+        self.inner.record_repeat(webhook_id, id, seen_at_ms).await
+    }
+
+    async fn purge(&self, webhook_id: &str, event_type: Option<&str>, before: i64, keep: &[String]) -> Result<u64> {
+        self.inner.purge(webhook_id, event_type, before, keep).await
+    }
+
+    async fn strip_payloads(&self, webhook_id: &str, before: i64, keep: &[String]) -> Result<u64> {
+        self.inner.strip_payloads(webhook_id, before, keep).await
+    }
+
+    /// Ciphertext cannot be searched in SQL, so every capture is decrypted and matched here
+    async fn find_containing(&self, webhook_id: &str, needle: &str, limit: u32) -> Result<Vec<StoredRequest>> {
+        let mut found = Vec::new();
+        let mut offset = 0;
+        loop {
+            let query = RequestQuery {
+                webhook_id: webhook_id.to_string(),
+                limit: SCAN_PAGE,
+                offset,
+                since: None,
+                until: None,
+                sort: SortColumn::ReceivedAt,
+                ascending: true,
+                filters: Vec::new(),
+            };
+            let page = self.list_requests(&query).await?;
+            let full = page.len() == SCAN_PAGE as usize;
+            for request in page {
+                if !erasure::matched_columns(&request, needle).is_empty() {
+                    found.push(request);
+                    if found.len() >= limit as usize {
+                        return Ok(found);
+                    }
+                }
+            }
+            if !full {
+                return Ok(found);
+            }
+            offset += SCAN_PAGE;
+        }
+    }
+
+    async fn delete_captures(&self, webhook_id: &str, ids: &[String]) -> Result<u64> {
+        self.inner.delete_captures(webhook_id, ids).await
+    }
+
+    async fn replace_payloads(&self, requests: &[StoredRequest]) -> Result<u64> {
+        let mut sealed = Vec::with_capacity(requests.len());
+        for request in requests {
+            sealed.push(self.encrypt_request(request).await?);
+        }
+        self.inner.replace_payloads(&sealed).await
+    }
+
+    async fn daily_counts(&self, webhook_id: &str, before: i64) -> Result<Vec<DailyCount>> {
+        self.inner.daily_counts(webhook_id, before).await
+    }
+
+    async fn shopify_counts(&self, webhook_id: &str, since: i64, until: i64) -> Result<Vec<ShopifyCount>> {
+        self.inner.shopify_counts(webhook_id, since, until).await
+    }
+
+    async fn webhook_volumes(&self, since: i64) -> Result<Vec<WebhookVolume>> {
+        self.inner.webhook_volumes(since).await
+    }
+
+    async fn count_received(&self, webhook_id: &str, event_type: Option<&str>, since: i64, until: Option<i64>) -> Result<u64> {
+        self.inner.count_received(webhook_id, event_type, since, until).await
+    }
+
+    async fn maintain(&self, now: i64) -> Result<()> {
+        self.inner.maintain(now).await
+    }
+
+    fn bookmark(&self) -> Option<String> {
+        self.inner.bookmark()
+    }
+}
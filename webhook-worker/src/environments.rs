@@ -0,0 +1,125 @@
+//! Webhook environments
+//! A webhook can have named environments (`dev`, `staging`, `prod`, ...), each with
+//! its own capture UUID and optional forwarding target. Captures sent to an
+//! environment UUID are stored under the parent webhook, tagged with the
+//! environment name, so config, signature secrets and stats stay shared.
+
+use crate::chain;
+use crate::ids;
+use crate::storage::optional_str;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Longest accepted environment name
+const MAX_NAME_LENGTH: usize = 32;
+
+const ENVIRONMENT_COLUMNS: &str = "id, name, uuid, forward_url, created_at_ms";
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Environment {
+    pub id: String,
+    pub name: String,
+    /// Capture UUID for this environment (`/w/{uuid}`)
+    pub uuid: String,
+    /// Where captures are forwarded after they are stored
+    pub forward_url: Option<String>,
+    pub created_at_ms: i64,
+}
+
+/// Names are short lowercase slugs: letters, digits and dashes
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-')
+}
+
+/// Forwarding targets must be absolute http(s) URLs
+pub fn is_valid_forward_url(value: &str) -> bool {
+    Url::parse(value).is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.host().is_some())
+}
+
+/// Forwarding targets: http(s) URLs, or `webhook:{uuid}` to chain into another webhook
+pub fn is_valid_forward_target(value: &str) -> bool {
+    is_valid_forward_url(value) || chain::target_uuid(value).is_some()
+}
+
+/// Environments of a webhook, oldest first
+pub async fn list(db: &D1Database, webhook_id: &str) -> Result<Vec<Environment>> {
+    db.prepare(format!(
+        "SELECT {} FROM webhook_environments WHERE webhook_id = ?1 ORDER BY created_at_ms",
+        ENVIRONMENT_COLUMNS
+    ))
+    .bind(&[JsValue::from_str(webhook_id)])?
+    .all()
+    .await?
+    .results::<Environment>()
+}
+
+/// A webhook's environment by name
+pub async fn get(db: &D1Database, webhook_id: &str, name: &str) -> Result<Option<Environment>> {
+    db.prepare(format!(
+        "SELECT {} FROM webhook_environments WHERE webhook_id = ?1 AND name = ?2",
+        ENVIRONMENT_COLUMNS
+    ))
+    .bind(&[JsValue::from_str(webhook_id), JsValue::from_str(name)])?
+    .first::<Environment>(None)
+    .await
+}
+
+/// Create an environment with a fresh capture UUID; None if the name is taken
+pub async fn create(
+    db: &D1Database,
+    webhook_id: &str,
+    name: &str,
+    forward_url: Option<String>,
+) -> Result<Option<Environment>> {
+    if get(db, webhook_id, name).await?.is_some() {
+        return Ok(None);
+    }
+
+    let now = Date::now().as_millis() as i64;
+    let environment = Environment {
+        id: ids::ulid(now),
+        name: name.to_string(),
+        uuid: uuid::Uuid::new_v4().to_string(),
+        forward_url,
+        created_at_ms: now,
+    };
+    db.prepare(
+        "INSERT INTO webhook_environments (id, webhook_id, name, uuid, forward_url, created_at_ms) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )
+    .bind(&[
+        JsValue::from_str(&environment.id),
+        JsValue::from_str(webhook_id),
+        JsValue::from_str(&environment.name),
+        JsValue::from_str(&environment.uuid),
+        optional_str(&environment.forward_url),
+        JsValue::from_f64(now as f64),
+    ])?
+    .run()
+    .await?;
+
+    Ok(Some(environment))
+}
+
+/// Change (or clear) an environment's forwarding target
+pub async fn set_forward_url(db: &D1Database, id: &str, forward_url: Option<String>) -> Result<()> {
+    db.prepare("UPDATE webhook_environments SET forward_url = ?2 WHERE id = ?1")
+        .bind(&[JsValue::from_str(id), optional_str(&forward_url)])?
+        .run()
+        .await?;
+    Ok(())
+}
+
+/// Delete an environment (its captures are kept)
+pub async fn delete(db: &D1Database, id: &str) -> Result<()> {
+    db.prepare("DELETE FROM webhook_environments WHERE id = ?1")
+        .bind(&[JsValue::from_str(id)])?
+        .run()
+        .await?;
+    Ok(())
+}
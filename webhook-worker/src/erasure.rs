@@ -0,0 +1,239 @@
+//! Erasure by data subject
+//! Right-to-erasure requests name an identifier (an email address, a customer
+//! ID) rather than captures. Every webhook in the caller's project is searched
+//! for captures containing it, ASCII case-insensitively, in the body, headers,
+//! trailers, canonical copy, idempotency key and binary preview. Matches are
+//! deleted, or with `redact` rewritten with each occurrence replaced by
+//! `[REDACTED]`; the canonical copy is rebuilt from the redacted body, and the
+//! original bytes kept by charset normalization are dropped since they cannot
+//! be redacted in place. Legally held captures are reported but left alone.
+//! The report lists what was removed; the identifier itself is never stored,
+//! only its SHA-256.
+
+use crate::canonical;
+use crate::legal_hold::{self, Held};
+use crate::slack;
+use crate::storage::{self, Consistency, Storage, StoredRequest, ERASURE_COLUMNS};
+use crate::webhooks::Webhook;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use worker::*;
+
+/// Replacement for every occurrence of a redacted identifier
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Shorter identifiers would match unrelated captures
+pub const MIN_IDENTIFIER_LEN: usize = 3;
+
+/// Captures handled per webhook and run; the report is `truncated` beyond this
+pub const MAX_MATCHES: u32 = 1000;
+
+/// What happens to matching captures
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    #[default]
+    Delete,
+    Redact,
+}
+
+/// Normalized search needle for an identifier, or why it cannot be searched for
+pub fn needle(identifier: &str) -> std::result::Result<String, String> {
+    let needle = identifier.trim().to_ascii_lowercase();
+    if needle.chars().count() < MIN_IDENTIFIER_LEN {
+        return Err(format!("identifier must be at least {} characters", MIN_IDENTIFIER_LEN));
+    }
+    if REDACTED.to_ascii_lowercase().contains(&needle) {
+        return Err(format!("identifier must not be part of {}", REDACTED));
+    }
+    Ok(needle)
+}
+
+/// Hex SHA-256 of a normalized identifier, recorded instead of the identifier
+pub fn identifier_sha256(needle: &str) -> String {
+    Sha256::digest(needle.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// `text` with every ASCII case-insensitive occurrence of `needle` redacted; None without one
+pub fn redact_text(text: &str, needle: &str) -> Option<String> {
+    // ASCII lowercasing keeps byte offsets, so matches index into `text` too
+    let lower = text.to_ascii_lowercase();
+    let mut redacted = String::with_capacity(text.len());
+    let mut last = 0;
+    for (start, _) in lower.match_indices(needle) {
+        redacted.push_str(&text[last..start]);
+        redacted.push_str(REDACTED);
+        last = start + needle.len();
+    }
+    if last == 0 {
+        return None;
+    }
+    redacted.push_str(&text[last..]);
+    Some(redacted)
+}
+
+/// The `ERASURE_COLUMNS` values of a capture, in the same order
+fn columns(request: &StoredRequest) -> [Option<&String>; 8] {
+    [
+        Some(&request.data),
+        Some(&request.headers),
+        request.trailers.as_ref(),
+        request.canonical_data.as_ref(),
+        request.idempotency_key.as_ref(),
+        request.preview.as_ref(),
+        request.stripe_cross_check.as_ref(),
+        request.oauth_exchange.as_ref(),
+    ]
+}
+
+/// Columns of a capture that contain `needle`
+pub fn matched_columns(request: &StoredRequest, needle: &str) -> Vec<&'static str> {
+    ERASURE_COLUMNS
+        .iter()
+        .zip(columns(request))
+        .filter(|(_, value)| value.is_some_and(|value| value.to_ascii_lowercase().contains(needle)))
+        .map(|(column, _)| *column)
+        .collect()
+}
+
+/// A capture with `needle` redacted from every searched column
+pub fn redact(request: &StoredRequest, needle: &str) -> StoredRequest {
+    let mut redacted = request.clone();
+    if let Some(data) = redact_text(&request.data, needle) {
+        redacted.canonical_data = request.canonical_data.as_ref().and_then(|_| {
+            canonical::canonicalize(&data).or_else(|| slack::canonical(request.content_type.as_deref(), &data))
+        });
+        redacted.original_body = None;
+        redacted.data = data;
+    }
+    if let Some(headers) = redact_text(&request.headers, needle) {
+        redacted.headers = headers;
+    }
+    let redact_optional = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(|value| redact_text(value, needle).unwrap_or_else(|| value.to_string()))
+    };
+    redacted.trailers = redact_optional(&request.trailers);
+    redacted.idempotency_key = redact_optional(&request.idempotency_key);
+    redacted.stripe_cross_check = redact_optional(&request.stripe_cross_check);
+    redacted.oauth_exchange = redact_optional(&request.oauth_exchange);
+    if let Some(canonical) = &redacted.canonical_data {
+        redacted.canonical_data = Some(redact_text(canonical, needle).unwrap_or_else(|| canonical.clone()));
+    }
+    // A preview describes the raw bytes; a redacted one would no longer match them
+    if request.preview.as_deref().is_some_and(|preview| preview.to_ascii_lowercase().contains(needle)) {
+        redacted.preview = None;
+    }
+    redacted
+}
+
+/// One capture erased (or to be erased, on a dry run)
+#[derive(Debug, Clone, Serialize)]
+pub struct Erased {
+    pub id: String,
+    pub received_at: i64,
+    pub event_type: Option<String>,
+    /// Columns the identifier was found in
+    pub columns: Vec<&'static str>,
+}
+
+/// What was done to one webhook's captures
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookReport {
+    pub uuid: String,
+    pub erased: Vec<Erased>,
+    /// Matching captures kept because of a legal hold
+    pub held: Vec<String>,
+    /// More than `MAX_MATCHES` captures matched; run again for the rest
+    pub truncated: bool,
+}
+
+/// Result of an erasure run across a project
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub identifier_sha256: String,
+    pub mode: Mode,
+    pub dry_run: bool,
+    pub erased: usize,
+    pub held: usize,
+    pub truncated: bool,
+    /// Webhooks with matching captures
+    pub webhooks: Vec<WebhookReport>,
+}
+
+/// Erase one webhook's captures containing `needle`, except held ones
+pub async fn erase_webhook(
+    storage: &dyn Storage,
+    webhook: &Webhook,
+    held: &Held,
+    needle: &str,
+    mode: Mode,
+    dry_run: bool,
+) -> Result<WebhookReport> {
+    let mut found = storage.find_containing(&webhook.id, needle, MAX_MATCHES + 1).await?;
+    let truncated = found.len() > MAX_MATCHES as usize;
+    found.truncate(MAX_MATCHES as usize);
+
+    let (kept, erasable): (Vec<StoredRequest>, Vec<StoredRequest>) = found
+        .into_iter()
+        .partition(|request| held.webhook || held.captures.contains(&request.id));
+
+    if !dry_run && !erasable.is_empty() {
+        match mode {
+            Mode::Delete => {
+                let ids: Vec<String> = erasable.iter().map(|request| request.id.clone()).collect();
+                storage.delete_captures(&webhook.id, &ids).await?;
+            }
+            Mode::Redact => {
+                let redacted: Vec<StoredRequest> = erasable.iter().map(|request| redact(request, needle)).collect();
+                storage.replace_payloads(&redacted).await?;
+            }
+        }
+    }
+
+    Ok(WebhookReport {
+        uuid: webhook.uuid.clone(),
+        erased: erasable
+            .iter()
+            .map(|request| Erased {
+                id: request.id.clone(),
+                received_at: request.received_at,
+                event_type: request.event_type.clone(),
+                columns: matched_columns(request, needle),
+            })
+            .collect(),
+        held: kept.into_iter().map(|request| request.id).collect(),
+        truncated,
+    })
+}
+
+/// Erase captures containing `needle` across `webhooks`, each in its jurisdiction's store
+pub async fn erase(
+    env: &Env,
+    db: &D1Database,
+    webhooks: &[Webhook],
+    needle: &str,
+    mode: Mode,
+    dry_run: bool,
+) -> Result<Report> {
+    let mut reports = Vec::new();
+    for webhook in webhooks {
+        let held = legal_hold::active(db, &webhook.id).await?;
+        let storage = storage::for_webhook(env, &webhook.id, Consistency::Primary).await?;
+        let report = erase_webhook(storage.as_ref(), webhook, &held, needle, mode, dry_run).await?;
+        if !report.erased.is_empty() || !report.held.is_empty() {
+            reports.push(report);
+        }
+    }
+
+    Ok(Report {
+        identifier_sha256: identifier_sha256(needle),
+        mode,
+        dry_run,
+        erased: reports.iter().map(|report| report.erased.len()).sum(),
+        held: reports.iter().map(|report| report.held.len()).sum(),
+        truncated: reports.iter().any(|report| report.truncated),
+        webhooks: reports,
+    })
+}
@@ -0,0 +1,289 @@
+//! Ingestion error budget
+//! With `ERROR_BUDGET_THRESHOLD` set, every delivery on `/w/` counts towards
+//! the share of failed ingestions (5xx answers, e.g. while D1 is down; senders'
+//! own 4xx mistakes don't count). Isolates tally locally and add their tally to
+//! per-minute counters of the `Counter` Durable Object (scope `error_budget`,
+//! see `counters.rs`) at most every `FLUSH_INTERVAL_MS`, off the response path.
+//! The 15-minute cron sums the last `ERROR_BUDGET_WINDOW_MINUTES` of them; once
+//! the failure rate exceeds the threshold over at least
+//! `ERROR_BUDGET_MIN_REQUESTS` deliveries an `exhausted` alert goes to the
+//! Slack incoming webhook `ALERT_SLACK_WEBHOOK_URL` and/or the JSON endpoint
+//! `ALERT_WEBHOOK_URL` (a mail relay, a pager), and a `recovered` one once it is
+//! back under. None of this touches D1: the alert state lives in KV.
+
+use crate::forward;
+use crate::kv::KvBackend;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use worker::*;
+
+/// Longest an isolate keeps its tally before adding it to the shared counters
+pub const FLUSH_INTERVAL_MS: i64 = 10_000;
+
+/// Counter scope of the per-minute tallies
+pub const SCOPE: &str = "error_budget";
+
+/// Longest window that can be configured
+pub const MAX_WINDOW_MINUTES: u32 = 24 * 60;
+
+const DEFAULT_WINDOW_MINUTES: u32 = 15;
+const DEFAULT_MIN_REQUESTS: u64 = 20;
+
+/// KV key of the current alert, present while the budget is exhausted
+const ALERT_KEY: &str = "error_budget:alert";
+
+/// `ERROR_BUDGET_*` settings; None when the budget is not tracked
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Budget {
+    /// Failed share of ingestions above which the budget is exhausted (0.05 = 5%)
+    pub threshold: f64,
+    pub window_minutes: u32,
+    /// Fewer deliveries in the window never change the alert state
+    pub min_requests: u64,
+}
+
+impl Budget {
+    pub fn parse(threshold: Option<&str>, window_minutes: Option<&str>, min_requests: Option<&str>) -> Option<Self> {
+        let threshold = threshold?.trim().parse::<f64>().ok().filter(|t| t.is_finite() && *t > 0.0)?;
+        Some(Self {
+            threshold: threshold.min(1.0),
+            window_minutes: window_minutes
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(DEFAULT_WINDOW_MINUTES)
+                .clamp(1, MAX_WINDOW_MINUTES),
+            min_requests: min_requests
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(DEFAULT_MIN_REQUESTS),
+        })
+    }
+
+    pub fn from_env(env: &Env) -> Option<Self> {
+        let var = |name: &str| env.var(name).ok().map(|value| value.to_string());
+        Self::parse(
+            var("ERROR_BUDGET_THRESHOLD").as_deref(),
+            var("ERROR_BUDGET_WINDOW_MINUTES").as_deref(),
+            var("ERROR_BUDGET_MIN_REQUESTS").as_deref(),
+        )
+    }
+
+    /// Compare a window's counts with the budget, given whether it is already alerting
+    pub fn evaluate(&self, window: &Window, alerting: bool) -> Option<Alert> {
+        if window.total < self.min_requests {
+            return None;
+        }
+        let exhausted = window.failure_rate() > self.threshold;
+        match (alerting, exhausted) {
+            (false, true) => Some(Alert::Exhausted),
+            (true, false) => Some(Alert::Recovered),
+            _ => None,
+        }
+    }
+}
+
+/// A change of the budget's state worth notifying about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alert {
+    Exhausted,
+    Recovered,
+}
+
+impl Alert {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Exhausted => "exhausted",
+            Self::Recovered => "recovered",
+        }
+    }
+}
+
+/// Deliveries and failures over a window
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Window {
+    pub total: u64,
+    pub failed: u64,
+}
+
+impl Window {
+    pub fn failure_rate(&self) -> f64 {
+        match self.total {
+            0 => 0.0,
+            total => self.failed as f64 / total as f64,
+        }
+    }
+
+    /// Sum the per-minute counters (`total:{minute}`, `failed:{minute}`) of the
+    /// `window_minutes` minutes before the one `now_ms` falls in
+    pub fn sum(counters: &BTreeMap<String, i64>, window_minutes: u32, now_ms: i64) -> Self {
+        let current = now_ms / 60_000;
+        let first = current - window_minutes as i64;
+        let mut window = Self::default();
+        for (key, value) in counters {
+            let Some((kind, minute)) = key.split_once(':') else {
+                continue;
+            };
+            let Ok(minute) = minute.parse::<i64>() else {
+                continue;
+            };
+            if minute < first || minute >= current {
+                continue;
+            }
+            match kind {
+                "total" => window.total += (*value).max(0) as u64,
+                "failed" => window.failed += (*value).max(0) as u64,
+                _ => {}
+            }
+        }
+        window
+    }
+}
+
+/// Deliveries and failures an isolate has not added to the counters yet
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub total: i64,
+    pub failed: i64,
+    /// Minute the tally's first delivery fell in
+    pub minute: i64,
+    pub since_ms: i64,
+}
+
+impl Tally {
+    /// Count one delivery; returns the finished tally to flush when it is due
+    /// or when the minute changed (which starts a new one)
+    pub fn add(&mut self, failed: bool, now_ms: i64) -> Option<Tally> {
+        let minute = now_ms / 60_000;
+        let due = if self.total > 0 && (minute != self.minute || now_ms - self.since_ms >= FLUSH_INTERVAL_MS) {
+            Some(std::mem::take(self))
+        } else {
+            None
+        };
+        if self.total == 0 {
+            *self = Tally {
+                minute,
+                since_ms: now_ms,
+                ..Tally::default()
+            };
+        }
+        self.total += 1;
+        self.failed += failed as i64;
+        due
+    }
+}
+
+thread_local! {
+    static TALLY: Cell<Tally> = const { Cell::new(Tally { total: 0, failed: 0, minute: 0, since_ms: 0 }) };
+}
+
+/// Count a delivery in this isolate's tally, handing back a tally due for `flush`
+pub fn record(failed: bool, now_ms: i64) -> Option<Tally> {
+    TALLY.with(|cell| {
+        let mut tally = cell.get();
+        let due = tally.add(failed, now_ms);
+        cell.set(tally);
+        due
+    })
+}
+
+/// Add a tally to the shared per-minute counters
+pub async fn flush(env: Env, budget: Budget, tally: Tally) {
+    // Counters outlive the longest window they can be part of
+    let ttl_ms = Some((budget.window_minutes as i64 + 2) * 60_000);
+    let mut counts = vec![("total", tally.total)];
+    if tally.failed > 0 {
+        counts.push(("failed", tally.failed));
+    }
+    for (kind, by) in counts {
+        let key = format!("{}:{}", kind, tally.minute);
+        if let Err(e) = crate::durable::counter::increment(&env, SCOPE, &key, by, ttl_ms).await {
+            log_warn!("⚠️  Failed to count ingestions for the error budget: {:?}", e);
+        }
+    }
+}
+
+/// Current alert, stored in KV while the budget is exhausted
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AlertState {
+    pub since_ms: i64,
+}
+
+/// The window's counts and the current alert
+pub async fn status(env: &Env, budget: &Budget, now_ms: i64) -> Result<(Window, Option<AlertState>)> {
+    let counters = crate::durable::counter::snapshot(env, SCOPE).await?;
+    let values: BTreeMap<String, i64> = counters.into_iter().map(|(key, entry)| (key, entry.value)).collect();
+    let window = Window::sum(&values, budget.window_minutes, now_ms);
+    let alert = env.kv("WEBHOOK_CACHE")?.get_text(ALERT_KEY).await?;
+    let alert = alert.and_then(|alert| serde_json::from_str(&alert).ok());
+    Ok((window, alert))
+}
+
+/// JSON body POSTed to `ALERT_WEBHOOK_URL`
+pub fn notification(budget: &Budget, alert: Alert, window: &Window, since_ms: i64, now_ms: i64) -> serde_json::Value {
+    serde_json::json!({
+        "type": format!("error_budget.{}", alert.as_str()),
+        "threshold": budget.threshold,
+        "window_minutes": budget.window_minutes,
+        "total": window.total,
+        "failed": window.failed,
+        "failure_rate": window.failure_rate(),
+        "exhausted_since_ms": since_ms,
+        "checked_at_ms": now_ms,
+    })
+}
+
+/// Text of the Slack message
+pub fn slack_text(budget: &Budget, alert: Alert, window: &Window) -> String {
+    let rate = window.failure_rate() * 100.0;
+    match alert {
+        Alert::Exhausted => format!(
+            "🚨 Webhook ingestion error budget exhausted: {:.1}% of {} deliveries failed in the last {} minutes \
+             (threshold {:.1}%)",
+            rate,
+            window.total,
+            budget.window_minutes,
+            budget.threshold * 100.0
+        ),
+        Alert::Recovered => format!(
+            "✅ Webhook ingestion recovered: {:.1}% of {} deliveries failed in the last {} minutes",
+            rate, window.total, budget.window_minutes
+        ),
+    }
+}
+
+/// Compare the window with the budget and notify about changes; `now` is Unix seconds
+pub async fn check(env: &Env, now: i64) -> Result<()> {
+    let Some(budget) = Budget::from_env(env) else {
+        return Ok(());
+    };
+    let now_ms = now * 1000;
+    let (window, alert_state) = status(env, &budget, now_ms).await?;
+    let Some(alert) = budget.evaluate(&window, alert_state.is_some()) else {
+        return Ok(());
+    };
+
+    let kv = env.kv("WEBHOOK_CACHE")?;
+    let since_ms = alert_state.map_or(now_ms, |state| state.since_ms);
+    match alert {
+        Alert::Exhausted => {
+            let state = serde_json::to_string(&AlertState { since_ms })?;
+            kv.put_text(ALERT_KEY, &state, None).await?;
+        }
+        Alert::Recovered => kv.delete(ALERT_KEY).await?,
+    }
+    log_warn!(
+        "🚨 Ingestion error budget {} ({} of {} failed in {} minutes)",
+        alert.as_str(),
+        window.failed,
+        window.total,
+        budget.window_minutes
+    );
+
+    if let Ok(url) = env.secret("ALERT_SLACK_WEBHOOK_URL") {
+        let body = serde_json::json!({ "text": slack_text(&budget, alert, &window) });
+        forward::notify(&url.to_string(), &body).await;
+    }
+    if let Some(url) = env.var("ALERT_WEBHOOK_URL").ok().map(|url| url.to_string()).filter(|url| !url.is_empty()) {
+        forward::notify(&url, &notification(&budget, alert, &window, since_ms, now_ms)).await;
+    }
+    Ok(())
+}
@@ -0,0 +1,44 @@
+//! Client-supplied event time
+//! Extracts when the sender says the event happened, from provider timestamp
+//! headers or the standard `Date` header, as Unix milliseconds.
+
+use chrono::DateTime;
+use std::collections::HashMap;
+
+/// Headers carrying a Unix timestamp in seconds
+const TIMESTAMP_HEADERS: &[&str] = &[
+    "svix-timestamp",
+    "webhook-timestamp",
+    "x-slack-request-timestamp",
+    "x-timestamp",
+];
+
+/// Event time in Unix milliseconds, if the sender supplied one
+pub fn extract(headers: &HashMap<String, String>) -> Option<i64> {
+    TIMESTAMP_HEADERS
+        .iter()
+        .filter_map(|name| headers.get(*name))
+        .find_map(|value| parse_unix_seconds(value))
+        .or_else(|| headers.get("stripe-signature").and_then(|value| stripe_timestamp(value)))
+        .or_else(|| headers.get("date").and_then(|value| parse_http_date(value)))
+}
+
+/// Seconds too large to express in milliseconds are rejected, not wrapped
+fn parse_unix_seconds(value: &str) -> Option<i64> {
+    value.trim().parse::<i64>().ok()?.checked_mul(1000)
+}
+
+/// `Stripe-Signature: t=1492774577,v1=...`
+fn stripe_timestamp(value: &str) -> Option<i64> {
+    value
+        .split(',')
+        .find_map(|part| part.trim().strip_prefix("t="))
+        .and_then(parse_unix_seconds)
+}
+
+/// `Date: Wed, 21 Oct 2015 07:28:00 GMT`
+fn parse_http_date(value: &str) -> Option<i64> {
+    DateTime::parse_from_rfc2822(value.trim())
+        .ok()
+        .map(|date| date.timestamp_millis())
+}
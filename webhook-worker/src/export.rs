@@ -0,0 +1,152 @@
+//! CSV export of request metadata
+//! Rows for `GET /api/webhooks/{uuid}/export.csv`: one line per capture with
+//! the columns picked by `columns=` (metadata names, plus `header:NAME` and
+//! `body:PATH` extracted fields). Values are quoted per RFC 4180, and cells a
+//! spreadsheet would evaluate as a formula are prefixed with `'`.
+
+use crate::config::FieldSource;
+use crate::storage::StoredRequest;
+use chrono::SecondsFormat;
+use std::collections::HashMap;
+
+/// Columns exported when `columns` is not given
+pub const DEFAULT_COLUMNS: &str = "time,method,size_bytes,verification,provider,event_type";
+
+/// An exported column
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    Id,
+    /// Receive time, RFC 3339 UTC with milliseconds
+    Time,
+    ReceivedAtMs,
+    Method,
+    SizeBytes,
+    ContentType,
+    EventType,
+    Verification,
+    /// Signature provider configured for the webhook
+    Provider,
+    Environment,
+    Sequence,
+    UserAgent,
+    IdempotencyKey,
+    ConnectionId,
+    /// Extracted header or body field, headed by its spec (`body:data.id`)
+    Field(String, FieldSource),
+}
+
+impl Column {
+    fn parse(name: &str) -> Option<Self> {
+        if let Some(header) = name.strip_prefix("header:") {
+            return (!header.is_empty()).then(|| Self::Field(name.to_string(), FieldSource::Header(header.to_string())));
+        }
+        if let Some(path) = name.strip_prefix("body:") {
+            return (!path.is_empty()).then(|| Self::Field(name.to_string(), FieldSource::Body(path.to_string())));
+        }
+        Some(match name {
+            "id" => Self::Id,
+            "time" => Self::Time,
+            "received_at_ms" => Self::ReceivedAtMs,
+            "method" => Self::Method,
+            "size_bytes" => Self::SizeBytes,
+            "content_type" => Self::ContentType,
+            "event_type" => Self::EventType,
+            "verification" => Self::Verification,
+            "provider" => Self::Provider,
+            "environment" => Self::Environment,
+            "sequence" => Self::Sequence,
+            "user_agent" => Self::UserAgent,
+            "idempotency_key" => Self::IdempotencyKey,
+            "connection_id" => Self::ConnectionId,
+            _ => return None,
+        })
+    }
+
+    fn title(&self) -> &str {
+        match self {
+            Self::Id => "id",
+            Self::Time => "time",
+            Self::ReceivedAtMs => "received_at_ms",
+            Self::Method => "method",
+            Self::SizeBytes => "size_bytes",
+            Self::ContentType => "content_type",
+            Self::EventType => "event_type",
+            Self::Verification => "verification",
+            Self::Provider => "provider",
+            Self::Environment => "environment",
+            Self::Sequence => "sequence",
+            Self::UserAgent => "user_agent",
+            Self::IdempotencyKey => "idempotency_key",
+            Self::ConnectionId => "connection_id",
+            Self::Field(title, _) => title,
+        }
+    }
+}
+
+/// Parse a comma-separated column list; the error names the first unknown column
+pub fn parse_columns(spec: &str) -> Result<Vec<Column>, String> {
+    let columns = spec
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| Column::parse(name).ok_or_else(|| name.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if columns.is_empty() {
+        return Err(spec.to_string());
+    }
+    Ok(columns)
+}
+
+/// The header line
+pub fn header_row(columns: &[Column]) -> String {
+    line(columns.iter().map(|column| column.title().to_string()))
+}
+
+/// One capture as a CSV line; `provider` is the webhook's signature provider
+pub fn row(columns: &[Column], request: &StoredRequest, provider: Option<&str>) -> String {
+    let received_at_ms = request.received_at_ms.unwrap_or(request.received_at * 1000);
+    let headers: HashMap<String, String> = if columns.iter().any(|column| matches!(column, Column::Field(..))) {
+        serde_json::from_str(&request.headers).unwrap_or_default()
+    } else {
+        HashMap::new()
+    };
+    line(columns.iter().map(|column| match column {
+        Column::Id => request.id.clone(),
+        Column::Time => chrono::DateTime::from_timestamp_millis(received_at_ms)
+            .map(|time| time.to_rfc3339_opts(SecondsFormat::Millis, true))
+            .unwrap_or_default(),
+        Column::ReceivedAtMs => received_at_ms.to_string(),
+        Column::Method => request.method.clone(),
+        Column::SizeBytes => request.size_bytes.to_string(),
+        Column::ContentType => request.content_type.clone().unwrap_or_default(),
+        Column::EventType => request.event_type.clone().unwrap_or_default(),
+        Column::Verification => request.verification.clone().unwrap_or_default(),
+        Column::Provider => provider.unwrap_or_default().to_string(),
+        Column::Environment => request.environment.clone().unwrap_or_default(),
+        Column::Sequence => request.sequence.map(|sequence| sequence.to_string()).unwrap_or_default(),
+        Column::UserAgent => request.user_agent.clone().unwrap_or_default(),
+        Column::IdempotencyKey => request.idempotency_key.clone().unwrap_or_default(),
+        Column::ConnectionId => request.connection_id.clone().unwrap_or_default(),
+        Column::Field(_, source) => source.extract(&headers, &request.data).unwrap_or_default(),
+    }))
+}
+
+fn line(values: impl Iterator<Item = String>) -> String {
+    let mut line = values.map(|value| escape(&value)).collect::<Vec<_>>().join(",");
+    line.push_str("\r\n");
+    line
+}
+
+fn escape(value: &str) -> String {
+    // Spreadsheets evaluate cells starting with these as formulas
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
@@ -0,0 +1,169 @@
+//! Pipeline fixtures
+//! Tests for webhook configs: a fixture is a named sample delivery, typed in
+//! or saved from a capture, with the outcome it should have. Running it puts
+//! the sample through the webhook's current pipeline as a simulation does
+//! (see `simulate.rs`; nothing is stored or forwarded) and checks each
+//! expectation: whether the delivery is stored or refused, whether and where
+//! it is forwarded, the status the sender gets, the event type and the route.
+//! Fixtures live in the `fixtures` table, `MAX_PER_WEBHOOK` per webhook.
+
+use crate::pipeline;
+use crate::simulate::{Outcome, Sample};
+use crate::storage::StoredRequest;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Fixtures per webhook
+pub const MAX_PER_WEBHOOK: usize = 50;
+
+const MAX_NAME_CHARS: usize = 64;
+
+/// Fixture names are 1-64 letters, digits, `-` or `_`
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_CHARS
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+pub use webhook_types::fixtures::{Expect, Failure, Fixture};
+
+/// Expectations `outcome` misses; empty when the fixture passes
+pub fn check(expect: &Expect, outcome: &Outcome) -> Vec<Failure> {
+    let processing = outcome.processing.as_ref();
+    let forwards: Vec<String> = processing.map_or_else(Vec::new, |processing| processing.forwards.clone());
+    let event_type = outcome.capture.as_ref().and_then(|capture| capture.event_type.clone());
+    let route = processing.and_then(|processing| processing.route.clone());
+    let checks = [
+        ("stored", expect.stored.map(Value::from), Value::from(outcome.accepted)),
+        ("forwarded", expect.forwarded.map(Value::from), Value::from(!forwards.is_empty())),
+        ("status", expect.status.map(Value::from), Value::from(outcome.response.status)),
+        ("event_type", expect.event_type.clone().map(Value::from), Value::from(event_type)),
+        ("route", expect.route.clone().map(Value::from), Value::from(route)),
+        ("forwards", expect.forwards.clone().map(Value::from), Value::from(forwards)),
+    ];
+    checks
+        .into_iter()
+        .filter_map(|(field, expected, actual)| {
+            let expected = expected?;
+            (expected != actual).then(|| Failure {
+                field: field.to_string(),
+                expected,
+                actual,
+            })
+        })
+        .collect()
+}
+
+/// A capture as a sample delivery: the method, headers and body it arrived with
+/// (the query parameters for methods without a body) and its environment
+pub fn sample_from_capture(capture: &StoredRequest) -> Sample {
+    let headers: HashMap<String, String> = serde_json::from_str(&capture.headers).unwrap_or_default();
+    let (body, query) = if pipeline::has_body(&capture.method) {
+        (Some(Value::String(capture.data.clone())), None)
+    } else {
+        let params: HashMap<String, String> = serde_json::from_str(&capture.data).unwrap_or_default();
+        let mut pairs: Vec<(String, String)> = params.into_iter().collect();
+        pairs.sort();
+        let query = form_urlencoded::Serializer::new(String::new()).extend_pairs(pairs).finish();
+        (None, Some(query).filter(|query| !query.is_empty()))
+    };
+    Sample {
+        method: capture.method.clone(),
+        headers,
+        body,
+        query,
+        environment: capture.environment.clone(),
+    }
+}
+
+#[derive(Deserialize)]
+struct FixtureRow {
+    name: String,
+    request: String,
+    expect: String,
+    capture_id: Option<String>,
+    actor: String,
+    created_at_ms: f64,
+}
+
+impl FixtureRow {
+    fn into_fixture(self) -> Option<Fixture> {
+        match (serde_json::from_str(&self.request), serde_json::from_str(&self.expect)) {
+            (Ok(request), Ok(expect)) => Some(Fixture {
+                name: self.name,
+                request,
+                expect,
+                capture_id: self.capture_id,
+                actor: self.actor,
+                created_at_ms: self.created_at_ms as i64,
+            }),
+            _ => {
+                log_warn!("⚠️  Skipping unreadable fixture {}", self.name);
+                None
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CountRow {
+    count: f64,
+}
+
+/// Store a fixture, replacing the one with its name; false when the webhook has `MAX_PER_WEBHOOK` others
+pub async fn save(db: &D1Database, webhook_id: &str, fixture: &Fixture) -> Result<bool> {
+    let others = db
+        .prepare("SELECT COUNT(*) AS count FROM fixtures WHERE webhook_id = ?1 AND name != ?2")
+        .bind(&[JsValue::from_str(webhook_id), JsValue::from_str(&fixture.name)])?
+        .first::<CountRow>(None)
+        .await?
+        .map_or(0, |row| row.count as usize);
+    if others >= MAX_PER_WEBHOOK {
+        return Ok(false);
+    }
+    db.prepare(
+        "INSERT OR REPLACE INTO fixtures (webhook_id, name, request, expect, capture_id, actor, created_at_ms) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )
+    .bind(&[
+        JsValue::from_str(webhook_id),
+        JsValue::from_str(&fixture.name),
+        JsValue::from_str(&serde_json::to_string(&fixture.request)?),
+        JsValue::from_str(&serde_json::to_string(&fixture.expect)?),
+        fixture.capture_id.as_deref().map_or(JsValue::NULL, JsValue::from_str),
+        JsValue::from_str(&fixture.actor),
+        JsValue::from_f64(fixture.created_at_ms as f64),
+    ])?
+    .run()
+    .await?;
+    Ok(true)
+}
+
+/// The webhook's fixtures by name
+pub async fn list(db: &D1Database, webhook_id: &str) -> Result<Vec<Fixture>> {
+    Ok(db
+        .prepare(
+            "SELECT name, request, expect, capture_id, actor, created_at_ms FROM fixtures \
+             WHERE webhook_id = ?1 ORDER BY name",
+        )
+        .bind(&[JsValue::from_str(webhook_id)])?
+        .all()
+        .await?
+        .results::<FixtureRow>()?
+        .into_iter()
+        .filter_map(FixtureRow::into_fixture)
+        .collect())
+}
+
+/// Delete a fixture; false if it doesn't exist
+pub async fn delete(db: &D1Database, webhook_id: &str, name: &str) -> Result<bool> {
+    let result = db
+        .prepare("DELETE FROM fixtures WHERE webhook_id = ?1 AND name = ?2")
+        .bind(&[JsValue::from_str(webhook_id), JsValue::from_str(name)])?
+        .run()
+        .await?;
+    Ok(result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) > 0)
+}
@@ -0,0 +1,227 @@
+//! Capture forwarding
+//! Replays a stored capture to a downstream URL (an environment's forwarding
+//! target) with the original method, body and end-to-end headers. Forwarding is
+//! best effort: the capture is already stored, so failures are only logged. The
+//! target's answer (status, headers, body up to `MAX_RESPONSE_BODY_BYTES`) is
+//! kept on the outcome so it can be stored next to the capture.
+//! With `forward_signing` a forward follows the Standard Webhooks spec: each
+//! attempt is signed afresh under the capture ID, and failures, timeouts and
+//! 429/5xx answers are retried (see `SIGNED_RETRY_DELAYS`).
+
+use crate::capture_log;
+use crate::signature::standard::{self, STANDARD_HEADERS};
+use futures_util::future::{select, Either};
+use std::collections::HashMap;
+use std::time::Duration;
+use worker::*;
+
+/// Give up on slow targets after this long
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest response body kept from a target
+pub const MAX_RESPONSE_BODY_BYTES: usize = 8 * 1024;
+
+/// Headers that describe the inbound hop rather than the delivery
+const SKIPPED_HEADERS: &[&str] = &["host", "content-length", "connection", "keep-alive", "transfer-encoding", "upgrade"];
+
+/// Header prefixes added by Cloudflare's edge
+const SKIPPED_PREFIXES: &[&str] = &["cf-", "x-forwarded-", "x-real-ip"];
+
+/// Waits before the retries of a signed forward. The spec's sender schedule
+/// (5 s, 5 min, 30 min, ...) outlives a request, so only short steps are taken.
+pub const SIGNED_RETRY_DELAYS: [Duration; 3] = [
+    Duration::from_secs(1),
+    Duration::from_secs(2),
+    Duration::from_secs(4),
+];
+
+/// Result of one forwarding attempt
+#[derive(Debug, Clone, Default)]
+pub struct ForwardOutcome {
+    /// Target response status (None when the request failed or timed out)
+    pub status: Option<u16>,
+    pub duration_ms: i64,
+    pub error: Option<String>,
+    /// What the target answered (None when the request failed or timed out)
+    pub response: Option<TargetResponse>,
+}
+
+/// A target's answer, body truncated to `MAX_RESPONSE_BODY_BYTES`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TargetResponse {
+    pub headers: HashMap<String, String>,
+    pub body: String,
+    pub body_truncated: bool,
+}
+
+impl TargetResponse {
+    /// Keep at most `MAX_RESPONSE_BODY_BYTES` of a body, cut on a character boundary
+    pub fn new(headers: HashMap<String, String>, body: &[u8]) -> Self {
+        let body_truncated = body.len() > MAX_RESPONSE_BODY_BYTES;
+        let mut body = String::from_utf8_lossy(&body[..body.len().min(MAX_RESPONSE_BODY_BYTES)]).into_owned();
+        if body_truncated && body.ends_with(char::REPLACEMENT_CHARACTER) {
+            body.pop();
+        }
+        Self {
+            headers,
+            body,
+            body_truncated,
+        }
+    }
+}
+
+/// Status and captured answer of a response
+pub(crate) async fn read_response(mut response: Response) -> Result<(u16, TargetResponse)> {
+    let headers = response.headers().entries().collect();
+    let body = response.bytes().await?;
+    Ok((response.status_code(), TargetResponse::new(headers, &body)))
+}
+
+/// A delivery to forward
+pub struct Delivery<'a> {
+    pub method: &'a str,
+    pub headers: &'a HashMap<String, String>,
+    /// Request body (ignored for methods without one)
+    pub body: &'a str,
+    /// Original query string, appended to the target URL
+    pub query: Option<&'a str>,
+}
+
+fn forwarded_headers(headers: &HashMap<String, String>) -> Result<Headers> {
+    let forwarded = Headers::new();
+    for (name, value) in headers {
+        let name = name.to_ascii_lowercase();
+        if SKIPPED_HEADERS.contains(&name.as_str()) || SKIPPED_PREFIXES.iter().any(|prefix| name.starts_with(prefix)) {
+            continue;
+        }
+        forwarded.set(&name, value)?;
+    }
+    Ok(forwarded)
+}
+
+fn build_request(target: &str, delivery: &Delivery<'_>) -> Result<Request> {
+    let mut url = Url::parse(target)?;
+    if let Some(query) = delivery.query.filter(|query| !query.is_empty()) {
+        let merged = match url.query() {
+            Some(existing) => format!("{}&{}", existing, query),
+            None => query.to_string(),
+        };
+        url.set_query(Some(&merged));
+    }
+
+    let method = Method::from(delivery.method.to_string());
+    let mut init = RequestInit::new();
+    init.with_headers(forwarded_headers(delivery.headers)?);
+    if matches!(method, Method::Post | Method::Put | Method::Patch) {
+        init.with_body(Some(delivery.body.into()));
+    }
+    init.with_method(method);
+    Request::new_with_init(url.as_str(), &init)
+}
+
+/// Forward a delivery to `target`, waiting at most `FORWARD_TIMEOUT`
+pub async fn send(target: &str, delivery: &Delivery<'_>) -> ForwardOutcome {
+    let started = capture_log::now_ms();
+    let result = match build_request(target, delivery) {
+        Ok(request) => {
+            let exchange = async { read_response(Fetch::Request(request).send().await?).await };
+            let result = match select(Box::pin(exchange), Delay::from(FORWARD_TIMEOUT)).await {
+                Either::Left((Ok(answer), _)) => Ok(answer),
+                Either::Left((Err(e), _)) => Err(e.to_string()),
+                Either::Right(_) => Err("timed out".to_string()),
+            };
+            result
+        }
+        Err(e) => Err(e.to_string()),
+    };
+
+    let duration_ms = capture_log::now_ms() - started;
+    match result {
+        Ok((status, response)) => ForwardOutcome {
+            status: Some(status),
+            duration_ms,
+            error: None,
+            response: Some(response),
+        },
+        Err(error) => ForwardOutcome {
+            status: None,
+            duration_ms,
+            error: Some(error),
+            response: None,
+        },
+    }
+}
+
+/// POST a JSON notification (SLA and anomaly alerts); failures are logged
+pub async fn notify(target: &str, body: &serde_json::Value) {
+    let body = body.to_string();
+    let headers = HashMap::from([("content-type".to_string(), "application/json".to_string())]);
+    let delivery = Delivery {
+        method: "POST",
+        headers: &headers,
+        body: &body,
+        query: None,
+    };
+    if let Some(error) = send(target, &delivery).await.error {
+        log_warn!("⚠️  Notification to {} failed: {}", target, error);
+    }
+}
+
+/// Standard Webhooks signing of one capture's forwards
+#[derive(Clone)]
+pub struct Signer {
+    pub secret: String,
+    /// `webhook-id`, the same for every attempt
+    pub id: String,
+    pub retries: u32,
+}
+
+/// Delivery headers with the Standard Webhooks headers of an attempt at `timestamp` (Unix seconds)
+pub fn signed_headers(
+    headers: &HashMap<String, String>,
+    signer: &Signer,
+    timestamp: i64,
+    body: &str,
+) -> HashMap<String, String> {
+    let mut signed = headers.clone();
+    signed.insert(STANDARD_HEADERS.id.to_string(), signer.id.clone());
+    signed.insert(STANDARD_HEADERS.timestamp.to_string(), timestamp.to_string());
+    signed.insert(
+        STANDARD_HEADERS.signature.to_string(),
+        standard::sign(&signer.secret, &signer.id, timestamp, body),
+    );
+    signed
+}
+
+/// Whether the spec has a sender try again: no answer, 429 or 5xx
+pub fn is_retryable(outcome: &ForwardOutcome) -> bool {
+    outcome.status.is_none_or(|status| status == 429 || status >= 500)
+}
+
+/// Forward a delivery signed per Standard Webhooks, retrying transient failures;
+/// the outcome is the last attempt's, with the time of all of them
+pub async fn send_signed(target: &str, delivery: &Delivery<'_>, signer: &Signer) -> ForwardOutcome {
+    let mut duration_ms = 0;
+    let mut attempt = 0;
+    loop {
+        let headers = signed_headers(delivery.headers, signer, capture_log::now_ms() / 1000, delivery.body);
+        let signed = Delivery {
+            headers: &headers,
+            ..*delivery
+        };
+        let mut outcome = send(target, &signed).await;
+        duration_ms += outcome.duration_ms;
+        let delay = SIGNED_RETRY_DELAYS.get(attempt as usize).filter(|_| attempt < signer.retries);
+        match delay {
+            Some(delay) if is_retryable(&outcome) => {
+                log_info!("🔁 Signed forward to {} failed, retrying in {:?}", target, delay);
+                Delay::from(*delay).await;
+                attempt += 1;
+            }
+            _ => {
+                outcome.duration_ms = duration_ms;
+                return outcome;
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! GitHub delivery enrichment
+//! Deliveries carrying `X-GitHub-Event` are stored with the event, the
+//! delivery GUID, the App installation and the repository's `owner/name` in
+//! their own `webhook_data` columns, whatever the webhook's extraction rules
+//! say. With the `GITHUB_APP_TOKEN` secret set (a token accepted by
+//! `GET /app/installations/{id}`), the installation's account login labels the
+//! capture too; labels are cached in KV for a day so a busy App costs one API
+//! call per installation, and a failed lookup only leaves the label empty.
+
+use crate::kv::KvBackend;
+use futures_util::future::{select, Either};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+use worker::*;
+
+/// KV key prefix for installation labels
+const LABEL_PREFIX: &str = "github:installation:";
+
+const LABEL_TTL_SECONDS: u64 = 86_400;
+
+/// Give up on a slow GitHub API after this long
+const API_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// GitHub columns of a capture; all None for other deliveries
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GithubFields {
+    pub event: Option<String>,
+    pub delivery: Option<String>,
+    /// App installation ID, as text
+    pub installation_id: Option<String>,
+    /// `owner/name`
+    pub repository: Option<String>,
+    /// Account login of the installation (API lookup)
+    pub installation: Option<String>,
+}
+
+impl GithubFields {
+    /// Fields of a delivery, from lowercase-keyed headers and the JSON body
+    pub fn extract(headers: &HashMap<String, String>, body: &str) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let Some(event) = header("x-github-event") else {
+            return Self::default();
+        };
+        let payload: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+        let installation = payload.get("installation");
+        Self {
+            event: Some(event),
+            delivery: header("x-github-delivery"),
+            installation_id: installation
+                .and_then(|installation| installation.get("id"))
+                .and_then(Value::as_u64)
+                .map(|id| id.to_string()),
+            repository: payload
+                .pointer("/repository/full_name")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            // Installation events name their account themselves
+            installation: installation
+                .and_then(|installation| installation.pointer("/account/login"))
+                .and_then(Value::as_str)
+                .map(str::to_string),
+        }
+    }
+}
+
+/// Fill in the installation label of an App delivery that lacks one
+pub async fn label(env: &Env, kv: &(impl KvBackend + ?Sized), fields: &mut GithubFields) {
+    let Some(installation_id) = fields.installation_id.clone().filter(|_| fields.installation.is_none()) else {
+        return;
+    };
+    let Ok(token) = env.secret("GITHUB_APP_TOKEN").map(|secret| secret.to_string()) else {
+        return;
+    };
+    let key = format!("{}{}", LABEL_PREFIX, installation_id);
+    if let Ok(Some(cached)) = kv.get_text(&key).await {
+        fields.installation = Some(cached);
+        return;
+    }
+    match fetch_label(&token, &installation_id).await {
+        Ok(login) => {
+            if let Err(e) = kv.put_text(&key, &login, Some(LABEL_TTL_SECONDS)).await {
+                log_warn!("⚠️  Failed to cache GitHub installation label: {:?}", e);
+            }
+            fields.installation = Some(login);
+        }
+        Err(e) => log_warn!("⚠️  GitHub installation {} lookup failed: {}", installation_id, e),
+    }
+}
+
+async fn fetch_label(token: &str, installation_id: &str) -> std::result::Result<String, String> {
+    let headers = Headers::new();
+    let set = |name: &str, value: &str| headers.set(name, value).map_err(|e| e.to_string());
+    set("Authorization", &format!("Bearer {}", token))?;
+    set("Accept", "application/vnd.github+json")?;
+    set("X-GitHub-Api-Version", "2022-11-28")?;
+    set("User-Agent", "webhook-ingestion")?;
+    let mut init = RequestInit::new();
+    init.with_method(Method::Get).with_headers(headers);
+    let url = format!("https://api.github.com/app/installations/{}", installation_id);
+    let request = Request::new_with_init(&url, &init).map_err(|e| e.to_string())?;
+
+    let exchange = async {
+        let mut response = Fetch::Request(request).send().await?;
+        let status = response.status_code();
+        Ok::<_, Error>((status, response.json::<Value>().await?))
+    };
+    match select(Box::pin(exchange), Delay::from(API_TIMEOUT)).await {
+        Either::Left((Ok((200, installation)), _)) => installation
+            .pointer("/account/login")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| "no account login in the answer".to_string()),
+        Either::Left((Ok((status, _)), _)) => Err(format!("GitHub answered {}", status)),
+        Either::Left((Err(e), _)) => Err(e.to_string()),
+        Either::Right(_) => Err("timed out".to_string()),
+    }
+}
@@ -0,0 +1,207 @@
+//! Indexed header extraction
+//! Pulls high-value headers out of the request so they can be stored in
+//! dedicated, indexed columns next to the raw headers JSON blob, and bounds
+//! and sanitizes the header map before it is stored.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use worker::Env;
+
+/// Default maximum number of request headers
+const DEFAULT_MAX_COUNT: usize = 100;
+
+/// Default maximum total header bytes (names plus values)
+const DEFAULT_MAX_BYTES: usize = 32 * 1024;
+
+/// Hop-by-hop headers (RFC 9110 §7.6.1), never stored
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Headers carrying a provider signature, in lookup order
+const SIGNATURE_HEADERS: &[&str] = &[
+    "stripe-signature",
+    "x-hub-signature-256",
+    "x-hub-signature",
+    "x-shopify-hmac-sha256",
+    "x-slack-signature",
+    "x-twilio-signature",
+    "svix-signature",
+    "webhook-signature",
+    "x-signature",
+];
+
+/// Headers carrying a client-supplied idempotency / delivery key
+const IDEMPOTENCY_HEADERS: &[&str] = &[
+    "idempotency-key",
+    "x-idempotency-key",
+    "x-github-delivery",
+    "x-shopify-webhook-id",
+    "svix-id",
+    "webhook-id",
+];
+
+/// Headers carrying the provider event type
+const EVENT_TYPE_HEADERS: &[&str] = &[
+    "x-github-event",
+    "x-gitlab-event",
+    "x-shopify-topic",
+    "x-event-type",
+    "x-webhook-event",
+    "mqtt-topic",
+];
+
+/// Normalized values for the indexed `webhook_data` header columns
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedHeaders {
+    pub content_type: Option<String>,
+    pub user_agent: Option<String>,
+    pub signature: Option<String>,
+    pub idempotency_key: Option<String>,
+    pub event_type: Option<String>,
+    /// Message ID and signing time (Unix seconds) of Svix and Standard Webhooks deliveries
+    #[serde(default)]
+    pub svix_id: Option<String>,
+    #[serde(default)]
+    pub svix_timestamp: Option<i64>,
+    /// Topic and sending shop of Shopify deliveries
+    #[serde(default)]
+    pub shopify_topic: Option<String>,
+    #[serde(default)]
+    pub shopify_shop_domain: Option<String>,
+    /// Correlation ID taken where the webhook's `correlation_id` config says (see `api/trace.rs`)
+    #[serde(default)]
+    pub correlation_id: Option<String>,
+}
+
+impl IndexedHeaders {
+    /// Extract indexed header values from a lowercase-keyed header map
+    pub fn extract(headers: &HashMap<String, String>) -> Self {
+        Self {
+            content_type: headers
+                .get("content-type")
+                .and_then(|value| normalize_content_type(value)),
+            user_agent: first_present(headers, &["user-agent"]),
+            signature: first_present(headers, SIGNATURE_HEADERS),
+            idempotency_key: first_present(headers, IDEMPOTENCY_HEADERS),
+            event_type: first_present(headers, EVENT_TYPE_HEADERS),
+            svix_id: first_present(headers, &["svix-id", "webhook-id"]),
+            svix_timestamp: first_present(headers, &["svix-timestamp", "webhook-timestamp"])
+                .and_then(|value| value.parse().ok()),
+            shopify_topic: first_present(headers, &["x-shopify-topic"]),
+            shopify_shop_domain: first_present(headers, &["x-shopify-shop-domain"])
+                .map(|domain| domain.to_ascii_lowercase()),
+            correlation_id: None,
+        }
+    }
+}
+
+/// Bounds on the headers a capture may carry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderLimits {
+    pub max_count: usize,
+    pub max_bytes: usize,
+}
+
+impl Default for HeaderLimits {
+    fn default() -> Self {
+        Self {
+            max_count: DEFAULT_MAX_COUNT,
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+}
+
+impl HeaderLimits {
+    /// Limits from `MAX_HEADER_COUNT` and `MAX_HEADER_BYTES`, falling back to the defaults
+    pub fn from_env(env: &Env) -> Self {
+        let var = |name: &str| env.var(name).ok().and_then(|value| value.to_string().parse().ok());
+        Self {
+            max_count: var("MAX_HEADER_COUNT").unwrap_or(DEFAULT_MAX_COUNT),
+            max_bytes: var("MAX_HEADER_BYTES").unwrap_or(DEFAULT_MAX_BYTES),
+        }
+    }
+}
+
+/// Which header limit a request exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    Count,
+    Bytes,
+}
+
+/// Enforce the limits on the raw headers, then build the stored map: names are
+/// trimmed and lowercased, repeated headers are joined with ", ", and hop-by-hop
+/// headers (plus any listed in `Connection`) are dropped
+pub fn sanitize<I>(raw: I, limits: &HeaderLimits) -> Result<HashMap<String, String>, LimitExceeded>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let mut count = 0;
+    let mut bytes = 0;
+    let mut headers: HashMap<String, String> = HashMap::new();
+    for (name, value) in raw {
+        count += 1;
+        bytes += name.len() + value.len();
+        if count > limits.max_count {
+            return Err(LimitExceeded::Count);
+        }
+        if bytes > limits.max_bytes {
+            return Err(LimitExceeded::Bytes);
+        }
+
+        let name = name.trim().to_ascii_lowercase();
+        if name.is_empty() {
+            continue;
+        }
+        let value = value.trim();
+        headers
+            .entry(name)
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+
+    let listed: Vec<String> = headers
+        .get("connection")
+        .map(|value| {
+            value
+                .split(',')
+                .map(|name| name.trim().to_ascii_lowercase())
+                .filter(|name| !name.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    headers.retain(|name, _| !HOP_BY_HOP_HEADERS.contains(&name.as_str()) && !listed.contains(name));
+    Ok(headers)
+}
+
+/// Strip parameters (charset, boundary) and lowercase the media type
+fn normalize_content_type(value: &str) -> Option<String> {
+    let media_type = value.split(';').next().unwrap_or("").trim();
+    if media_type.is_empty() {
+        None
+    } else {
+        Some(media_type.to_ascii_lowercase())
+    }
+}
+
+/// Return the first non-empty header value from a list of candidates
+fn first_present(headers: &HashMap<String, String>, names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .filter_map(|name| headers.get(*name))
+        .map(|value| value.trim())
+        .find(|value| !value.is_empty())
+        .map(str::to_string)
+}
@@ -0,0 +1,157 @@
+//! Heartbeat deliveries
+//! Some providers ping every minute with the same payload, burying real events
+//! in the capture list. A webhook with `heartbeat` in its config tracks each
+//! distinct delivery (method and body, see `dedup::fingerprint`) in KV under
+//! `heartbeat:{webhook_id}:{fingerprint}`: how many arrived in the current run,
+//! when it started and last arrived, the latest intervals between them and the
+//! capture the last one was stored as. A delivery whose interval strays more
+//! than `tolerance` from the run's median starts a new run; once a run reaches
+//! `min_repeats` deliveries each one is a heartbeat, which the processing trail
+//! notes with the run length and its interval.
+//!
+//! With `collapse`, a heartbeat is not stored as a capture of its own: the
+//! capture of the previous beat counts it instead (`repeat_count`,
+//! `last_seen_at_ms`), and the sender is answered with that capture. Heartbeats
+//! are still forwarded but not fanned out to subscribers. Without collapse,
+//! keyless identical pings inside one dedup window are already stored once
+//! (see `dedup.rs`). Webhooks buffered as hot are classified but never folded.
+//! A payload unseen for a day is forgotten.
+
+use crate::config::Heartbeat;
+use crate::dedup;
+use crate::kv::KvBackend;
+use serde::{Deserialize, Serialize};
+
+/// Intervals kept per tracked payload
+pub const MAX_INTERVALS: usize = 8;
+
+/// Tracked payloads expire this long after their last delivery
+const TRACK_TTL_SECONDS: u64 = 86_400;
+
+/// One payload's current run of identical deliveries
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Track {
+    pub count: u32,
+    pub first_seen_ms: i64,
+    pub last_seen_ms: i64,
+    /// Gaps between the run's latest deliveries, oldest first
+    pub intervals: Vec<i64>,
+    /// Capture the payload was last stored as
+    pub capture_id: Option<String>,
+}
+
+impl Track {
+    /// Median gap between deliveries; None before the second one
+    pub fn interval_ms(&self) -> Option<i64> {
+        let mut intervals = self.intervals.clone();
+        intervals.sort_unstable();
+        intervals.get(intervals.len() / 2).copied()
+    }
+
+    /// The track once another delivery arrives at `now_ms`
+    pub fn advance(mut self, config: &Heartbeat, now_ms: i64) -> Self {
+        if self.count == 0 {
+            return Self {
+                count: 1,
+                first_seen_ms: now_ms,
+                last_seen_ms: now_ms,
+                ..Self::default()
+            };
+        }
+        let interval = (now_ms - self.last_seen_ms).max(0);
+        let steady = self
+            .interval_ms()
+            .is_none_or(|median| (interval - median).abs() as f64 <= median as f64 * config.tolerance);
+        if steady {
+            self.count += 1;
+        } else {
+            self.count = 2;
+            self.first_seen_ms = self.last_seen_ms;
+            self.intervals.clear();
+        }
+        self.intervals.push(interval);
+        if self.intervals.len() > MAX_INTERVALS {
+            self.intervals.remove(0);
+        }
+        self.last_seen_ms = now_ms;
+        self
+    }
+
+    pub fn is_heartbeat(&self, config: &Heartbeat) -> bool {
+        self.count >= config.min_repeats && self.intervals.iter().all(|interval| *interval > 0)
+    }
+}
+
+/// What the processing trail records about a heartbeat
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Trail {
+    /// Deliveries in the run, this one included
+    pub repeats: u32,
+    pub interval_ms: i64,
+}
+
+/// One delivery's place in its payload's track
+#[derive(Debug, Clone, PartialEq)]
+pub struct Beat {
+    key: String,
+    pub track: Track,
+    pub heartbeat: bool,
+}
+
+impl Beat {
+    pub fn trail(&self) -> Option<Trail> {
+        self.heartbeat.then(|| Trail {
+            repeats: self.track.count,
+            interval_ms: self.track.interval_ms().unwrap_or(0),
+        })
+    }
+
+    /// Capture to fold this delivery into, under `collapse`
+    pub fn collapse_into<'a>(&'a self, config: &Heartbeat) -> Option<&'a str> {
+        self.track.capture_id.as_deref().filter(|_| config.collapse && self.heartbeat)
+    }
+}
+
+pub fn key(webhook_id: &str, method: &str, body: &[u8]) -> String {
+    let fingerprint = dedup::fingerprint(webhook_id, None, method, body);
+    let hex: String = fingerprint.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("heartbeat:{}:{}", webhook_id, hex)
+}
+
+/// Track a delivery arriving at `now_ms`; an unreadable track starts over
+pub async fn observe(
+    kv: &(impl KvBackend + ?Sized),
+    config: &Heartbeat,
+    webhook_id: &str,
+    method: &str,
+    body: &[u8],
+    now_ms: i64,
+) -> Beat {
+    let key = key(webhook_id, method, body);
+    let track = match kv.get_text(&key).await {
+        Ok(Some(value)) => serde_json::from_str::<Track>(&value).unwrap_or_default(),
+        Ok(None) => Track::default(),
+        Err(e) => {
+            log_warn!("⚠️  Failed to read heartbeat track {}: {:?}", key, e);
+            Track::default()
+        }
+    };
+    let track = track.advance(config, now_ms);
+    let heartbeat = track.is_heartbeat(config);
+    Beat { key, track, heartbeat }
+}
+
+/// Save a beat's track, naming the capture the delivery was stored as or folded into
+pub async fn save(kv: &(impl KvBackend + ?Sized), mut beat: Beat, capture_id: &str) {
+    beat.track.capture_id = Some(capture_id.to_string());
+    let value = match serde_json::to_string(&beat.track) {
+        Ok(value) => value,
+        Err(e) => {
+            log_warn!("⚠️  Failed to encode heartbeat track {}: {:?}", beat.key, e);
+            return;
+        }
+    };
+    if let Err(e) = kv.put_text(&beat.key, &value, Some(TRACK_TTL_SECONDS)).await {
+        log_warn!("⚠️  Failed to save heartbeat track {}: {:?}", beat.key, e);
+    }
+}
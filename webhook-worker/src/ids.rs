@@ -0,0 +1,47 @@
+//! Record identifiers
+//! Capture IDs are ULIDs by default: 48-bit millisecond timestamp + 80 random bits,
+//! Crockford base32 encoded. They sort by creation time, so IDs generated in
+//! different regions never conflict and still order naturally.
+
+use worker::*;
+
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// New capture ID in the format selected by the `ID_FORMAT` var (`ulid` or `uuid`)
+pub fn new_capture_id(env: &Env, timestamp_ms: i64) -> String {
+    if uses_uuid(env) {
+        uuid::Uuid::new_v4().to_string()
+    } else {
+        ulid(timestamp_ms)
+    }
+}
+
+/// Capture IDs are UUIDs rather than ULIDs (`ID_FORMAT = "uuid"`)
+pub fn uses_uuid(env: &Env) -> bool {
+    env.var("ID_FORMAT").is_ok_and(|value| value.to_string() == "uuid")
+}
+
+/// Generate a ULID for the given Unix millisecond timestamp, its 80 random bits
+/// from the platform CSPRNG (`crypto.getRandomValues` in the Workers runtime)
+pub fn ulid(timestamp_ms: i64) -> String {
+    let mut entropy = [0u8; 16];
+    getrandom::fill(&mut entropy[6..]).expect("the runtime provides a CSPRNG");
+    ulid_from(timestamp_ms, u128::from_be_bytes(entropy))
+}
+
+/// ULID for a timestamp with the low 80 bits of `entropy` as its random part
+pub fn ulid_from(timestamp_ms: i64, entropy: u128) -> String {
+    let random = entropy & ((1u128 << 80) - 1);
+    let value = ((timestamp_ms as u128 & ((1u128 << 48) - 1)) << 80) | random;
+    encode(value)
+}
+
+/// Crockford base32 encoding of a 128-bit value (26 characters)
+fn encode(mut value: u128) -> String {
+    let mut out = [0u8; 26];
+    for slot in out.iter_mut().rev() {
+        *slot = CROCKFORD[(value & 0x1f) as usize];
+        value >>= 5;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
@@ -0,0 +1,976 @@
+//! Webhook ingestion handler
+//! Captures requests sent to /w/{uuid} into D1, stores files PUT to signed
+//! /w/{uuid}/upload/{filename} URLs in R2, and hands WebSocket upgrades on
+//! /w/{uuid}/ws and /w/{uuid}/mqtt to the webhook's capture socket DO
+
+use crate::auth::RouteData;
+use crate::backchannel_logout;
+use crate::abuse::{self, AbuseConfig, Miss};
+use crate::cache;
+use crate::config::{self, SignatureProvider, WebhookSettings};
+use crate::capture_log::{self, CaptureEvent};
+use crate::chain;
+use crate::compression;
+use crate::console;
+use crate::dedup;
+use crate::error_budget;
+use crate::charset::{self, Charset};
+use crate::durable::socket::{self, Protocol};
+use crate::durable::{events, hot_webhook, relay, sequence};
+use crate::forward;
+use crate::github;
+use crate::headers::HeaderLimits;
+use crate::heartbeat;
+use crate::ids;
+use crate::kv::TolerantKv;
+use crate::oauth;
+use crate::latency;
+use crate::pipeline::{self, CaptureMeta, IncomingRequest, Rejection};
+use crate::preview::{self, Preview};
+use crate::processing::{Processing, SignedUrl};
+use crate::residency;
+use crate::responses;
+use crate::scim::{self, ScimRequest};
+use crate::security_events::{self, Kind, SecurityEvent};
+use crate::shapes;
+use crate::signature::{self, Verification};
+use crate::storage::{self, CaptureRecord, Consistency, RequestQuery, SortColumn, Storage};
+use crate::slack::{self, SlackRequest};
+use crate::split;
+use crate::stripe;
+use crate::twiml;
+use crate::usage;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use worker::*;
+
+/// R2 bucket for file-drop uploads
+const UPLOAD_BUCKET: &str = "UPLOADS";
+
+/// Largest accepted upload unless `MAX_UPLOAD_BYTES` is set
+const DEFAULT_MAX_UPLOAD_BYTES: usize = 100 * 1024 * 1024;
+
+/// Capture a single webhook delivery, emitting one structured log event per request
+pub async fn capture(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let mut event = CaptureEvent::start(&uuid, req.method().as_ref(), capture_log::now_ms());
+
+    let result = capture_request(req, &ctx, &uuid, &mut event).await;
+    let status = result.as_ref().map_or(500, Response::status_code);
+    match &result {
+        Ok(_) => event.finish(status, None),
+        Err(e) => event.finish(status, Some(e.to_string())),
+    }
+    // Failed ingestions count against the error budget (a webhook over its storage quota is no failure);
+    // full tallies are flushed after the response
+    if let Some(budget) = error_budget::Budget::from_env(&ctx.env) {
+        if let Some(tally) = error_budget::record(status >= 500 && status != 507, capture_log::now_ms()) {
+            ctx.data.context.wait_until(error_budget::flush(ctx.env.clone(), budget, tally));
+        }
+    }
+    result
+}
+
+async fn capture_request(
+    mut req: Request,
+    ctx: &RouteContext<RouteData>,
+    uuid: &str,
+    event: &mut CaptureEvent,
+) -> Result<Response> {
+    if uuid.is_empty() {
+        return Response::error("Invalid webhook URL", 400);
+    }
+
+    let env = &ctx.env;
+    // Pathological header sets are refused before anything is read or looked up
+    let mut headers = match pipeline::sanitize_headers(req.headers(), &HeaderLimits::from_env(env)) {
+        Ok(headers) => headers,
+        Err(rejection) => return reject(rejection),
+    };
+    // Only the worker itself chains deliveries
+    headers.remove(chain::CHAIN_HEADER);
+    let method = req.method().to_string();
+    // Read raw bytes: text is normalized to UTF-8, binary payloads are previewed
+    let bytes = if pipeline::has_body(&method) {
+        req.bytes().await.ok()
+    } else {
+        None
+    };
+    let (body, raw) = match bytes {
+        Some(bytes) => {
+            let (body, raw) = RawBody::read(&bytes, headers.get("content-type").map(String::as_str));
+            (Some(body), raw)
+        }
+        None => (None, RawBody::default()),
+    };
+    // OAuth callbacks never store, log or forward the authorization code
+    let mut url = req.url()?;
+    let mut raw = raw;
+    if url.path().ends_with(oauth::CALLBACK_PATH) {
+        let (stripped, code) = oauth::strip_secrets(&url);
+        url = stripped;
+        raw.oauth_callback = true;
+        raw.oauth_code = code;
+    }
+    let incoming = IncomingRequest {
+        url,
+        headers,
+        body,
+        method,
+        received_at_ms: event.received_at_ms,
+    };
+    capture_incoming(env, Some(&ctx.data.context), uuid, incoming, raw, abuse::client_ip(&req), event).await
+}
+
+/// What the stored text body doesn't carry about the bytes that arrived
+#[derive(Default)]
+struct RawBody {
+    preview: Option<Preview>,
+    charset: Option<Charset>,
+    /// Base64 original, when UTF-8 normalization changed it
+    original: Option<String>,
+    /// An OAuth callback (see `oauth.rs`), and the code stripped from its stored query
+    oauth_callback: bool,
+    oauth_code: Option<String>,
+}
+
+impl RawBody {
+    /// The body as UTF-8 text, plus what to store about the original bytes
+    fn read(bytes: &[u8], content_type: Option<&str>) -> (String, Self) {
+        match charset::decode(bytes, content_type) {
+            Some(decoded) => {
+                let raw = Self {
+                    preview: None,
+                    charset: Some(decoded.charset),
+                    original: decoded.transcoded.then(|| BASE64.encode(bytes)),
+                    ..Self::default()
+                };
+                (decoded.text, raw)
+            }
+            None => {
+                let raw = Self {
+                    preview: preview::generate(bytes, content_type),
+                    ..Self::default()
+                };
+                (String::from_utf8_lossy(bytes).into_owned(), raw)
+            }
+        }
+    }
+}
+
+/// Run a delivery through lookup, verification, storage and forwarding; chained
+/// deliveries (see `chain.rs`) come in here without a client IP
+async fn capture_incoming(
+    env: &Env,
+    context: Option<&Context>,
+    uuid: &str,
+    incoming: IncomingRequest,
+    raw: RawBody,
+    client_ip: Option<String>,
+    event: &mut CaptureEvent,
+) -> Result<Response> {
+    let upstream = incoming.headers.get(chain::CHAIN_HEADER).cloned();
+    let chained = upstream.is_some();
+    event.chain = upstream.clone();
+    let mut parsed = pipeline::parse(&incoming)?;
+    let url = incoming.url;
+    // Identity sinks answer as the protocol requires (see `oauth.rs`, `backchannel_logout.rs`, `scim.rs`)
+    let logout = parsed.method == "POST" && url.path().ends_with(backchannel_logout::PATH);
+    let scim = ScimRequest::parse(&url, uuid, &parsed.method, &parsed.data);
+    if parsed.indexed_headers.event_type.is_none() {
+        parsed.indexed_headers.event_type = if raw.oauth_callback {
+            Some(oauth::event_type(&url).to_string())
+        } else if logout {
+            Some(backchannel_logout::EVENT_TYPE.to_string())
+        } else {
+            scim.as_ref().map(ScimRequest::event_type)
+        };
+    }
+    event.content_type = parsed.indexed_headers.content_type.clone();
+    event.event_type = parsed.indexed_headers.event_type.clone();
+    event.request_bytes = Some(parsed.size_bytes as i64);
+
+    // Get KV cache and D1 database (webhook definitions)
+    let kv_store = env.kv("WEBHOOK_CACHE")?;
+    let kv = TolerantKv::new(&kv_store);
+    let db = env.d1("DB")?;
+
+    // Step 1: Lookup webhook ID (KV first, D1 fallback); decoy UUIDs never resolve
+    let abuse_config = AbuseConfig::from_env(env);
+    let lookup_started = capture_log::now_ms();
+    let webhook_id = if abuse_config.is_decoy(uuid) {
+        None
+    } else {
+        cache::resolve_webhook_id(&kv, &db, uuid).await?
+    };
+    event.lookup_ms = Some(capture_log::now_ms() - lookup_started);
+    let webhook_id = match webhook_id {
+        Some(id) => id,
+        None => {
+            if let Some(ip) = client_ip {
+                let user_agent = parsed.indexed_headers.user_agent.as_ref();
+                let received_at_ms = parsed.received_at_ms;
+                match abuse::record_miss(&db, &abuse_config, &ip, uuid, user_agent, received_at_ms).await {
+                    Ok(Miss::Counted) => {}
+                    Ok(miss) => {
+                        let security_event = match miss {
+                            Miss::Flagged { distinct_uuids, decoy } => {
+                                SecurityEvent::new(Kind::Enumeration, received_at_ms)
+                                    .detail(serde_json::json!({ "distinct_uuids": distinct_uuids, "decoy": decoy }))
+                            }
+                            _ => SecurityEvent::new(Kind::IpBlocked, received_at_ms)
+                                .detail(serde_json::json!({ "decoy_mode": abuse_config.mode.as_str() })),
+                        };
+                        let security_event = security_event.ip(Some(ip)).uuid(uuid).user_agent(user_agent.cloned());
+                        security_events::record(env, security_event).await;
+                        event.decoy = true;
+                        return abuse::decoy_response(abuse_config.mode, uuid, &parsed.method, received_at_ms).await;
+                    }
+                    Err(e) => log_warn!("⚠️  Failed to record enumeration miss: {:?}", e),
+                }
+            }
+            return Response::error("Webhook not found", 404);
+        }
+    };
+    event.webhook_id = Some(webhook_id.clone());
+
+    // Environment, extraction rules and event route from the webhook's settings
+    let settings = config::load(&kv, &db, &webhook_id).await?;
+    event.kv_fallback = kv.fell_back();
+    if let Err(rejection) = check_residency(env, &settings) {
+        return rejection;
+    }
+    let applied = pipeline::apply(&mut parsed, uuid, &settings);
+    event.environment = applied.environment.map(|environment| environment.name.clone());
+    event.event_type = parsed.indexed_headers.event_type.clone();
+    event.route = applied.route.map(|route| route.event_type.clone());
+    let mut data_id = dedup::new_capture_id(
+        env,
+        &webhook_id,
+        parsed.indexed_headers.idempotency_key.as_deref(),
+        settings.config.dedup_bodies,
+        &parsed.method,
+        parsed.data.as_bytes(),
+        parsed.received_at_ms,
+    );
+
+    // Signed URLs (exp + sig), mandatory for webhooks that require them; chained hops never went public
+    if !chained {
+        if let Some(rejection) = pipeline::check_signed_url(&url, uuid, &settings, parsed.received_at) {
+            return reject(rejection);
+        }
+    }
+
+    // Webhooks with a storage quota reserve the delivery's bytes before anything is stored (see `usage.rs`)
+    let usage_changes = usage::capture_changes(parsed.size_bytes as i64);
+    if let Some(quota) = &settings.config.storage_quota {
+        if !usage::reserve(env, &webhook_id, &usage_changes, quota).await {
+            log_warn!("⚠️  Webhook {} is over its storage quota of {} bytes", webhook_id, quota.max_bytes);
+            return Response::error("Storage quota exceeded", 507);
+        }
+    }
+
+    // Provider signature + timestamp window; failures are stored (flagged) before rejecting
+    let verification = match settings.config.signature.as_ref() {
+        Some(config) => {
+            // Signed over the body as delivered, before any hook script edit
+            let body = applied.script.original_body.as_deref().unwrap_or(&parsed.data);
+            Some(signature::verify(env, config, &url, &parsed.headers, body, parsed.received_at).await)
+        }
+        None => None,
+    };
+    event.verification = verification.map(|verification| verification.as_str());
+    // An enforced failure is still stored (flagged), but neither fanned out nor forwarded
+    let rejection = pipeline::signature_rejection(&settings, verification);
+    if let Some(failure) = signature_failure(&settings, verification) {
+        let security_event = failure
+            .ip(client_ip)
+            .uuid(uuid)
+            .webhook(webhook_id.as_str())
+            .user_agent(parsed.indexed_headers.user_agent.clone());
+        security_events::record(env, security_event).await;
+    }
+
+    // Reserve the next per-webhook sequence number
+    let sequence = match sequence::next(env, &webhook_id).await {
+        Ok(sequence) => Some(sequence),
+        Err(e) => {
+            log_warn!("⚠️  Failed to assign sequence number: {:?}", e);
+            None
+        }
+    };
+
+    let headers = parsed.headers.clone();
+    let signed_url = if chained { SignedUrl::Internal } else { SignedUrl::checked(&url) };
+    let mut processing = Processing::new(&applied, &settings, Some(signed_url), verification);
+    // Providers that expect a particular answer: TwiML for Twilio, a message for Slack, SCIM resources, ...
+    let content_type = parsed.indexed_headers.content_type.as_deref();
+    let config = &settings.config;
+    let twiml = config.twiml.as_ref().and_then(|twiml| twiml::respond(twiml, content_type, &parsed.data));
+    let slack = config
+        .slack
+        .as_ref()
+        .and_then(|slack| Some((slack, SlackRequest::parse(content_type, &parsed.data)?)));
+    let mut scim_change = None;
+    let provider_response = match (twiml, &slack, &scim) {
+        (Some(twiml), _, _) => Some(("twiml", twiml, Vec::new())),
+        (None, Some((config, request)), _) => Some(("slack", slack::respond(config, request), Vec::new())),
+        (None, None, Some(request)) => {
+            let existing = match request.resource_type() {
+                Some(resource_type) => match scim::load(&db, &webhook_id, resource_type).await {
+                    Ok(existing) => existing,
+                    Err(e) => {
+                        release(env, &settings, &webhook_id, &usage_changes).await;
+                        return Err(e);
+                    }
+                },
+                None => Vec::new(),
+            };
+            let new_id = uuid::Uuid::new_v4().to_string();
+            let (reply, change) = scim::handle(request, &existing, parsed.received_at_ms, &new_id);
+            scim_change = change;
+            let (response, headers) = reply.into_response();
+            Some(("scim", response, headers))
+        }
+        (None, None, None) if logout => {
+            let response = backchannel_logout::respond(content_type, &parsed.data, parsed.received_at);
+            Some(("oidc_logout", response, vec![("Cache-Control", "no-store".to_string())]))
+        }
+        (None, None, None) => None,
+    };
+    if let (Some((kind, _, _)), None) = (&provider_response, applied.response()) {
+        processing.response = kind;
+    }
+    processing.simulated_latency_ms = config.latency_profile.as_ref().map(latency::simulated_delay_ms);
+    // A/B and shadow forwarding are configured forwarding too, so a script clearing it skips them
+    let forwarding = !applied.script.clear_forwarding && rejection.is_none();
+    let split_arm = config
+        .split
+        .as_ref()
+        .filter(|_| forwarding)
+        .map(|traffic| (traffic, split::arm(traffic, &data_id)));
+    let shadow = config.shadow.as_ref().filter(|_| forwarding);
+    let mut extra_targets = split_arm.map_or(Vec::new(), |(traffic, arm)| split::targets(traffic, arm));
+    extra_targets.extend(shadow.map(|shadow| shadow.primary.as_str()));
+    processing.split = split_arm.map(|(_, arm)| arm);
+    processing.shadow = shadow.map(|shadow| shadow.shadow.clone());
+    for target in &extra_targets {
+        if !processing.forwards.iter().any(|forward| forward == target) {
+            processing.forwards.push(target.to_string());
+        }
+    }
+    // Identical deliveries at a steady interval are heartbeats (see `heartbeat.rs`)
+    let beat = match &config.heartbeat {
+        Some(tracking) => {
+            let body = parsed.data.as_bytes();
+            Some(heartbeat::observe(&kv, tracking, &webhook_id, &parsed.method, body, parsed.received_at_ms).await)
+        }
+        None => None,
+    };
+    processing.heartbeat = beat.as_ref().and_then(heartbeat::Beat::trail);
+    // An open debug console may hold forwarding or answer in the configured response's place
+    let session = console::load(&kv, &webhook_id).await;
+    let paused = session.as_ref().is_some_and(|session| session.paused);
+    let console_response = session.as_ref().and_then(|session| session.response.clone());
+    processing.paused = paused;
+    if console_response.is_some() {
+        processing.response = "console";
+    }
+    let mut record = pipeline::into_record(
+        parsed,
+        CaptureMeta {
+            id: data_id.clone(),
+            webhook_id,
+            sequence,
+            verification,
+            environment: applied.environment,
+        },
+    );
+    record.processing = Some(processing.to_json());
+    record.preview = raw.preview.map(|preview| preview.to_json());
+    record.charset = raw.charset.map(|charset| charset.as_str().to_string());
+    record.original_body = raw.original;
+    github::label(env, &kv, &mut record.github).await;
+    let stripe_verified = verification == Some(Verification::Valid)
+        && settings.config.signature.as_ref().is_some_and(|config| config.provider == SignatureProvider::Stripe);
+    if settings.config.stripe_cross_check && stripe_verified {
+        let checked = stripe::cross_check(env, &record.data, record.received_at_ms).await;
+        record.stripe_cross_check = checked.and_then(|checked| serde_json::to_string(&checked).ok());
+    }
+    if let (Some(code), Some(client)) = (&raw.oauth_code, &settings.config.oauth) {
+        let exchange = oauth::exchange(env, client, code, &url).await;
+        record.oauth_exchange = serde_json::to_string(&exchange).ok();
+    }
+
+    // Step 2: Persist the capture (hot webhooks buffer in their Durable Object first)
+    let store_started = capture_log::now_ms();
+    event.hot = hot_webhook::is_hot(env, uuid) && settings.jurisdiction.is_none();
+    // Bodies are compressed for storage only; forwards and subscribers get them as received
+    let mut compressed = compression::for_storage(settings.config.compression.as_ref(), &record).await;
+    let mut collapsed = false;
+    let stored: Result<()> = async {
+        if event.hot {
+            // Hot captures are deduplicated when the buffer is flushed
+            hot_webhook::enqueue(env, compressed.as_ref().unwrap_or(&record)).await?;
+        } else {
+            let storage = storage::open_in(env, Consistency::Primary, settings.jurisdiction).await?;
+            // A Svix or Standard Webhooks retry outside the dedup bucket still names the message it redelivers;
+            // it is answered with that capture rather than inserted again (possibly into a newer partition)
+            let mut redelivered = false;
+            if let Some(svix_id) = record.indexed_headers.svix_id.clone() {
+                let received_at = record.received_at;
+                match dedup::svix_original(storage.as_ref(), &record.webhook_id, &svix_id, received_at).await {
+                    Ok(Some(original)) => {
+                        record.id = original.clone();
+                        data_id = original;
+                        redelivered = true;
+                    }
+                    Ok(None) => {}
+                    Err(e) => log_warn!("⚠️  Failed to look up Svix message {}: {:?}", svix_id, e),
+                }
+            }
+            if let Some(compressed) = compressed.as_mut() {
+                compressed.id = record.id.clone();
+            }
+            // Collapsed heartbeats count towards the previous beat's capture instead
+            let previous = beat.as_ref().zip(settings.config.heartbeat.as_ref()).filter(|_| !redelivered);
+            if let Some(previous) = previous.and_then(|(beat, tracking)| beat.collapse_into(tracking)) {
+                collapsed = storage.record_repeat(&record.webhook_id, previous, record.received_at_ms).await?;
+                if collapsed {
+                    log_debug!("💓 Heartbeat for webhook {} folded into capture {}", record.webhook_id, previous);
+                    record.id = previous.to_string();
+                    data_id = previous.to_string();
+                }
+            }
+            if redelivered || (!collapsed && !storage.insert_capture(compressed.as_ref().unwrap_or(&record)).await?) {
+                log_info!("♻️  Redelivery of capture {} for webhook {}, already stored", record.id, record.webhook_id);
+                event.duplicate = true;
+                stored_original(storage.as_ref(), &mut record).await?;
+            }
+        }
+        Ok(())
+    }
+    .await;
+    // A delivery that failed to store gives its quota reservation back
+    if let Err(e) = stored {
+        release(env, &settings, &record.webhook_id, &usage_changes).await;
+        return Err(e);
+    }
+    event.store_ms = Some(capture_log::now_ms() - store_started);
+    if let Some(beat) = beat {
+        heartbeat::save(&kv, beat, &record.id).await;
+    }
+    // A redelivery (or folded heartbeat) gives its reservation back; without a quota,
+    // new captures are counted once stored
+    let usage_changes = match (settings.config.storage_quota.is_some(), event.duplicate || collapsed) {
+        (true, true) => Some(usage::undo(&usage_changes)),
+        (false, false) => Some(usage_changes),
+        _ => None,
+    };
+    if let Some(changes) = usage_changes {
+        let counted = usage::add(env.clone(), record.webhook_id.clone(), changes);
+        match context {
+            Some(context) => context.wait_until(counted),
+            None => counted.await,
+        }
+    }
+
+    if !event.duplicate && !collapsed {
+        // Waiters, subscribers and relays only see deliveries that passed an enforced signature check
+        if rejection.is_none() {
+            fan_out(env, context, &record, &settings).await;
+        }
+        if session.is_some() {
+            if let Err(e) = crate::durable::console::publish(env, &record).await {
+                log_warn!("⚠️  Failed to push capture to the debug console: {:?}", e);
+            }
+        }
+        if let Some(tracking) = &settings.config.shapes {
+            shapes::track(&db, tracking, uuid, &record).await;
+        }
+    }
+
+    event.data_id = Some(data_id);
+    event.sequence = sequence;
+    if let Some(rejection) = rejection {
+        return reject(rejection);
+    }
+
+    // The environment's and the matching route's forwarding targets get the delivery replayed downstream;
+    // a redelivery already had its turn, and a console's pause holds them all
+    let held = event.duplicate || paused;
+    let mut targets = if held { Vec::new() } else { applied.forward_targets() };
+    for target in extra_targets.into_iter().filter(|_| !held) {
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+    let (mut split_outcomes, mut primary_outcome) = ((None, None), None);
+    let signer = settings.config.forward_signing.as_ref().and_then(|signing| {
+        let secret = config::resolve_secret(env, &signing.secret);
+        if secret.is_none() {
+            log_warn!("⚠️  Forward signing secret {} is not configured", signing.secret);
+        }
+        secret.map(|secret| forward::Signer {
+            secret,
+            id: record.id.clone(),
+            retries: signing.retries,
+        })
+    });
+    for target in targets {
+        let delivery = forward::Delivery {
+            method: &record.method,
+            headers: &headers,
+            body: &record.data,
+            query: url.query(),
+        };
+        let outcome = match chain::target_uuid(target) {
+            Some(next) => forward_chained(env, &url, next, uuid, upstream.as_deref(), &record, &headers).await,
+            None => match &signer {
+                Some(signer) => forward::send_signed(target, &delivery, signer).await,
+                None => forward::send(target, &delivery).await,
+            },
+        };
+        if let Some(error) = &outcome.error {
+            log_warn!("⚠️  Forwarding to {} failed: {}", target, error);
+        }
+        if let Err(e) = latency::record(&db, &record.webhook_id, target, &outcome, record.received_at).await {
+            log_warn!("⚠️  Failed to record forward latency: {:?}", e);
+        }
+        if let Err(e) = responses::record(&db, &record.webhook_id, &record.id, target, &outcome, capture_log::now_ms()).await {
+            log_warn!("⚠️  Failed to store forward response: {:?}", e);
+        }
+        event.forward_status = outcome.status;
+        event.forward_ms = Some(event.forward_ms.unwrap_or(0) + outcome.duration_ms);
+        if shadow.is_some_and(|shadow| target == shadow.primary) {
+            primary_outcome = Some(outcome.clone());
+        }
+        if let Some((traffic, split::Arm::Both)) = split_arm {
+            if target == traffic.a {
+                split_outcomes.0 = Some(outcome);
+            } else if target == traffic.b {
+                split_outcomes.1 = Some(outcome);
+            }
+        }
+    }
+    if let (Some(a), Some(b)) = &split_outcomes {
+        let comparison = split::compare(&record.id, a, b, capture_log::now_ms());
+        if let Err(e) = split::record(&db, &record.webhook_id, split::Mode::Split, &comparison).await {
+            log_warn!("⚠️  Failed to store split comparison: {:?}", e);
+        }
+    }
+    // The shadow gets the delivery once the sender has its answer; chained captures have no fetch event to wait on
+    if let (Some(shadow), Some(primary)) = (shadow, primary_outcome) {
+        let delivery = split::ShadowDelivery {
+            target: shadow.shadow.clone(),
+            method: record.method.clone(),
+            headers: headers.clone(),
+            body: record.data.clone(),
+            query: url.query().map(str::to_string),
+            signer: signer.clone(),
+            webhook_id: record.webhook_id.clone(),
+            capture_id: record.id.clone(),
+            primary,
+        };
+        match context {
+            Some(context) => context.wait_until(split::shadow(env.clone(), delivery)),
+            None => split::shadow(env.clone(), delivery).await,
+        }
+    }
+
+    // Scripts and routes may answer with the response the provider expects instead of the capture summary
+    if let Some((config, request)) = &slack {
+        slack::follow_up(config, request).await;
+    }
+    let custom = match (&console_response, applied.response(), &provider_response) {
+        (Some(custom), _, _) | (None, Some(custom), _) => Some((custom, &[][..])),
+        (None, None, Some((_, custom, headers))) => Some((custom, headers.as_slice())),
+        (None, None, None) => applied.fallback_response.map(|custom| (custom, &[][..])),
+    };
+    // SCIM writes only land when the SCIM answer is the one sent
+    if let (Some(change), None, None) = (&scim_change, &console_response, applied.response()) {
+        scim::save(&db, &record.webhook_id, change).await?;
+    }
+    if let Some(delay_ms) = processing.simulated_latency_ms.filter(|delay_ms| *delay_ms > 0) {
+        Delay::from(std::time::Duration::from_millis(delay_ms as u64)).await;
+    }
+    if let Some((custom, headers)) = custom {
+        let mut response = custom.to_response()?;
+        for (name, value) in headers {
+            response.headers_mut().set(name, value)?;
+        }
+        event.response_bytes = Some(custom.body.len() as i64);
+        crate::set_cors_headers(response.headers_mut())?;
+        return Ok(response);
+    }
+
+    // Success response
+    let body = pipeline::success_body(uuid, &record).to_string();
+    event.response_bytes = Some(body.len() as i64);
+
+    let mut response = Response::ok(body)?;
+    response.headers_mut().set("Content-Type", "application/json")?;
+
+    crate::set_cors_headers(response.headers_mut())?;
+
+    Ok(response)
+}
+
+/// Give back what a delivery reserved against its webhook's storage quota
+async fn release(env: &Env, settings: &WebhookSettings, webhook_id: &str, changes: &[(&'static str, i64)]) {
+    if settings.config.storage_quota.is_some() {
+        usage::add(env.clone(), webhook_id.to_string(), usage::undo(changes)).await;
+    }
+}
+
+/// Point `record` at the already stored capture with its ID, so a redelivery is
+/// answered with the original's receive time and sequence number
+async fn stored_original(storage: &dyn Storage, record: &mut CaptureRecord) -> Result<()> {
+    let query = RequestQuery {
+        webhook_id: record.webhook_id.clone(),
+        limit: 1,
+        offset: 0,
+        since: None,
+        until: None,
+        sort: SortColumn::ReceivedAt,
+        ascending: false,
+        filters: vec![("id", record.id.clone())],
+    };
+    if let Some(original) = storage.list_requests(&query).await?.into_iter().next() {
+        record.received_at = original.received_at;
+        record.received_at_ms = original.received_at_ms.unwrap_or(original.received_at * 1000);
+        record.event_time = original.event_time;
+        record.sequence = original.sequence;
+        record.size_bytes = original.size_bytes as i32;
+    }
+    Ok(())
+}
+
+/// Capture a delivery again under the `webhook:{uuid}` target `next`, inside this worker
+async fn forward_chained(
+    env: &Env,
+    url: &Url,
+    next: &str,
+    uuid: &str,
+    upstream: Option<&str>,
+    record: &CaptureRecord,
+    headers: &std::collections::HashMap<String, String>,
+) -> forward::ForwardOutcome {
+    let started = capture_log::now_ms();
+    let Some(chain) = chain::next(upstream, uuid, &record.id, next) else {
+        return forward::ForwardOutcome {
+            status: None,
+            duration_ms: 0,
+            error: Some(format!("chain to {} would loop or exceed {} hops", next, chain::MAX_DEPTH)),
+            response: None,
+        };
+    };
+
+    let mut headers = headers.clone();
+    headers.insert(chain::CHAIN_HEADER.to_string(), chain);
+    let mut url = url.clone();
+    url.set_path(&format!("/w/{}", next));
+    let incoming = IncomingRequest {
+        method: record.method.clone(),
+        url,
+        headers,
+        body: pipeline::has_body(&record.method).then(|| record.data.clone()),
+        received_at_ms: started,
+    };
+
+    let mut event = CaptureEvent::start(next, &record.method, started);
+    let captured = Box::pin(capture_incoming(env, None, next, incoming, RawBody::default(), None, &mut event));
+    let result = match captured.await {
+        Ok(response) => forward::read_response(response).await,
+        Err(e) => Err(e),
+    };
+    let duration_ms = capture_log::now_ms() - started;
+    match result {
+        Ok((status, response)) => {
+            event.finish(status, None);
+            forward::ForwardOutcome {
+                status: Some(status),
+                duration_ms,
+                error: None,
+                response: Some(response),
+            }
+        }
+        Err(e) => {
+            event.finish(500, Some(e.to_string()));
+            forward::ForwardOutcome {
+                status: None,
+                duration_ms,
+                error: Some(e.to_string()),
+                response: None,
+            }
+        }
+    }
+}
+
+/// Store a file PUT to a signed upload URL in R2 and capture it like a delivery
+pub async fn upload(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let mut event = CaptureEvent::start(&uuid, req.method().as_ref(), capture_log::now_ms());
+
+    match store_upload(req, &ctx, &uuid, &mut event).await {
+        Ok(response) => {
+            event.finish(response.status_code(), None);
+            Ok(response)
+        }
+        Err(e) => {
+            event.finish(500, Some(e.to_string()));
+            Err(e)
+        }
+    }
+}
+
+async fn store_upload(
+    mut req: Request,
+    ctx: &RouteContext<RouteData>,
+    uuid: &str,
+    event: &mut CaptureEvent,
+) -> Result<Response> {
+    let filename = ctx.param("filename").cloned().unwrap_or_default();
+    if !pipeline::valid_upload_filename(&filename) {
+        return Response::error("Invalid upload file name", 400);
+    }
+
+    let env = &ctx.env;
+    let headers = match pipeline::sanitize_headers(req.headers(), &HeaderLimits::from_env(env)) {
+        Ok(headers) => headers,
+        Err(rejection) => return reject(rejection),
+    };
+    let max_bytes = env
+        .var("MAX_UPLOAD_BYTES")
+        .ok()
+        .and_then(|value| value.to_string().parse().ok())
+        .unwrap_or(DEFAULT_MAX_UPLOAD_BYTES);
+    let declared: Option<usize> = headers.get("content-length").and_then(|length| length.parse().ok());
+    if declared.is_some_and(|length| length > max_bytes) {
+        return Response::error("Upload too large", 413);
+    }
+
+    let kv_store = env.kv("WEBHOOK_CACHE")?;
+    let kv = TolerantKv::new(&kv_store);
+    let db = env.d1("DB")?;
+    let webhook_id = if AbuseConfig::from_env(env).is_decoy(uuid) {
+        None
+    } else {
+        cache::resolve_webhook_id(&kv, &db, uuid).await?
+    };
+    let Some(webhook_id) = webhook_id else {
+        return Response::error("Webhook not found", 404);
+    };
+    event.webhook_id = Some(webhook_id.clone());
+
+    let settings = config::load(&kv, &db, &webhook_id).await?;
+    event.kv_fallback = kv.fell_back();
+    if let Err(rejection) = check_residency(env, &settings) {
+        return rejection;
+    }
+    let url = req.url()?;
+    if let Some(rejection) = pipeline::check_upload_url(&url, uuid, &filename, &settings, event.received_at_ms / 1000) {
+        return reject(rejection);
+    }
+    let bucket = match residency::bucket(env, UPLOAD_BUCKET, settings.jurisdiction) {
+        Ok(Some(bucket)) => bucket,
+        Ok(None) => return Response::error("File uploads are not configured", 503),
+        Err(e) => {
+            log_error!("❌ {:?}", e);
+            return Response::error("File uploads are not configured", 503);
+        }
+    };
+
+    let file = req.bytes().await?;
+    if file.len() > max_bytes {
+        return Response::error("Upload too large", 413);
+    }
+    let object_changes = usage::object_changes(file.len() as u64);
+    if let Some(quota) = &settings.config.storage_quota {
+        if !usage::reserve(env, &webhook_id, &object_changes, quota).await {
+            log_warn!("⚠️  Webhook {} is over its storage quota of {} bytes", webhook_id, quota.max_bytes);
+            return Response::error("Storage quota exceeded", 507);
+        }
+    }
+    let owner = webhook_id.clone();
+    let stored: Result<_> = async {
+        let data_id = ids::new_capture_id(env, event.received_at_ms);
+        let key = format!("uploads/{}/{}/{}", webhook_id, data_id, filename);
+        let metadata = HttpMetadata {
+            content_type: headers.get("content-type").cloned(),
+            ..HttpMetadata::default()
+        };
+        bucket.put(&key, file.clone()).http_metadata(metadata).execute().await?;
+        let preview = preview::generate(&file, headers.get("content-type").map(String::as_str));
+
+        let incoming = pipeline::upload_request(url, headers, &filename, &key, &file, event.received_at_ms);
+        let mut parsed = pipeline::parse(&incoming)?;
+        let applied = pipeline::apply(&mut parsed, uuid, &settings);
+        event.content_type = parsed.indexed_headers.content_type.clone();
+        event.event_type = parsed.indexed_headers.event_type.clone();
+        event.environment = applied.environment.map(|environment| environment.name.clone());
+        event.request_bytes = Some(file.len() as i64);
+
+        let sequence = match sequence::next(env, &webhook_id).await {
+            Ok(sequence) => Some(sequence),
+            Err(e) => {
+                log_warn!("⚠️  Failed to assign sequence number: {:?}", e);
+                None
+            }
+        };
+        let processing = Processing::new(&applied, &settings, Some(SignedUrl::Verified), None);
+        let mut record = pipeline::into_record(
+            parsed,
+            CaptureMeta {
+                id: data_id.clone(),
+                webhook_id,
+                sequence,
+                verification: None,
+                environment: applied.environment,
+            },
+        );
+        record.processing = Some(processing.to_json());
+        record.preview = preview.map(|preview| preview.to_json());
+
+        let store_started = capture_log::now_ms();
+        let compressed = compression::for_storage(settings.config.compression.as_ref(), &record).await;
+        storage::open_in(env, Consistency::Primary, settings.jurisdiction)
+            .await?
+            .insert_capture(compressed.as_ref().unwrap_or(&record))
+            .await?;
+        Ok((record, key, data_id, sequence, store_started))
+    }
+    .await;
+    let (record, key, data_id, sequence, store_started) = match stored {
+        Ok(stored) => stored,
+        // A failed upload gives its quota reservation back
+        Err(e) => {
+            release(env, &settings, &owner, &object_changes).await;
+            return Err(e);
+        }
+    };
+    event.store_ms = Some(capture_log::now_ms() - store_started);
+    event.data_id = Some(data_id);
+    event.sequence = sequence;
+    // The file was reserved against a quota already; its capture is counted either way
+    let mut usage_changes = usage::capture_changes(record.size_bytes as i64);
+    if settings.config.storage_quota.is_none() {
+        usage_changes.extend(object_changes);
+    }
+    ctx.data.context.wait_until(usage::add(env.clone(), record.webhook_id.clone(), usage_changes));
+
+    fan_out(env, Some(&ctx.data.context), &record, &settings).await;
+
+    let mut body = pipeline::success_body(uuid, &record);
+    body["key"] = serde_json::Value::String(key);
+    let body = body.to_string();
+    event.response_bytes = Some(body.len() as i64);
+    let mut response = Response::ok(body)?.with_status(201);
+    response.headers_mut().set("Content-Type", "application/json")?;
+    crate::set_cors_headers(response.headers_mut())?;
+    Ok(response)
+}
+
+/// Accept a capture WebSocket; every message it receives is stored as a capture
+pub async fn socket(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    upgrade(req, &ctx, Protocol::WebSocket).await
+}
+
+#[cfg(feature = "mqtt")]
+/// Accept an MQTT-over-WebSocket client; every PUBLISH is stored as a capture
+pub async fn mqtt(req: Request, ctx: RouteContext<RouteData>) -> Result<Response> {
+    let offered = req.headers().get("Sec-WebSocket-Protocol")?.unwrap_or_default();
+    if !offered.split(',').any(|protocol| protocol.trim() == socket::MQTT_SUBPROTOCOL) {
+        return Response::error("Expected the mqtt WebSocket subprotocol", 400);
+    }
+    upgrade(req, &ctx, Protocol::Mqtt).await
+}
+
+async fn upgrade(req: Request, ctx: &RouteContext<RouteData>, protocol: Protocol) -> Result<Response> {
+    let uuid = ctx.param("uuid").cloned().unwrap_or_default();
+    let upgrade = req.headers().get("Upgrade")?.unwrap_or_default();
+    if !upgrade.eq_ignore_ascii_case("websocket") {
+        return Response::error("Expected WebSocket upgrade", 426);
+    }
+
+    let env = &ctx.env;
+    let headers = match pipeline::sanitize_headers(req.headers(), &HeaderLimits::from_env(env)) {
+        Ok(headers) => headers,
+        Err(rejection) => return reject(rejection),
+    };
+
+    let kv_store = env.kv("WEBHOOK_CACHE")?;
+    let kv = TolerantKv::new(&kv_store);
+    let db = env.d1("DB")?;
+    let webhook_id = if AbuseConfig::from_env(env).is_decoy(&uuid) {
+        None
+    } else {
+        cache::resolve_webhook_id(&kv, &db, &uuid).await?
+    };
+    let Some(webhook_id) = webhook_id else {
+        return Response::error("Webhook not found", 404);
+    };
+
+    // Signed URLs apply to the handshake; messages on an accepted socket are trusted
+    let settings = config::load(&kv, &db, &webhook_id).await?;
+    let now = capture_log::now_ms() / 1000;
+    if let Some(rejection) = pipeline::check_signed_url(&req.url()?, &uuid, &settings, now) {
+        return reject(rejection);
+    }
+
+    socket::connect(env, &webhook_id, &uuid, protocol, &headers).await
+}
+
+/// Wake long-poll waiters and queue relay deliveries; a failure here never loses the capture.
+/// Live events go out after the sender is answered when there is a `context`.
+pub(crate) async fn fan_out(
+    env: &Env,
+    context: Option<&Context>,
+    record: &CaptureRecord,
+    settings: &WebhookSettings,
+) {
+    if events::is_enabled(env) {
+        let (env, record) = (env.clone(), record.clone());
+        let published = async move {
+            if let Err(e) = events::publish(&env, &record).await {
+                log_warn!("⚠️  Failed to publish live event: {:?}", e);
+            }
+        };
+        match context {
+            Some(context) => context.wait_until(published),
+            None => published.await,
+        }
+    }
+    if settings.config.relay {
+        if let Err(e) = relay::deliver(env, record).await {
+            log_warn!("⚠️  Failed to queue relay delivery: {:?}", e);
+        }
+    }
+}
+
+/// Security event for a delivery whose signature didn't verify; a missing one
+/// only counts when the webhook enforces signatures
+fn signature_failure(settings: &WebhookSettings, verification: Option<Verification>) -> Option<SecurityEvent> {
+    let enforced = settings.config.signature.as_ref().is_some_and(|config| config.enforce);
+    match verification? {
+        Verification::Valid => None,
+        Verification::Missing if !enforced => None,
+        failure => Some(
+            SecurityEvent::new(Kind::SignatureFailure, capture_log::now_ms())
+                .detail(serde_json::json!({ "verification": failure.as_str(), "enforced": enforced })),
+        ),
+    }
+}
+
+fn reject(rejection: Rejection) -> Result<Response> {
+    Response::error(rejection.message, rejection.status)
+}
+
+/// Refuse captures of a pinned project whose jurisdiction's bindings are missing
+/// (see `residency.rs`); the sender only learns that storage is unavailable
+pub(crate) fn check_residency(env: &Env, settings: &WebhookSettings) -> std::result::Result<(), Result<Response>> {
+    residency::check(env, settings.jurisdiction).map_err(|message| {
+        log_error!("❌ Refusing capture: {}", message);
+        Response::error("Capture storage unavailable", 503)
+    })
+}
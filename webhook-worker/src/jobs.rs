@@ -0,0 +1,623 @@
+//! Background jobs
+//! Bulk operations that would outgrow one request's CPU and time limits are
+//! queued as jobs instead: `POST /api/webhooks/{uuid}/jobs` takes a bulk
+//! delete, bulk replay or CSV export and `POST /api/admin/migrations/apply?async=true`
+//! a migration run, and both answer 202 with the job at once. Each job is
+//! run by its own `JobRunner` Durable Object (see `durable/jobs.rs`), one
+//! bounded step per alarm, and its status, progress (`done` of `total`) and
+//! result are kept in the `jobs` table for the status endpoints to report.
+//! Every step re-reads the row, so a cancelled job stops at its next step.
+
+use crate::api::requests::{find_request, COLUMN_FILTERS};
+use crate::config;
+use crate::export::{self, Column};
+use crate::legal_hold::Held;
+use crate::migrations;
+use crate::replay::{self, Overrides};
+use crate::storage::{RequestQuery, SortColumn, Storage};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Finished jobs (and their export files) are kept this long
+pub const RETENTION_DAYS: i64 = 7;
+
+/// Captures deleted per step
+pub const DELETE_BATCH: u32 = 100;
+
+/// Rows exported per step (one file chunk)
+pub const EXPORT_PAGE: u32 = 500;
+
+/// Most rows one export job writes unless `limit` is lower
+pub const MAX_EXPORT_ROWS: u32 = 1_000_000;
+
+/// Captures resent per step without pacing
+pub const REPLAY_BATCH: usize = 10;
+
+/// Captures one delete or replay job takes by ID
+pub const MAX_JOB_IDS: usize = 10_000;
+
+/// Jobs listed per webhook
+pub const MAX_LISTED: u32 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Delete,
+    Replay,
+    Export,
+    Migrate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl Status {
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Succeeded | Self::Failed | Self::Cancelled)
+    }
+}
+
+/// Delete captures by ID, or every capture matching a time bound and filters
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeleteParams {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ids: Vec<String>,
+    /// Received before this Unix time (seconds)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<i64>,
+    /// Indexed column filters, as the request listing takes them (`event_type`, `method`...)
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub filters: BTreeMap<String, String>,
+}
+
+/// Resend captures with a saved recipe or inline overrides (see `replay.rs`)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplayParams {
+    pub ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipe: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overrides: Option<Overrides>,
+    /// Capture URL the variants are stored as received on; set by the API
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture_url: Option<String>,
+}
+
+/// Write request metadata as CSV, like `GET /api/webhooks/{uuid}/export.csv`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub columns: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<i64>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub filters: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    pub bom: bool,
+}
+
+/// What a job does, as it was posted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Params {
+    Delete(DeleteParams),
+    Replay(ReplayParams),
+    Export(ExportParams),
+    Migrate,
+}
+
+impl Params {
+    pub fn kind(&self) -> Kind {
+        match self {
+            Self::Delete(_) => Kind::Delete,
+            Self::Replay(_) => Kind::Replay,
+            Self::Export(_) => Kind::Export,
+            Self::Migrate => Kind::Migrate,
+        }
+    }
+
+    /// What makes the job unusable before it starts
+    pub fn validate(&self) -> Option<String> {
+        let filters = match self {
+            Self::Delete(params) => {
+                if params.ids.is_empty() && params.until.is_none() && params.filters.is_empty() {
+                    return Some("A delete job needs ids, until or filters".to_string());
+                }
+                if params.ids.len() > MAX_JOB_IDS {
+                    return Some(format!("At most {} ids per job", MAX_JOB_IDS));
+                }
+                &params.filters
+            }
+            Self::Replay(params) => {
+                if !(1..=MAX_JOB_IDS).contains(&params.ids.len()) {
+                    return Some(format!("A replay job needs 1 to {} ids", MAX_JOB_IDS));
+                }
+                if params.recipe.is_some() && params.overrides.is_some() {
+                    return Some("Pass either recipe or overrides, not both".to_string());
+                }
+                return None;
+            }
+            Self::Export(params) => {
+                let columns = params.columns.as_deref().unwrap_or(export::DEFAULT_COLUMNS);
+                if let Err(column) = export::parse_columns(columns) {
+                    return Some(format!("Unknown export column: {}", column));
+                }
+                &params.filters
+            }
+            Self::Migrate => return None,
+        };
+        filters
+            .keys()
+            .find(|name| !COLUMN_FILTERS.iter().any(|(param, _)| param == name))
+            .map(|name| format!("Unknown filter: {}", name))
+    }
+
+    /// Expected `done` once finished, when known up front
+    pub fn total(&self) -> Option<u64> {
+        match self {
+            Self::Delete(params) if !params.ids.is_empty() => Some(params.ids.len() as u64),
+            Self::Replay(params) => Some(params.ids.len() as u64),
+            _ => None,
+        }
+    }
+}
+
+/// Where the runner is between steps
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Cursor {
+    /// Next position in `ids`, or rows already read from storage
+    pub offset: u32,
+    /// Captures left alone: under legal hold (delete) or not resendable (replay)
+    pub skipped: u64,
+    /// Export chunks written
+    pub chunks: u32,
+}
+
+/// A job and its progress
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Job {
+    pub id: String,
+    pub kind: Kind,
+    /// Internal webhook ID; None for migrations
+    #[serde(skip)]
+    pub webhook_id: Option<String>,
+    pub created_by: String,
+    pub status: Status,
+    pub params: Params,
+    #[serde(skip)]
+    pub cursor: Cursor,
+    /// Captures, rows or migrations processed so far
+    pub done: u64,
+    pub total: Option<u64>,
+    pub result: Option<Value>,
+    pub error: Option<String>,
+    pub created_at_ms: i64,
+    pub updated_at_ms: i64,
+    pub finished_at_ms: Option<i64>,
+}
+
+impl Job {
+    /// A queued job
+    pub fn new(webhook_id: Option<String>, created_by: &str, params: Params, now_ms: i64) -> Self {
+        Self {
+            id: crate::ids::ulid(now_ms),
+            kind: params.kind(),
+            webhook_id,
+            created_by: created_by.to_string(),
+            status: Status::Queued,
+            total: params.total(),
+            params,
+            cursor: Cursor::default(),
+            done: 0,
+            result: None,
+            error: None,
+            created_at_ms: now_ms,
+            updated_at_ms: now_ms,
+            finished_at_ms: None,
+        }
+    }
+
+    pub fn finish(&mut self, status: Status, now_ms: i64) {
+        self.status = status;
+        self.finished_at_ms = Some(now_ms);
+    }
+}
+
+/// What a step produced
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Step {
+    /// Export output to append to the job's file
+    pub chunk: Option<String>,
+    /// Wait before the next step; None once the job is done
+    pub next_in_ms: Option<u32>,
+}
+
+impl Step {
+    fn more(next_in_ms: u32) -> Self {
+        Self {
+            chunk: None,
+            next_in_ms: Some(next_in_ms),
+        }
+    }
+}
+
+fn filters(named: &BTreeMap<String, String>) -> Vec<(&'static str, String)> {
+    COLUMN_FILTERS
+        .iter()
+        .filter_map(|(param, column)| named.get(*param).map(|value| (*column, value.clone())))
+        .collect()
+}
+
+/// Delete the next batch, keeping held captures
+pub async fn delete_step(storage: &dyn Storage, job: &mut Job, params: &DeleteParams, held: &Held) -> Result<Step> {
+    if held.webhook {
+        return Err(Error::RustError("The webhook is under legal hold".to_string()));
+    }
+    let webhook_id = job.webhook_id.clone().unwrap_or_default();
+    let ids: Vec<String> = if params.ids.is_empty() {
+        // Held captures stay at the front of the listing; everything before them is gone
+        let query = RequestQuery {
+            webhook_id: webhook_id.clone(),
+            limit: DELETE_BATCH,
+            offset: job.cursor.skipped as u32,
+            since: None,
+            until: params.until,
+            sort: SortColumn::ReceivedAt,
+            ascending: true,
+            filters: filters(&params.filters),
+        };
+        storage.list_requests(&query).await?.into_iter().map(|request| request.id).collect()
+    } else {
+        let batch: Vec<String> = params
+            .ids
+            .iter()
+            .skip(job.cursor.offset as usize)
+            .take(DELETE_BATCH as usize)
+            .cloned()
+            .collect();
+        job.cursor.offset += batch.len() as u32;
+        batch
+    };
+    if ids.is_empty() {
+        job.result = Some(delete_result(job));
+        return Ok(Step::default());
+    }
+
+    let (kept, erasable): (Vec<String>, Vec<String>) = ids.into_iter().partition(|id| held.captures.contains(id));
+    if !erasable.is_empty() {
+        storage.delete_captures(&webhook_id, &erasable).await?;
+    }
+    job.cursor.skipped += kept.len() as u64;
+    job.done += (kept.len() + erasable.len()) as u64;
+    job.result = Some(delete_result(job));
+    Ok(Step::more(0))
+}
+
+fn delete_result(job: &Job) -> Value {
+    serde_json::json!({ "deleted": job.done - job.cursor.skipped, "held": job.cursor.skipped })
+}
+
+/// Render the next page of the export; the first one carries the header row
+pub async fn export_step(
+    storage: &dyn Storage,
+    job: &mut Job,
+    params: &ExportParams,
+    provider: Option<&str>,
+) -> Result<Step> {
+    let columns = params.columns.as_deref().unwrap_or(export::DEFAULT_COLUMNS);
+    let columns: Vec<Column> = export::parse_columns(columns)
+        .map_err(|column| Error::RustError(format!("Unknown export column: {}", column)))?;
+    let max_rows = params.limit.unwrap_or(MAX_EXPORT_ROWS).clamp(1, MAX_EXPORT_ROWS);
+    let limit = EXPORT_PAGE.min(max_rows.saturating_sub(job.cursor.offset));
+    let rows = if limit == 0 {
+        Vec::new()
+    } else {
+        let query = RequestQuery {
+            webhook_id: job.webhook_id.clone().unwrap_or_default(),
+            limit,
+            offset: job.cursor.offset,
+            since: params.since,
+            until: params.until,
+            sort: SortColumn::ReceivedAt,
+            ascending: true,
+            filters: filters(&params.filters),
+        };
+        storage.list_requests(&query).await?
+    };
+
+    let mut chunk = String::new();
+    if job.cursor.chunks == 0 {
+        // Excel only detects UTF-8 with a byte order mark
+        if params.bom {
+            chunk.push('\u{feff}');
+        }
+        chunk.push_str(&export::header_row(&columns));
+    }
+    chunk.extend(rows.iter().map(|request| export::row(&columns, request, provider)));
+    job.cursor.offset += rows.len() as u32;
+    job.done = job.cursor.offset as u64;
+    let finished = (rows.len() as u32) < limit || limit == 0;
+    let chunk = (!chunk.is_empty()).then(|| {
+        job.cursor.chunks += 1;
+        chunk
+    });
+    job.result = Some(serde_json::json!({ "rows": job.done, "chunks": job.cursor.chunks }));
+    Ok(Step {
+        chunk,
+        next_in_ms: (!finished).then_some(0),
+    })
+}
+
+/// Resend the next batch (one capture with pacing); missing or unusable captures are skipped
+async fn replay_step(env: &Env, job: &mut Job, params: &ReplayParams, storage: &dyn Storage) -> Result<Step> {
+    let db = env.d1("DB")?;
+    let webhook_id = job.webhook_id.clone().unwrap_or_default();
+    let settings = config::load_from_d1(&db, &webhook_id).await?;
+    let (overrides, sign, pacing_ms) = match &params.recipe {
+        Some(name) => match settings.config.replay_recipes.get(name) {
+            Some(recipe) => (recipe.overrides.clone(), recipe.sign, recipe.pacing_ms),
+            None => return Err(Error::RustError(format!("Replay recipe {:?} not found", name))),
+        },
+        None => (params.overrides.clone().unwrap_or_default(), true, 0),
+    };
+    let capture_url = params.capture_url.as_deref().unwrap_or_default();
+    let capture_url = Url::parse(capture_url).map_err(|e| Error::RustError(format!("Invalid capture URL: {}", e)))?;
+    let resend = replay::Resend {
+        env,
+        db: &db,
+        storage,
+        webhook_id: &webhook_id,
+        capture_url,
+        signing: settings.config.forward_signing.as_ref().filter(|_| sign),
+    };
+
+    let batch = if pacing_ms > 0 { 1 } else { REPLAY_BATCH };
+    let ids: Vec<String> = params.ids.iter().skip(job.cursor.offset as usize).take(batch).cloned().collect();
+    for id in &ids {
+        let original = find_request(storage, &webhook_id, id.clone()).await?;
+        let variant = original.and_then(|original| {
+            let variant = replay::apply(&original, &overrides).ok()?;
+            Some((original, variant))
+        });
+        match variant {
+            Some((original, variant)) => {
+                resend.send(&original, &variant).await?;
+            }
+            None => job.cursor.skipped += 1,
+        }
+    }
+    job.cursor.offset += ids.len() as u32;
+    job.done = job.cursor.offset as u64;
+    job.result = Some(serde_json::json!({ "replayed": job.done - job.cursor.skipped, "skipped": job.cursor.skipped }));
+    let finished = job.cursor.offset as usize >= params.ids.len();
+    Ok(Step {
+        chunk: None,
+        next_in_ms: (!finished).then_some(pacing_ms),
+    })
+}
+
+/// Apply the next pending migration
+async fn migrate_step(env: &Env, job: &mut Job) -> Result<Step> {
+    let db = env.d1("DB")?;
+    if job.total.is_none() {
+        job.total = Some(migrations::status(&db).await?.pending.len() as u64);
+    }
+    let mut applied: Vec<Value> = job
+        .result
+        .as_ref()
+        .and_then(|result| result["applied"].as_array().cloned())
+        .unwrap_or_default();
+    let Some(name) = migrations::apply_next(&db).await? else {
+        job.result = Some(serde_json::json!({ "applied": applied }));
+        return Ok(Step::default());
+    };
+    applied.push(Value::String(name));
+    job.done += 1;
+    job.result = Some(serde_json::json!({ "applied": applied }));
+    Ok(Step::more(0))
+}
+
+/// Run one step of a job
+pub async fn step(env: &Env, job: &mut Job) -> Result<Step> {
+    let webhook_id = job.webhook_id.clone().unwrap_or_default();
+    match job.params.clone() {
+        Params::Delete(params) => {
+            let db = env.d1("DB")?;
+            let held = crate::legal_hold::active(&db, &webhook_id).await?;
+            let storage = crate::storage::for_webhook(env, &webhook_id, crate::storage::Consistency::Primary).await?;
+            delete_step(storage.as_ref(), job, &params, &held).await
+        }
+        Params::Replay(params) => {
+            let storage = crate::storage::for_webhook(env, &webhook_id, crate::storage::Consistency::Primary).await?;
+            replay_step(env, job, &params, storage.as_ref()).await
+        }
+        Params::Export(params) => {
+            let db = env.d1("DB")?;
+            let settings = config::load_from_d1(&db, &webhook_id).await?;
+            let provider = settings
+                .config
+                .signature
+                .and_then(|signature| serde_json::to_value(signature.provider).ok())
+                .and_then(|provider| provider.as_str().map(str::to_string));
+            let consistency = crate::storage::Consistency::Replica { bookmark: None };
+            let storage = crate::storage::for_webhook(env, &webhook_id, consistency).await?;
+            export_step(storage.as_ref(), job, &params, provider.as_deref()).await
+        }
+        Params::Migrate => migrate_step(env, job).await,
+    }
+}
+
+#[derive(Deserialize)]
+struct JobRow {
+    id: String,
+    webhook_id: Option<String>,
+    created_by: String,
+    status: String,
+    params: String,
+    state: Option<String>,
+    done: f64,
+    total: Option<f64>,
+    result: Option<String>,
+    error: Option<String>,
+    created_at_ms: f64,
+    updated_at_ms: f64,
+    finished_at_ms: Option<f64>,
+}
+
+impl TryFrom<JobRow> for Job {
+    type Error = Error;
+
+    fn try_from(row: JobRow) -> Result<Self> {
+        let params: Params = serde_json::from_str(&row.params)?;
+        Ok(Self {
+            id: row.id,
+            kind: params.kind(),
+            webhook_id: row.webhook_id,
+            created_by: row.created_by,
+            status: serde_json::from_value(Value::String(row.status))?,
+            params,
+            cursor: row.state.and_then(|state| serde_json::from_str(&state).ok()).unwrap_or_default(),
+            done: row.done as u64,
+            total: row.total.map(|total| total as u64),
+            result: row.result.and_then(|result| serde_json::from_str(&result).ok()),
+            error: row.error,
+            created_at_ms: row.created_at_ms as i64,
+            updated_at_ms: row.updated_at_ms as i64,
+            finished_at_ms: row.finished_at_ms.map(|ms| ms as i64),
+        })
+    }
+}
+
+const JOB_COLUMNS: &str = "id, webhook_id, created_by, status, params, state, done, total, result, error, \
+                           created_at_ms, updated_at_ms, finished_at_ms";
+
+fn text(value: &impl Serialize) -> JsValue {
+    JsValue::from_str(&serde_json::to_string(value).unwrap_or_default())
+}
+
+fn status_text(status: Status) -> JsValue {
+    serde_json::to_value(status)
+        .ok()
+        .and_then(|status| status.as_str().map(JsValue::from_str))
+        .unwrap_or(JsValue::NULL)
+}
+
+fn optional_f64(value: Option<f64>) -> JsValue {
+    value.map_or(JsValue::NULL, JsValue::from_f64)
+}
+
+/// Store a new job
+pub async fn create(db: &D1Database, job: &Job) -> Result<()> {
+    let kind = serde_json::to_value(job.kind)?;
+    db.prepare(format!(
+        "INSERT INTO jobs (kind, {}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        JOB_COLUMNS
+    ))
+    .bind(&[
+        JsValue::from_str(kind.as_str().unwrap_or_default()),
+        JsValue::from_str(&job.id),
+        job.webhook_id.as_deref().map_or(JsValue::NULL, JsValue::from_str),
+        JsValue::from_str(&job.created_by),
+        status_text(job.status),
+        text(&job.params),
+        text(&job.cursor),
+        JsValue::from_f64(job.done as f64),
+        optional_f64(job.total.map(|total| total as f64)),
+        job.result.as_ref().map_or(JsValue::NULL, text),
+        job.error.as_deref().map_or(JsValue::NULL, JsValue::from_str),
+        JsValue::from_f64(job.created_at_ms as f64),
+        JsValue::from_f64(job.updated_at_ms as f64),
+        optional_f64(job.finished_at_ms.map(|ms| ms as f64)),
+    ])?
+    .run()
+    .await?;
+    Ok(())
+}
+
+/// Record a step's progress; false if the job was cancelled (or finished) meanwhile
+pub async fn save(db: &D1Database, job: &Job) -> Result<bool> {
+    let saved = db
+        .prepare(
+            "UPDATE jobs SET status = ?2, state = ?3, done = ?4, total = ?5, result = ?6, error = ?7, \
+             updated_at_ms = ?8, finished_at_ms = ?9 WHERE id = ?1 AND status IN ('queued', 'running')",
+        )
+        .bind(&[
+            JsValue::from_str(&job.id),
+            status_text(job.status),
+            text(&job.cursor),
+            JsValue::from_f64(job.done as f64),
+            optional_f64(job.total.map(|total| total as f64)),
+            job.result.as_ref().map_or(JsValue::NULL, text),
+            job.error.as_deref().map_or(JsValue::NULL, JsValue::from_str),
+            JsValue::from_f64(job.updated_at_ms as f64),
+            optional_f64(job.finished_at_ms.map(|ms| ms as f64)),
+        ])?
+        .run()
+        .await?;
+    Ok(saved.meta()?.and_then(|meta| meta.changes).unwrap_or(0) > 0)
+}
+
+/// One job
+pub async fn get(db: &D1Database, id: &str) -> Result<Option<Job>> {
+    db.prepare(format!("SELECT {} FROM jobs WHERE id = ?1", JOB_COLUMNS))
+        .bind(&[JsValue::from_str(id)])?
+        .first::<JobRow>(None)
+        .await?
+        .map(Job::try_from)
+        .transpose()
+}
+
+/// A webhook's most recent jobs, newest first
+pub async fn list(db: &D1Database, webhook_id: &str) -> Result<Vec<Job>> {
+    db.prepare(format!(
+        "SELECT {} FROM jobs WHERE webhook_id = ?1 ORDER BY created_at_ms DESC LIMIT {}",
+        JOB_COLUMNS, MAX_LISTED
+    ))
+    .bind(&[JsValue::from_str(webhook_id)])?
+    .all()
+    .await?
+    .results::<JobRow>()?
+    .into_iter()
+    .map(Job::try_from)
+    .collect()
+}
+
+/// Cancel a job that has not finished; false if it already had
+pub async fn cancel(db: &D1Database, id: &str, now_ms: i64) -> Result<bool> {
+    let cancelled = db
+        .prepare(
+            "UPDATE jobs SET status = 'cancelled', updated_at_ms = ?2, finished_at_ms = ?2 \
+             WHERE id = ?1 AND status IN ('queued', 'running')",
+        )
+        .bind(&[JsValue::from_str(id), JsValue::from_f64(now_ms as f64)])?
+        .run()
+        .await?;
+    Ok(cancelled.meta()?.and_then(|meta| meta.changes).unwrap_or(0) > 0)
+}
+
+/// Forget jobs finished before `before_ms`
+pub async fn prune(db: &D1Database, before_ms: i64) -> Result<u64> {
+    let pruned = db
+        .prepare("DELETE FROM jobs WHERE finished_at_ms < ?1")
+        .bind(&[JsValue::from_f64(before_ms as f64)])?
+        .run()
+        .await?;
+    Ok(pruned.meta()?.and_then(|meta| meta.changes).unwrap_or(0) as u64)
+}
@@ -0,0 +1,121 @@
+//! Key-value cache access
+//! The UUID and settings caches go through `KvBackend` so they run against
+//! Workers KV in production and an in-memory map under the `local` feature.
+//! The hot path wraps the store in `TolerantKv`: a failed KV read is logged,
+//! counted and treated as a miss, so lookups fall through to D1 instead of
+//! failing the delivery. The counts are per isolate and shown by `/health`.
+
+use serde::Serialize;
+use std::cell::Cell;
+use worker::*;
+
+thread_local! {
+    static READS: Cell<u64> = const { Cell::new(0) };
+    static READ_FAILURES: Cell<u64> = const { Cell::new(0) };
+    static WRITE_FAILURES: Cell<u64> = const { Cell::new(0) };
+}
+
+/// KV operations seen by this isolate through `TolerantKv`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct KvHealth {
+    pub reads: u64,
+    /// Reads that failed and were served from D1 instead
+    pub read_failures: u64,
+    pub write_failures: u64,
+}
+
+/// This isolate's KV health counters
+pub fn health() -> KvHealth {
+    KvHealth {
+        reads: READS.with(Cell::get),
+        read_failures: READ_FAILURES.with(Cell::get),
+        write_failures: WRITE_FAILURES.with(Cell::get),
+    }
+}
+
+fn bump(counter: &'static std::thread::LocalKey<Cell<u64>>) {
+    counter.with(|count| count.set(count.get() + 1));
+}
+
+#[async_trait::async_trait(?Send)]
+pub trait KvBackend {
+    async fn get_text(&self, key: &str) -> Result<Option<String>>;
+
+    /// Write a value, expiring after `ttl_seconds` when given
+    async fn put_text(&self, key: &str, value: &str, ttl_seconds: Option<u64>) -> Result<()>;
+
+    async fn delete(&self, key: &str) -> Result<()>;
+}
+
+#[async_trait::async_trait(?Send)]
+impl KvBackend for KvStore {
+    async fn get_text(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.get(key).text().await?)
+    }
+
+    async fn put_text(&self, key: &str, value: &str, ttl_seconds: Option<u64>) -> Result<()> {
+        let mut put = self.put(key, value)?;
+        if let Some(ttl) = ttl_seconds {
+            put = put.expiration_ttl(ttl);
+        }
+        put.execute().await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        KvStore::delete(self, key).await?;
+        Ok(())
+    }
+}
+
+/// KV wrapper that turns read errors into misses, so callers fall back to D1
+pub struct TolerantKv<'a, K: KvBackend + ?Sized> {
+    inner: &'a K,
+    fell_back: Cell<bool>,
+}
+
+impl<'a, K: KvBackend + ?Sized> TolerantKv<'a, K> {
+    pub fn new(inner: &'a K) -> Self {
+        Self {
+            inner,
+            fell_back: Cell::new(false),
+        }
+    }
+
+    /// A read through this wrapper failed and was reported as a miss
+    pub fn fell_back(&self) -> bool {
+        self.fell_back.get()
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl<K: KvBackend + ?Sized> KvBackend for TolerantKv<'_, K> {
+    async fn get_text(&self, key: &str) -> Result<Option<String>> {
+        bump(&READS);
+        match self.inner.get_text(key).await {
+            Ok(value) => Ok(value),
+            Err(e) => {
+                log_error!("⚠️  KV read failed for {}, falling back to D1: {:?}", key, e);
+                bump(&READ_FAILURES);
+                self.fell_back.set(true);
+                Ok(None)
+            }
+        }
+    }
+
+    async fn put_text(&self, key: &str, value: &str, ttl_seconds: Option<u64>) -> Result<()> {
+        let result = self.inner.put_text(key, value, ttl_seconds).await;
+        if result.is_err() {
+            bump(&WRITE_FAILURES);
+        }
+        result
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let result = self.inner.delete(key).await;
+        if result.is_err() {
+            bump(&WRITE_FAILURES);
+        }
+        result
+    }
+}
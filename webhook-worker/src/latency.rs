@@ -0,0 +1,222 @@
+//! Forward target latency histograms
+//! Every forwarding attempt adds its duration to a per-webhook, per-target,
+//! per-day histogram in `forward_latency` (one row per bucket), with failed
+//! attempts (no response, timeout or a non-2xx status) counted alongside.
+//! `GET /api/webhooks/{uuid}/stats/forwarding` merges the days asked for and
+//! estimates p50/p95/p99 by interpolating inside the bucket a quantile falls in.
+//! The same buckets make a webhook's `latency_profile`: imported from these
+//! histograms (`POST /api/webhooks/{uuid}/latency-profile`) or given by hand,
+//! every answer then waits a duration drawn from it, so a mock endpoint jitters
+//! like the real service instead of answering instantly.
+
+use crate::config::{LatencyBucket, LatencyProfile};
+use crate::forward::ForwardOutcome;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Upper bucket bounds in milliseconds; a last, open bucket holds slower attempts
+pub const BUCKET_BOUNDS_MS: &[i64] = &[10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000];
+
+const DAY: i64 = 86_400;
+
+/// Longest simulated delay; an open bucket is drawn from its lower bound up to twice that, within this
+pub const MAX_SIMULATED_MS: i64 = 30_000;
+
+/// Bucket index for a duration
+pub fn bucket(duration_ms: i64) -> usize {
+    BUCKET_BOUNDS_MS
+        .iter()
+        .position(|bound| duration_ms <= *bound)
+        .unwrap_or(BUCKET_BOUNDS_MS.len())
+}
+
+/// Whether an attempt counts as failed: no response, or a non-2xx status
+pub fn failed(outcome: &ForwardOutcome) -> bool {
+    !outcome.status.is_some_and(|status| (200..300).contains(&status))
+}
+
+/// Attempts per bucket, with failures
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Histogram {
+    pub counts: Vec<u64>,
+    pub failures: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; BUCKET_BOUNDS_MS.len() + 1],
+            failures: 0,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn add(&mut self, bucket: usize, count: u64, failures: u64) {
+        if let Some(slot) = self.counts.get_mut(bucket) {
+            *slot += count;
+        }
+        self.failures += failures;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Add another histogram's attempts (all targets of a webhook as one)
+    pub fn merge(&mut self, other: &Histogram) {
+        for (bucket, count) in other.counts.iter().enumerate() {
+            self.add(bucket, *count, 0);
+        }
+        self.failures += other.failures;
+    }
+
+    /// The attempts as a latency profile; None when there were none
+    pub fn profile(&self, source: &str) -> Option<LatencyProfile> {
+        if self.total() == 0 {
+            return None;
+        }
+        let bounds = BUCKET_BOUNDS_MS.iter().map(|bound| Some(*bound)).chain(std::iter::once(None));
+        Some(LatencyProfile {
+            buckets: bounds
+                .zip(&self.counts)
+                .map(|(le_ms, count)| LatencyBucket { le_ms, count: *count })
+                .collect(),
+            source: Some(source.to_string()),
+        })
+    }
+
+    /// Estimated duration at quantile `q` (0..=1), interpolated within its bucket;
+    /// the open bucket reports its lower bound
+    pub fn percentile(&self, q: f64) -> Option<i64> {
+        let total = self.total();
+        if total == 0 {
+            return None;
+        }
+        let rank = (q.clamp(0.0, 1.0) * total as f64).max(1.0);
+        let mut seen = 0u64;
+        for (index, count) in self.counts.iter().enumerate() {
+            if *count == 0 {
+                continue;
+            }
+            if (seen + count) as f64 >= rank {
+                let lower = if index == 0 { 0 } else { BUCKET_BOUNDS_MS[index - 1] };
+                let Some(upper) = BUCKET_BOUNDS_MS.get(index) else {
+                    return Some(lower);
+                };
+                let within = (rank - seen as f64) / *count as f64;
+                return Some(lower + ((upper - lower) as f64 * within).round() as i64);
+            }
+            seen += count;
+        }
+        BUCKET_BOUNDS_MS.last().copied()
+    }
+
+    /// Stats API representation
+    pub fn summary(&self) -> serde_json::Value {
+        serde_json::json!({
+            "count": self.total(),
+            "failures": self.failures,
+            "p50_ms": self.percentile(0.50),
+            "p95_ms": self.percentile(0.95),
+            "p99_ms": self.percentile(0.99),
+            "buckets": BUCKET_BOUNDS_MS
+                .iter()
+                .map(|bound| Some(*bound))
+                .chain(std::iter::once(None))
+                .zip(&self.counts)
+                .map(|(bound, count)| serde_json::json!({ "le_ms": bound, "count": count }))
+                .collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Delay drawn from `profile`: `roll` (0..1) picks a bucket weighted by its count,
+/// `within` (0..1) the point between the bucket's bounds
+pub fn sample(profile: &LatencyProfile, roll: f64, within: f64) -> i64 {
+    let total: u64 = profile.buckets.iter().map(|bucket| bucket.count).sum();
+    let rank = roll.clamp(0.0, 1.0) * total as f64;
+    let mut seen = 0u64;
+    let mut lower = 0;
+    for bucket in &profile.buckets {
+        seen += bucket.count;
+        let upper = bucket.le_ms.unwrap_or(lower * 2).min(MAX_SIMULATED_MS);
+        if bucket.count > 0 && rank < seen as f64 {
+            return lower + ((upper - lower) as f64 * within.clamp(0.0, 1.0)).round() as i64;
+        }
+        lower = upper;
+    }
+    lower
+}
+
+/// A random delay from `profile`, for the answer about to be sent
+pub fn simulated_delay_ms(profile: &LatencyProfile) -> i64 {
+    // The first and last four bytes of a v4 UUID are random (version and variant bits sit between)
+    let random = uuid::Uuid::new_v4().as_u128();
+    let unit = |bits: u128| (bits as u32) as f64 / (u32::MAX as f64 + 1.0);
+    sample(profile, unit(random), unit(random >> 96))
+}
+
+/// Add one forwarding attempt to today's histogram; `now` is Unix seconds
+pub async fn record(db: &D1Database, webhook_id: &str, target: &str, outcome: &ForwardOutcome, now: i64) -> Result<()> {
+    db.prepare(
+        "INSERT INTO forward_latency (webhook_id, target, day, bucket, count, failures) VALUES (?1, ?2, ?3, ?4, 1, ?5) \
+         ON CONFLICT(webhook_id, target, day, bucket) DO UPDATE SET count = count + 1, failures = failures + ?5",
+    )
+    .bind(&[
+        JsValue::from_str(webhook_id),
+        JsValue::from_str(target),
+        JsValue::from_f64((now - now.rem_euclid(DAY)) as f64),
+        JsValue::from_f64(bucket(outcome.duration_ms) as f64),
+        JsValue::from_f64(if failed(outcome) { 1.0 } else { 0.0 }),
+    ])?
+    .run()
+    .await?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct BucketRow {
+    target: String,
+    bucket: f64,
+    count: f64,
+    failures: f64,
+}
+
+/// Histograms per target over the last `days` days (today included)
+pub async fn histograms(db: &D1Database, webhook_id: &str, days: u32, now: i64) -> Result<BTreeMap<String, Histogram>> {
+    let since = now - now.rem_euclid(DAY) - (days.saturating_sub(1) as i64) * DAY;
+    let rows = db
+        .prepare(
+            "SELECT target, bucket, SUM(count) AS count, SUM(failures) AS failures FROM forward_latency \
+             WHERE webhook_id = ?1 AND day >= ?2 GROUP BY target, bucket",
+        )
+        .bind(&[JsValue::from_str(webhook_id), JsValue::from_f64(since as f64)])?
+        .all()
+        .await?
+        .results::<BucketRow>()?;
+
+    let mut histograms: BTreeMap<String, Histogram> = BTreeMap::new();
+    for row in rows {
+        histograms
+            .entry(row.target)
+            .or_default()
+            .add(row.bucket as usize, row.count as u64, row.failures as u64);
+    }
+    Ok(histograms)
+}
+
+/// Days of histograms kept (and the longest range the stats API merges)
+pub const RETENTION_DAYS: u32 = 30;
+
+/// Drop histogram days older than `days`
+pub async fn prune(db: &D1Database, days: u32, now: i64) -> Result<()> {
+    let before = now - now.rem_euclid(DAY) - days as i64 * DAY;
+    db.prepare("DELETE FROM forward_latency WHERE day < ?1")
+        .bind(&[JsValue::from_f64(before as f64)])?
+        .run()
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,159 @@
+//! Legal holds
+//! A hold on a webhook, or on one of its captures, exempts the data from
+//! every cleanup path while it is active: per-webhook and per-route
+//! `retention_days`, tiered retention (no stripping either), and partition
+//! drops, which first copy held rows into the unpartitioned table. Anything
+//! added later that destroys capture data (erasure, key rotation) must check
+//! `active` first. Holds are never deleted: releasing one records who did it
+//! and when, next to who placed it and why, so the trail survives the hold.
+
+use crate::ids;
+use crate::storage::optional_str;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// SQL condition matching rows of `legal_holds` still in force
+const ACTIVE: &str = "released_at_ms IS NULL";
+
+/// Rows to keep when a partition is dropped: captures of held webhooks and held captures
+pub const HELD_ROWS_CLAUSE: &str = "webhook_id IN (SELECT webhook_id FROM legal_holds \
+     WHERE released_at_ms IS NULL AND capture_id IS NULL) \
+     OR id IN (SELECT capture_id FROM legal_holds WHERE released_at_ms IS NULL AND capture_id IS NOT NULL)";
+
+/// A placed hold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegalHold {
+    pub id: String,
+    /// None for a hold on the whole webhook
+    pub capture_id: Option<String>,
+    pub reason: String,
+    pub created_by: String,
+    pub created_at_ms: i64,
+    pub released_by: Option<String>,
+    pub released_at_ms: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct HoldRow {
+    id: String,
+    capture_id: Option<String>,
+    reason: String,
+    created_by: String,
+    created_at_ms: f64,
+    released_by: Option<String>,
+    released_at_ms: Option<f64>,
+}
+
+impl From<HoldRow> for LegalHold {
+    fn from(row: HoldRow) -> Self {
+        Self {
+            id: row.id,
+            capture_id: row.capture_id,
+            reason: row.reason,
+            created_by: row.created_by,
+            created_at_ms: row.created_at_ms as i64,
+            released_by: row.released_by,
+            released_at_ms: row.released_at_ms.map(|ms| ms as i64),
+        }
+    }
+}
+
+/// What a webhook's active holds protect
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Held {
+    /// The whole webhook is held: nothing may be cleaned up
+    pub webhook: bool,
+    /// Individually held captures
+    pub captures: Vec<String>,
+}
+
+impl Held {
+    pub fn from_holds(holds: &[LegalHold]) -> Self {
+        let active = holds.iter().filter(|hold| hold.released_at_ms.is_none());
+        let mut held = Self::default();
+        for hold in active {
+            match &hold.capture_id {
+                Some(capture_id) => held.captures.push(capture_id.clone()),
+                None => held.webhook = true,
+            }
+        }
+        held
+    }
+}
+
+/// Holds on a webhook, newest first; released ones only with `include_released`
+pub async fn list(db: &D1Database, webhook_id: &str, include_released: bool) -> Result<Vec<LegalHold>> {
+    let filter = if include_released { String::new() } else { format!(" AND {}", ACTIVE) };
+    Ok(db
+        .prepare(format!(
+            "SELECT id, capture_id, reason, created_by, created_at_ms, released_by, released_at_ms \
+             FROM legal_holds WHERE webhook_id = ?1{} ORDER BY created_at_ms DESC",
+            filter
+        ))
+        .bind(&[JsValue::from_str(webhook_id)])?
+        .all()
+        .await?
+        .results::<HoldRow>()?
+        .into_iter()
+        .map(LegalHold::from)
+        .collect())
+}
+
+/// What a webhook's active holds protect
+pub async fn active(db: &D1Database, webhook_id: &str) -> Result<Held> {
+    Ok(Held::from_holds(&list(db, webhook_id, false).await?))
+}
+
+/// Place a hold on a webhook, or on one capture
+pub async fn place(
+    db: &D1Database,
+    webhook_id: &str,
+    capture_id: Option<&str>,
+    reason: &str,
+    created_by: &str,
+    now_ms: i64,
+) -> Result<LegalHold> {
+    let hold = LegalHold {
+        id: ids::ulid(now_ms),
+        capture_id: capture_id.map(str::to_string),
+        reason: reason.to_string(),
+        created_by: created_by.to_string(),
+        created_at_ms: now_ms,
+        released_by: None,
+        released_at_ms: None,
+    };
+    db.prepare(
+        "INSERT INTO legal_holds (id, webhook_id, capture_id, reason, created_by, created_at_ms) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )
+    .bind(&[
+        JsValue::from_str(&hold.id),
+        JsValue::from_str(webhook_id),
+        optional_str(&hold.capture_id),
+        JsValue::from_str(&hold.reason),
+        JsValue::from_str(&hold.created_by),
+        JsValue::from_f64(now_ms as f64),
+    ])?
+    .run()
+    .await?;
+    Ok(hold)
+}
+
+/// Release an active hold; false when there is none with that id
+pub async fn release(db: &D1Database, webhook_id: &str, id: &str, released_by: &str, now_ms: i64) -> Result<bool> {
+    let result = db
+        .prepare(format!(
+            "UPDATE legal_holds SET released_by = ?3, released_at_ms = ?4 WHERE webhook_id = ?1 AND id = ?2 AND {}",
+            ACTIVE
+        ))
+        .bind(&[
+            JsValue::from_str(webhook_id),
+            JsValue::from_str(id),
+            JsValue::from_str(released_by),
+            JsValue::from_f64(now_ms as f64),
+        ])?
+        .run()
+        .await?;
+    Ok(result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) > 0)
+}
@@ -1,142 +1,497 @@
-/**
- * Webhook Ingestion Worker
- * High-performance Rust worker for receiving webhooks
- */
-
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use wasm_bindgen::JsValue;
-use worker::*;
+//! Webhook Ingestion Worker
+//! High-performance Rust worker for receiving webhooks
+//! The fetch, scheduled and email handlers and the Durable Object classes are
+//! only exported with the `entrypoints` feature (default); crates that just
+//! reuse the ingestion logic turn it off.
+
+// Without the entrypoints most of the worker is unreachable
+#![cfg_attr(not(feature = "entrypoints"), allow(dead_code, unused_imports))]
+
+/// Write a line if the calling module's `LOG_LEVEL` and `LOG_SAMPLE` let it
+/// through (see `logging.rs`). Code shared with the `local` fakes runs off wasm
+/// (native `cargo test`) too, where there is no JS console, so it prints instead.
+macro_rules! log_at {
+    ($level:ident, $console:ident, $print:ident, $($t:tt)*) => {{
+        if crate::logging::enabled(module_path!(), crate::logging::Level::$level) {
+            #[cfg(target_arch = "wasm32")]
+            worker::$console!($($t)*);
+            #[cfg(not(target_arch = "wasm32"))]
+            $print!($($t)*);
+        }
+    }};
+}
 
-#[derive(Deserialize, Serialize)]
-struct WebhookRow {
-    id: String,
+/// `console_debug!` through `log_at!`
+macro_rules! log_debug {
+    ($($t:tt)*) => { log_at!(Debug, console_debug, println, $($t)*) };
 }
 
-#[event(fetch)]
-async fn main(mut req: Request, env: Env, _ctx: Context) -> Result<Response> {
+/// `console_log!` through `log_at!`
+macro_rules! log_info {
+    ($($t:tt)*) => { log_at!(Info, console_log, println, $($t)*) };
+}
+
+/// `console_warn!` through `log_at!`
+macro_rules! log_warn {
+    ($($t:tt)*) => { log_at!(Warn, console_warn, eprintln, $($t)*) };
+}
+
+/// `console_error!` through `log_at!`
+macro_rules! log_error {
+    ($($t:tt)*) => { log_at!(Error, console_error, eprintln, $($t)*) };
+}
+
+mod abuse;
+pub mod annotations;
+pub mod anomaly;
+mod api;
+mod audit;
+mod auth;
+pub mod backchannel_logout;
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod bodies;
+mod cache;
+pub mod canonical;
+mod capture_log;
+pub mod chain;
+pub mod charset;
+pub mod compression;
+mod config;
+mod config_document;
+pub mod config_history;
+pub mod console;
+pub mod counters;
+pub mod dashboard;
+mod db;
+pub mod docs;
+pub mod dedup;
+mod directory;
+mod durable;
+#[cfg(feature = "email")]
+mod email;
+pub mod encryption;
+mod environments;
+pub mod erasure;
+pub mod error_budget;
+mod event_time;
+pub mod export;
+pub mod fixtures;
+mod forward;
+pub mod github;
+mod headers;
+pub mod heartbeat;
+mod ids;
+mod ingest;
+pub mod jobs;
+mod kv;
+pub mod latency;
+pub mod legal_hold;
+#[cfg(feature = "local")]
+pub mod local;
+pub mod logging;
+mod migrations;
+#[cfg(feature = "email")]
+pub mod mime;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod oauth;
+mod oidc;
+pub mod overview;
+mod partition;
+pub mod pipeline;
+mod placeholders;
+pub mod preview;
+pub mod processing;
+pub mod replay;
+pub mod residency;
+mod responses;
+pub mod retention;
+pub mod scim;
+pub mod script;
+pub mod security_events;
+pub mod sessions;
+pub mod shapes;
+mod signature;
+mod signed_url;
+pub mod simulate;
+pub mod sla;
+pub mod slack;
+pub mod split;
+pub mod snapshot;
+pub mod status_page;
+mod storage;
+pub mod stripe;
+mod templates;
+pub mod timestamps;
+mod tokens;
+mod trailers;
+pub mod transfer;
+pub mod twiml;
+pub mod usage;
+pub mod versioning;
+mod webcrypto;
+mod webhooks;
+
+use worker::*;
+
+#[cfg_attr(feature = "entrypoints", event(fetch))]
+async fn main(req: Request, env: Env, ctx: Context) -> Result<Response> {
+    logging::init(&env);
     // Handle OPTIONS preflight requests
     if req.method() == Method::Options {
         let mut response = Response::empty()?;
-        let headers = response.headers_mut();
-        headers.set("Access-Control-Allow-Origin", "*")?;
-        headers.set("Access-Control-Allow-Methods", "GET, POST, OPTIONS")?;
-        headers.set("Access-Control-Allow-Headers", "*")?;
+        set_cors_headers(response.headers_mut())?;
         return Ok(response);
     }
 
-    // Route: /w/{uuid}
-    let url = req.url()?;
-    let path = url.path();
+    // API versioning: `/api/v1/...` is routed on its unversioned path, before the guard reads it
+    let (req, version) = if req.path().starts_with("/api/") {
+        let header = req.headers().get(versioning::HEADER)?;
+        match versioning::negotiate(&req.path(), header.as_deref()) {
+            Ok(negotiated) => (api::with_path(req, &negotiated.path)?, Some(negotiated)),
+            Err(message) => return Response::error(message, 400),
+        }
+    } else {
+        (req, None)
+    };
 
-    if !path.starts_with("/w/") {
-        return Response::error("Not Found", 404);
-    }
+    // API middleware: authenticate and enforce the route's role requirement
+    let principal = if req.path().starts_with("/api/") {
+        match auth::guard(&req, &env).await? {
+            Ok(principal) => Some(principal),
+            Err(response) => return Ok(response),
+        }
+    } else {
+        None
+    };
+    // API JSON responses get RFC 3339 twins of their timestamps (`?tz=`, `?ages=true`)
+    let time_options = if principal.is_some() {
+        let url = req.url()?;
+        let tz = api::query_param(&url, "tz");
+        match timestamps::TimeOptions::parse(tz.as_deref(), api::query_param(&url, "ages").as_deref()) {
+            Ok(options) => Some(options),
+            Err(message) => return Response::error(message, 400),
+        }
+    } else {
+        None
+    };
 
-    let uuid = path.strip_prefix("/w/").unwrap_or("");
+    // Ingestion: /w/{uuid}. Deliveries get a router of their own, so the hot path
+    // neither registers nor matches the management routes below
+    if req.path().starts_with("/w/") {
+        let router = Router::with_data(auth::RouteData { principal, context: ctx });
+        #[cfg(feature = "mqtt")]
+        let router = router.get_async("/w/:uuid/mqtt", ingest::mqtt);
+        return router
+            .on_async("/w/", ingest::capture)
+            .on_async("/w/:uuid", ingest::capture)
+            .get_async("/w/:uuid/ws", ingest::socket)
+            .get_async("/w/:uuid/oauth/callback", ingest::capture)
+            .post_async("/w/:uuid/oidc/backchannel-logout", ingest::capture)
+            .on_async("/w/:uuid/scim/v2/*path", ingest::capture)
+            .put_async("/w/:uuid/upload/:filename", ingest::upload)
+            .run(req, env)
+            .await;
+    }
 
-    if uuid.is_empty() {
-        return Response::error("Invalid webhook URL", 400);
+    let response = Router::with_data(auth::RouteData { principal, context: ctx })
+        // Health check (public)
+        .get_async("/health", api::health::check)
+        // Standard Webhooks capability document (public)
+        .get_async("/.well-known/standard-webhooks", api::capabilities::standard_webhooks)
+        // Local dev relay agents (relay token auth)
+        .get_async("/relay/:uuid", api::relay::connect)
+        // Shareable status summaries (signed link auth)
+        .get_async("/status/:uuid", api::status::show)
+        // Management API
+        .post_async("/api/webhooks", api::webhooks::create)
+        .get_async("/api/templates", api::webhooks::templates)
+        .get_async("/api/webhooks/:uuid", api::webhooks::show)
+        .put_async("/api/webhooks/:uuid", api::webhooks::upsert)
+        .get_async("/api/webhooks/:uuid/requests", api::requests::list)
+        .get_async("/api/webhooks/:uuid/requests/wait", api::requests::wait)
+        .get_async("/api/webhooks/:uuid/requests/:id", api::requests::show)
+        .get_async("/api/webhooks/:uuid/requests/:id/export", api::requests::export_one)
+        .post_async("/api/webhooks/:uuid/requests/:id/replay", api::requests::replay)
+        .get_async("/api/webhooks/:uuid/requests/:id/annotations", api::annotations::list)
+        .post_async("/api/webhooks/:uuid/requests/:id/annotations", api::annotations::create)
+        .post_async("/api/webhooks/:uuid/replay/:recipe", api::requests::replay_recipe)
+        .get_async("/api/webhooks/:uuid/jobs", api::jobs::list)
+        .post_async("/api/webhooks/:uuid/jobs", api::jobs::create)
+        .get_async("/api/webhooks/:uuid/jobs/:id", api::jobs::show)
+        .delete_async("/api/webhooks/:uuid/jobs/:id", api::jobs::cancel)
+        .get_async("/api/webhooks/:uuid/jobs/:id/download", api::jobs::download)
+        .post_async("/api/webhooks/:uuid/requests/import", api::requests::import)
+        .get_async("/api/webhooks/:uuid/sessions", api::sessions::list)
+        .get_async("/api/webhooks/:uuid/export.csv", api::requests::export)
+        .get_async("/api/webhooks/:uuid/tail", api::tail::stream)
+        .get_async("/api/webhooks/:uuid/console", api::console::connect)
+        .get_async("/api/webhooks/:uuid/inbox", api::inbox::peek)
+        .post_async("/api/webhooks/:uuid/inbox", api::inbox::lease)
+        .post_async("/api/webhooks/:uuid/inbox/ack", api::inbox::ack)
+        .get_async("/api/webhooks/:uuid/config", api::webhooks::config_show)
+        .patch_async("/api/webhooks/:uuid/config", api::webhooks::config_update)
+        .get_async("/api/webhooks/:uuid/config/export", api::webhooks::config_export)
+        .post_async("/api/webhooks/:uuid/config/import", api::webhooks::config_import)
+        .get_async("/api/webhooks/:uuid/config/history", api::webhooks::config_history)
+        .get_async("/api/webhooks/:uuid/config/history/:version", api::webhooks::config_version)
+        .post_async("/api/webhooks/:uuid/config/rollback/:version", api::webhooks::config_rollback)
+        .post_async("/api/webhooks/:uuid/simulate", api::webhooks::simulate)
+        .get_async("/api/webhooks/:uuid/fixtures", api::fixtures::list)
+        .post_async("/api/webhooks/:uuid/fixtures", api::fixtures::save)
+        .post_async("/api/webhooks/:uuid/fixtures/run", api::fixtures::run_all)
+        .delete_async("/api/webhooks/:uuid/fixtures/:name", api::fixtures::delete)
+        .post_async("/api/webhooks/:uuid/fixtures/:name/run", api::fixtures::run)
+        .post_async("/api/webhooks/:uuid/signed-url", api::webhooks::signed_url)
+        .post_async("/api/webhooks/:uuid/upload-url", api::webhooks::upload_url)
+        .post_async("/api/webhooks/:uuid/signature/rotate", api::webhooks::rotate_secret)
+        .post_async("/api/webhooks/:uuid/latency-profile", api::webhooks::latency_profile)
+        .get_async("/api/webhooks/:uuid/volume", api::webhooks::volume)
+        .get_async("/api/webhooks/:uuid/docs", api::docs::show)
+        .get_async("/api/webhooks/:uuid/shapes", api::docs::shapes)
+        .get_async("/api/webhooks/:uuid/stats/forwarding", api::stats::forwarding)
+        .get_async("/api/webhooks/:uuid/stats/split", api::stats::split)
+        .get_async("/api/webhooks/:uuid/stats/shadow", api::stats::shadow)
+        .get_async("/api/webhooks/:uuid/stats/daily", api::stats::daily)
+        .get_async("/api/webhooks/:uuid/stats/shopify", api::stats::shopify)
+        .get_async("/api/webhooks/:uuid/stats/storage", api::stats::storage)
+        .get_async("/api/webhooks/:uuid/legal-holds", api::legal_holds::list)
+        .post_async("/api/webhooks/:uuid/legal-holds", api::legal_holds::place)
+        .delete_async("/api/webhooks/:uuid/legal-holds/:id", api::legal_holds::release)
+        .post_async("/api/webhooks/:uuid/status-url", api::status::create_url)
+        .get_async("/api/webhooks/:uuid/environments", api::environments::list)
+        .post_async("/api/webhooks/:uuid/environments", api::environments::create)
+        .patch_async("/api/webhooks/:uuid/environments/:name", api::environments::update)
+        .delete_async("/api/webhooks/:uuid/environments/:name", api::environments::delete)
+        .get_async("/api/webhooks/:uuid/relay", api::relay::status)
+        .post_async("/api/webhooks/:uuid/relay/tokens", api::relay::create_token)
+        .delete_async("/api/webhooks/:uuid/relay/tokens/:id", api::relay::revoke_token)
+        .post_async("/api/erasure", api::erasure::erase)
+        .get_async("/api/project", api::projects::show)
+        .patch_async("/api/project", api::projects::update)
+        .get_async("/api/project/storage", api::projects::storage)
+        .get_async("/api/trace/:correlation_id", api::trace::show)
+        // Operator API
+        .get_async("/api/admin/overview", api::overview::show)
+        .get_async("/api/admin/cache", api::cache::list)
+        .delete_async("/api/admin/cache", api::cache::flush_all)
+        .post_async("/api/admin/cache/warm", api::cache::warm)
+        .get_async("/api/admin/cache/:uuid", api::cache::show)
+        .delete_async("/api/admin/cache/:uuid", api::cache::flush_one)
+        .get_async("/api/audit", api::audit::list)
+        .get_async("/api/tokens", api::tokens::list)
+        .post_async("/api/tokens", api::tokens::create)
+        .delete_async("/api/tokens/:id", api::tokens::revoke)
+        .post_async("/api/tokens/:id/rotate", api::tokens::rotate)
+        .get_async("/api/admin/abuse", api::abuse::list)
+        .delete_async("/api/admin/abuse/:ip", api::abuse::clear)
+        .get_async("/api/admin/migrations", api::migrations::status)
+        .post_async("/api/admin/migrations/apply", api::migrations::apply)
+        .get_async("/api/admin/jobs/:id", api::jobs::admin_show)
+        .delete_async("/api/admin/jobs/:id", api::jobs::admin_cancel)
+        .get_async("/api/admin/counters/:scope", api::counters::show)
+        .post_async("/api/admin/counters/:scope/:key", api::counters::adjust)
+        .delete_async("/api/admin/counters/:scope/:key", api::counters::reset)
+        .get_async("/api/admin/error-budget", api::error_budget::show)
+        .get_async("/api/admin/security-events", api::security_events::list)
+        .get_async("/api/admin/encryption", api::encryption::status)
+        .post_async("/api/admin/encryption/rewrap", api::encryption::rewrap)
+        .get_async("/api/admin/webhooks/:uuid/load", api::load::status)
+        .post_async("/api/admin/webhooks/:uuid/load", api::load::start)
+        .delete_async("/api/admin/webhooks/:uuid/load", api::load::stop)
+        .get_async("/api/admin/webhooks/:uuid/transfer", api::transfer::export)
+        .post_async("/api/admin/webhooks/:uuid/transfer", api::transfer::import)
+        .run(req, env)
+        .await?;
+    let mut response = match time_options {
+        Some(options) => api::with_timestamps(response, &options).await?,
+        None => response,
+    };
+    if let Some(negotiated) = &version {
+        let headers = response.headers_mut();
+        for (name, value) in versioning::response_headers(negotiated) {
+            // Upgrades answered by a Durable Object come back with immutable headers
+            let _ = headers.set(name, &value);
+        }
+        let _ = headers.set("Access-Control-Expose-Headers", versioning::EXPOSED_HEADERS);
     }
+    Ok(response)
+}
+
+/// Cron trigger that only runs the SLA and volume checks; every other trigger also runs maintenance
+const SLA_CRON: &str = "*/15 * * * *";
 
-    // Extract request data
-    let method = req.method().to_string();
+#[cfg_attr(feature = "entrypoints", event(scheduled))]
+async fn scheduled(event: ScheduledEvent, env: Env, _ctx: ScheduleContext) {
+    logging::init(&env);
+    let now = (Date::now().as_millis() / 1000) as i64;
 
-    // Collect headers as JSON
-    let mut headers_map = HashMap::new();
-    for (name, value) in req.headers() {
-        headers_map.insert(name, value);
+    // Replication heartbeat read by /health
+    if let Err(e) = db::write_heartbeat(&env, Date::now().as_millis() as i64).await {
+        log_error!("❌ Replication heartbeat failed: {:?}", e);
+    }
+    // Delivery expectations (silent webhook outages)
+    if let Err(e) = sla::check(&env, now).await {
+        log_error!("❌ Expectation check failed: {:?}", e);
+    }
+    // Hourly volume baselines (spikes and droughts)
+    if let Err(e) = anomaly::check(&env, now).await {
+        log_error!("❌ Volume anomaly check failed: {:?}", e);
+    }
+    // Ingestion failure rate (D1 outages and the like)
+    if let Err(e) = error_budget::check(&env, now).await {
+        log_error!("❌ Error budget check failed: {:?}", e);
+    }
+    if event.cron() == SLA_CRON {
+        return;
     }
-    let _headers_json = serde_json::to_string(&headers_map)?;
 
-    // Extract body or query params
-    let data_json = if method == "POST" || method == "PUT" || method == "PATCH" {
-        match req.text().await {
-            Ok(body) => body,
-            Err(_) => "{}".to_string(),
+    // Apply pending schema migrations before anything touches the new schema
+    if env.var("AUTO_MIGRATE").map(|v| v.to_string() == "true").unwrap_or(false) {
+        match env.d1("DB") {
+            Ok(db) => match migrations::apply_pending(&db).await {
+                Ok(applied) if !applied.is_empty() => {
+                    log_info!("🧱 Applied migrations: {}", applied.join(", "));
+                    let entry = audit::AuditEntry::system("migrations.apply")
+                        .after(&serde_json::json!({ "applied": applied }));
+                    audit::record(&db, entry).await;
+                }
+                Ok(_) => {}
+                Err(e) => log_error!("❌ Migrations failed: {:?}", e),
+            },
+            Err(e) => log_error!("❌ Migrations failed: {:?}", e),
         }
-    } else {
-        // For GET requests, store query parameters
-        let query_params: HashMap<String, String> = url
-            .query_pairs()
-            .map(|(k, v)| (k.to_string(), v.to_string()))
-            .collect();
-        serde_json::to_string(&query_params)?
+    }
+
+    // Storage maintenance (D1: create upcoming partitions, drop expired ones)
+    let result = match storage::from_env(&env).await {
+        Ok(storage) => storage.maintain(now).await,
+        Err(e) => Err(e),
     };
+    if let Err(e) = result {
+        log_error!("❌ Storage maintenance failed: {:?}", e);
+    }
 
-    let size_bytes = data_json.len() as i32;
-    let received_at = (Date::now().as_millis() / 1000) as i64; // Convert to Unix seconds
-    let data_id = uuid::Uuid::new_v4().to_string();
+    // Per-webhook retention shorter than the global cleanup
+    if let Err(e) = enforce_retention(&env, now).await {
+        log_error!("❌ Webhook retention failed: {:?}", e);
+    }
+    // Tiered retention: strip old payloads, roll expired rows into daily aggregates
+    if let Err(e) = retention::enforce(&env, now).await {
+        log_error!("❌ Tiered retention failed: {:?}", e);
+    }
+    // Recount each webhook's stored bytes now that retention has deleted what it does
+    match usage::reconcile(&env).await {
+        Ok(webhooks) => log_info!("📦 Recounted storage of {} webhooks", webhooks),
+        Err(e) => log_error!("❌ Storage usage recount failed: {:?}", e),
+    }
 
-    // Get KV cache and D1 database
-    let kv = env.kv("WEBHOOK_CACHE")?;
-    let db = env.d1("DB")?;
+    // Forget old enumeration misses (flagged scanners are kept)
+    let result = match env.d1("DB") {
+        Ok(db) => abuse::prune(&db, now * 1000).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        log_error!("❌ Enumeration miss pruning failed: {:?}", e);
+    }
 
-    // Step 1: Lookup webhook ID (KV first, D1 fallback)
-    let cache_key = format!("webhook:uuid:{}", uuid);
-    let webhook_id: String;
+    // Security events past their retention
+    if let Err(e) = security_events::prune(&env, now * 1000).await {
+        log_error!("❌ Security event pruning failed: {:?}", e);
+    }
+
+    // Forward latency histogram days past what the stats API can ask for
+    let result = match env.d1("DB") {
+        Ok(db) => latency::prune(&db, latency::RETENTION_DAYS, now).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        log_error!("❌ Forward latency pruning failed: {:?}", e);
+    }
+
+    // Downstream responses to forwarded captures
+    let result = match env.d1("DB") {
+        Ok(db) => responses::prune(&db, now * 1000).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        log_error!("❌ Forward response pruning failed: {:?}", e);
+    }
+
+    // Annotations external systems attached to captures
+    let result = match env.d1("DB") {
+        Ok(db) => annotations::prune(&db, now * 1000).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        log_error!("❌ Annotation pruning failed: {:?}", e);
+    }
 
-    // Try KV cache first
-    match kv.get(&cache_key).text().await? {
-        Some(cached_id) => {
-            // Cache hit! Use cached webhook ID
-            webhook_id = cached_id;
-            console_log!("✅ KV cache hit for UUID: {}", uuid);
+    // A/B forwarding comparisons
+    let result = match env.d1("DB") {
+        Ok(db) => split::prune(&db, now * 1000).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        log_error!("❌ Split comparison pruning failed: {:?}", e);
+    }
+
+    // Jobs past their retention (their runners clear their own storage)
+    let result = match env.d1("DB") {
+        Ok(db) => jobs::prune(&db, (now - jobs::RETENTION_DAYS * 86_400) * 1000).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        log_error!("❌ Job pruning failed: {:?}", e);
+    }
+
+    // Flushed counters whose window has passed
+    let result = match env.d1("DB") {
+        Ok(db) => counters::prune(&db, now * 1000).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        log_error!("❌ Counter pruning failed: {:?}", e);
+    }
+}
+
+/// Delete captures of webhooks (or event types) whose config sets `retention_days`;
+/// legally held webhooks and captures are skipped
+async fn enforce_retention(env: &Env, now: i64) -> Result<()> {
+    let db = env.d1("DB")?;
+    let rules = config::retention_rules(&db).await?;
+    if rules.is_empty() {
+        return Ok(());
+    }
+
+    for rule in rules {
+        let held = legal_hold::active(&db, &rule.webhook_id).await?;
+        if held.webhook {
+            continue;
         }
-        None => {
-            // Cache miss - query D1
-            console_log!("❌ KV cache miss for UUID: {}, querying D1", uuid);
-
-            let webhook_statement = db.prepare("SELECT id FROM webhooks WHERE uuid = ?1");
-            let webhook_query = webhook_statement.bind(&[JsValue::from_str(uuid)])?;
-            let webhook_result = webhook_query.first::<WebhookRow>(None).await?;
-
-            match webhook_result {
-                Some(webhook_row) => {
-                    webhook_id = webhook_row.id.clone();
-
-                    // Cache the result for future requests (1 hour TTL)
-                    match kv.put(&cache_key, &webhook_id)?.expiration_ttl(3600).execute().await {
-                        Ok(_) => console_log!("📝 Cached webhook ID in KV: {}", webhook_id),
-                        Err(e) => console_error!("⚠️  Failed to cache webhook ID: {:?}", e),
-                    }
-                }
-                None => {
-                    // Webhook not found
-                    return Response::error("Webhook not found", 404);
-                }
-            }
+        let storage = storage::for_webhook(env, &rule.webhook_id, storage::Consistency::Primary).await?;
+        let before = now - rule.days as i64 * 86_400;
+        let deleted = storage
+            .purge(&rule.webhook_id, rule.event_type.as_deref(), before, &held.captures)
+            .await?;
+        if deleted > 0 {
+            log_info!(
+                "🧹 Deleted {} captures of webhook {} ({}) past {} days",
+                deleted,
+                rule.webhook_id,
+                rule.event_type.as_deref().unwrap_or("all events"),
+                rule.days
+            );
         }
     }
+    Ok(())
+}
 
-    // Step 2: Insert webhook data to D1
-    let insert_statement = db.prepare("INSERT INTO webhook_data (id, webhook_id, method, headers, data, size_bytes, received_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)");
-    let insert_query = insert_statement.bind(&[
-        JsValue::from_str(&data_id),
-        JsValue::from_str(&webhook_id),
-        JsValue::from_str(&method),
-        JsValue::from_str(&_headers_json),
-        JsValue::from_str(&data_json),
-        JsValue::from_f64(size_bytes as f64),
-        JsValue::from_f64(received_at as f64),
-    ])?;
-    insert_query.run().await?;
-
-    // Success response
-    let mut response = Response::from_json(&serde_json::json!({
-        "success": true,
-        "message": "Webhook received",
-        "webhook_id": uuid,
-        "data_id": data_id,
-        "method": method,
-        "received_at": received_at,
-        "size_bytes": size_bytes,
-    }))?;
-
-    let headers = response.headers_mut();
+/// Permissive CORS headers for browser-based senders
+pub(crate) fn set_cors_headers(headers: &mut Headers) -> Result<()> {
     headers.set("Access-Control-Allow-Origin", "*")?;
-    headers.set("Access-Control-Allow-Methods", "GET, POST, OPTIONS")?;
+    headers.set("Access-Control-Allow-Methods", "GET, POST, PUT, PATCH, DELETE, OPTIONS")?;
     headers.set("Access-Control-Allow-Headers", "*")?;
-
-    Ok(response)
+    Ok(())
 }
@@ -0,0 +1,467 @@
+//! In-memory bindings for local development and tests (`local` feature)
+//! `MemoryKv`, `MemoryDirectory` and `MemoryStorage` stand in for Workers KV,
+//! the D1 webhook tables and the capture backend, so UUID resolution, settings
+//! caching, routing and storage semantics can be exercised with native
+//! `cargo test`. The ingestion building blocks they plug into are re-exported.
+
+pub use crate::api::tail::BacklogDedup;
+pub use crate::audit::second_bounds as audit_second_bounds;
+pub use crate::auth::{requirement, webhook_access, Requirement, Role, Scope};
+pub use crate::cache::resolve_webhook_id;
+pub use crate::config::{
+    invalidate, load, Compression, CompressionAlgorithm, Condition, CustomResponse, EventRoute, Expectation,
+    FieldSource, ForwardSigning, Heartbeat, HmacAlgorithm, HmacScheme, LatencyBucket, LatencyProfile, OauthClient,
+    PaypalApp, ReplayRecipe, ResponseRule, ResponseRules, RetentionTiers, Sessions, ShadowForwarding, ShapeTracking,
+    SignatureConfig, SignatureEncoding, SignatureProvider, SlackConfig, StorageQuota, TrafficSplit, TwimlConfig,
+    WebhookConfig, WebhookSettings,
+};
+pub use crate::directory::Directory;
+pub use crate::environments::Environment;
+pub use crate::event_time::extract as extract_event_time;
+pub use crate::forward::{
+    is_retryable, signed_headers, ForwardOutcome, Signer, TargetResponse, MAX_RESPONSE_BODY_BYTES,
+};
+pub use crate::headers::{HeaderLimits, IndexedHeaders};
+pub use crate::ids::ulid;
+pub use crate::kv::{health as kv_health, KvBackend, KvHealth, TolerantKv};
+pub use crate::migrations::statements as migration_statements;
+pub use crate::oidc::select_key as oidc_select_key;
+pub use crate::partition::mirror_index as mirror_partition_index;
+pub use crate::signature::paypal::{
+    plausible as paypal_plausible, transmission_time as paypal_transmission_time,
+    verification_body as paypal_verification_body,
+};
+pub use crate::signature::standard::sign as sign_standard_webhook;
+pub use crate::signature::{providers as signature_providers, verify_with_secret, verify_with_secrets, Verification};
+pub use crate::signed_url::{sign, sign_upload};
+pub use crate::storage::{
+    CaptureRecord, DailyCount, InboxQuery, RequestQuery, ShopifyCount, SortColumn, Storage, StoredRequest,
+    WebhookVolume,
+};
+pub use crate::tokens::{expires_at_ms as token_expires_at_ms, MAX_EXPIRES_IN_SECONDS as MAX_TOKEN_EXPIRES_IN_SECONDS};
+pub use crate::webhooks::Webhook;
+use crate::storage::{merge_daily_counts, merge_shopify_counts, merge_webhook_volumes};
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use worker::*;
+
+/// Workers KV stand-in; TTLs are recorded but entries never expire
+#[derive(Default)]
+pub struct MemoryKv {
+    entries: RefCell<HashMap<String, (String, Option<u64>)>>,
+    failing: Cell<bool>,
+}
+
+impl MemoryKv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries.borrow().contains_key(key)
+    }
+
+    /// TTL an entry was written with
+    pub fn ttl(&self, key: &str) -> Option<u64> {
+        self.entries.borrow().get(key).and_then(|(_, ttl)| *ttl)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().is_empty()
+    }
+
+    /// Make every operation fail like a KV transport error until switched back
+    pub fn set_failing(&self, failing: bool) {
+        self.failing.set(failing);
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.failing.get() {
+            return Err(Error::RustError("KV unavailable".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl KvBackend for MemoryKv {
+    async fn get_text(&self, key: &str) -> Result<Option<String>> {
+        self.check()?;
+        Ok(self.entries.borrow().get(key).map(|(value, _)| value.clone()))
+    }
+
+    async fn put_text(&self, key: &str, value: &str, ttl_seconds: Option<u64>) -> Result<()> {
+        self.check()?;
+        self.entries
+            .borrow_mut()
+            .insert(key.to_string(), (value.to_string(), ttl_seconds));
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.check()?;
+        self.entries.borrow_mut().remove(key);
+        Ok(())
+    }
+}
+
+/// Webhook tables stand-in, counting lookups so tests can assert cache hits
+#[derive(Default)]
+pub struct MemoryDirectory {
+    uuids: RefCell<HashMap<String, String>>,
+    settings: RefCell<HashMap<String, WebhookSettings>>,
+    lookups: Cell<u32>,
+}
+
+impl MemoryDirectory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a webhook; its environments' UUIDs resolve to it as well
+    pub fn insert(&self, uuid: &str, webhook_id: &str, settings: WebhookSettings) {
+        let mut uuids = self.uuids.borrow_mut();
+        uuids.insert(uuid.to_string(), webhook_id.to_string());
+        for environment in &settings.environments {
+            uuids.insert(environment.uuid.clone(), webhook_id.to_string());
+        }
+        self.settings.borrow_mut().insert(webhook_id.to_string(), settings);
+    }
+
+    /// Replace a webhook's settings, as a config write would
+    pub fn update_settings(&self, webhook_id: &str, settings: WebhookSettings) {
+        self.settings.borrow_mut().insert(webhook_id.to_string(), settings);
+    }
+
+    /// How many lookups reached the directory (cache misses)
+    pub fn lookups(&self) -> u32 {
+        self.lookups.get()
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Directory for MemoryDirectory {
+    async fn find_webhook_id(&self, uuid: &str) -> Result<Option<String>> {
+        self.lookups.set(self.lookups.get() + 1);
+        Ok(self.uuids.borrow().get(uuid).cloned())
+    }
+
+    async fn load_settings(&self, webhook_id: &str) -> Result<WebhookSettings> {
+        self.lookups.set(self.lookups.get() + 1);
+        Ok(self.settings.borrow().get(webhook_id).cloned().unwrap_or_default())
+    }
+}
+
+/// Capture backend stand-in with the SQL backends' ordering, filter, inbox
+/// lease and purge semantics
+#[derive(Default)]
+pub struct MemoryStorage {
+    requests: RefCell<Vec<StoredRequest>>,
+    leases: RefCell<HashMap<String, i64>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.requests.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.borrow().is_empty()
+    }
+}
+
+fn received_ms(request: &StoredRequest) -> i64 {
+    request.received_at_ms.unwrap_or(request.received_at * 1000)
+}
+
+/// Value of an allow-listed filter column
+fn column<'a>(request: &'a StoredRequest, name: &str) -> Option<&'a str> {
+    match name {
+        "id" => Some(&request.id),
+        "method" => Some(&request.method),
+        "content_type" => request.content_type.as_deref(),
+        "event_type" => request.event_type.as_deref(),
+        "idempotency_key" => request.idempotency_key.as_deref(),
+        "verification" => request.verification.as_deref(),
+        "environment" => request.environment.as_deref(),
+        "connection_id" => request.connection_id.as_deref(),
+        "svix_id" => request.svix_id.as_deref(),
+        "shopify_topic" => request.shopify_topic.as_deref(),
+        "shopify_shop_domain" => request.shopify_shop_domain.as_deref(),
+        "github_event" => request.github_event.as_deref(),
+        "github_installation_id" => request.github_installation_id.as_deref(),
+        "github_repository" => request.github_repository.as_deref(),
+        "replay_of" => request.replay_of.as_deref(),
+        "correlation_id" => request.correlation_id.as_deref(),
+        _ => None,
+    }
+}
+
+/// Same matching as `storage::event_type_clause`
+fn event_type_matches(pattern: &str, event_type: Option<&str>) -> bool {
+    match pattern.strip_suffix('*') {
+        Some("") => true,
+        Some(prefix) => event_type.is_some_and(|event_type| event_type.starts_with(prefix)),
+        None => event_type == Some(pattern),
+    }
+}
+
+/// Ordering key for a sort column; NULLs sort last in both directions, as in SQL
+fn sort_key(request: &StoredRequest, sort: SortColumn) -> Option<i64> {
+    match sort {
+        SortColumn::ReceivedAt => Some(received_ms(request)),
+        SortColumn::EventTime => request.event_time,
+        SortColumn::Sequence => request.sequence,
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Storage for MemoryStorage {
+    async fn insert_capture(&self, record: &CaptureRecord) -> Result<bool> {
+        let mut requests = self.requests.borrow_mut();
+        if requests.iter().any(|existing| existing.id == record.id) {
+            return Ok(false);
+        }
+        requests.push(StoredRequest::from(record));
+        Ok(true)
+    }
+
+    async fn list_requests(&self, query: &RequestQuery) -> Result<Vec<StoredRequest>> {
+        let requests = self.requests.borrow();
+        let mut matching: Vec<&StoredRequest> = requests
+            .iter()
+            .filter(|request| request.webhook_id == query.webhook_id)
+            .filter(|request| query.since.is_none_or(|since| request.received_at >= since))
+            .filter(|request| query.until.is_none_or(|until| request.received_at < until))
+            .filter(|request| {
+                query
+                    .filters
+                    .iter()
+                    .all(|(name, value)| column(request, name) == Some(value.as_str()))
+            })
+            .collect();
+
+        matching.sort_by(|a, b| match (sort_key(a, query.sort), sort_key(b, query.sort)) {
+            (Some(a), Some(b)) if query.ascending => a.cmp(&b),
+            (Some(a), Some(b)) => b.cmp(&a),
+            (a, b) => b.is_some().cmp(&a.is_some()),
+        });
+
+        Ok(matching
+            .into_iter()
+            .skip(query.offset as usize)
+            .take(query.limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn inbox_fetch(&self, query: &InboxQuery) -> Result<Vec<StoredRequest>> {
+        let mut requests = self.requests.borrow_mut();
+        let mut leases = self.leases.borrow_mut();
+
+        let mut available: Vec<&mut StoredRequest> = requests
+            .iter_mut()
+            .filter(|request| request.webhook_id == query.webhook_id && request.acked_at_ms.is_none())
+            .filter(|request| leases.get(&request.id).is_none_or(|until| *until <= query.now_ms))
+            .filter(|request| !query.unread_only || request.read_at_ms.is_none())
+            .collect();
+        available.sort_by_key(|request| received_ms(request));
+
+        Ok(available
+            .into_iter()
+            .take(query.limit as usize)
+            .map(|request| {
+                if query.lease {
+                    request.read_at_ms = request.read_at_ms.or(Some(query.now_ms));
+                    leases.insert(request.id.clone(), query.now_ms + query.lease_ms);
+                }
+                request.clone()
+            })
+            .collect())
+    }
+
+    async fn inbox_ack(&self, webhook_id: &str, ids: &[String], now_ms: i64) -> Result<u64> {
+        let mut acked = 0;
+        for request in self.requests.borrow_mut().iter_mut() {
+            if request.webhook_id == webhook_id && request.acked_at_ms.is_none() && ids.contains(&request.id) {
+                request.acked_at_ms = Some(now_ms);
+                self.leases.borrow_mut().remove(&request.id);
+                acked += 1;
+            }
+        }
+        Ok(acked)
+    }
+
+    async fn record_repeat(&self, webhook_id: &str, id: &str, seen_at_ms: i64) -> Result<bool> {
+        let mut requests = self.requests.borrow_mut();
+        let Some(request) = requests.iter_mut().find(|request| request.webhook_id == webhook_id && request.id == id)
+        else {
+            return Ok(false);
+        };
+        request.repeat_count = Some(request.repeat_count.unwrap_or(1) + 1);
+        request.last_seen_at_ms = Some(request.last_seen_at_ms.unwrap_or(seen_at_ms).max(seen_at_ms));
+        Ok(true)
+    }
+
+    async fn purge(&self, webhook_id: &str, event_type: Option<&str>, before: i64, keep: &[String]) -> Result<u64> {
+        let mut requests = self.requests.borrow_mut();
+        let count = requests.len();
+        requests.retain(|request| {
+            !(request.webhook_id == webhook_id
+                && request.received_at < before
+                && !keep.contains(&request.id)
+                && event_type.is_none_or(|pattern| event_type_matches(pattern, request.event_type.as_deref())))
+        });
+        Ok((count - requests.len()) as u64)
+    }
+
+    async fn strip_payloads(&self, webhook_id: &str, before: i64, keep: &[String]) -> Result<u64> {
+        let mut stripped = 0;
+        for request in self.requests.borrow_mut().iter_mut() {
+            if request.webhook_id == webhook_id
+                && request.received_at < before
+                && !keep.contains(&request.id)
+                && (!request.data.is_empty() || request.headers != "{}")
+            {
+                request.data = String::new();
+                request.headers = "{}".to_string();
+                request.trailers = None;
+                request.canonical_data = None;
+                request.original_body = None;
+                request.stripe_cross_check = None;
+                request.oauth_exchange = None;
+                stripped += 1;
+            }
+        }
+        Ok(stripped)
+    }
+
+    async fn find_containing(&self, webhook_id: &str, needle: &str, limit: u32) -> Result<Vec<StoredRequest>> {
+        let mut found: Vec<StoredRequest> = self
+            .requests
+            .borrow()
+            .iter()
+            .filter(|request| request.webhook_id == webhook_id)
+            .filter(|request| {
+                [
+                    Some(&request.data),
+                    Some(&request.headers),
+                    request.trailers.as_ref(),
+                    request.canonical_data.as_ref(),
+                    request.idempotency_key.as_ref(),
+                    request.preview.as_ref(),
+                ]
+                .into_iter()
+                .flatten()
+                .any(|value| value.to_ascii_lowercase().contains(needle))
+            })
+            .cloned()
+            .collect();
+        found.sort_by_key(received_ms);
+        found.truncate(limit as usize);
+        Ok(found)
+    }
+
+    async fn delete_captures(&self, webhook_id: &str, ids: &[String]) -> Result<u64> {
+        let mut requests = self.requests.borrow_mut();
+        let count = requests.len();
+        requests.retain(|request| !(request.webhook_id == webhook_id && ids.contains(&request.id)));
+        Ok((count - requests.len()) as u64)
+    }
+
+    async fn replace_payloads(&self, replacements: &[StoredRequest]) -> Result<u64> {
+        let mut replaced = 0;
+        for request in self.requests.borrow_mut().iter_mut() {
+            let replacement = replacements
+                .iter()
+                .find(|replacement| replacement.webhook_id == request.webhook_id && replacement.id == request.id);
+            if let Some(replacement) = replacement {
+                request.data = replacement.data.clone();
+                request.headers = replacement.headers.clone();
+                request.trailers = replacement.trailers.clone();
+                request.canonical_data = replacement.canonical_data.clone();
+                request.idempotency_key = replacement.idempotency_key.clone();
+                request.preview = replacement.preview.clone();
+                request.original_body = replacement.original_body.clone();
+                request.stripe_cross_check = replacement.stripe_cross_check.clone();
+                request.oauth_exchange = replacement.oauth_exchange.clone();
+                replaced += 1;
+            }
+        }
+        Ok(replaced)
+    }
+
+    async fn daily_counts(&self, webhook_id: &str, before: i64) -> Result<Vec<DailyCount>> {
+        Ok(merge_daily_counts(
+            self.requests
+                .borrow()
+                .iter()
+                .filter(|request| request.webhook_id == webhook_id && request.received_at < before)
+                .map(|request| DailyCount {
+                    day: request.received_at - request.received_at.rem_euclid(86_400),
+                    event_type: request.event_type.clone(),
+                    count: 1,
+                    bytes: request.size_bytes.max(0) as u64,
+                }),
+        ))
+    }
+
+    async fn webhook_volumes(&self, since: i64) -> Result<Vec<WebhookVolume>> {
+        Ok(merge_webhook_volumes(
+            self.requests
+                .borrow()
+                .iter()
+                .filter(|request| request.received_at >= since)
+                .map(|request| WebhookVolume {
+                    webhook_id: request.webhook_id.clone(),
+                    count: 1,
+                    bytes: request.size_bytes.max(0) as u64,
+                    unverified: request.verification.as_deref().is_some_and(|v| v != "valid") as u64,
+                }),
+        ))
+    }
+
+    async fn shopify_counts(&self, webhook_id: &str, since: i64, until: i64) -> Result<Vec<ShopifyCount>> {
+        Ok(merge_shopify_counts(
+            self.requests
+                .borrow()
+                .iter()
+                .filter(|request| {
+                    request.webhook_id == webhook_id && (since..until).contains(&request.received_at)
+                })
+                .filter_map(|request| {
+                    Some(ShopifyCount {
+                        shop_domain: request.shopify_shop_domain.clone(),
+                        topic: request.shopify_topic.clone()?,
+                        count: 1,
+                        bytes: request.size_bytes.max(0) as u64,
+                        unverified: request.verification.as_deref().is_some_and(|v| v != "valid") as u64,
+                        last_received_at_ms: request.received_at_ms.unwrap_or(request.received_at * 1000),
+                    })
+                }),
+        ))
+    }
+
+    async fn count_received(&self, webhook_id: &str, event_type: Option<&str>, since: i64, until: Option<i64>) -> Result<u64> {
+        Ok(self
+            .requests
+            .borrow()
+            .iter()
+            .filter(|request| {
+                request.webhook_id == webhook_id
+                    && request.received_at >= since
+                    && until.is_none_or(|until| request.received_at < until)
+                    && event_type.is_none_or(|pattern| event_type_matches(pattern, request.event_type.as_deref()))
+            })
+            .count() as u64)
+    }
+}
@@ -0,0 +1,195 @@
+//! Log levels and sampling
+//! `LOG_LEVEL` sets how much each module logs, RUST_LOG style: a default level
+//! and per-module overrides, e.g. `warn,cache=debug,durable::jobs=info`
+//! (`error`, `warn`, `info`, `debug` or `off`; the longest matching module
+//! prefix wins). `LOG_SAMPLE` keeps only a share of a module's info and debug
+//! lines, e.g. `capture=0.01` writes one successful capture line in a hundred;
+//! warnings and errors are never sampled. Modules are named by their path in
+//! the crate (`cache`, `forward`, `durable::jobs`); the per-request capture
+//! line (see `capture_log.rs`) is `capture`.
+//!
+//! Sampling is systematic rather than random: every line adds the rate to what
+//! the module is owed and is written once a whole line is owed, so a 1% rate
+//! writes exactly every hundredth line. The settings are read once per isolate.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use worker::*;
+
+/// Crate prefix of `module_path!()`, left out of module names
+const CRATE_PREFIX: &str = "webhook_ingestion::";
+
+/// How much a module logs; a line is written when its level is at or below the module's
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "off" => Some(Self::Off),
+            "error" => Some(Self::Error),
+            "warn" | "warning" => Some(Self::Warn),
+            "info" => Some(Self::Info),
+            "debug" | "trace" => Some(Self::Debug),
+            _ => None,
+        }
+    }
+}
+
+/// Parsed `LOG_LEVEL` and `LOG_SAMPLE`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogConfig {
+    default_level: Level,
+    levels: Vec<(String, Level)>,
+    default_rate: f64,
+    rates: Vec<(String, f64)>,
+}
+
+impl Default for LogConfig {
+    /// Everything at info, unsampled
+    fn default() -> Self {
+        Self {
+            default_level: Level::Info,
+            levels: Vec::new(),
+            default_rate: 1.0,
+            rates: Vec::new(),
+        }
+    }
+}
+
+impl LogConfig {
+    /// Parse both settings; unknown levels and rates are ignored
+    pub fn parse(levels: Option<&str>, rates: Option<&str>) -> Self {
+        let mut config = Self::default();
+        for (module, value) in directives(levels.unwrap_or_default()) {
+            let Some(level) = Level::parse(value) else {
+                continue;
+            };
+            match module {
+                Some(module) => config.levels.push((module.to_string(), level)),
+                None => config.default_level = level,
+            }
+        }
+        for (module, value) in directives(rates.unwrap_or_default()) {
+            let Some(rate) = value.trim().parse::<f64>().ok().filter(|rate| rate.is_finite()) else {
+                continue;
+            };
+            let rate = rate.clamp(0.0, 1.0);
+            match module {
+                Some(module) => config.rates.push((module.to_string(), rate)),
+                None => config.default_rate = rate,
+            }
+        }
+        config
+    }
+
+    /// The level of a module (or of the closest enclosing one that has its own)
+    pub fn level(&self, module: &str) -> Level {
+        closest(&self.levels, module).unwrap_or(self.default_level)
+    }
+
+    /// Share of the module's info and debug lines that are written
+    pub fn rate(&self, module: &str) -> f64 {
+        closest(&self.rates, module).unwrap_or(self.default_rate)
+    }
+}
+
+/// `module=value` and bare `value` parts of a comma-separated setting
+fn directives(setting: &str) -> impl Iterator<Item = (Option<&str>, &str)> {
+    setting
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(|part| match part.split_once('=') {
+            Some((module, value)) => (Some(module.trim()), value),
+            None => (None, part),
+        })
+}
+
+/// The value of the longest module prefix (on `::` boundaries) that matches
+fn closest<T: Copy>(entries: &[(String, T)], module: &str) -> Option<T> {
+    entries
+        .iter()
+        .filter(|(prefix, _)| {
+            module == prefix || module.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.starts_with("::"))
+        })
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, value)| *value)
+}
+
+/// Decides line by line what gets written
+#[derive(Debug, Default)]
+pub struct Logger {
+    config: LogConfig,
+    /// Sampled share each module has accumulated towards its next line
+    owed: BTreeMap<String, f64>,
+}
+
+impl Logger {
+    pub fn new(config: LogConfig) -> Self {
+        Self {
+            config,
+            owed: BTreeMap::new(),
+        }
+    }
+
+    /// Whether a line at `level` from `module` is written; counts towards the module's sample
+    pub fn allows(&mut self, module: &str, level: Level) -> bool {
+        let module = module.strip_prefix(CRATE_PREFIX).unwrap_or(module);
+        if level == Level::Off || level > self.config.level(module) {
+            return false;
+        }
+        if level <= Level::Warn {
+            return true;
+        }
+        let rate = self.config.rate(module);
+        if rate >= 1.0 {
+            return true;
+        }
+        let owed = self.owed.entry(module.to_string()).or_insert(0.0);
+        *owed += rate;
+        if *owed >= 1.0 {
+            *owed -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn config(&self) -> &LogConfig {
+        &self.config
+    }
+}
+
+thread_local! {
+    static LOGGER: RefCell<Option<Logger>> = const { RefCell::new(None) };
+}
+
+/// Read `LOG_LEVEL` and `LOG_SAMPLE`, unless this isolate already has
+pub fn init(env: &Env) {
+    if LOGGER.with(|logger| logger.borrow().is_some()) {
+        return;
+    }
+    let var = |name: &str| env.var(name).ok().map(|value| value.to_string());
+    let config = LogConfig::parse(var("LOG_LEVEL").as_deref(), var("LOG_SAMPLE").as_deref());
+    LOGGER.with(|logger| *logger.borrow_mut() = Some(Logger::new(config)));
+}
+
+/// Whether this isolate writes a line at `level` from `module` (info, unsampled, before `init`)
+pub fn enabled(module: &str, level: Level) -> bool {
+    LOGGER.with(|logger| logger.borrow_mut().get_or_insert_with(Logger::default).allows(module, level))
+}
+
+/// Sampling rate applied to a module's info lines
+pub fn rate(module: &str) -> f64 {
+    LOGGER.with(|logger| match logger.borrow().as_ref() {
+        Some(logger) => logger.config().rate(module),
+        None => 1.0,
+    })
+}
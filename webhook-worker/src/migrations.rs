@@ -0,0 +1,209 @@
+//! Embedded schema migrations
+//! The numbered SQL files from ../migrations are compiled into the worker (see build.rs)
+//! and applied to D1 on demand, tracked in a `schema_migrations` table. Databases
+//! previously migrated with `wrangler d1 migrations apply` are adopted from `d1_migrations`.
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+use worker::*;
+
+pub struct Migration {
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+include!(concat!(env!("OUT_DIR"), "/migrations.rs"));
+
+#[derive(Serialize)]
+pub struct MigrationStatus {
+    pub applied: Vec<String>,
+    pub pending: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct NameRow {
+    name: String,
+}
+
+/// Create the tracking table and adopt wrangler's history on first use
+async fn ensure_tracking(db: &D1Database) -> Result<()> {
+    db.prepare(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (name TEXT PRIMARY KEY, applied_at INTEGER NOT NULL)",
+    )
+    .run()
+    .await?;
+
+    let wrangler_table = db
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'd1_migrations'")
+        .first::<NameRow>(None)
+        .await?;
+    if wrangler_table.is_some() {
+        db.prepare(
+            "INSERT OR IGNORE INTO schema_migrations (name, applied_at) \
+             SELECT name, CAST(strftime('%s', applied_at) AS INTEGER) FROM d1_migrations",
+        )
+        .run()
+        .await?;
+    }
+
+    Ok(())
+}
+
+async fn applied(db: &D1Database) -> Result<Vec<String>> {
+    Ok(db
+        .prepare("SELECT name FROM schema_migrations ORDER BY name")
+        .all()
+        .await?
+        .results::<NameRow>()?
+        .into_iter()
+        .map(|row| row.name)
+        .collect())
+}
+
+/// Applied and pending migrations
+pub async fn status(db: &D1Database) -> Result<MigrationStatus> {
+    ensure_tracking(db).await?;
+    let applied = applied(db).await?;
+    let pending = MIGRATIONS
+        .iter()
+        .filter(|migration| !applied.iter().any(|name| name == migration.name))
+        .map(|migration| migration.name.to_string())
+        .collect();
+    Ok(MigrationStatus { applied, pending })
+}
+
+/// Apply every pending migration in order; each one runs as a single atomic batch
+pub async fn apply_pending(db: &D1Database) -> Result<Vec<String>> {
+    let mut applied = Vec::new();
+    while let Some(name) = apply_next(db).await? {
+        applied.push(name);
+    }
+    Ok(applied)
+}
+
+/// Apply the first pending migration, if any, as a single atomic batch
+pub async fn apply_next(db: &D1Database) -> Result<Option<String>> {
+    let pending = status(db).await?.pending;
+    let Some(migration) = MIGRATIONS.iter().find(|m| pending.iter().any(|name| name == m.name)) else {
+        return Ok(None);
+    };
+    log_info!("🧱 Applying migration {}", migration.name);
+
+    let mut batch = statements(migration.sql)
+        .into_iter()
+        .map(|statement| db.prepare(statement))
+        .collect::<Vec<_>>();
+    batch.push(
+        db.prepare("INSERT INTO schema_migrations (name, applied_at) VALUES (?1, ?2)")
+            .bind(&[
+                JsValue::from_str(migration.name),
+                JsValue::from_f64((Date::now().as_millis() / 1000) as f64),
+            ])?,
+    );
+
+    if let Err(e) = db.batch(batch).await {
+        log_error!("❌ Migration {} failed: {:?}", migration.name, e);
+        return Err(e);
+    }
+    Ok(Some(migration.name.to_string()))
+}
+
+/// Split a migration file into individual statements, dropping comments. A `;`
+/// only ends a statement outside quotes, comments and the `BEGIN ... END` body
+/// of a `CREATE TRIGGER`.
+pub fn statements(sql: &str) -> Vec<String> {
+    let chars = sql.chars().collect::<Vec<_>>();
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    // Leading keywords of the current statement, enough to spot CREATE [TEMP] TRIGGER
+    let mut keywords = Vec::new();
+    // BEGIN / CASE nesting inside a trigger body
+    let mut depth = 0usize;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            '-' if next == Some('-') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '/' if next == Some('*') => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    i += 1;
+                }
+                i += 2;
+                current.push(' ');
+                continue;
+            }
+            '\'' | '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                current.push(c);
+                i += 1;
+                while i < chars.len() {
+                    current.push(chars[i]);
+                    i += 1;
+                    if chars[i - 1] == close {
+                        // A doubled quote is an escaped one
+                        if close != ']' && chars.get(i) == Some(&close) {
+                            current.push(close);
+                            i += 1;
+                        } else {
+                            break;
+                        }
+                    }
+                }
+                continue;
+            }
+            ';' if depth == 0 => {
+                let statement = current.trim();
+                if !statement.is_empty() {
+                    statements.push(statement.to_string());
+                }
+                current.clear();
+                keywords.clear();
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let word = chars[start..i].iter().collect::<String>();
+                let upper = word.to_ascii_uppercase();
+                if keywords.len() < 3 {
+                    keywords.push(upper.clone());
+                }
+                if is_trigger(&keywords) {
+                    match upper.as_str() {
+                        "BEGIN" | "CASE" => depth += 1,
+                        "END" => depth = depth.saturating_sub(1),
+                        _ => {}
+                    }
+                }
+                current.push_str(&word);
+                continue;
+            }
+            c => current.push(c),
+        }
+        i += 1;
+    }
+
+    let statement = current.trim();
+    if !statement.is_empty() {
+        statements.push(statement.to_string());
+    }
+    statements
+}
+
+/// Whether a statement's leading keywords are `CREATE [TEMP|TEMPORARY] TRIGGER`
+fn is_trigger(keywords: &[String]) -> bool {
+    match keywords {
+        [create, trigger, ..] if create == "CREATE" && trigger == "TRIGGER" => true,
+        [create, temp, trigger] if create == "CREATE" && trigger == "TRIGGER" => temp == "TEMP" || temp == "TEMPORARY",
+        _ => false,
+    }
+}
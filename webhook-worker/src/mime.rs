@@ -0,0 +1,269 @@
+//! MIME message parsing
+//! Enough of RFC 5322 / 2045-2047 to inspect inbound email: unfolded headers
+//! (encoded words decoded), nested multipart bodies flattened into leaf parts,
+//! and base64 / quoted-printable transfer encodings undone. Malformed input
+//! never fails; whatever can't be split is kept as a single part.
+
+use crate::charset::Charset;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::collections::HashMap;
+
+/// Multipart nesting deeper than this is kept as an opaque part
+const MAX_DEPTH: usize = 8;
+
+/// A parsed message: top-level headers and every leaf part
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Message {
+    /// Header names lowercased, in order (repeats such as `received` kept)
+    pub headers: Vec<(String, String)>,
+    pub parts: Vec<Part>,
+}
+
+/// A leaf MIME part with its transfer encoding removed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    /// Media type, lowercased (`text/plain` when absent)
+    pub content_type: String,
+    pub charset: Option<String>,
+    pub filename: Option<String>,
+    /// `Content-Disposition: attachment`, or a part carrying a filename
+    pub attachment: bool,
+    pub body: Vec<u8>,
+}
+
+impl Message {
+    /// First value of a header
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// First inline part of the given media type, decoded from its charset
+    pub fn body_text(&self, content_type: &str) -> Option<String> {
+        self.parts
+            .iter()
+            .find(|part| !part.attachment && part.content_type == content_type)
+            .map(|part| decode_text(part.charset.as_deref(), &part.body))
+    }
+
+    pub fn attachments(&self) -> impl Iterator<Item = &Part> {
+        self.parts.iter().filter(|part| part.attachment)
+    }
+}
+
+/// Parse a raw RFC 5322 message
+pub fn parse(raw: &[u8]) -> Message {
+    let (headers, body) = split_headers(raw);
+    let mut parts = Vec::new();
+    collect_parts(&headers, body, 0, &mut parts);
+    Message { headers, parts }
+}
+
+/// Headers and body, split at the first empty line
+fn split_headers(raw: &[u8]) -> (Vec<(String, String)>, &[u8]) {
+    let (head, body) = match find(raw, b"\r\n\r\n") {
+        Some(at) => (&raw[..at], &raw[at + 4..]),
+        None => match find(raw, b"\n\n") {
+            Some(at) => (&raw[..at], &raw[at + 2..]),
+            None => (raw, &raw[raw.len()..]),
+        },
+    };
+
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in String::from_utf8_lossy(head).lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    for (_, value) in headers.iter_mut() {
+        *value = decode_words(value);
+    }
+    (headers, body)
+}
+
+fn collect_parts(headers: &[(String, String)], body: &[u8], depth: usize, parts: &mut Vec<Part>) {
+    let header = |name: &str| headers.iter().find(|(header, _)| header == name).map(|(_, value)| value.as_str());
+    let (content_type, type_params) = header_params(header("content-type").unwrap_or("text/plain"));
+
+    if content_type.starts_with("multipart/") && depth < MAX_DEPTH {
+        if let Some(boundary) = type_params.get("boundary") {
+            for section in split_multipart(body, boundary) {
+                let (headers, body) = split_headers(section);
+                collect_parts(&headers, body, depth + 1, parts);
+            }
+            return;
+        }
+    }
+
+    let (disposition, disposition_params) = header_params(header("content-disposition").unwrap_or(""));
+    let filename = disposition_params
+        .get("filename")
+        .or_else(|| type_params.get("name"))
+        .map(|name| decode_words(name));
+    let encoding = header("content-transfer-encoding").unwrap_or("").to_ascii_lowercase();
+    parts.push(Part {
+        content_type,
+        charset: type_params.get("charset").map(|charset| charset.to_ascii_lowercase()),
+        attachment: disposition == "attachment" || filename.is_some(),
+        filename,
+        body: decode_transfer(&encoding, body),
+    });
+}
+
+/// Sections between `--boundary` delimiter lines, up to the closing `--boundary--`
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut sections = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut offset = 0;
+    for line in body.split_inclusive(|byte| *byte == b'\n') {
+        let trimmed = trim_line(line);
+        if trimmed.starts_with(delimiter.as_bytes()) {
+            if let Some(start) = start {
+                sections.push(trim_trailing_newline(&body[start..offset]));
+            }
+            if trimmed == format!("{}--", delimiter).as_bytes() {
+                return sections;
+            }
+            start = Some(offset + line.len());
+        }
+        offset += line.len();
+    }
+    if let Some(start) = start {
+        sections.push(&body[start..]);
+    }
+    sections
+}
+
+/// Media type (lowercased) and its `key=value` parameters (keys lowercased, quotes removed)
+fn header_params(value: &str) -> (String, HashMap<String, String>) {
+    let mut pieces = value.split(';');
+    let main = pieces.next().unwrap_or("").trim().to_ascii_lowercase();
+    let params = pieces
+        .filter_map(|piece| piece.split_once('='))
+        .map(|(key, value)| {
+            let value = value.trim();
+            let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+            (key.trim().to_ascii_lowercase(), value.to_string())
+        })
+        .collect();
+    (main, params)
+}
+
+fn decode_transfer(encoding: &str, body: &[u8]) -> Vec<u8> {
+    match encoding {
+        "base64" => {
+            let compact: Vec<u8> = body.iter().copied().filter(|byte| !byte.is_ascii_whitespace()).collect();
+            BASE64.decode(&compact).unwrap_or_else(|_| body.to_vec())
+        }
+        "quoted-printable" => decode_quoted_printable(body, false),
+        _ => body.to_vec(),
+    }
+}
+
+/// Quoted-printable (RFC 2045 §6.7); `underscores` is the encoded-word variant (RFC 2047 "Q")
+fn decode_quoted_printable(input: &[u8], underscores: bool) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        match input[i] {
+            b'=' if input[i + 1..].starts_with(b"\r\n") => i += 3,
+            b'=' if input[i + 1..].starts_with(b"\n") => i += 2,
+            b'=' => match input.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()) {
+                Some(byte) => {
+                    output.push(byte);
+                    i += 3;
+                }
+                None => {
+                    output.push(b'=');
+                    i += 1;
+                }
+            },
+            b'_' if underscores => {
+                output.push(b' ');
+                i += 1;
+            }
+            byte => {
+                output.push(byte);
+                i += 1;
+            }
+        }
+    }
+    output
+}
+
+/// Webhook UUID an address delivers to: the local part, or its last `+` tag
+pub fn mailbox_uuid(address: &str) -> Option<String> {
+    let local = address.trim().trim_matches(['<', '>']).rsplit_once('@')?.0;
+    let uuid = local.rsplit('+').next().unwrap_or(local).trim();
+    (!uuid.is_empty()).then(|| uuid.to_ascii_lowercase())
+}
+
+/// Decode RFC 2047 encoded words (`=?charset?B|Q?text?=`); undecodable words are kept
+pub fn decode_words(value: &str) -> String {
+    let mut output = String::new();
+    let mut rest = value;
+    let mut previous_was_word = false;
+    while let Some(start) = rest.find("=?") {
+        let decoded = rest[start + 2..].find("?=").and_then(|end| {
+            let word = &rest[start + 2..start + 2 + end];
+            let mut fields = word.splitn(3, '?');
+            let (charset, encoding, text) = (fields.next()?, fields.next()?, fields.next()?);
+            let bytes = match encoding.to_ascii_uppercase().as_str() {
+                "B" => BASE64.decode(text).ok()?,
+                "Q" => decode_quoted_printable(text.as_bytes(), true),
+                _ => return None,
+            };
+            Some((decode_text(Some(charset), &bytes), start + 2 + end + 2))
+        });
+        match decoded {
+            Some((text, consumed)) => {
+                let between = &rest[..start];
+                // Whitespace between adjacent encoded words is not part of the text
+                if !(previous_was_word && between.trim().is_empty()) {
+                    output.push_str(between);
+                }
+                output.push_str(&text);
+                rest = &rest[consumed..];
+                previous_was_word = true;
+            }
+            None => {
+                output.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                previous_was_word = false;
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Text in a labelled charset; unknown labels are read as UTF-8
+fn decode_text(label: Option<&str>, bytes: &[u8]) -> String {
+    label.and_then(Charset::from_label).unwrap_or(Charset::Utf8).decode(bytes)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn trim_line(line: &[u8]) -> &[u8] {
+    let end = line
+        .iter()
+        .rposition(|byte| !byte.is_ascii_whitespace())
+        .map_or(0, |at| at + 1);
+    &line[..end]
+}
+
+fn trim_trailing_newline(section: &[u8]) -> &[u8] {
+    let section = section.strip_suffix(b"\n").unwrap_or(section);
+    section.strip_suffix(b"\r").unwrap_or(section)
+}
@@ -0,0 +1,194 @@
+//! MQTT packet codec (experimental)
+//! The subset a message sink needs, for MQTT 3.1 / 3.1.1 over WebSocket: CONNECT,
+//! PUBLISH (QoS 0-2), PUBREL, SUBSCRIBE / UNSUBSCRIBE (refused, nothing is ever
+//! published to clients), PINGREQ and DISCONNECT, plus the acknowledgements the
+//! broker side sends back. Each WebSocket message must carry whole packets, as
+//! the common clients (mqtt.js, Paho, MQTT.fx) send them.
+
+/// A decoded client packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Packet {
+    Connect { protocol_level: u8, client_id: String },
+    Publish(Publish),
+    PubRel { packet_id: u16 },
+    Subscribe { packet_id: u16, filters: usize },
+    Unsubscribe { packet_id: u16 },
+    PingReq,
+    Disconnect,
+    /// A packet type clients never send to a broker (or MQTT 5 AUTH)
+    Unsupported(u8),
+}
+
+/// An application message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Publish {
+    pub topic: String,
+    pub qos: u8,
+    pub retain: bool,
+    pub dup: bool,
+    /// Present for QoS 1 and 2
+    pub packet_id: Option<u16>,
+    pub payload: Vec<u8>,
+}
+
+/// The bytes are not a sequence of well-formed packets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Malformed;
+
+/// Protocol levels accepted in CONNECT (3.1 and 3.1.1)
+pub const SUPPORTED_LEVELS: &[u8] = &[3, 4];
+
+/// CONNACK return codes
+pub const CONNECT_ACCEPTED: u8 = 0x00;
+pub const UNACCEPTABLE_PROTOCOL: u8 = 0x01;
+
+/// SUBACK return code for a refused subscription
+const SUBSCRIPTION_FAILURE: u8 = 0x80;
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Malformed> {
+        if self.bytes.len() < n {
+            return Err(Malformed);
+        }
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, Malformed> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Malformed> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn string(&mut self) -> Result<String, Malformed> {
+        let len = self.u16()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| Malformed)
+    }
+
+    /// Variable byte integer (remaining length), at most four bytes
+    fn varint(&mut self) -> Result<usize, Malformed> {
+        let mut value = 0usize;
+        for shift in (0..4).map(|i| i * 7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Malformed)
+    }
+}
+
+/// Decode every packet in one WebSocket message
+pub fn decode_all(bytes: &[u8]) -> Result<Vec<Packet>, Malformed> {
+    let mut reader = Reader { bytes };
+    let mut packets = Vec::new();
+    while !reader.bytes.is_empty() {
+        let header = reader.u8()?;
+        let length = reader.varint()?;
+        packets.push(decode(header, reader.take(length)?)?);
+    }
+    Ok(packets)
+}
+
+fn decode(header: u8, body: &[u8]) -> Result<Packet, Malformed> {
+    let mut body = Reader { bytes: body };
+    let flags = header & 0x0f;
+    Ok(match header >> 4 {
+        1 => {
+            let _protocol = body.string()?;
+            let protocol_level = body.u8()?;
+            let _connect_flags = body.u8()?;
+            let _keep_alive = body.u16()?;
+            // MQTT 5 inserts properties here; its CONNECT is refused by level anyway
+            let client_id = if SUPPORTED_LEVELS.contains(&protocol_level) {
+                body.string()?
+            } else {
+                String::new()
+            };
+            Packet::Connect { protocol_level, client_id }
+        }
+        3 => {
+            let qos = (flags >> 1) & 0x03;
+            if qos == 3 {
+                return Err(Malformed);
+            }
+            let topic = body.string()?;
+            let packet_id = if qos > 0 { Some(body.u16()?) } else { None };
+            Packet::Publish(Publish {
+                topic,
+                qos,
+                retain: flags & 0x01 != 0,
+                dup: flags & 0x08 != 0,
+                packet_id,
+                payload: body.bytes.to_vec(),
+            })
+        }
+        6 => Packet::PubRel { packet_id: body.u16()? },
+        8 => {
+            let packet_id = body.u16()?;
+            let mut filters = 0;
+            while !body.bytes.is_empty() {
+                body.string()?;
+                body.u8()?;
+                filters += 1;
+            }
+            Packet::Subscribe { packet_id, filters }
+        }
+        10 => Packet::Unsubscribe { packet_id: body.u16()? },
+        12 => Packet::PingReq,
+        14 => Packet::Disconnect,
+        kind => Packet::Unsupported(kind),
+    })
+}
+
+/// Fixed header with the remaining length, then the body
+fn encode(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    let mut length = body.len();
+    loop {
+        let byte = (length % 128) as u8;
+        length /= 128;
+        if length == 0 {
+            packet.push(byte);
+            break;
+        }
+        packet.push(byte | 0x80);
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn ack(header: u8, packet_id: u16) -> Vec<u8> {
+    encode(header, &packet_id.to_be_bytes())
+}
+
+/// CONNACK without a session present
+pub fn connack(return_code: u8) -> Vec<u8> {
+    encode(0x20, &[0, return_code])
+}
+
+/// What the broker answers to a packet (CONNECT is answered with `connack`)
+pub fn response(packet: &Packet) -> Option<Vec<u8>> {
+    match packet {
+        Packet::Publish(Publish { qos: 1, packet_id: Some(id), .. }) => Some(ack(0x40, *id)),
+        Packet::Publish(Publish { qos: 2, packet_id: Some(id), .. }) => Some(ack(0x50, *id)),
+        Packet::PubRel { packet_id } => Some(ack(0x70, *packet_id)),
+        Packet::Subscribe { packet_id, filters } => {
+            let mut body = packet_id.to_be_bytes().to_vec();
+            body.extend(std::iter::repeat_n(SUBSCRIPTION_FAILURE, *filters));
+            Some(encode(0x90, &body))
+        }
+        Packet::Unsubscribe { packet_id } => Some(ack(0xb0, *packet_id)),
+        Packet::PingReq => Some(encode(0xd0, &[])),
+        _ => None,
+    }
+}
@@ -0,0 +1,216 @@
+//! OAuth2 callback capture
+//! `GET /w/{uuid}/oauth/callback` can be registered as the redirect URI of an
+//! OAuth2 client. The callback's query parameters are stored like any
+//! body-less capture, except that `code` (and any token a provider puts in
+//! the query) is replaced by a short SHA-256 fingerprint before anything is
+//! stored, logged or forwarded, so captures never hold a usable credential.
+//! With `oauth` in the webhook config, the code is first exchanged at the
+//! token endpoint with the stored client credentials; what came back is kept in
+//! `webhook_data.oauth_exchange`: the token type, scope and lifetime, which
+//! tokens were issued and the claims of a JWT ID or access token, with every
+//! claim that may identify the user redacted. Tokens themselves are dropped.
+
+use crate::config::{self, OauthClient};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use base64::Engine;
+use futures_util::future::{select, Either};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use worker::*;
+
+/// Path suffix of the callback route
+pub const CALLBACK_PATH: &str = "/oauth/callback";
+
+/// Query parameters never stored as sent; `code` is the one exchanged
+const SECRET_PARAMS: &[&str] = &["code", "access_token", "id_token", "refresh_token"];
+
+/// Claims kept as issued; everything else is redacted
+const PUBLIC_CLAIMS: &[&str] = &[
+    "iss", "aud", "azp", "exp", "iat", "nbf", "auth_time", "scope", "scp", "token_use", "amr", "acr", "typ",
+];
+
+/// Stored in place of redacted values
+const REDACTED: &str = "[REDACTED]";
+
+/// Give up on a slow token endpoint after this long
+const EXCHANGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `sha256:` and the first 12 hex digits of the value's SHA-256
+pub fn fingerprint(value: &str) -> String {
+    let digest = Sha256::digest(value.as_bytes());
+    let hex: String = digest.iter().take(6).map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256:{}", hex)
+}
+
+/// The callback URL with secret query parameters replaced by their fingerprints,
+/// and the authorization code that was in it
+pub fn strip_secrets(url: &Url) -> (Url, Option<String>) {
+    let mut code = None;
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(name, value)| {
+            if name == "code" {
+                code = Some(value.to_string());
+            }
+            if SECRET_PARAMS.contains(&name.as_ref()) {
+                (name.to_string(), fingerprint(&value))
+            } else {
+                (name.to_string(), value.to_string())
+            }
+        })
+        .collect();
+    let mut stripped = url.clone();
+    if !pairs.is_empty() {
+        stripped.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    (stripped, code)
+}
+
+/// `oauth.callback`, or `oauth.error` when the provider reported one
+pub fn event_type(url: &Url) -> &'static str {
+    if url.query_pairs().any(|(name, _)| name == "error") {
+        "oauth.error"
+    } else {
+        "oauth.callback"
+    }
+}
+
+/// The token endpoint's answer, without the tokens
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Exchange {
+    /// HTTP status of the token endpoint (None when it could not be reached)
+    pub status: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<i64>,
+    /// Which of `access_token`, `refresh_token` and `id_token` were issued
+    #[serde(default)]
+    pub issued: Vec<String>,
+    /// Redacted claims of the ID token, else of a JWT access token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claims: Option<Value>,
+    /// `error` and `error_description` of a refusal, or why the endpoint was not reached
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Summarize a token endpoint response
+pub fn summarize(status: u16, response: &Value) -> Exchange {
+    let text = |name: &str| response.get(name).and_then(Value::as_str).map(str::to_string);
+    let issued: Vec<String> = ["access_token", "refresh_token", "id_token"]
+        .into_iter()
+        .filter(|name| response.get(*name).is_some_and(|token| !token.is_null()))
+        .map(str::to_string)
+        .collect();
+    let claims = ["id_token", "access_token"]
+        .into_iter()
+        .find_map(|name| response.get(name).and_then(Value::as_str).and_then(jwt_claims))
+        .map(|claims| redact_claims(&claims));
+    let error = text("error").map(|error| match text("error_description") {
+        Some(description) => format!("{}: {}", error, description),
+        None => error,
+    });
+    Exchange {
+        status: Some(status),
+        token_type: text("token_type"),
+        scope: text("scope"),
+        expires_in: response.get("expires_in").and_then(Value::as_i64),
+        issued,
+        claims,
+        error,
+    }
+}
+
+/// Payload of a JWT, unverified (the token came straight from the issuer)
+pub fn jwt_claims(token: &str) -> Option<Value> {
+    let mut parts = token.split('.');
+    let (_header, payload, _signature) = (parts.next()?, parts.next()?, parts.next()?);
+    let decoded = BASE64URL.decode(payload.trim_end_matches('=')).ok()?;
+    serde_json::from_slice::<Value>(&decoded).ok().filter(Value::is_object)
+}
+
+/// Claims with every value outside `PUBLIC_CLAIMS` replaced by `[REDACTED]`
+pub fn redact_claims(claims: &Value) -> Value {
+    let Some(claims) = claims.as_object() else {
+        return Value::Null;
+    };
+    let redacted: Map<String, Value> = claims
+        .iter()
+        .map(|(name, value)| {
+            let value = if PUBLIC_CLAIMS.contains(&name.as_str()) {
+                value.clone()
+            } else {
+                Value::String(REDACTED.to_string())
+            };
+            (name.clone(), value)
+        })
+        .collect();
+    Value::Object(redacted)
+}
+
+/// Redirect URI to send with the code: configured, else the callback URL without its query
+pub fn redirect_uri(client: &OauthClient, callback: &Url) -> String {
+    client.redirect_uri.clone().unwrap_or_else(|| {
+        let mut url = callback.clone();
+        url.set_query(None);
+        url.to_string()
+    })
+}
+
+/// Exchange `code` at the client's token endpoint
+pub async fn exchange(env: &Env, client: &OauthClient, code: &str, callback: &Url) -> Exchange {
+    let Some(secret) = config::resolve_secret(env, &client.client_secret) else {
+        log_warn!("⚠️  OAuth client secret {} is not configured", client.client_secret);
+        return Exchange {
+            error: Some("client secret is not configured".to_string()),
+            ..Exchange::default()
+        };
+    };
+    let form = form_urlencoded::Serializer::new(String::new())
+        .append_pair("grant_type", "authorization_code")
+        .append_pair("code", code)
+        .append_pair("redirect_uri", &redirect_uri(client, callback))
+        .append_pair("client_id", &client.client_id)
+        .append_pair("client_secret", &secret)
+        .finish();
+    match post(&client.token_url, form).await {
+        Ok((status, response)) => summarize(status, &response),
+        Err(error) => {
+            log_warn!("⚠️  OAuth token exchange failed: {}", error);
+            Exchange {
+                error: Some(error),
+                ..Exchange::default()
+            }
+        }
+    }
+}
+
+async fn post(url: &str, form: String) -> std::result::Result<(u16, Value), String> {
+    let headers = Headers::new();
+    headers
+        .set("Content-Type", "application/x-www-form-urlencoded")
+        .map_err(|e| e.to_string())?;
+    headers.set("Accept", "application/json").map_err(|e| e.to_string())?;
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_headers(headers)
+        .with_body(Some(form.into()));
+    let request = Request::new_with_init(url, &init).map_err(|e| e.to_string())?;
+
+    let exchange = async {
+        let mut response = Fetch::Request(request).send().await?;
+        let status = response.status_code();
+        let body = response.text().await?;
+        Ok::<_, Error>((status, serde_json::from_str(&body).unwrap_or(Value::Null)))
+    };
+    match select(Box::pin(exchange), Delay::from(EXCHANGE_TIMEOUT)).await {
+        Either::Left((Ok(answer), _)) => Ok(answer),
+        Either::Left((Err(e), _)) => Err(e.to_string()),
+        Either::Right(_) => Err("timed out".to_string()),
+    }
+}
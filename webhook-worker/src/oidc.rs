@@ -0,0 +1,361 @@
+//! OAuth2 / OIDC bearer token validation
+//! JWTs issued by an external IdP (`OIDC_ISSUER`) are verified against the issuer's
+//! JWKS with WebCrypto (RS256 / ES256), then the `sub` claim is mapped to a user
+//! through Better Auth's `account` table, falling back to the `email` claim.
+//! An unknown `kid` refetches the JWKS once (at most once a minute per issuer),
+//! and an unreachable IdP fails the token rather than the request.
+
+use crate::auth::{Principal, Role};
+use crate::webcrypto::{self, call_async};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use js_sys::{Array, Uint8Array};
+use serde::Deserialize;
+use serde_json::Value;
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// KV key prefix for cached JWKS documents
+const JWKS_CACHE_PREFIX: &str = "oidc:jwks:";
+const JWKS_CACHE_TTL_SECONDS: u64 = 3600;
+
+/// KV key prefix marking a recent forced JWKS refetch, and how long it holds
+const JWKS_REFRESH_PREFIX: &str = "oidc:jwks-refresh:";
+const JWKS_REFRESH_TTL_SECONDS: u64 = 60;
+
+/// Clock skew tolerated on `exp` / `nbf`
+const LEEWAY_SECONDS: i64 = 60;
+
+/// IdP settings from the environment
+struct OidcConfig {
+    issuer: String,
+    audience: Option<String>,
+    jwks_url: Option<String>,
+    /// Better Auth `account.provider_id` whose `account_id` holds the `sub` claim
+    provider_id: String,
+    role_claim: String,
+    default_role: Role,
+}
+
+impl OidcConfig {
+    fn from_env(env: &Env) -> Option<Self> {
+        let var = |name: &str| {
+            env.var(name)
+                .ok()
+                .map(|value| value.to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        Some(Self {
+            issuer: var("OIDC_ISSUER")?.trim_end_matches('/').to_string(),
+            audience: var("OIDC_AUDIENCE"),
+            jwks_url: var("OIDC_JWKS_URL"),
+            provider_id: var("OIDC_PROVIDER_ID").unwrap_or_else(|| "oidc".to_string()),
+            role_claim: var("OIDC_ROLE_CLAIM").unwrap_or_else(|| "webhook_role".to_string()),
+            default_role: var("OIDC_DEFAULT_ROLE")
+                .and_then(|value| Role::parse(&value))
+                .unwrap_or(Role::Viewer),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    iss: String,
+    sub: String,
+    #[serde(default)]
+    aud: Option<Value>,
+    exp: i64,
+    nbf: Option<i64>,
+    email: Option<String>,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct Jwks {
+    keys: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+struct Discovery {
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct UserRow {
+    user_id: String,
+}
+
+/// Whether OIDC validation is configured
+pub fn is_enabled(env: &Env) -> bool {
+    OidcConfig::from_env(env).is_some()
+}
+
+/// Whether a bearer token has the shape of a JWT
+pub fn looks_like_jwt(token: &str) -> bool {
+    token.split('.').count() == 3
+}
+
+/// Validate an IdP-issued JWT and map it to a principal. Returns None for any
+/// invalid, expired or unmapped token.
+pub async fn authenticate(env: &Env, token: &str) -> Result<Option<Principal>> {
+    let config = match OidcConfig::from_env(env) {
+        Some(config) => config,
+        None => return Ok(None),
+    };
+
+    let claims = match verify(env, &config, token).await? {
+        Some(claims) => claims,
+        None => return Ok(None),
+    };
+
+    let user_id = match find_user(&env.d1("DB")?, &config, &claims).await? {
+        Some(user_id) => user_id,
+        None => {
+            log_info!("🔒 OIDC subject {} has no matching user", claims.sub);
+            return Ok(None);
+        }
+    };
+
+    let role = claims
+        .extra
+        .get(&config.role_claim)
+        .and_then(Value::as_str)
+        .and_then(Role::parse)
+        .unwrap_or(config.default_role);
+
+    Ok(Some(Principal {
+        actor: format!("oidc:{}", claims.sub),
+        user_id: Some(user_id),
+        role,
+        scopes: None,
+    }))
+}
+
+async fn verify(env: &Env, config: &OidcConfig, token: &str) -> Result<Option<Claims>> {
+    let mut parts = token.split('.');
+    let (header_b64, payload_b64, signature_b64) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(header), Some(payload), Some(signature)) => (header, payload, signature),
+        _ => return Ok(None),
+    };
+
+    let header: Header = match decode_json(header_b64) {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+    let claims: Claims = match decode_json(payload_b64) {
+        Some(claims) => claims,
+        None => return Ok(None),
+    };
+    let signature = match URL_SAFE_NO_PAD.decode(signature_b64) {
+        Ok(signature) => signature,
+        Err(_) => return Ok(None),
+    };
+
+    let (import_algorithm, verify_algorithm) = match header.alg.as_str() {
+        "RS256" => (
+            serde_json::json!({ "name": "RSASSA-PKCS1-v1_5", "hash": "SHA-256" }),
+            serde_json::json!({ "name": "RSASSA-PKCS1-v1_5" }),
+        ),
+        "ES256" => (
+            serde_json::json!({ "name": "ECDSA", "namedCurve": "P-256" }),
+            serde_json::json!({ "name": "ECDSA", "hash": "SHA-256" }),
+        ),
+        _ => return Ok(None), // Reject "none" and symmetric algorithms
+    };
+
+    // Cheap claim checks before any network or crypto work
+    let now = (Date::now().as_millis() / 1000) as i64;
+    if claims.iss.trim_end_matches('/') != config.issuer
+        || claims.exp.saturating_add(LEEWAY_SECONDS) < now
+        || claims.nbf.is_some_and(|nbf| nbf.saturating_sub(LEEWAY_SECONDS) > now)
+        || !audience_matches(&claims.aud, config.audience.as_deref())
+    {
+        return Ok(None);
+    }
+
+    let kid = header.kid.as_deref();
+    let (mut jwks, cached) = match jwks(env, config).await {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            log_warn!("⚠️  Failed to load JWKS for {}: {:?}", config.issuer, e);
+            return Ok(None);
+        }
+    };
+    // A kid the cached set doesn't know may belong to a key rotated in since
+    if cached && kid.is_some() && select_key(&jwks.keys, &header.alg, kid).is_none() {
+        match refetch_jwks(env, config).await {
+            Ok(Some(refetched)) => jwks = refetched,
+            Ok(None) => {}
+            Err(e) => log_warn!("⚠️  Failed to refetch JWKS for {}: {:?}", config.issuer, e),
+        }
+    }
+    let jwk = match select_key(&jwks.keys, &header.alg, kid) {
+        Some(jwk) => jwk,
+        None => return Ok(None),
+    };
+
+    let signed = format!("{}.{}", header_b64, payload_b64);
+    let valid = webcrypto_verify(jwk, &import_algorithm, &verify_algorithm, &signature, signed.as_bytes())
+        .await
+        .unwrap_or(false);
+
+    Ok(valid.then_some(claims))
+}
+
+fn decode_json<T: serde::de::DeserializeOwned>(segment: &str) -> Option<T> {
+    let bytes = URL_SAFE_NO_PAD.decode(segment).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// `aud` may be a string or an array of strings
+fn audience_matches(aud: &Option<Value>, expected: Option<&str>) -> bool {
+    let expected = match expected {
+        Some(expected) => expected,
+        None => return true,
+    };
+    match aud {
+        Some(Value::String(aud)) => aud == expected,
+        Some(Value::Array(values)) => values.iter().any(|value| value.as_str() == Some(expected)),
+        _ => false,
+    }
+}
+
+/// The key a token names: matching `kid` (any key when the token has none), a
+/// key type fitting `alg`, and neither a different `alg` nor a non-signing `use`
+pub fn select_key<'a>(keys: &'a [Value], alg: &str, kid: Option<&str>) -> Option<&'a Value> {
+    let field = |key: &'a Value, name: &str| key.get(name).and_then(Value::as_str);
+    keys.iter().find(|key| {
+        let kty_fits = match alg {
+            "RS256" => field(key, "kty") == Some("RSA"),
+            "ES256" => field(key, "kty") == Some("EC") && field(key, "crv") == Some("P-256"),
+            _ => false,
+        };
+        kty_fits
+            && (kid.is_none() || field(key, "kid") == kid)
+            && field(key, "alg").is_none_or(|key_alg| key_alg == alg)
+            && field(key, "use").is_none_or(|key_use| key_use == "sig")
+    })
+}
+
+/// JWKS for the issuer, cached in KV; true when it came from the cache
+async fn jwks(env: &Env, config: &OidcConfig) -> Result<(Jwks, bool)> {
+    let kv = env.kv("WEBHOOK_CACHE")?;
+    let cache_key = format!("{}{}", JWKS_CACHE_PREFIX, config.issuer);
+
+    if let Some(cached) = kv.get(&cache_key).json::<Jwks>().await? {
+        return Ok((cached, true));
+    }
+    Ok((fetch_jwks(&kv, config).await?, false))
+}
+
+/// Refetch the JWKS past the cache, unless another token forced a refetch
+/// within the last `JWKS_REFRESH_TTL_SECONDS`
+async fn refetch_jwks(env: &Env, config: &OidcConfig) -> Result<Option<Jwks>> {
+    let kv = env.kv("WEBHOOK_CACHE")?;
+    let refresh_key = format!("{}{}", JWKS_REFRESH_PREFIX, config.issuer);
+    if kv.get(&refresh_key).text().await?.is_some() {
+        return Ok(None);
+    }
+    kv.put(&refresh_key, "1")?
+        .expiration_ttl(JWKS_REFRESH_TTL_SECONDS)
+        .execute()
+        .await?;
+    Ok(Some(fetch_jwks(&kv, config).await?))
+}
+
+/// Fetch the issuer's JWKS (via discovery unless `OIDC_JWKS_URL` is set) and cache it
+async fn fetch_jwks(kv: &kv::KvStore, config: &OidcConfig) -> Result<Jwks> {
+    let cache_key = format!("{}{}", JWKS_CACHE_PREFIX, config.issuer);
+    let jwks_url = match &config.jwks_url {
+        Some(url) => url.clone(),
+        None => {
+            let discovery_url = format!("{}/.well-known/openid-configuration", config.issuer);
+            let discovery: Discovery = Fetch::Url(Url::parse(&discovery_url)?).send().await?.json().await?;
+            discovery.jwks_uri
+        }
+    };
+
+    let body = Fetch::Url(Url::parse(&jwks_url)?).send().await?.text().await?;
+    let jwks: Jwks = serde_json::from_str(&body)?;
+
+    kv.put(&cache_key, body)?
+        .expiration_ttl(JWKS_CACHE_TTL_SECONDS)
+        .execute()
+        .await?;
+    log_info!("🔑 Cached JWKS for {}", config.issuer);
+
+    Ok(jwks)
+}
+
+/// Map the token to a user: linked IdP account first, then verified email
+async fn find_user(db: &D1Database, config: &OidcConfig, claims: &Claims) -> Result<Option<String>> {
+    let linked = db
+        .prepare("SELECT user_id FROM account WHERE provider_id = ?1 AND account_id = ?2")
+        .bind(&[JsValue::from_str(&config.provider_id), JsValue::from_str(&claims.sub)])?
+        .first::<UserRow>(None)
+        .await?;
+    if let Some(row) = linked {
+        return Ok(Some(row.user_id));
+    }
+
+    let email_verified = claims.extra.get("email_verified").and_then(Value::as_bool) == Some(true);
+    match &claims.email {
+        Some(email) if email_verified => Ok(db
+            .prepare("SELECT id AS user_id FROM user WHERE email = ?1")
+            .bind(&[JsValue::from_str(email)])?
+            .first::<UserRow>(None)
+            .await?
+            .map(|row| row.user_id)),
+        _ => Ok(None),
+    }
+}
+
+/// Verify a JWS signature with the runtime's WebCrypto implementation
+async fn webcrypto_verify(
+    jwk: &Value,
+    import_algorithm: &Value,
+    verify_algorithm: &Value,
+    signature: &[u8],
+    data: &[u8],
+) -> Result<bool> {
+    let subtle = webcrypto::subtle()?;
+
+    let key = call_async(
+        &subtle,
+        "importKey",
+        &[
+            JsValue::from_str("jwk"),
+            to_js(jwk)?,
+            to_js(import_algorithm)?,
+            JsValue::FALSE,
+            Array::of1(&JsValue::from_str("verify")).into(),
+        ],
+    )
+    .await?;
+
+    let valid = call_async(
+        &subtle,
+        "verify",
+        &[
+            to_js(verify_algorithm)?,
+            key,
+            Uint8Array::from(signature).into(),
+            Uint8Array::from(data).into(),
+        ],
+    )
+    .await?;
+
+    Ok(valid.as_bool().unwrap_or(false))
+}
+
+fn to_js(value: &Value) -> Result<JsValue> {
+    Ok(js_sys::JSON::parse(&value.to_string())?)
+}
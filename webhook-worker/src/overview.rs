@@ -0,0 +1,154 @@
+//! Deployment overview
+//! Totals across every webhook for whoever operates a shared instance
+//! (`GET /api/admin/overview`): how many webhooks there are, today's captures
+//! (UTC) and the busiest webhooks, the error rates (unverified signatures and
+//! failed forwards today, plus the ingestion failure rate when the error budget
+//! is tracked) and the captures stored. Captures are counted in every capture
+//! store, including the jurisdictions' (see `residency.rs`) that are wired up.
+
+use crate::error_budget::{self, Budget};
+use crate::storage::{self, WebhookVolume};
+use crate::webhooks::{self, Webhook};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Webhooks listed in `top_webhooks`
+pub const TOP_WEBHOOKS: usize = 10;
+
+/// One of the busiest webhooks today
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TopWebhook {
+    pub uuid: Option<String>,
+    pub name: Option<String>,
+    pub requests: u64,
+    pub bytes: u64,
+}
+
+/// Shares of today's captures and forwards that went wrong
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ErrorRates {
+    /// Captures whose signature verification was not `valid`
+    pub unverified: u64,
+    pub unverified_rate: f64,
+    pub forward_attempts: u64,
+    pub forward_failures: u64,
+    pub forward_failure_rate: f64,
+    /// Failed share of ingestions over the error budget window (`ERROR_BUDGET_THRESHOLD` set)
+    pub ingestion_failure_rate: Option<f64>,
+}
+
+/// Captures currently stored
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct StorageUse {
+    pub captures: u64,
+    /// Sum of the captures' `size_bytes`
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Overview {
+    pub webhooks: usize,
+    pub requests_today: u64,
+    pub bytes_today: u64,
+    pub top_webhooks: Vec<TopWebhook>,
+    pub error_rates: ErrorRates,
+    pub storage: StorageUse,
+    pub generated_at_ms: i64,
+}
+
+fn rate(part: u64, total: u64) -> f64 {
+    match total {
+        0 => 0.0,
+        total => part as f64 / total as f64,
+    }
+}
+
+impl Overview {
+    /// Put the overview together from today's and all stored volumes and today's forwards
+    /// (attempts, failures)
+    pub fn build(
+        webhooks: &[Webhook],
+        today: &[WebhookVolume],
+        stored: &[WebhookVolume],
+        forwards: (u64, u64),
+        now_ms: i64,
+    ) -> Self {
+        let by_id: HashMap<&str, &Webhook> = webhooks.iter().map(|webhook| (webhook.id.as_str(), webhook)).collect();
+        let requests_today = today.iter().map(|volume| volume.count).sum();
+        let unverified = today.iter().map(|volume| volume.unverified).sum();
+        let (forward_attempts, forward_failures) = forwards;
+        Self {
+            webhooks: webhooks.len(),
+            requests_today,
+            bytes_today: today.iter().map(|volume| volume.bytes).sum(),
+            top_webhooks: today
+                .iter()
+                .take(TOP_WEBHOOKS)
+                .map(|volume| {
+                    // Captures of a deleted webhook linger until retention removes them
+                    let webhook = by_id.get(volume.webhook_id.as_str());
+                    TopWebhook {
+                        uuid: webhook.map(|webhook| webhook.uuid.clone()),
+                        name: webhook.map(|webhook| webhook.name.clone()),
+                        requests: volume.count,
+                        bytes: volume.bytes,
+                    }
+                })
+                .collect(),
+            error_rates: ErrorRates {
+                unverified,
+                unverified_rate: rate(unverified, requests_today),
+                forward_attempts,
+                forward_failures,
+                forward_failure_rate: rate(forward_failures, forward_attempts),
+                ingestion_failure_rate: None,
+            },
+            storage: StorageUse {
+                captures: stored.iter().map(|volume| volume.count).sum(),
+                bytes: stored.iter().map(|volume| volume.bytes).sum(),
+            },
+            generated_at_ms: now_ms,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ForwardRow {
+    count: Option<f64>,
+    failures: Option<f64>,
+}
+
+/// Forwarding attempts and failures of a UTC day (Unix seconds) across all webhooks
+async fn forwards_on(db: &D1Database, day: i64) -> Result<(u64, u64)> {
+    let row = db
+        .prepare("SELECT SUM(count) AS count, SUM(failures) AS failures FROM forward_latency WHERE day = ?1")
+        .bind(&[JsValue::from_f64(day as f64)])?
+        .first::<ForwardRow>(None)
+        .await?;
+    Ok(row.map_or((0, 0), |row| {
+        (row.count.unwrap_or(0.0) as u64, row.failures.unwrap_or(0.0) as u64)
+    }))
+}
+
+/// The overview as of `now_ms`
+pub async fn load(env: &Env, now_ms: i64) -> Result<Overview> {
+    let db = env.d1("DB")?;
+    let webhooks = webhooks::in_project(&db, None).await?;
+    let now = now_ms / 1000;
+    let today = now - now.rem_euclid(86_400);
+
+    let today_volumes = storage::all_webhook_volumes(env, today).await?;
+    let stored_volumes = storage::all_webhook_volumes(env, 0).await?;
+    let forwards = forwards_on(&db, today).await?;
+
+    let mut overview = Overview::build(&webhooks, &today_volumes, &stored_volumes, forwards, now_ms);
+    if let Some(budget) = Budget::from_env(env) {
+        match error_budget::status(env, &budget, now_ms).await {
+            Ok((window, _)) => overview.error_rates.ingestion_failure_rate = Some(window.failure_rate()),
+            Err(e) => log_warn!("⚠️  Failed to read the error budget for the overview: {:?}", e),
+        }
+    }
+    Ok(overview)
+}
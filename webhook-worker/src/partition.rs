@@ -0,0 +1,255 @@
+//! Time-partitioned webhook_data storage
+//! When `DATA_PARTITIONING = "monthly"`, captures are written to monthly tables
+//! (`webhook_data_2025_01`, ...) that mirror the `webhook_data` schema.
+//! The scheduled handler creates upcoming partitions and drops expired ones,
+//! so retention becomes a cheap `DROP TABLE` instead of a large `DELETE`
+//! (legally held rows are copied to `webhook_data` first).
+
+use crate::bodies;
+use crate::legal_hold;
+use chrono::{DateTime, Datelike, NaiveDate};
+use serde::Deserialize;
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Unpartitioned table; always included in reads so pre-partitioning data stays visible
+pub const LEGACY_TABLE: &str = "webhook_data";
+
+/// Partitions older than this many months (before the current one) are dropped
+const DEFAULT_RETENTION_MONTHS: i32 = 1;
+
+#[derive(Deserialize)]
+struct NameRow {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct SqlRow {
+    sql: String,
+}
+
+#[derive(Deserialize)]
+struct IndexRow {
+    sql: String,
+}
+
+#[derive(Deserialize)]
+struct ColumnRow {
+    name: String,
+    #[serde(rename = "type")]
+    column_type: String,
+}
+
+/// Whether monthly partitioning is enabled for this deployment
+pub fn is_enabled(env: &Env) -> bool {
+    env.var("DATA_PARTITIONING")
+        .map(|value| value.to_string() == "monthly")
+        .unwrap_or(false)
+}
+
+/// Months of partitions to keep before the current one
+pub fn retention_months(env: &Env) -> i32 {
+    env.var("PARTITION_RETENTION_MONTHS")
+        .ok()
+        .and_then(|value| value.to_string().parse::<i32>().ok())
+        .unwrap_or(DEFAULT_RETENTION_MONTHS)
+}
+
+/// Table that a capture received at `timestamp` should be written to
+pub fn write_table(partitioning: bool, timestamp: i64) -> String {
+    if partitioning {
+        table_for_month(month_index(timestamp))
+    } else {
+        LEGACY_TABLE.to_string()
+    }
+}
+
+/// Tables to read for an optional `[since, until)` time range, legacy table first
+pub fn read_tables(partitions: &[String], since: Option<i64>, until: Option<i64>) -> Vec<String> {
+    let first = since.map(month_index);
+    let last = until.map(month_index);
+
+    let mut tables = vec![LEGACY_TABLE.to_string()];
+    tables.extend(
+        partitions
+            .iter()
+            .filter(|name| match parse_month_index(name) {
+                Some(month) => first.is_none_or(|f| month >= f) && last.is_none_or(|l| month <= l),
+                None => false,
+            })
+            .cloned(),
+    );
+    tables
+}
+
+/// List existing partition tables, oldest first
+pub async fn list(db: &D1Database) -> Result<Vec<String>> {
+    let result = db
+        .prepare(
+            "SELECT name FROM sqlite_master WHERE type = 'table' \
+             AND name GLOB 'webhook_data_[0-9][0-9][0-9][0-9]_[0-9][0-9]' ORDER BY name",
+        )
+        .all()
+        .await?;
+    Ok(result
+        .results::<NameRow>()?
+        .into_iter()
+        .map(|row| row.name)
+        .collect())
+}
+
+/// Create a partition table if it doesn't exist yet, and bring its columns and
+/// indexes up to date with webhook_data
+pub async fn ensure(db: &D1Database, table: &str) -> Result<()> {
+    if parse_month_index(table).is_none() {
+        return Err(Error::RustError(format!("Invalid partition name: {}", table)));
+    }
+
+    // Mirror the current webhook_data definition so partitions pick up schema changes
+    let legacy = db
+        .prepare("SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?1")
+        .bind(&[JsValue::from_str(LEGACY_TABLE)])?
+        .first::<SqlRow>(None)
+        .await?
+        .ok_or_else(|| Error::RustError("webhook_data table is missing".to_string()))?;
+
+    let create_sql = legacy.sql.replacen(
+        &format!("CREATE TABLE {}", LEGACY_TABLE),
+        &format!("CREATE TABLE IF NOT EXISTS {}", table),
+        1,
+    );
+    db.prepare(create_sql).run().await?;
+    db.prepare(format!(
+        "CREATE INDEX IF NOT EXISTS {0}_webhook_received_idx ON {0}(webhook_id, received_at)",
+        table
+    ))
+    .run()
+    .await?;
+
+    // An existing partition may predate later migrations
+    sync_columns(db, table).await?;
+    let indexes = db
+        .prepare("SELECT sql FROM sqlite_master WHERE type = 'index' AND tbl_name = ?1 AND sql IS NOT NULL")
+        .bind(&[JsValue::from_str(LEGACY_TABLE)])?
+        .all()
+        .await?
+        .results::<IndexRow>()?;
+    for index in indexes {
+        if let Some(sql) = mirror_index(&index.sql, table) {
+            db.prepare(sql).run().await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// `CREATE INDEX` statement giving a partition the same index as a webhook_data
+/// one; None for definitions that don't follow the `webhook_data_*` naming
+pub fn mirror_index(sql: &str, table: &str) -> Option<String> {
+    let (head, columns) = sql.split_once(&format!(" ON {}(", LEGACY_TABLE))?;
+    let suffix = head.rsplit(' ').next()?.strip_prefix("webhook_data_")?;
+    let unique = if head.to_ascii_uppercase().contains("UNIQUE") { "UNIQUE " } else { "" };
+    Some(format!("CREATE {}INDEX IF NOT EXISTS {}_{} ON {}({}", unique, table, suffix, table, columns))
+}
+
+/// Add any columns that later migrations added to webhook_data but the partition lacks
+async fn sync_columns(db: &D1Database, table: &str) -> Result<()> {
+    let legacy_columns = columns(db, LEGACY_TABLE).await?;
+    let partition_columns = columns(db, table).await?;
+
+    for column in legacy_columns {
+        if !partition_columns.iter().any(|c| c.name == column.name) {
+            log_info!("🧱 Adding column {} to partition {}", column.name, table);
+            db.prepare(format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                table, column.name, column.column_type
+            ))
+            .run()
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn columns(db: &D1Database, table: &str) -> Result<Vec<ColumnRow>> {
+    db.prepare("SELECT name, type FROM pragma_table_info(?1)")
+        .bind(&[JsValue::from_str(table)])?
+        .all()
+        .await?
+        .results::<ColumnRow>()
+}
+
+/// Scheduled maintenance: create current/next partitions, sync schemas, drop expired ones
+pub async fn rollover(db: &D1Database, now: i64, retention_months: i32) -> Result<()> {
+    let current = month_index(now);
+
+    for month in [current, current + 1] {
+        ensure(db, &table_for_month(month)).await?;
+    }
+
+    let oldest_kept = current - retention_months;
+
+    for table in list(db).await? {
+        match parse_month_index(&table) {
+            Some(month) if month < oldest_kept => {
+                preserve_held(db, &table).await?;
+                bodies::release_table(db, &table).await?;
+                log_info!("🗑️ Dropping expired partition {}", table);
+                db.prepare(format!("DROP TABLE IF EXISTS {}", table)).run().await?;
+            }
+            Some(_) => sync_columns(db, &table).await?,
+            None => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy legally held rows of a partition about to be dropped into the legacy table
+async fn preserve_held(db: &D1Database, table: &str) -> Result<()> {
+    let columns = columns(db, table)
+        .await?
+        .into_iter()
+        .map(|column| column.name)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let result = db
+        .prepare(format!(
+            "INSERT OR IGNORE INTO {legacy} ({columns}) SELECT {columns} FROM {table} WHERE {held}",
+            legacy = LEGACY_TABLE,
+            columns = columns,
+            table = table,
+            held = legal_hold::HELD_ROWS_CLAUSE
+        ))
+        .run()
+        .await?;
+    let kept = result.meta()?.and_then(|meta| meta.changes).unwrap_or(0);
+    if kept > 0 {
+        log_info!("⚖️  Kept {} legally held captures of {} in {}", kept, table, LEGACY_TABLE);
+    }
+    Ok(())
+}
+
+/// Months since year 0 for a Unix timestamp (seconds)
+fn month_index(timestamp: i64) -> i32 {
+    let date = DateTime::from_timestamp(timestamp, 0).unwrap_or_default();
+    date.year() * 12 + date.month0() as i32
+}
+
+fn table_for_month(month: i32) -> String {
+    format!("{}_{:04}_{:02}", LEGACY_TABLE, month / 12, month % 12 + 1)
+}
+
+/// Parse `webhook_data_YYYY_MM` back into a month index
+fn parse_month_index(table: &str) -> Option<i32> {
+    let suffix = table.strip_prefix("webhook_data_")?;
+    let (year, month) = suffix.split_once('_')?;
+    if year.len() != 4 || month.len() != 2 {
+        return None;
+    }
+    let year: i32 = year.parse().ok()?;
+    let month: u32 = month.parse().ok()?;
+    NaiveDate::from_ymd_opt(year, month, 1)?;
+    Some(year * 12 + month as i32 - 1)
+}
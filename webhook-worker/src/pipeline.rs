@@ -0,0 +1,439 @@
+//! Ingestion pipeline core
+//! Everything ingestion decides about a delivery that doesn't need the Workers
+//! runtime: parsing an `IncomingRequest`, applying the webhook's settings
+//! (environment, extraction rules, hook script, event route), signed URL and signature
+//! checks, and building the `CaptureRecord` and success body. `ingest.rs` is
+//! the shim that reads the request, performs the lookups and I/O, and turns
+//! `Rejection`s into responses.
+
+use crate::canonical;
+use crate::config::{CustomResponse, EventRoute, ResponseRule, WebhookSettings};
+use crate::environments::Environment;
+use crate::event_time;
+use crate::github::GithubFields;
+use crate::headers::{self, HeaderLimits, IndexedHeaders, LimitExceeded};
+use crate::script::{self, Script};
+use crate::signature::Verification;
+use crate::signed_url::{self, SignedUrlError};
+use crate::slack::{self, SlackRequest};
+use crate::storage::CaptureRecord;
+use crate::trailers;
+use std::collections::HashMap;
+use worker::Url;
+
+/// A delivery as received, independent of the runtime
+pub struct IncomingRequest {
+    pub method: String,
+    pub url: Url,
+    /// Header names lowercased (see `sanitize_headers`)
+    pub headers: HashMap<String, String>,
+    /// Request body (None for methods without one, or when it couldn't be read)
+    pub body: Option<String>,
+    pub received_at_ms: i64,
+}
+
+/// A delivery parsed into the values that get stored
+#[derive(Debug, Clone)]
+pub struct ParsedRequest {
+    pub method: String,
+    pub headers: HashMap<String, String>,
+    pub headers_json: String,
+    /// Body, or the query parameters as JSON for methods without a body
+    pub data: String,
+    pub size_bytes: i32,
+    /// Unix seconds (legacy column)
+    pub received_at: i64,
+    pub received_at_ms: i64,
+    pub event_time: Option<i64>,
+    pub indexed_headers: IndexedHeaders,
+    /// Trailing metadata as a JSON object
+    pub trailers: Option<String>,
+    /// Capture socket the message arrived on (WebSocket ingestion only)
+    pub connection_id: Option<String>,
+    pub frame_type: Option<FrameType>,
+}
+
+/// Method stored for messages captured over a WebSocket
+pub const SOCKET_METHOD: &str = "WS";
+
+/// Method stored for MQTT PUBLISH messages
+pub const MQTT_METHOD: &str = "MQTT";
+
+/// WebSocket data frame kinds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    Text,
+    Binary,
+}
+
+impl FrameType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::Binary => "binary",
+        }
+    }
+}
+
+/// A message received on a capture WebSocket
+pub struct IncomingFrame {
+    pub connection_id: String,
+    /// `SOCKET_METHOD` or `MQTT_METHOD`
+    pub method: String,
+    /// Handshake headers (see `sanitize_headers`)
+    pub headers: HashMap<String, String>,
+    pub frame_type: FrameType,
+    /// Text frames verbatim, binary frames base64-encoded
+    pub data: String,
+    pub received_at_ms: i64,
+}
+
+/// Why a delivery is refused, as an HTTP status and message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rejection {
+    pub status: u16,
+    pub message: &'static str,
+}
+
+impl Rejection {
+    const fn new(status: u16, message: &'static str) -> Self {
+        Self { status, message }
+    }
+}
+
+/// Bound and sanitize raw request headers; oversized header sets are refused with 431
+pub fn sanitize_headers<I>(raw: I, limits: &HeaderLimits) -> Result<HashMap<String, String>, Rejection>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    headers::sanitize(raw, limits).map_err(|exceeded| match exceeded {
+        LimitExceeded::Count => Rejection::new(431, "Too many request headers"),
+        LimitExceeded::Bytes => Rejection::new(431, "Request header fields too large"),
+    })
+}
+
+/// Method stored for inbound email (see `email.rs`)
+pub const EMAIL_METHOD: &str = "EMAIL";
+
+/// Whether the method carries a stored body (others store their query string)
+pub fn has_body(method: &str) -> bool {
+    matches!(method, "POST" | "PUT" | "PATCH" | EMAIL_METHOD)
+}
+
+/// Parse a delivery; query strings of body-less methods are stored without signed URL parameters
+pub fn parse(request: &IncomingRequest) -> serde_json::Result<ParsedRequest> {
+    let data = if has_body(&request.method) {
+        request.body.clone().unwrap_or_else(|| "{}".to_string())
+    } else {
+        let url = &request.url;
+        let signed = url.query_pairs().any(|(k, _)| k == signed_url::SIG_PARAM);
+        let query_params: HashMap<String, String> = url
+            .query_pairs()
+            .filter(|(k, _)| !signed || (k != signed_url::EXP_PARAM && k != signed_url::SIG_PARAM))
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        serde_json::to_string(&query_params)?
+    };
+
+    let mut indexed_headers = IndexedHeaders::extract(&request.headers);
+    if indexed_headers.event_type.is_none() {
+        let slack = SlackRequest::parse(indexed_headers.content_type.as_deref(), &data);
+        indexed_headers.event_type = slack.and_then(|slack| slack.event_type());
+    }
+
+    Ok(ParsedRequest {
+        method: request.method.clone(),
+        headers_json: serde_json::to_string(&request.headers)?,
+        size_bytes: data.len() as i32,
+        received_at: request.received_at_ms / 1000,
+        received_at_ms: request.received_at_ms,
+        event_time: event_time::extract(&request.headers),
+        indexed_headers,
+        trailers: trailers::extract(&request.headers, &data)
+            .map(|trailers| serde_json::to_string(&trailers))
+            .transpose()?,
+        headers: request.headers.clone(),
+        data,
+        connection_id: None,
+        frame_type: None,
+    })
+}
+
+/// Parse a WebSocket message; the handshake headers stand in for request headers
+pub fn parse_frame(frame: &IncomingFrame) -> serde_json::Result<ParsedRequest> {
+    Ok(ParsedRequest {
+        method: frame.method.clone(),
+        headers_json: serde_json::to_string(&frame.headers)?,
+        size_bytes: frame.data.len() as i32,
+        received_at: frame.received_at_ms / 1000,
+        received_at_ms: frame.received_at_ms,
+        event_time: event_time::extract(&frame.headers),
+        indexed_headers: IndexedHeaders::extract(&frame.headers),
+        trailers: None,
+        headers: frame.headers.clone(),
+        data: frame.data.clone(),
+        connection_id: Some(frame.connection_id.clone()),
+        frame_type: Some(frame.frame_type),
+    })
+}
+
+/// How a webhook's settings apply to one delivery
+pub struct Applied<'a> {
+    /// Environment the capture UUID belongs to
+    pub environment: Option<&'a Environment>,
+    /// First config route matching the event type
+    pub route: Option<&'a EventRoute>,
+    /// Decisions of the webhook's hook script
+    pub script: script::Outcome,
+    /// First response rule matching the delivery
+    pub response_rule: Option<&'a ResponseRule>,
+    /// Response rules' default, for deliveries no rule matches
+    pub fallback_response: Option<&'a CustomResponse>,
+}
+
+impl Applied<'_> {
+    /// Forwarding targets: the environment's, then the route's, then the script's
+    /// (deduplicated); `forward none` in the script drops the first two
+    pub fn forward_targets(&self) -> Vec<&str> {
+        let mut targets: Vec<&str> = Vec::new();
+        let forward_urls = [
+            self.environment.and_then(|environment| environment.forward_url.as_deref()),
+            self.route.and_then(|route| route.forward_url.as_deref()),
+        ];
+        let configured = forward_urls
+            .into_iter()
+            .flatten()
+            .filter(|_| !self.script.clear_forwarding);
+        for target in configured.chain(self.script.forward.iter().map(String::as_str)) {
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+        targets
+    }
+
+    /// Response for the sender: the script's, else the matching response rule's, else the route's
+    pub fn response(&self) -> Option<&CustomResponse> {
+        self.script
+            .response
+            .as_ref()
+            .or_else(|| self.response_rule.map(|rule| &rule.response))
+            .or_else(|| self.route.and_then(|route| route.response.as_ref()))
+    }
+}
+
+/// Apply per-webhook extraction rules (they override the well-known headers),
+/// then pick the environment and event route
+pub fn apply<'a>(parsed: &mut ParsedRequest, uuid: &str, settings: &'a WebhookSettings) -> Applied<'a> {
+    let config = &settings.config;
+    if let Some(source) = &config.event_type {
+        parsed.indexed_headers.event_type = source.extract(&parsed.headers, &parsed.data);
+    }
+    if let Some(source) = &config.idempotency_key {
+        parsed.indexed_headers.idempotency_key = source.extract(&parsed.headers, &parsed.data);
+    }
+    if let Some(source) = &config.correlation_id {
+        parsed.indexed_headers.correlation_id = source.extract(&parsed.headers, &parsed.data);
+    }
+    // Scripts are validated on save; one written around the API that doesn't parse is skipped
+    let script = match config.script.as_deref().and_then(Script::cached) {
+        Some(script) => script.run(parsed),
+        None => script::Outcome::default(),
+    };
+
+    let event_type = parsed.indexed_headers.event_type.as_deref();
+    let rules = config.response_rules.as_ref();
+    Applied {
+        environment: settings.environments.iter().find(|environment| environment.uuid == uuid),
+        route: config.route_for(event_type),
+        script,
+        response_rule: rules.and_then(|rules| {
+            rules
+                .rules
+                .iter()
+                .find(|rule| rule.matches(&parsed.method, event_type, &parsed.headers, &parsed.data))
+        }),
+        fallback_response: rules.and_then(|rules| rules.default.as_ref()),
+    }
+}
+
+/// Reject captures whose signed URL is invalid or expired, or that are unsigned
+/// while the webhook requires signed URLs
+pub fn check_signed_url(url: &Url, uuid: &str, settings: &WebhookSettings, now: i64) -> Option<Rejection> {
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.to_string())
+    };
+    let exp = param(signed_url::EXP_PARAM);
+    let sig = param(signed_url::SIG_PARAM);
+
+    if sig.is_none() {
+        return settings
+            .config
+            .require_signed_urls
+            .then_some(Rejection::new(403, "Signed URL required"));
+    }
+
+    signed_url_rejection(match &settings.secret {
+        Some(secret) => signed_url::verify(secret, uuid, exp.as_deref(), sig.as_deref(), now),
+        None => Err(SignedUrlError::BadSignature),
+    })
+}
+
+/// Reject uploads whose URL isn't signed for this file name or has expired;
+/// unlike capture URLs, upload URLs are always signed
+pub fn check_upload_url(url: &Url, uuid: &str, filename: &str, settings: &WebhookSettings, now: i64) -> Option<Rejection> {
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.to_string())
+    };
+    let exp = param(signed_url::EXP_PARAM);
+    let sig = param(signed_url::SIG_PARAM);
+
+    if sig.is_none() {
+        return Some(Rejection::new(403, "Signed URL required"));
+    }
+    signed_url_rejection(match &settings.secret {
+        Some(secret) => signed_url::verify_upload(secret, uuid, filename, exp.as_deref(), sig.as_deref(), now),
+        None => Err(SignedUrlError::BadSignature),
+    })
+}
+
+fn signed_url_rejection(result: Result<(), SignedUrlError>) -> Option<Rejection> {
+    match result {
+        Ok(()) => None,
+        Err(SignedUrlError::Expired) => Some(Rejection::new(410, "Signed URL expired")),
+        Err(SignedUrlError::Malformed) => Some(Rejection::new(400, "Malformed signed URL")),
+        Err(SignedUrlError::BadSignature) => Some(Rejection::new(403, "Invalid URL signature")),
+    }
+}
+
+/// Pseudo headers stored with file-drop uploads: the R2 object key, file name and size
+pub const UPLOAD_KEY_HEADER: &str = "upload-key";
+pub const UPLOAD_FILENAME_HEADER: &str = "upload-filename";
+pub const UPLOAD_SIZE_HEADER: &str = "upload-size";
+
+/// UTF-8 uploads up to this size are also stored inline as the capture body
+pub const UPLOAD_INLINE_BYTES: usize = 1024 * 1024;
+
+const MAX_UPLOAD_FILENAME: usize = 200;
+
+/// Whether an upload file name is usable as a URL segment and R2 key suffix:
+/// letters, digits, `.`, `_` and `-`, not starting with a dot
+pub fn valid_upload_filename(filename: &str) -> bool {
+    !filename.is_empty()
+        && filename.len() <= MAX_UPLOAD_FILENAME
+        && !filename.starts_with('.')
+        && filename
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'.' | b'_' | b'-'))
+}
+
+/// A file stored from a signed upload URL, as the PUT delivery it gets captured as
+pub fn upload_request(
+    url: Url,
+    mut headers: HashMap<String, String>,
+    filename: &str,
+    key: &str,
+    file: &[u8],
+    received_at_ms: i64,
+) -> IncomingRequest {
+    headers.insert(UPLOAD_KEY_HEADER.to_string(), key.to_string());
+    headers.insert(UPLOAD_FILENAME_HEADER.to_string(), filename.to_string());
+    headers.insert(UPLOAD_SIZE_HEADER.to_string(), file.len().to_string());
+    let inline = (file.len() <= UPLOAD_INLINE_BYTES)
+        .then(|| std::str::from_utf8(file).ok())
+        .flatten();
+    IncomingRequest {
+        method: "PUT".to_string(),
+        url,
+        headers,
+        body: Some(inline.unwrap_or_default().to_string()),
+        received_at_ms,
+    }
+}
+
+/// Rejection for a failed signature check, when the webhook enforces signatures
+pub fn signature_rejection(settings: &WebhookSettings, verification: Option<Verification>) -> Option<Rejection> {
+    if !settings.config.signature.as_ref().is_some_and(|config| config.enforce) {
+        return None;
+    }
+    match verification? {
+        Verification::Valid => None,
+        Verification::ReplaySuspected => Some(Rejection::new(400, "Timestamp outside tolerance window")),
+        Verification::Missing | Verification::Invalid => Some(Rejection::new(401, "Invalid signature")),
+    }
+}
+
+/// Targets a delivery is forwarded to; none when its enforced signature check failed
+pub fn forward_targets<'a>(
+    applied: &'a Applied<'_>,
+    settings: &WebhookSettings,
+    verification: Option<Verification>,
+) -> Vec<&'a str> {
+    match signature_rejection(settings, verification) {
+        Some(_) => Vec::new(),
+        None => applied.forward_targets(),
+    }
+}
+
+/// Identity and outcome fields assigned while capturing
+pub struct CaptureMeta<'a> {
+    pub id: String,
+    pub webhook_id: String,
+    pub sequence: Option<i64>,
+    pub verification: Option<Verification>,
+    pub environment: Option<&'a Environment>,
+}
+
+/// The record to persist
+pub fn into_record(parsed: ParsedRequest, meta: CaptureMeta<'_>) -> CaptureRecord {
+    CaptureRecord {
+        id: meta.id,
+        webhook_id: meta.webhook_id,
+        method: parsed.method,
+        headers_json: parsed.headers_json,
+        canonical_data: canonical::canonicalize(&parsed.data)
+            .or_else(|| slack::canonical(parsed.indexed_headers.content_type.as_deref(), &parsed.data)),
+        github: GithubFields::extract(&parsed.headers, &parsed.data),
+        data: parsed.data,
+        size_bytes: parsed.size_bytes,
+        received_at: parsed.received_at,
+        received_at_ms: parsed.received_at_ms,
+        event_time: parsed.event_time,
+        sequence: meta.sequence,
+        indexed_headers: parsed.indexed_headers,
+        verification: meta.verification.map(|verification| verification.as_str().to_string()),
+        environment: meta.environment.map(|environment| environment.name.clone()),
+        trailers: parsed.trailers,
+        connection_id: parsed.connection_id,
+        frame_type: parsed.frame_type.map(|frame_type| frame_type.as_str().to_string()),
+        processing: None,
+        preview: None,
+        charset: None,
+        original_body: None,
+        stripe_cross_check: None,
+        oauth_exchange: None,
+        replay_of: None,
+        storage_form: false,
+    }
+}
+
+/// JSON body of the default success response
+pub fn success_body(uuid: &str, record: &CaptureRecord) -> serde_json::Value {
+    serde_json::json!({
+        "success": true,
+        "message": "Webhook received",
+        "webhook_id": uuid,
+        "data_id": record.id,
+        "method": record.method,
+        "received_at": record.received_at,
+        "received_at_ms": record.received_at_ms,
+        "event_time": record.event_time,
+        "sequence": record.sequence,
+        "size_bytes": record.size_bytes,
+        "verification": record.verification,
+    })
+}
@@ -0,0 +1,21 @@
+//! `{{name}}` placeholders in response templates (TwiML, Slack messages)
+
+use std::collections::HashMap;
+
+/// `template` with each `{{name}}` replaced by `escape` of its value (empty when absent);
+/// an unterminated `{{` is kept as is
+pub fn fill(template: &str, values: &HashMap<String, String>, escape: fn(&str) -> String) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        filled.push_str(&rest[..start]);
+        let name = rest[start + 2..start + 2 + end].trim();
+        filled.push_str(&escape(values.get(name).map(String::as_str).unwrap_or("")));
+        rest = &rest[start + 2 + end + 2..];
+    }
+    filled.push_str(rest);
+    filled
+}
@@ -0,0 +1,209 @@
+//! Previews of non-text payloads
+//! Binary captures (images, PDFs, archives, binary WebSocket frames, uploaded
+//! files) get a small JSON summary at ingest, stored in the `preview` column:
+//! the MIME type sniffed from magic bytes next to the declared one, image
+//! dimensions, the PDF version, a SHA-256 and a hexdump of the first bytes.
+//! That is enough for the API or dashboard to show something useful without
+//! fetching the whole blob. Text payloads (valid UTF-8 without a binary magic
+//! number) get no preview.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Bytes shown in the hexdump
+pub const HEXDUMP_BYTES: usize = 64;
+
+/// Summary of a binary payload
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Preview {
+    /// Type recognized from the leading bytes
+    pub sniffed_type: Option<&'static str>,
+    /// `Content-Type` the sender declared, without parameters
+    pub declared_type: Option<String>,
+    pub size_bytes: usize,
+    pub sha256: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pdf_version: Option<String>,
+    /// `xxd`-style lines for the first `HEXDUMP_BYTES` bytes
+    pub hexdump: String,
+}
+
+impl Preview {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Preview for a payload, or None when it is text
+pub fn generate(bytes: &[u8], declared_type: Option<&str>) -> Option<Preview> {
+    let sniffed_type = sniff(bytes);
+    if sniffed_type.is_none() && std::str::from_utf8(bytes).is_ok() {
+        return None;
+    }
+    let (width, height) = match dimensions(sniffed_type, bytes) {
+        Some((width, height)) => (Some(width), Some(height)),
+        None => (None, None),
+    };
+    Some(Preview {
+        sniffed_type,
+        declared_type: declared_type
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_ascii_lowercase())
+            .filter(|value| !value.is_empty()),
+        size_bytes: bytes.len(),
+        sha256: Sha256::digest(bytes).iter().map(|byte| format!("{:02x}", byte)).collect(),
+        width,
+        height,
+        pdf_version: (sniffed_type == Some("application/pdf")).then(|| pdf_version(bytes)).flatten(),
+        hexdump: hexdump(&bytes[..bytes.len().min(HEXDUMP_BYTES)]),
+    })
+}
+
+/// MIME type from well-known magic numbers
+pub fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"BM", "image/bmp"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"\0asm", "application/wasm"),
+        (b"OggS", "audio/ogg"),
+        (b"ID3", "audio/mpeg"),
+        (b"\x1a\x45\xdf\xa3", "video/webm"),
+    ];
+    if let Some((_, mime)) = SIGNATURES.iter().find(|(magic, _)| bytes.starts_with(magic)) {
+        // "BM" alone is too weak; require the BMP header size to match
+        if *mime == "image/bmp" && !(bytes.len() >= 26 && u32_le(bytes, 2).is_some_and(|size| size as usize >= 26)) {
+            return None;
+        }
+        return Some(mime);
+    }
+    if bytes.len() >= 12 && &bytes[..4] == b"RIFF" {
+        return match &bytes[8..12] {
+            b"WEBP" => Some("image/webp"),
+            b"WAVE" => Some("audio/wav"),
+            _ => None,
+        };
+    }
+    if bytes.len() >= 12 && &bytes[4..8] == b"ftyp" {
+        return Some(match &bytes[8..12] {
+            b"avif" | b"avis" => "image/avif",
+            b"heic" | b"heix" | b"mif1" => "image/heic",
+            _ => "video/mp4",
+        });
+    }
+    None
+}
+
+fn u16_be(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32)
+}
+
+fn u16_le(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32)
+}
+
+fn u32_be(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u32_le(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u24_le(bytes: &[u8], at: usize) -> Option<u32> {
+    let slice = bytes.get(at..at + 3)?;
+    Some(slice[0] as u32 | (slice[1] as u32) << 8 | (slice[2] as u32) << 16)
+}
+
+/// Pixel dimensions of the image formats whose headers carry them up front
+pub fn dimensions(mime: Option<&str>, bytes: &[u8]) -> Option<(u32, u32)> {
+    match mime? {
+        "image/png" => Some((u32_be(bytes, 16)?, u32_be(bytes, 20)?)),
+        "image/gif" => Some((u16_le(bytes, 6)?, u16_le(bytes, 8)?)),
+        "image/bmp" => {
+            let height = u32_le(bytes, 22)? as i32;
+            Some((u32_le(bytes, 18)?, height.unsigned_abs()))
+        }
+        "image/jpeg" => jpeg_dimensions(bytes),
+        "image/webp" => match bytes.get(12..16)? {
+            b"VP8X" => Some((u24_le(bytes, 24)? + 1, u24_le(bytes, 27)? + 1)),
+            b"VP8 " => Some((u16_le(bytes, 26)? & 0x3fff, u16_le(bytes, 28)? & 0x3fff)),
+            b"VP8L" => {
+                let bits = u32_le(bytes, 21)?;
+                Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Walk JPEG segments to the first start-of-frame marker
+fn jpeg_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let mut at = 2;
+    while at + 4 <= bytes.len() {
+        if bytes[at] != 0xff {
+            return None;
+        }
+        let marker = bytes[at + 1];
+        // Fill bytes and standalone markers carry no length
+        if marker == 0xff {
+            at += 1;
+            continue;
+        }
+        if matches!(marker, 0x01 | 0xd0..=0xd7) {
+            at += 2;
+            continue;
+        }
+        let length = u16_be(bytes, at + 2)? as usize;
+        let start_of_frame = matches!(marker, 0xc0..=0xcf) && !matches!(marker, 0xc4 | 0xc8 | 0xcc);
+        if start_of_frame {
+            return Some((u16_be(bytes, at + 7)?, u16_be(bytes, at + 5)?));
+        }
+        if length < 2 {
+            return None;
+        }
+        at += 2 + length;
+    }
+    None
+}
+
+/// `1.7` from a `%PDF-1.7` header
+fn pdf_version(bytes: &[u8]) -> Option<String> {
+    let version: String = bytes
+        .get(5..bytes.len().min(12))?
+        .iter()
+        .take_while(|byte| byte.is_ascii_digit() || **byte == b'.')
+        .map(|byte| *byte as char)
+        .collect();
+    (!version.is_empty()).then_some(version)
+}
+
+/// `00000000: 8950 4e47 0d0a 1a0a  .PNG....` lines of 16 bytes
+pub fn hexdump(bytes: &[u8]) -> String {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let hex: Vec<String> = chunk
+                .chunks(2)
+                .map(|pair| pair.iter().map(|byte| format!("{:02x}", byte)).collect())
+                .collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' })
+                .collect();
+            format!("{:08x}: {:<39}  {}", index * 16, hex.join(" "), ascii)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,138 @@
+//! Per-capture processing trail
+//! What the pipeline did with one delivery, stored as JSON in the `processing`
+//! column and returned by the request detail API, so "why was this forwarded /
+//! relabelled / answered like that" can be answered from the capture alone:
+//! the environment and signed URL check, signature verification, where the
+//! event type and dedup key came from, what the hook script changed (header
+//! removals are the redactions), the route that matched, the forwarding
+//! targets and which response the sender got.
+
+use crate::config::{FieldSource, WebhookSettings};
+use crate::heartbeat;
+use crate::pipeline::{self, Applied};
+use crate::script;
+use crate::signature::Verification;
+use crate::signed_url;
+use crate::split;
+use serde::Serialize;
+use worker::Url;
+
+/// How the signed URL check went for a stored capture (rejected ones aren't stored)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignedUrl {
+    /// `exp` / `sig` present and valid
+    Verified,
+    /// Unsigned, and the webhook doesn't require signed URLs
+    NotRequired,
+    /// Checked once, when the capture socket connected
+    Connection,
+    /// Chained from another webhook inside the worker (see `chain.rs`)
+    Internal,
+}
+
+impl SignedUrl {
+    /// Outcome for a URL that passed `pipeline::check_signed_url`
+    pub fn checked(url: &Url) -> Self {
+        if url.query_pairs().any(|(key, _)| key == signed_url::SIG_PARAM) {
+            Self::Verified
+        } else {
+            Self::NotRequired
+        }
+    }
+}
+
+/// Where an extracted value came from: `header:NAME`, `body:PATH` or `default`
+fn source(source: Option<&FieldSource>) -> String {
+    match source {
+        Some(FieldSource::Header(name)) => format!("header:{}", name),
+        Some(FieldSource::Body(path)) => format!("body:{}", path),
+        None => "default".to_string(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Extraction {
+    pub event_type: String,
+    pub idempotency_key: String,
+}
+
+/// The stored trail
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Processing {
+    pub environment: Option<String>,
+    /// Null for deliveries without a capture URL (email)
+    pub signed_url: Option<SignedUrl>,
+    /// Verification outcome, null when the webhook doesn't verify signatures
+    pub signature: Option<&'static str>,
+    pub extraction: Extraction,
+    /// Null when the webhook has no script
+    pub script: Option<script::Trail>,
+    /// Event type pattern of the matched route
+    pub route: Option<String>,
+    pub forwards: Vec<String>,
+    /// `console`, `script`, `rule`, `route`, `twiml`, `slack`, `scim`, `oidc_logout`, `rule_default` or `default`
+    pub response: &'static str,
+    /// A/B forwarding arm (see `split.rs`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub split: Option<split::Arm>,
+    /// Shadow target the delivery is mirrored to after the answer (see `split.rs`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<String>,
+    /// Delay drawn from the webhook's latency profile before answering
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub simulated_latency_ms: Option<i64>,
+    /// Repeat of an identical delivery at a steady interval (see `heartbeat.rs`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heartbeat: Option<heartbeat::Trail>,
+    /// Forwarding held by an open debug console (see `console.rs`)
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub paused: bool,
+}
+
+impl Processing {
+    pub fn new(
+        applied: &Applied<'_>,
+        settings: &WebhookSettings,
+        signed_url: Option<SignedUrl>,
+        verification: Option<Verification>,
+    ) -> Self {
+        let config = &settings.config;
+        let response = if applied.script.response.is_some() {
+            "script"
+        } else if applied.response_rule.is_some() {
+            "rule"
+        } else if applied.response().is_some() {
+            "route"
+        } else if applied.fallback_response.is_some() {
+            "rule_default"
+        } else {
+            "default"
+        };
+        Self {
+            environment: applied.environment.map(|environment| environment.name.clone()),
+            signed_url,
+            signature: verification.map(|verification| verification.as_str()),
+            extraction: Extraction {
+                event_type: source(config.event_type.as_ref()),
+                idempotency_key: source(config.idempotency_key.as_ref()),
+            },
+            script: config.script.as_ref().map(|_| applied.script.trail.clone()),
+            route: applied.route.map(|route| route.event_type.clone()),
+            forwards: pipeline::forward_targets(applied, settings, verification)
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            response,
+            split: None,
+            shadow: None,
+            simulated_latency_ms: None,
+            heartbeat: None,
+            paused: false,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
@@ -0,0 +1,232 @@
+//! Edit-and-resend of stored captures
+//! `POST /api/webhooks/{uuid}/requests/{id}/replay` takes a capture, applies
+//! an override object (method, headers to set or remove, a replacement body
+//! or a JSON merge patch of it, and the target) and forwards the result. The
+//! variant is stored as a new capture of the webhook with `replay_of` naming
+//! the original, together with what the target answered, so a payload can be
+//! tweaked and resent over and over while debugging a consumer and every
+//! attempt stays listed under the capture it came from (`replay_of={id}`).
+//! Overrides used again and again can be saved as `replay_recipes` in the
+//! webhook config, together with whether to sign and how far apart to send,
+//! and run by name on one or more captures with
+//! `POST /api/webhooks/{uuid}/replay/{recipe}`.
+
+use crate::api::webhooks::merge;
+use crate::config::{self, ForwardSigning, ReplayRecipe};
+use crate::environments;
+use crate::forward;
+use crate::ids;
+use crate::pipeline::{self, CaptureMeta, IncomingRequest};
+use crate::responses;
+use crate::storage::{Storage, StoredRequest};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use worker::*;
+
+/// Recipes per webhook
+pub const MAX_RECIPES: usize = 20;
+
+/// Captures one recipe run resends
+pub const MAX_RUN_CAPTURES: usize = 20;
+
+/// Longest wait between the resends of a run
+pub const MAX_PACING_MS: u32 = 10_000;
+
+/// Changes to make before resending; anything left out is sent as captured
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Overrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    /// Headers to set; a null value removes the header
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, Option<String>>,
+    /// Replacement body
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// JSON merge patch (RFC 7386) applied to a JSON body
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json: Option<Value>,
+    /// Where to send the variant (default: the original's first forwarding target)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+/// The delivery to resend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant {
+    pub method: String,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+    pub target: String,
+}
+
+/// First forwarding target of the original that is an external URL, from its processing trail
+pub fn default_target(original: &StoredRequest) -> Option<String> {
+    let processing: Value = serde_json::from_str(original.processing.as_deref()?).ok()?;
+    processing
+        .get("forwards")?
+        .as_array()?
+        .iter()
+        .filter_map(Value::as_str)
+        .find(|target| environments::is_valid_forward_url(target))
+        .map(str::to_string)
+}
+
+/// The original with the overrides applied, or what makes them unusable
+pub fn apply(original: &StoredRequest, overrides: &Overrides) -> Result<Variant, String> {
+    if overrides.body.is_some() && overrides.json.is_some() {
+        return Err("Pass either body or json, not both".to_string());
+    }
+    let target = match overrides.target.clone().or_else(|| default_target(original)) {
+        Some(target) if environments::is_valid_forward_url(&target) => target,
+        Some(_) => return Err("target must be an http(s) URL".to_string()),
+        None => return Err("The capture was not forwarded anywhere; pass a target".to_string()),
+    };
+    let method = overrides
+        .method
+        .as_deref()
+        .unwrap_or(&original.method)
+        .to_ascii_uppercase();
+    if method.is_empty() || !method.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(format!("Invalid method {:?}", method));
+    }
+
+    let mut headers: HashMap<String, String> = serde_json::from_str(&original.headers).unwrap_or_default();
+    for (name, value) in &overrides.headers {
+        let name = name.to_ascii_lowercase();
+        match value {
+            Some(value) => headers.insert(name, value.clone()),
+            None => headers.remove(&name),
+        };
+    }
+
+    let body = match (&overrides.body, &overrides.json) {
+        (Some(body), _) => body.clone(),
+        (None, Some(patch)) => {
+            let Ok(mut body) = serde_json::from_str::<Value>(&original.data) else {
+                return Err("json patches need a JSON body; pass body instead".to_string());
+            };
+            merge(&mut body, patch.clone());
+            body.to_string()
+        }
+        (None, None) => original.data.clone(),
+    };
+    let body = if pipeline::has_body(&method) { body } else { String::new() };
+    Ok(Variant {
+        method,
+        headers,
+        body,
+        target,
+    })
+}
+
+/// What makes a saved recipe unusable, before any capture is at hand
+pub fn validate_recipe(name: &str, recipe: &ReplayRecipe) -> Option<String> {
+    let valid_name = !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid_name {
+        return Some("names are 1-64 letters, digits, - or _".to_string());
+    }
+    let overrides = &recipe.overrides;
+    if overrides.body.is_some() && overrides.json.is_some() {
+        return Some("pass either body or json, not both".to_string());
+    }
+    if overrides.target.as_deref().is_some_and(|target| !environments::is_valid_forward_url(target)) {
+        return Some("target must be an http(s) URL".to_string());
+    }
+    if recipe.pacing_ms > MAX_PACING_MS {
+        return Some(format!("pacing_ms is at most {}", MAX_PACING_MS));
+    }
+    None
+}
+
+/// `/w/{uuid}` on the host the management request came to
+pub fn capture_url(request_url: &Url, uuid: &str) -> Url {
+    let mut url = request_url.clone();
+    url.set_path(&format!("/w/{}", uuid));
+    url.set_query(None);
+    url
+}
+
+/// Stores and forwards edited variants of one webhook's captures
+pub struct Resend<'a> {
+    pub env: &'a Env,
+    pub db: &'a D1Database,
+    pub storage: &'a dyn Storage,
+    pub webhook_id: &'a str,
+    /// Capture URL (`/w/{uuid}`) the variants are parsed as received on
+    pub capture_url: Url,
+    /// None sends the variants unsigned
+    pub signing: Option<&'a ForwardSigning>,
+}
+
+impl Resend<'_> {
+    /// Store the variant as a child capture, forward it and store the answer
+    pub async fn send(&self, original: &StoredRequest, variant: &Variant) -> Result<Value> {
+        // Parsed like a delivery, so the indexed columns follow the edited headers and body
+        let received_at_ms = Date::now().as_millis() as i64;
+        let incoming = IncomingRequest {
+            method: variant.method.clone(),
+            url: self.capture_url.clone(),
+            headers: variant.headers.clone(),
+            body: pipeline::has_body(&variant.method).then(|| variant.body.clone()),
+            received_at_ms,
+        };
+        let parsed = pipeline::parse(&incoming)?;
+        let child_id = ids::new_capture_id(self.env, received_at_ms);
+        let meta = CaptureMeta {
+            id: child_id.clone(),
+            webhook_id: self.webhook_id.to_string(),
+            sequence: None,
+            verification: None,
+            environment: None,
+        };
+        let mut record = pipeline::into_record(parsed, meta);
+        if record.indexed_headers.event_type.is_none() {
+            record.indexed_headers.event_type = original.event_type.clone();
+        }
+        record.environment = original.environment.clone();
+        record.replay_of = Some(original.id.clone());
+        self.storage.insert_capture(&record).await?;
+
+        let signer = self.signing.and_then(|signing| {
+            config::resolve_secret(self.env, &signing.secret).map(|secret| forward::Signer {
+                secret,
+                id: child_id.clone(),
+                retries: signing.retries,
+            })
+        });
+        let delivery = forward::Delivery {
+            method: &variant.method,
+            headers: &variant.headers,
+            body: &variant.body,
+            query: None,
+        };
+        let outcome = match &signer {
+            Some(signer) => forward::send_signed(&variant.target, &delivery, signer).await,
+            None => forward::send(&variant.target, &delivery).await,
+        };
+        let answered_at_ms = Date::now().as_millis() as i64;
+        let stored = responses::record(self.db, self.webhook_id, &child_id, &variant.target, &outcome, answered_at_ms);
+        if let Err(e) = stored.await {
+            log_warn!("⚠️  Failed to store replay response: {:?}", e);
+        }
+
+        Ok(serde_json::json!({
+            "id": child_id,
+            "replay_of": original.id,
+            "target": variant.target,
+            "status": outcome.status,
+            "duration_ms": outcome.duration_ms,
+            "error": outcome.error,
+            "response": outcome.response.as_ref().map(|response| serde_json::json!({
+                "headers": response.headers,
+                "body": response.body,
+                "body_truncated": response.body_truncated,
+            })),
+        }))
+    }
+}
@@ -0,0 +1,137 @@
+//! Data residency
+//! A project can be pinned to a jurisdiction (`eu` or `us`) through
+//! `project_settings`. Its captures are then written to and read from
+//! region-pinned bindings named after the default ones with the jurisdiction
+//! as suffix: `DB_EU` for D1 capture storage, `HYPERDRIVE_EU` for Postgres,
+//! `UPLOADS_EU` and `EMAIL_ATTACHMENTS_EU` for R2. Webhook definitions and
+//! other metadata stay in `DB`. A pinned project whose binding is missing is
+//! miswired: its captures are refused rather than written to the default
+//! region, so one deployment can serve every jurisdiction safely. Pinned
+//! webhooks skip the hot store, whose Durable Object is not region-pinned.
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Supported jurisdictions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Jurisdiction {
+    Eu,
+    Us,
+}
+
+impl Jurisdiction {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "eu" => Some(Self::Eu),
+            "us" => Some(Self::Us),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Eu => "eu",
+            Self::Us => "us",
+        }
+    }
+}
+
+/// Binding name for `base` in a jurisdiction (`DB` → `DB_EU`); the default binding without one
+pub fn binding(base: &str, jurisdiction: Option<Jurisdiction>) -> String {
+    match jurisdiction {
+        Some(jurisdiction) => format!("{}_{}", base, jurisdiction.as_str().to_ascii_uppercase()),
+        None => base.to_string(),
+    }
+}
+
+/// Why a pinned capture cannot be stored
+pub fn miswired(binding: &str) -> String {
+    format!("Data residency binding {} is not configured", binding)
+}
+
+/// Check that the capture store binding for a jurisdiction exists; Err describes the miswiring
+pub fn check(env: &Env, jurisdiction: Option<Jurisdiction>) -> std::result::Result<(), String> {
+    let Some(jurisdiction) = jurisdiction else {
+        return Ok(());
+    };
+    let backend = env
+        .var("STORAGE_BACKEND")
+        .map(|value| value.to_string())
+        .unwrap_or_else(|_| "d1".to_string());
+    let name = binding(if backend == "postgres" { "HYPERDRIVE" } else { "DB" }, Some(jurisdiction));
+    let bound = match backend.as_str() {
+        "postgres" => env.hyperdrive(&name).is_ok(),
+        _ => env.d1(&name).is_ok(),
+    };
+    if bound {
+        Ok(())
+    } else {
+        Err(miswired(&name))
+    }
+}
+
+/// Optional R2 bucket for `base` in a jurisdiction; Ok(None) when the feature has no
+/// bucket at all. A pinned jurisdiction missing the bucket the default region has is an error.
+pub fn bucket(env: &Env, base: &str, jurisdiction: Option<Jurisdiction>) -> Result<Option<Bucket>> {
+    let name = binding(base, jurisdiction);
+    match env.bucket(&name) {
+        Ok(bucket) => Ok(Some(bucket)),
+        Err(_) if jurisdiction.is_some() && env.bucket(base).is_ok() => Err(Error::RustError(miswired(&name))),
+        Err(_) => Ok(None),
+    }
+}
+
+#[derive(Deserialize)]
+struct JurisdictionRow {
+    jurisdiction: Option<String>,
+}
+
+fn parse_stored(row: Option<JurisdictionRow>) -> Result<Option<Jurisdiction>> {
+    match row.and_then(|row| row.jurisdiction) {
+        Some(value) => Jurisdiction::parse(&value)
+            .map(Some)
+            .ok_or_else(|| Error::RustError(format!("Unknown jurisdiction '{}'", value))),
+        None => Ok(None),
+    }
+}
+
+/// A project's jurisdiction (None: default region)
+pub async fn of_project(db: &D1Database, user_id: &str) -> Result<Option<Jurisdiction>> {
+    let row = db
+        .prepare("SELECT jurisdiction FROM project_settings WHERE user_id = ?1")
+        .bind(&[JsValue::from_str(user_id)])?
+        .first::<JurisdictionRow>(None)
+        .await?;
+    parse_stored(row)
+}
+
+/// The jurisdiction of the project owning a webhook
+pub async fn of_webhook(db: &D1Database, webhook_id: &str) -> Result<Option<Jurisdiction>> {
+    let row = db
+        .prepare(
+            "SELECT p.jurisdiction FROM webhooks w JOIN project_settings p ON p.user_id = w.user_id \
+             WHERE w.id = ?1",
+        )
+        .bind(&[JsValue::from_str(webhook_id)])?
+        .first::<JurisdictionRow>(None)
+        .await?;
+    parse_stored(row)
+}
+
+/// Pin a project to a jurisdiction, or back to the default region with None
+pub async fn set(db: &D1Database, user_id: &str, jurisdiction: Option<Jurisdiction>, now_ms: i64) -> Result<()> {
+    db.prepare(
+        "INSERT INTO project_settings (user_id, jurisdiction, updated_at_ms) VALUES (?1, ?2, ?3) \
+         ON CONFLICT(user_id) DO UPDATE SET jurisdiction = excluded.jurisdiction, updated_at_ms = excluded.updated_at_ms",
+    )
+    .bind(&[
+        JsValue::from_str(user_id),
+        jurisdiction.map_or(JsValue::NULL, |jurisdiction| JsValue::from_str(jurisdiction.as_str())),
+        JsValue::from_f64(now_ms as f64),
+    ])?
+    .run()
+    .await?;
+    Ok(())
+}
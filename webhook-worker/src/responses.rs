@@ -0,0 +1,147 @@
+//! Downstream responses to forwarded captures
+//! Every forwarding attempt stores what the target answered in
+//! `forward_responses`, keyed by the originating capture: status, headers,
+//! the body up to `forward::MAX_RESPONSE_BODY_BYTES`, latency, or the error
+//! when there was no answer. `GET /api/webhooks/{uuid}/requests/{id}` returns
+//! them next to the capture, so a delivery and what each server said about it
+//! can be read as a pair.
+
+use crate::forward::ForwardOutcome;
+use crate::ids;
+use crate::storage::{optional_i64, optional_str};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Days of responses kept
+pub const RETENTION_DAYS: i64 = 30;
+
+/// A stored downstream response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredResponse {
+    pub id: String,
+    pub target: String,
+    pub status: Option<u16>,
+    pub headers: HashMap<String, String>,
+    pub body: Option<String>,
+    pub body_truncated: bool,
+    pub duration_ms: i64,
+    pub error: Option<String>,
+    pub created_at_ms: i64,
+}
+
+#[derive(Deserialize)]
+struct ResponseRow {
+    id: String,
+    target: String,
+    status: Option<f64>,
+    headers: Option<String>,
+    body: Option<String>,
+    body_truncated: f64,
+    duration_ms: f64,
+    error: Option<String>,
+    created_at_ms: f64,
+}
+
+impl From<ResponseRow> for StoredResponse {
+    fn from(row: ResponseRow) -> Self {
+        Self {
+            id: row.id,
+            target: row.target,
+            status: row.status.map(|status| status as u16),
+            headers: row
+                .headers
+                .and_then(|headers| serde_json::from_str(&headers).ok())
+                .unwrap_or_default(),
+            body: row.body,
+            body_truncated: row.body_truncated != 0.0,
+            duration_ms: row.duration_ms as i64,
+            error: row.error,
+            created_at_ms: row.created_at_ms as i64,
+        }
+    }
+}
+
+/// Store the result of forwarding capture `capture_id` to `target`
+pub async fn record(
+    db: &D1Database,
+    webhook_id: &str,
+    capture_id: &str,
+    target: &str,
+    outcome: &ForwardOutcome,
+    now_ms: i64,
+) -> Result<()> {
+    let response = outcome.response.as_ref();
+    let headers = response.map(|response| serde_json::to_string(&response.headers)).transpose()?;
+    db.prepare(
+        "INSERT INTO forward_responses (id, webhook_id, capture_id, target, status, headers, body, body_truncated, \
+         duration_ms, error, created_at_ms) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+    )
+    .bind(&[
+        JsValue::from_str(&ids::ulid(now_ms)),
+        JsValue::from_str(webhook_id),
+        JsValue::from_str(capture_id),
+        JsValue::from_str(target),
+        optional_i64(outcome.status.map(i64::from)),
+        optional_str(&headers),
+        optional_str(&response.map(|response| response.body.clone())),
+        JsValue::from_f64(if response.is_some_and(|response| response.body_truncated) { 1.0 } else { 0.0 }),
+        JsValue::from_f64(outcome.duration_ms as f64),
+        optional_str(&outcome.error),
+        JsValue::from_f64(now_ms as f64),
+    ])?
+    .run()
+    .await?;
+    Ok(())
+}
+
+/// Store a response carried over from another capture (snapshot import) under a new ID
+pub async fn restore(db: &D1Database, webhook_id: &str, capture_id: &str, response: &StoredResponse) -> Result<()> {
+    let headers = serde_json::to_string(&response.headers)?;
+    db.prepare(
+        "INSERT INTO forward_responses (id, webhook_id, capture_id, target, status, headers, body, body_truncated, \
+         duration_ms, error, created_at_ms) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+    )
+    .bind(&[
+        JsValue::from_str(&ids::ulid(response.created_at_ms)),
+        JsValue::from_str(webhook_id),
+        JsValue::from_str(capture_id),
+        JsValue::from_str(&response.target),
+        optional_i64(response.status.map(i64::from)),
+        JsValue::from_str(&headers),
+        optional_str(&response.body),
+        JsValue::from_f64(if response.body_truncated { 1.0 } else { 0.0 }),
+        JsValue::from_f64(response.duration_ms as f64),
+        optional_str(&response.error),
+        JsValue::from_f64(response.created_at_ms as f64),
+    ])?
+    .run()
+    .await?;
+    Ok(())
+}
+
+/// Responses to one capture, oldest first
+pub async fn for_capture(db: &D1Database, webhook_id: &str, capture_id: &str) -> Result<Vec<StoredResponse>> {
+    Ok(db
+        .prepare(
+            "SELECT id, target, status, headers, body, body_truncated, duration_ms, error, created_at_ms \
+             FROM forward_responses WHERE webhook_id = ?1 AND capture_id = ?2 ORDER BY created_at_ms, id",
+        )
+        .bind(&[JsValue::from_str(webhook_id), JsValue::from_str(capture_id)])?
+        .all()
+        .await?
+        .results::<ResponseRow>()?
+        .into_iter()
+        .map(StoredResponse::from)
+        .collect())
+}
+
+/// Drop responses older than `RETENTION_DAYS`
+pub async fn prune(db: &D1Database, now_ms: i64) -> Result<()> {
+    db.prepare("DELETE FROM forward_responses WHERE created_at_ms < ?1")
+        .bind(&[JsValue::from_f64((now_ms - RETENTION_DAYS * 86_400_000) as f64)])?
+        .run()
+        .await?;
+    Ok(())
+}
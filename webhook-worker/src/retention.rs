@@ -0,0 +1,147 @@
+//! Tiered retention
+//! Webhooks with `retention_tiers` are downsampled by the scheduled handler
+//! instead of cut off at one age: captures older than `full_days` lose their
+//! body and headers (the indexed metadata stays listable and filterable), rows
+//! older than `metadata_days` are deleted, and with `aggregates` their counts
+//! and bytes per UTC day and event type are first rolled into `capture_daily`,
+//! which is never pruned. Legally held webhooks are skipped, and held captures
+//! are counted but neither stripped nor deleted. Cutoffs fall on UTC midnight
+//! so a day is always aggregated whole; re-running after a failed delete
+//! recounts the same rows, and the upsert keeps the larger count, so nothing is
+//! counted twice.
+
+use crate::config::{self, RetentionTiers};
+use crate::legal_hold;
+use crate::storage::{self, Consistency, DailyCount};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+use worker::*;
+
+const DAY: i64 = 86_400;
+
+/// Where the tiers fall on a given run (Unix seconds, UTC midnights)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cutoffs {
+    /// Strip payloads of captures received before this
+    pub strip_before: i64,
+    /// Aggregate and delete captures received before this
+    pub delete_before: i64,
+}
+
+impl Cutoffs {
+    pub fn new(tiers: &RetentionTiers, now: i64) -> Self {
+        let today = now - now.rem_euclid(DAY);
+        Self {
+            strip_before: today - tiers.full_days as i64 * DAY,
+            delete_before: today - tiers.metadata_days as i64 * DAY,
+        }
+    }
+}
+
+/// Apply every webhook's retention tiers
+pub async fn enforce(env: &Env, now: i64) -> Result<()> {
+    let db = env.d1("DB")?;
+    let rules = config::tier_rules(&db).await?;
+    if rules.is_empty() {
+        return Ok(());
+    }
+
+    for rule in rules {
+        let held = legal_hold::active(&db, &rule.webhook_id).await?;
+        if held.webhook {
+            continue;
+        }
+        let storage = storage::for_webhook(env, &rule.webhook_id, Consistency::Primary).await?;
+        let cutoffs = Cutoffs::new(&rule.tiers, now);
+        let stripped = storage
+            .strip_payloads(&rule.webhook_id, cutoffs.strip_before, &held.captures)
+            .await?;
+        if rule.tiers.aggregates {
+            let counts = storage.daily_counts(&rule.webhook_id, cutoffs.delete_before).await?;
+            record(&db, &rule.webhook_id, &counts).await?;
+        }
+        let deleted = storage
+            .purge(&rule.webhook_id, None, cutoffs.delete_before, &held.captures)
+            .await?;
+        if stripped > 0 || deleted > 0 {
+            log_info!(
+                "🗜️  Webhook {}: stripped {} payloads past {} days, deleted {} rows past {} days",
+                rule.webhook_id,
+                stripped,
+                rule.tiers.full_days,
+                deleted,
+                rule.tiers.metadata_days
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Upsert daily counts; a recount of the same day keeps the larger numbers
+async fn record(db: &D1Database, webhook_id: &str, counts: &[DailyCount]) -> Result<()> {
+    if counts.is_empty() {
+        return Ok(());
+    }
+    let statements = counts
+        .iter()
+        .map(|count| {
+            db.prepare(
+                "INSERT INTO capture_daily (webhook_id, day, event_type, count, bytes) VALUES (?1, ?2, ?3, ?4, ?5) \
+                 ON CONFLICT(webhook_id, day, event_type) DO UPDATE SET \
+                 count = MAX(count, excluded.count), bytes = MAX(bytes, excluded.bytes)",
+            )
+            .bind(&[
+                JsValue::from_str(webhook_id),
+                JsValue::from_f64(count.day as f64),
+                JsValue::from_str(count.event_type.as_deref().unwrap_or("")),
+                JsValue::from_f64(count.count as f64),
+                JsValue::from_f64(count.bytes as f64),
+            ])
+        })
+        .collect::<Result<Vec<_>>>()?;
+    db.batch(statements).await?;
+    Ok(())
+}
+
+/// One aggregated day
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyAggregate {
+    pub day: i64,
+    /// Empty for captures without an event type
+    pub event_type: String,
+    pub count: i64,
+    pub bytes: i64,
+}
+
+#[derive(Deserialize)]
+struct AggregateRow {
+    day: f64,
+    event_type: String,
+    count: f64,
+    bytes: f64,
+}
+
+/// Aggregated days in `[since, until)` (Unix seconds), oldest first
+pub async fn daily(db: &D1Database, webhook_id: &str, since: i64, until: i64) -> Result<Vec<DailyAggregate>> {
+    Ok(db
+        .prepare(
+            "SELECT day, event_type, count, bytes FROM capture_daily \
+             WHERE webhook_id = ?1 AND day >= ?2 AND day < ?3 ORDER BY day, event_type",
+        )
+        .bind(&[
+            JsValue::from_str(webhook_id),
+            JsValue::from_f64(since as f64),
+            JsValue::from_f64(until as f64),
+        ])?
+        .all()
+        .await?
+        .results::<AggregateRow>()?
+        .into_iter()
+        .map(|row| DailyAggregate {
+            day: row.day as i64,
+            event_type: row.event_type,
+            count: row.count as i64,
+            bytes: row.bytes as i64,
+        })
+        .collect())
+}
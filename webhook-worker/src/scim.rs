@@ -0,0 +1,812 @@
+//! SCIM 2.0 provisioning sink
+//! `/w/{uuid}/scim/v2/...` answers an identity provider's provisioning calls
+//! the way a SCIM service provider would (RFC 7643 and 7644), so Okta, Entra
+//! ID and friends can be pointed at a capture URL: users and groups are
+//! created, read, listed (with `eq` filters and paging), replaced, patched and
+//! deleted, and the discovery endpoints describe what is supported. Every call
+//! is still stored as a capture. Resources live in the `scim_resources` D1
+//! table, per webhook, and are deleted with it; bearer tokens are not checked.
+
+use crate::config::CustomResponse;
+use chrono::{DateTime, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Path segment the SCIM base URL ends with
+pub const PATH: &str = "/scim/v2";
+
+/// Content type of every SCIM response
+pub const CONTENT_TYPE: &str = "application/scim+json";
+
+/// Users, or groups, one webhook may hold
+pub const MAX_RESOURCES: usize = 1000;
+
+/// Page size when a list request gives no `count`
+const DEFAULT_COUNT: usize = 100;
+
+const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+const LIST_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+const PATCH_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:PatchOp";
+const CONFIG_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig";
+const RESOURCE_TYPE_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:ResourceType";
+const SCHEMA_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Schema";
+
+/// The two RFC 7643 core resource types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResourceType {
+    User,
+    Group,
+}
+
+impl ResourceType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::User => "User",
+            Self::Group => "Group",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "User" => Some(Self::User),
+            "Group" => Some(Self::Group),
+            _ => None,
+        }
+    }
+
+    /// Collection path under the base URL
+    pub fn endpoint(self) -> &'static str {
+        match self {
+            Self::User => "Users",
+            Self::Group => "Groups",
+        }
+    }
+
+    pub fn schema(self) -> &'static str {
+        match self {
+            Self::User => "urn:ietf:params:scim:schemas:core:2.0:User",
+            Self::Group => "urn:ietf:params:scim:schemas:core:2.0:Group",
+        }
+    }
+
+    /// Required attribute no two resources of the type may share (case-insensitively)
+    pub fn unique_attribute(self) -> &'static str {
+        match self {
+            Self::User => "userName",
+            Self::Group => "displayName",
+        }
+    }
+
+    fn from_endpoint(endpoint: &str) -> Option<Self> {
+        [Self::User, Self::Group]
+            .into_iter()
+            .find(|resource_type| resource_type.endpoint().eq_ignore_ascii_case(endpoint))
+    }
+}
+
+/// What a SCIM call addresses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    ServiceProviderConfig,
+    ResourceTypes,
+    Schemas,
+    Collection(ResourceType),
+    Resource(ResourceType, String),
+    Unknown,
+}
+
+/// A SCIM call, read from a capture
+#[derive(Debug, Clone)]
+pub struct ScimRequest {
+    pub method: String,
+    pub endpoint: Endpoint,
+    pub query: HashMap<String, String>,
+    /// JSON body, when there is one
+    pub body: Option<Value>,
+    /// Base URL, for `meta.location`
+    pub base: String,
+}
+
+impl ScimRequest {
+    /// The call, if `url` is under `/w/{uuid}/scim/v2`
+    pub fn parse(url: &Url, uuid: &str, method: &str, body: &str) -> Option<Self> {
+        let prefix = format!("/w/{}{}", uuid, PATH);
+        let rest = url.path().strip_prefix(&prefix)?;
+        if !(rest.is_empty() || rest.starts_with('/')) {
+            return None;
+        }
+        let segments: Vec<&str> = rest.split('/').filter(|segment| !segment.is_empty()).collect();
+        let endpoint = match segments.as_slice() {
+            ["ServiceProviderConfig"] => Endpoint::ServiceProviderConfig,
+            ["ResourceTypes", ..] => Endpoint::ResourceTypes,
+            ["Schemas", ..] => Endpoint::Schemas,
+            [collection] => ResourceType::from_endpoint(collection).map_or(Endpoint::Unknown, Endpoint::Collection),
+            [collection, id] => ResourceType::from_endpoint(collection)
+                .map_or(Endpoint::Unknown, |resource_type| Endpoint::Resource(resource_type, id.to_string())),
+            _ => Endpoint::Unknown,
+        };
+        Some(Self {
+            method: method.to_ascii_uppercase(),
+            endpoint,
+            query: url.query_pairs().into_owned().collect(),
+            body: serde_json::from_str(body).ok(),
+            base: format!("{}{}", url.origin().ascii_serialization(), prefix),
+        })
+    }
+
+    /// Resource type the call reads or writes
+    pub fn resource_type(&self) -> Option<ResourceType> {
+        match &self.endpoint {
+            Endpoint::Collection(resource_type) | Endpoint::Resource(resource_type, _) => Some(*resource_type),
+            _ => None,
+        }
+    }
+
+    /// `scim.user.create`, `scim.group.patch`, ..., `scim.discovery` for the rest
+    pub fn event_type(&self) -> String {
+        let Some(resource_type) = self.resource_type() else {
+            return "scim.discovery".to_string();
+        };
+        let action = match (self.method.as_str(), &self.endpoint) {
+            ("GET", Endpoint::Collection(_)) => "list",
+            ("POST", _) => "create",
+            ("GET", _) => "get",
+            ("PUT", _) => "replace",
+            ("PATCH", _) => "patch",
+            ("DELETE", _) => "delete",
+            _ => "other",
+        };
+        format!("scim.{}.{}", resource_type.as_str().to_ascii_lowercase(), action)
+    }
+}
+
+/// One stored user or group
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resource {
+    pub id: String,
+    pub resource_type: ResourceType,
+    /// Attributes as last written, without `id` and `meta`
+    pub attributes: Value,
+    /// Bumped by every write, served as the weak ETag
+    pub version: i64,
+    pub created_at_ms: i64,
+    pub updated_at_ms: i64,
+}
+
+impl Resource {
+    /// The resource as SCIM serves it
+    pub fn to_json(&self, base: &str) -> Value {
+        let mut body = self.attributes.as_object().cloned().unwrap_or_default();
+        body.insert("id".to_string(), Value::String(self.id.clone()));
+        body.insert(
+            "meta".to_string(),
+            serde_json::json!({
+                "resourceType": self.resource_type.as_str(),
+                "created": rfc3339(self.created_at_ms),
+                "lastModified": rfc3339(self.updated_at_ms),
+                "location": self.location(base),
+                "version": self.etag(),
+            }),
+        );
+        Value::Object(body)
+    }
+
+    pub fn location(&self, base: &str) -> String {
+        format!("{}/{}/{}", base, self.resource_type.endpoint(), self.id)
+    }
+
+    pub fn etag(&self) -> String {
+        format!("W/\"{}\"", self.version)
+    }
+}
+
+fn rfc3339(ms: i64) -> Option<String> {
+    DateTime::from_timestamp_millis(ms).map(|time| time.to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+/// A write to persist once the call is answered
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Put(Resource),
+    Delete(String),
+}
+
+/// The SCIM answer to a call
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reply {
+    pub status: u16,
+    pub body: Option<Value>,
+    /// `Location` of a created resource
+    pub location: Option<String>,
+    /// `ETag` of the resource returned
+    pub etag: Option<String>,
+}
+
+impl Reply {
+    fn ok(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            body: Some(body),
+            location: None,
+            etag: None,
+        }
+    }
+
+    fn no_content() -> Self {
+        Self {
+            status: 204,
+            body: None,
+            location: None,
+            etag: None,
+        }
+    }
+
+    fn resource(status: u16, resource: &Resource, base: &str) -> Self {
+        Self {
+            status,
+            body: Some(resource.to_json(base)),
+            location: (status == 201).then(|| resource.location(base)),
+            etag: Some(resource.etag()),
+        }
+    }
+
+    /// An RFC 7644 error response
+    pub fn error(status: u16, scim_type: Option<&str>, detail: impl Into<String>) -> Self {
+        let mut body = serde_json::json!({
+            "schemas": [ERROR_SCHEMA],
+            "status": status.to_string(),
+            "detail": detail.into(),
+        });
+        if let Some(scim_type) = scim_type {
+            body["scimType"] = Value::String(scim_type.to_string());
+        }
+        Self::ok(status, body)
+    }
+
+    /// The response to send, and its extra headers
+    pub fn into_response(self) -> (CustomResponse, Vec<(&'static str, String)>) {
+        let headers = [("Location", self.location), ("ETag", self.etag)]
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value?)))
+            .collect();
+        let response = CustomResponse {
+            status: self.status,
+            body: self.body.map(|body| body.to_string()).unwrap_or_default(),
+            content_type: Some(CONTENT_TYPE.to_string()),
+            headers: BTreeMap::new(),
+        };
+        (response, headers)
+    }
+}
+
+/// Answer `request` against the webhook's stored resources of its type, and
+/// what to persist. `new_id` names a resource the call creates.
+pub fn handle(request: &ScimRequest, existing: &[Resource], now_ms: i64, new_id: &str) -> (Reply, Option<Change>) {
+    let base = request.base.as_str();
+    let method = request.method.as_str();
+    match (&request.endpoint, method) {
+        (Endpoint::ServiceProviderConfig, "GET") => (Reply::ok(200, service_provider_config(base)), None),
+        (Endpoint::ResourceTypes, "GET") => (Reply::ok(200, resource_types(base)), None),
+        (Endpoint::Schemas, "GET") => (Reply::ok(200, schemas()), None),
+        (Endpoint::Collection(_), "GET") => (list(request, existing), None),
+        (Endpoint::Collection(resource_type), "POST") => create(request, *resource_type, existing, now_ms, new_id),
+        (Endpoint::Resource(_, id), _) => {
+            let Some(resource) = existing.iter().find(|resource| &resource.id == id) else {
+                return (Reply::error(404, None, format!("Resource {} not found", id)), None);
+            };
+            match method {
+                "GET" => (Reply::resource(200, resource, base), None),
+                "PUT" | "PATCH" => update(request, resource, existing, now_ms),
+                "DELETE" => (Reply::no_content(), Some(Change::Delete(id.clone()))),
+                _ => (not_implemented(method), None),
+            }
+        }
+        (Endpoint::Unknown, _) => (Reply::error(404, None, "Unknown SCIM endpoint"), None),
+        _ => (not_implemented(method), None),
+    }
+}
+
+fn not_implemented(method: &str) -> Reply {
+    Reply::error(501, None, format!("{} is not supported on this endpoint", method))
+}
+
+fn list(request: &ScimRequest, existing: &[Resource]) -> Reply {
+    let filter = match request.query.get("filter").map(|filter| Filter::parse(filter)) {
+        Some(Some(filter)) => Some(filter),
+        Some(None) => {
+            let detail = "Only `attribute eq \"value\"` filters are supported";
+            return Reply::error(400, Some("invalidFilter"), detail);
+        }
+        None => None,
+    };
+    let matching: Vec<&Resource> = existing
+        .iter()
+        .filter(|resource| filter.as_ref().is_none_or(|filter| filter.matches(&resource.attributes, &resource.id)))
+        .collect();
+    let number = |name: &str| request.query.get(name).and_then(|value| value.parse::<usize>().ok());
+    let start_index = number("startIndex").unwrap_or(1).max(1);
+    let count = number("count").unwrap_or(DEFAULT_COUNT).min(MAX_RESOURCES);
+    let page: Vec<Value> = matching
+        .iter()
+        .skip(start_index - 1)
+        .take(count)
+        .map(|resource| resource.to_json(&request.base))
+        .collect();
+    Reply::ok(
+        200,
+        serde_json::json!({
+            "schemas": [LIST_SCHEMA],
+            "totalResults": matching.len(),
+            "startIndex": start_index,
+            "itemsPerPage": page.len(),
+            "Resources": page,
+        }),
+    )
+}
+
+fn create(
+    request: &ScimRequest,
+    resource_type: ResourceType,
+    existing: &[Resource],
+    now_ms: i64,
+    new_id: &str,
+) -> (Reply, Option<Change>) {
+    let attributes = match attributes(request.body.as_ref(), resource_type) {
+        Ok(attributes) => attributes,
+        Err(reply) => return (reply, None),
+    };
+    if existing.len() >= MAX_RESOURCES {
+        let detail = format!("A webhook holds at most {} {}", MAX_RESOURCES, resource_type.endpoint());
+        return (Reply::error(400, None, detail), None);
+    }
+    if let Some(reply) = conflict(&attributes, resource_type, existing, None) {
+        return (reply, None);
+    }
+    let resource = Resource {
+        id: new_id.to_string(),
+        resource_type,
+        attributes,
+        version: 1,
+        created_at_ms: now_ms,
+        updated_at_ms: now_ms,
+    };
+    (Reply::resource(201, &resource, &request.base), Some(Change::Put(resource)))
+}
+
+fn update(request: &ScimRequest, resource: &Resource, existing: &[Resource], now_ms: i64) -> (Reply, Option<Change>) {
+    let resource_type = resource.resource_type;
+    let attributes = if request.method == "PUT" {
+        attributes(request.body.as_ref(), resource_type)
+    } else {
+        patch(&resource.attributes, request.body.as_ref()).and_then(|patched| attributes(Some(&patched), resource_type))
+    };
+    let attributes = match attributes {
+        Ok(attributes) => attributes,
+        Err(reply) => return (reply, None),
+    };
+    if let Some(reply) = conflict(&attributes, resource_type, existing, Some(&resource.id)) {
+        return (reply, None);
+    }
+    let updated = Resource {
+        attributes,
+        version: resource.version + 1,
+        updated_at_ms: now_ms,
+        ..resource.clone()
+    };
+    (Reply::resource(200, &updated, &request.base), Some(Change::Put(updated)))
+}
+
+/// A written resource's attributes: an object with the unique attribute, without `id` and `meta`
+fn attributes(body: Option<&Value>, resource_type: ResourceType) -> std::result::Result<Value, Reply> {
+    let Some(mut attributes) = body.and_then(Value::as_object).cloned() else {
+        return Err(Reply::error(400, Some("invalidSyntax"), "The body must be a JSON object"));
+    };
+    attributes.retain(|name, _| !name.eq_ignore_ascii_case("id") && !name.eq_ignore_ascii_case("meta"));
+    let unique = resource_type.unique_attribute();
+    let present = attribute(&attributes, unique).and_then(Value::as_str).is_some_and(|value| !value.is_empty());
+    if !present {
+        return Err(Reply::error(400, Some("invalidValue"), format!("{} is required", unique)));
+    }
+    if !attributes.contains_key("schemas") {
+        attributes.insert("schemas".to_string(), serde_json::json!([resource_type.schema()]));
+    }
+    Ok(Value::Object(attributes))
+}
+
+/// 409 when another resource of the type already has the unique attribute's value
+fn conflict(attributes: &Value, resource_type: ResourceType, existing: &[Resource], id: Option<&str>) -> Option<Reply> {
+    let unique = resource_type.unique_attribute();
+    let value = attributes.as_object().and_then(|attributes| attribute(attributes, unique))?.as_str()?;
+    existing
+        .iter()
+        .filter(|resource| Some(resource.id.as_str()) != id)
+        .any(|resource| {
+            resource
+                .attributes
+                .as_object()
+                .and_then(|attributes| attribute(attributes, unique))
+                .and_then(Value::as_str)
+                .is_some_and(|other| other.eq_ignore_ascii_case(value))
+        })
+        .then(|| Reply::error(409, Some("uniqueness"), format!("{} {:?} is already taken", unique, value)))
+}
+
+/// An attribute by name, case-insensitively as RFC 7643 requires
+fn attribute<'a>(attributes: &'a Map<String, Value>, name: &str) -> Option<&'a Value> {
+    attributes.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value)
+}
+
+/// The stored key for `name`: the existing spelling, else `name`
+fn key(attributes: &Map<String, Value>, name: &str) -> String {
+    attributes
+        .keys()
+        .find(|key| key.eq_ignore_ascii_case(name))
+        .cloned()
+        .unwrap_or_else(|| name.to_string())
+}
+
+/// `attribute eq "value"` (RFC 7644 §3.4.2.2, the one operator provisioning clients send)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    /// `userName`, or `emails.value` into a multi-valued attribute
+    pub path: Vec<String>,
+    pub value: Value,
+}
+
+impl Filter {
+    pub fn parse(filter: &str) -> Option<Self> {
+        let mut parts = filter.trim().splitn(3, char::is_whitespace);
+        let (path, operator, value) = (parts.next()?, parts.next()?, parts.next()?.trim());
+        if !operator.eq_ignore_ascii_case("eq") || path.is_empty() {
+            return None;
+        }
+        let value = serde_json::from_str::<Value>(value)
+            .ok()
+            .filter(|value| !value.is_object() && !value.is_array())?;
+        Some(Self {
+            path: path.split('.').map(str::to_string).collect(),
+            value,
+        })
+    }
+
+    /// Whether the filter holds for a resource's attributes (`id` included)
+    pub fn matches(&self, attributes: &Value, id: &str) -> bool {
+        if self.path.len() == 1 && self.path[0].eq_ignore_ascii_case("id") {
+            return self.value.as_str() == Some(id);
+        }
+        values(attributes, &self.path).into_iter().any(|value| equal(value, &self.value))
+    }
+}
+
+/// Every value at `path`, stepping into multi-valued attributes
+fn values<'a>(value: &'a Value, path: &[String]) -> Vec<&'a Value> {
+    let Some((name, rest)) = path.split_first() else {
+        return match value {
+            Value::Array(items) => items.iter().collect(),
+            value => vec![value],
+        };
+    };
+    match value {
+        Value::Object(attributes) => attribute(attributes, name).map(|value| values(value, rest)).unwrap_or_default(),
+        Value::Array(items) => items.iter().flat_map(|item| values(item, path)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Filter equality: strings compare case-insensitively
+fn equal(value: &Value, expected: &Value) -> bool {
+    match (value, expected) {
+        (Value::String(value), Value::String(expected)) => value.eq_ignore_ascii_case(expected),
+        (value, expected) => value == expected,
+    }
+}
+
+/// `attributes` with a PatchOp request's operations applied in order
+pub fn patch(attributes: &Value, body: Option<&Value>) -> std::result::Result<Value, Reply> {
+    let invalid = |detail: &str| Reply::error(400, Some("invalidSyntax"), detail);
+    let body = body.ok_or_else(|| invalid("The body must be a PatchOp request"))?;
+    let is_patch = body
+        .get("schemas")
+        .and_then(Value::as_array)
+        .is_some_and(|schemas| schemas.iter().any(|schema| schema.as_str() == Some(PATCH_SCHEMA)));
+    let operations = body.get("Operations").and_then(Value::as_array);
+    let (true, Some(operations)) = (is_patch, operations) else {
+        return Err(invalid("The body must be a PatchOp request with Operations"));
+    };
+    let mut patched = attributes.as_object().cloned().unwrap_or_default();
+    for operation in operations {
+        let op = operation.get("op").and_then(Value::as_str).unwrap_or_default().to_ascii_lowercase();
+        let path = operation.get("path").and_then(Value::as_str);
+        let value = operation.get("value");
+        match (op.as_str(), path) {
+            ("add" | "replace", None) => {
+                let Some(values) = value.and_then(Value::as_object) else {
+                    return Err(invalid("An operation without a path needs an object value"));
+                };
+                for (name, value) in values {
+                    // Azure-style `"name.givenName": "Ada"` keys are paths too
+                    set(&mut patched, &PatchPath::parse(name)?, value, op == "add")?;
+                }
+            }
+            ("add" | "replace", Some(path)) => {
+                let value = value.ok_or_else(|| invalid("add and replace need a value"))?;
+                set(&mut patched, &PatchPath::parse(path)?, value, op == "add")?;
+            }
+            ("remove", Some(path)) => remove(&mut patched, &PatchPath::parse(path)?, value),
+            ("remove", None) => return Err(Reply::error(400, Some("noTarget"), "remove needs a path")),
+            _ => return Err(invalid("op must be add, replace or remove")),
+        }
+    }
+    Ok(Value::Object(patched))
+}
+
+/// `attribute`, `attribute.sub`, `attribute[filter]` or `attribute[filter].sub`
+#[derive(Debug)]
+struct PatchPath {
+    attribute: String,
+    filter: Option<Filter>,
+    sub: Option<String>,
+}
+
+impl PatchPath {
+    fn parse(path: &str) -> std::result::Result<Self, Reply> {
+        // Extension attributes are stored by name, without their schema URN
+        let head = path.split('[').next().unwrap_or(path);
+        let path = match head.rfind(':') {
+            Some(colon) if path.starts_with("urn:") => &path[colon + 1..],
+            _ => path,
+        };
+        let invalid = || Reply::error(400, Some("invalidPath"), format!("Unsupported path {:?}", path));
+        let (attribute, filter, rest) = match path.split_once('[') {
+            Some((attribute, rest)) => {
+                let (filter, rest) = rest.split_once(']').ok_or_else(invalid)?;
+                let filter = Filter::parse(filter).ok_or_else(invalid)?;
+                (attribute, Some(filter), rest)
+            }
+            None => match path.split_once('.') {
+                Some((attribute, sub)) => (attribute, None, sub),
+                None => (path, None, ""),
+            },
+        };
+        let sub = rest.trim_start_matches('.');
+        if attribute.is_empty() || sub.contains('.') || sub.contains('[') {
+            return Err(invalid());
+        }
+        Ok(Self {
+            attribute: attribute.to_string(),
+            filter,
+            sub: (!sub.is_empty()).then(|| sub.to_string()),
+        })
+    }
+}
+
+fn set(
+    attributes: &mut Map<String, Value>,
+    path: &PatchPath,
+    value: &Value,
+    add: bool,
+) -> std::result::Result<(), Reply> {
+    let name = key(attributes, &path.attribute);
+    match (&path.filter, &path.sub) {
+        (None, None) => {
+            let merged = match (attributes.get(&name), value) {
+                (Some(Value::Array(current)), Value::Array(added)) if add => {
+                    let mut merged = current.clone();
+                    merged.extend(added.iter().filter(|item| !current.contains(item)).cloned());
+                    Value::Array(merged)
+                }
+                _ => value.clone(),
+            };
+            attributes.insert(name, merged);
+        }
+        (None, Some(sub)) => match attributes.entry(name).or_insert_with(|| Value::Object(Map::new())) {
+            Value::Object(complex) => {
+                let sub = key(complex, sub);
+                complex.insert(sub, value.clone());
+            }
+            _ => return Err(Reply::error(400, Some("invalidPath"), format!("{} is not complex", path.attribute))),
+        },
+        (Some(filter), sub) => {
+            let Some(Value::Array(items)) = attributes.get_mut(&name) else {
+                return Err(Reply::error(400, Some("noTarget"), format!("{} has no matching values", path.attribute)));
+            };
+            let mut matched = false;
+            for item in items.iter_mut().filter(|item| filter.matches(item, "")) {
+                matched = true;
+                match (sub, item.as_object_mut()) {
+                    (Some(sub), Some(item)) => {
+                        let sub = key(item, sub);
+                        item.insert(sub, value.clone());
+                    }
+                    _ => *item = value.clone(),
+                }
+            }
+            if !matched {
+                return Err(Reply::error(400, Some("noTarget"), format!("{} has no matching values", path.attribute)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Remove an attribute, a sub-attribute, or the matching values of a multi-valued one;
+/// `members` with a `value` list removes those members (the Entra ID form)
+fn remove(attributes: &mut Map<String, Value>, path: &PatchPath, value: Option<&Value>) {
+    let name = key(attributes, &path.attribute);
+    match (&path.filter, &path.sub, value.and_then(Value::as_array)) {
+        (None, None, Some(removed)) => {
+            if let Some(Value::Array(items)) = attributes.get_mut(&name) {
+                let removed: Vec<&Value> = removed.iter().filter_map(|item| item.get("value")).collect();
+                items.retain(|item| !item.get("value").is_some_and(|value| removed.contains(&value)));
+            }
+        }
+        (None, None, None) => {
+            attributes.remove(&name);
+        }
+        (None, Some(sub), _) => {
+            if let Some(Value::Object(complex)) = attributes.get_mut(&name) {
+                let sub = key(complex, sub);
+                complex.remove(&sub);
+            }
+        }
+        (Some(filter), sub, _) => {
+            if let Some(Value::Array(items)) = attributes.get_mut(&name) {
+                match sub {
+                    Some(sub) => items
+                        .iter_mut()
+                        .filter(|item| filter.matches(item, ""))
+                        .filter_map(Value::as_object_mut)
+                        .for_each(|item| {
+                            let sub = key(item, sub);
+                            item.remove(&sub);
+                        }),
+                    None => items.retain(|item| !filter.matches(item, "")),
+                }
+            }
+        }
+    }
+}
+
+fn service_provider_config(base: &str) -> Value {
+    serde_json::json!({
+        "schemas": [CONFIG_SCHEMA],
+        "documentationUri": "https://datatracker.ietf.org/doc/html/rfc7644",
+        "patch": { "supported": true },
+        "bulk": { "supported": false, "maxOperations": 0, "maxPayloadSize": 0 },
+        "filter": { "supported": true, "maxResults": MAX_RESOURCES },
+        "changePassword": { "supported": false },
+        "sort": { "supported": false },
+        "etag": { "supported": true },
+        "authenticationSchemes": [{
+            "type": "oauthbearertoken",
+            "name": "OAuth Bearer Token",
+            "description": "Any bearer token is accepted",
+        }],
+        "meta": { "resourceType": "ServiceProviderConfig", "location": format!("{}/ServiceProviderConfig", base) },
+    })
+}
+
+fn resource_types(base: &str) -> Value {
+    let types: Vec<Value> = [ResourceType::User, ResourceType::Group]
+        .into_iter()
+        .map(|resource_type| {
+            serde_json::json!({
+                "schemas": [RESOURCE_TYPE_SCHEMA],
+                "id": resource_type.as_str(),
+                "name": resource_type.as_str(),
+                "endpoint": format!("/{}", resource_type.endpoint()),
+                "schema": resource_type.schema(),
+                "meta": {
+                    "resourceType": "ResourceType",
+                    "location": format!("{}/ResourceTypes/{}", base, resource_type.as_str()),
+                },
+            })
+        })
+        .collect();
+    list_of(types)
+}
+
+fn schemas() -> Value {
+    let schemas: Vec<Value> = [ResourceType::User, ResourceType::Group]
+        .into_iter()
+        .map(|resource_type| {
+            serde_json::json!({
+                "schemas": [SCHEMA_SCHEMA],
+                "id": resource_type.schema(),
+                "name": resource_type.as_str(),
+                "attributes": [{
+                    "name": resource_type.unique_attribute(),
+                    "type": "string",
+                    "multiValued": false,
+                    "required": true,
+                    "caseExact": false,
+                    "mutability": "readWrite",
+                    "returned": "default",
+                    "uniqueness": "server",
+                }],
+            })
+        })
+        .collect();
+    list_of(schemas)
+}
+
+fn list_of(resources: Vec<Value>) -> Value {
+    serde_json::json!({
+        "schemas": [LIST_SCHEMA],
+        "totalResults": resources.len(),
+        "startIndex": 1,
+        "itemsPerPage": resources.len(),
+        "Resources": resources,
+    })
+}
+
+#[derive(Deserialize)]
+struct Row {
+    id: String,
+    resource_type: String,
+    attributes: String,
+    version: i64,
+    created_at_ms: i64,
+    updated_at_ms: i64,
+}
+
+/// The webhook's resources of a type, oldest first
+pub async fn load(db: &D1Database, webhook_id: &str, resource_type: ResourceType) -> Result<Vec<Resource>> {
+    let rows = db
+        .prepare(
+            "SELECT id, resource_type, attributes, version, created_at_ms, updated_at_ms FROM scim_resources \
+             WHERE webhook_id = ?1 AND resource_type = ?2 ORDER BY created_at_ms, id LIMIT ?3",
+        )
+        .bind(&[
+            JsValue::from_str(webhook_id),
+            JsValue::from_str(resource_type.as_str()),
+            JsValue::from_f64(MAX_RESOURCES as f64),
+        ])?
+        .all()
+        .await?
+        .results::<Row>()?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            Some(Resource {
+                id: row.id,
+                resource_type: ResourceType::parse(&row.resource_type)?,
+                attributes: serde_json::from_str(&row.attributes).ok()?,
+                version: row.version,
+                created_at_ms: row.created_at_ms,
+                updated_at_ms: row.updated_at_ms,
+            })
+        })
+        .collect())
+}
+
+/// Persist what a call changed
+pub async fn save(db: &D1Database, webhook_id: &str, change: &Change) -> Result<()> {
+    let statement = match change {
+        Change::Put(resource) => db
+            .prepare(
+                "INSERT INTO scim_resources \
+                 (webhook_id, id, resource_type, attributes, version, created_at_ms, updated_at_ms) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7) \
+                 ON CONFLICT (webhook_id, id) DO UPDATE SET attributes = excluded.attributes, \
+                 version = excluded.version, updated_at_ms = excluded.updated_at_ms",
+            )
+            .bind(&[
+                JsValue::from_str(webhook_id),
+                JsValue::from_str(&resource.id),
+                JsValue::from_str(resource.resource_type.as_str()),
+                JsValue::from_str(&resource.attributes.to_string()),
+                JsValue::from_f64(resource.version as f64),
+                JsValue::from_f64(resource.created_at_ms as f64),
+                JsValue::from_f64(resource.updated_at_ms as f64),
+            ])?,
+        Change::Delete(id) => db
+            .prepare("DELETE FROM scim_resources WHERE webhook_id = ?1 AND id = ?2")
+            .bind(&[JsValue::from_str(webhook_id), JsValue::from_str(id)])?,
+    };
+    statement.run().await?;
+    Ok(())
+}
@@ -0,0 +1,717 @@
+//! Per-webhook hook scripts
+//! A small sandboxed language for the cases config can't express, run on every
+//! delivery after the extraction rules and before the event route is picked:
+//!
+//! ```text
+//! # Sandbox deliveries keep their own event type and are never forwarded
+//! if header("x-env") == "test" and not body("mode") == "live" {
+//!     set event_type = "sandbox." + body("type")
+//!     forward none
+//! }
+//! if event_type == "ping" {
+//!     respond 200 "pong"
+//!     stop
+//! }
+//! if body("data.amount") starts_with "-" { forward "https://refunds.example.com/hook" }
+//! remove header("authorization")
+//! set body("meta.source") = "hook-" + method
+//! remove body("card.number")
+//! ```
+//!
+//! Statements: `if <cond> { ... } [else { ... } | else if ...]`,
+//! `set event_type = <expr>`, `set header("name") = <expr>`,
+//! `remove header("name")`, `set body("dot.path") = <expr>`,
+//! `remove body("dot.path")`, `forward "<url or webhook:uuid>"`, `forward none`
+//! (drop every forwarding target so far, config ones included),
+//! `respond <status> [<body> [<content type>]]` and `stop`. Values are
+//! strings or null: `"literals"`, `event_type`, `method`, `header("name")` and
+//! `body("dot.path")`, joined with `+`. Conditions compare with `==`, `!=`,
+//! `contains`, `starts_with` and `ends_with`, combine with `and`, `or`, `not`
+//! and parentheses, and a bare value is true when present and non-empty.
+//! Body edits only apply to JSON object bodies, set string values (creating
+//! missing parent objects) and re-serialize the body; signatures are still
+//! checked against the body as delivered.
+//!
+//! There are no loops, variables or calls out of the sandbox, scripts are
+//! capped at `MAX_SCRIPT_BYTES`, and forward targets must be literals, so a
+//! sender can't steer deliveries with its payload.
+
+use crate::config::{CustomResponse, FieldSource};
+use crate::environments;
+use crate::pipeline::ParsedRequest;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::rc::Rc;
+
+/// Longest accepted script
+pub const MAX_SCRIPT_BYTES: usize = 16 * 1024;
+
+/// Parsed scripts kept per isolate before the cache is reset
+const CACHED_SCRIPTS: usize = 64;
+
+thread_local! {
+    /// Parse results by source, so a warm isolate parses each webhook's script once
+    static PARSED: RefCell<HashMap<String, Option<Rc<Script>>>> = RefCell::new(HashMap::new());
+}
+
+/// Deepest accepted nesting of blocks and parentheses
+const MAX_NESTING: usize = 16;
+
+/// A syntax error, with the 1-based line it was found on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// What a script decided beyond editing the request
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Outcome {
+    /// Answer the sender with this instead of the route's response or the default JSON
+    pub response: Option<CustomResponse>,
+    /// `forward none` ran: the config's forwarding targets are skipped
+    pub clear_forwarding: bool,
+    /// Targets added by `forward`, after the last `forward none`
+    pub forward: Vec<String>,
+    /// The body as delivered, when the script edited it
+    pub original_body: Option<String>,
+    /// What ran, for the capture's processing trail
+    pub trail: Trail,
+}
+
+/// Effects of a script run on the request itself
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Trail {
+    pub event_type_set: bool,
+    pub headers_set: Vec<String>,
+    pub headers_removed: Vec<String>,
+    /// Body paths set or removed
+    pub body_set: Vec<String>,
+    pub body_removed: Vec<String>,
+    pub forward_none: bool,
+    /// Status chosen with `respond`
+    pub respond: Option<u16>,
+    pub stopped: bool,
+}
+
+fn note(names: &mut Vec<String>, name: &str) {
+    if !names.iter().any(|noted| noted == name) {
+        names.push(name.to_string());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Number(u16),
+    Symbol(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Literal(String),
+    EventType,
+    Method,
+    Field(FieldSource),
+    Concat(Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Equals,
+    NotEquals,
+    Contains,
+    StartsWith,
+    EndsWith,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Cond {
+    Truthy(Expr),
+    Compare(Expr, Comparison, Expr),
+    Not(Box<Cond>),
+    And(Box<Cond>, Box<Cond>),
+    Or(Box<Cond>, Box<Cond>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Stmt {
+    If(Cond, Vec<Stmt>, Vec<Stmt>),
+    SetEventType(Expr),
+    SetHeader(String, Expr),
+    RemoveHeader(String),
+    SetBody(String, Expr),
+    RemoveBody(String),
+    Forward(String),
+    ForwardNone,
+    Respond(u16, Option<Expr>, Option<Expr>),
+    Stop,
+}
+
+/// A parsed script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Script {
+    statements: Vec<Stmt>,
+}
+
+impl Script {
+    /// Parse `source` once per isolate; None when it doesn't parse
+    pub fn cached(source: &str) -> Option<Rc<Self>> {
+        PARSED.with(|parsed| {
+            let mut parsed = parsed.borrow_mut();
+            if let Some(script) = parsed.get(source) {
+                return script.clone();
+            }
+            if parsed.len() >= CACHED_SCRIPTS {
+                parsed.clear();
+            }
+            let script = Self::parse(source).ok().map(Rc::new);
+            parsed.insert(source.to_string(), script.clone());
+            script
+        })
+    }
+
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        if source.len() > MAX_SCRIPT_BYTES {
+            return Err(ParseError {
+                line: 1,
+                message: format!("script exceeds {} bytes", MAX_SCRIPT_BYTES),
+            });
+        }
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+            depth: 0,
+        };
+        let mut statements = Vec::new();
+        while parser.peek().is_some() {
+            statements.push(parser.statement()?);
+        }
+        Ok(Self { statements })
+    }
+
+    /// Run against a delivery, editing its headers, body and event type in place
+    pub fn run(&self, parsed: &mut ParsedRequest) -> Outcome {
+        let mut outcome = Outcome::default();
+        let mut headers_changed = false;
+        execute(&self.statements, parsed, &mut outcome, &mut headers_changed);
+        if headers_changed {
+            parsed.headers_json = serde_json::to_string(&parsed.headers).unwrap_or_default();
+        }
+        outcome
+    }
+}
+
+/// Whether to keep running the statements after this one
+enum Flow {
+    Continue,
+    Stop,
+}
+
+fn execute(statements: &[Stmt], parsed: &mut ParsedRequest, outcome: &mut Outcome, headers_changed: &mut bool) -> Flow {
+    for statement in statements {
+        match statement {
+            Stmt::If(cond, then, otherwise) => {
+                let branch = if test(cond, parsed) { then } else { otherwise };
+                if let Flow::Stop = execute(branch, parsed, outcome, headers_changed) {
+                    return Flow::Stop;
+                }
+            }
+            Stmt::SetEventType(expr) => {
+                parsed.indexed_headers.event_type = eval(expr, parsed);
+                outcome.trail.event_type_set = true;
+            }
+            Stmt::SetHeader(name, expr) => {
+                match eval(expr, parsed) {
+                    Some(value) => parsed.headers.insert(name.clone(), value),
+                    None => parsed.headers.remove(name),
+                };
+                note(&mut outcome.trail.headers_set, name);
+                *headers_changed = true;
+            }
+            Stmt::RemoveHeader(name) => {
+                if parsed.headers.remove(name).is_some() {
+                    note(&mut outcome.trail.headers_removed, name);
+                    *headers_changed = true;
+                }
+            }
+            Stmt::SetBody(path, expr) => {
+                let value = eval(expr, parsed);
+                edit_body(parsed, outcome, path, value);
+                note(&mut outcome.trail.body_set, path);
+            }
+            Stmt::RemoveBody(path) => {
+                if edit_body(parsed, outcome, path, None) {
+                    note(&mut outcome.trail.body_removed, path);
+                }
+            }
+            Stmt::Forward(target) => {
+                if !outcome.forward.contains(target) {
+                    outcome.forward.push(target.clone());
+                }
+            }
+            Stmt::ForwardNone => {
+                outcome.clear_forwarding = true;
+                outcome.forward.clear();
+                outcome.trail.forward_none = true;
+            }
+            Stmt::Respond(status, body, content_type) => {
+                outcome.response = Some(CustomResponse {
+                    status: *status,
+                    body: body.as_ref().and_then(|body| eval(body, parsed)).unwrap_or_default(),
+                    content_type: content_type.as_ref().and_then(|content_type| eval(content_type, parsed)),
+                    headers: BTreeMap::new(),
+                });
+                outcome.trail.respond = Some(*status);
+            }
+            Stmt::Stop => {
+                outcome.trail.stopped = true;
+                return Flow::Stop;
+            }
+        }
+    }
+    Flow::Continue
+}
+
+/// Set a dot path of a JSON object body to a string, or remove it for None;
+/// false when the body isn't a JSON object or nothing changed
+fn edit_body(parsed: &mut ParsedRequest, outcome: &mut Outcome, path: &str, value: Option<String>) -> bool {
+    let Ok(Value::Object(mut document)) = serde_json::from_str::<Value>(&parsed.data) else {
+        return false;
+    };
+    let keys: Vec<&str> = path.split('.').collect();
+    let Some((last, parents)) = keys.split_last() else {
+        return false;
+    };
+    let mut object = &mut document;
+    for key in parents {
+        let child = match value {
+            Some(_) => object.entry(key.to_string()).or_insert_with(|| Value::Object(Map::new())),
+            None => match object.get_mut(*key) {
+                Some(child) => child,
+                None => return false,
+            },
+        };
+        let Value::Object(child) = child else {
+            return false;
+        };
+        object = child;
+    }
+    let changed = match value {
+        Some(value) => object.insert(last.to_string(), Value::String(value.clone())) != Some(Value::String(value)),
+        None => object.remove(*last).is_some(),
+    };
+    if changed {
+        let edited = Value::Object(document).to_string();
+        outcome.original_body.get_or_insert_with(|| parsed.data.clone());
+        parsed.size_bytes = edited.len() as i32;
+        parsed.data = edited;
+    }
+    changed
+}
+
+fn eval(expr: &Expr, parsed: &ParsedRequest) -> Option<String> {
+    match expr {
+        Expr::Literal(value) => Some(value.clone()),
+        Expr::EventType => parsed.indexed_headers.event_type.clone(),
+        Expr::Method => Some(parsed.method.clone()),
+        Expr::Field(source) => source.extract(&parsed.headers, &parsed.data),
+        // Null parts join as empty strings; all-null stays null
+        Expr::Concat(parts) => {
+            let values: Vec<Option<String>> = parts.iter().map(|part| eval(part, parsed)).collect();
+            values
+                .iter()
+                .any(Option::is_some)
+                .then(|| values.into_iter().flatten().collect())
+        }
+    }
+}
+
+fn test(cond: &Cond, parsed: &ParsedRequest) -> bool {
+    match cond {
+        Cond::Truthy(expr) => eval(expr, parsed).is_some_and(|value| !value.is_empty()),
+        Cond::Compare(left, comparison, right) => {
+            let (left, right) = (eval(left, parsed), eval(right, parsed));
+            match comparison {
+                Comparison::Equals => left == right,
+                Comparison::NotEquals => left != right,
+                Comparison::Contains | Comparison::StartsWith | Comparison::EndsWith => {
+                    let (Some(left), Some(right)) = (left, right) else {
+                        return false;
+                    };
+                    match comparison {
+                        Comparison::Contains => left.contains(&right),
+                        Comparison::StartsWith => left.starts_with(&right),
+                        _ => left.ends_with(&right),
+                    }
+                }
+            }
+        }
+        Cond::Not(inner) => !test(inner, parsed),
+        Cond::And(left, right) => test(left, parsed) && test(right, parsed),
+        Cond::Or(left, right) => test(left, parsed) || test(right, parsed),
+    }
+}
+
+const SYMBOLS: &[&str] = &["==", "!=", "(", ")", "{", "}", "=", "+", ";"];
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let mut tokens = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message: &str| ParseError {
+            line: line_number,
+            message: message.to_string(),
+        };
+        let mut rest = line;
+        loop {
+            rest = rest.trim_start();
+            let Some(first) = rest.chars().next() else {
+                break;
+            };
+            if first == '#' {
+                break;
+            }
+            if first == '"' {
+                let mut value = String::new();
+                let mut chars = rest[1..].char_indices();
+                let end = loop {
+                    match chars.next() {
+                        Some((offset, '"')) => break offset + 2,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, 'n')) => value.push('\n'),
+                            Some((_, 't')) => value.push('\t'),
+                            Some((_, escaped @ ('"' | '\\'))) => value.push(escaped),
+                            _ => return Err(error("unknown escape in string")),
+                        },
+                        Some((_, ch)) => value.push(ch),
+                        None => return Err(error("unterminated string")),
+                    }
+                };
+                tokens.push((Token::Str(value), line_number));
+                rest = &rest[end..];
+            } else if first.is_ascii_digit() {
+                let end = rest.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(rest.len());
+                let number = rest[..end].parse().map_err(|_| error("number out of range"))?;
+                tokens.push((Token::Number(number), line_number));
+                rest = &rest[end..];
+            } else if first.is_ascii_alphabetic() || first == '_' {
+                let end = rest
+                    .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+                    .unwrap_or(rest.len());
+                tokens.push((Token::Ident(rest[..end].to_string()), line_number));
+                rest = &rest[end..];
+            } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+                tokens.push((Token::Symbol(symbol), line_number));
+                rest = &rest[symbol.len()..];
+            } else {
+                return Err(error(&format!("unexpected character '{}'", first)));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        let line = self
+            .tokens
+            .get(self.position)
+            .or(self.tokens.last())
+            .map_or(1, |(_, line)| *line);
+        ParseError {
+            line,
+            message: message.into(),
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        token
+    }
+
+    fn is_ident(&self, name: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(ident)) if ident == name)
+    }
+
+    fn eat_ident(&mut self, name: &str) -> bool {
+        let matched = self.is_ident(name);
+        if matched {
+            self.position += 1;
+        }
+        matched
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        let matched = matches!(self.peek(), Some(Token::Symbol(found)) if *found == symbol);
+        if matched {
+            self.position += 1;
+        }
+        matched
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), ParseError> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{}'", symbol)))
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        match self.next() {
+            Some(Token::Str(value)) => Ok(value),
+            _ => {
+                self.position -= 1;
+                Err(self.error("expected a string"))
+            }
+        }
+    }
+
+    fn nest(&mut self) -> Result<(), ParseError> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return Err(self.error("nested too deeply"));
+        }
+        Ok(())
+    }
+
+    /// `header("name")`, after the `header` keyword
+    fn header_name(&mut self) -> Result<String, ParseError> {
+        self.expect_symbol("(")?;
+        let name = self.string()?.to_ascii_lowercase();
+        self.expect_symbol(")")?;
+        if name.is_empty() {
+            return Err(self.error("header name must not be empty"));
+        }
+        Ok(name)
+    }
+
+    /// `body("dot.path")`, after the `body` keyword
+    fn body_path(&mut self) -> Result<String, ParseError> {
+        self.expect_symbol("(")?;
+        let path = self.string()?;
+        self.expect_symbol(")")?;
+        Ok(path)
+    }
+
+    /// A `body_path` that names a field to edit
+    fn edited_path(&mut self) -> Result<String, ParseError> {
+        let path = self.body_path()?;
+        if path.split('.').any(str::is_empty) {
+            return Err(self.error("body path must not have empty parts"));
+        }
+        Ok(path)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, ParseError> {
+        let statement = match self.next() {
+            Some(Token::Ident(keyword)) => match keyword.as_str() {
+                "if" => return self.if_statement(),
+                "set" => {
+                    if self.eat_ident("event_type") {
+                        self.expect_symbol("=")?;
+                        Stmt::SetEventType(self.expr()?)
+                    } else if self.eat_ident("header") {
+                        let name = self.header_name()?;
+                        self.expect_symbol("=")?;
+                        Stmt::SetHeader(name, self.expr()?)
+                    } else if self.eat_ident("body") {
+                        let path = self.edited_path()?;
+                        self.expect_symbol("=")?;
+                        Stmt::SetBody(path, self.expr()?)
+                    } else {
+                        return Err(self.error("expected event_type, header(...) or body(...) after set"));
+                    }
+                }
+                "remove" => {
+                    if self.eat_ident("header") {
+                        Stmt::RemoveHeader(self.header_name()?)
+                    } else if self.eat_ident("body") {
+                        Stmt::RemoveBody(self.edited_path()?)
+                    } else {
+                        return Err(self.error("expected header(...) or body(...) after remove"));
+                    }
+                }
+                "forward" => {
+                    if self.eat_ident("none") {
+                        Stmt::ForwardNone
+                    } else {
+                        let target = self.string()?;
+                        if !environments::is_valid_forward_target(&target) {
+                            return Err(self.error("forward target must be an http(s) URL or webhook:{uuid}"));
+                        }
+                        Stmt::Forward(target)
+                    }
+                }
+                "respond" => {
+                    let status = match self.next() {
+                        Some(Token::Number(status)) if (100..=599).contains(&status) => status,
+                        _ => {
+                            self.position -= 1;
+                            return Err(self.error("expected a status between 100 and 599"));
+                        }
+                    };
+                    let body = self.starts_expr().then(|| self.expr()).transpose()?;
+                    let content_type = match body {
+                        Some(_) if self.starts_expr() => Some(self.expr()?),
+                        _ => None,
+                    };
+                    Stmt::Respond(status, body, content_type)
+                }
+                "stop" => Stmt::Stop,
+                other => {
+                    self.position -= 1;
+                    return Err(self.error(format!("unknown statement '{}'", other)));
+                }
+            },
+            Some(_) => {
+                self.position -= 1;
+                return Err(self.error("expected a statement"));
+            }
+            None => return Err(self.error("expected a statement")),
+        };
+        self.eat_symbol(";");
+        Ok(statement)
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, ParseError> {
+        let cond = self.cond()?;
+        let then = self.block()?;
+        let otherwise = if self.eat_ident("else") {
+            if self.eat_ident("if") {
+                self.nest()?;
+                let nested = self.if_statement()?;
+                self.depth -= 1;
+                vec![nested]
+            } else {
+                self.block()?
+            }
+        } else {
+            Vec::new()
+        };
+        Ok(Stmt::If(cond, then, otherwise))
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        self.expect_symbol("{")?;
+        self.nest()?;
+        let mut statements = Vec::new();
+        while !self.eat_symbol("}") {
+            if self.peek().is_none() {
+                return Err(self.error("expected '}'"));
+            }
+            statements.push(self.statement()?);
+        }
+        self.depth -= 1;
+        Ok(statements)
+    }
+
+    fn cond(&mut self) -> Result<Cond, ParseError> {
+        let mut cond = self.and_cond()?;
+        while self.eat_ident("or") {
+            cond = Cond::Or(Box::new(cond), Box::new(self.and_cond()?));
+        }
+        Ok(cond)
+    }
+
+    fn and_cond(&mut self) -> Result<Cond, ParseError> {
+        let mut cond = self.unary_cond()?;
+        while self.eat_ident("and") {
+            cond = Cond::And(Box::new(cond), Box::new(self.unary_cond()?));
+        }
+        Ok(cond)
+    }
+
+    fn unary_cond(&mut self) -> Result<Cond, ParseError> {
+        if self.eat_ident("not") {
+            self.nest()?;
+            let inner = self.unary_cond()?;
+            self.depth -= 1;
+            return Ok(Cond::Not(Box::new(inner)));
+        }
+        if self.eat_symbol("(") {
+            self.nest()?;
+            let inner = self.cond()?;
+            self.expect_symbol(")")?;
+            self.depth -= 1;
+            return Ok(inner);
+        }
+        let left = self.expr()?;
+        let comparison = if self.eat_symbol("==") {
+            Comparison::Equals
+        } else if self.eat_symbol("!=") {
+            Comparison::NotEquals
+        } else if self.eat_ident("contains") {
+            Comparison::Contains
+        } else if self.eat_ident("starts_with") {
+            Comparison::StartsWith
+        } else if self.eat_ident("ends_with") {
+            Comparison::EndsWith
+        } else {
+            return Ok(Cond::Truthy(left));
+        };
+        Ok(Cond::Compare(left, comparison, self.expr()?))
+    }
+
+    fn starts_expr(&self) -> bool {
+        match self.peek() {
+            Some(Token::Str(_)) => true,
+            Some(Token::Ident(ident)) => matches!(ident.as_str(), "event_type" | "method" | "header" | "body"),
+            _ => false,
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        let mut parts = vec![self.value()?];
+        while self.eat_symbol("+") {
+            parts.push(self.value()?);
+        }
+        Ok(if parts.len() == 1 {
+            parts.remove(0)
+        } else {
+            Expr::Concat(parts)
+        })
+    }
+
+    fn value(&mut self) -> Result<Expr, ParseError> {
+        match self.next() {
+            Some(Token::Str(value)) => Ok(Expr::Literal(value)),
+            Some(Token::Ident(ident)) => match ident.as_str() {
+                "event_type" => Ok(Expr::EventType),
+                "method" => Ok(Expr::Method),
+                "header" => Ok(Expr::Field(FieldSource::Header(self.header_name()?))),
+                "body" => Ok(Expr::Field(FieldSource::Body(self.body_path()?))),
+                _ => {
+                    self.position -= 1;
+                    Err(self.error(format!("unknown value '{}'", ident)))
+                }
+            },
+            _ => {
+                self.position -= 1;
+                Err(self.error("expected a value"))
+            }
+        }
+    }
+}
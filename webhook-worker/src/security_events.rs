@@ -0,0 +1,336 @@
+//! Security event stream
+//! Abuse of the public ingestion URLs and the management API is recorded as
+//! structured events in the `security_events` D1 table: deliveries failing
+//! signature verification, requests from flagged scanners, rejected or
+//! under-privileged API tokens and newly detected UUID enumeration. With
+//! `SIEM_ENDPOINT` set, each event is also POSTed there as JSON (bearer
+//! `SIEM_TOKEN` when that secret exists). Recording never fails the request
+//! that triggered it.
+
+use crate::capture_log;
+use crate::ids;
+use crate::storage::optional_str;
+use futures_util::future::{select, Either};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// `source` of every pushed event, for SIEM-side routing
+pub const SOURCE: &str = "webhook-ingestion";
+
+/// Events older than this are pruned unless `SECURITY_EVENT_RETENTION_DAYS` is set
+const DEFAULT_RETENTION_DAYS: i64 = 90;
+
+/// Give up on a slow SIEM endpoint after this long
+const PUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    /// A delivery's provider signature did not verify (or its timestamp was stale)
+    SignatureFailure,
+    /// A flagged scanner got the decoy response
+    IpBlocked,
+    /// A management API token was unknown, revoked or expired, or lacked the route's role or scope
+    TokenMisuse,
+    /// An IP was newly flagged for probing unknown or decoy UUIDs
+    Enumeration,
+}
+
+impl Kind {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "signature_failure" => Some(Self::SignatureFailure),
+            "ip_blocked" => Some(Self::IpBlocked),
+            "token_misuse" => Some(Self::TokenMisuse),
+            "enumeration" => Some(Self::Enumeration),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::SignatureFailure => "signature_failure",
+            Self::IpBlocked => "ip_blocked",
+            Self::TokenMisuse => "token_misuse",
+            Self::Enumeration => "enumeration",
+        }
+    }
+
+    /// Default severity; flagging a scanner matters more than each of its later requests
+    pub fn severity(self) -> Severity {
+        match self {
+            Self::IpBlocked => Severity::Low,
+            Self::SignatureFailure | Self::TokenMisuse => Severity::Medium,
+            Self::Enumeration => Severity::High,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+        }
+    }
+}
+
+/// One security event, built up where it is detected and written once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityEvent {
+    pub id: String,
+    pub created_at_ms: i64,
+    pub kind: Kind,
+    pub severity: Severity,
+    pub ip: Option<String>,
+    /// Public UUID the request was sent to
+    pub uuid: Option<String>,
+    pub webhook_id: Option<String>,
+    /// API caller (`token:{id}`, `oidc:{sub}`) when the token resolved
+    pub actor: Option<String>,
+    pub user_agent: Option<String>,
+    /// Kind-specific fields (verification result, required role, miss count, ...)
+    #[serde(with = "json_text")]
+    pub detail: Option<Value>,
+}
+
+impl SecurityEvent {
+    pub fn new(kind: Kind, created_at_ms: i64) -> Self {
+        Self {
+            id: ids::ulid(created_at_ms),
+            created_at_ms,
+            kind,
+            severity: kind.severity(),
+            ip: None,
+            uuid: None,
+            webhook_id: None,
+            actor: None,
+            user_agent: None,
+            detail: None,
+        }
+    }
+
+    /// Start an event for a request, taking its client IP and user agent
+    pub fn from_request(req: &Request, kind: Kind) -> Self {
+        let header = |name: &str| req.headers().get(name).ok().flatten();
+        let mut event = Self::new(kind, capture_log::now_ms());
+        event.ip = header("CF-Connecting-IP");
+        event.user_agent = header("User-Agent");
+        event
+    }
+
+    pub fn ip(mut self, ip: Option<String>) -> Self {
+        self.ip = ip;
+        self
+    }
+
+    pub fn uuid(mut self, uuid: impl Into<String>) -> Self {
+        self.uuid = Some(uuid.into());
+        self
+    }
+
+    pub fn webhook(mut self, webhook_id: impl Into<String>) -> Self {
+        self.webhook_id = Some(webhook_id.into());
+        self
+    }
+
+    pub fn actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = Some(actor.into());
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: Option<String>) -> Self {
+        self.user_agent = user_agent;
+        self
+    }
+
+    pub fn detail(mut self, detail: Value) -> Self {
+        self.detail = Some(detail);
+        self
+    }
+
+    /// Flat JSON document pushed to the SIEM endpoint, with an RFC 3339 timestamp
+    pub fn siem_payload(&self) -> Value {
+        let timestamp = chrono::DateTime::from_timestamp_millis(self.created_at_ms)
+            .map(|time| time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true));
+        serde_json::json!({
+            "source": SOURCE,
+            "id": self.id,
+            "timestamp": timestamp,
+            "timestamp_ms": self.created_at_ms,
+            "kind": self.kind,
+            "severity": self.severity,
+            "ip": self.ip,
+            "uuid": self.uuid,
+            "webhook_id": self.webhook_id,
+            "actor": self.actor,
+            "user_agent": self.user_agent,
+            "detail": self.detail,
+        })
+    }
+}
+
+/// Filters for listing security events
+#[derive(Debug, Default)]
+pub struct EventQuery {
+    pub kind: Option<Kind>,
+    pub ip: Option<String>,
+    pub webhook_id: Option<String>,
+    /// Unix seconds (inclusive)
+    pub since: Option<i64>,
+    pub limit: u32,
+    pub offset: u32,
+}
+
+/// Store an event and push it to the SIEM endpoint. Failures are logged rather
+/// than failing the request being handled.
+pub async fn record(env: &Env, event: SecurityEvent) {
+    log_info!(
+        "🛡️  Security event {} ({}) from {}",
+        event.kind.as_str(),
+        event.severity.as_str(),
+        event.ip.as_deref().unwrap_or("unknown IP")
+    );
+    match env.d1("DB") {
+        Ok(db) => {
+            if let Err(e) = insert(&db, &event).await {
+                log_error!("❌ Failed to store security event ({}): {:?}", event.kind.as_str(), e);
+            }
+        }
+        Err(e) => log_error!("❌ Failed to store security event ({}): {:?}", event.kind.as_str(), e),
+    }
+    if let Some(endpoint) = env.var("SIEM_ENDPOINT").ok().map(|value| value.to_string()) {
+        if endpoint.is_empty() {
+            return;
+        }
+        let token = env.secret("SIEM_TOKEN").ok().map(|secret| secret.to_string());
+        if let Err(e) = push(&endpoint, token.as_deref(), &event).await {
+            log_warn!("⚠️  Failed to push security event to SIEM: {}", e);
+        }
+    }
+}
+
+async fn insert(db: &D1Database, event: &SecurityEvent) -> Result<()> {
+    let detail = event.detail.as_ref().map(Value::to_string);
+    db.prepare(
+        "INSERT INTO security_events \
+         (id, created_at_ms, kind, severity, ip, uuid, webhook_id, actor, user_agent, detail) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+    )
+    .bind(&[
+        JsValue::from_str(&event.id),
+        JsValue::from_f64(event.created_at_ms as f64),
+        JsValue::from_str(event.kind.as_str()),
+        JsValue::from_str(event.severity.as_str()),
+        optional_str(&event.ip),
+        optional_str(&event.uuid),
+        optional_str(&event.webhook_id),
+        optional_str(&event.actor),
+        optional_str(&event.user_agent),
+        optional_str(&detail),
+    ])?
+    .run()
+    .await?;
+    Ok(())
+}
+
+async fn push(endpoint: &str, token: Option<&str>, event: &SecurityEvent) -> std::result::Result<(), String> {
+    let headers = Headers::new();
+    headers.set("Content-Type", "application/json").map_err(|e| e.to_string())?;
+    if let Some(token) = token.filter(|token| !token.is_empty()) {
+        headers.set("Authorization", &format!("Bearer {}", token)).map_err(|e| e.to_string())?;
+    }
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_headers(headers)
+        .with_body(Some(event.siem_payload().to_string().into()));
+    let request = Request::new_with_init(endpoint, &init).map_err(|e| e.to_string())?;
+
+    let send = async { Fetch::Request(request).send().await };
+    match select(Box::pin(send), Delay::from(PUSH_TIMEOUT)).await {
+        Either::Left((Ok(response), _)) if (200..300).contains(&response.status_code()) => Ok(()),
+        Either::Left((Ok(response), _)) => Err(format!("endpoint answered {}", response.status_code())),
+        Either::Left((Err(e), _)) => Err(e.to_string()),
+        Either::Right(_) => Err("timed out".to_string()),
+    }
+}
+
+/// List security events, newest first
+pub async fn list(db: &D1Database, query: &EventQuery) -> Result<Vec<SecurityEvent>> {
+    let mut conditions = Vec::new();
+    let mut params = Vec::new();
+
+    let kind = query.kind.map(|kind| kind.as_str().to_string());
+    let text_filters = [("kind", &kind), ("ip", &query.ip), ("webhook_id", &query.webhook_id)];
+    for (column, value) in text_filters {
+        if let Some(value) = value {
+            params.push(JsValue::from_str(value));
+            conditions.push(format!("{} = ?{}", column, params.len()));
+        }
+    }
+    if let Some(since) = query.since {
+        params.push(JsValue::from_f64((since * 1000) as f64));
+        conditions.push(format!("created_at_ms >= ?{}", params.len()));
+    }
+
+    let where_clause = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", conditions.join(" AND "))
+    };
+
+    params.push(JsValue::from_f64(query.limit as f64));
+    params.push(JsValue::from_f64(query.offset as f64));
+    let sql = format!(
+        "SELECT id, created_at_ms, kind, severity, ip, uuid, webhook_id, actor, user_agent, detail \
+         FROM security_events {} ORDER BY created_at_ms DESC, id DESC LIMIT ?{} OFFSET ?{}",
+        where_clause,
+        params.len() - 1,
+        params.len()
+    );
+
+    db.prepare(&sql).bind(&params)?.all().await?.results::<SecurityEvent>()
+}
+
+/// Drop events past the retention window (scheduled)
+pub async fn prune(env: &Env, now_ms: i64) -> Result<()> {
+    let days = env
+        .var("SECURITY_EVENT_RETENTION_DAYS")
+        .ok()
+        .and_then(|value| value.to_string().parse::<i64>().ok())
+        .unwrap_or(DEFAULT_RETENTION_DAYS);
+    env.d1("DB")?
+        .prepare("DELETE FROM security_events WHERE created_at_ms < ?1")
+        .bind(&[JsValue::from_f64((now_ms - days * 86_400_000) as f64)])?
+        .run()
+        .await?;
+    Ok(())
+}
+
+/// Details are stored as JSON text and returned as JSON values
+mod json_text {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde_json::Value;
+
+    pub fn serialize<S: Serializer>(value: &Option<Value>, serializer: S) -> Result<S::Ok, S::Error> {
+        value.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+        let text = Option::<String>::deserialize(deserializer)?;
+        Ok(text.and_then(|text| serde_json::from_str(&text).ok()))
+    }
+}
@@ -0,0 +1,137 @@
+//! Delivery sessions
+//! A business flow (an order created, paid, shipped) arrives as several captures
+//! scattered among others. Sessions put them back together: captures sharing a
+//! value of one of the webhook's correlation `keys` (an `X-Request-Id` header,
+//! an order ID in the body; see `FieldSource`) belong together, as long as each
+//! arrives within `gap_seconds` of the previous one with that value. Captures
+//! carrying none of the keys are grouped by time alone, each one joining the
+//! previous keyless capture within the gap. A capture carrying two keys links
+//! their sessions. Sessions are worked out when listed, from the most recent
+//! captures of the requested time range, so a flow reaching past it is cut.
+
+use crate::config::{FieldSource, Sessions};
+use crate::storage::StoredRequest;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Most captures read to work out one listing
+pub const MAX_CAPTURES: u32 = 2000;
+
+/// A correlation value the session's captures share
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SessionKey {
+    /// `header:NAME` or `body:PATH`
+    pub source: String,
+    pub value: String,
+}
+
+/// Related captures, oldest first
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Session {
+    /// ID of the session's first capture
+    pub id: String,
+    pub started_at_ms: i64,
+    pub ended_at_ms: i64,
+    pub capture_ids: Vec<String>,
+    /// Distinct event types in the order they first appear
+    pub event_types: Vec<String>,
+    /// Empty for a session grouped by time alone
+    pub keys: Vec<SessionKey>,
+}
+
+fn describe(source: &FieldSource) -> String {
+    match source {
+        FieldSource::Header(name) => format!("header:{}", name.to_ascii_lowercase()),
+        FieldSource::Body(path) => format!("body:{}", path),
+    }
+}
+
+fn received_ms(request: &StoredRequest) -> i64 {
+    request.received_at_ms.unwrap_or(request.received_at * 1000)
+}
+
+fn root(parents: &mut [usize], mut index: usize) -> usize {
+    while parents[index] != index {
+        parents[index] = parents[parents[index]];
+        index = parents[index];
+    }
+    index
+}
+
+fn join(parents: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (root(parents, a), root(parents, b));
+    parents[a.max(b)] = a.min(b);
+}
+
+/// Group captures into sessions, newest session first
+pub fn group(requests: &[StoredRequest], config: &Sessions) -> Vec<Session> {
+    let gap_ms = config.gap_seconds as i64 * 1000;
+    let mut order: Vec<usize> = (0..requests.len()).collect();
+    order.sort_by_key(|index| (received_ms(&requests[*index]), *index));
+
+    let mut parents: Vec<usize> = (0..requests.len()).collect();
+    let mut keys: Vec<Vec<SessionKey>> = vec![Vec::new(); requests.len()];
+    let mut last_by_key: HashMap<(usize, String), (usize, i64)> = HashMap::new();
+    let mut last_keyless: Option<(usize, i64)> = None;
+    for &index in &order {
+        let request = &requests[index];
+        let at = received_ms(request);
+        let headers: HashMap<String, String> = serde_json::from_str(&request.headers).unwrap_or_default();
+        let values: Vec<(usize, String)> = config
+            .keys
+            .iter()
+            .enumerate()
+            .filter_map(|(key, source)| Some((key, source.extract(&headers, &request.data)?)))
+            .collect();
+        if values.is_empty() {
+            if let Some((previous, _)) = last_keyless.filter(|(_, previous_at)| at - previous_at <= gap_ms) {
+                join(&mut parents, previous, index);
+            }
+            last_keyless = Some((index, at));
+            continue;
+        }
+        for (key, value) in values {
+            let last = last_by_key.insert((key, value.clone()), (index, at));
+            if let Some((previous, _)) = last.filter(|(_, previous_at)| at - previous_at <= gap_ms) {
+                join(&mut parents, previous, index);
+            }
+            keys[index].push(SessionKey {
+                source: describe(&config.keys[key]),
+                value,
+            });
+        }
+    }
+
+    let mut sessions: Vec<Session> = Vec::new();
+    let mut by_root: HashMap<usize, usize> = HashMap::new();
+    for &index in &order {
+        let request = &requests[index];
+        let at = received_ms(request);
+        let position = *by_root.entry(root(&mut parents, index)).or_insert_with(|| {
+            sessions.push(Session {
+                id: request.id.clone(),
+                started_at_ms: at,
+                ended_at_ms: at,
+                capture_ids: Vec::new(),
+                event_types: Vec::new(),
+                keys: Vec::new(),
+            });
+            sessions.len() - 1
+        });
+        let session = &mut sessions[position];
+        session.ended_at_ms = at;
+        session.capture_ids.push(request.id.clone());
+        if let Some(event_type) = &request.event_type {
+            if !session.event_types.contains(event_type) {
+                session.event_types.push(event_type.clone());
+            }
+        }
+        for key in keys[index].drain(..) {
+            if !session.keys.contains(&key) {
+                session.keys.push(key);
+            }
+        }
+    }
+    sessions.reverse();
+    sessions
+}
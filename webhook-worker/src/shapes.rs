@@ -0,0 +1,298 @@
+//! Payload shape tracking and schema regression detection
+//! With `shapes` in the webhook config, every JSON delivery's shape (each field
+//! path with its JSON types, as `docs.rs` infers them) is fingerprinted and
+//! counted per event type in `payload_shapes`. The first delivery of a new
+//! shape is diffed against the event type's dominant shape, the one seen most
+//! often so far: fields added, removed and changed type. The diff is stored
+//! with the new shape and, with `notify_url`, POSTed there, so a provider-side
+//! breaking change is noticed before it reaches production. The first shape of
+//! an event type is its baseline and is never reported.
+
+use crate::config::ShapeTracking;
+use crate::docs;
+use crate::forward;
+use crate::storage::CaptureRecord;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Shapes kept per event type; a provider sending more is not following any schema
+pub const MAX_SHAPES: u32 = 50;
+
+/// Field paths (`data.items[].price`) and the JSON types seen at each
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shape(pub BTreeMap<String, BTreeSet<String>>);
+
+impl Shape {
+    /// Shape of a JSON object or array body; None for anything else
+    pub fn of(body: &Value) -> Option<Self> {
+        if !body.is_object() && !body.is_array() {
+            return None;
+        }
+        let mut fields = BTreeMap::new();
+        walk(body, String::new(), &mut fields);
+        Some(Self(fields))
+    }
+
+    /// `sha256:` and 16 hex digits over the canonical field list
+    pub fn fingerprint(&self) -> String {
+        let canonical = serde_json::to_string(&self.0).unwrap_or_default();
+        let digest = Sha256::digest(canonical.as_bytes());
+        let hex: String = digest.iter().take(8).map(|byte| format!("{:02x}", byte)).collect();
+        format!("sha256:{}", hex)
+    }
+}
+
+fn walk(value: &Value, path: String, fields: &mut BTreeMap<String, BTreeSet<String>>) {
+    if !path.is_empty() {
+        fields.entry(path.clone()).or_default().insert(docs::type_name(value).to_string());
+    }
+    match value {
+        Value::Object(members) => {
+            for (name, member) in members {
+                let child = if path.is_empty() { name.clone() } else { format!("{}.{}", path, name) };
+                walk(member, child, fields);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                walk(item, format!("{}[]", path), fields);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A field whose JSON types differ between two shapes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypeChange {
+    pub path: String,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+/// How a new shape differs from the dominant one
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShapeDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub type_changed: Vec<TypeChange>,
+}
+
+impl ShapeDiff {
+    /// Removed fields and changed types break consumers; added fields usually don't
+    pub fn breaking(&self) -> bool {
+        !self.removed.is_empty() || !self.type_changed.is_empty()
+    }
+}
+
+/// What changed from `previous` to `current`
+pub fn diff(previous: &Shape, current: &Shape) -> ShapeDiff {
+    let mut changes = ShapeDiff::default();
+    for (path, types) in &current.0 {
+        match previous.0.get(path) {
+            None => changes.added.push(path.clone()),
+            Some(before) if before != types => changes.type_changed.push(TypeChange {
+                path: path.clone(),
+                before: before.iter().cloned().collect(),
+                after: types.iter().cloned().collect(),
+            }),
+            Some(_) => {}
+        }
+    }
+    changes.removed = previous.0.keys().filter(|path| !current.0.contains_key(*path)).cloned().collect();
+    changes
+}
+
+/// A shape seen for the first time
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NewShape {
+    pub fingerprint: String,
+    /// The dominant shape it was compared with; None for an event type's first shape
+    pub previous_fingerprint: Option<String>,
+    pub diff: Option<ShapeDiff>,
+}
+
+/// JSON body POSTed to `notify_url` for a new shape
+pub fn notification(uuid: &str, event_type: Option<&str>, shape: &NewShape, capture_id: &str, now_ms: i64) -> Value {
+    let breaking = shape.diff.as_ref().is_some_and(ShapeDiff::breaking);
+    serde_json::json!({
+        "type": if breaking { "shape.breaking" } else { "shape.changed" },
+        "webhook_id": uuid,
+        "event_type": event_type,
+        "fingerprint": shape.fingerprint,
+        "previous_fingerprint": shape.previous_fingerprint,
+        "diff": shape.diff,
+        "capture_id": capture_id,
+        "detected_at_ms": now_ms,
+    })
+}
+
+/// A tracked shape, for the management API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredShape {
+    /// Empty for captures without an event type
+    pub event_type: String,
+    pub fingerprint: String,
+    pub count: i64,
+    pub first_seen_ms: i64,
+    pub last_seen_ms: i64,
+    /// First capture with the shape
+    pub capture_id: String,
+    pub previous_fingerprint: Option<String>,
+    #[serde(with = "json_text")]
+    pub diff: Option<Value>,
+    #[serde(with = "json_text")]
+    pub shape: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct DominantRow {
+    fingerprint: String,
+    shape: String,
+}
+
+#[derive(Deserialize)]
+struct CountRow {
+    shapes: f64,
+}
+
+/// Count a delivery's shape; returns the shape when it is new for the event type
+pub async fn observe(
+    db: &D1Database,
+    webhook_id: &str,
+    event_type: Option<&str>,
+    shape: &Shape,
+    capture_id: &str,
+    now_ms: i64,
+) -> Result<Option<NewShape>> {
+    let event_type = event_type.unwrap_or_default();
+    let fingerprint = shape.fingerprint();
+    let key = [JsValue::from_str(webhook_id), JsValue::from_str(event_type), JsValue::from_str(&fingerprint)];
+    let seen = db
+        .prepare(
+            "UPDATE payload_shapes SET count = count + 1, last_seen_ms = ?4 \
+             WHERE webhook_id = ?1 AND event_type = ?2 AND fingerprint = ?3",
+        )
+        .bind(&[key[0].clone(), key[1].clone(), key[2].clone(), JsValue::from_f64(now_ms as f64)])?
+        .run()
+        .await?;
+    if seen.meta()?.and_then(|meta| meta.changes).unwrap_or(0) > 0 {
+        return Ok(None);
+    }
+
+    let kept = db
+        .prepare("SELECT COUNT(*) AS shapes FROM payload_shapes WHERE webhook_id = ?1 AND event_type = ?2")
+        .bind(&key[..2])?
+        .first::<CountRow>(None)
+        .await?
+        .map_or(0.0, |row| row.shapes);
+    if kept >= MAX_SHAPES as f64 {
+        return Ok(None);
+    }
+    let dominant = db
+        .prepare(
+            "SELECT fingerprint, shape FROM payload_shapes WHERE webhook_id = ?1 AND event_type = ?2 \
+             ORDER BY count DESC, first_seen_ms LIMIT 1",
+        )
+        .bind(&key[..2])?
+        .first::<DominantRow>(None)
+        .await?;
+    let (previous_fingerprint, diff) = match dominant {
+        Some(row) => {
+            let previous: Shape = serde_json::from_str(&row.shape).unwrap_or_default();
+            (Some(row.fingerprint), Some(diff(&previous, shape)))
+        }
+        None => (None, None),
+    };
+    let diff_json = diff.as_ref().and_then(|diff| serde_json::to_string(diff).ok());
+    db.prepare(
+        "INSERT OR IGNORE INTO payload_shapes (webhook_id, event_type, fingerprint, shape, count, first_seen_ms, \
+         last_seen_ms, capture_id, previous_fingerprint, diff) VALUES (?1, ?2, ?3, ?4, 1, ?5, ?5, ?6, ?7, ?8)",
+    )
+    .bind(&[
+        key[0].clone(),
+        key[1].clone(),
+        key[2].clone(),
+        JsValue::from_str(&serde_json::to_string(shape).unwrap_or_default()),
+        JsValue::from_f64(now_ms as f64),
+        JsValue::from_str(capture_id),
+        previous_fingerprint.as_deref().map_or(JsValue::NULL, JsValue::from_str),
+        diff_json.as_deref().map_or(JsValue::NULL, JsValue::from_str),
+    ])?
+    .run()
+    .await?;
+    Ok(Some(NewShape {
+        fingerprint,
+        previous_fingerprint,
+        diff,
+    }))
+}
+
+/// Track a stored capture's shape and report a new one (errors are logged, never fail the capture)
+pub async fn track(db: &D1Database, tracking: &ShapeTracking, uuid: &str, record: &CaptureRecord) {
+    // Form-encoded providers (Slack, Twilio) have their decoded form in `canonical_data`
+    let body = record.canonical_data.as_deref().unwrap_or(&record.data);
+    let Some(shape) = serde_json::from_str::<Value>(body).ok().as_ref().and_then(Shape::of) else {
+        return;
+    };
+    let event_type = record.indexed_headers.event_type.as_deref();
+    let (capture_id, now_ms) = (record.id.as_str(), record.received_at_ms);
+    let new = match observe(db, &record.webhook_id, event_type, &shape, capture_id, now_ms).await {
+        Ok(Some(new)) => new,
+        Ok(None) => return,
+        Err(e) => {
+            log_warn!("⚠️  Failed to track payload shape: {:?}", e);
+            return;
+        }
+    };
+    let Some(diff) = &new.diff else {
+        return;
+    };
+    log_info!(
+        "🧬 Webhook {} has a new {} shape {} (+{} -{} ~{})",
+        uuid,
+        event_type.unwrap_or("untyped"),
+        new.fingerprint,
+        diff.added.len(),
+        diff.removed.len(),
+        diff.type_changed.len()
+    );
+    if let Some(notify_url) = &tracking.notify_url {
+        if diff.breaking() || !tracking.breaking_only {
+            forward::notify(notify_url, &notification(uuid, event_type, &new, capture_id, now_ms)).await;
+        }
+    }
+}
+
+/// Tracked shapes of a webhook, newest first
+pub async fn list(db: &D1Database, webhook_id: &str, event_type: Option<&str>) -> Result<Vec<StoredShape>> {
+    let mut sql = "SELECT event_type, fingerprint, count, first_seen_ms, last_seen_ms, capture_id, \
+                   previous_fingerprint, diff, shape FROM payload_shapes WHERE webhook_id = ?1"
+        .to_string();
+    let mut params = vec![JsValue::from_str(webhook_id)];
+    if let Some(event_type) = event_type {
+        sql.push_str(" AND event_type = ?2");
+        params.push(JsValue::from_str(event_type));
+    }
+    sql.push_str(" ORDER BY first_seen_ms DESC");
+    db.prepare(&sql).bind(&params)?.all().await?.results::<StoredShape>()
+}
+
+/// Diffs and shapes are stored as JSON text and returned as JSON values
+mod json_text {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde_json::Value;
+
+    pub fn serialize<S: Serializer>(value: &Option<Value>, serializer: S) -> Result<S::Ok, S::Error> {
+        value.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+        let text = Option::<String>::deserialize(deserializer)?;
+        Ok(text.and_then(|text| serde_json::from_str(&text).ok()))
+    }
+}
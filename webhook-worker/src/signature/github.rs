@@ -0,0 +1,25 @@
+//! GitHub: `X-Hub-Signature-256: sha256={hex}` over the body (no timestamp)
+
+use super::{hmac_matches, SignatureVerifier, Signed, SignedRequest};
+use crate::config::SignatureProvider;
+
+pub struct Github;
+
+impl SignatureVerifier for Github {
+    fn provider(&self) -> SignatureProvider {
+        SignatureProvider::Github
+    }
+
+    fn description(&self) -> &'static str {
+        "X-Hub-Signature-256 (HMAC-SHA256 over the body)"
+    }
+
+    fn check(&self, secret: &str, request: &SignedRequest<'_>) -> Signed {
+        let signature = request
+            .headers
+            .get("x-hub-signature-256")?
+            .trim()
+            .strip_prefix("sha256=")?;
+        Some((hmac_matches(secret, request.body.as_bytes(), signature), None))
+    }
+}
@@ -0,0 +1,63 @@
+//! Generic HMAC for providers without a dedicated verifier: the header, hash,
+//! encoding and optional prefix come from `signature.hmac`. With a
+//! `timestamp_header`, the signed payload is `{timestamp}.{body}` and stale
+//! timestamps are flagged like Stripe's.
+
+use super::{decode_hex, SignatureVerifier, Signed, SignedRequest};
+use crate::config::{HmacAlgorithm, SignatureEncoding, SignatureProvider};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+pub struct GenericHmac;
+
+fn matches(algorithm: HmacAlgorithm, secret: &str, payload: &[u8], expected: &[u8]) -> bool {
+    let key = secret.as_bytes();
+    match algorithm {
+        HmacAlgorithm::Sha256 => Hmac::<Sha256>::new_from_slice(key)
+            .map(|mac| mac.chain_update(payload).verify_slice(expected).is_ok())
+            .unwrap_or(false),
+        HmacAlgorithm::Sha1 => Hmac::<Sha1>::new_from_slice(key)
+            .map(|mac| mac.chain_update(payload).verify_slice(expected).is_ok())
+            .unwrap_or(false),
+        HmacAlgorithm::Sha512 => Hmac::<Sha512>::new_from_slice(key)
+            .map(|mac| mac.chain_update(payload).verify_slice(expected).is_ok())
+            .unwrap_or(false),
+    }
+}
+
+impl SignatureVerifier for GenericHmac {
+    fn provider(&self) -> SignatureProvider {
+        SignatureProvider::Hmac
+    }
+
+    fn description(&self) -> &'static str {
+        "Any HMAC header (`hmac`: header, sha256/sha1/sha512, hex/base64, prefix, optional timestamp header)"
+    }
+
+    fn check(&self, secret: &str, request: &SignedRequest<'_>) -> Signed {
+        let scheme = request.config.hmac.as_ref()?;
+        let value = request.headers.get(&scheme.header.to_ascii_lowercase())?.trim();
+        let value = match &scheme.prefix {
+            Some(prefix) => value.strip_prefix(prefix.as_str())?,
+            None => value,
+        };
+        let timestamp = match &scheme.timestamp_header {
+            Some(header) => Some(request.headers.get(&header.to_ascii_lowercase())?.trim().parse::<i64>().ok()?),
+            None => None,
+        };
+
+        let signature = match scheme.encoding {
+            SignatureEncoding::Hex => decode_hex(value),
+            SignatureEncoding::Base64 => BASE64.decode(value).ok(),
+        };
+        let payload = match timestamp {
+            Some(timestamp) => format!("{}.{}", timestamp, request.body),
+            None => request.body.to_string(),
+        };
+        let valid = signature.is_some_and(|signature| matches(scheme.algorithm, secret, payload.as_bytes(), &signature));
+        Some((valid, timestamp))
+    }
+}
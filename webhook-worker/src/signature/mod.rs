@@ -0,0 +1,215 @@
+//! Provider signature verification with replay protection
+//! Each provider's scheme is a `SignatureVerifier` in the `VERIFIERS` registry,
+//! picked per webhook by `signature.provider`; adding a provider means adding a
+//! verifier module and listing it there, the capture handler only ever calls
+//! `verify`. A correctly signed request whose signed timestamp is outside the
+//! tolerance window is flagged `replay_suspected`, matching the providers' own
+//! verification libraries; schemes that sign no timestamp are never flagged.
+//! While a signing secret is being rotated, deliveries signed with either the
+//! new or the previous secret verify until the previous one expires.
+//! PayPal signs with a certificate instead of a shared secret, so `verify`
+//! asks PayPal's API about its deliveries (see `paypal.rs`).
+
+mod adyen;
+mod github;
+mod hmac;
+pub mod paypal;
+mod shopify;
+mod slack;
+mod square;
+pub mod standard;
+mod stripe;
+mod svix;
+mod twilio;
+
+use crate::config::{self, SignatureConfig, SignatureProvider};
+use ::hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::HashMap;
+use worker::*;
+
+/// Verification outcome, stored in `webhook_data.verification`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    Valid,
+    /// No signature or timestamp header
+    Missing,
+    Invalid,
+    /// Signature is valid but the timestamp is outside the tolerance window
+    ReplaySuspected,
+}
+
+impl Verification {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Valid => "valid",
+            Self::Missing => "missing",
+            Self::Invalid => "invalid",
+            Self::ReplaySuspected => "replay_suspected",
+        }
+    }
+}
+
+/// The parts of a delivery a verifier may read
+pub struct SignedRequest<'a> {
+    pub config: &'a SignatureConfig,
+    pub url: &'a Url,
+    /// Lowercase header names
+    pub headers: &'a HashMap<String, String>,
+    pub body: &'a str,
+}
+
+/// `(signature matches, signed timestamp)`, or None when headers are missing
+pub type Signed = Option<(bool, Option<i64>)>;
+
+/// One provider's signature scheme
+pub trait SignatureVerifier: Sync {
+    fn provider(&self) -> SignatureProvider;
+
+    /// What the provider signs, for provider listings
+    fn description(&self) -> &'static str;
+
+    /// Check the delivery against one resolved secret
+    fn check(&self, secret: &str, request: &SignedRequest<'_>) -> Signed;
+}
+
+/// Every known verifier, one per provider
+static VERIFIERS: &[&dyn SignatureVerifier] = &[
+    &stripe::Stripe,
+    &github::Github,
+    &shopify::Shopify,
+    &slack::Slack,
+    &twilio::Twilio,
+    &svix::Svix,
+    &standard::StandardWebhooks,
+    &paypal::Paypal,
+    &adyen::Adyen,
+    &square::Square,
+    &hmac::GenericHmac,
+];
+
+/// The verifier for a provider
+pub fn verifier(provider: SignatureProvider) -> Option<&'static dyn SignatureVerifier> {
+    VERIFIERS.iter().copied().find(|verifier| verifier.provider() == provider)
+}
+
+/// A registered provider, as listed by `GET /api/templates`
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderInfo {
+    pub provider: SignatureProvider,
+    pub description: &'static str,
+}
+
+/// Registered providers, in registry order
+pub fn providers() -> Vec<ProviderInfo> {
+    VERIFIERS
+        .iter()
+        .map(|verifier| ProviderInfo {
+            provider: verifier.provider(),
+            description: verifier.description(),
+        })
+        .collect()
+}
+
+/// Verify a delivery against the webhook's signature config at `now` (Unix seconds)
+pub async fn verify(
+    env: &Env,
+    config: &SignatureConfig,
+    url: &Url,
+    headers: &HashMap<String, String>,
+    body: &str,
+    now: i64,
+) -> Verification {
+    let secrets: Vec<String> = config
+        .active_secrets(now * 1000)
+        .into_iter()
+        .filter_map(|reference| {
+            let secret = config::resolve_secret(env, reference);
+            if secret.is_none() {
+                log_warn!("⚠️  Signature secret {} is not configured", reference);
+            }
+            secret
+        })
+        .collect();
+    if config.provider == SignatureProvider::Paypal {
+        return paypal::verify(config, &secrets, headers, body, now).await;
+    }
+    verify_with_secrets(&secrets, config, url, headers, body, now)
+}
+
+/// `verify_with_secret` against each secret of a rotation in turn: the first
+/// outcome other than `Invalid`, else `Invalid`
+pub fn verify_with_secrets(
+    secrets: &[String],
+    config: &SignatureConfig,
+    url: &Url,
+    headers: &HashMap<String, String>,
+    body: &str,
+    now: i64,
+) -> Verification {
+    secrets
+        .iter()
+        .map(|secret| verify_with_secret(secret, config, url, headers, body, now))
+        .find(|verification| *verification != Verification::Invalid)
+        .unwrap_or(Verification::Invalid)
+}
+
+/// `verify` with the secret already resolved (no Workers environment needed)
+pub fn verify_with_secret(
+    secret: &str,
+    config: &SignatureConfig,
+    url: &Url,
+    headers: &HashMap<String, String>,
+    body: &str,
+    now: i64,
+) -> Verification {
+    let Some(verifier) = verifier(config.provider) else {
+        return Verification::Missing;
+    };
+    let request = SignedRequest {
+        config,
+        url,
+        headers,
+        body,
+    };
+
+    match verifier.check(secret, &request) {
+        None => Verification::Missing,
+        Some((false, _)) => Verification::Invalid,
+        Some((true, Some(timestamp))) if (now - timestamp).abs() > config.tolerance_seconds => {
+            Verification::ReplaySuspected
+        }
+        Some((true, _)) => Verification::Valid,
+    }
+}
+
+/// Constant-time comparison of HMAC-SHA256(secret, payload) with a hex signature
+pub fn hmac_matches(secret: &str, payload: &[u8], hex_signature: &str) -> bool {
+    let expected = match decode_hex(hex_signature) {
+        Some(bytes) => bytes,
+        None => return false,
+    };
+    hmac_sha256_matches(secret.as_bytes(), payload, &expected)
+}
+
+/// Constant-time comparison of HMAC-SHA256(key, payload) with raw signature bytes
+fn hmac_sha256_matches(key: &[u8], payload: &[u8], expected: &[u8]) -> bool {
+    let mut mac = match Hmac::<Sha256>::new_from_slice(key) {
+        Ok(mac) => mac,
+        Err(_) => return false,
+    };
+    mac.update(payload);
+    mac.verify_slice(expected).is_ok()
+}
+
+pub fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    let value = value.trim();
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
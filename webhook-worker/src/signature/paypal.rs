@@ -0,0 +1,219 @@
+//! PayPal: deliveries are signed with PayPal's certificate rather than a shared
+//! secret, so they are checked by PayPal's `verify-webhook-signature` API, with
+//! an OAuth token for the app in `signature.paypal` and its client secret as
+//! `signature.secret`. `paypal-transmission-time` is the signed timestamp. The
+//! registry's offline `check` cannot decide and reports the delivery unsigned.
+//! Headers that could not come from PayPal, or a transmission outside the
+//! replay window, are decided without asking, and OAuth tokens are reused
+//! until they expire, so forged deliveries cost no outbound calls.
+
+use super::{SignatureVerifier, Signed, SignedRequest, Verification};
+use crate::config::{PaypalApp, SignatureConfig, SignatureProvider};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use futures_util::future::{select, Either};
+use serde::Serialize;
+use serde_json::value::RawValue;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::Duration;
+use worker::*;
+
+const LIVE_API: &str = "https://api-m.paypal.com";
+const SANDBOX_API: &str = "https://api-m.sandbox.paypal.com";
+
+/// Give up on a slow PayPal API after this long
+const API_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Seconds before its `expires_in` that a cached token is treated as expired
+const TOKEN_MARGIN_SECONDS: i64 = 60;
+
+/// API, client ID and client secret a token was issued for
+type TokenKey = (String, String, String);
+
+thread_local! {
+    /// OAuth tokens and their expiry in Unix seconds
+    static TOKENS: RefCell<HashMap<TokenKey, (String, i64)>> = RefCell::new(HashMap::new());
+}
+
+pub struct Paypal;
+
+/// Signed transmission time in Unix seconds
+pub fn transmission_time(headers: &HashMap<String, String>) -> Option<i64> {
+    let time = headers.get("paypal-transmission-time")?;
+    chrono::DateTime::parse_from_rfc3339(time.trim())
+        .ok()
+        .map(|time| time.timestamp())
+}
+
+/// Whether the signing headers could have come from PayPal: a certificate
+/// served over HTTPS by a paypal.com host and an RSA algorithm
+pub fn plausible(headers: &HashMap<String, String>) -> bool {
+    let certificate = headers
+        .get("paypal-cert-url")
+        .and_then(|url| Url::parse(url.trim()).ok())
+        .is_some_and(|url| {
+            url.scheme() == "https"
+                && url
+                    .host_str()
+                    .is_some_and(|host| host == "paypal.com" || host.ends_with(".paypal.com"))
+        });
+    let algorithm = headers
+        .get("paypal-auth-algo")
+        .is_some_and(|algo| algo.trim().starts_with("SHA") && algo.trim().ends_with("withRSA"));
+    certificate && algorithm
+}
+
+#[derive(Serialize)]
+struct VerificationRequest<'a> {
+    auth_algo: &'a str,
+    cert_url: &'a str,
+    transmission_id: &'a str,
+    transmission_sig: &'a str,
+    transmission_time: &'a str,
+    webhook_id: &'a str,
+    webhook_event: &'a RawValue,
+}
+
+/// Request body for `verify-webhook-signature`, embedding the delivered body
+/// byte for byte since PayPal checks the event it signed; None when a header
+/// is missing or the body is not JSON
+pub fn verification_body(app: &PaypalApp, headers: &HashMap<String, String>, body: &str) -> Option<String> {
+    let header = |name: &str| headers.get(name).map(|value| value.trim());
+    let request = VerificationRequest {
+        auth_algo: header("paypal-auth-algo")?,
+        cert_url: header("paypal-cert-url")?,
+        transmission_id: header("paypal-transmission-id")?,
+        transmission_sig: header("paypal-transmission-sig")?,
+        transmission_time: header("paypal-transmission-time")?,
+        webhook_id: &app.webhook_id,
+        webhook_event: serde_json::from_str(body).ok()?,
+    };
+    serde_json::to_string(&request).ok()
+}
+
+impl SignatureVerifier for Paypal {
+    fn provider(&self) -> SignatureProvider {
+        SignatureProvider::Paypal
+    }
+
+    fn description(&self) -> &'static str {
+        "PAYPAL-TRANSMISSION-SIG, checked by PayPal's verify-webhook-signature API (replay window)"
+    }
+
+    fn check(&self, _secret: &str, _request: &SignedRequest<'_>) -> Signed {
+        None
+    }
+}
+
+/// Ask PayPal about a delivery with each client secret of a rotation in turn.
+/// An unreachable API leaves the delivery `Missing`, not `Invalid`.
+pub(super) async fn verify(
+    config: &SignatureConfig,
+    secrets: &[String],
+    headers: &HashMap<String, String>,
+    body: &str,
+    now: i64,
+) -> Verification {
+    let Some(app) = config.paypal.as_ref() else {
+        return Verification::Missing;
+    };
+    let Some(request) = verification_body(app, headers, body) else {
+        return Verification::Missing;
+    };
+    if !plausible(headers) {
+        return Verification::Invalid;
+    }
+    match transmission_time(headers) {
+        Some(time) if (now - time).abs() > config.tolerance_seconds => return Verification::ReplaySuspected,
+        Some(_) => {}
+        None => return Verification::Invalid,
+    }
+    let api = if app.sandbox { SANDBOX_API } else { LIVE_API };
+
+    let mut refused = false;
+    for secret in secrets {
+        match ask(api, app, secret, &request, now).await {
+            Ok(true) => return Verification::Valid,
+            Ok(false) => refused = true,
+            Err(e) => log_warn!("⚠️  PayPal signature verification failed: {}", e),
+        }
+    }
+    if refused {
+        Verification::Invalid
+    } else {
+        Verification::Missing
+    }
+}
+
+/// Whether PayPal answers `SUCCESS` for the delivery. A cached token PayPal
+/// no longer accepts is dropped, so the next delivery fetches a fresh one.
+async fn ask(api: &str, app: &PaypalApp, secret: &str, request: &str, now: i64) -> std::result::Result<bool, String> {
+    let key = (api.to_string(), app.client_id.clone(), secret.to_string());
+    let token = match TOKENS.with(|tokens| tokens.borrow().get(&key).cloned()) {
+        Some((token, expires_at)) if expires_at > now => token,
+        _ => {
+            let (token, expires_in) = token(api, app, secret).await?;
+            let expires_at = now.saturating_add(expires_in.saturating_sub(TOKEN_MARGIN_SECONDS));
+            TOKENS.with(|tokens| tokens.borrow_mut().insert(key.clone(), (token.clone(), expires_at)));
+            token
+        }
+    };
+
+    let answer = post(
+        &format!("{}/v1/notifications/verify-webhook-signature", api),
+        &format!("Bearer {}", token),
+        "application/json",
+        request.to_string(),
+    )
+    .await
+    .inspect_err(|_| {
+        TOKENS.with(|tokens| tokens.borrow_mut().remove(&key));
+    })?;
+    Ok(answer["verification_status"].as_str() == Some("SUCCESS"))
+}
+
+/// A fresh OAuth token for the app and the seconds it stays valid
+async fn token(api: &str, app: &PaypalApp, secret: &str) -> std::result::Result<(String, i64), String> {
+    let credentials = BASE64.encode(format!("{}:{}", app.client_id, secret));
+    let answer = post(
+        &format!("{}/v1/oauth2/token", api),
+        &format!("Basic {}", credentials),
+        "application/x-www-form-urlencoded",
+        "grant_type=client_credentials".to_string(),
+    )
+    .await?;
+    let token = answer["access_token"]
+        .as_str()
+        .ok_or("PayPal returned no access token")?;
+    Ok((token.to_string(), answer["expires_in"].as_i64().unwrap_or(0)))
+}
+
+async fn post(
+    url: &str,
+    authorization: &str,
+    content_type: &str,
+    body: String,
+) -> std::result::Result<Value, String> {
+    let headers = Headers::new();
+    headers.set("Authorization", authorization).map_err(|e| e.to_string())?;
+    headers.set("Content-Type", content_type).map_err(|e| e.to_string())?;
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_headers(headers)
+        .with_body(Some(body.into()));
+    let request = Request::new_with_init(url, &init).map_err(|e| e.to_string())?;
+
+    let exchange = async {
+        let mut response = Fetch::Request(request).send().await?;
+        let status = response.status_code();
+        Ok::<_, Error>((status, response.json::<Value>().await?))
+    };
+    match select(Box::pin(exchange), Delay::from(API_TIMEOUT)).await {
+        Either::Left((Ok((status, answer)), _)) if (200..300).contains(&status) => Ok(answer),
+        Either::Left((Ok((status, _)), _)) => Err(format!("PayPal answered {}", status)),
+        Either::Left((Err(e), _)) => Err(e.to_string()),
+        Either::Right(_) => Err("timed out".to_string()),
+    }
+}
@@ -0,0 +1,25 @@
+//! Shopify: `X-Shopify-Hmac-Sha256: {base64}` over the body (no timestamp)
+
+use super::{hmac_sha256_matches, SignatureVerifier, Signed, SignedRequest};
+use crate::config::SignatureProvider;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+pub struct Shopify;
+
+impl SignatureVerifier for Shopify {
+    fn provider(&self) -> SignatureProvider {
+        SignatureProvider::Shopify
+    }
+
+    fn description(&self) -> &'static str {
+        "X-Shopify-Hmac-Sha256 (base64 HMAC-SHA256 over the body)"
+    }
+
+    fn check(&self, secret: &str, request: &SignedRequest<'_>) -> Signed {
+        let signature = BASE64.decode(request.headers.get("x-shopify-hmac-sha256")?.trim()).ok();
+        let valid = signature
+            .is_some_and(|signature| hmac_sha256_matches(secret.as_bytes(), request.body.as_bytes(), &signature));
+        Some((valid, None))
+    }
+}
@@ -0,0 +1,29 @@
+//! Slack: `X-Slack-Signature: v0={hex}` over `v0:{X-Slack-Request-Timestamp}:{body}`
+
+use super::{hmac_matches, SignatureVerifier, Signed, SignedRequest};
+use crate::config::SignatureProvider;
+
+pub struct Slack;
+
+impl SignatureVerifier for Slack {
+    fn provider(&self) -> SignatureProvider {
+        SignatureProvider::Slack
+    }
+
+    fn description(&self) -> &'static str {
+        "X-Slack-Signature (HMAC-SHA256 over the request timestamp and body, replay window)"
+    }
+
+    fn check(&self, secret: &str, request: &SignedRequest<'_>) -> Signed {
+        let signature = request.headers.get("x-slack-signature")?.strip_prefix("v0=")?;
+        let timestamp = request
+            .headers
+            .get("x-slack-request-timestamp")?
+            .trim()
+            .parse::<i64>()
+            .ok()?;
+
+        let payload = format!("v0:{}:{}", timestamp, request.body);
+        Some((hmac_matches(secret, payload.as_bytes(), signature), Some(timestamp)))
+    }
+}
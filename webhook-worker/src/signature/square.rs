@@ -0,0 +1,28 @@
+//! Square: `x-square-hmacsha256-signature: {base64}`, HMAC-SHA256 over the
+//! notification URL followed by the body; no timestamp
+
+use super::{hmac_sha256_matches, SignatureVerifier, Signed, SignedRequest};
+use crate::config::SignatureProvider;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+pub struct Square;
+
+impl SignatureVerifier for Square {
+    fn provider(&self) -> SignatureProvider {
+        SignatureProvider::Square
+    }
+
+    fn description(&self) -> &'static str {
+        "x-square-hmacsha256-signature (base64 HMAC-SHA256 over the notification URL and body)"
+    }
+
+    fn check(&self, secret: &str, request: &SignedRequest<'_>) -> Signed {
+        let signature = request.headers.get("x-square-hmacsha256-signature")?;
+        let valid = BASE64.decode(signature.trim()).is_ok_and(|signature| {
+            let payload = format!("{}{}", request.url, request.body);
+            hmac_sha256_matches(secret.as_bytes(), payload.as_bytes(), &signature)
+        });
+        Some((valid, None))
+    }
+}
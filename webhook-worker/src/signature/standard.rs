@@ -0,0 +1,89 @@
+//! Standard Webhooks (standardwebhooks.com): `webhook-signature: v1,{base64} ...`
+//! over `{webhook-id}.{webhook-timestamp}.{body}`, keyed with the base64 part of
+//! a `whsec_` secret. Svix uses the same scheme under `svix-` header names.
+//! Forwards are signed with `sign` when the webhook has `forward_signing`.
+
+use super::{hmac_sha256_matches, SignatureVerifier, Signed, SignedRequest};
+use crate::config::SignatureProvider;
+use ::hmac::{Hmac, Mac};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use sha2::Sha256;
+
+/// Prefix of Standard Webhooks and Svix signing secrets; the rest is the base64 key
+const SECRET_PREFIX: &str = "whsec_";
+
+/// Signature scheme version defined by the spec (HMAC-SHA256)
+pub const VERSION: &str = "v1";
+
+/// Message ID, timestamp and signature headers of one flavour of the scheme
+pub struct Headers {
+    pub id: &'static str,
+    pub timestamp: &'static str,
+    pub signature: &'static str,
+}
+
+pub const STANDARD_HEADERS: Headers = Headers {
+    id: "webhook-id",
+    timestamp: "webhook-timestamp",
+    signature: "webhook-signature",
+};
+
+pub struct StandardWebhooks;
+
+/// HMAC key of a secret (secrets without the prefix are used as raw bytes)
+fn key(secret: &str) -> Vec<u8> {
+    match secret.strip_prefix(SECRET_PREFIX) {
+        Some(encoded) => BASE64.decode(encoded).unwrap_or_else(|_| secret.as_bytes().to_vec()),
+        None => secret.as_bytes().to_vec(),
+    }
+}
+
+fn signed_payload(id: &str, timestamp: i64, body: &str) -> String {
+    format!("{}.{}.{}", id, timestamp, body)
+}
+
+/// Check a delivery signed under `headers`
+pub(super) fn check(headers: &Headers, secret: &str, request: &SignedRequest<'_>) -> Signed {
+    let id = request.headers.get(headers.id)?;
+    let timestamp = request.headers.get(headers.timestamp)?.trim().parse::<i64>().ok()?;
+    let header = request.headers.get(headers.signature)?;
+
+    // Space-separated `version,signature` pairs; asymmetric `v1a` entries are not verified
+    let signatures: Vec<Vec<u8>> = header
+        .split_whitespace()
+        .filter_map(|entry| entry.strip_prefix("v1,"))
+        .filter_map(|signature| BASE64.decode(signature).ok())
+        .collect();
+    if signatures.is_empty() {
+        return None;
+    }
+
+    let key = key(secret);
+    let payload = signed_payload(id, timestamp, request.body);
+    let valid = signatures
+        .iter()
+        .any(|signature| hmac_sha256_matches(&key, payload.as_bytes(), signature));
+    Some((valid, Some(timestamp)))
+}
+
+/// `webhook-signature` value for a message
+pub fn sign(secret: &str, id: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(&key(secret)).expect("HMAC accepts keys of any length");
+    mac.update(signed_payload(id, timestamp, body).as_bytes());
+    format!("{},{}", VERSION, BASE64.encode(mac.finalize().into_bytes()))
+}
+
+impl SignatureVerifier for StandardWebhooks {
+    fn provider(&self) -> SignatureProvider {
+        SignatureProvider::StandardWebhooks
+    }
+
+    fn description(&self) -> &'static str {
+        "webhook-signature (HMAC-SHA256 over webhook-id, webhook-timestamp and body, replay window)"
+    }
+
+    fn check(&self, secret: &str, request: &SignedRequest<'_>) -> Signed {
+        check(&STANDARD_HEADERS, secret, request)
+    }
+}
@@ -0,0 +1,39 @@
+//! Stripe: `Stripe-Signature: t={timestamp},v1={hex}` over `{timestamp}.{body}`
+
+use super::{hmac_matches, SignatureVerifier, Signed, SignedRequest};
+use crate::config::SignatureProvider;
+
+pub struct Stripe;
+
+impl SignatureVerifier for Stripe {
+    fn provider(&self) -> SignatureProvider {
+        SignatureProvider::Stripe
+    }
+
+    fn description(&self) -> &'static str {
+        "Stripe-Signature (HMAC-SHA256 over the timestamp and body, replay window)"
+    }
+
+    fn check(&self, secret: &str, request: &SignedRequest<'_>) -> Signed {
+        let header = request.headers.get("stripe-signature")?;
+        let mut timestamp = None;
+        let mut signatures = Vec::new();
+        for part in header.split(',') {
+            match part.trim().split_once('=') {
+                Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+                Some(("v1", value)) => signatures.push(value),
+                _ => {}
+            }
+        }
+        let timestamp = timestamp?;
+        if signatures.is_empty() {
+            return None;
+        }
+
+        let payload = format!("{}.{}", timestamp, request.body);
+        let valid = signatures
+            .iter()
+            .any(|signature| hmac_matches(secret, payload.as_bytes(), signature));
+        Some((valid, Some(timestamp)))
+    }
+}
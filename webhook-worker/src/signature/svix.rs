@@ -0,0 +1,28 @@
+//! Svix: the Standard Webhooks scheme under `svix-id`, `svix-timestamp` and
+//! `svix-signature`
+
+use super::standard::{self, Headers};
+use super::{SignatureVerifier, Signed, SignedRequest};
+use crate::config::SignatureProvider;
+
+const SVIX_HEADERS: Headers = Headers {
+    id: "svix-id",
+    timestamp: "svix-timestamp",
+    signature: "svix-signature",
+};
+
+pub struct Svix;
+
+impl SignatureVerifier for Svix {
+    fn provider(&self) -> SignatureProvider {
+        SignatureProvider::Svix
+    }
+
+    fn description(&self) -> &'static str {
+        "svix-signature (HMAC-SHA256 over svix-id, svix-timestamp and body, replay window)"
+    }
+
+    fn check(&self, secret: &str, request: &SignedRequest<'_>) -> Signed {
+        standard::check(&SVIX_HEADERS, secret, request)
+    }
+}
@@ -0,0 +1,97 @@
+//! Time-limited signed capture URLs
+//! `/w/{uuid}?exp={unix seconds}&sig={hex}` where `sig` is
+//! HMAC-SHA256(webhook secret, "{uuid}:{exp}"). Verification is stateless.
+//! Upload URLs (`PUT /w/{uuid}/upload/{filename}`) sign
+//! "{uuid}:upload:{filename}:{exp}" instead, so a capture URL's signature never
+//! authorizes an upload and an upload URL is bound to its file name.
+//! Status page links (`GET /status/{uuid}`) sign "{uuid}:status:{exp}" and
+//! only ever grant a read of the public summary.
+
+use crate::signature::decode_hex;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+pub const EXP_PARAM: &str = "exp";
+pub const SIG_PARAM: &str = "sig";
+
+/// Why a signed URL was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignedUrlError {
+    /// `exp` or `sig` missing or malformed
+    Malformed,
+    Expired,
+    BadSignature,
+}
+
+fn mac(secret: &str, message: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(message.as_bytes());
+    mac
+}
+
+fn capture_message(uuid: &str, exp: i64) -> String {
+    format!("{}:{}", uuid, exp)
+}
+
+fn upload_message(uuid: &str, filename: &str, exp: i64) -> String {
+    format!("{}:upload:{}:{}", uuid, filename, exp)
+}
+
+fn status_message(uuid: &str, exp: i64) -> String {
+    format!("{}:status:{}", uuid, exp)
+}
+
+/// Signature for a capture URL expiring at `exp`
+pub fn sign(secret: &str, uuid: &str, exp: i64) -> String {
+    hex(&mac(secret, &capture_message(uuid, exp)).finalize().into_bytes())
+}
+
+/// Signature for an upload URL of `filename` expiring at `exp`
+pub fn sign_upload(secret: &str, uuid: &str, filename: &str, exp: i64) -> String {
+    hex(&mac(secret, &upload_message(uuid, filename, exp)).finalize().into_bytes())
+}
+
+/// Signature for a status page link expiring at `exp`
+pub fn sign_status(secret: &str, uuid: &str, exp: i64) -> String {
+    hex(&mac(secret, &status_message(uuid, exp)).finalize().into_bytes())
+}
+
+/// Check `exp` / `sig` query values against the webhook secret at time `now` (Unix seconds)
+pub fn verify(secret: &str, uuid: &str, exp: Option<&str>, sig: Option<&str>, now: i64) -> Result<(), SignedUrlError> {
+    check(exp, sig, now, |exp| mac(secret, &capture_message(uuid, exp)))
+}
+
+/// Check an upload URL's `exp` / `sig` for `filename`
+pub fn verify_upload(
+    secret: &str,
+    uuid: &str,
+    filename: &str,
+    exp: Option<&str>,
+    sig: Option<&str>,
+    now: i64,
+) -> Result<(), SignedUrlError> {
+    check(exp, sig, now, |exp| mac(secret, &upload_message(uuid, filename, exp)))
+}
+
+/// Check a status page link's `exp` / `sig`
+pub fn verify_status(secret: &str, uuid: &str, exp: Option<&str>, sig: Option<&str>, now: i64) -> Result<(), SignedUrlError> {
+    check(exp, sig, now, |exp| mac(secret, &status_message(uuid, exp)))
+}
+
+fn check(exp: Option<&str>, sig: Option<&str>, now: i64, mac: impl Fn(i64) -> Hmac<Sha256>) -> Result<(), SignedUrlError> {
+    let exp: i64 = exp.and_then(|exp| exp.parse().ok()).ok_or(SignedUrlError::Malformed)?;
+    let sig = sig.and_then(decode_hex).ok_or(SignedUrlError::Malformed)?;
+
+    // Check the signature first so expiry can't be probed with forged URLs
+    mac(exp)
+        .verify_slice(&sig)
+        .map_err(|_| SignedUrlError::BadSignature)?;
+    if exp < now {
+        return Err(SignedUrlError::Expired);
+    }
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
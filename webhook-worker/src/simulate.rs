@@ -0,0 +1,126 @@
+//! Pipeline simulation
+//! `POST /api/webhooks/{uuid}/simulate` runs a sample delivery through the
+//! webhook's pipeline, with a config patch applied first when one is given,
+//! and returns what would have happened: the signed URL check, the extracted
+//! event type and keys, the environment, what the hook script changed or
+//! removed, the matching route, the forwarding targets, the capture as it
+//! would be stored and the answer the sender would get. Nothing is stored,
+//! forwarded or counted. Signature verification, deduplication, heartbeats,
+//! A/B and shadow forwarding and provider answers (TwiML, Slack, SCIM) depend
+//! on secrets, stored state or the capture ID and are left out.
+
+use crate::config::WebhookSettings;
+use crate::headers::HeaderLimits;
+use crate::pipeline::{self, CaptureMeta, IncomingRequest};
+use crate::processing::{Processing, SignedUrl};
+use crate::storage::StoredRequest;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use worker::Url;
+
+/// ID the simulated capture is given
+pub const CAPTURE_ID: &str = "simulated";
+
+pub use webhook_types::simulate::{Answer, Sample};
+
+/// What the pipeline would do with a sample
+#[derive(Debug, Clone, Serialize)]
+pub struct Outcome {
+    /// False when the delivery would be refused; only `response` is set then
+    pub accepted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub processing: Option<Processing>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture: Option<StoredRequest>,
+    pub response: Answer,
+}
+
+fn refused(status: u16, message: &str) -> Outcome {
+    Outcome {
+        accepted: false,
+        processing: None,
+        capture: None,
+        response: Answer {
+            status,
+            content_type: "application/json".to_string(),
+            body: serde_json::json!({ "error": message }).to_string(),
+            headers: BTreeMap::new(),
+        },
+    }
+}
+
+/// Run a sample through the pipeline of a webhook with `settings`, received on `capture_url` at `now_ms`
+pub fn run(
+    sample: &Sample,
+    capture_url: &Url,
+    webhook_id: &str,
+    settings: &WebhookSettings,
+    now_ms: i64,
+) -> Result<Outcome, String> {
+    let uuid = match &sample.environment {
+        Some(name) => match settings.environments.iter().find(|environment| &environment.name == name) {
+            Some(environment) => environment.uuid.clone(),
+            None => return Err(format!("Unknown environment: {}", name)),
+        },
+        None => capture_url.path().trim_start_matches("/w/").to_string(),
+    };
+    let mut url = capture_url.clone();
+    url.set_path(&format!("/w/{}", uuid));
+    url.set_query(sample.query.as_deref());
+
+    let headers = match pipeline::sanitize_headers(sample.headers.clone(), &HeaderLimits::default()) {
+        Ok(headers) => headers,
+        Err(rejection) => return Ok(refused(rejection.status, rejection.message)),
+    };
+    if let Some(rejection) = pipeline::check_signed_url(&url, &uuid, settings, now_ms / 1000) {
+        return Ok(refused(rejection.status, rejection.message));
+    }
+    let method = sample.method.to_ascii_uppercase();
+    let body = sample.body.as_ref().map(|body| match body {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    });
+    let incoming = IncomingRequest {
+        method: method.clone(),
+        url: url.clone(),
+        headers,
+        body: pipeline::has_body(&method).then(|| body.unwrap_or_default()),
+        received_at_ms: now_ms,
+    };
+    let mut parsed = pipeline::parse(&incoming).map_err(|e| format!("Invalid sample: {}", e))?;
+    let applied = pipeline::apply(&mut parsed, &uuid, settings);
+    let processing = Processing::new(&applied, settings, Some(SignedUrl::checked(&url)), None);
+
+    let mut record = pipeline::into_record(
+        parsed,
+        CaptureMeta {
+            id: CAPTURE_ID.to_string(),
+            webhook_id: webhook_id.to_string(),
+            sequence: None,
+            verification: None,
+            environment: applied.environment,
+        },
+    );
+    let response = match applied.response().or(applied.fallback_response) {
+        Some(custom) => Answer {
+            status: custom.status,
+            content_type: custom.content_type.clone().unwrap_or_else(|| "text/plain".to_string()),
+            body: custom.body.clone(),
+            headers: custom.headers.clone(),
+        },
+        None => Answer {
+            status: 200,
+            content_type: "application/json".to_string(),
+            body: pipeline::success_body(&uuid, &record).to_string(),
+            headers: BTreeMap::new(),
+        },
+    };
+    record.processing = Some(processing.to_json());
+    Ok(Outcome {
+        accepted: true,
+        processing: Some(processing),
+        capture: Some(StoredRequest::from(&record)),
+        response,
+    })
+}
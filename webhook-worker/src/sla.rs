@@ -0,0 +1,143 @@
+//! Delivery SLA monitoring
+//! Webhook configs can list `expectations` such as "at least one `invoice.paid`
+//! every 24h". The scheduled handler counts matching captures over each
+//! trailing window; an expectation that stops being met is recorded in
+//! `expectation_alerts` and its `notify_url` receives a `violated` POST, and
+//! once it is met again the row is cleared with a `recovered` POST. Outages
+//! therefore notify exactly twice however often the check runs.
+
+use crate::config::{self, Expectation};
+use crate::forward;
+use crate::storage::{self, Consistency};
+use serde::Deserialize;
+use std::collections::HashMap;
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// A change of an expectation's state worth notifying about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    Violated,
+    Recovered,
+}
+
+impl Transition {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Violated => "violated",
+            Self::Recovered => "recovered",
+        }
+    }
+}
+
+/// What a check means given whether the expectation is already alerting
+pub fn transition(alerting: bool, expectation: &Expectation, count: u64) -> Option<Transition> {
+    let met = count >= expectation.min_count as u64;
+    match (alerting, met) {
+        (false, false) => Some(Transition::Violated),
+        (true, true) => Some(Transition::Recovered),
+        _ => None,
+    }
+}
+
+/// Start of the trailing window, in Unix seconds
+pub fn window_start(expectation: &Expectation, now: i64) -> i64 {
+    now - expectation.window_hours as i64 * 3600
+}
+
+/// JSON body POSTed to `notify_url`
+pub fn notification(
+    uuid: &str,
+    expectation: &Expectation,
+    transition: Transition,
+    count: u64,
+    violated_since_ms: i64,
+    now_ms: i64,
+) -> serde_json::Value {
+    serde_json::json!({
+        "type": format!("expectation.{}", transition.as_str()),
+        "webhook_id": uuid,
+        "expectation": {
+            "event_type": expectation.event_type,
+            "min_count": expectation.min_count,
+            "window_hours": expectation.window_hours,
+        },
+        "observed_count": count,
+        "violated_since_ms": violated_since_ms,
+        "checked_at_ms": now_ms,
+    })
+}
+
+#[derive(Deserialize)]
+struct AlertRow {
+    webhook_id: String,
+    expectation: String,
+    violated_since_ms: f64,
+}
+
+/// Evaluate every configured expectation; `now` is Unix seconds
+pub async fn check(env: &Env, now: i64) -> Result<()> {
+    let db = env.d1("DB")?;
+    let rules = config::expectation_rules(&db).await?;
+    if rules.is_empty() {
+        return Ok(());
+    }
+
+    let alerts: HashMap<(String, String), i64> = db
+        .prepare("SELECT webhook_id, expectation, violated_since_ms FROM expectation_alerts")
+        .all()
+        .await?
+        .results::<AlertRow>()?
+        .into_iter()
+        .map(|row| ((row.webhook_id, row.expectation), row.violated_since_ms as i64))
+        .collect();
+
+    let now_ms = now * 1000;
+    for rule in rules {
+        let storage = storage::for_webhook(env, &rule.webhook_id, Consistency::Primary).await?;
+        let key = rule.expectation.key();
+        let since = window_start(&rule.expectation, now);
+        let count = storage
+            .count_received(&rule.webhook_id, Some(&rule.expectation.event_type), since, None)
+            .await?;
+        let alerting = alerts.get(&(rule.webhook_id.clone(), key.clone())).copied();
+        let Some(transition) = transition(alerting.is_some(), &rule.expectation, count) else {
+            continue;
+        };
+
+        let violated_since_ms = alerting.unwrap_or(now_ms);
+        match transition {
+            Transition::Violated => {
+                db.prepare(
+                    "INSERT OR IGNORE INTO expectation_alerts (webhook_id, expectation, violated_since_ms, observed_count) \
+                     VALUES (?1, ?2, ?3, ?4)",
+                )
+                .bind(&[
+                    JsValue::from_str(&rule.webhook_id),
+                    JsValue::from_str(&key),
+                    JsValue::from_f64(now_ms as f64),
+                    JsValue::from_f64(count as f64),
+                ])?
+                .run()
+                .await?;
+            }
+            Transition::Recovered => {
+                db.prepare("DELETE FROM expectation_alerts WHERE webhook_id = ?1 AND expectation = ?2")
+                    .bind(&[JsValue::from_str(&rule.webhook_id), JsValue::from_str(&key)])?
+                    .run()
+                    .await?;
+            }
+        }
+        log_info!(
+            "⏰ Expectation {} of webhook {} {} ({} in {}h)",
+            key,
+            rule.uuid,
+            transition.as_str(),
+            count,
+            rule.expectation.window_hours
+        );
+        let body = notification(&rule.uuid, &rule.expectation, transition, count, violated_since_ms, now_ms);
+        forward::notify(&rule.expectation.notify_url, &body).await;
+    }
+    Ok(())
+}
@@ -0,0 +1,195 @@
+//! Slack slash commands and interactivity
+//! Slack posts slash commands as form fields (`command`, `text`, `user_name`,
+//! `response_url`, ...) and interactive components (buttons, modals,
+//! shortcuts) as a form with a single `payload` field of JSON. Both are
+//! recognized: the decoded request is stored as the capture's `canonical_data`
+//! and its command (`/deploy`) or payload type (`block_actions`) becomes the
+//! event type when nothing else set one. With `slack` in the webhook config the
+//! capture URL can stand in for a Slack app's request URL: the immediate
+//! answer is a templated message, and a second template is posted to the
+//! request's `response_url` once the capture is stored, the way a real
+//! handler follows up on slow work. Verify the requests with the `slack`
+//! signature provider.
+
+use crate::canonical;
+use crate::config::{CustomResponse, SlackConfig};
+use crate::placeholders;
+use futures_util::future::{select, Either};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use worker::*;
+
+/// Slack wants the acknowledgment within 3 seconds, so a follow-up gets less
+const FOLLOW_UP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Hosts a `response_url` may point at
+const RESPONSE_URL_HOSTS: &[&str] = &["hooks.slack.com", "hooks.slack-gov.com"];
+
+/// Nesting levels of an interaction payload available as placeholders
+const MAX_DEPTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Slash command (`command` field)
+    Command,
+    /// Interactive component (`payload` field)
+    Interaction,
+}
+
+/// A decoded Slack request
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlackRequest {
+    pub kind: Kind,
+    /// The command's fields, or the interaction payload
+    pub decoded: Value,
+    /// Placeholder values: the command's fields, or the payload's scalars by dotted
+    /// path (`user.name`, `actions.0.value`)
+    pub fields: HashMap<String, String>,
+}
+
+impl SlackRequest {
+    /// A Slack form body; None for anything else
+    pub fn parse(content_type: Option<&str>, body: &str) -> Option<Self> {
+        if !content_type.is_some_and(|content_type| content_type.starts_with("application/x-www-form-urlencoded")) {
+            return None;
+        }
+        let form: HashMap<String, String> = form_urlencoded::parse(body.as_bytes()).into_owned().collect();
+        if let Some(payload) = form.get("payload") {
+            let decoded: Value = serde_json::from_str(payload).ok()?;
+            decoded.get("type")?.as_str()?;
+            let mut fields = HashMap::new();
+            flatten("", &decoded, 0, &mut fields);
+            return Some(Self {
+                kind: Kind::Interaction,
+                decoded,
+                fields,
+            });
+        }
+        if !form.contains_key("command") || !form.contains_key("response_url") {
+            return None;
+        }
+        let decoded = Value::Object(
+            form.iter()
+                .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+                .collect::<Map<_, _>>(),
+        );
+        Some(Self {
+            kind: Kind::Command,
+            decoded,
+            fields: form,
+        })
+    }
+
+    /// `/deploy` for a command, the payload type (`block_actions`, `view_submission`) otherwise
+    pub fn event_type(&self) -> Option<String> {
+        let field = match self.kind {
+            Kind::Command => "command",
+            Kind::Interaction => "type",
+        };
+        self.fields.get(field).cloned()
+    }
+
+    /// Where follow-up messages go, when it is a Slack URL
+    pub fn response_url(&self) -> Option<Url> {
+        let url = Url::parse(self.fields.get("response_url")?).ok()?;
+        let host = url.host_str()?;
+        (url.scheme() == "https" && RESPONSE_URL_HOSTS.contains(&host)).then_some(url)
+    }
+}
+
+fn flatten(path: &str, value: &Value, depth: usize, fields: &mut HashMap<String, String>) {
+    let child = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    match value {
+        Value::Object(object) if depth < MAX_DEPTH => {
+            for (key, value) in object {
+                flatten(&child(key), value, depth + 1, fields);
+            }
+        }
+        Value::Array(items) if depth < MAX_DEPTH => {
+            for (index, value) in items.iter().enumerate() {
+                flatten(&child(&index.to_string()), value, depth + 1, fields);
+            }
+        }
+        Value::String(text) => {
+            fields.insert(path.to_string(), text.clone());
+        }
+        Value::Number(_) | Value::Bool(_) => {
+            fields.insert(path.to_string(), value.to_string());
+        }
+        _ => {}
+    }
+}
+
+/// Canonical JSON of a Slack form body, stored as `canonical_data`
+pub fn canonical(content_type: Option<&str>, body: &str) -> Option<String> {
+    let request = SlackRequest::parse(content_type, body)?;
+    canonical::canonicalize(&request.decoded.to_string())
+}
+
+/// `template` with each `{{field}}` replaced by the field, escaped for a JSON string
+pub fn render(template: &str, fields: &HashMap<String, String>) -> String {
+    placeholders::fill(template, fields, escape)
+}
+
+fn escape(value: &str) -> String {
+    let quoted = Value::String(value.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+/// The immediate answer: the rendered `response` as JSON, or an empty acknowledgment
+pub fn respond(config: &SlackConfig, request: &SlackRequest) -> CustomResponse {
+    match &config.response {
+        Some(template) => CustomResponse {
+            status: 200,
+            body: render(template, &request.fields),
+            content_type: Some("application/json".to_string()),
+            headers: BTreeMap::new(),
+        },
+        None => CustomResponse {
+            status: 200,
+            body: String::new(),
+            content_type: None,
+            headers: BTreeMap::new(),
+        },
+    }
+}
+
+/// Post the rendered `follow_up` to the request's `response_url`. Failures are
+/// logged rather than failing the capture.
+pub async fn follow_up(config: &SlackConfig, request: &SlackRequest) {
+    let Some(template) = &config.follow_up else {
+        return;
+    };
+    let Some(url) = request.response_url() else {
+        log_warn!("⚠️  Slack request has no Slack response_url to follow up on");
+        return;
+    };
+    if let Err(e) = post(&url, render(template, &request.fields)).await {
+        log_warn!("⚠️  Slack follow-up failed: {}", e);
+    }
+}
+
+async fn post(url: &Url, message: String) -> std::result::Result<(), String> {
+    let headers = Headers::new();
+    headers.set("Content-Type", "application/json").map_err(|e| e.to_string())?;
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_headers(headers)
+        .with_body(Some(message.into()));
+    let request = Request::new_with_init(url.as_str(), &init).map_err(|e| e.to_string())?;
+
+    let send = async { Fetch::Request(request).send().await };
+    match select(Box::pin(send), Delay::from(FOLLOW_UP_TIMEOUT)).await {
+        Either::Left((Ok(response), _)) if response.status_code() == 200 => Ok(()),
+        Either::Left((Ok(response), _)) => Err(format!("Slack answered {}", response.status_code())),
+        Either::Left((Err(e), _)) => Err(e.to_string()),
+        Either::Right(_) => Err("timed out".to_string()),
+    }
+}
@@ -0,0 +1,128 @@
+//! Freeze-frame capture snapshots
+//! `GET /api/webhooks/{uuid}/requests/{id}/export` packs one capture into a
+//! single self-contained JSON document for a bug report: the stored row, the
+//! body bytes as base64 (the pre-normalization original when charset decoding
+//! changed them), headers and processing trail as readable objects, and what
+//! every forwarding target answered. `POST /api/webhooks/{uuid}/requests/import`
+//! loads a snapshot into a webhook on any instance as a new capture that keeps
+//! the original receive time.
+
+use crate::github::GithubFields;
+use crate::headers::IndexedHeaders;
+use crate::responses::StoredResponse;
+use crate::storage::{CaptureRecord, StoredRequest};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// `format` of snapshots this worker writes and accepts
+pub const FORMAT: &str = "webhook-capture-snapshot/v1";
+
+/// Where a snapshot was taken
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Source {
+    pub webhook_uuid: String,
+    pub capture_id: String,
+    /// Host of the exporting instance
+    pub host: Option<String>,
+}
+
+/// One capture, frozen
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub format: String,
+    pub exported_at_ms: i64,
+    pub source: Source,
+    /// Body bytes as received, base64
+    pub body_base64: String,
+    pub headers: HashMap<String, String>,
+    pub processing: Value,
+    pub preview: Value,
+    /// The stored row, as `GET /api/webhooks/{uuid}/requests/{id}` returns it
+    pub request: StoredRequest,
+    #[serde(default)]
+    pub responses: Vec<StoredResponse>,
+}
+
+/// Snapshot of a stored capture and its downstream responses
+pub fn build(source: Source, request: StoredRequest, responses: Vec<StoredResponse>, exported_at_ms: i64) -> Snapshot {
+    let as_object = |column: &Option<String>| {
+        column
+            .as_deref()
+            .and_then(|value| serde_json::from_str(value).ok())
+            .unwrap_or(Value::Null)
+    };
+    Snapshot {
+        format: FORMAT.to_string(),
+        exported_at_ms,
+        source,
+        body_base64: request
+            .original_body
+            .clone()
+            .unwrap_or_else(|| BASE64.encode(request.data.as_bytes())),
+        headers: serde_json::from_str(&request.headers).unwrap_or_default(),
+        processing: as_object(&request.processing),
+        preview: as_object(&request.preview),
+        request,
+        responses,
+    }
+}
+
+/// The capture to store when importing `snapshot` into `webhook_id` as `id`.
+/// Inbox state, the sequence number and the replay parent belong to the source webhook and are dropped.
+pub fn into_record(snapshot: &Snapshot, webhook_id: &str, id: &str) -> Result<CaptureRecord, String> {
+    if snapshot.format != FORMAT {
+        return Err(format!("unsupported snapshot format {:?}, expected {:?}", snapshot.format, FORMAT));
+    }
+    if BASE64.decode(&snapshot.body_base64).is_err() {
+        return Err("body_base64 is not valid base64".to_string());
+    }
+    let request = &snapshot.request;
+    Ok(CaptureRecord {
+        id: id.to_string(),
+        webhook_id: webhook_id.to_string(),
+        method: request.method.clone(),
+        headers_json: request.headers.clone(),
+        data: request.data.clone(),
+        size_bytes: request.size_bytes as i32,
+        received_at: request.received_at,
+        received_at_ms: request.received_at_ms.unwrap_or(request.received_at * 1000),
+        event_time: request.event_time,
+        sequence: None,
+        indexed_headers: IndexedHeaders {
+            content_type: request.content_type.clone(),
+            user_agent: request.user_agent.clone(),
+            signature: request.signature.clone(),
+            idempotency_key: request.idempotency_key.clone(),
+            event_type: request.event_type.clone(),
+            svix_id: request.svix_id.clone(),
+            svix_timestamp: request.svix_timestamp,
+            shopify_topic: request.shopify_topic.clone(),
+            shopify_shop_domain: request.shopify_shop_domain.clone(),
+            correlation_id: request.correlation_id.clone(),
+        },
+        verification: request.verification.clone(),
+        environment: request.environment.clone(),
+        trailers: request.trailers.clone(),
+        connection_id: request.connection_id.clone(),
+        frame_type: request.frame_type.clone(),
+        processing: request.processing.clone(),
+        preview: request.preview.clone(),
+        charset: request.charset.clone(),
+        original_body: request.original_body.clone(),
+        canonical_data: request.canonical_data.clone(),
+        github: GithubFields {
+            event: request.github_event.clone(),
+            delivery: request.github_delivery.clone(),
+            installation_id: request.github_installation_id.clone(),
+            repository: request.github_repository.clone(),
+            installation: request.github_installation.clone(),
+        },
+        stripe_cross_check: request.stripe_cross_check.clone(),
+        oauth_exchange: request.oauth_exchange.clone(),
+        replay_of: None,
+        storage_form: false,
+    })
+}
@@ -0,0 +1,417 @@
+//! A/B forwarding
+//! With `split` in the webhook config, deliveries are forwarded to one of two
+//! targets: `b_percent` of them to the candidate `b`, the rest to the control
+//! `a`. The arm is picked from a hash of the capture ID, so a replayed capture
+//! lands on the same target again. `compare_percent` of the deliveries go to
+//! both; their answers are compared (status, latency, and the JSON paths whose
+//! values differ) and kept in `split_comparisons`. `GET .../stats/split` puts
+//! each arm's status codes and latency (from `forward_responses` and the
+//! latency histograms) next to the comparisons, which is what a consumer
+//! migration needs to show the new service answers like the old one.
+//! With `shadow`, every delivery is forwarded to the primary as usual and,
+//! once the sender has been answered, to the shadow URL; only the primary's
+//! answer is stored with the capture. The two are compared the same way and
+//! `GET .../stats/shadow` sums the mismatches up: status pairs, the body
+//! paths that differ most often and the latest mismatching captures.
+
+use crate::capture_log;
+use crate::config::TrafficSplit;
+use crate::forward::{self, Delivery, ForwardOutcome, Signer};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Body paths listed for a comparison; the rest only count as a mismatch
+pub const MAX_DIFF_PATHS: usize = 20;
+
+/// Days of comparisons kept
+pub const RETENTION_DAYS: i64 = 30;
+
+/// Where a delivery goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Arm {
+    A,
+    B,
+    /// Both targets, answers compared
+    Both,
+}
+
+impl Arm {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::A => "a",
+            Self::B => "b",
+            Self::Both => "both",
+        }
+    }
+}
+
+/// The arm of capture `capture_id`; the same ID always gets the same arm
+pub fn arm(split: &TrafficSplit, capture_id: &str) -> Arm {
+    let digest = Sha256::digest(capture_id.as_bytes());
+    let roll = |offset: usize| u16::from_be_bytes([digest[offset], digest[offset + 1]]) % 100;
+    if roll(2) < split.compare_percent as u16 {
+        Arm::Both
+    } else if roll(0) < split.b_percent as u16 {
+        Arm::B
+    } else {
+        Arm::A
+    }
+}
+
+/// The targets an arm forwards to
+pub fn targets(split: &TrafficSplit, arm: Arm) -> Vec<&str> {
+    match arm {
+        Arm::A => vec![split.a.as_str()],
+        Arm::B => vec![split.b.as_str()],
+        Arm::Both => vec![split.a.as_str(), split.b.as_str()],
+    }
+}
+
+/// What sent a delivery to two targets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// A traffic split's `compare_percent` (`a` and `b`)
+    Split,
+    /// Shadow forwarding (`a` is the primary, `b` the shadow)
+    Shadow,
+}
+
+impl Mode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Split => "split",
+            Self::Shadow => "shadow",
+        }
+    }
+}
+
+/// How the two targets' answers to one delivery differ
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Comparison {
+    pub capture_id: String,
+    pub status_a: Option<u16>,
+    pub status_b: Option<u16>,
+    pub duration_a_ms: i64,
+    pub duration_b_ms: i64,
+    pub status_match: bool,
+    pub body_match: bool,
+    /// JSON paths whose values differ (`data.items[0].price`), at most `MAX_DIFF_PATHS`;
+    /// empty for non-JSON bodies, which only compare as equal or not
+    pub body_diff: Vec<String>,
+    pub created_at_ms: i64,
+}
+
+/// Compare the answers of `a` and `b` to capture `capture_id`
+pub fn compare(capture_id: &str, a: &ForwardOutcome, b: &ForwardOutcome, now_ms: i64) -> Comparison {
+    let body = |outcome: &ForwardOutcome| outcome.response.as_ref().map(|response| response.body.clone());
+    let (body_a, body_b) = (body(a), body(b));
+    let mut body_diff = Vec::new();
+    let parsed = |body: &Option<String>| body.as_deref().and_then(|body| serde_json::from_str::<Value>(body).ok());
+    let body_match = match (parsed(&body_a), parsed(&body_b)) {
+        (Some(json_a), Some(json_b)) => {
+            diff_values(&json_a, &json_b, String::new(), &mut body_diff);
+            json_a == json_b
+        }
+        _ => body_a == body_b,
+    };
+    body_diff.truncate(MAX_DIFF_PATHS);
+    Comparison {
+        capture_id: capture_id.to_string(),
+        status_a: a.status,
+        status_b: b.status,
+        duration_a_ms: a.duration_ms,
+        duration_b_ms: b.duration_ms,
+        status_match: a.status == b.status,
+        body_match,
+        body_diff,
+        created_at_ms: now_ms,
+    }
+}
+
+fn diff_values(a: &Value, b: &Value, path: String, paths: &mut Vec<String>) {
+    if paths.len() > MAX_DIFF_PATHS {
+        return;
+    }
+    match (a, b) {
+        (Value::Object(members_a), Value::Object(members_b)) => {
+            let mut names: Vec<&String> = members_a.keys().chain(members_b.keys()).collect();
+            names.sort();
+            names.dedup();
+            for name in names {
+                let child = if path.is_empty() { name.clone() } else { format!("{}.{}", path, name) };
+                match (members_a.get(name), members_b.get(name)) {
+                    (Some(member_a), Some(member_b)) => diff_values(member_a, member_b, child, paths),
+                    _ => paths.push(child),
+                }
+            }
+        }
+        (Value::Array(items_a), Value::Array(items_b)) => {
+            for index in 0..items_a.len().max(items_b.len()) {
+                let child = format!("{}[{}]", path, index);
+                match (items_a.get(index), items_b.get(index)) {
+                    (Some(item_a), Some(item_b)) => diff_values(item_a, item_b, child, paths),
+                    _ => paths.push(child),
+                }
+            }
+        }
+        _ if a != b => paths.push(if path.is_empty() { "$".to_string() } else { path }),
+        _ => {}
+    }
+}
+
+/// A delivery to mirror to the shadow target, owned so it can be sent after the response
+pub struct ShadowDelivery {
+    pub target: String,
+    pub method: String,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+    pub query: Option<String>,
+    /// Signs the shadow forward like the primary's (`forward_signing`)
+    pub signer: Option<Signer>,
+    pub webhook_id: String,
+    pub capture_id: String,
+    /// What the primary answered
+    pub primary: ForwardOutcome,
+}
+
+/// Forward to the shadow target and store how its answer compares with the primary's
+pub async fn shadow(env: Env, delivery: ShadowDelivery) {
+    let forwarded = Delivery {
+        method: &delivery.method,
+        headers: &delivery.headers,
+        body: &delivery.body,
+        query: delivery.query.as_deref(),
+    };
+    let outcome = match &delivery.signer {
+        Some(signer) => forward::send_signed(&delivery.target, &forwarded, signer).await,
+        None => forward::send(&delivery.target, &forwarded).await,
+    };
+    let comparison = compare(&delivery.capture_id, &delivery.primary, &outcome, capture_log::now_ms());
+    if !comparison.status_match || !comparison.body_match {
+        log_info!(
+            "🌗 Shadow {} answered capture {} differently ({:?} vs {:?}, {} body paths)",
+            delivery.target,
+            delivery.capture_id,
+            comparison.status_a,
+            comparison.status_b,
+            comparison.body_diff.len()
+        );
+    }
+    let stored = match env.d1("DB") {
+        Ok(db) => record(&db, &delivery.webhook_id, Mode::Shadow, &comparison).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = stored {
+        log_warn!("⚠️  Failed to store shadow comparison: {:?}", e);
+    }
+}
+
+/// Store a comparison (a redelivered capture keeps its first one)
+pub async fn record(db: &D1Database, webhook_id: &str, mode: Mode, comparison: &Comparison) -> Result<()> {
+    let status = |status: Option<u16>| status.map_or(JsValue::NULL, |status| JsValue::from_f64(status as f64));
+    let flag = |value: bool| JsValue::from_f64(if value { 1.0 } else { 0.0 });
+    db.prepare(
+        "INSERT OR IGNORE INTO split_comparisons (webhook_id, capture_id, status_a, status_b, duration_a_ms, \
+         duration_b_ms, status_match, body_match, body_diff, created_at_ms, mode) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+    )
+    .bind(&[
+        JsValue::from_str(webhook_id),
+        JsValue::from_str(&comparison.capture_id),
+        status(comparison.status_a),
+        status(comparison.status_b),
+        JsValue::from_f64(comparison.duration_a_ms as f64),
+        JsValue::from_f64(comparison.duration_b_ms as f64),
+        flag(comparison.status_match),
+        flag(comparison.body_match),
+        JsValue::from_str(&serde_json::to_string(&comparison.body_diff)?),
+        JsValue::from_f64(comparison.created_at_ms as f64),
+        JsValue::from_str(mode.as_str()),
+    ])?
+    .run()
+    .await?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct ComparisonRow {
+    capture_id: String,
+    status_a: Option<f64>,
+    status_b: Option<f64>,
+    duration_a_ms: f64,
+    duration_b_ms: f64,
+    status_match: f64,
+    body_match: f64,
+    body_diff: Option<String>,
+    created_at_ms: f64,
+}
+
+impl From<ComparisonRow> for Comparison {
+    fn from(row: ComparisonRow) -> Self {
+        Self {
+            capture_id: row.capture_id,
+            status_a: row.status_a.map(|status| status as u16),
+            status_b: row.status_b.map(|status| status as u16),
+            duration_a_ms: row.duration_a_ms as i64,
+            duration_b_ms: row.duration_b_ms as i64,
+            status_match: row.status_match != 0.0,
+            body_match: row.body_match != 0.0,
+            body_diff: row
+                .body_diff
+                .and_then(|diff| serde_json::from_str(&diff).ok())
+                .unwrap_or_default(),
+            created_at_ms: row.created_at_ms as i64,
+        }
+    }
+}
+
+/// Comparisons made by `mode` since `since_ms`, newest first
+pub async fn comparisons(
+    db: &D1Database,
+    webhook_id: &str,
+    mode: Mode,
+    since_ms: i64,
+    limit: u32,
+) -> Result<Vec<Comparison>> {
+    let rows = db
+        .prepare(
+            "SELECT capture_id, status_a, status_b, duration_a_ms, duration_b_ms, status_match, body_match, \
+             body_diff, created_at_ms FROM split_comparisons WHERE webhook_id = ?1 AND mode = ?2 \
+             AND created_at_ms >= ?3 ORDER BY created_at_ms DESC LIMIT ?4",
+        )
+        .bind(&[
+            JsValue::from_str(webhook_id),
+            JsValue::from_str(mode.as_str()),
+            JsValue::from_f64(since_ms as f64),
+            JsValue::from_f64(limit as f64),
+        ])?
+        .all()
+        .await?
+        .results::<ComparisonRow>()?;
+    Ok(rows.into_iter().map(Comparison::from).collect())
+}
+
+/// How often a primary and shadow status pair occurred (None for no answer)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StatusPair {
+    pub primary: Option<u16>,
+    pub shadow: Option<u16>,
+    pub count: u64,
+}
+
+/// A body path and the comparisons it differed in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PathCount {
+    pub path: String,
+    pub count: u64,
+}
+
+/// Shadow forwarding mismatches summed up
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MismatchReport {
+    pub compared: u64,
+    pub status_mismatches: u64,
+    pub body_mismatches: u64,
+    /// Share of comparisons where both status and body matched (1 with none compared)
+    pub match_rate: f64,
+    /// Most frequent first
+    pub statuses: Vec<StatusPair>,
+    /// Most frequent first
+    pub paths: Vec<PathCount>,
+    /// Shadow minus primary duration, averaged
+    pub mean_latency_delta_ms: Option<i64>,
+    /// The latest mismatching comparisons, newest first
+    pub samples: Vec<Comparison>,
+}
+
+/// Report on `comparisons` (newest first), keeping up to `samples` mismatches
+pub fn report(comparisons: &[Comparison], samples: usize) -> MismatchReport {
+    let mut statuses: BTreeMap<(Option<u16>, Option<u16>), u64> = BTreeMap::new();
+    let mut paths: BTreeMap<&str, u64> = BTreeMap::new();
+    for comparison in comparisons {
+        *statuses.entry((comparison.status_a, comparison.status_b)).or_default() += 1;
+        for path in &comparison.body_diff {
+            *paths.entry(path).or_default() += 1;
+        }
+    }
+    let mut statuses: Vec<StatusPair> = statuses
+        .into_iter()
+        .map(|((primary, shadow), count)| StatusPair { primary, shadow, count })
+        .collect();
+    statuses.sort_by_key(|pair| std::cmp::Reverse(pair.count));
+    let mut paths: Vec<PathCount> = paths
+        .into_iter()
+        .map(|(path, count)| PathCount { path: path.to_string(), count })
+        .collect();
+    paths.sort_by_key(|path| std::cmp::Reverse(path.count));
+
+    let compared = comparisons.len() as u64;
+    let matched = comparisons.iter().filter(|c| c.status_match && c.body_match).count() as u64;
+    let delta: i64 = comparisons.iter().map(|c| c.duration_b_ms - c.duration_a_ms).sum();
+    MismatchReport {
+        compared,
+        status_mismatches: comparisons.iter().filter(|c| !c.status_match).count() as u64,
+        body_mismatches: comparisons.iter().filter(|c| !c.body_match).count() as u64,
+        match_rate: if compared == 0 { 1.0 } else { matched as f64 / compared as f64 },
+        statuses,
+        paths,
+        mean_latency_delta_ms: (compared > 0).then(|| delta / compared as i64),
+        samples: comparisons
+            .iter()
+            .filter(|c| !c.status_match || !c.body_match)
+            .take(samples)
+            .cloned()
+            .collect(),
+    }
+}
+
+#[derive(Deserialize)]
+struct StatusRow {
+    target: String,
+    status: Option<f64>,
+    count: f64,
+}
+
+/// Forward answers of both arms per target and status since `since_ms` (`none` for no answer)
+pub async fn statuses(
+    db: &D1Database,
+    webhook_id: &str,
+    split: &TrafficSplit,
+    since_ms: i64,
+) -> Result<BTreeMap<String, BTreeMap<String, u64>>> {
+    let rows = db
+        .prepare(
+            "SELECT target, status, COUNT(*) AS count FROM forward_responses \
+             WHERE webhook_id = ?1 AND target IN (?2, ?3) AND created_at_ms >= ?4 GROUP BY target, status",
+        )
+        .bind(&[
+            JsValue::from_str(webhook_id),
+            JsValue::from_str(&split.a),
+            JsValue::from_str(&split.b),
+            JsValue::from_f64(since_ms as f64),
+        ])?
+        .all()
+        .await?
+        .results::<StatusRow>()?;
+    let mut statuses: BTreeMap<String, BTreeMap<String, u64>> = BTreeMap::new();
+    for row in rows {
+        let status = row.status.map_or("none".to_string(), |status| (status as u16).to_string());
+        *statuses.entry(row.target).or_default().entry(status).or_default() += row.count as u64;
+    }
+    Ok(statuses)
+}
+
+/// Drop comparisons past `RETENTION_DAYS`
+pub async fn prune(db: &D1Database, now_ms: i64) -> Result<()> {
+    db.prepare("DELETE FROM split_comparisons WHERE created_at_ms < ?1")
+        .bind(&[JsValue::from_f64((now_ms - RETENTION_DAYS * 86_400_000) as f64)])?
+        .run()
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,159 @@
+//! Public webhook status summaries
+//! A shareable, read-only view of one webhook for partners during an
+//! integration go-live: capture volume over the last hour, day and week, the
+//! forwarding success rate and when the last delivery arrived. Served as JSON
+//! or a small self-contained HTML page from `GET /status/{uuid}` behind a
+//! signed link; nothing about payloads, headers or config is exposed.
+
+use crate::latency::Histogram;
+use serde::Serialize;
+
+/// Forwarding success rate below which the webhook is reported `degraded`
+pub const DEGRADED_SUCCESS_RATE: f64 = 0.95;
+
+/// Captures received over trailing windows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Volume {
+    pub last_hour: u64,
+    pub last_24h: u64,
+    pub last_7d: u64,
+}
+
+/// Forwarding attempts over the last 7 days, all targets combined
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Forwarding {
+    pub attempts: u64,
+    pub failures: u64,
+    /// Share of attempts answered with a 2xx, null without attempts
+    pub success_rate: Option<f64>,
+}
+
+impl Forwarding {
+    pub fn from_histograms<'a>(histograms: impl IntoIterator<Item = &'a Histogram>) -> Self {
+        let (attempts, failures) = histograms
+            .into_iter()
+            .fold((0, 0), |(attempts, failures), histogram| {
+                (attempts + histogram.total(), failures + histogram.failures)
+            });
+        Self {
+            attempts,
+            failures,
+            success_rate: (attempts > 0).then(|| attempts.saturating_sub(failures) as f64 / attempts as f64),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    /// Receiving, and forwarding (if any) mostly succeeds
+    Operational,
+    /// Forwarding success rate below `DEGRADED_SUCCESS_RATE`
+    Degraded,
+    /// Nothing received in the last 24 hours
+    Idle,
+}
+
+impl State {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Operational => "operational",
+            Self::Degraded => "degraded",
+            Self::Idle => "idle",
+        }
+    }
+}
+
+/// The public summary
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Summary {
+    pub webhook_id: String,
+    pub name: String,
+    pub state: State,
+    pub volume: Volume,
+    pub forwarding: Forwarding,
+    pub last_delivery_ms: Option<i64>,
+    pub generated_at_ms: i64,
+}
+
+impl Summary {
+    pub fn new(
+        webhook_id: String,
+        name: String,
+        volume: Volume,
+        forwarding: Forwarding,
+        last_delivery_ms: Option<i64>,
+        generated_at_ms: i64,
+    ) -> Self {
+        let state = if forwarding.success_rate.is_some_and(|rate| rate < DEGRADED_SUCCESS_RATE) {
+            State::Degraded
+        } else if volume.last_24h == 0 {
+            State::Idle
+        } else {
+            State::Operational
+        };
+        Self {
+            webhook_id,
+            name,
+            state,
+            volume,
+            forwarding,
+            last_delivery_ms,
+            generated_at_ms,
+        }
+    }
+
+    /// Self-contained HTML page (no scripts or external assets)
+    pub fn html(&self) -> String {
+        let time = |ms: Option<i64>| {
+            ms.and_then(chrono::DateTime::from_timestamp_millis)
+                .map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                .unwrap_or_else(|| "never".to_string())
+        };
+        let success_rate = self
+            .forwarding
+            .success_rate
+            .map(|rate| format!("{:.1}%", rate * 100.0))
+            .unwrap_or_else(|| "n/a".to_string());
+        format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+             <title>{name} status</title>\n<style>\n\
+             body {{ font-family: system-ui, sans-serif; max-width: 36rem; margin: 3rem auto; padding: 0 1rem; color: #1f2328; }}\n\
+             .state {{ display: inline-block; padding: .25rem .75rem; border-radius: 1rem; color: #fff; }}\n\
+             .operational {{ background: #1a7f37; }} .degraded {{ background: #bf8700; }} .idle {{ background: #6e7781; }}\n\
+             td {{ padding: .35rem 1.5rem .35rem 0; }} footer {{ margin-top: 2rem; color: #6e7781; font-size: .85rem; }}\n\
+             </style>\n</head>\n<body>\n<h1>{name}</h1>\n<p><span class=\"state {state}\">{state}</span></p>\n<table>\n\
+             <tr><td>Last delivery</td><td>{last_delivery}</td></tr>\n\
+             <tr><td>Received (last hour)</td><td>{last_hour}</td></tr>\n\
+             <tr><td>Received (24 hours)</td><td>{last_24h}</td></tr>\n\
+             <tr><td>Received (7 days)</td><td>{last_7d}</td></tr>\n\
+             <tr><td>Forward success (7 days)</td><td>{success_rate} of {attempts}</td></tr>\n\
+             </table>\n<footer>Generated {generated}</footer>\n</body>\n</html>\n",
+            name = escape(&self.name),
+            state = self.state.as_str(),
+            last_delivery = time(self.last_delivery_ms),
+            last_hour = self.volume.last_hour,
+            last_24h = self.volume.last_24h,
+            last_7d = self.volume.last_7d,
+            success_rate = success_rate,
+            attempts = self.forwarding.attempts,
+            generated = time(Some(self.generated_at_ms)),
+        )
+    }
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
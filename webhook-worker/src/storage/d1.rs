@@ -0,0 +1,643 @@
+//! D1 storage backend
+//! Writes to `webhook_data` or its monthly partitions (see `partition`),
+//! routed through D1 sessions (see `db`). Region-pinned databases (see
+//! `residency`) are never partitioned. Large bodies may be stored once in
+//! `bodies` and referenced (see `bodies`).
+
+use super::{
+    capture_placeholders, event_type_clause, merge_daily_counts, merge_shopify_counts, merge_webhook_volumes,
+    CaptureRecord, Consistency, DailyCount, InboxQuery, RequestQuery, ShopifyCount, Storage, StoredRequest,
+    WebhookVolume, CAPTURE_COLUMNS, ERASURE_COLUMNS, INBOX_ORDER, PAYLOAD_STRIP, REQUEST_COLUMNS,
+};
+use crate::residency::{self, Jurisdiction};
+use crate::{bodies, db, partition};
+use serde::Deserialize;
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Excludes the JSON array of capture IDs bound as `?3`
+const KEEP_CLAUSE: &str = "id NOT IN (SELECT value FROM json_each(?3))";
+
+#[derive(Deserialize)]
+struct DailyRow {
+    day: f64,
+    event_type: Option<String>,
+    count: f64,
+    bytes: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct ShopifyRow {
+    shopify_shop_domain: Option<String>,
+    shopify_topic: String,
+    count: f64,
+    bytes: Option<f64>,
+    unverified: Option<f64>,
+    last_received_at_ms: f64,
+}
+
+#[derive(Deserialize)]
+struct VolumeRow {
+    webhook_id: String,
+    count: f64,
+    bytes: Option<f64>,
+    unverified: Option<f64>,
+}
+
+pub struct D1Storage {
+    db: D1Database,
+    partitioning: bool,
+    retention_months: i32,
+    /// Bodies at least this large are stored in `bodies` (None: inline)
+    body_min_bytes: Option<usize>,
+}
+
+impl D1Storage {
+    pub fn from_env(env: &Env, consistency: Consistency, jurisdiction: Option<Jurisdiction>) -> Result<Self> {
+        let binding = residency::binding("DB", jurisdiction);
+        let db = match consistency {
+            Consistency::Primary => db::primary_in(env, &binding)?,
+            Consistency::Replica { bookmark } => db::replica_in(env, &binding, bookmark.as_deref())?,
+        };
+        Ok(Self {
+            db,
+            partitioning: partition::is_enabled(env) && jurisdiction.is_none(),
+            retention_months: partition::retention_months(env),
+            body_min_bytes: bodies::min_bytes(env),
+        })
+    }
+}
+
+impl D1Storage {
+    /// Prepared INSERT for one capture into `table`
+    fn insert_statement(&self, table: &str, record: &CaptureRecord) -> Result<D1PreparedStatement> {
+        let indexed = &record.indexed_headers;
+        self.db
+            .prepare(format!(
+                "INSERT OR IGNORE INTO {} ({}) VALUES ({})",
+                table,
+                CAPTURE_COLUMNS,
+                capture_placeholders('?')
+            ))
+            .bind(&[
+                JsValue::from_str(&record.id),
+                JsValue::from_str(&record.webhook_id),
+                JsValue::from_str(&record.method),
+                JsValue::from_str(&record.headers_json),
+                JsValue::from_str(&record.data),
+                JsValue::from_f64(record.size_bytes as f64),
+                JsValue::from_f64(record.received_at as f64),
+                JsValue::from_f64(record.received_at_ms as f64),
+                optional_i64(record.event_time),
+                optional_i64(record.sequence),
+                optional_str(&indexed.content_type),
+                optional_str(&indexed.user_agent),
+                optional_str(&indexed.signature),
+                optional_str(&indexed.idempotency_key),
+                optional_str(&indexed.event_type),
+                optional_str(&record.verification),
+                optional_str(&record.environment),
+                optional_str(&record.trailers),
+                optional_str(&record.connection_id),
+                optional_str(&record.frame_type),
+                optional_str(&record.processing),
+                optional_str(&record.preview),
+                optional_str(&record.charset),
+                optional_str(&record.original_body),
+                optional_str(&record.canonical_data),
+                optional_str(&indexed.svix_id),
+                optional_i64(indexed.svix_timestamp),
+                optional_str(&record.github.event),
+                optional_str(&record.github.delivery),
+                optional_str(&record.github.installation_id),
+                optional_str(&record.github.repository),
+                optional_str(&record.github.installation),
+                optional_str(&record.stripe_cross_check),
+                optional_str(&indexed.shopify_topic),
+                optional_str(&indexed.shopify_shop_domain),
+                optional_str(&record.oauth_exchange),
+                optional_str(&record.replay_of),
+                optional_str(&indexed.correlation_id),
+            ])
+    }
+
+    /// Every table that may hold captures, oldest first
+    async fn all_tables(&self) -> Result<Vec<String>> {
+        let partitions = if self.partitioning {
+            partition::list(&self.db).await?
+        } else {
+            Vec::new()
+        };
+        Ok(partition::read_tables(&partitions, None, None))
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Storage for D1Storage {
+    async fn insert_capture(&self, record: &CaptureRecord) -> Result<bool> {
+        let table = partition::write_table(self.partitioning, record.received_at);
+        let split = self.body_min_bytes.and_then(|min_bytes| bodies::split(record, min_bytes));
+        let statements = || -> Result<Vec<D1PreparedStatement>> {
+            Ok(match &split {
+                Some((body, referencing)) => vec![
+                    bodies::add_reference(&self.db, &record.webhook_id, body, record.received_at_ms)?,
+                    self.insert_statement(&table, referencing)?,
+                ],
+                None => vec![self.insert_statement(&table, &bodies::inline(record))?],
+            })
+        };
+
+        let results = match self.db.batch(statements()?).await {
+            Ok(results) => results,
+            Err(e) if table == partition::LEGACY_TABLE => return Err(e),
+            Err(e) => {
+                // The scheduled handler normally creates partitions ahead of time;
+                // create it (or bring an old one's columns and indexes up to date) on demand
+                log_warn!("⚠️  Insert into {} failed ({:?}), ensuring partition", table, e);
+                partition::ensure(&self.db, &table).await?;
+                self.db.batch(statements()?).await?
+            }
+        };
+
+        let inserted = match results.last() {
+            Some(result) => result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) > 0,
+            None => false,
+        };
+        // A redelivery already holds its reference
+        if let (false, Some((_, referencing))) = (inserted, &split) {
+            if let Some(hash) = bodies::parse_reference(&referencing.data) {
+                bodies::drop_reference(&self.db, &record.webhook_id, hash)?.run().await?;
+            }
+        }
+        Ok(inserted)
+    }
+
+    async fn insert_captures(&self, records: &[CaptureRecord]) -> Result<()> {
+        let mut statements = Vec::with_capacity(records.len());
+        let mut tables: Vec<String> = Vec::new();
+
+        // Captures referencing a body, by the index of their insert
+        let mut referencing = Vec::new();
+
+        for record in records {
+            let table = partition::write_table(self.partitioning, record.received_at);
+            match self.body_min_bytes.and_then(|min_bytes| bodies::split(record, min_bytes)) {
+                Some((body, record)) => {
+                    let insert = self.insert_statement(&table, &record)?;
+                    statements.push(bodies::add_reference(&self.db, &record.webhook_id, &body, record.received_at_ms)?);
+                    referencing.push((statements.len(), record));
+                    statements.push(insert);
+                }
+                None => statements.push(self.insert_statement(&table, &bodies::inline(record))?),
+            }
+            if !tables.contains(&table) {
+                tables.push(table);
+            }
+        }
+
+        // A batch is atomic, so make sure every target partition exists up front
+        for table in tables.iter().filter(|table| *table != partition::LEGACY_TABLE) {
+            partition::ensure(&self.db, table).await?;
+        }
+
+        let results = self.db.batch(statements).await?;
+        let mut duplicates = Vec::new();
+        for (index, record) in &referencing {
+            let inserted = match results.get(*index) {
+                Some(result) => result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) > 0,
+                None => true,
+            };
+            if let (false, Some(hash)) = (inserted, bodies::parse_reference(&record.data)) {
+                duplicates.push(bodies::drop_reference(&self.db, &record.webhook_id, hash)?);
+            }
+        }
+        if !duplicates.is_empty() {
+            self.db.batch(duplicates).await?;
+        }
+        Ok(())
+    }
+
+    async fn list_requests(&self, query: &RequestQuery) -> Result<Vec<StoredRequest>> {
+        // Shared WHERE clause; numbered parameters are reused by every UNION branch
+        let mut params = vec![JsValue::from_str(&query.webhook_id)];
+        let mut conditions = vec!["webhook_id = ?1".to_string()];
+
+        if let Some(since) = query.since {
+            params.push(JsValue::from_f64(since as f64));
+            conditions.push(format!("received_at >= ?{}", params.len()));
+        }
+        if let Some(until) = query.until {
+            params.push(JsValue::from_f64(until as f64));
+            conditions.push(format!("received_at < ?{}", params.len()));
+        }
+        for (column, value) in &query.filters {
+            params.push(JsValue::from_str(value));
+            conditions.push(format!("{} = ?{}", column, params.len()));
+        }
+
+        let where_clause = conditions.join(" AND ");
+        let partitions = if self.partitioning {
+            partition::list(&self.db).await?
+        } else {
+            Vec::new()
+        };
+        let union = partition::read_tables(&partitions, query.since, query.until)
+            .iter()
+            .map(|table| format!("SELECT {} FROM {} WHERE {}", REQUEST_COLUMNS, table, where_clause))
+            .collect::<Vec<_>>()
+            .join(" UNION ALL ");
+
+        params.push(JsValue::from_f64(query.limit as f64));
+        let limit_param = params.len();
+        params.push(JsValue::from_f64(query.offset as f64));
+        let offset_param = params.len();
+
+        let sql = format!(
+            "SELECT * FROM ({}) ORDER BY {} LIMIT ?{} OFFSET ?{}",
+            union,
+            query.sort.order_by(query.ascending),
+            limit_param,
+            offset_param
+        );
+        let mut requests = self.db.prepare(sql).bind(&params)?.all().await?.results::<StoredRequest>()?;
+        bodies::resolve(&self.db, &mut requests).await?;
+        Ok(requests)
+    }
+
+    async fn inbox_fetch(&self, query: &InboxQuery) -> Result<Vec<StoredRequest>> {
+        let unread = if query.unread_only { " AND read_at_ms IS NULL" } else { "" };
+        let mut leased = Vec::new();
+
+        // Oldest tables first; each UPDATE .. RETURNING leases atomically, so
+        // concurrent consumers never receive the same request (a peek is a plain SELECT)
+        for table in self.all_tables().await? {
+            let remaining = query.limit as usize - leased.len();
+            if remaining == 0 {
+                break;
+            }
+
+            let available = format!(
+                "SELECT {{select}} FROM {table} WHERE webhook_id = ?1 AND acked_at_ms IS NULL \
+                 AND (lease_until_ms IS NULL OR lease_until_ms <= ?2){unread} ORDER BY {order} LIMIT ?3",
+                table = table,
+                unread = unread,
+                order = INBOX_ORDER,
+            );
+            let sql = if query.lease {
+                format!(
+                    "UPDATE {} SET read_at_ms = COALESCE(read_at_ms, ?2), lease_until_ms = ?4 \
+                     WHERE id IN ({}) RETURNING {}",
+                    table,
+                    available.replace("{select}", "id"),
+                    REQUEST_COLUMNS
+                )
+            } else {
+                available.replace("{select}", REQUEST_COLUMNS)
+            };
+            let mut params = vec![
+                JsValue::from_str(&query.webhook_id),
+                JsValue::from_f64(query.now_ms as f64),
+                JsValue::from_f64(remaining as f64),
+            ];
+            if query.lease {
+                params.push(JsValue::from_f64((query.now_ms + query.lease_ms) as f64));
+            }
+            let mut rows = self.db.prepare(sql).bind(&params)?.all().await?.results::<StoredRequest>()?;
+            rows.sort_by_key(|row| row.received_at_ms.unwrap_or(row.received_at * 1000));
+            leased.extend(rows);
+        }
+
+        bodies::resolve(&self.db, &mut leased).await?;
+        Ok(leased)
+    }
+
+    async fn inbox_ack(&self, webhook_id: &str, ids: &[String], now_ms: i64) -> Result<u64> {
+        if ids.is_empty() {
+            return Ok(0);
+        }
+
+        let placeholders = (3..ids.len() + 3)
+            .map(|n| format!("?{}", n))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut params = vec![JsValue::from_str(webhook_id), JsValue::from_f64(now_ms as f64)];
+        params.extend(ids.iter().map(|id| JsValue::from_str(id)));
+
+        let mut acked = 0;
+        for table in self.all_tables().await? {
+            let sql = format!(
+                "UPDATE {} SET acked_at_ms = ?2, lease_until_ms = NULL \
+                 WHERE webhook_id = ?1 AND acked_at_ms IS NULL AND id IN ({})",
+                table, placeholders
+            );
+            let result = self.db.prepare(sql).bind(&params)?.run().await?;
+            acked += result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) as u64;
+        }
+
+        Ok(acked)
+    }
+
+    async fn record_repeat(&self, webhook_id: &str, id: &str, seen_at_ms: i64) -> Result<bool> {
+        let params = [
+            JsValue::from_str(webhook_id),
+            JsValue::from_str(id),
+            JsValue::from_f64(seen_at_ms as f64),
+        ];
+        for table in self.all_tables().await? {
+            let sql = format!(
+                "UPDATE {} SET repeat_count = COALESCE(repeat_count, 1) + 1, \
+                 last_seen_at_ms = MAX(COALESCE(last_seen_at_ms, ?3), ?3) WHERE webhook_id = ?1 AND id = ?2",
+                table
+            );
+            let result = self.db.prepare(sql).bind(&params)?.run().await?;
+            if result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) > 0 {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+    async fn purge(&self, webhook_id: &str, event_type: Option<&str>, before: i64, keep: &[String]) -> Result<u64> {
+        let mut params = vec![
+            JsValue::from_str(webhook_id),
+            JsValue::from_f64(before as f64),
+            JsValue::from_str(&serde_json::to_string(keep)?),
+        ];
+        let mut condition = String::new();
+        if let Some((clause, value)) = event_type.and_then(|pattern| event_type_clause(pattern, "?4")) {
+            condition = format!(" AND {}", clause);
+            params.push(JsValue::from_str(&value));
+        }
+
+        let mut deleted = 0;
+        for table in self.all_tables().await? {
+            let purged = format!("webhook_id = ?1 AND received_at < ?2 AND {}{}", KEEP_CLAUSE, condition);
+            let results = self
+                .db
+                .batch(vec![
+                    bodies::release(&self.db, &table, &purged, &params)?,
+                    self.db.prepare(format!("DELETE FROM {} WHERE {}", table, purged)).bind(&params)?,
+                ])
+                .await?;
+            deleted += changes(&results)?;
+        }
+        Ok(deleted)
+    }
+
+    async fn strip_payloads(&self, webhook_id: &str, before: i64, keep: &[String]) -> Result<u64> {
+        let params = [
+            JsValue::from_str(webhook_id),
+            JsValue::from_f64(before as f64),
+            JsValue::from_str(&serde_json::to_string(keep)?),
+        ];
+        let mut stripped = 0;
+        for table in self.all_tables().await? {
+            let old = format!("webhook_id = ?1 AND received_at < ?2 AND {}", KEEP_CLAUSE);
+            let sql = format!(
+                "UPDATE {} SET {} WHERE {} AND (data != '' OR headers != '{{}}')",
+                table, PAYLOAD_STRIP, old
+            );
+            let results = self
+                .db
+                .batch(vec![
+                    bodies::release(&self.db, &table, &old, &params)?,
+                    self.db.prepare(sql).bind(&params)?,
+                ])
+                .await?;
+            stripped += changes(&results)?;
+        }
+        Ok(stripped)
+    }
+
+    async fn find_containing(&self, webhook_id: &str, needle: &str, limit: u32) -> Result<Vec<StoredRequest>> {
+        // D1's lower() folds ASCII only, the same as the caller's needle
+        let matches = ERASURE_COLUMNS
+            .iter()
+            .map(|column| format!("instr(lower({}), ?2) > 0", column))
+            .collect::<Vec<_>>()
+            .join(" OR ");
+        let mut found = Vec::new();
+        for table in self.all_tables().await? {
+            let remaining = limit as usize - found.len();
+            if remaining == 0 {
+                break;
+            }
+            let sql = format!(
+                "SELECT {} FROM {} WHERE webhook_id = ?1 AND ({} OR {}) ORDER BY {} LIMIT ?3",
+                REQUEST_COLUMNS,
+                table,
+                matches,
+                bodies::body_contains(&table, "?2"),
+                INBOX_ORDER
+            );
+            let rows = self
+                .db
+                .prepare(sql)
+                .bind(&[
+                    JsValue::from_str(webhook_id),
+                    JsValue::from_str(needle),
+                    JsValue::from_f64(remaining as f64),
+                ])?
+                .all()
+                .await?
+                .results::<StoredRequest>()?;
+            found.extend(rows);
+        }
+        bodies::resolve(&self.db, &mut found).await?;
+        Ok(found)
+    }
+
+    async fn delete_captures(&self, webhook_id: &str, ids: &[String]) -> Result<u64> {
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        let params = [JsValue::from_str(webhook_id), JsValue::from_str(&serde_json::to_string(ids)?)];
+        let selected = "webhook_id = ?1 AND id IN (SELECT value FROM json_each(?2))";
+        let mut deleted = 0;
+        for table in self.all_tables().await? {
+            let results = self
+                .db
+                .batch(vec![
+                    bodies::release(&self.db, &table, selected, &params)?,
+                    self.db.prepare(format!("DELETE FROM {} WHERE {}", table, selected)).bind(&params)?,
+                ])
+                .await?;
+            deleted += changes(&results)?;
+        }
+        Ok(deleted)
+    }
+
+    async fn replace_payloads(&self, requests: &[StoredRequest]) -> Result<u64> {
+        if requests.is_empty() {
+            return Ok(0);
+        }
+        let mut replaced = 0;
+        for table in self.all_tables().await? {
+            let sql = format!(
+                "UPDATE {} SET data = ?3, headers = ?4, trailers = ?5, canonical_data = ?6, idempotency_key = ?7, \
+                 preview = ?8, original_body = ?9, stripe_cross_check = ?10, oauth_exchange = ?11 \
+                 WHERE webhook_id = ?1 AND id = ?2",
+                table
+            );
+            // Rewritten payloads are stored inline (escaped), giving back the body they referenced
+            let mut statements = Vec::with_capacity(requests.len() * 2);
+            for request in requests {
+                let row = [JsValue::from_str(&request.webhook_id), JsValue::from_str(&request.id)];
+                statements.push(bodies::release(&self.db, &table, "webhook_id = ?1 AND id = ?2", &row)?);
+                statements.push(self.db.prepare(&sql).bind(&[
+                    JsValue::from_str(&request.webhook_id),
+                    JsValue::from_str(&request.id),
+                    JsValue::from_str(&bodies::escape(&request.data)),
+                    JsValue::from_str(&request.headers),
+                    optional_str(&request.trailers),
+                    optional_str(&request.canonical_data),
+                    optional_str(&request.idempotency_key),
+                    optional_str(&request.preview),
+                    optional_str(&request.original_body),
+                    optional_str(&request.stripe_cross_check),
+                    optional_str(&request.oauth_exchange),
+                ])?);
+            }
+            for result in self.db.batch(statements).await?.iter().skip(1).step_by(2) {
+                replaced += result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) as u64;
+            }
+        }
+        Ok(replaced)
+    }
+
+    async fn daily_counts(&self, webhook_id: &str, before: i64) -> Result<Vec<DailyCount>> {
+        let params = [JsValue::from_str(webhook_id), JsValue::from_f64(before as f64)];
+        let mut counts = Vec::new();
+        for table in self.all_tables().await? {
+            let sql = format!(
+                "SELECT received_at - received_at % 86400 AS day, event_type, COUNT(*) AS count, SUM(size_bytes) AS bytes \
+                 FROM {} WHERE webhook_id = ?1 AND received_at < ?2 GROUP BY day, event_type",
+                table
+            );
+            let rows = self.db.prepare(sql).bind(&params)?.all().await?.results::<DailyRow>()?;
+            counts.extend(rows.into_iter().map(|row| DailyCount {
+                day: row.day as i64,
+                event_type: row.event_type,
+                count: row.count as u64,
+                bytes: row.bytes.unwrap_or(0.0) as u64,
+            }));
+        }
+        Ok(merge_daily_counts(counts))
+    }
+
+    async fn shopify_counts(&self, webhook_id: &str, since: i64, until: i64) -> Result<Vec<ShopifyCount>> {
+        let params = [
+            JsValue::from_str(webhook_id),
+            JsValue::from_f64(since as f64),
+            JsValue::from_f64(until as f64),
+        ];
+        let mut counts = Vec::new();
+        for table in self.all_tables().await? {
+            let sql = format!(
+                "SELECT shopify_shop_domain, shopify_topic, COUNT(*) AS count, SUM(size_bytes) AS bytes, \
+                 SUM(CASE WHEN verification IS NOT NULL AND verification != 'valid' THEN 1 ELSE 0 END) AS unverified, \
+                 MAX(COALESCE(received_at_ms, received_at * 1000)) AS last_received_at_ms FROM {} \
+                 WHERE webhook_id = ?1 AND received_at >= ?2 AND received_at < ?3 AND shopify_topic IS NOT NULL \
+                 GROUP BY shopify_shop_domain, shopify_topic",
+                table
+            );
+            let rows = self.db.prepare(sql).bind(&params)?.all().await?.results::<ShopifyRow>()?;
+            counts.extend(rows.into_iter().map(|row| ShopifyCount {
+                shop_domain: row.shopify_shop_domain,
+                topic: row.shopify_topic,
+                count: row.count as u64,
+                bytes: row.bytes.unwrap_or(0.0) as u64,
+                unverified: row.unverified.unwrap_or(0.0) as u64,
+                last_received_at_ms: row.last_received_at_ms as i64,
+            }));
+        }
+        Ok(merge_shopify_counts(counts))
+    }
+
+    async fn webhook_volumes(&self, since: i64) -> Result<Vec<WebhookVolume>> {
+        let mut volumes = Vec::new();
+        for table in self.all_tables().await? {
+            let sql = format!(
+                "SELECT webhook_id, COUNT(*) AS count, SUM(size_bytes) AS bytes, \
+                 SUM(CASE WHEN verification IS NOT NULL AND verification != 'valid' THEN 1 ELSE 0 END) AS unverified \
+                 FROM {} WHERE received_at >= ?1 GROUP BY webhook_id",
+                table
+            );
+            let rows = self
+                .db
+                .prepare(sql)
+                .bind(&[JsValue::from_f64(since as f64)])?
+                .all()
+                .await?
+                .results::<VolumeRow>()?;
+            volumes.extend(rows.into_iter().map(|row| WebhookVolume {
+                webhook_id: row.webhook_id,
+                count: row.count as u64,
+                bytes: row.bytes.unwrap_or(0.0) as u64,
+                unverified: row.unverified.unwrap_or(0.0) as u64,
+            }));
+        }
+        Ok(merge_webhook_volumes(volumes))
+    }
+
+    async fn count_received(&self, webhook_id: &str, event_type: Option<&str>, since: i64, until: Option<i64>) -> Result<u64> {
+        let mut params = vec![
+            JsValue::from_str(webhook_id),
+            JsValue::from_f64(since as f64),
+            JsValue::from_f64(until.unwrap_or(i64::MAX) as f64),
+        ];
+        let mut condition = String::new();
+        if let Some((clause, value)) = event_type.and_then(|pattern| event_type_clause(pattern, "?4")) {
+            condition = format!(" AND {}", clause);
+            params.push(JsValue::from_str(&value));
+        }
+
+        let mut count = 0;
+        for table in self.all_tables().await? {
+            let sql = format!(
+                "SELECT COUNT(*) AS count FROM {} WHERE webhook_id = ?1 AND received_at >= ?2 AND received_at < ?3{}",
+                table, condition
+            );
+            count += self.db.prepare(sql).bind(&params)?.first::<f64>(Some("count")).await?.unwrap_or(0.0) as u64;
+        }
+        Ok(count)
+    }
+
+    async fn maintain(&self, now: i64) -> Result<()> {
+        if self.partitioning {
+            partition::rollover(&self.db, now, self.retention_months).await?;
+        }
+        let pruned = bodies::prune(&self.db).await?;
+        if pruned > 0 {
+            log_info!("🗑️ Deleted {} unreferenced bodies", pruned);
+        }
+        Ok(())
+    }
+
+    fn bookmark(&self) -> Option<String> {
+        db::bookmark(&self.db)
+    }
+}
+
+/// Rows changed by the last statement of a batch (the one doing the work)
+fn changes(results: &[D1Result]) -> Result<u64> {
+    match results.last() {
+        Some(result) => Ok(result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) as u64),
+        None => Ok(0),
+    }
+}
+
+/// Bind an optional integer as a nullable D1 parameter
+pub(crate) fn optional_i64(value: Option<i64>) -> JsValue {
+    match value {
+        Some(value) => JsValue::from_f64(value as f64),
+        None => JsValue::NULL,
+    }
+}
+
+/// Bind an optional string as a nullable D1 parameter
+pub(crate) fn optional_str(value: &Option<String>) -> JsValue {
+    match value {
+        Some(value) => JsValue::from_str(value),
+        None => JsValue::NULL,
+    }
+}
@@ -0,0 +1,474 @@
+//! Capture storage backends
+//! Captured requests are persisted through the `Storage` trait so the worker can
+//! run against D1 (default) or Postgres via Hyperdrive (`STORAGE_BACKEND = "postgres"`).
+//! Webhook definitions always live in D1, where the admin worker manages them.
+
+mod d1;
+#[cfg(feature = "postgres")]
+mod postgres;
+
+use crate::compression;
+use crate::encryption;
+use crate::github::GithubFields;
+use crate::headers::IndexedHeaders;
+use crate::residency::{self, Jurisdiction};
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+pub use d1::D1Storage;
+pub(crate) use d1::{optional_i64, optional_str};
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresStorage;
+
+/// A captured request ready to be persisted
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CaptureRecord {
+    pub id: String,
+    pub webhook_id: String,
+    pub method: String,
+    pub headers_json: String,
+    pub data: String,
+    pub size_bytes: i32,
+    pub received_at: i64,
+    /// Receive time in Unix milliseconds
+    pub received_at_ms: i64,
+    /// Sender-supplied event time in Unix milliseconds
+    pub event_time: Option<i64>,
+    /// Per-webhook sequence number (None if the sequence DO was unavailable)
+    pub sequence: Option<i64>,
+    pub indexed_headers: IndexedHeaders,
+    /// Signature verification outcome (None when the webhook does not verify)
+    #[serde(default)]
+    pub verification: Option<String>,
+    /// Environment the capture arrived through (None for the webhook's own UUID)
+    #[serde(default)]
+    pub environment: Option<String>,
+    /// Trailing metadata as a JSON object (None when the delivery carried none)
+    #[serde(default)]
+    pub trailers: Option<String>,
+    /// Capture socket connection and frame type (`text`/`binary`) for WebSocket messages
+    #[serde(default)]
+    pub connection_id: Option<String>,
+    #[serde(default)]
+    pub frame_type: Option<String>,
+    /// Pipeline stages that ran, as JSON (see `processing.rs`)
+    #[serde(default)]
+    pub processing: Option<String>,
+    /// Summary of a binary payload, as JSON (see `preview.rs`)
+    #[serde(default)]
+    pub preview: Option<String>,
+    /// Detected body charset (see `charset.rs`)
+    #[serde(default)]
+    pub charset: Option<String>,
+    /// Base64 body bytes, kept when UTF-8 normalization changed them
+    #[serde(default)]
+    pub original_body: Option<String>,
+    /// JSON body with sorted keys, minified (see `canonical.rs`)
+    #[serde(default)]
+    pub canonical_data: Option<String>,
+    /// GitHub event, delivery, installation and repository (see `github.rs`)
+    #[serde(default)]
+    pub github: GithubFields,
+    /// Event vs. current Stripe object, as JSON (see `stripe.rs`)
+    #[serde(default)]
+    pub stripe_cross_check: Option<String>,
+    /// Token endpoint answer to an OAuth callback's code, as JSON (see `oauth.rs`)
+    #[serde(default)]
+    pub oauth_exchange: Option<String>,
+    /// Capture this one is an edited resend of (see `api/requests.rs`)
+    #[serde(default)]
+    pub replay_of: Option<String>,
+    /// Body columns are already in their compressed storage form (see `compression::for_storage`);
+    /// the store escapes them otherwise
+    #[serde(default)]
+    pub storage_form: bool,
+}
+
+/// A captured request as returned by the management API
+pub use webhook_types::captures::StoredRequest;
+
+impl From<&CaptureRecord> for StoredRequest {
+    fn from(record: &CaptureRecord) -> Self {
+        let indexed = record.indexed_headers.clone();
+        Self {
+            id: record.id.clone(),
+            webhook_id: record.webhook_id.clone(),
+            method: record.method.clone(),
+            headers: record.headers_json.clone(),
+            data: record.data.clone(),
+            size_bytes: record.size_bytes as i64,
+            received_at: record.received_at,
+            received_at_ms: Some(record.received_at_ms),
+            event_time: record.event_time,
+            sequence: record.sequence,
+            content_type: indexed.content_type,
+            user_agent: indexed.user_agent,
+            signature: indexed.signature,
+            idempotency_key: indexed.idempotency_key,
+            event_type: indexed.event_type,
+            verification: record.verification.clone(),
+            environment: record.environment.clone(),
+            trailers: record.trailers.clone(),
+            connection_id: record.connection_id.clone(),
+            frame_type: record.frame_type.clone(),
+            processing: record.processing.clone(),
+            preview: record.preview.clone(),
+            charset: record.charset.clone(),
+            original_body: record.original_body.clone(),
+            canonical_data: record.canonical_data.clone(),
+            svix_id: indexed.svix_id,
+            correlation_id: indexed.correlation_id,
+            svix_timestamp: indexed.svix_timestamp,
+            shopify_topic: indexed.shopify_topic,
+            shopify_shop_domain: indexed.shopify_shop_domain,
+            github_event: record.github.event.clone(),
+            github_delivery: record.github.delivery.clone(),
+            github_installation_id: record.github.installation_id.clone(),
+            github_repository: record.github.repository.clone(),
+            github_installation: record.github.installation.clone(),
+            stripe_cross_check: record.stripe_cross_check.clone(),
+            oauth_exchange: record.oauth_exchange.clone(),
+            replay_of: record.replay_of.clone(),
+            repeat_count: None,
+            last_seen_at_ms: None,
+            read_at_ms: None,
+            acked_at_ms: None,
+        }
+    }
+}
+
+/// Columns written for a `CaptureRecord`, in bind order
+pub const CAPTURE_COLUMNS: &str = "id, webhook_id, method, headers, data, size_bytes, received_at, \
+    received_at_ms, event_time, sequence, content_type, user_agent, signature, idempotency_key, event_type, \
+    verification, environment, trailers, connection_id, frame_type, processing, preview, charset, original_body, \
+    canonical_data, svix_id, svix_timestamp, github_event, github_delivery, github_installation_id, github_repository, \
+    github_installation, stripe_cross_check, shopify_topic, shopify_shop_domain, oauth_exchange, replay_of, \
+    correlation_id";
+
+/// Columns selected for `StoredRequest`, shared by every SQL backend
+pub const REQUEST_COLUMNS: &str = "id, webhook_id, method, headers, data, size_bytes, received_at, \
+    received_at_ms, event_time, sequence, content_type, user_agent, signature, idempotency_key, event_type, \
+    verification, environment, trailers, connection_id, frame_type, processing, preview, charset, \
+    original_body, canonical_data, svix_id, svix_timestamp, github_event, github_delivery, github_installation_id, \
+    github_repository, github_installation, stripe_cross_check, shopify_topic, shopify_shop_domain, oauth_exchange, \
+    replay_of, correlation_id, repeat_count, last_seen_at_ms, read_at_ms, acked_at_ms";
+
+/// Inbox delivery order (oldest first)
+pub const INBOX_ORDER: &str = "COALESCE(received_at_ms, received_at * 1000) ASC";
+
+/// Numbered placeholders for `CAPTURE_COLUMNS` (`prefix` is `?` for D1, `$` for Postgres)
+pub fn capture_placeholders(prefix: char) -> String {
+    (1..=CAPTURE_COLUMNS.split(',').count())
+        .map(|n| format!("{}{}", prefix, n))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// SQL condition and bind value for an event type pattern (`push`, `pull_request*`)
+/// using placeholder `param`; None when the pattern matches everything
+pub fn event_type_clause(pattern: &str, param: &str) -> Option<(String, String)> {
+    match pattern.strip_suffix('*') {
+        Some("") => None,
+        Some(prefix) => Some((
+            format!("substr(event_type, 1, length({0})) = {0}", param),
+            prefix.to_string(),
+        )),
+        None => Some((format!("event_type = {}", param), pattern.to_string())),
+    }
+}
+
+/// Sort orders for listing captured requests
+#[derive(Clone, Copy, Default)]
+pub enum SortColumn {
+    #[default]
+    ReceivedAt,
+    EventTime,
+    Sequence,
+}
+
+impl SortColumn {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "received_at" => Some(Self::ReceivedAt),
+            "event_time" => Some(Self::EventTime),
+            "sequence" => Some(Self::Sequence),
+            _ => None,
+        }
+    }
+
+    /// SQL ORDER BY clause; rows captured before millisecond precision fall back to seconds
+    pub fn order_by(&self, ascending: bool) -> String {
+        let direction = if ascending { "ASC" } else { "DESC" };
+        match self {
+            Self::ReceivedAt => format!("COALESCE(received_at_ms, received_at * 1000) {}", direction),
+            Self::EventTime => format!("event_time IS NULL, event_time {}", direction),
+            Self::Sequence => format!("sequence IS NULL, sequence {}", direction),
+        }
+    }
+}
+
+/// Filters for listing captured requests
+pub struct RequestQuery {
+    pub webhook_id: String,
+    pub limit: u32,
+    pub offset: u32,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub sort: SortColumn,
+    pub ascending: bool,
+    /// Equality filters as (column, value); columns come from a fixed allow-list
+    pub filters: Vec<(&'static str, String)>,
+}
+
+/// Captures of one UTC day and event type, for tiered retention (see `retention.rs`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DailyCount {
+    /// Start of the UTC day, Unix seconds
+    pub day: i64,
+    pub event_type: Option<String>,
+    pub count: u64,
+    pub bytes: u64,
+}
+
+/// Merge per-table counts of the same day and event type
+pub fn merge_daily_counts(counts: impl IntoIterator<Item = DailyCount>) -> Vec<DailyCount> {
+    let mut merged: std::collections::BTreeMap<(i64, Option<String>), DailyCount> = Default::default();
+    for count in counts {
+        let entry = merged.entry((count.day, count.event_type.clone())).or_insert(DailyCount {
+            count: 0,
+            bytes: 0,
+            ..count.clone()
+        });
+        entry.count += count.count;
+        entry.bytes += count.bytes;
+    }
+    merged.into_values().collect()
+}
+
+/// Shopify captures of one shop and topic (`GET /api/webhooks/{uuid}/stats/shopify`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ShopifyCount {
+    pub shop_domain: Option<String>,
+    pub topic: String,
+    pub count: u64,
+    pub bytes: u64,
+    /// Captures whose signature verification was not `valid`
+    pub unverified: u64,
+    pub last_received_at_ms: i64,
+}
+
+/// Merge per-table counts of the same shop and topic, busiest first
+pub fn merge_shopify_counts(counts: impl IntoIterator<Item = ShopifyCount>) -> Vec<ShopifyCount> {
+    let mut merged: std::collections::BTreeMap<(Option<String>, String), ShopifyCount> = Default::default();
+    for count in counts {
+        let key = (count.shop_domain.clone(), count.topic.clone());
+        match merged.get_mut(&key) {
+            Some(entry) => {
+                entry.count += count.count;
+                entry.bytes += count.bytes;
+                entry.unverified += count.unverified;
+                entry.last_received_at_ms = entry.last_received_at_ms.max(count.last_received_at_ms);
+            }
+            None => {
+                merged.insert(key, count);
+            }
+        }
+    }
+    let mut counts: Vec<ShopifyCount> = merged.into_values().collect();
+    counts.sort_by_key(|count| std::cmp::Reverse(count.count));
+    counts
+}
+
+/// Captures of one webhook, for the deployment overview (`GET /api/admin/overview`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WebhookVolume {
+    pub webhook_id: String,
+    pub count: u64,
+    pub bytes: u64,
+    /// Captures whose signature verification was not `valid`
+    pub unverified: u64,
+}
+
+/// Merge per-table (or per-backend) volumes of the same webhook, busiest first
+pub fn merge_webhook_volumes(volumes: impl IntoIterator<Item = WebhookVolume>) -> Vec<WebhookVolume> {
+    let mut merged: std::collections::BTreeMap<String, WebhookVolume> = Default::default();
+    for volume in volumes {
+        match merged.get_mut(&volume.webhook_id) {
+            Some(entry) => {
+                entry.count += volume.count;
+                entry.bytes += volume.bytes;
+                entry.unverified += volume.unverified;
+            }
+            None => {
+                merged.insert(volume.webhook_id.clone(), volume);
+            }
+        }
+    }
+    let mut volumes: Vec<WebhookVolume> = merged.into_values().collect();
+    volumes.sort_by_key(|volume| std::cmp::Reverse(volume.count));
+    volumes
+}
+
+/// Columns cleared when a capture drops to metadata only
+pub const PAYLOAD_STRIP: &str = "data = '', headers = '{}', trailers = NULL, canonical_data = NULL, \
+    original_body = NULL, stripe_cross_check = NULL, oauth_exchange = NULL";
+
+/// Columns searched for a data subject's identifier and rewritten on redaction (see `erasure.rs`)
+pub const ERASURE_COLUMNS: [&str; 8] = [
+    "data",
+    "headers",
+    "trailers",
+    "canonical_data",
+    "idempotency_key",
+    "preview",
+    "stripe_cross_check",
+    "oauth_exchange",
+];
+
+/// Lease request for inbox consumers
+pub struct InboxQuery {
+    pub webhook_id: String,
+    pub limit: u32,
+    /// Leased requests are hidden from other fetches until this long after `now_ms`
+    pub lease_ms: i64,
+    pub now_ms: i64,
+    /// Only requests never fetched before (skip expired leases)
+    pub unread_only: bool,
+    /// Lease and mark read what is returned; false only looks at what a lease would get
+    pub lease: bool,
+}
+
+#[async_trait::async_trait(?Send)]
+pub trait Storage {
+    /// Persist a single captured request; false when a capture with its ID already
+    /// exists, which is left unchanged (see `dedup`)
+    async fn insert_capture(&self, record: &CaptureRecord) -> Result<bool>;
+
+    /// Persist a batch of captured requests (used by the hot store flush), skipping existing IDs
+    async fn insert_captures(&self, records: &[CaptureRecord]) -> Result<()> {
+        for record in records {
+            self.insert_capture(record).await?;
+        }
+        Ok(())
+    }
+
+    /// List captured requests in the requested order
+    async fn list_requests(&self, query: &RequestQuery) -> Result<Vec<StoredRequest>>;
+
+    /// Lease up to `limit` unacknowledged requests, oldest first, marking them read.
+    /// Requests whose lease expires without an ack are handed out again.
+    async fn inbox_fetch(&self, query: &InboxQuery) -> Result<Vec<StoredRequest>>;
+
+    /// Acknowledge requests so they are never fetched again; returns how many changed
+    async fn inbox_ack(&self, webhook_id: &str, ids: &[String], now_ms: i64) -> Result<u64>;
+
+    /// Fold a repeated delivery seen at `seen_at_ms` into a stored capture, counting it
+    /// (see `heartbeat.rs`); false when the capture no longer exists
+    async fn record_repeat(&self, webhook_id: &str, id: &str, seen_at_ms: i64) -> Result<bool>;
+
+    /// Delete a webhook's captures received before `before` (Unix seconds), optionally only
+    /// those matching an event type pattern (see `event_type_clause`), except the `keep` IDs
+    /// (legal holds); returns how many
+    async fn purge(&self, webhook_id: &str, event_type: Option<&str>, before: i64, keep: &[String]) -> Result<u64>;
+
+    /// Clear bodies and headers (`PAYLOAD_STRIP`) of a webhook's captures received before
+    /// `before` (Unix seconds) except the `keep` IDs, keeping the indexed metadata; returns how many changed
+    async fn strip_payloads(&self, webhook_id: &str, before: i64, keep: &[String]) -> Result<u64>;
+
+    /// A webhook's captures with `needle` (ASCII lowercase) in any of `ERASURE_COLUMNS`,
+    /// compared ASCII case-insensitively; at most `limit`
+    async fn find_containing(&self, webhook_id: &str, needle: &str, limit: u32) -> Result<Vec<StoredRequest>>;
+
+    /// Delete a webhook's captures by ID; returns how many
+    async fn delete_captures(&self, webhook_id: &str, ids: &[String]) -> Result<u64>;
+
+    /// Overwrite the `ERASURE_COLUMNS` and `original_body` of stored captures with
+    /// those of `requests` (matched by webhook and ID); returns how many changed
+    async fn replace_payloads(&self, requests: &[StoredRequest]) -> Result<u64>;
+
+    /// A webhook's captures received before `before` (Unix seconds), counted per UTC day and event type
+    async fn daily_counts(&self, webhook_id: &str, before: i64) -> Result<Vec<DailyCount>>;
+
+    /// Count a webhook's captures received in `[since, until)` (Unix seconds; no
+    /// upper bound without `until`), optionally only those matching an event type pattern
+    async fn count_received(&self, webhook_id: &str, event_type: Option<&str>, since: i64, until: Option<i64>) -> Result<u64>;
+
+    /// A webhook's Shopify captures received in `[since, until)` (Unix seconds), counted per shop and topic
+    async fn shopify_counts(&self, webhook_id: &str, since: i64, until: i64) -> Result<Vec<ShopifyCount>>;
+
+    /// Every webhook's captures received since `since` (Unix seconds), busiest first
+    async fn webhook_volumes(&self, since: i64) -> Result<Vec<WebhookVolume>>;
+
+    /// Periodic maintenance run by the scheduled handler
+    async fn maintain(&self, _now: i64) -> Result<()> {
+        Ok(())
+    }
+
+    /// Session bookmark to hand back to the client after reads (D1 replication)
+    fn bookmark(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Read consistency requested from the backend
+pub enum Consistency {
+    /// Route to the primary (all writes)
+    Primary,
+    /// Nearest replica, resuming from the client's bookmark if given
+    Replica { bookmark: Option<String> },
+}
+
+/// Build the storage backend selected by the `STORAGE_BACKEND` var, for writes
+pub async fn from_env(env: &Env) -> Result<Box<dyn Storage>> {
+    open(env, Consistency::Primary).await
+}
+
+/// Build the storage backend with explicit read consistency
+pub async fn open(env: &Env, consistency: Consistency) -> Result<Box<dyn Storage>> {
+    open_in(env, consistency, None).await
+}
+
+/// Storage for one webhook's captures, in its project's jurisdiction
+pub async fn for_webhook(env: &Env, webhook_id: &str, consistency: Consistency) -> Result<Box<dyn Storage>> {
+    let jurisdiction = residency::of_webhook(&env.d1("DB")?, webhook_id).await?;
+    open_in(env, consistency, jurisdiction).await
+}
+
+/// Every webhook's captures received since `since` (Unix seconds) in every capture
+/// store: the default one and each jurisdiction's that is wired up, busiest first
+pub async fn all_webhook_volumes(env: &Env, since: i64) -> Result<Vec<WebhookVolume>> {
+    let mut volumes = Vec::new();
+    for jurisdiction in [None, Some(Jurisdiction::Eu), Some(Jurisdiction::Us)] {
+        if jurisdiction.is_some() && residency::check(env, jurisdiction).is_err() {
+            continue;
+        }
+        let storage = open_in(env, Consistency::Replica { bookmark: None }, jurisdiction).await?;
+        volumes.extend(storage.webhook_volumes(since).await?);
+    }
+    Ok(merge_webhook_volumes(volumes))
+}
+
+/// Build the storage backend on a jurisdiction's bindings (see `residency.rs`);
+/// Err when a pinned jurisdiction's binding is missing
+pub async fn open_in(env: &Env, consistency: Consistency, jurisdiction: Option<Jurisdiction>) -> Result<Box<dyn Storage>> {
+    residency::check(env, jurisdiction).map_err(Error::RustError)?;
+    let backend = env
+        .var("STORAGE_BACKEND")
+        .map(|value| value.to_string())
+        .unwrap_or_else(|_| "d1".to_string());
+
+    let storage: Box<dyn Storage> = match backend.as_str() {
+        "d1" => Box::new(D1Storage::from_env(env, consistency, jurisdiction)?),
+        #[cfg(feature = "postgres")]
+        "postgres" => Box::new(PostgresStorage::connect(env, &residency::binding("HYPERDRIVE", jurisdiction)).await?),
+        other => {
+            return Err(Error::RustError(format!(
+                "Unsupported STORAGE_BACKEND: {}",
+                other
+            )))
+        }
+    };
+    // Payload encryption applies to every backend (see `encryption.rs`), and bodies
+    // are decompressed once decrypted (see `compression.rs`)
+    Ok(compression::wrap(encryption::wrap(env, storage)?))
+}
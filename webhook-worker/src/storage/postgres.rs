@@ -0,0 +1,423 @@
+//! Postgres storage backend (via Hyperdrive)
+//! Enabled with the `postgres` cargo feature and `STORAGE_BACKEND = "postgres"`.
+//! Expects the schema from `webhook-worker/postgres/schema.sql`.
+
+use super::{
+    capture_placeholders, event_type_clause, merge_shopify_counts, merge_webhook_volumes, CaptureRecord, DailyCount,
+    InboxQuery, RequestQuery, ShopifyCount, Storage, StoredRequest, WebhookVolume, CAPTURE_COLUMNS, ERASURE_COLUMNS,
+    INBOX_ORDER, PAYLOAD_STRIP, REQUEST_COLUMNS,
+};
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{Client, Config, Row};
+use worker::postgres_tls::PassthroughTls;
+use worker::*;
+
+/// Excludes the capture IDs bound as `$3` (a text array)
+const KEEP_CLAUSE: &str = "NOT (id = ANY($3))";
+
+pub struct PostgresStorage {
+    client: Client,
+}
+
+impl PostgresStorage {
+    /// Open a connection through a Hyperdrive binding (`HYPERDRIVE`, or a region-pinned one)
+    pub async fn connect(env: &Env, binding: &str) -> Result<Self> {
+        let hyperdrive = env.hyperdrive(binding)?;
+        let config = hyperdrive
+            .connection_string()
+            .parse::<Config>()
+            .map_err(pg_error)?;
+
+        let socket = Socket::builder()
+            .secure_transport(SecureTransport::StartTls)
+            .connect(hyperdrive.host(), hyperdrive.port())?;
+        let (client, connection) = config
+            .connect_raw(socket, PassthroughTls)
+            .await
+            .map_err(pg_error)?;
+
+        // Drive the connection in the background for the lifetime of the request
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(e) = connection.await {
+                log_error!("❌ Postgres connection error: {:?}", e);
+            }
+        });
+
+        Ok(Self { client })
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Storage for PostgresStorage {
+    async fn insert_capture(&self, record: &CaptureRecord) -> Result<bool> {
+        let indexed = &record.indexed_headers;
+        let sql = format!(
+            "INSERT INTO webhook_data ({}) VALUES ({}) ON CONFLICT (id) DO NOTHING",
+            CAPTURE_COLUMNS,
+            capture_placeholders('$')
+        );
+        let inserted = self
+            .client
+            .execute(
+                &sql,
+                &[
+                    &record.id,
+                    &record.webhook_id,
+                    &record.method,
+                    &record.headers_json,
+                    &record.data,
+                    &(record.size_bytes as i64),
+                    &record.received_at,
+                    &record.received_at_ms,
+                    &record.event_time,
+                    &record.sequence,
+                    &indexed.content_type,
+                    &indexed.user_agent,
+                    &indexed.signature,
+                    &indexed.idempotency_key,
+                    &indexed.event_type,
+                    &record.verification,
+                    &record.environment,
+                    &record.trailers,
+                    &record.connection_id,
+                    &record.frame_type,
+                    &record.processing,
+                    &record.preview,
+                    &record.charset,
+                    &record.original_body,
+                    &record.canonical_data,
+                    &indexed.svix_id,
+                    &indexed.svix_timestamp,
+                    &record.github.event,
+                    &record.github.delivery,
+                    &record.github.installation_id,
+                    &record.github.repository,
+                    &record.github.installation,
+                    &record.stripe_cross_check,
+                    &record.indexed_headers.shopify_topic,
+                    &record.indexed_headers.shopify_shop_domain,
+                    &record.oauth_exchange,
+                    &record.replay_of,
+                    &indexed.correlation_id,
+                ],
+            )
+            .await
+            .map_err(pg_error)?;
+        Ok(inserted > 0)
+    }
+
+    async fn list_requests(&self, query: &RequestQuery) -> Result<Vec<StoredRequest>> {
+        let limit = query.limit as i64;
+        let offset = query.offset as i64;
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![&query.webhook_id];
+        let mut conditions = vec!["webhook_id = $1".to_string()];
+
+        if let Some(since) = &query.since {
+            params.push(since);
+            conditions.push(format!("received_at >= ${}", params.len()));
+        }
+        if let Some(until) = &query.until {
+            params.push(until);
+            conditions.push(format!("received_at < ${}", params.len()));
+        }
+        for (column, value) in &query.filters {
+            params.push(value);
+            conditions.push(format!("{} = ${}", column, params.len()));
+        }
+
+        params.push(&limit);
+        let limit_param = params.len();
+        params.push(&offset);
+        let offset_param = params.len();
+
+        let sql = format!(
+            "SELECT {} FROM webhook_data WHERE {} ORDER BY {} LIMIT ${} OFFSET ${}",
+            REQUEST_COLUMNS,
+            conditions.join(" AND "),
+            query.sort.order_by(query.ascending),
+            limit_param,
+            offset_param
+        );
+        let rows = self.client.query(&sql, &params).await.map_err(pg_error)?;
+        Ok(rows.iter().map(stored_request).collect())
+    }
+
+    async fn inbox_fetch(&self, query: &InboxQuery) -> Result<Vec<StoredRequest>> {
+        let unread = if query.unread_only { " AND read_at_ms IS NULL" } else { "" };
+        let limit = query.limit as i64;
+        let rows = if query.lease {
+            let sql = format!(
+                "UPDATE webhook_data SET read_at_ms = COALESCE(read_at_ms, $2), lease_until_ms = $3 \
+                 WHERE id IN (SELECT id FROM webhook_data WHERE webhook_id = $1 AND acked_at_ms IS NULL \
+                 AND (lease_until_ms IS NULL OR lease_until_ms <= $2){} ORDER BY {} LIMIT $4 \
+                 FOR UPDATE SKIP LOCKED) RETURNING {}",
+                unread, INBOX_ORDER, REQUEST_COLUMNS
+            );
+            let lease_until = query.now_ms + query.lease_ms;
+            self.client
+                .query(&sql, &[&query.webhook_id, &query.now_ms, &lease_until, &limit])
+                .await
+        } else {
+            let sql = format!(
+                "SELECT {} FROM webhook_data WHERE webhook_id = $1 AND acked_at_ms IS NULL \
+                 AND (lease_until_ms IS NULL OR lease_until_ms <= $2){} ORDER BY {} LIMIT $3",
+                REQUEST_COLUMNS, unread, INBOX_ORDER
+            );
+            self.client.query(&sql, &[&query.webhook_id, &query.now_ms, &limit]).await
+        }
+        .map_err(pg_error)?;
+
+        let mut leased: Vec<StoredRequest> = rows.iter().map(stored_request).collect();
+        leased.sort_by_key(|row| row.received_at_ms.unwrap_or(row.received_at * 1000));
+        Ok(leased)
+    }
+
+    async fn inbox_ack(&self, webhook_id: &str, ids: &[String], now_ms: i64) -> Result<u64> {
+        self.client
+            .execute(
+                "UPDATE webhook_data SET acked_at_ms = $2, lease_until_ms = NULL \
+                 WHERE webhook_id = $1 AND acked_at_ms IS NULL AND id = ANY($3)",
+                &[&webhook_id, &now_ms, &ids],
+            )
+            .await
+            .map_err(pg_error)
+    }
+
+    async fn record_repeat(&self, webhook_id: &str, id: &str, seen_at_ms: i64) -> Result<bool> {
+        let changed = self
+            .client
+            .execute(
+                "UPDATE webhook_data SET repeat_count = COALESCE(repeat_count, 1) + 1, \
+                 last_seen_at_ms = GREATEST(COALESCE(last_seen_at_ms, $3), $3) WHERE webhook_id = $1 AND id = $2",
+                &[&webhook_id, &id, &seen_at_ms],
+            )
+            .await
+            .map_err(pg_error)?;
+        Ok(changed > 0)
+    }
+
+    async fn purge(&self, webhook_id: &str, event_type: Option<&str>, before: i64, keep: &[String]) -> Result<u64> {
+        match event_type.and_then(|pattern| event_type_clause(pattern, "$4")) {
+            Some((clause, value)) => {
+                let sql = format!(
+                    "DELETE FROM webhook_data WHERE webhook_id = $1 AND received_at < $2 AND {} AND {}",
+                    KEEP_CLAUSE, clause
+                );
+                self.client.execute(&sql, &[&webhook_id, &before, &keep, &value]).await
+            }
+            None => {
+                let sql = format!(
+                    "DELETE FROM webhook_data WHERE webhook_id = $1 AND received_at < $2 AND {}",
+                    KEEP_CLAUSE
+                );
+                self.client.execute(&sql, &[&webhook_id, &before, &keep]).await
+            }
+        }
+        .map_err(pg_error)
+    }
+
+    async fn strip_payloads(&self, webhook_id: &str, before: i64, keep: &[String]) -> Result<u64> {
+        let sql = format!(
+            "UPDATE webhook_data SET {} WHERE webhook_id = $1 AND received_at < $2 AND {} \
+             AND (data != '' OR headers != '{{}}')",
+            PAYLOAD_STRIP, KEEP_CLAUSE
+        );
+        self.client.execute(&sql, &[&webhook_id, &before, &keep]).await.map_err(pg_error)
+    }
+
+    async fn find_containing(&self, webhook_id: &str, needle: &str, limit: u32) -> Result<Vec<StoredRequest>> {
+        let matches = ERASURE_COLUMNS
+            .iter()
+            .map(|column| format!("strpos(lower({}), $2) > 0", column))
+            .collect::<Vec<_>>()
+            .join(" OR ");
+        let sql = format!(
+            "SELECT {} FROM webhook_data WHERE webhook_id = $1 AND ({}) ORDER BY {} LIMIT $3",
+            REQUEST_COLUMNS, matches, INBOX_ORDER
+        );
+        let limit = limit as i64;
+        let rows = self
+            .client
+            .query(&sql, &[&webhook_id, &needle, &limit])
+            .await
+            .map_err(pg_error)?;
+        Ok(rows.iter().map(stored_request).collect())
+    }
+
+    async fn delete_captures(&self, webhook_id: &str, ids: &[String]) -> Result<u64> {
+        self.client
+            .execute("DELETE FROM webhook_data WHERE webhook_id = $1 AND id = ANY($2)", &[&webhook_id, &ids])
+            .await
+            .map_err(pg_error)
+    }
+
+    async fn replace_payloads(&self, requests: &[StoredRequest]) -> Result<u64> {
+        let mut replaced = 0;
+        for request in requests {
+            replaced += self
+                .client
+                .execute(
+                    "UPDATE webhook_data SET data = $3, headers = $4, trailers = $5, canonical_data = $6, \
+                     idempotency_key = $7, preview = $8, original_body = $9, stripe_cross_check = $10, \
+                     oauth_exchange = $11 WHERE webhook_id = $1 AND id = $2",
+                    &[
+                        &request.webhook_id,
+                        &request.id,
+                        &request.data,
+                        &request.headers,
+                        &request.trailers,
+                        &request.canonical_data,
+                        &request.idempotency_key,
+                        &request.preview,
+                        &request.original_body,
+                        &request.stripe_cross_check,
+                        &request.oauth_exchange,
+                    ],
+                )
+                .await
+                .map_err(pg_error)?;
+        }
+        Ok(replaced)
+    }
+
+    async fn daily_counts(&self, webhook_id: &str, before: i64) -> Result<Vec<DailyCount>> {
+        let rows = self
+            .client
+            .query(
+                "SELECT received_at - received_at % 86400 AS day, event_type, COUNT(*) AS count, \
+                 COALESCE(SUM(size_bytes), 0)::BIGINT AS bytes FROM webhook_data \
+                 WHERE webhook_id = $1 AND received_at < $2 GROUP BY day, event_type ORDER BY day",
+                &[&webhook_id, &before],
+            )
+            .await
+            .map_err(pg_error)?;
+        Ok(rows
+            .iter()
+            .map(|row| DailyCount {
+                day: row.get("day"),
+                event_type: row.get("event_type"),
+                count: row.get::<_, i64>("count") as u64,
+                bytes: row.get::<_, i64>("bytes") as u64,
+            })
+            .collect())
+    }
+
+    async fn shopify_counts(&self, webhook_id: &str, since: i64, until: i64) -> Result<Vec<ShopifyCount>> {
+        let rows = self
+            .client
+            .query(
+                "SELECT shopify_shop_domain, shopify_topic, COUNT(*) AS count, \
+                 COALESCE(SUM(size_bytes), 0)::BIGINT AS bytes, \
+                 COUNT(*) FILTER (WHERE verification IS NOT NULL AND verification != 'valid') AS unverified, \
+                 MAX(COALESCE(received_at_ms, received_at * 1000)) AS last_received_at_ms FROM webhook_data \
+                 WHERE webhook_id = $1 AND received_at >= $2 AND received_at < $3 AND shopify_topic IS NOT NULL \
+                 GROUP BY shopify_shop_domain, shopify_topic",
+                &[&webhook_id, &since, &until],
+            )
+            .await
+            .map_err(pg_error)?;
+        Ok(merge_shopify_counts(rows.iter().map(|row| ShopifyCount {
+            shop_domain: row.get("shopify_shop_domain"),
+            topic: row.get("shopify_topic"),
+            count: row.get::<_, i64>("count") as u64,
+            bytes: row.get::<_, i64>("bytes") as u64,
+            unverified: row.get::<_, i64>("unverified") as u64,
+            last_received_at_ms: row.get("last_received_at_ms"),
+        })))
+    }
+
+    async fn webhook_volumes(&self, since: i64) -> Result<Vec<WebhookVolume>> {
+        let rows = self
+            .client
+            .query(
+                "SELECT webhook_id, COUNT(*) AS count, COALESCE(SUM(size_bytes), 0)::BIGINT AS bytes, \
+                 COUNT(*) FILTER (WHERE verification IS NOT NULL AND verification != 'valid') AS unverified \
+                 FROM webhook_data WHERE received_at >= $1 GROUP BY webhook_id",
+                &[&since],
+            )
+            .await
+            .map_err(pg_error)?;
+        Ok(merge_webhook_volumes(rows.iter().map(|row| WebhookVolume {
+            webhook_id: row.get("webhook_id"),
+            count: row.get::<_, i64>("count") as u64,
+            bytes: row.get::<_, i64>("bytes") as u64,
+            unverified: row.get::<_, i64>("unverified") as u64,
+        })))
+    }
+
+    async fn count_received(&self, webhook_id: &str, event_type: Option<&str>, since: i64, until: Option<i64>) -> Result<u64> {
+        let until = until.unwrap_or(i64::MAX);
+        let row = match event_type.and_then(|pattern| event_type_clause(pattern, "$4")) {
+            Some((clause, value)) => {
+                let sql = format!(
+                    "SELECT COUNT(*) AS count FROM webhook_data \
+                     WHERE webhook_id = $1 AND received_at >= $2 AND received_at < $3 AND {}",
+                    clause
+                );
+                self.client.query_one(&sql, &[&webhook_id, &since, &until, &value]).await
+            }
+            None => {
+                self.client
+                    .query_one(
+                        "SELECT COUNT(*) AS count FROM webhook_data \
+                         WHERE webhook_id = $1 AND received_at >= $2 AND received_at < $3",
+                        &[&webhook_id, &since, &until],
+                    )
+                    .await
+            }
+        }
+        .map_err(pg_error)?;
+        Ok(row.get::<_, i64>("count") as u64)
+    }
+}
+
+fn stored_request(row: &Row) -> StoredRequest {
+    StoredRequest {
+        id: row.get("id"),
+        webhook_id: row.get("webhook_id"),
+        method: row.get("method"),
+        headers: row.get("headers"),
+        data: row.get("data"),
+        size_bytes: row.get("size_bytes"),
+        received_at: row.get("received_at"),
+        received_at_ms: row.get("received_at_ms"),
+        event_time: row.get("event_time"),
+        sequence: row.get("sequence"),
+        content_type: row.get("content_type"),
+        user_agent: row.get("user_agent"),
+        signature: row.get("signature"),
+        idempotency_key: row.get("idempotency_key"),
+        event_type: row.get("event_type"),
+        verification: row.get("verification"),
+        environment: row.get("environment"),
+        trailers: row.get("trailers"),
+        connection_id: row.get("connection_id"),
+        frame_type: row.get("frame_type"),
+        processing: row.get("processing"),
+        preview: row.get("preview"),
+        charset: row.get("charset"),
+        original_body: row.get("original_body"),
+        canonical_data: row.get("canonical_data"),
+        svix_id: row.get("svix_id"),
+        svix_timestamp: row.get("svix_timestamp"),
+        github_event: row.get("github_event"),
+        github_delivery: row.get("github_delivery"),
+        github_installation_id: row.get("github_installation_id"),
+        github_repository: row.get("github_repository"),
+        github_installation: row.get("github_installation"),
+        stripe_cross_check: row.get("stripe_cross_check"),
+        shopify_topic: row.get("shopify_topic"),
+        shopify_shop_domain: row.get("shopify_shop_domain"),
+        oauth_exchange: row.get("oauth_exchange"),
+        replay_of: row.get("replay_of"),
+        correlation_id: row.get("correlation_id"),
+        repeat_count: row.get("repeat_count"),
+        last_seen_at_ms: row.get("last_seen_at_ms"),
+        read_at_ms: row.get("read_at_ms"),
+        acked_at_ms: row.get("acked_at_ms"),
+    }
+}
+
+fn pg_error(e: tokio_postgres::Error) -> Error {
+    Error::RustError(format!("Postgres error: {}", e))
+}
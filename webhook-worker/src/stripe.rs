@@ -0,0 +1,191 @@
+//! Stripe API cross-check
+//! With `stripe_cross_check` in the webhook config, a verified Stripe event is
+//! compared with the referenced object as the Stripe API returns it now (read
+//! with the restricted key in the `STRIPE_API_KEY` secret, which only needs
+//! read access to the object types involved). The changed fields are stored in
+//! `webhook_data.stripe_cross_check`, so a handler that processed an event late
+//! can see what no longer matches. The lookup is bounded by `API_TIMEOUT` and a
+//! failure is stored as such instead of failing the capture.
+
+use futures_util::future::{select, Either};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+use worker::*;
+
+const API: &str = "https://api.stripe.com/v1";
+
+/// Give up on a slow Stripe API after this long (the capture waits for it)
+const API_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Most changed fields recorded for one event
+pub const MAX_CHANGES: usize = 100;
+
+/// The object an event is about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Referenced {
+    /// Stripe object type (`payment_intent`, `checkout.session`, ...)
+    pub object: String,
+    pub id: String,
+}
+
+impl Referenced {
+    /// `data.object` of an event body
+    pub fn from_event(body: &Value) -> Option<Self> {
+        let object = body.pointer("/data/object")?;
+        Some(Self {
+            object: object.get("object")?.as_str()?.to_string(),
+            id: object.get("id")?.as_str()?.to_string(),
+        })
+    }
+
+    /// API path of the object: the type pluralized, with `.` as a path separator
+    pub fn path(&self) -> String {
+        let plural = match self.object.as_str() {
+            object if object.ends_with('y') => format!("{}ies", &object[..object.len() - 1]),
+            object if object.ends_with('s') => format!("{}es", object),
+            object => format!("{}s", object),
+        };
+        format!("{}/{}", plural.replace('.', "/"), self.id)
+    }
+}
+
+/// One field that differs between the event and the object now
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+    /// Dotted path (`status`, `charges.data.0.amount`)
+    pub path: String,
+    /// Value in the event (None when the field is new)
+    pub event: Option<Value>,
+    /// Value now (None when the field is gone)
+    pub current: Option<Value>,
+}
+
+/// What the cross-check found, stored as JSON
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CrossCheck {
+    pub object: String,
+    pub id: String,
+    pub checked_at_ms: i64,
+    /// Changed fields, at most `MAX_CHANGES`
+    #[serde(default)]
+    pub changes: Vec<Change>,
+    /// More fields changed than were recorded
+    #[serde(default)]
+    pub truncated: bool,
+    /// Why the object could not be read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Fields that differ between the event's copy of an object and the current one
+pub fn diff(event: &Value, current: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+    walk("", event, current, &mut changes);
+    changes
+}
+
+fn walk(path: &str, event: &Value, current: &Value, changes: &mut Vec<Change>) {
+    let child = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    match (event, current) {
+        (Value::Object(event), Value::Object(current)) => {
+            let added = current.keys().filter(|key| !event.contains_key(*key));
+            let mut keys: Vec<&String> = event.keys().chain(added).collect();
+            keys.sort();
+            for key in keys {
+                match (event.get(key), current.get(key)) {
+                    (Some(before), Some(now)) => walk(&child(key), before, now, changes),
+                    (before, now) => changes.push(Change {
+                        path: child(key),
+                        event: before.cloned(),
+                        current: now.cloned(),
+                    }),
+                }
+            }
+        }
+        (Value::Array(event), Value::Array(current)) if event.len() == current.len() => {
+            for (index, (before, now)) in event.iter().zip(current).enumerate() {
+                walk(&child(&index.to_string()), before, now, changes);
+            }
+        }
+        (before, now) if before != now => changes.push(Change {
+            path: path.to_string(),
+            event: Some(before.clone()),
+            current: Some(now.clone()),
+        }),
+        _ => {}
+    }
+}
+
+/// The cross-check of an event body against the `current` object read at `checked_at_ms`
+pub fn compare(referenced: &Referenced, event: &Value, current: &Value, checked_at_ms: i64) -> CrossCheck {
+    let event_object = event.pointer("/data/object").unwrap_or(&Value::Null);
+    let mut changes = diff(event_object, current);
+    let truncated = changes.len() > MAX_CHANGES;
+    changes.truncate(MAX_CHANGES);
+    CrossCheck {
+        object: referenced.object.clone(),
+        id: referenced.id.clone(),
+        checked_at_ms,
+        changes,
+        truncated,
+        error: None,
+    }
+}
+
+/// Cross-check an event body; None when it references no object or no key is configured
+pub async fn cross_check(env: &Env, body: &str, checked_at_ms: i64) -> Option<CrossCheck> {
+    let event: Value = serde_json::from_str(body).ok()?;
+    let referenced = Referenced::from_event(&event)?;
+    let Ok(key) = env.secret("STRIPE_API_KEY").map(|secret| secret.to_string()) else {
+        log_warn!("⚠️  stripe_cross_check is on but STRIPE_API_KEY is not configured");
+        return None;
+    };
+    Some(match fetch(&key, &referenced).await {
+        Ok(current) => compare(&referenced, &event, &current, checked_at_ms),
+        Err(error) => {
+            log_warn!("⚠️  Stripe cross-check of {} failed: {}", referenced.id, error);
+            CrossCheck {
+                object: referenced.object,
+                id: referenced.id,
+                checked_at_ms,
+                changes: Vec::new(),
+                truncated: false,
+                error: Some(error),
+            }
+        }
+    })
+}
+
+async fn fetch(key: &str, referenced: &Referenced) -> std::result::Result<Value, String> {
+    let headers = Headers::new();
+    headers
+        .set("Authorization", &format!("Bearer {}", key))
+        .map_err(|e| e.to_string())?;
+    let mut init = RequestInit::new();
+    init.with_method(Method::Get).with_headers(headers);
+    let url = format!("{}/{}", API, referenced.path());
+    let request = Request::new_with_init(&url, &init).map_err(|e| e.to_string())?;
+
+    let exchange = async {
+        let mut response = Fetch::Request(request).send().await?;
+        let status = response.status_code();
+        Ok::<_, Error>((status, response.json::<Value>().await?))
+    };
+    match select(Box::pin(exchange), Delay::from(API_TIMEOUT)).await {
+        Either::Left((Ok((200, current)), _)) => Ok(current),
+        Either::Left((Ok((status, answer)), _)) => Err(answer
+            .pointer("/error/message")
+            .and_then(Value::as_str)
+            .map(|message| format!("Stripe answered {}: {}", status, message))
+            .unwrap_or_else(|| format!("Stripe answered {}", status))),
+        Either::Left((Err(e), _)) => Err(e.to_string()),
+        Either::Right(_) => Err("timed out".to_string()),
+    }
+}
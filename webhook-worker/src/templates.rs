@@ -0,0 +1,122 @@
+//! Built-in webhook templates
+//! Starting configs for common providers: signature verification (secret read from
+//! a conventionally named worker secret unless the caller passes one), where the
+//! provider puts its event type and dedup key, and a suggested retention.
+
+use crate::config::{FieldSource, SignatureConfig, SignatureProvider, SlackConfig, TwimlConfig, WebhookConfig};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Template {
+    pub id: &'static str,
+    pub description: &'static str,
+    pub config: WebhookConfig,
+}
+
+/// Template IDs, in listing order
+pub const TEMPLATE_IDS: &[&str] = &["stripe", "github", "shopify", "twilio", "slack", "svix", "standard_webhooks"];
+
+fn header(name: &str) -> Option<FieldSource> {
+    Some(FieldSource::Header(name.to_string()))
+}
+
+fn body(path: &str) -> Option<FieldSource> {
+    Some(FieldSource::Body(path.to_string()))
+}
+
+fn signature(provider: SignatureProvider, secret_env: &str) -> Option<SignatureConfig> {
+    Some(SignatureConfig {
+        provider,
+        secret: format!("env:{}", secret_env),
+        tolerance_seconds: 300,
+        enforce: true,
+        previous_secret: None,
+        previous_expires_at_ms: None,
+        hmac: None,
+        paypal: None,
+    })
+}
+
+/// A template by ID
+pub fn find(id: &str) -> Option<Template> {
+    let (description, config) = match id {
+        "stripe" => (
+            "Stripe events: Stripe-Signature verification, `type` event, event `id` dedup",
+            WebhookConfig {
+                signature: signature(SignatureProvider::Stripe, "STRIPE_WEBHOOK_SECRET"),
+                event_type: body("type"),
+                idempotency_key: body("id"),
+                retention_days: Some(30),
+                ..WebhookConfig::default()
+            },
+        ),
+        "github" => (
+            "GitHub webhooks: X-Hub-Signature-256 verification, X-GitHub-Event, X-GitHub-Delivery dedup",
+            WebhookConfig {
+                signature: signature(SignatureProvider::Github, "GITHUB_WEBHOOK_SECRET"),
+                event_type: header("x-github-event"),
+                idempotency_key: header("x-github-delivery"),
+                retention_days: Some(14),
+                ..WebhookConfig::default()
+            },
+        ),
+        "shopify" => (
+            "Shopify webhooks: X-Shopify-Hmac-Sha256 verification, X-Shopify-Topic, X-Shopify-Webhook-Id dedup",
+            WebhookConfig {
+                signature: signature(SignatureProvider::Shopify, "SHOPIFY_WEBHOOK_SECRET"),
+                event_type: header("x-shopify-topic"),
+                idempotency_key: header("x-shopify-webhook-id"),
+                retention_days: Some(30),
+                ..WebhookConfig::default()
+            },
+        ),
+        "twilio" => (
+            "Twilio callbacks: X-Twilio-Signature verification, I-Twilio-Idempotency-Token dedup, empty TwiML answers",
+            WebhookConfig {
+                signature: signature(SignatureProvider::Twilio, "TWILIO_AUTH_TOKEN"),
+                idempotency_key: header("i-twilio-idempotency-token"),
+                twiml: Some(TwimlConfig::default()),
+                retention_days: Some(7),
+                ..WebhookConfig::default()
+            },
+        ),
+        "slack" => (
+            "Slack slash commands and interactivity: X-Slack-Signature verification, empty acknowledgments",
+            WebhookConfig {
+                signature: signature(SignatureProvider::Slack, "SLACK_SIGNING_SECRET"),
+                slack: Some(SlackConfig::default()),
+                retention_days: Some(7),
+                ..WebhookConfig::default()
+            },
+        ),
+        "svix" => (
+            "Svix-style webhooks: svix-signature verification, `type` event, svix-id dedup",
+            WebhookConfig {
+                signature: signature(SignatureProvider::Svix, "SVIX_WEBHOOK_SECRET"),
+                event_type: body("type"),
+                idempotency_key: header("svix-id"),
+                retention_days: Some(30),
+                ..WebhookConfig::default()
+            },
+        ),
+        "standard_webhooks" => (
+            "Standard Webhooks senders: webhook-signature verification, `type` event, webhook-id dedup",
+            WebhookConfig {
+                signature: signature(SignatureProvider::StandardWebhooks, "STANDARD_WEBHOOK_SECRET"),
+                event_type: body("type"),
+                idempotency_key: header("webhook-id"),
+                retention_days: Some(30),
+                ..WebhookConfig::default()
+            },
+        ),
+        _ => return None,
+    };
+
+    let id = TEMPLATE_IDS.iter().find(|known| **known == id)?;
+    Some(Template { id, description, config })
+}
+
+/// Every built-in template
+pub fn all() -> Vec<Template> {
+    TEMPLATE_IDS.iter().filter_map(|id| find(id)).collect()
+}
@@ -0,0 +1,119 @@
+//! Readable timestamps in API responses
+//! Stored times are Unix milliseconds. Every JSON response from `/api/` gets
+//! an RFC 3339 twin next to each timestamp field (`received_at_ms` →
+//! `received_at_iso`, `event_time` → `event_time_iso`), in UTC unless the
+//! request asks for `?tz=`: `UTC`, `Z` or a fixed offset such as `+02:00`,
+//! `-0530` or `+09`. `?ages=true` adds `{field}_age_ms`, how long before the
+//! response the time was. Timestamp fields are keys ending in `_at_ms` or
+//! `_since_ms`, plus `event_time`; durations (`duration_ms`, `store_ms`) are
+//! left alone.
+
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+use serde_json::{Map, Value};
+
+/// Longest accepted offset, matching what RFC 3339 timestamps can carry in practice
+const MAX_OFFSET_SECONDS: i32 = 18 * 3600;
+
+/// How timestamps are rendered for one request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeOptions {
+    pub offset: FixedOffset,
+    pub ages: bool,
+}
+
+impl Default for TimeOptions {
+    fn default() -> Self {
+        Self {
+            offset: FixedOffset::east_opt(0).expect("zero offset"),
+            ages: false,
+        }
+    }
+}
+
+impl TimeOptions {
+    /// Options from `tz` and `ages` query values; Err names an unsupported `tz`
+    pub fn parse(tz: Option<&str>, ages: Option<&str>) -> Result<Self, String> {
+        let offset = match tz {
+            Some(value) => parse_offset(value).ok_or_else(|| {
+                format!("Unsupported tz '{}': use UTC or a fixed offset such as +02:00", value)
+            })?,
+            None => Self::default().offset,
+        };
+        Ok(Self {
+            offset,
+            ages: ages == Some("true"),
+        })
+    }
+}
+
+/// `UTC`, `Z`, `GMT`, `+HH:MM`, `-HHMM` or `+HH`
+pub fn parse_offset(value: &str) -> Option<FixedOffset> {
+    let value = value.trim();
+    if ["utc", "z", "gmt"].iter().any(|name| value.eq_ignore_ascii_case(name)) {
+        return FixedOffset::east_opt(0);
+    }
+    let (sign, digits) = match value.as_bytes().first()? {
+        b'+' => (1, &value[1..]),
+        b'-' => (-1, &value[1..]),
+        _ => return None,
+    };
+    let digits = digits.replace(':', "");
+    if !matches!(digits.len(), 2 | 4) || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = if digits.len() == 4 { digits[2..].parse().ok()? } else { 0 };
+    let seconds = hours * 3600 + minutes * 60;
+    if minutes >= 60 || seconds > MAX_OFFSET_SECONDS {
+        return None;
+    }
+    FixedOffset::east_opt(sign * seconds)
+}
+
+/// RFC 3339 with milliseconds at `offset` (`Z` for UTC)
+pub fn rfc3339(ms: i64, offset: FixedOffset) -> Option<String> {
+    let time = DateTime::from_timestamp_millis(ms)?.with_timezone(&offset);
+    Some(time.to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+/// Base name of a timestamp field (`received_at` for `received_at_ms`), None for other keys
+fn timestamp_base(key: &str) -> Option<&str> {
+    if key == "event_time" {
+        Some(key)
+    } else if key.ends_with("_at_ms") || key.ends_with("_since_ms") {
+        key.strip_suffix("_ms")
+    } else {
+        None
+    }
+}
+
+/// Add `_iso` (and with `ages`, `_age_ms`) siblings to every timestamp field
+pub fn annotate(value: &mut Value, options: &TimeOptions, now_ms: i64) {
+    match value {
+        Value::Object(object) => {
+            let mut added = Map::new();
+            for (key, field) in object.iter_mut() {
+                if let (Some(base), Some(ms)) = (timestamp_base(key), field.as_i64()) {
+                    if let Some(iso) = rfc3339(ms, options.offset) {
+                        added.insert(format!("{}_iso", base), Value::String(iso));
+                    }
+                    if options.ages {
+                        let age = now_ms.checked_sub(ms).map_or(Value::Null, Value::from);
+                        added.insert(format!("{}_age_ms", base), age);
+                    }
+                } else {
+                    annotate(field, options, now_ms);
+                }
+            }
+            for (key, field) in added {
+                object.entry(key).or_insert(field);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                annotate(item, options, now_ms);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,227 @@
+//! Project API tokens
+//! Tokens belong to a project (the owning user's workspace) and carry a role,
+//! optional scopes and an optional expiry. Only a SHA-256 hash of the secret is
+//! stored; the plain token is shown once. Rotation keeps the previous secret
+//! valid for a short grace period so deployments can switch over.
+
+use crate::auth::{Role, Scope};
+use crate::ids;
+use crate::storage::{optional_i64, optional_str};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Prefix that makes leaked tokens easy to recognize and scan for
+const TOKEN_PREFIX: &str = "whk_";
+
+/// `last_used_at_ms` is refreshed at most this often per token
+const TOUCH_INTERVAL_MS: i64 = 60_000;
+
+/// Default and maximum grace period for a rotated-out secret
+pub const DEFAULT_GRACE_SECONDS: i64 = 300;
+pub const MAX_GRACE_SECONDS: i64 = 86_400;
+
+/// Longest lifetime a new token can be issued with (five years)
+pub const MAX_EXPIRES_IN_SECONDS: i64 = 5 * 365 * 86_400;
+
+const TOKEN_COLUMNS: &str =
+    "id, user_id, name, role, scopes, created_at_ms, expires_at_ms, last_used_at_ms, revoked_at_ms, previous_expires_at_ms";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ApiToken {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub role: String,
+    /// Space-separated scopes (OAuth style); None grants every scope the role allows
+    pub scopes: Option<String>,
+    pub created_at_ms: i64,
+    pub expires_at_ms: Option<i64>,
+    pub last_used_at_ms: Option<i64>,
+    pub revoked_at_ms: Option<i64>,
+    /// Until when the secret replaced by the last rotation is still accepted
+    pub previous_expires_at_ms: Option<i64>,
+}
+
+impl ApiToken {
+    pub fn scope_list(&self) -> Option<Vec<Scope>> {
+        self.scopes
+            .as_ref()
+            .map(|scopes| scopes.split_whitespace().filter_map(Scope::parse).collect())
+    }
+}
+
+/// Settings for a new token
+pub struct NewToken<'a> {
+    pub user_id: &'a str,
+    pub name: &'a str,
+    pub role: Role,
+    pub scopes: Option<Vec<Scope>>,
+    pub expires_at_ms: Option<i64>,
+}
+
+/// SHA-256 of a token secret, hex encoded
+pub fn hash(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn now_ms() -> i64 {
+    Date::now().as_millis() as i64
+}
+
+/// Generate a new random token secret
+fn generate_secret() -> String {
+    // Two v4 UUIDs give 244 random bits
+    format!(
+        "{}{}{}",
+        TOKEN_PREFIX,
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+/// Look up a usable token by its secret: not revoked, not expired, and either
+/// the current secret or a rotated-out one still inside its grace period
+pub async fn find_active(db: &D1Database, secret: &str) -> Result<Option<ApiToken>> {
+    let sql = format!(
+        "SELECT {} FROM api_tokens \
+         WHERE (token_hash = ?1 OR (previous_token_hash = ?1 AND previous_expires_at_ms > ?2)) \
+         AND revoked_at_ms IS NULL AND (expires_at_ms IS NULL OR expires_at_ms > ?2)",
+        TOKEN_COLUMNS
+    );
+    db.prepare(&sql)
+        .bind(&[JsValue::from_str(&hash(secret)), JsValue::from_f64(now_ms() as f64)])?
+        .first::<ApiToken>(None)
+        .await
+}
+
+/// Record token usage (throttled). Failures are logged, not surfaced.
+pub async fn touch(db: &D1Database, token: &ApiToken) {
+    let now = now_ms();
+    if token.last_used_at_ms.is_some_and(|used| now - used < TOUCH_INTERVAL_MS) {
+        return;
+    }
+
+    let result = match db
+        .prepare("UPDATE api_tokens SET last_used_at_ms = ?2 WHERE id = ?1")
+        .bind(&[JsValue::from_str(&token.id), JsValue::from_f64(now as f64)])
+    {
+        Ok(statement) => statement.run().await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        log_warn!("⚠️  Failed to record token usage: {:?}", e);
+    }
+}
+
+/// Fetch a token by id
+pub async fn get(db: &D1Database, id: &str) -> Result<Option<ApiToken>> {
+    let sql = format!("SELECT {} FROM api_tokens WHERE id = ?1", TOKEN_COLUMNS);
+    db.prepare(&sql)
+        .bind(&[JsValue::from_str(id)])?
+        .first::<ApiToken>(None)
+        .await
+}
+
+/// List tokens, optionally limited to one project
+pub async fn list(db: &D1Database, user_id: Option<&str>) -> Result<Vec<ApiToken>> {
+    let statement = match user_id {
+        Some(user_id) => db
+            .prepare(format!(
+                "SELECT {} FROM api_tokens WHERE user_id = ?1 ORDER BY created_at_ms DESC",
+                TOKEN_COLUMNS
+            ))
+            .bind(&[JsValue::from_str(user_id)])?,
+        None => db.prepare(format!(
+            "SELECT {} FROM api_tokens ORDER BY created_at_ms DESC",
+            TOKEN_COLUMNS
+        )),
+    };
+    statement.all().await?.results::<ApiToken>()
+}
+
+/// Create a token, returning the record and the plain secret
+pub async fn create(db: &D1Database, new: NewToken<'_>) -> Result<(ApiToken, String)> {
+    let now = now_ms();
+    let secret = generate_secret();
+    let scopes = new.scopes.map(|scopes| {
+        scopes
+            .iter()
+            .map(|scope| scope.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    });
+    let token = ApiToken {
+        id: ids::ulid(now),
+        user_id: new.user_id.to_string(),
+        name: new.name.to_string(),
+        role: new.role.as_str().to_string(),
+        scopes,
+        created_at_ms: now,
+        expires_at_ms: new.expires_at_ms,
+        last_used_at_ms: None,
+        revoked_at_ms: None,
+        previous_expires_at_ms: None,
+    };
+
+    db.prepare(
+        "INSERT INTO api_tokens (id, user_id, name, role, scopes, token_hash, created_at_ms, expires_at_ms) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    )
+    .bind(&[
+        JsValue::from_str(&token.id),
+        JsValue::from_str(&token.user_id),
+        JsValue::from_str(&token.name),
+        JsValue::from_str(&token.role),
+        optional_str(&token.scopes),
+        JsValue::from_str(&hash(&secret)),
+        JsValue::from_f64(now as f64),
+        optional_i64(token.expires_at_ms),
+    ])?
+    .run()
+    .await?;
+
+    Ok((token, secret))
+}
+
+/// When a token issued at `now_ms` for `expires_in_seconds` expires; None unless
+/// the lifetime is positive and at most `MAX_EXPIRES_IN_SECONDS`
+pub fn expires_at_ms(now_ms: i64, expires_in_seconds: i64) -> Option<i64> {
+    if !(1..=MAX_EXPIRES_IN_SECONDS).contains(&expires_in_seconds) {
+        return None;
+    }
+    now_ms.checked_add(expires_in_seconds.checked_mul(1000)?)
+}
+
+/// Issue a new secret for a token; the old one keeps working for `grace_seconds`
+pub async fn rotate(db: &D1Database, id: &str, grace_seconds: i64) -> Result<Option<(ApiToken, String)>> {
+    let secret = generate_secret();
+    let previous_expires_at_ms = now_ms() + grace_seconds * 1000;
+
+    db.prepare(
+        "UPDATE api_tokens SET previous_token_hash = token_hash, previous_expires_at_ms = ?2, token_hash = ?3 \
+         WHERE id = ?1 AND revoked_at_ms IS NULL",
+    )
+    .bind(&[
+        JsValue::from_str(id),
+        JsValue::from_f64(previous_expires_at_ms as f64),
+        JsValue::from_str(&hash(&secret)),
+    ])?
+    .run()
+    .await?;
+
+    Ok(get(db, id).await?.map(|token| (token, secret)))
+}
+
+/// Revoke a token (kept for the audit trail)
+pub async fn revoke(db: &D1Database, id: &str) -> Result<()> {
+    db.prepare("UPDATE api_tokens SET revoked_at_ms = ?2 WHERE id = ?1 AND revoked_at_ms IS NULL")
+        .bind(&[JsValue::from_str(id), JsValue::from_f64(now_ms() as f64)])?
+        .run()
+        .await?;
+    Ok(())
+}
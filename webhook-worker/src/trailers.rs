@@ -0,0 +1,62 @@
+//! Trailing metadata extraction
+//! The Workers runtime never exposes HTTP/2 trailer frames on an inbound request,
+//! so trailers are recovered where the protocol carries them in-band: gRPC-Web
+//! ends the body with a trailer frame (flag 0x80) holding `grpc-status`,
+//! `grpc-message` and custom metadata as an HTTP/1 header block. Only the
+//! base64 `grpc-web-text` variant survives the text body capture. The body is
+//! stored untouched, so replays and forwards reproduce the trailers as sent;
+//! the extracted map is stored separately for display and filtering.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::collections::{BTreeMap, HashMap};
+
+/// gRPC-Web frame flag marking the trailer frame
+const TRAILER_FLAG: u8 = 0x80;
+
+/// Trailers carried by the delivery, as a name → value map with lowercased names
+pub fn extract(headers: &HashMap<String, String>, body: &str) -> Option<BTreeMap<String, String>> {
+    let content_type = headers.get("content-type")?.to_ascii_lowercase();
+    if !content_type.starts_with("application/grpc-web-text") {
+        return None;
+    }
+
+    let trailers = parse_frames(&decode_text(body)?)?;
+    (!trailers.is_empty()).then_some(trailers)
+}
+
+/// grpc-web-text bodies are base64, one padded chunk per frame; every 4-character
+/// group decodes independently, so chunks can be decoded group by group
+fn decode_text(body: &str) -> Option<Vec<u8>> {
+    let text: Vec<u8> = body.bytes().filter(|byte| !byte.is_ascii_whitespace()).collect();
+    if !text.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut decoded = Vec::with_capacity(text.len() / 4 * 3);
+    for group in text.chunks(4) {
+        decoded.extend(BASE64.decode(group).ok()?);
+    }
+    Some(decoded)
+}
+
+/// Walk length-prefixed frames and parse the header block of the trailer frame
+fn parse_frames(mut frames: &[u8]) -> Option<BTreeMap<String, String>> {
+    let mut trailers = BTreeMap::new();
+    while !frames.is_empty() {
+        let flag = *frames.first()?;
+        let length = u32::from_be_bytes(frames.get(1..5)?.try_into().ok()?) as usize;
+        let payload = frames.get(5..5usize.checked_add(length)?)?;
+        if flag & TRAILER_FLAG != 0 {
+            for line in String::from_utf8_lossy(payload).split("\r\n") {
+                if let Some((name, value)) = line.split_once(':') {
+                    let name = name.trim().to_ascii_lowercase();
+                    if !name.is_empty() {
+                        trailers.insert(name, value.trim().to_string());
+                    }
+                }
+            }
+        }
+        frames = &frames[5 + length..];
+    }
+    Some(trailers)
+}
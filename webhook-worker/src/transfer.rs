@@ -0,0 +1,178 @@
+//! Cross-instance webhook transfer
+//! Moves a webhook between deployments (another Cloudflare account, staging to
+//! production) with its history. The export is a sequence of pages: the first
+//! carries the webhook (UUID, name, tags and its config document), and with
+//! `data` every page carries up to `limit` captures, oldest first, as
+//! snapshots (see `snapshot.rs`) with their downstream responses. Pages are
+//! imported in order on the target; captures keep their IDs, so re-importing a
+//! page after a failure skips what already landed. Shares, tokens, legal holds
+//! and relay tokens are deployment specific and not transferred.
+
+use crate::config;
+use crate::config_document::ConfigDocument;
+use crate::responses;
+use crate::snapshot::{self, Snapshot, Source};
+use crate::storage::{self, Consistency, RequestQuery, SortColumn};
+use crate::webhooks::Webhook;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+/// `format` of every transfer page
+pub const FORMAT: &str = "webhook-transfer/v1";
+
+/// Captures per page unless `limit` is given
+pub const DEFAULT_PAGE: u32 = 200;
+pub const MAX_PAGE: u32 = 1000;
+
+/// The webhook itself, on the first page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookSpec {
+    pub uuid: String,
+    pub name: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub document: ConfigDocument,
+}
+
+/// One page of an export
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page {
+    pub format: String,
+    /// Present on the first page only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<WebhookSpec>,
+    #[serde(default)]
+    pub captures: Vec<Snapshot>,
+    /// Pass as `cursor` for the next page; None on the last one
+    pub next_cursor: Option<u32>,
+}
+
+/// What importing a page did
+#[derive(Debug, Default, Serialize)]
+pub struct ImportOutcome {
+    pub imported: usize,
+    /// Captures already present from an earlier import of the page
+    pub skipped: usize,
+    pub responses: usize,
+}
+
+/// Cursor after a page of `returned` captures read at `cursor`
+pub fn next_cursor(cursor: u32, limit: u32, returned: usize) -> Option<u32> {
+    (returned as u32 >= limit).then(|| cursor + returned as u32)
+}
+
+/// First problem that stops a page from importing
+pub fn validate(page: &Page) -> Option<String> {
+    if page.format != FORMAT {
+        return Some(format!("unsupported transfer format {:?}, expected {:?}", page.format, FORMAT));
+    }
+    if let Some(problem) = page.webhook.as_ref().and_then(|webhook| webhook.document.validate()) {
+        return Some(problem);
+    }
+    page.captures
+        .iter()
+        .find(|capture| capture.format != snapshot::FORMAT)
+        .map(|capture| format!("capture {} has unsupported format {:?}", capture.request.id, capture.format))
+}
+
+/// What an export page holds
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    /// Include captures, not just the webhook
+    pub data: bool,
+    /// Keep literal signing secrets instead of masking them, so the target can verify right away
+    pub secrets: bool,
+    /// 0 for the first page
+    pub cursor: u32,
+    pub limit: u32,
+    /// Host of this instance, recorded in every snapshot
+    pub host: Option<String>,
+}
+
+/// Read one export page
+pub async fn export_page(env: &Env, db: &D1Database, webhook: &Webhook, options: &ExportOptions) -> Result<Page> {
+    let ExportOptions {
+        data,
+        secrets,
+        cursor,
+        limit,
+        ..
+    } = *options;
+    let spec = if cursor == 0 {
+        let mut document = ConfigDocument::export(db, &webhook.id).await?;
+        if secrets {
+            document.config = config::load_from_d1(db, &webhook.id).await?.config;
+        }
+        Some(WebhookSpec {
+            uuid: webhook.uuid.clone(),
+            name: webhook.name.clone(),
+            tags: webhook.tag_list(),
+            document,
+        })
+    } else {
+        None
+    };
+    if !data {
+        return Ok(Page {
+            format: FORMAT.to_string(),
+            webhook: spec,
+            captures: Vec::new(),
+            next_cursor: None,
+        });
+    }
+
+    let storage = storage::for_webhook(env, &webhook.id, Consistency::Primary).await?;
+    let rows = storage
+        .list_requests(&RequestQuery {
+            webhook_id: webhook.id.clone(),
+            limit,
+            offset: cursor,
+            since: None,
+            until: None,
+            sort: SortColumn::ReceivedAt,
+            ascending: true,
+            filters: Vec::new(),
+        })
+        .await?;
+    let next_cursor = next_cursor(cursor, limit, rows.len());
+    let exported_at_ms = Date::now().as_millis() as i64;
+    let mut captures = Vec::with_capacity(rows.len());
+    for row in rows {
+        let responses = responses::for_capture(db, &webhook.id, &row.id).await?;
+        let source = Source {
+            webhook_uuid: webhook.uuid.clone(),
+            capture_id: row.id.clone(),
+            host: options.host.clone(),
+        };
+        captures.push(snapshot::build(source, row, responses, exported_at_ms));
+    }
+
+    Ok(Page {
+        format: FORMAT.to_string(),
+        webhook: spec,
+        captures,
+        next_cursor,
+    })
+}
+
+/// Store a validated page's captures under `webhook_id`, keeping their IDs
+pub async fn import_captures(env: &Env, db: &D1Database, webhook_id: &str, page: &Page) -> Result<ImportOutcome> {
+    let mut outcome = ImportOutcome::default();
+    if page.captures.is_empty() {
+        return Ok(outcome);
+    }
+    let storage = storage::for_webhook(env, webhook_id, Consistency::Primary).await?;
+    for capture in &page.captures {
+        let record = snapshot::into_record(capture, webhook_id, &capture.request.id).map_err(Error::RustError)?;
+        if !storage.insert_capture(&record).await? {
+            outcome.skipped += 1;
+            continue;
+        }
+        for response in &capture.responses {
+            responses::restore(db, webhook_id, &record.id, response).await?;
+        }
+        outcome.imported += 1;
+        outcome.responses += capture.responses.len();
+    }
+    Ok(outcome)
+}
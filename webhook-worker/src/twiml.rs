@@ -0,0 +1,81 @@
+//! TwiML auto-responses
+//! With `twiml` in the webhook config, Twilio's voice and messaging webhooks
+//! are answered with TwiML instead of the capture summary, so a real Twilio
+//! number can point at a capture URL: calls hear the configured `<Say>` (or
+//! end), texts get the configured reply (or none), and every request is still
+//! stored for inspection. Templates are filled with the request's form
+//! parameters, escaped for XML. Script and route responses take precedence.
+
+use crate::config::{CustomResponse, TwimlConfig};
+use crate::placeholders;
+use std::collections::{BTreeMap, HashMap};
+
+/// Content type Twilio expects TwiML in
+pub const CONTENT_TYPE: &str = "text/xml";
+
+/// A TwiML document that does nothing
+pub const EMPTY: &str = r#"<?xml version="1.0" encoding="UTF-8"?><Response/>"#;
+
+/// What Twilio is asking TwiML for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Voice,
+    Messaging,
+}
+
+/// Form parameters of a Twilio webhook
+pub fn params(content_type: Option<&str>, body: &str) -> Option<HashMap<String, String>> {
+    if !content_type.is_some_and(|content_type| content_type.starts_with("application/x-www-form-urlencoded")) {
+        return None;
+    }
+    Some(form_urlencoded::parse(body.as_bytes()).into_owned().collect())
+}
+
+/// Voice for calls, messaging for messages; None for other Twilio callbacks
+/// (status callbacks ignore what they are answered with)
+pub fn kind(params: &HashMap<String, String>) -> Option<Kind> {
+    if params.contains_key("MessageStatus") {
+        None
+    } else if params.contains_key("CallSid") {
+        Some(Kind::Voice)
+    } else if params.contains_key("MessageSid") || params.contains_key("SmsSid") {
+        Some(Kind::Messaging)
+    } else {
+        None
+    }
+}
+
+/// `template` with each `{{Param}}` replaced by the escaped parameter (empty when absent)
+pub fn render(template: &str, params: &HashMap<String, String>) -> String {
+    placeholders::fill(template, params, escape)
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The TwiML answer to a delivery, if it is a Twilio voice or messaging webhook
+pub fn respond(config: &TwimlConfig, content_type: Option<&str>, body: &str) -> Option<CustomResponse> {
+    let params = params(content_type, body)?;
+    let template = match kind(&params)? {
+        Kind::Voice => config.voice.as_deref(),
+        Kind::Messaging => config.messaging.as_deref(),
+    };
+    Some(CustomResponse {
+        status: 200,
+        body: template.map(|template| render(template, &params)).unwrap_or_else(|| EMPTY.to_string()),
+        content_type: Some(CONTENT_TYPE.to_string()),
+        headers: BTreeMap::new(),
+    })
+}
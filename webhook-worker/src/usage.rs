@@ -0,0 +1,222 @@
+//! Storage usage accounting
+//! Each webhook's stored bytes are counted in the `Counter` Durable Object of
+//! scope `storage:{webhook_id}` (see `counters.rs`): captures and their
+//! `size_bytes`, and R2 objects (uploads, email attachments) and their sizes.
+//! Ingestion adds to them as deliveries are stored; cleanup (retention, purges,
+//! erasure, bulk deletes) happens in too many places to subtract exactly, so
+//! the daily maintenance recounts every webhook from the capture stores and the
+//! buckets once retention has run, and overwrites the counters with the result.
+//!
+//! A webhook with a `storage_quota` reserves a delivery's bytes before storing
+//! it and is refused with 507 when that would take it past `max_bytes`; usage
+//! in between recounts errs high, since deletions only show at the next one.
+//! The counters are flushed to D1, where project totals are read.
+
+use crate::config::StorageQuota;
+use crate::durable::counter;
+use crate::residency::{self, Jurisdiction};
+use crate::storage;
+use crate::webhooks;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use wasm_bindgen::JsValue;
+use worker::*;
+
+/// Bytes of captures plus objects, the counter quotas compare with
+const BYTES: &str = "bytes";
+const CAPTURES: &str = "captures";
+const CAPTURE_BYTES: &str = "capture_bytes";
+const OBJECTS: &str = "objects";
+const OBJECT_BYTES: &str = "object_bytes";
+
+/// Buckets and key prefixes of the objects stored per webhook (`{prefix}{webhook_id}/...`)
+const OBJECT_BUCKETS: [(&str, &str); 2] = [("UPLOADS", "uploads/"), ("EMAIL_ATTACHMENTS", "email/")];
+
+/// Counter scope of a webhook's usage
+pub fn scope(webhook_id: &str) -> String {
+    format!("storage:{}", webhook_id)
+}
+
+/// What a webhook stores
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Usage {
+    pub captures: u64,
+    pub capture_bytes: u64,
+    pub objects: u64,
+    pub object_bytes: u64,
+}
+
+impl Usage {
+    pub fn bytes(&self) -> u64 {
+        self.capture_bytes + self.object_bytes
+    }
+
+    /// Usage from a scope's counter values
+    pub fn from_values(values: &BTreeMap<String, i64>) -> Self {
+        let value = |key: &str| values.get(key).copied().unwrap_or(0).max(0) as u64;
+        Self {
+            captures: value(CAPTURES),
+            capture_bytes: value(CAPTURE_BYTES),
+            objects: value(OBJECTS),
+            object_bytes: value(OBJECT_BYTES),
+        }
+    }
+
+    /// Counter values recording this usage
+    pub fn values(&self) -> BTreeMap<String, i64> {
+        [
+            (BYTES, self.bytes()),
+            (CAPTURES, self.captures),
+            (CAPTURE_BYTES, self.capture_bytes),
+            (OBJECTS, self.objects),
+            (OBJECT_BYTES, self.object_bytes),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value as i64))
+        .collect()
+    }
+}
+
+/// Counter changes for one stored capture
+pub fn capture_changes(size_bytes: i64) -> Vec<(&'static str, i64)> {
+    let size_bytes = size_bytes.max(0);
+    vec![(BYTES, size_bytes), (CAPTURES, 1), (CAPTURE_BYTES, size_bytes)]
+}
+
+/// Counter changes for one stored R2 object
+pub fn object_changes(size_bytes: u64) -> Vec<(&'static str, i64)> {
+    let size_bytes = size_bytes as i64;
+    vec![(BYTES, size_bytes), (OBJECTS, 1), (OBJECT_BYTES, size_bytes)]
+}
+
+/// The same changes, undone
+pub fn undo(changes: &[(&'static str, i64)]) -> Vec<(&'static str, i64)> {
+    changes.iter().map(|(key, by)| (*key, -by)).collect()
+}
+
+/// Whether counter values after a reservation exceed the quota
+pub fn exceeds(quota: &StorageQuota, values: &BTreeMap<String, i64>) -> bool {
+    values.get(BYTES).is_some_and(|bytes| *bytes > quota.max_bytes as i64)
+}
+
+/// The webhook that owns an object key of `OBJECT_BUCKETS`
+pub fn object_owner<'a>(key: &'a str, prefix: &str) -> Option<&'a str> {
+    let (webhook_id, _) = key.strip_prefix(prefix)?.split_once('/')?;
+    Some(webhook_id).filter(|webhook_id| !webhook_id.is_empty())
+}
+
+/// A webhook's current usage
+pub async fn current(env: &Env, webhook_id: &str) -> Result<Usage> {
+    let counters = counter::snapshot(env, &scope(webhook_id)).await?;
+    let values = counters.into_iter().map(|(key, entry)| (key, entry.value)).collect();
+    Ok(Usage::from_values(&values))
+}
+
+/// Count a delivery against a webhook's quota before it is stored; false (and
+/// nothing counted) when it does not fit. The counters failing lets it through.
+pub async fn reserve(env: &Env, webhook_id: &str, changes: &[(&'static str, i64)], quota: &StorageQuota) -> bool {
+    let scope = scope(webhook_id);
+    let values = match counter::add(env, &scope, changes).await {
+        Ok(values) => values,
+        Err(e) => {
+            log_warn!("⚠️  Failed to check the storage quota of webhook {}: {:?}", webhook_id, e);
+            return true;
+        }
+    };
+    if !exceeds(quota, &values) {
+        return true;
+    }
+    if let Err(e) = counter::add(env, &scope, &undo(changes)).await {
+        log_warn!("⚠️  Failed to release refused storage of webhook {}: {:?}", webhook_id, e);
+    }
+    false
+}
+
+/// Add stored (or, negated, released) bytes to a webhook's usage
+pub async fn add(env: Env, webhook_id: String, changes: Vec<(&'static str, i64)>) {
+    if let Err(e) = counter::add(&env, &scope(&webhook_id), &changes).await {
+        log_warn!("⚠️  Failed to count storage of webhook {}: {:?}", webhook_id, e);
+    }
+}
+
+#[derive(Deserialize)]
+struct CounterRow {
+    scope: String,
+    key: String,
+    value: f64,
+}
+
+/// Usage of several webhooks as last flushed to D1 (at most seconds behind)
+pub async fn flushed(db: &D1Database, webhook_ids: &[String]) -> Result<BTreeMap<String, Usage>> {
+    let scopes: Vec<String> = webhook_ids.iter().map(|webhook_id| scope(webhook_id)).collect();
+    let rows = db
+        .prepare("SELECT scope, key, value FROM counters WHERE scope IN (SELECT value FROM json_each(?1))")
+        .bind(&[JsValue::from_str(&serde_json::to_string(&scopes)?)])?
+        .all()
+        .await?
+        .results::<CounterRow>()?;
+    let mut values: BTreeMap<String, BTreeMap<String, i64>> = BTreeMap::new();
+    for row in rows {
+        if let Some(webhook_id) = row.scope.strip_prefix("storage:") {
+            values.entry(webhook_id.to_string()).or_default().insert(row.key, row.value as i64);
+        }
+    }
+    Ok(webhook_ids
+        .iter()
+        .map(|webhook_id| {
+            let usage = values.get(webhook_id).map(Usage::from_values).unwrap_or_default();
+            (webhook_id.clone(), usage)
+        })
+        .collect())
+}
+
+/// Count every webhook's R2 objects, in each bucket that is wired up
+async fn count_objects(env: &Env, usage: &mut BTreeMap<String, Usage>) -> Result<()> {
+    for jurisdiction in [None, Some(Jurisdiction::Eu), Some(Jurisdiction::Us)] {
+        for (base, prefix) in OBJECT_BUCKETS {
+            let Ok(Some(bucket)) = residency::bucket(env, base, jurisdiction) else {
+                continue;
+            };
+            let mut cursor = None;
+            loop {
+                let mut list = bucket.list().prefix(prefix);
+                if let Some(cursor) = cursor.take() {
+                    list = list.cursor(cursor);
+                }
+                let listed = list.execute().await?;
+                for object in listed.objects() {
+                    let key = object.key();
+                    if let Some(entry) = object_owner(&key, prefix).and_then(|owner| usage.get_mut(owner)) {
+                        entry.objects += 1;
+                        entry.object_bytes += object.size();
+                    }
+                }
+                match listed.cursor().filter(|_| listed.truncated()) {
+                    Some(next) => cursor = Some(next),
+                    None => break,
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Recount every webhook's usage and overwrite its counters; returns how many were recounted
+pub async fn reconcile(env: &Env) -> Result<usize> {
+    let db = env.d1("DB")?;
+    let webhooks = webhooks::in_project(&db, None).await?;
+    let mut usage: BTreeMap<String, Usage> =
+        webhooks.iter().map(|webhook| (webhook.id.clone(), Usage::default())).collect();
+    for volume in storage::all_webhook_volumes(env, 0).await? {
+        if let Some(entry) = usage.get_mut(&volume.webhook_id) {
+            entry.captures = volume.count;
+            entry.capture_bytes = volume.bytes;
+        }
+    }
+    count_objects(env, &mut usage).await?;
+
+    for (webhook_id, usage) in &usage {
+        counter::set(env, &scope(webhook_id), &usage.values()).await?;
+    }
+    Ok(usage.len())
+}
@@ -0,0 +1,105 @@
+//! API versioning
+//! Management and operator routes are versioned: `/api/v1/webhooks/...` asks
+//! for version 1 explicitly, as does an `API-Version: 1` header on an
+//! unversioned path. Versioned requests are routed on their unversioned path,
+//! so the version prefix only selects behaviour and every route exists in
+//! each version that serves it. Unversioned requests without the header are
+//! the legacy API: they are served as version 1 for existing dashboard and
+//! CLI clients, with `Deprecation`, `Sunset` and a `successor-version` link to
+//! the versioned path on the response. Every API response names the version
+//! it was served as in `API-Version`.
+
+/// Header naming the requested or served version
+pub const HEADER: &str = webhook_types::API_VERSION_HEADER;
+
+/// Versions this worker serves, oldest first
+pub const SUPPORTED: &[u32] = &[1];
+
+/// Version the legacy unversioned routes are served as
+pub const LEGACY_VERSION: u32 = 1;
+
+/// When unversioned routes were deprecated, Unix seconds (2026-10-14)
+pub const LEGACY_DEPRECATED_AT: i64 = 1_791_936_000;
+
+/// When unversioned routes stop being served, as an HTTP date
+pub const LEGACY_SUNSET: &str = "Wed, 14 Apr 2027 00:00:00 GMT";
+
+/// Response headers browsers may read cross-origin
+pub const EXPOSED_HEADERS: &str = "API-Version, Deprecation, Sunset, Link";
+
+/// How an API request is served
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Negotiated {
+    pub version: u32,
+    /// Path the request is routed on, without the version prefix
+    pub path: String,
+    /// Unversioned request without a version header
+    pub legacy: bool,
+}
+
+fn supported(version: &str) -> Result<u32, String> {
+    match version.trim().parse::<u32>() {
+        Ok(version) if SUPPORTED.contains(&version) => Ok(version),
+        _ => Err(format!(
+            "Unsupported API version: {} (supported: {})",
+            version.trim(),
+            SUPPORTED.iter().map(|version| version.to_string()).collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
+/// `v1` and the path after it from `/api/v1/...`
+fn split_version(path: &str) -> Option<(&str, &str)> {
+    let rest = path.strip_prefix("/api/v")?;
+    let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    let (version, tail) = rest.split_at(digits);
+    (!version.is_empty() && (tail.is_empty() || tail.starts_with('/'))).then_some((version, tail))
+}
+
+/// Version and routing path of an `/api/` request, given its `API-Version` header
+pub fn negotiate(path: &str, header: Option<&str>) -> Result<Negotiated, String> {
+    if let Some((version, tail)) = split_version(path) {
+        let version = supported(version)?;
+        if let Some(requested) = header {
+            if supported(requested)? != version {
+                return Err(format!("{} header disagrees with the /api/v{} path", HEADER, version));
+            }
+        }
+        return Ok(Negotiated {
+            version,
+            path: format!("/api{}", tail),
+            legacy: false,
+        });
+    }
+    match header {
+        Some(requested) => Ok(Negotiated {
+            version: supported(requested)?,
+            path: path.to_string(),
+            legacy: false,
+        }),
+        None => Ok(Negotiated {
+            version: LEGACY_VERSION,
+            path: path.to_string(),
+            legacy: true,
+        }),
+    }
+}
+
+/// The versioned path a legacy `/api/...` path moves to
+pub fn successor(path: &str) -> String {
+    match path.strip_prefix("/api") {
+        Some(rest) => format!("/api/v{}{}", LEGACY_VERSION, rest),
+        None => path.to_string(),
+    }
+}
+
+/// Headers announcing a response's version, and for legacy requests their deprecation
+pub fn response_headers(negotiated: &Negotiated) -> Vec<(&'static str, String)> {
+    let mut headers = vec![(HEADER, negotiated.version.to_string())];
+    if negotiated.legacy {
+        headers.push(("Deprecation", format!("@{}", LEGACY_DEPRECATED_AT)));
+        headers.push(("Sunset", LEGACY_SUNSET.to_string()));
+        headers.push(("Link", format!("<{}>; rel=\"successor-version\"", successor(&negotiated.path))));
+    }
+    headers
+}
@@ -0,0 +1,81 @@
+//! WebCrypto access
+//! worker-rs exposes no SubtleCrypto bindings, so the runtime's `crypto` global
+//! is called through `Reflect`. Only available inside the Workers runtime.
+
+use js_sys::{Array, Function, Object, Promise, Reflect, Uint8Array};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use worker::*;
+
+/// AES-GCM nonce length
+pub const IV_LEN: usize = 12;
+
+fn crypto() -> Result<JsValue> {
+    Ok(Reflect::get(&js_sys::global(), &JsValue::from_str("crypto"))?)
+}
+
+/// `crypto.subtle`
+pub fn subtle() -> Result<JsValue> {
+    Ok(Reflect::get(&crypto()?, &JsValue::from_str("subtle"))?)
+}
+
+/// Call a promise-returning method on a JS object and await it
+pub async fn call_async(target: &JsValue, method: &str, args: &[JsValue]) -> Result<JsValue> {
+    let function: Function = Reflect::get(target, &JsValue::from_str(method))?
+        .dyn_into()
+        .map_err(|_| Error::RustError(format!("{} is not a function", method)))?;
+    let args: Array = args.iter().collect();
+    let promise: Promise = function
+        .apply(target, &args)?
+        .dyn_into()
+        .map_err(|_| Error::RustError(format!("{} did not return a promise", method)))?;
+    Ok(JsFuture::from(promise).await?)
+}
+
+/// `len` bytes from `crypto.getRandomValues`
+pub fn random_bytes(len: usize) -> Result<Vec<u8>> {
+    let crypto = crypto()?;
+    let function: Function = Reflect::get(&crypto, &JsValue::from_str("getRandomValues"))?
+        .dyn_into()
+        .map_err(|_| Error::RustError("getRandomValues is not a function".to_string()))?;
+    let buffer = Uint8Array::new_with_length(len as u32);
+    function.call1(&crypto, &buffer)?;
+    Ok(buffer.to_vec())
+}
+
+async fn aes_gcm(method: &str, key: &[u8], iv: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let subtle = subtle()?;
+    let algorithm = Object::new();
+    Reflect::set(&algorithm, &JsValue::from_str("name"), &JsValue::from_str("AES-GCM"))?;
+    let key = call_async(
+        &subtle,
+        "importKey",
+        &[
+            JsValue::from_str("raw"),
+            Uint8Array::from(key).into(),
+            algorithm.clone().into(),
+            JsValue::FALSE,
+            Array::of1(&JsValue::from_str(method)).into(),
+        ],
+    )
+    .await?;
+    Reflect::set(&algorithm, &JsValue::from_str("iv"), &Uint8Array::from(iv))?;
+    let output = call_async(&subtle, method, &[algorithm.into(), key, Uint8Array::from(data).into()]).await?;
+    Ok(Uint8Array::new(&output).to_vec())
+}
+
+/// AES-256-GCM encryption under a fresh random nonce; returns nonce followed by ciphertext and tag
+pub async fn seal(key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let iv = random_bytes(IV_LEN)?;
+    let ciphertext = aes_gcm("encrypt", key, &iv, plaintext).await?;
+    Ok([iv, ciphertext].concat())
+}
+
+/// Reverse of `seal`; Err when the key is wrong or the data was tampered with
+pub async fn open(key: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < IV_LEN {
+        return Err(Error::RustError("Sealed value is too short".to_string()));
+    }
+    let (iv, ciphertext) = sealed.split_at(IV_LEN);
+    aes_gcm("decrypt", key, iv, ciphertext).await
+}
@@ -0,0 +1,96 @@
+//! Webhook definitions
+//! The admin worker owns the `webhooks` table; the API writes rows here only for
+//! infrastructure-as-code upserts (`PUT /api/webhooks/{uuid}`), using the same
+//! column conventions (random UUID ids, `created_at` in Unix seconds, JSON tags).
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+use worker::*;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Webhook {
+    pub id: String,
+    pub user_id: String,
+    pub uuid: String,
+    pub name: String,
+    /// JSON array of tags
+    pub tags: Option<String>,
+    pub created_at: i64,
+}
+
+impl Webhook {
+    pub fn tag_list(&self) -> Vec<String> {
+        self.tags
+            .as_deref()
+            .and_then(|tags| serde_json::from_str(tags).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// A webhook by its capture UUID (environment UUIDs do not match)
+pub async fn find_by_uuid(db: &D1Database, uuid: &str) -> Result<Option<Webhook>> {
+    db.prepare("SELECT id, user_id, uuid, name, tags, created_at FROM webhooks WHERE uuid = ?1")
+        .bind(&[JsValue::from_str(uuid)])?
+        .first::<Webhook>(None)
+        .await
+}
+
+/// Webhooks in a project: owned by `user_id` or shared with them (accepted shares).
+/// None lists every webhook, for global callers.
+pub async fn in_project(db: &D1Database, user_id: Option<&str>) -> Result<Vec<Webhook>> {
+    let statement = match user_id {
+        Some(user_id) => db
+            .prepare(
+                "SELECT id, user_id, uuid, name, tags, created_at FROM webhooks WHERE user_id = ?1 \
+                 OR id IN (SELECT webhook_id FROM webhook_shares WHERE shared_with_user_id = ?1 \
+                 AND accepted_at IS NOT NULL) ORDER BY created_at",
+            )
+            .bind(&[JsValue::from_str(user_id)])?,
+        None => db.prepare("SELECT id, user_id, uuid, name, tags, created_at FROM webhooks ORDER BY created_at"),
+    };
+    statement.all().await?.results::<Webhook>()
+}
+
+fn tags_json(tags: &[String]) -> Result<JsValue> {
+    Ok(if tags.is_empty() {
+        JsValue::NULL
+    } else {
+        JsValue::from_str(&serde_json::to_string(tags)?)
+    })
+}
+
+/// Create a webhook with a caller-chosen UUID; None if the UUID was taken meanwhile
+pub async fn create(db: &D1Database, user_id: &str, uuid: &str, name: &str, tags: &[String]) -> Result<Option<Webhook>> {
+    let changes = db
+        .prepare(
+            "INSERT INTO webhooks (id, user_id, uuid, name, tags, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6) \
+             ON CONFLICT(uuid) DO NOTHING",
+        )
+        .bind(&[
+            JsValue::from_str(&uuid::Uuid::new_v4().to_string()),
+            JsValue::from_str(user_id),
+            JsValue::from_str(uuid),
+            JsValue::from_str(name),
+            tags_json(tags)?,
+            JsValue::from_f64((Date::now().as_millis() / 1000) as f64),
+        ])?
+        .run()
+        .await?
+        .meta()?
+        .and_then(|meta| meta.changes)
+        .unwrap_or(0);
+
+    if changes == 0 {
+        return Ok(None);
+    }
+    find_by_uuid(db, uuid).await
+}
+
+/// Rename a webhook and replace its tags
+pub async fn update(db: &D1Database, id: &str, name: &str, tags: &[String]) -> Result<()> {
+    db.prepare("UPDATE webhooks SET name = ?2, tags = ?3 WHERE id = ?1")
+        .bind(&[JsValue::from_str(id), JsValue::from_str(name), tags_json(tags)?])?
+        .run()
+        .await?;
+    Ok(())
+}
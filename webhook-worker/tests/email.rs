@@ -0,0 +1,78 @@
+//! MIME parsing for email-in capture
+
+use webhook_ingestion::mime;
+
+const MESSAGE: &str = "From: Alice <alice@example.com>\r\n\
+To: 3f9a1c2e@hooks.example.com\r\n\
+Subject: =?UTF-8?B?SW52b2ljZSDinJM=?= =?UTF-8?Q?_for_May?=\r\n\
+Received: from a\r\n\
+Received: from b\r\n\
+Content-Type: multipart/mixed;\r\n\
+\tboundary=\"outer\"\r\n\
+\r\n\
+preamble\r\n\
+--outer\r\n\
+Content-Type: multipart/alternative; boundary=inner\r\n\
+\r\n\
+--inner\r\n\
+Content-Type: text/plain; charset=utf-8\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\
+\r\n\
+Total: 10 =E2=82=AC, due =\r\n\
+soon\r\n\
+--inner\r\n\
+Content-Type: text/html; charset=utf-8\r\n\
+\r\n\
+<p>Total: 10</p>\r\n\
+--inner--\r\n\
+--outer\r\n\
+Content-Type: application/pdf; name=\"invoice.pdf\"\r\n\
+Content-Disposition: attachment; filename=\"invoice.pdf\"\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+JVBERi0x\r\n\
+LjQK\r\n\
+--outer--\r\n\
+epilogue\r\n";
+
+#[test]
+fn parses_nested_multipart() {
+    let message = mime::parse(MESSAGE.as_bytes());
+
+    assert_eq!(message.header("subject"), Some("Invoice ✓ for May"));
+    assert_eq!(message.headers.iter().filter(|(name, _)| name == "received").count(), 2);
+    assert_eq!(message.body_text("text/plain").as_deref(), Some("Total: 10 €, due soon"));
+    assert_eq!(message.body_text("text/html").as_deref(), Some("<p>Total: 10</p>"));
+
+    let attachments: Vec<_> = message.attachments().collect();
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(attachments[0].filename.as_deref(), Some("invoice.pdf"));
+    assert_eq!(attachments[0].content_type, "application/pdf");
+    assert_eq!(attachments[0].body, b"%PDF-1.4\n");
+}
+
+#[test]
+fn keeps_single_part_and_undecodable_words() {
+    let message = mime::parse(b"Subject: =?x-unknown?Z?abc?= hi\n\nplain body\n");
+
+    assert_eq!(message.header("subject"), Some("=?x-unknown?Z?abc?= hi"));
+    assert_eq!(message.body_text("text/plain").as_deref(), Some("plain body\n"));
+    assert_eq!(message.attachments().count(), 0);
+}
+
+#[test]
+fn decodes_declared_charsets() {
+    let raw = b"Subject: =?ISO-8859-1?Q?Caf=E9?=\nContent-Type: text/plain; charset=iso-8859-1\n\nd\xe9j\xe0 vu\n";
+    let message = mime::parse(raw);
+
+    assert_eq!(message.header("subject"), Some("Caf\u{e9}"));
+    assert_eq!(message.body_text("text/plain").as_deref(), Some("d\u{e9}j\u{e0} vu\n"));
+}
+
+#[test]
+fn resolves_mailbox_uuid() {
+    assert_eq!(mime::mailbox_uuid("3F9A1C2E@hooks.example.com").as_deref(), Some("3f9a1c2e"));
+    assert_eq!(mime::mailbox_uuid("<billing+3f9a1c2e@hooks.example.com>").as_deref(), Some("3f9a1c2e"));
+    assert_eq!(mime::mailbox_uuid("no-domain"), None);
+    assert_eq!(mime::mailbox_uuid("@hooks.example.com"), None);
+}
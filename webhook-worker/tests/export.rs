@@ -0,0 +1,56 @@
+//! CSV export rows
+
+use webhook_ingestion::export::{self, Column};
+use webhook_ingestion::local::StoredRequest;
+use webhook_ingestion::pipeline::{self, CaptureMeta, IncomingRequest};
+use worker::Url;
+
+fn stored(headers: &[(&str, &str)], body: &str) -> StoredRequest {
+    let incoming = IncomingRequest {
+        method: "POST".to_string(),
+        url: Url::parse("https://hooks.example.com/w/0b9c9a4e-5c55-4d3a-8f5d-3f6b1b2c7d8e").unwrap(),
+        headers: headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+        body: Some(body.to_string()),
+        received_at_ms: 1_760_000_000_123,
+    };
+    let record = pipeline::into_record(
+        pipeline::parse(&incoming).unwrap(),
+        CaptureMeta {
+            id: "req_1".to_string(),
+            webhook_id: "wh_1".to_string(),
+            sequence: Some(7),
+            verification: None,
+            environment: None,
+        },
+    );
+    StoredRequest::from(&record)
+}
+
+#[test]
+fn renders_selected_columns() {
+    let columns = export::parse_columns("time,method,size_bytes,sequence,provider,event_type,body:data.id").unwrap();
+    let request = stored(&[("x-github-event", "push")], r#"{"data":{"id":"evt_1"}}"#);
+
+    assert_eq!(export::header_row(&columns), "time,method,size_bytes,sequence,provider,event_type,body:data.id\r\n");
+    assert_eq!(
+        export::row(&columns, &request, Some("github")),
+        "2025-10-09T08:53:20.123Z,POST,23,7,github,push,evt_1\r\n"
+    );
+    assert_eq!(export::parse_columns("time,status"), Err("status".to_string()));
+    assert!(export::parse_columns(export::DEFAULT_COLUMNS).is_ok());
+}
+
+#[test]
+fn quotes_and_defuses_cells() {
+    let columns = export::parse_columns("header:x-note, body:name").unwrap();
+    assert!(matches!(&columns[0], Column::Field(title, _) if title == "header:x-note"));
+    let request = stored(&[("x-note", "says \"hi\", twice")], r#"{"name":"=HYPERLINK(\"x\")"}"#);
+
+    assert_eq!(
+        export::row(&columns, &request, None),
+        "\"says \"\"hi\"\", twice\",\"'=HYPERLINK(\"\"x\"\")\"\r\n"
+    );
+}
@@ -0,0 +1,1679 @@
+//! Ingestion building blocks against the in-memory bindings (`local` feature)
+
+use futures_executor::block_on;
+use std::collections::{BTreeMap, HashMap};
+use webhook_ingestion::local::*;
+use webhook_ingestion::annotations::{self, NewAnnotation};
+use webhook_ingestion::anomaly::{self, Anomaly, Baseline};
+use webhook_ingestion::bodies;
+use webhook_ingestion::compression;
+use webhook_ingestion::console::{self, Command, ConsoleState};
+use webhook_ingestion::counters::{Counters, Entry};
+use webhook_ingestion::dedup;
+use webhook_ingestion::docs;
+use webhook_ingestion::encryption;
+use webhook_ingestion::erasure::{self, Mode};
+use webhook_ingestion::error_budget::{Alert, Budget, Tally, Window};
+use webhook_ingestion::github::GithubFields;
+use webhook_ingestion::heartbeat;
+use webhook_ingestion::latency::{self, Histogram};
+use webhook_ingestion::jobs::{self, Job, Params, Status};
+use webhook_ingestion::legal_hold::{Held, LegalHold};
+use webhook_ingestion::overview::Overview;
+use webhook_ingestion::logging::{Level, LogConfig, Logger};
+use webhook_ingestion::preview;
+use webhook_ingestion::residency::{self, Jurisdiction};
+use webhook_ingestion::replay::{self, Overrides};
+use webhook_ingestion::retention::Cutoffs;
+use webhook_ingestion::shapes::{self, NewShape, Shape, TypeChange};
+use webhook_ingestion::security_events::{Kind, SecurityEvent, Severity};
+use webhook_ingestion::sessions;
+use webhook_ingestion::sla::{self, Transition};
+use webhook_ingestion::snapshot::{self, Snapshot, Source};
+use webhook_ingestion::split::{self, Arm, PathCount, StatusPair};
+use webhook_ingestion::status_page::{Forwarding, State, Summary, Volume};
+use webhook_ingestion::timestamps::{self, TimeOptions};
+use webhook_ingestion::versioning::{self, Negotiated};
+use webhook_ingestion::transfer::{self, Page};
+use webhook_ingestion::usage::{self, Usage};
+
+const UUID: &str = "0b9c9a4e-5c55-4d3a-8f5d-3f6b1b2c7d8e";
+const STAGING_UUID: &str = "5f0e4c1a-2b3d-4e5f-8a9b-0c1d2e3f4a5b";
+const WEBHOOK_ID: &str = "wh_1";
+
+fn settings() -> WebhookSettings {
+    WebhookSettings {
+        config: WebhookConfig {
+            event_type: Some(FieldSource::Body("type".to_string())),
+            idempotency_key: Some(FieldSource::Header("x-delivery".to_string())),
+            routes: vec![
+                EventRoute {
+                    event_type: "invoice.*".to_string(),
+                    forward_url: Some("https://billing.example.com/hooks".to_string()),
+                    response: Some(CustomResponse {
+                        status: 202,
+                        body: "queued".to_string(),
+                        content_type: None,
+                        headers: BTreeMap::new(),
+                    }),
+                    retention_days: Some(90),
+                },
+                EventRoute {
+                    event_type: "*".to_string(),
+                    forward_url: None,
+                    response: None,
+                    retention_days: None,
+                },
+            ],
+            ..WebhookConfig::default()
+        },
+        secret: None,
+        version: 3,
+        environments: vec![Environment {
+            id: "env_1".to_string(),
+            name: "staging".to_string(),
+            uuid: STAGING_UUID.to_string(),
+            forward_url: Some("https://staging.example.com/hooks".to_string()),
+            created_at_ms: 1_760_000_000_000,
+        }],
+        jurisdiction: None,
+    }
+}
+
+fn bindings() -> (MemoryKv, MemoryDirectory) {
+    let directory = MemoryDirectory::new();
+    directory.insert(UUID, WEBHOOK_ID, settings());
+    (MemoryKv::new(), directory)
+}
+
+fn record(id: &str, received_at: i64, event_type: Option<&str>) -> CaptureRecord {
+    CaptureRecord {
+        id: id.to_string(),
+        webhook_id: WEBHOOK_ID.to_string(),
+        method: "POST".to_string(),
+        headers_json: "{}".to_string(),
+        data: "{}".to_string(),
+        size_bytes: 2,
+        received_at,
+        received_at_ms: received_at * 1000,
+        event_time: None,
+        sequence: None,
+        indexed_headers: IndexedHeaders {
+            event_type: event_type.map(str::to_string),
+            ..IndexedHeaders::default()
+        },
+        verification: None,
+        environment: None,
+        trailers: None,
+        connection_id: None,
+        frame_type: None,
+        processing: None,
+        preview: None,
+        charset: None,
+        original_body: None,
+        canonical_data: None,
+        github: GithubFields::default(),
+        stripe_cross_check: None,
+        oauth_exchange: None,
+        replay_of: None,
+        storage_form: false,
+    }
+}
+
+fn query(filters: Vec<(&'static str, String)>) -> RequestQuery {
+    RequestQuery {
+        webhook_id: WEBHOOK_ID.to_string(),
+        limit: 100,
+        offset: 0,
+        since: None,
+        until: None,
+        sort: SortColumn::ReceivedAt,
+        ascending: false,
+        filters,
+    }
+}
+
+#[test]
+fn resolves_uuids_through_the_cache() {
+    let (kv, directory) = bindings();
+
+    let first = block_on(resolve_webhook_id(&kv, &directory, UUID)).unwrap();
+    let second = block_on(resolve_webhook_id(&kv, &directory, UUID)).unwrap();
+
+    assert_eq!(first.as_deref(), Some(WEBHOOK_ID));
+    assert_eq!(second.as_deref(), Some(WEBHOOK_ID));
+    assert_eq!(directory.lookups(), 1, "second resolution is a KV hit");
+    assert_eq!(kv.ttl(&format!("webhook:uuid:{}", UUID)), Some(3600));
+}
+
+#[test]
+fn environment_uuids_resolve_to_their_webhook() {
+    let (kv, directory) = bindings();
+
+    let resolved = block_on(resolve_webhook_id(&kv, &directory, STAGING_UUID)).unwrap();
+
+    assert_eq!(resolved.as_deref(), Some(WEBHOOK_ID));
+}
+
+#[test]
+fn unknown_uuids_are_not_cached() {
+    let (kv, directory) = bindings();
+
+    let resolved = block_on(resolve_webhook_id(&kv, &directory, "missing")).unwrap();
+
+    assert_eq!(resolved, None);
+    assert!(kv.is_empty());
+}
+
+#[test]
+fn kv_failures_fall_back_to_the_directory() {
+    let (store, directory) = bindings();
+    block_on(resolve_webhook_id(&store, &directory, UUID)).unwrap();
+    store.set_failing(true);
+    let before = kv_health();
+
+    let kv = TolerantKv::new(&store);
+    let resolved = block_on(resolve_webhook_id(&kv, &directory, UUID)).unwrap();
+    let loaded = block_on(load(&kv, &directory, WEBHOOK_ID)).unwrap();
+    let after = kv_health();
+
+    assert_eq!(resolved.as_deref(), Some(WEBHOOK_ID));
+    assert_eq!(loaded.version, 3);
+    assert!(kv.fell_back());
+    assert_eq!(directory.lookups(), 3, "the cached ID and settings were unreadable, so D1 answered");
+    assert_eq!(after.read_failures - before.read_failures, 2);
+    assert!(after.write_failures > before.write_failures, "re-caching failed too");
+    assert!(block_on(resolve_webhook_id(&store, &directory, UUID)).is_err(), "untolerated reads still fail");
+}
+
+#[test]
+fn settings_are_cached_until_invalidated() {
+    let (kv, directory) = bindings();
+
+    let cached = block_on(load(&kv, &directory, WEBHOOK_ID)).unwrap();
+    let mut changed = settings();
+    changed.version = 4;
+    directory.update_settings(WEBHOOK_ID, changed);
+    let stale = block_on(load(&kv, &directory, WEBHOOK_ID)).unwrap();
+    block_on(invalidate(&kv, WEBHOOK_ID));
+    let fresh = block_on(load(&kv, &directory, WEBHOOK_ID)).unwrap();
+
+    assert_eq!(cached.version, 3);
+    assert_eq!(stale.version, 3);
+    assert_eq!(fresh.version, 4);
+    assert_eq!(cached.config, settings().config, "config survives the KV round trip");
+}
+
+#[test]
+fn loaded_config_extracts_and_routes_events() {
+    let (kv, directory) = bindings();
+    let settings = block_on(load(&kv, &directory, WEBHOOK_ID)).unwrap();
+    let headers = HashMap::from([("x-delivery".to_string(), "dlv_42".to_string())]);
+    let body = r#"{"type": "invoice.paid", "id": "evt_1"}"#;
+
+    let event_type = settings.config.event_type.as_ref().unwrap().extract(&headers, body);
+    let idempotency_key = settings.config.idempotency_key.as_ref().unwrap().extract(&headers, body);
+    let route = settings.config.route_for(event_type.as_deref()).unwrap();
+
+    assert_eq!(event_type.as_deref(), Some("invoice.paid"));
+    assert_eq!(idempotency_key.as_deref(), Some("dlv_42"));
+    assert_eq!(route.forward_url.as_deref(), Some("https://billing.example.com/hooks"));
+    assert_eq!(route.response.as_ref().map(|response| response.status), Some(202));
+    assert_eq!(settings.config.route_for(Some("customer.created")).unwrap().event_type, "*");
+    assert_eq!(settings.config.validate(), None);
+}
+
+#[test]
+fn invalid_routes_fail_validation() {
+    let mut config = settings().config;
+    config.routes[0].event_type = String::new();
+
+    assert!(config.validate().is_some());
+}
+
+#[test]
+fn storage_lists_newest_first_with_filters() {
+    let storage = MemoryStorage::new();
+    block_on(storage.insert_capture(&record("a", 100, Some("push")))).unwrap();
+    block_on(storage.insert_capture(&record("b", 200, Some("release")))).unwrap();
+    block_on(storage.insert_capture(&record("c", 300, Some("push")))).unwrap();
+
+    let all = block_on(storage.list_requests(&query(Vec::new()))).unwrap();
+    let pushes = block_on(storage.list_requests(&query(vec![("event_type", "push".to_string())]))).unwrap();
+
+    let ids: Vec<_> = all.iter().map(|request| request.id.as_str()).collect();
+    assert_eq!(ids, ["c", "b", "a"]);
+    assert_eq!(pushes.len(), 2);
+}
+
+#[test]
+fn inbox_leases_until_acked() {
+    let storage = MemoryStorage::new();
+    block_on(storage.insert_capture(&record("a", 100, None))).unwrap();
+    let lease = |now_ms| InboxQuery {
+        webhook_id: WEBHOOK_ID.to_string(),
+        limit: 10,
+        lease_ms: 30_000,
+        now_ms,
+        unread_only: false,
+        lease: true,
+    };
+
+    let peeked = block_on(storage.inbox_fetch(&InboxQuery { lease: false, ..lease(500) })).unwrap();
+    let leased = block_on(storage.inbox_fetch(&lease(1_000))).unwrap();
+    let hidden = block_on(storage.inbox_fetch(&lease(2_000))).unwrap();
+    let redelivered = block_on(storage.inbox_fetch(&lease(40_000))).unwrap();
+    let acked = block_on(storage.inbox_ack(WEBHOOK_ID, &["a".to_string()], 41_000)).unwrap();
+    let after_ack = block_on(storage.inbox_fetch(&lease(80_000))).unwrap();
+
+    assert_eq!(peeked.len(), 1);
+    assert_eq!(peeked[0].read_at_ms, None, "a peek leaves the request unread");
+    assert_eq!(leased.len(), 1);
+    assert!(hidden.is_empty());
+    assert_eq!(redelivered[0].read_at_ms, Some(1_000));
+    assert_eq!(acked, 1);
+    assert!(after_ack.is_empty());
+}
+
+#[test]
+fn purge_respects_event_type_patterns() {
+    let storage = MemoryStorage::new();
+    block_on(storage.insert_capture(&record("a", 100, Some("invoice.paid")))).unwrap();
+    block_on(storage.insert_capture(&record("b", 100, Some("customer.created")))).unwrap();
+    block_on(storage.insert_capture(&record("c", 500, Some("invoice.voided")))).unwrap();
+
+    let deleted = block_on(storage.purge(WEBHOOK_ID, Some("invoice.*"), 200, &[])).unwrap();
+
+    assert_eq!(deleted, 1);
+    assert_eq!(storage.len(), 2);
+    assert_eq!(block_on(storage.purge(WEBHOOK_ID, None, 1_000, &[])).unwrap(), 2);
+}
+
+#[test]
+fn expectations_alert_once_per_outage() {
+    let storage = MemoryStorage::new();
+    block_on(storage.insert_capture(&record("a", 1_000, Some("invoice.paid")))).unwrap();
+    block_on(storage.insert_capture(&record("b", 90_000, Some("invoice.paid")))).unwrap();
+    block_on(storage.insert_capture(&record("c", 95_000, Some("customer.created")))).unwrap();
+    let expectation = Expectation {
+        event_type: "invoice.paid".to_string(),
+        min_count: 1,
+        window_hours: 24,
+        notify_url: "https://ops.example.com/alerts".to_string(),
+    };
+    let count_at = |now: i64| {
+        block_on(storage.count_received(WEBHOOK_ID, Some(&expectation.event_type), sla::window_start(&expectation, now), None))
+            .unwrap()
+    };
+
+    assert_eq!(count_at(100_000), 1);
+    assert_eq!(sla::transition(false, &expectation, count_at(100_000)), None);
+    assert_eq!(count_at(200_000), 0);
+    assert_eq!(sla::transition(false, &expectation, 0), Some(Transition::Violated));
+    assert_eq!(sla::transition(true, &expectation, 0), None);
+    assert_eq!(sla::transition(true, &expectation, 1), Some(Transition::Recovered));
+    assert_eq!(block_on(storage.count_received(WEBHOOK_ID, None, 0, None)).unwrap(), 3);
+    assert_eq!(block_on(storage.count_received(WEBHOOK_ID, None, 0, Some(90_000))).unwrap(), 1);
+
+    let body = sla::notification("uuid-1", &expectation, Transition::Violated, 0, 5_000, 6_000);
+    assert_eq!(body["type"], "expectation.violated");
+    assert_eq!(body["expectation"]["window_hours"], 24);
+}
+
+#[test]
+fn invalid_expectations_fail_validation() {
+    let mut config = settings().config;
+    config.expectations.push(Expectation {
+        event_type: "invoice.paid".to_string(),
+        min_count: 1,
+        window_hours: 24,
+        notify_url: "https://ops.example.com/alerts".to_string(),
+    });
+    assert_eq!(config.validate(), None);
+
+    config.expectations[0].window_hours = 0;
+    assert!(config.validate().is_some());
+    config.expectations[0].window_hours = 24;
+    config.expectations[0].notify_url = "ftp://ops.example.com".to_string();
+    assert!(config.validate().is_some());
+}
+
+#[test]
+fn volume_baselines_flag_spikes_and_droughts() {
+    let mut baseline = Baseline::default();
+    for _ in 0..anomaly::WARMUP_HOURS - 1 {
+        assert_eq!(anomaly::classify(&baseline, 500, 3.0), None);
+        baseline = baseline.observe(10, 0.2);
+    }
+    assert_eq!(anomaly::classify(&baseline, 500, 3.0), None);
+    baseline = baseline.observe(10, 0.2);
+
+    assert_eq!(baseline.hours, anomaly::WARMUP_HOURS);
+    assert!((baseline.ewma - 10.0).abs() < 1e-9);
+    assert_eq!(anomaly::classify(&baseline, 12, 3.0), None);
+    assert_eq!(anomaly::classify(&baseline, 30, 3.0), Some(Anomaly::Spike));
+    assert_eq!(anomaly::classify(&baseline, 3, 3.0), Some(Anomaly::Drought));
+    assert!((baseline.observe(30, 0.2).ewma - 14.0).abs() < 1e-9);
+
+    // Near-silent webhooks never report droughts, but still spike
+    let quiet = Baseline { ewma: 0.2, hours: 48 };
+    assert_eq!(anomaly::classify(&quiet, 0, 3.0), None);
+    assert_eq!(anomaly::classify(&quiet, 3, 3.0), Some(Anomaly::Spike));
+
+    let body = anomaly::notification("uuid-1", Some(Anomaly::Spike), None, 7_200, 11, &baseline, 9_000);
+    assert_eq!(body["type"], "volume.normal");
+    assert_eq!(body["previous"], "spike");
+}
+
+#[test]
+fn latency_histograms_interpolate_percentiles() {
+    assert_eq!(latency::bucket(0), 0);
+    assert_eq!(latency::bucket(10), 0);
+    assert_eq!(latency::bucket(11), 1);
+    assert_eq!(latency::bucket(60_000), latency::BUCKET_BOUNDS_MS.len());
+
+    let mut histogram = Histogram::default();
+    assert_eq!(histogram.percentile(0.5), None);
+    histogram.add(latency::bucket(70), 10, 0);
+    assert_eq!(histogram.percentile(0.5), Some(75));
+    assert_eq!(histogram.percentile(1.0), Some(100));
+
+    histogram.add(latency::bucket(30_000), 10, 10);
+    assert_eq!(histogram.percentile(0.5), Some(100));
+    assert_eq!(histogram.percentile(0.99), Some(10_000));
+
+    let summary = histogram.summary();
+    assert_eq!(summary["count"], 20);
+    assert_eq!(summary["failures"], 10);
+    assert_eq!(summary["buckets"][3], serde_json::json!({ "le_ms": 100, "count": 10 }));
+    assert_eq!(summary["buckets"][10]["le_ms"], serde_json::Value::Null);
+}
+
+#[test]
+fn status_summaries_report_state_and_escape_names() {
+    let mut healthy = Histogram::default();
+    healthy.add(2, 99, 1);
+    let mut failing = Histogram::default();
+    failing.add(5, 1, 1);
+    let forwarding = Forwarding::from_histograms([&healthy, &failing]);
+    assert_eq!((forwarding.attempts, forwarding.failures), (100, 2));
+    assert_eq!(forwarding.success_rate, Some(0.98));
+
+    let volume = Volume { last_hour: 2, last_24h: 40, last_7d: 300 };
+    let summary = Summary::new("uuid-1".into(), "Acme <prod>".into(), volume, forwarding, Some(1_760_000_000_000), 1_760_000_060_000);
+    assert_eq!(summary.state, State::Operational);
+    let html = summary.html();
+    assert!(html.contains("<h1>Acme &lt;prod&gt;</h1>"));
+    assert!(html.contains("98.0% of 100"));
+    assert!(html.contains("2025-10-09 08:53:20 UTC"));
+
+    let idle = Summary::new("uuid-1".into(), "Acme".into(), Volume::default(), Forwarding::default(), None, 0);
+    assert_eq!(idle.state, State::Idle);
+    let degraded = Forwarding::from_histograms([&failing]);
+    assert_eq!(Summary::new("uuid-1".into(), "Acme".into(), volume, degraded, None, 0).state, State::Degraded);
+    assert_eq!(serde_json::to_value(&idle).unwrap()["state"], "idle");
+}
+
+#[test]
+fn target_responses_keep_a_bounded_body() {
+    let short = TargetResponse::new(HashMap::from([("x-id".to_string(), "1".to_string())]), b"ok");
+    assert_eq!((short.body.as_str(), short.body_truncated), ("ok", false));
+
+    // A multi-byte character cut at the limit is dropped rather than mangled
+    let mut body = vec![b'a'; MAX_RESPONSE_BODY_BYTES - 1];
+    body.extend_from_slice("é and more".as_bytes());
+    let long = TargetResponse::new(HashMap::new(), &body);
+    assert!(long.body_truncated);
+    assert_eq!(long.body.len(), MAX_RESPONSE_BODY_BYTES - 1);
+    assert!(long.body.bytes().all(|byte| byte == b'a'));
+}
+
+#[test]
+fn binary_payloads_get_previews() {
+    // 1x1 PNG header through IHDR
+    let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+    png.extend_from_slice(&[0, 0, 0, 3, 0, 0, 0, 2, 8, 6, 0, 0, 0]);
+    let preview = preview::generate(&png, Some("image/png; charset=binary")).unwrap();
+    assert_eq!(preview.sniffed_type, Some("image/png"));
+    assert_eq!(preview.declared_type.as_deref(), Some("image/png"));
+    assert_eq!((preview.width, preview.height), (Some(3), Some(2)));
+    assert_eq!(preview.size_bytes, png.len());
+    assert!(preview.hexdump.starts_with("00000000: 8950 4e47 0d0a 1a0a 0000 000d 4948 4452  .PNG........IHDR"));
+
+    let gif = b"GIF89a\x40\x01\xf0\x00\x00\x00";
+    let preview = preview::generate(gif, None).unwrap();
+    assert_eq!((preview.width, preview.height), (Some(320), Some(240)));
+
+    // Baseline JPEG: SOI, an APP0 segment, then SOF0 with height 16 and width 32
+    let jpeg = [0xff, 0xd8, 0xff, 0xe0, 0, 4, 0, 0, 0xff, 0xc0, 0, 11, 8, 0, 16, 0, 32, 1, 1, 0x11, 0];
+    let preview = preview::generate(&jpeg, None).unwrap();
+    assert_eq!((preview.width, preview.height), (Some(32), Some(16)));
+
+    let pdf = b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n";
+    assert_eq!(preview::generate(pdf, None).unwrap().pdf_version.as_deref(), Some("1.7"));
+
+    // Unrecognized but not UTF-8 still gets a hexdump; text gets nothing
+    let unknown = preview::generate(&[0xde, 0xad, 0xbe, 0xef], None).unwrap();
+    assert_eq!(unknown.sniffed_type, None);
+    assert_eq!(unknown.hexdump, "00000000: dead beef                                ....");
+    assert!(preview::generate(br#"{"ok":true}"#, Some("application/json")).is_none());
+}
+
+#[test]
+fn api_timestamps_render_in_the_requested_offset() {
+    let mut value = serde_json::json!({
+        "requests": [{"received_at_ms": 1_700_000_000_123_i64, "event_time": null, "size_bytes": 2}],
+        "responses": [{"duration_ms": 40, "created_at_ms": 1_700_000_060_000_i64}],
+    });
+    let options = TimeOptions::parse(Some("+05:30"), Some("true")).unwrap();
+    timestamps::annotate(&mut value, &options, 1_700_000_100_000);
+
+    let request = &value["requests"][0];
+    assert_eq!(request["received_at_iso"], "2023-11-15T03:43:20.123+05:30");
+    assert_eq!(request["received_at_age_ms"], 99_877);
+    assert!(request.get("event_time_iso").is_none());
+    assert_eq!(value["responses"][0]["created_at_iso"], "2023-11-15T03:44:20.000+05:30");
+    assert!(value["responses"][0].get("duration_iso").is_none());
+
+    let utc = TimeOptions::parse(None, None).unwrap();
+    assert_eq!(timestamps::rfc3339(1_700_000_000_123, utc.offset).as_deref(), Some("2023-11-14T22:13:20.123Z"));
+    assert!(timestamps::parse_offset("-0800").is_some());
+    assert!(timestamps::parse_offset("Europe/Paris").is_none());
+    assert!(timestamps::parse_offset("+25:00").is_none());
+    assert!(TimeOptions::parse(Some("EST"), None).is_err());
+}
+
+#[test]
+fn retention_tiers_strip_then_aggregate() {
+    let storage = MemoryStorage::new();
+    let day = 86_400;
+    let now = 100 * day + 3_600;
+    for (id, age_days, event_type) in [("a", 2, "push"), ("b", 10, "push"), ("c", 40, "push"), ("d", 40, "ping")] {
+        block_on(storage.insert_capture(&record(id, now - age_days * day, Some(event_type)))).unwrap();
+    }
+    let tiers: RetentionTiers = serde_json::from_str("{}").unwrap();
+    let cutoffs = Cutoffs::new(&tiers, now);
+    assert_eq!((cutoffs.strip_before, cutoffs.delete_before), (93 * day, 70 * day));
+
+    assert_eq!(block_on(storage.strip_payloads(WEBHOOK_ID, cutoffs.strip_before, &[])).unwrap(), 3);
+    // Already stripped rows are not counted again
+    assert_eq!(block_on(storage.strip_payloads(WEBHOOK_ID, cutoffs.strip_before, &[])).unwrap(), 0);
+    let counts = block_on(storage.daily_counts(WEBHOOK_ID, cutoffs.delete_before)).unwrap();
+    assert_eq!(counts.len(), 2);
+    assert!(counts.iter().all(|count| count.day == 60 * day && count.count == 1));
+    assert_eq!(block_on(storage.purge(WEBHOOK_ID, None, cutoffs.delete_before, &[])).unwrap(), 2);
+
+    let left = block_on(storage.list_requests(&query(vec![]))).unwrap();
+    let ids: Vec<(&str, bool)> = left.iter().map(|request| (request.id.as_str(), request.data.is_empty())).collect();
+    assert_eq!(ids, vec![("a", false), ("b", true)]);
+}
+
+#[test]
+fn shopify_captures_are_counted_per_shop_and_topic() {
+    let storage = MemoryStorage::new();
+    let shopify = |id: &str, received_at: i64, shop: &str, topic: &str, verification: &str| {
+        let headers = HashMap::from([
+            ("x-shopify-topic".to_string(), topic.to_string()),
+            ("x-shopify-shop-domain".to_string(), shop.to_string()),
+        ]);
+        let mut capture = record(id, received_at, Some(topic));
+        capture.indexed_headers = IndexedHeaders::extract(&headers);
+        capture.verification = Some(verification.to_string());
+        capture
+    };
+    let captures = [
+        shopify("a", 100, "Demo.myshopify.com", "orders/create", "valid"),
+        shopify("b", 200, "demo.myshopify.com", "orders/create", "invalid"),
+        shopify("c", 300, "demo.myshopify.com", "app/uninstalled", "valid"),
+        shopify("d", 400, "other.myshopify.com", "orders/create", "valid"),
+        shopify("e", 5_000, "demo.myshopify.com", "orders/create", "valid"),
+        record("f", 150, Some("push")),
+    ];
+    for capture in &captures {
+        block_on(storage.insert_capture(capture)).unwrap();
+    }
+    let stored = block_on(storage.list_requests(&query(vec![("shopify_topic", "app/uninstalled".to_string())]))).unwrap();
+    assert_eq!(stored[0].shopify_shop_domain.as_deref(), Some("demo.myshopify.com"));
+
+    let counts = block_on(storage.shopify_counts(WEBHOOK_ID, 0, 1_000)).unwrap();
+    let summary: Vec<(Option<&str>, &str, u64, u64, i64)> = counts
+        .iter()
+        .map(|count| {
+            let shop = count.shop_domain.as_deref();
+            (shop, count.topic.as_str(), count.count, count.unverified, count.last_received_at_ms)
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (Some("demo.myshopify.com"), "orders/create", 2, 1, 200_000),
+            (Some("demo.myshopify.com"), "app/uninstalled", 1, 0, 300_000),
+            (Some("other.myshopify.com"), "orders/create", 1, 0, 400_000),
+        ]
+    );
+}
+
+fn hold(id: &str, capture_id: Option<&str>, released: bool) -> LegalHold {
+    LegalHold {
+        id: id.to_string(),
+        capture_id: capture_id.map(str::to_string),
+        reason: "incident review".to_string(),
+        created_by: "token:ops".to_string(),
+        created_at_ms: 1_000,
+        released_by: released.then(|| "api_token".to_string()),
+        released_at_ms: released.then_some(2_000),
+    }
+}
+
+#[test]
+fn legal_holds_keep_captures_through_cleanup() {
+    let held = Held::from_holds(&[hold("h1", Some("b"), false), hold("h2", Some("c"), true)]);
+    assert_eq!(held, Held { webhook: false, captures: vec!["b".to_string()] });
+    assert!(Held::from_holds(&[hold("h3", None, false)]).webhook);
+    assert!(!Held::from_holds(&[hold("h4", None, true)]).webhook);
+
+    let storage = MemoryStorage::new();
+    let day = 86_400;
+    let now = 100 * day;
+    for id in ["a", "b", "c"] {
+        block_on(storage.insert_capture(&record(id, now - 40 * day, None))).unwrap();
+    }
+    assert_eq!(block_on(storage.strip_payloads(WEBHOOK_ID, now, &held.captures)).unwrap(), 2);
+    assert_eq!(block_on(storage.purge(WEBHOOK_ID, None, now, &held.captures)).unwrap(), 2);
+
+    let left = block_on(storage.list_requests(&query(vec![]))).unwrap();
+    assert_eq!(left.len(), 1);
+    assert_eq!(left[0].id, "b");
+    assert!(!left[0].data.is_empty());
+}
+
+fn webhook() -> Webhook {
+    Webhook {
+        id: WEBHOOK_ID.to_string(),
+        user_id: "user_1".to_string(),
+        uuid: UUID.to_string(),
+        name: "orders".to_string(),
+        tags: None,
+        created_at: 0,
+    }
+}
+
+#[test]
+fn erasure_deletes_or_redacts_a_subjects_captures() {
+    assert!(erasure::needle("ab").is_err());
+    assert!(erasure::needle("DACT").is_err());
+    let needle = erasure::needle(" Jane@Example.com ").unwrap();
+    assert_eq!(needle, "jane@example.com");
+
+    let storage = MemoryStorage::new();
+    let mut body = record("a", 100, Some("order.paid"));
+    body.data = r#"{"email":"JANE@example.com","total":5}"#.to_string();
+    body.canonical_data = webhook_ingestion::canonical::canonicalize(&body.data);
+    body.original_body = Some("eyJlbWFpbCI6...".to_string());
+    let mut header = record("b", 200, None);
+    header.headers_json = r#"{"x-customer":"jane@example.com"}"#.to_string();
+    let mut held = record("c", 300, None);
+    held.data = "jane@example.com".to_string();
+    for capture in [&body, &header, &held, &record("d", 400, None)] {
+        block_on(storage.insert_capture(capture)).unwrap();
+    }
+    let holds = Held { webhook: false, captures: vec!["c".to_string()] };
+
+    let report = block_on(erasure::erase_webhook(&storage, &webhook(), &holds, &needle, Mode::Redact, true)).unwrap();
+    let erased: Vec<(&str, Vec<&str>)> = report.erased.iter().map(|e| (e.id.as_str(), e.columns.clone())).collect();
+    assert_eq!(erased, vec![("a", vec!["data", "canonical_data"]), ("b", vec!["headers"])]);
+    assert_eq!(report.held, vec!["c".to_string()]);
+    // Dry runs change nothing
+    assert_eq!(block_on(storage.find_containing(WEBHOOK_ID, &needle, 10)).unwrap().len(), 3);
+
+    block_on(erasure::erase_webhook(&storage, &webhook(), &holds, &needle, Mode::Redact, false)).unwrap();
+    let left = block_on(storage.list_requests(&query(vec![("id", "a".to_string())]))).unwrap();
+    assert_eq!(left[0].data, r#"{"email":"[REDACTED]","total":5}"#);
+    assert_eq!(left[0].canonical_data.as_deref(), Some(r#"{"email":"[REDACTED]","total":5}"#));
+    assert_eq!(left[0].original_body, None);
+    let remaining = block_on(storage.find_containing(WEBHOOK_ID, &needle, 10)).unwrap();
+    assert_eq!(remaining.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), vec!["c"]);
+
+    let report = block_on(erasure::erase_webhook(&storage, &webhook(), &Held::default(), &needle, Mode::Delete, false)).unwrap();
+    assert_eq!(report.erased.len(), 1);
+    assert_eq!(block_on(storage.list_requests(&query(vec![]))).unwrap().len(), 3);
+}
+
+#[test]
+fn residency_pins_bindings_per_jurisdiction() {
+    assert_eq!(Jurisdiction::parse(" EU "), Some(Jurisdiction::Eu));
+    assert_eq!(Jurisdiction::parse("apac"), None);
+    assert_eq!(residency::binding("DB", Some(Jurisdiction::Eu)), "DB_EU");
+    assert_eq!(residency::binding("UPLOADS", Some(Jurisdiction::Us)), "UPLOADS_US");
+    assert_eq!(residency::binding("DB", None), "DB");
+
+    // Settings cached before jurisdictions existed stay in the default region
+    let mut cached = serde_json::to_value(settings()).unwrap();
+    cached.as_object_mut().unwrap().remove("jurisdiction");
+    let restored: WebhookSettings = serde_json::from_value(cached.clone()).unwrap();
+    assert_eq!(restored.jurisdiction, None);
+    cached["jurisdiction"] = serde_json::json!("eu");
+    let pinned: WebhookSettings = serde_json::from_value(cached).unwrap();
+    assert_eq!(pinned.jurisdiction, Some(Jurisdiction::Eu));
+}
+
+#[test]
+fn redeliveries_share_a_capture_id() {
+    let window_ms = 300_000;
+    let keyed = dedup::fingerprint(WEBHOOK_ID, Some("evt_1"), "POST", b"{}");
+    let resent = dedup::fingerprint(WEBHOOK_ID, Some("evt_1"), "POST", br#"{"attempt": 2}"#);
+    let body = dedup::fingerprint(WEBHOOK_ID, None, "POST", b"{}");
+
+    let first = dedup::capture_id(&keyed, 1_760_000_010_000, window_ms, false);
+    assert_eq!(first, dedup::capture_id(&resent, 1_760_000_090_000, window_ms, false), "the key decides");
+    assert_ne!(first, dedup::capture_id(&keyed, 1_760_000_310_000, window_ms, false), "next bucket");
+    assert_ne!(first, dedup::capture_id(&body, 1_760_000_010_000, window_ms, false));
+    assert_ne!(keyed, dedup::fingerprint("wh_2", Some("evt_1"), "POST", b"{}"));
+    assert_eq!(dedup::delivery_fingerprint(WEBHOOK_ID, Some("evt_1"), false, "POST", b"{}"), Some(keyed));
+    assert_eq!(dedup::delivery_fingerprint(WEBHOOK_ID, None, false, "POST", b"{}"), None, "keyless is opt-in");
+    assert_eq!(dedup::delivery_fingerprint(WEBHOOK_ID, None, true, "POST", b"{}"), Some(body));
+    assert!(!WebhookConfig::default().dedup_bodies);
+    assert_eq!(first.len(), 26);
+    assert_eq!(dedup::capture_id(&keyed, 1_760_000_010_000, window_ms, true).len(), 36);
+
+    let storage = MemoryStorage::new();
+    let original = CaptureRecord { id: first.clone(), sequence: Some(7), ..record("unused", 100, None) };
+    let retry = CaptureRecord { id: first, sequence: Some(8), ..record("unused", 160, None) };
+    assert!(block_on(storage.insert_capture(&original)).unwrap());
+    assert!(!block_on(storage.insert_capture(&retry)).unwrap());
+    let stored = block_on(storage.list_requests(&query(Vec::new()))).unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].sequence, Some(7), "the original is kept");
+}
+
+#[test]
+fn svix_retries_find_their_original_capture() {
+    let headers = HashMap::from([
+        ("svix-id".to_string(), " msg_2Lg ".to_string()),
+        ("svix-timestamp".to_string(), "1760000010".to_string()),
+    ]);
+    let indexed = IndexedHeaders::extract(&headers);
+    assert_eq!(indexed.svix_id.as_deref(), Some("msg_2Lg"));
+    assert_eq!(indexed.svix_timestamp, Some(1_760_000_010));
+    assert_eq!(indexed.idempotency_key.as_deref(), Some("msg_2Lg"));
+
+    let storage = MemoryStorage::new();
+    let original = CaptureRecord { indexed_headers: indexed, ..record("cap_first", 100, None) };
+    block_on(storage.insert_capture(&original)).unwrap();
+    block_on(storage.insert_capture(&record("cap_other", 110, None))).unwrap();
+
+    // Hours later, in another dedup bucket
+    let later = 100 + 6 * 3600;
+    let found = block_on(dedup::svix_original(&storage, WEBHOOK_ID, "msg_2Lg", later)).unwrap();
+    assert_eq!(found.as_deref(), Some("cap_first"));
+    assert_eq!(block_on(dedup::svix_original(&storage, WEBHOOK_ID, "msg_new", later)).unwrap(), None);
+    assert_eq!(block_on(dedup::svix_original(&storage, "wh_2", "msg_2Lg", later)).unwrap(), None);
+    // Past the retry window the message ID is a new delivery, and older partitions aren't searched
+    let reused = 100 + dedup::SVIX_RETRY_WINDOW_SECONDS + 1;
+    assert_eq!(block_on(dedup::svix_original(&storage, WEBHOOK_ID, "msg_2Lg", reused)).unwrap(), None);
+
+    let stored = block_on(storage.list_requests(&query(vec![("svix_id", "msg_2Lg".to_string())]))).unwrap();
+    assert_eq!(stored[0].svix_timestamp, Some(1_760_000_010));
+}
+
+#[test]
+fn envelopes_mark_encrypted_values() {
+    let sealed = vec![7u8; 40];
+    let value = encryption::envelope("01JDATAKEY", &sealed);
+
+    assert!(value.starts_with(encryption::ENVELOPE_PREFIX));
+    assert_eq!(encryption::parse_envelope(&value), Some(("01JDATAKEY", sealed)));
+    assert_eq!(encryption::parse_envelope(r#"{"type":"invoice.paid"}"#), None, "plaintext passes through");
+    assert_eq!(encryption::parse_envelope("enc:v1:no-separator"), None);
+    assert_eq!(encryption::master_key_name(2), "MASTER_KEY_2");
+    assert_eq!(encryption::decode_master_key(&format!("{}=", "A".repeat(43))).map(|key| key.len()), Ok(32));
+    assert!(encryption::decode_master_key("c2hvcnQ=").is_err());
+
+    // Plaintext that reads like an envelope is escaped, so it is never opened on read
+    let escaped = encryption::escape(&value).unwrap();
+    assert_eq!(encryption::parse_envelope(&escaped), None);
+    assert_eq!(escaped.strip_prefix(encryption::ESCAPE_PREFIX), Some(value.as_str()));
+    assert!(encryption::escape(&escaped).is_some());
+    assert_eq!(encryption::escape(r#"{"type":"invoice.paid"}"#), None);
+}
+
+#[test]
+fn security_events_flatten_for_the_siem() {
+    assert_eq!(Kind::parse("token_misuse"), Some(Kind::TokenMisuse));
+    assert_eq!(Kind::parse("login"), None);
+    assert_eq!(Kind::Enumeration.severity(), Severity::High);
+
+    let event = SecurityEvent::new(Kind::SignatureFailure, 1_700_000_000_123)
+        .ip(Some("203.0.113.7".to_string()))
+        .uuid(UUID)
+        .webhook(WEBHOOK_ID)
+        .detail(serde_json::json!({ "verification": "invalid", "enforced": true }));
+    let payload = event.siem_payload();
+
+    assert_eq!(payload["source"], "webhook-ingestion");
+    assert_eq!(payload["kind"], "signature_failure");
+    assert_eq!(payload["severity"], "medium");
+    assert_eq!(payload["timestamp"], "2023-11-14T22:13:20.123Z");
+    assert_eq!(payload["ip"], "203.0.113.7");
+    assert_eq!(payload["webhook_id"], WEBHOOK_ID);
+    assert_eq!(payload["detail"]["verification"], "invalid");
+    assert!(payload["actor"].is_null());
+    assert_eq!(payload["id"].as_str().map(str::len), Some(26), "ULID");
+}
+
+#[test]
+fn snapshots_round_trip_into_another_webhook() {
+    let mut original = record("01JORIGINAL", 1_700_000_000, Some("invoice.paid"));
+    original.headers_json = r#"{"content-type":"application/json"}"#.to_string();
+    original.data = r#"{"type":"invoice.paid"}"#.to_string();
+    original.sequence = Some(42);
+    original.processing = Some(r#"{"signature":"valid"}"#.to_string());
+    let source = Source {
+        webhook_uuid: UUID.to_string(),
+        capture_id: original.id.clone(),
+        host: Some("hooks.example.com".to_string()),
+    };
+    let frozen = snapshot::build(source, StoredRequest::from(&original), Vec::new(), 1_700_000_100_000);
+
+    assert_eq!(frozen.format, snapshot::FORMAT);
+    assert_eq!(frozen.body_base64, "eyJ0eXBlIjoiaW52b2ljZS5wYWlkIn0=");
+    assert_eq!(frozen.headers["content-type"], "application/json");
+    assert_eq!(frozen.processing["signature"], "valid");
+
+    let archived: Snapshot = serde_json::from_str(&serde_json::to_string(&frozen).unwrap()).unwrap();
+    let imported = snapshot::into_record(&archived, "wh_2", "01JIMPORTED").unwrap();
+    assert_eq!((imported.id.as_str(), imported.webhook_id.as_str()), ("01JIMPORTED", "wh_2"));
+    assert_eq!(imported.data, original.data);
+    assert_eq!(imported.received_at_ms, original.received_at_ms);
+    assert_eq!(imported.indexed_headers.event_type.as_deref(), Some("invoice.paid"));
+    assert_eq!(imported.sequence, None, "sequence numbers belong to the source webhook");
+
+    let mut foreign = archived.clone();
+    foreign.format = "something-else/v2".to_string();
+    assert!(snapshot::into_record(&foreign, "wh_2", "01JIMPORTED").is_err());
+}
+
+#[test]
+fn transfer_pages_resume_from_their_cursor() {
+    assert_eq!(transfer::next_cursor(0, 200, 200), Some(200));
+    assert_eq!(transfer::next_cursor(400, 200, 37), None, "a short page is the last one");
+
+    let capture = snapshot::build(
+        Source {
+            webhook_uuid: UUID.to_string(),
+            capture_id: "01JORIGINAL".to_string(),
+            host: None,
+        },
+        StoredRequest::from(&record("01JORIGINAL", 1_700_000_000, None)),
+        Vec::new(),
+        1_700_000_100_000,
+    );
+    let page: Page = serde_json::from_value(serde_json::json!({
+        "format": transfer::FORMAT,
+        "captures": [capture],
+        "next_cursor": null,
+    }))
+    .unwrap();
+    assert!(page.webhook.is_none());
+    assert_eq!(transfer::validate(&page), None);
+
+    let mut stale = page.clone();
+    stale.captures[0].format = "webhook-capture-snapshot/v0".to_string();
+    assert!(transfer::validate(&stale).unwrap().contains("01JORIGINAL"));
+    stale.format = "other".to_string();
+    assert!(transfer::validate(&stale).unwrap().contains("transfer format"));
+}
+
+#[test]
+fn docs_describe_what_each_event_type_carries() {
+    let capture = |id: &str, received_at: i64, event_type: &str, body: serde_json::Value| {
+        let mut record = record(id, received_at, Some(event_type));
+        record.data = body.to_string();
+        record.indexed_headers.content_type = Some("application/json; charset=utf-8".to_string());
+        StoredRequest::from(&record)
+    };
+    let captures = vec![
+        capture("a", 100, "customer.created", serde_json::json!({ "id": "cus_1", "status": "active", "tags": ["x"] })),
+        capture("b", 300, "customer.created", serde_json::json!({ "id": "cus_2", "status": "active" })),
+        capture("c", 200, "customer.created", serde_json::json!({
+            "id": "cus_3", "status": "trialing", "email": "ada@example.com", "created": "2026-10-14T08:00:00Z",
+        })),
+        capture("d", 250, "customer.created", serde_json::json!({ "id": "cus_4", "status": "active", "age": 36 })),
+        StoredRequest::from(&record("e", 150, Some("ping"))),
+        StoredRequest::from(&record("f", 160, None)),
+    ];
+    let document = docs::infer(UUID, &captures);
+    assert_eq!(document.sampled, 6);
+    assert_eq!(document.events[0].event_type.as_deref(), Some("customer.created"));
+    let created = &document.events[0];
+    assert_eq!((created.count, created.first_seen_ms, created.last_seen_ms), (4, 100_000, 300_000));
+    assert_eq!(created.content_types, vec!["application/json"]);
+
+    let field = |path: &str| created.fields.iter().find(|field| field.path == path).unwrap();
+    assert_eq!((field("id").seen, field("id").frequency), (4, 1.0));
+    assert!(field("id").values.is_empty(), "IDs never repeat, so they are not an enum");
+    assert_eq!(field("status").values, vec!["active", "trialing"]);
+    assert_eq!(field("tags[]").types, vec!["string"]);
+    assert_eq!((field("age").types.clone(), field("age").frequency), (vec!["integer"], 0.25));
+
+    // The latest body is the example, strings redacted
+    assert_eq!(created.example, Some(serde_json::json!({ "id": "<string>", "status": "<string>" })));
+    let redacted = docs::redact(&serde_json::json!({ "email": "ada@example.com", "at": "2026-10-14T08:00:00Z" }));
+    assert_eq!(redacted, serde_json::json!({ "email": "<email>", "at": "<date-time>" }));
+
+    let markdown = docs::markdown(&document, UUID);
+    assert!(markdown.contains("## `customer.created`"));
+    assert!(markdown.contains("| `status` | string | 100% | `active`, `trialing` |"));
+    assert!(markdown.contains("(no event type)"));
+    assert!(!markdown.contains("ada@example.com") && !markdown.contains("cus_"));
+}
+
+#[test]
+fn new_payload_shapes_are_diffed_against_the_dominant_one() {
+    let shape = |body: serde_json::Value| Shape::of(&body).unwrap();
+    let dominant = shape(serde_json::json!({ "id": "evt_1", "amount": 12, "customer": { "email": "a@example.com" } }));
+    let reordered = shape(serde_json::json!({ "customer": { "email": "b@example.com" }, "amount": 5, "id": "evt_2" }));
+    assert_eq!(dominant.fingerprint(), reordered.fingerprint(), "values and key order do not change a shape");
+    assert!(Shape::of(&serde_json::json!("text")).is_none());
+
+    let added = shape(serde_json::json!({ "id": "e", "amount": 1, "customer": { "email": "e" }, "livemode": false }));
+    let diff = shapes::diff(&dominant, &added);
+    assert_eq!(diff.added, vec!["livemode"]);
+    assert!(!diff.breaking());
+
+    let broken = shape(serde_json::json!({ "id": "e", "amount": "12.00", "customer_email": "e" }));
+    let diff = shapes::diff(&dominant, &broken);
+    assert!(diff.breaking());
+    assert_eq!(diff.added, vec!["customer_email"]);
+    assert_eq!(diff.removed, vec!["customer", "customer.email"]);
+    assert_eq!(
+        diff.type_changed,
+        vec![TypeChange {
+            path: "amount".to_string(),
+            before: vec!["integer".to_string()],
+            after: vec!["string".to_string()],
+        }]
+    );
+
+    let new = NewShape {
+        fingerprint: broken.fingerprint(),
+        previous_fingerprint: Some(dominant.fingerprint()),
+        diff: Some(diff),
+    };
+    let notification = shapes::notification(UUID, Some("charge.succeeded"), &new, "01JCAPTURE", 1_700_000_000_000);
+    assert_eq!(notification["type"], "shape.breaking");
+    assert_eq!(notification["diff"]["type_changed"][0]["after"], serde_json::json!(["string"]));
+
+    let mut config = WebhookConfig {
+        shapes: Some(ShapeTracking {
+            notify_url: Some("not a url".to_string()),
+            breaking_only: true,
+        }),
+        ..WebhookConfig::default()
+    };
+    assert!(config.validate().unwrap().contains("shape tracking"));
+    config.shapes = Some(ShapeTracking::default());
+    assert_eq!(config.validate(), None);
+}
+
+#[test]
+fn latency_profiles_replay_imported_forward_timings() {
+    let mut histogram = Histogram::default();
+    histogram.add(latency::bucket(30), 8, 0);
+    histogram.add(latency::bucket(60_000), 2, 2);
+    let mut merged = Histogram::default();
+    merged.merge(&histogram);
+    let profile = merged.profile("forwarding:*").unwrap();
+    assert_eq!(profile.validate(), None);
+    assert_eq!(profile.buckets.len(), latency::BUCKET_BOUNDS_MS.len() + 1);
+    assert!(Histogram::default().profile("forwarding:*").is_none());
+
+    // Eight in ten answers fall in 25..=50 ms, the rest in the open bucket above 10 s
+    assert_eq!(latency::sample(&profile, 0.0, 0.0), 25);
+    assert_eq!(latency::sample(&profile, 0.79, 1.0), 50);
+    assert_eq!(latency::sample(&profile, 0.8, 0.0), 10_000);
+    assert_eq!(latency::sample(&profile, 0.99, 1.0), 20_000);
+    for _ in 0..100 {
+        let delay = latency::simulated_delay_ms(&profile);
+        assert!((25..=50).contains(&delay) || (10_000..=20_000).contains(&delay), "{}", delay);
+    }
+
+    let bucket = |le_ms, count| LatencyBucket { le_ms, count };
+    let invalid = |buckets| LatencyProfile { buckets, source: None }.validate();
+    assert!(invalid(vec![bucket(Some(100), 0)]).is_some());
+    assert!(invalid(vec![bucket(None, 1), bucket(Some(100), 1)]).is_some());
+    assert!(invalid(vec![bucket(Some(100), 1), bucket(Some(50), 1)]).is_some());
+    assert!(invalid(vec![bucket(Some(60_000), 1)]).is_some());
+    let config: WebhookConfig = serde_json::from_value(serde_json::json!({
+        "latency_profile": {"buckets": [{"le_ms": 100, "count": 1}, {"le_ms": 50, "count": 1}]},
+    }))
+    .unwrap();
+    assert!(config.validate().unwrap().starts_with("Invalid latency_profile"));
+}
+
+#[test]
+fn traffic_splits_assign_stable_arms_and_diff_answers() {
+    let mut traffic = TrafficSplit {
+        a: "https://old.example.com/hooks".to_string(),
+        b: "https://new.example.com/hooks".to_string(),
+        b_percent: 25,
+        compare_percent: 0,
+    };
+    let ids: Vec<String> = (0..400).map(|n| format!("capture-{}", n)).collect();
+    let arms: Vec<Arm> = ids.iter().map(|id| split::arm(&traffic, id)).collect();
+    let to_b = arms.iter().filter(|arm| **arm == Arm::B).count();
+    assert!((60..=140).contains(&to_b), "{}", to_b);
+    assert!(!arms.contains(&Arm::Both));
+    assert_eq!(split::arm(&traffic, &ids[7]), arms[7]);
+    traffic.compare_percent = 100;
+    assert_eq!(split::arm(&traffic, &ids[7]), Arm::Both);
+    assert_eq!(split::targets(&traffic, Arm::Both), vec![traffic.a.as_str(), traffic.b.as_str()]);
+
+    let answer = |status, body: &str, duration_ms| ForwardOutcome {
+        status: Some(status),
+        duration_ms,
+        error: None,
+        response: Some(TargetResponse::new(HashMap::new(), body.as_bytes())),
+    };
+    let a = answer(200, r#"{"ok":true,"items":[{"price":10}],"old":1}"#, 40);
+    let b = answer(200, r#"{"ok":true,"items":[{"price":12}],"new":1}"#, 90);
+    let comparison = split::compare("capture-1", &a, &b, 1_000);
+    assert!(comparison.status_match);
+    assert!(!comparison.body_match);
+    assert_eq!(comparison.body_diff, vec!["items[0].price", "new", "old"]);
+    assert_eq!((comparison.duration_a_ms, comparison.duration_b_ms), (40, 90));
+    let text = split::compare("capture-2", &answer(200, "ok", 1), &answer(500, "ok", 1), 1_000);
+    assert!(!text.status_match && text.body_match && text.body_diff.is_empty());
+
+    let same = |split: TrafficSplit| WebhookConfig { split: Some(split), ..WebhookConfig::default() }.validate();
+    assert_eq!(same(traffic.clone()), None);
+    assert!(same(TrafficSplit { b: traffic.a.clone(), ..traffic.clone() }).is_some());
+    assert!(same(TrafficSplit { b_percent: 101, ..traffic }).is_some());
+}
+
+#[test]
+fn shadow_reports_sum_up_mismatches() {
+    let answer = |status, body: &str, duration_ms| ForwardOutcome {
+        status: Some(status),
+        duration_ms,
+        error: None,
+        response: Some(TargetResponse::new(HashMap::new(), body.as_bytes())),
+    };
+    let primary = answer(200, r#"{"id":"ord_1","total":10}"#, 50);
+    let comparisons = vec![
+        split::compare("c3", &primary, &answer(500, "oops", 80), 3),
+        split::compare("c2", &primary, &answer(200, r#"{"id":"ord_1","total":11}"#, 70), 2),
+        split::compare("c1", &primary, &answer(200, r#"{"id":"ord_1","total":10}"#, 60), 1),
+    ];
+    let report = split::report(&comparisons, 1);
+    assert_eq!(report.compared, 3);
+    assert_eq!((report.status_mismatches, report.body_mismatches), (1, 2));
+    assert!((report.match_rate - 1.0 / 3.0).abs() < 1e-9);
+    assert_eq!(report.statuses[0], StatusPair { primary: Some(200), shadow: Some(200), count: 2 });
+    assert_eq!(report.paths, vec![PathCount { path: "total".to_string(), count: 1 }]);
+    assert_eq!(report.mean_latency_delta_ms, Some(20));
+    assert_eq!(report.samples.len(), 1);
+    assert_eq!(report.samples[0].capture_id, "c3");
+    assert_eq!(split::report(&[], 5).match_rate, 1.0);
+
+    let shadowed = |shadow: &str| {
+        let shadow = ShadowForwarding { primary: "https://old.example.com".to_string(), shadow: shadow.to_string() };
+        WebhookConfig { shadow: Some(shadow), ..WebhookConfig::default() }.validate()
+    };
+    assert_eq!(shadowed("https://new.example.com"), None);
+    assert!(shadowed("https://old.example.com").is_some());
+    assert!(shadowed("webhook:2b6c1d4e-0000-4000-8000-000000000000").is_some());
+}
+
+#[test]
+fn replays_apply_overrides_to_the_captured_delivery() {
+    let mut original = StoredRequest::from(&record("cap_1", 1_700_000_000, Some("invoice.paid")));
+    original.headers = r#"{"content-type":"application/json","authorization":"Bearer x"}"#.to_string();
+    original.data = r#"{"data":{"amount":100,"currency":"usd"}}"#.to_string();
+    original.processing = Some(r#"{"forwards":["webhook:other","https://api.example.com/hooks"]}"#.to_string());
+    assert_eq!(replay::default_target(&original).as_deref(), Some("https://api.example.com/hooks"));
+
+    let overrides: Overrides = serde_json::from_value(serde_json::json!({
+        "headers": {"X-Debug": "1", "authorization": null},
+        "json": {"data": {"amount": 0, "currency": null}},
+    }))
+    .unwrap();
+    let variant = replay::apply(&original, &overrides).unwrap();
+    assert_eq!(variant.method, "POST");
+    assert_eq!(variant.target, "https://api.example.com/hooks");
+    assert_eq!(variant.headers.get("x-debug").map(String::as_str), Some("1"));
+    assert!(!variant.headers.contains_key("authorization"));
+    assert_eq!(variant.body, r#"{"data":{"amount":0}}"#);
+
+    let get = Overrides { method: Some("get".to_string()), ..Overrides::default() };
+    assert_eq!(replay::apply(&original, &get).unwrap().body, "");
+    let both = Overrides { body: Some("x".to_string()), json: Some(serde_json::json!({})), ..Overrides::default() };
+    assert!(replay::apply(&original, &both).is_err());
+    original.processing = None;
+    assert!(replay::apply(&original, &Overrides::default()).is_err());
+    let target = Overrides { target: Some("ftp://example.com".to_string()), ..Overrides::default() };
+    assert!(replay::apply(&original, &target).is_err());
+    assert!(serde_json::from_str::<Overrides>(r#"{"bogus": 1}"#).is_err());
+}
+
+#[test]
+fn replay_recipes_round_trip_and_validate() {
+    let config: WebhookConfig = serde_json::from_value(serde_json::json!({
+        "replay_recipes": {
+            "staging": {"overrides": {"target": "https://staging.example.com/hooks", "headers": {"x-test": "1"}}},
+            "slow": {"overrides": {"json": {"livemode": false}}, "sign": false, "pacing_ms": 500},
+        }
+    }))
+    .unwrap();
+    assert_eq!(config.validate(), None);
+    let staging = &config.replay_recipes["staging"];
+    assert!(staging.sign);
+    assert_eq!(staging.pacing_ms, 0);
+    assert_eq!(staging.overrides.headers.get("x-test"), Some(&Some("1".to_string())));
+    let stored = serde_json::to_value(&config).unwrap();
+    assert_eq!(stored["replay_recipes"]["slow"]["overrides"], serde_json::json!({"json": {"livemode": false}}));
+    assert_eq!(serde_json::from_value::<WebhookConfig>(stored).unwrap(), config);
+
+    let invalid = |recipe: serde_json::Value| {
+        let config: WebhookConfig = serde_json::from_value(serde_json::json!({"replay_recipes": recipe})).unwrap();
+        config.validate()
+    };
+    assert!(invalid(serde_json::json!({"has space": {}})).is_some());
+    assert!(invalid(serde_json::json!({"x": {"pacing_ms": 60_000}})).is_some());
+    assert!(invalid(serde_json::json!({"x": {"overrides": {"target": "ftp://example.com"}}})).is_some());
+    assert!(invalid(serde_json::json!({"x": {"overrides": {"body": "a", "json": {}}}})).is_some());
+    let many: serde_json::Map<String, serde_json::Value> =
+        (0..21).map(|index| (format!("r{}", index), serde_json::json!({}))).collect();
+    assert!(invalid(serde_json::Value::Object(many)).is_some());
+}
+
+#[test]
+fn jobs_delete_and_export_in_bounded_steps() {
+    let storage = MemoryStorage::new();
+    for (id, received_at) in [("a", 100), ("b", 200), ("c", 300), ("d", 400), ("e", 500)] {
+        block_on(storage.insert_capture(&record(id, received_at, Some("invoice.paid")))).unwrap();
+    }
+
+    let params: Params = serde_json::from_str(r#"{"kind": "delete", "until": 450}"#).unwrap();
+    assert_eq!(params.validate(), None);
+    assert!(serde_json::from_str::<Params>(r#"{"kind": "delete", "bogus": 1}"#).is_err());
+    let nothing: Params = serde_json::from_str(r#"{"kind": "delete"}"#).unwrap();
+    assert!(nothing.validate().is_some());
+    let unknown: Params = serde_json::from_str(r#"{"kind": "export", "filters": {"bogus": "x"}}"#).unwrap();
+    assert!(unknown.validate().is_some());
+
+    let Params::Delete(delete) = params.clone() else { unreachable!() };
+    let mut job = Job::new(Some(WEBHOOK_ID.to_string()), "api_token", params, 1_000);
+    assert_eq!((job.status, job.total), (Status::Queued, None));
+    let held = Held { webhook: false, captures: vec!["c".to_string()] };
+    let step = block_on(jobs::delete_step(&storage, &mut job, &delete, &held)).unwrap();
+    assert_eq!(step.next_in_ms, Some(0));
+    let step = block_on(jobs::delete_step(&storage, &mut job, &delete, &held)).unwrap();
+    assert_eq!(step.next_in_ms, None, "only the held capture is left before `until`");
+    assert_eq!(job.result, Some(serde_json::json!({"deleted": 3, "held": 1})));
+    let left = block_on(storage.list_requests(&query(Vec::new()))).unwrap();
+    let left: Vec<String> = left.into_iter().map(|request| request.id).collect();
+    assert_eq!(left.len(), 2);
+    assert!(left.contains(&"c".to_string()) && left.contains(&"e".to_string()));
+    let hold_all = Held { webhook: true, captures: Vec::new() };
+    assert!(block_on(jobs::delete_step(&storage, &mut job, &delete, &hold_all)).is_err());
+
+    let params: Params = serde_json::from_str(r#"{"kind": "export", "columns": "id,event_type", "limit": 1}"#).unwrap();
+    let Params::Export(export) = params.clone() else { unreachable!() };
+    let mut job = Job::new(Some(WEBHOOK_ID.to_string()), "api_token", params, 1_000);
+    let first = block_on(jobs::export_step(&storage, &mut job, &export, None)).unwrap();
+    assert_eq!(first.chunk.as_deref(), Some("id,event_type\r\nc,invoice.paid\r\n"));
+    assert_eq!(first.next_in_ms, Some(0));
+    let last = block_on(jobs::export_step(&storage, &mut job, &export, None)).unwrap();
+    assert_eq!((last.chunk, last.next_in_ms), (None, None));
+    assert_eq!(job.result, Some(serde_json::json!({"rows": 1, "chunks": 1})));
+}
+
+#[test]
+fn counters_expire_reset_and_track_unflushed_changes() {
+    let mut counters = Counters::default();
+    assert_eq!(counters.increment("captures:today", 1, Some(1_000), 0), 1);
+    assert_eq!(counters.increment("captures:today", 2, Some(1_000), 500), 3);
+    assert_eq!(counters.increment("total", -4, None, 500), -4);
+    assert_eq!(counters.get("missing", 500), 0);
+
+    // Both changes are waiting; taking them leaves nothing to flush
+    let changes = counters.take_dirty();
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0], ("captures:today".to_string(), Some(Entry { value: 3, expires_at_ms: Some(1_000) })));
+    assert!(!counters.is_dirty());
+    counters.restore(&changes);
+    assert!(counters.is_dirty());
+    counters.take_dirty();
+
+    // The window passes: the counter reads as zero and restarts with a new expiry
+    assert_eq!(counters.get("captures:today", 1_000), 0);
+    assert_eq!(counters.snapshot(1_000).len(), 1);
+    assert_eq!(counters.increment("captures:today", 1, Some(1_000), 1_200), 1);
+    assert_eq!(counters.snapshot(1_200)["captures:today"].expires_at_ms, Some(2_200));
+    assert_eq!(counters.expire(2_200), 1);
+
+    // A reset is flushed as a deletion
+    counters.take_dirty();
+    assert!(counters.reset("total"));
+    assert!(!counters.reset("total"));
+    assert_eq!(counters.take_dirty(), vec![("total".to_string(), None)]);
+}
+
+#[test]
+fn log_levels_and_sampling_follow_the_closest_module() {
+    let config = LogConfig::parse(Some("warn, durable=info, durable::jobs=debug, bogus=loud"), Some("capture=0.25"));
+    assert_eq!(config.level("cache"), Level::Warn);
+    assert_eq!(config.level("durable::socket"), Level::Info);
+    assert_eq!(config.level("durable::jobs"), Level::Debug);
+    assert_eq!(config.level("durable_x"), Level::Warn);
+    assert_eq!(config.level("bogus"), Level::Warn);
+    assert_eq!(config.rate("capture"), 0.25);
+    assert_eq!(config.rate("capture_log"), 1.0);
+
+    let mut logger = Logger::new(config);
+    assert!(!logger.allows("webhook_ingestion::cache", Level::Info));
+    assert!(logger.allows("webhook_ingestion::cache", Level::Warn));
+    assert!(logger.allows("webhook_ingestion::durable::jobs", Level::Debug));
+
+    assert!(!logger.allows("capture", Level::Info));
+
+    // One successful capture line in four, but every failure
+    let mut logger = Logger::new(LogConfig::parse(None, Some("capture=0.25")));
+    assert_eq!((0..100).filter(|_| logger.allows("capture", Level::Info)).count(), 25);
+    assert_eq!((0..100).filter(|_| logger.allows("capture", Level::Warn)).count(), 100);
+    assert!(logger.allows("forward", Level::Info));
+    assert!(!logger.allows("forward", Level::Debug));
+}
+
+#[test]
+fn error_budget_alerts_on_the_failure_rate_of_the_window() {
+    assert_eq!(Budget::parse(None, None, None), None);
+    assert_eq!(Budget::parse(Some(""), Some("5"), None), None);
+    let budget = Budget::parse(Some("0.1"), Some("5"), Some("10")).unwrap();
+    assert_eq!((budget.window_minutes, budget.min_requests), (5, 10));
+
+    // Tallies are handed over every 10 seconds, and whenever the minute changes
+    let mut tally = Tally::default();
+    assert_eq!(tally.add(false, 60_000), None);
+    assert_eq!(tally.add(true, 65_000), None);
+    let due = tally.add(false, 70_000).unwrap();
+    assert_eq!((due.total, due.failed, due.minute), (2, 1, 1));
+    assert_eq!(tally.add(true, 120_000).map(|due| due.total), Some(1));
+    assert_eq!((tally.total, tally.failed, tally.minute), (1, 1, 2));
+
+    // Minutes 5..=9 make up the window at 10:30; the current minute is still being counted
+    let counters: std::collections::BTreeMap<String, i64> = [
+        ("total:4", 100),
+        ("failed:4", 100),
+        ("total:5", 20),
+        ("failed:5", 1),
+        ("total:9", 20),
+        ("failed:9", 4),
+        ("total:10", 50),
+        ("failed:10", 50),
+        ("other", 7),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value))
+    .collect();
+    let window = Window::sum(&counters, budget.window_minutes, 630_000);
+    assert_eq!(window, Window { total: 40, failed: 5 });
+    assert_eq!(budget.evaluate(&window, false), Some(Alert::Exhausted));
+    assert_eq!(budget.evaluate(&window, true), None);
+    assert_eq!(budget.evaluate(&Window { total: 40, failed: 4 }, true), Some(Alert::Recovered));
+    // Too few deliveries say nothing either way
+    assert_eq!(budget.evaluate(&Window { total: 9, failed: 9 }, false), None);
+}
+
+#[test]
+fn overview_totals_volumes_across_webhooks() {
+    let storage = MemoryStorage::new();
+    let capture = |id: &str, webhook_id: &str, received_at: i64, verification: Option<&str>| {
+        let mut capture = record(id, received_at, None);
+        capture.webhook_id = webhook_id.to_string();
+        capture.verification = verification.map(str::to_string);
+        capture
+    };
+    let captures = [
+        capture("a", WEBHOOK_ID, 86_400, Some("valid")),
+        capture("b", WEBHOOK_ID, 86_500, Some("invalid")),
+        capture("c", "webhook-2", 86_600, None),
+        capture("d", WEBHOOK_ID, 90_000, None),
+        capture("e", "deleted-webhook", 100, None),
+    ];
+    for capture in &captures {
+        block_on(storage.insert_capture(capture)).unwrap();
+    }
+
+    let today = block_on(storage.webhook_volumes(86_400)).unwrap();
+    assert_eq!(today.iter().map(|volume| (volume.webhook_id.as_str(), volume.count)).collect::<Vec<_>>(), [
+        (WEBHOOK_ID, 3),
+        ("webhook-2", 1)
+    ]);
+    let stored = block_on(storage.webhook_volumes(0)).unwrap();
+    let webhook = |id: &str, uuid: &str| Webhook {
+        id: id.to_string(),
+        user_id: "user-1".to_string(),
+        uuid: uuid.to_string(),
+        name: format!("{} name", uuid),
+        tags: None,
+        created_at: 0,
+    };
+    let webhooks = [webhook(WEBHOOK_ID, UUID), webhook("webhook-2", "uuid-2"), webhook("webhook-3", "uuid-3")];
+
+    let overview = Overview::build(&webhooks, &today, &stored, (8, 2), 90_000_000);
+    assert_eq!((overview.webhooks, overview.requests_today, overview.bytes_today), (3, 4, 8));
+    assert_eq!(overview.top_webhooks[0].uuid.as_deref(), Some(UUID));
+    assert_eq!(overview.top_webhooks[0].requests, 3);
+    assert_eq!(overview.error_rates.unverified, 1);
+    assert_eq!(overview.error_rates.unverified_rate, 0.25);
+    assert_eq!(overview.error_rates.forward_failure_rate, 0.25);
+    assert_eq!((overview.storage.captures, overview.storage.bytes), (5, 10));
+
+    // Captures of a deleted webhook still count, without a name
+    let deleted: Vec<_> = stored.iter().filter(|volume| volume.webhook_id == "deleted-webhook").cloned().collect();
+    let overview = Overview::build(&webhooks, &deleted, &stored, (0, 0), 0);
+    assert_eq!(overview.top_webhooks[0].uuid, None);
+    assert_eq!(overview.error_rates.forward_failure_rate, 0.0);
+}
+
+#[test]
+fn storage_usage_reserves_against_the_quota() {
+    // Captures and an upload counted the way the Counter object adds them up
+    fn add(counters: &mut Counters, changes: Vec<(&str, i64)>) -> std::collections::BTreeMap<String, i64> {
+        changes
+            .into_iter()
+            .map(|(key, by)| (key.to_string(), counters.increment(key, by, None, 0)))
+            .collect()
+    }
+    fn usage_of(counters: &Counters) -> Usage {
+        Usage::from_values(&counters.snapshot(0).into_iter().map(|(key, entry)| (key, entry.value)).collect())
+    }
+    let mut counters = Counters::default();
+    add(&mut counters, usage::capture_changes(300));
+    add(&mut counters, usage::capture_changes(200));
+    let values = add(&mut counters, usage::object_changes(1_000));
+    let usage = usage_of(&counters);
+    assert_eq!(usage, Usage { captures: 2, capture_bytes: 500, objects: 1, object_bytes: 1_000 });
+    assert_eq!(usage.bytes(), 1_500);
+    assert_eq!(Usage::from_values(&usage.values()), usage);
+
+    let quota = StorageQuota { max_bytes: 1_600 };
+    assert!(!usage::exceeds(&quota, &values));
+    assert!(usage::exceeds(&quota, &add(&mut counters, usage::capture_changes(101))));
+    // A refused delivery is taken back out
+    add(&mut counters, usage::undo(&usage::capture_changes(101)));
+    assert_eq!(usage_of(&counters), usage);
+
+    assert_eq!(usage::object_owner("uploads/wh_1/cap_1/orders.csv", "uploads/"), Some(WEBHOOK_ID));
+    assert_eq!(usage::object_owner("email/wh_1/cap_2/0-invoice.pdf", "email/"), Some(WEBHOOK_ID));
+    assert_eq!(usage::object_owner("uploads//cap_1/orders.csv", "uploads/"), None);
+    assert_eq!(usage::object_owner("exports/wh_1/job.csv", "uploads/"), None);
+
+    let config = WebhookConfig {
+        storage_quota: Some(StorageQuota { max_bytes: 0 }),
+        ..WebhookConfig::default()
+    };
+    assert!(config.validate().is_some());
+}
+
+#[test]
+fn compressed_bodies_are_marked_and_only_large_ones_qualify() {
+    let config: WebhookConfig = serde_json::from_str(r#"{"compression": {}}"#).unwrap();
+    let compression = config.compression.unwrap();
+    assert_eq!(compression, Compression { algorithm: CompressionAlgorithm::Gzip, min_bytes: 1024 });
+
+    let value = compression::envelope(CompressionAlgorithm::Deflate, &[1, 2, 3]);
+    assert!(value.starts_with(compression::PREFIX));
+    assert_eq!(compression::parse_envelope(&value), Some((CompressionAlgorithm::Deflate, vec![1, 2, 3])));
+    // Plain bodies (and unknown algorithms) are read as they are
+    assert_eq!(compression::parse_envelope(r#"{"type": "invoice.paid"}"#), None);
+    assert_eq!(compression::parse_envelope("cmp:v1:zstd:AQID"), None);
+
+    assert!(!compression::eligible(&compression, &"x".repeat(1023)));
+    assert!(compression::eligible(&compression, &"x".repeat(1024)));
+    // A sender body that reads like a compressed value is stored escaped, never inflated
+    let forged = compression::envelope(CompressionAlgorithm::Gzip, &[0; 1024]);
+    let escaped = compression::escape(&forged).unwrap();
+    assert_eq!(compression::parse_envelope(&escaped), None);
+    assert_eq!(escaped.strip_prefix(compression::ESCAPE_PREFIX), Some(forged.as_str()));
+    assert!(compression::escape(&escaped).unwrap().starts_with(compression::ESCAPE_PREFIX));
+    assert_eq!(compression::escape(r#"{"type": "invoice.paid"}"#), None);
+    let stored = compression::escape_record(&CaptureRecord { data: forged.clone(), ..record("a", 100, None) });
+    assert!(stored.storage_form && stored.data == escaped);
+}
+
+#[test]
+fn large_bodies_are_referenced_by_their_hash() {
+    let mut heartbeat = record("a", 100, None);
+    heartbeat.data = format!(r#"{{"type": "heartbeat", "padding": "{}"}}"#, "x".repeat(600));
+    let (body, referencing) = bodies::split(&heartbeat, 512).unwrap();
+    assert_eq!(body, heartbeat.data);
+    let hash = bodies::parse_reference(&referencing.data).unwrap();
+    assert_eq!(hash, bodies::hash(&heartbeat.data));
+    assert_eq!(hash.len(), 64);
+    assert_eq!(referencing.size_bytes, heartbeat.size_bytes);
+
+    // The same body always lands on the same reference
+    let mut retry = record("b", 200, None);
+    retry.data = heartbeat.data.clone();
+    assert_eq!(bodies::split(&retry, 512).unwrap().1.data, referencing.data);
+
+    // Small bodies stay as they are
+    assert!(bodies::split(&record("c", 300, None), 512).is_none());
+    assert_eq!(bodies::parse_reference("body:sha256:short"), None);
+
+    // A sender posting a reference gets that text stored, never the referenced body
+    let forged = CaptureRecord { data: referencing.data.clone(), ..record("d", 400, None) };
+    let stored = bodies::inline(&forged);
+    assert_eq!(bodies::parse_reference(&stored.data), None);
+    assert_eq!(stored.data, format!("{}{}", bodies::ESCAPE_PREFIX, referencing.data));
+    let (body, apart) = bodies::split(&forged, 1).unwrap();
+    assert_eq!(body, forged.data);
+    assert_eq!(bodies::parse_reference(&apart.data), Some(bodies::hash(&forged.data).as_str()));
+    assert_eq!(bodies::inline(&heartbeat).data, heartbeat.data);
+}
+
+#[test]
+fn steady_identical_deliveries_fold_into_one_capture() {
+    let kv = MemoryKv::new();
+    let config = Heartbeat {
+        min_repeats: 3,
+        tolerance: 0.2,
+        collapse: true,
+    };
+    let ping = br#"{"type": "ping"}"#;
+    let beat_at = |now_ms: i64| block_on(heartbeat::observe(&kv, &config, WEBHOOK_ID, "POST", ping, now_ms));
+
+    // The third delivery a minute apart (give or take some jitter) is a heartbeat
+    let first = beat_at(0);
+    assert!(!first.heartbeat);
+    block_on(heartbeat::save(&kv, first, "cap_1"));
+    let second = beat_at(60_000);
+    assert!(!second.heartbeat && second.collapse_into(&config).is_none());
+    block_on(heartbeat::save(&kv, second, "cap_2"));
+    let third = beat_at(125_000);
+    assert_eq!(third.trail().map(|trail| (trail.repeats, trail.interval_ms)), Some((3, 65_000)));
+    assert_eq!(third.collapse_into(&config), Some("cap_2"));
+    block_on(heartbeat::save(&kv, third, "cap_2"));
+
+    // A gap far off the usual interval starts a new run; other payloads are tracked apart
+    let late = beat_at(600_000);
+    assert!(!late.heartbeat);
+    assert_eq!(late.track.count, 2);
+    assert!(!block_on(heartbeat::observe(&kv, &config, WEBHOOK_ID, "POST", b"{}", 600_000)).heartbeat);
+
+    // The folded capture counts the deliveries it stands for
+    let storage = MemoryStorage::new();
+    block_on(storage.insert_capture(&record("cap_2", 60, None))).unwrap();
+    assert!(block_on(storage.record_repeat(WEBHOOK_ID, "cap_2", 125_000)).unwrap());
+    assert!(block_on(storage.record_repeat(WEBHOOK_ID, "cap_2", 185_000)).unwrap());
+    assert!(!block_on(storage.record_repeat(WEBHOOK_ID, "gone", 185_000)).unwrap());
+    let stored = block_on(storage.list_requests(&query(Vec::new()))).unwrap();
+    assert_eq!((stored[0].repeat_count, stored[0].last_seen_at_ms), (Some(3), Some(185_000)));
+
+    let invalid = WebhookConfig {
+        heartbeat: Some(Heartbeat { min_repeats: 1, ..config.clone() }),
+        ..WebhookConfig::default()
+    };
+    assert!(invalid.validate().is_some());
+}
+
+#[test]
+fn related_captures_are_grouped_into_sessions() {
+    let capture = |id: &str, received_at: i64, event_type: &str, order: Option<&str>| {
+        let mut record = record(id, received_at, Some(event_type));
+        if let Some(order) = order {
+            record.data = format!(r#"{{"data": {{"order_id": "{}"}}}}"#, order);
+        }
+        StoredRequest::from(&record)
+    };
+    let captures = vec![
+        capture("created", 1_000, "order.created", Some("o_1")),
+        capture("other", 1_010, "order.created", Some("o_2")),
+        capture("ping_1", 1_020, "ping", None),
+        capture("ping_2", 1_200, "ping", None),
+        capture("paid", 1_250, "order.paid", Some("o_1")),
+        capture("shipped", 9_000, "order.shipped", Some("o_1")),
+    ];
+    let config = Sessions {
+        gap_seconds: 300,
+        keys: vec![FieldSource::Body("data.order_id".to_string())],
+    };
+
+    let grouped = sessions::group(&captures, &config);
+    let ids: Vec<Vec<&str>> = grouped
+        .iter()
+        .map(|session| session.capture_ids.iter().map(String::as_str).collect())
+        .collect();
+    // Newest first; a pause longer than the gap starts a new session for the same order
+    assert_eq!(ids, vec![vec!["shipped"], vec!["ping_1", "ping_2"], vec!["other"], vec!["created", "paid"]]);
+    let order = &grouped[3];
+    assert_eq!(order.id, "created");
+    assert_eq!((order.started_at_ms, order.ended_at_ms), (1_000_000, 1_250_000));
+    assert_eq!(order.event_types, vec!["order.created", "order.paid"]);
+    assert_eq!(order.keys[0].source, "body:data.order_id");
+    assert_eq!(order.keys[0].value, "o_1");
+    assert!(grouped[1].keys.is_empty());
+
+    // Without keys captures are grouped by time alone
+    let bursts = sessions::group(&captures, &Sessions::default());
+    assert_eq!(bursts.iter().map(|session| session.capture_ids.len()).collect::<Vec<_>>(), vec![1, 5]);
+}
+
+#[test]
+fn annotations_are_validated_before_they_are_stored() {
+    let annotation: NewAnnotation = serde_json::from_value(serde_json::json!({
+        "source": "ci",
+        "status": "failure",
+        "message": "processed by job #123: failed with timeout",
+        "url": "https://ci.example.com/jobs/123",
+        "data": {"job": 123, "attempt": 2},
+    }))
+    .unwrap();
+    assert_eq!(annotation.status, annotations::Status::Failure);
+    assert_eq!(annotation.validate(), None);
+
+    // The status defaults to info
+    let note: NewAnnotation = serde_json::from_str(r#"{"source": "monitor", "message": "seen"}"#).unwrap();
+    assert_eq!(note.status, annotations::Status::Info);
+    assert!(serde_json::from_str::<NewAnnotation>(r#"{"source": "ci", "message": "x", "status": "odd"}"#).is_err());
+
+    let invalid = [
+        NewAnnotation { source: " ".to_string(), ..annotation.clone() },
+        NewAnnotation { message: "x".repeat(2001), ..annotation.clone() },
+        NewAnnotation { url: Some("javascript:alert(1)".to_string()), ..annotation.clone() },
+        NewAnnotation { data: Some(serde_json::json!([1, 2])), ..annotation.clone() },
+        NewAnnotation { data: Some(serde_json::json!({"log": "x".repeat(9000)})), ..annotation.clone() },
+    ];
+    for annotation in invalid {
+        assert!(annotation.validate().is_some(), "{:?}", annotation);
+    }
+}
+
+#[test]
+fn console_commands_steer_the_shared_session() {
+    let kv = MemoryKv::new();
+    assert_eq!(block_on(console::load(&kv, WEBHOOK_ID)), None);
+
+    // State commands change the session; replays and pings leave it alone
+    let mut session = ConsoleState::default();
+    assert!(session.apply(&Command::parse(r#"{"type": "pause"}"#).unwrap()));
+    let respond = Command::parse(r#"{"type": "respond", "response": {"status": 503, "body": "down"}}"#).unwrap();
+    assert!(session.apply(&respond));
+    let replay = Command::parse(r#"{"type": "replay", "id": "cap_1", "overrides": {"method": "PUT"}}"#).unwrap();
+    assert!(!session.apply(&replay));
+    assert!(!session.apply(&Command::Ping));
+    assert!(session.paused);
+    assert_eq!(session.response.as_ref().map(|response| response.status), Some(503));
+
+    // Ingestion sees what the consoles set until the session ends
+    block_on(console::save(&kv, WEBHOOK_ID, &session));
+    assert_eq!(block_on(console::load(&kv, WEBHOOK_ID)), Some(session.clone()));
+    assert!(session.apply(&Command::Resume));
+    assert!(session.apply(&Command::parse(r#"{"type": "clear_response"}"#).unwrap()));
+    assert_eq!(session, ConsoleState::default());
+    block_on(console::close(&kv, WEBHOOK_ID));
+    assert_eq!(block_on(console::load(&kv, WEBHOOK_ID)), None);
+
+    let invalid = [
+        r#"{"type": "shutdown"}"#,
+        r#"{"type": "replay"}"#,
+        r#"{"type": "respond", "response": {"status": 42}}"#,
+        "not json",
+    ];
+    for text in invalid {
+        assert!(Command::parse(text).is_err(), "{}", text);
+    }
+}
+
+#[test]
+fn api_versions_route_on_unversioned_paths_and_deprecate_legacy_ones() {
+    let v1 = versioning::negotiate("/api/v1/webhooks/abc/config", None).unwrap();
+    assert_eq!(
+        v1,
+        Negotiated { version: 1, path: "/api/webhooks/abc/config".to_string(), legacy: false }
+    );
+    assert_eq!(versioning::response_headers(&v1), vec![("API-Version", "1".to_string())]);
+    // The header negotiates without the prefix and must agree with it
+    assert!(!versioning::negotiate("/api/webhooks", Some("1")).unwrap().legacy);
+    assert!(versioning::negotiate("/api/v2/webhooks", None).is_err());
+    assert!(versioning::negotiate("/api/webhooks", Some("2")).is_err());
+    // Paths merely starting with a `v` aren't versions
+    assert!(versioning::negotiate("/api/verify", None).unwrap().legacy);
+
+    let legacy = versioning::negotiate("/api/webhooks/abc", None).unwrap();
+    assert_eq!((legacy.version, legacy.path.as_str()), (1, "/api/webhooks/abc"));
+    let headers: HashMap<&str, String> = versioning::response_headers(&legacy).into_iter().collect();
+    assert_eq!(headers["Deprecation"], "@1791936000");
+    assert_eq!(headers["Sunset"], versioning::LEGACY_SUNSET);
+    assert_eq!(headers["Link"], "</api/v1/webhooks/abc>; rel=\"successor-version\"");
+}
+
+#[test]
+fn viewer_shares_are_refused_on_mutating_routes() {
+    let path = "/api/webhooks/0b9c9a4e-5c55-4d3a-8f5d-3f6b1b2c7d8e/config";
+    // A project token passes the guard; the share caps the role on the webhook
+    let token = Role::Editor;
+    assert!(requirement(&worker::Method::Patch, path).role <= token);
+    let viewer = Role::from_share("viewer").map(|role| role.min(token));
+    assert_eq!(webhook_access(viewer, requirement(&worker::Method::Patch, path).role), Err(403));
+    assert_eq!(webhook_access(viewer, requirement(&worker::Method::Get, path).role), Ok(()));
+
+    let editor = Role::from_share("editor").map(|role| role.min(token));
+    assert_eq!(webhook_access(editor, requirement(&worker::Method::Patch, path).role), Ok(()));
+    // Unknown share roles grant nothing, like a webhook outside the project
+    assert_eq!(Role::from_share("collaborator"), None);
+    assert_eq!(webhook_access(None, Role::Viewer), Err(404));
+
+    // Looking at the inbox is a read; leasing and acking are not
+    let inbox = "/api/webhooks/0b9c9a4e-5c55-4d3a-8f5d-3f6b1b2c7d8e/inbox";
+    assert_eq!(requirement(&worker::Method::Get, inbox).role, Role::Viewer);
+    for path in [inbox.to_string(), format!("{}/ack", inbox)] {
+        let required = requirement(&worker::Method::Post, &path);
+        assert_eq!((required.role, required.scope), (Role::Editor, Scope::InboxConsume));
+        assert_eq!(webhook_access(viewer, required.role), Err(403));
+    }
+}
+
+#[test]
+fn partitions_mirror_webhook_data_indexes() {
+    let table = "webhook_data_2025_10";
+    let event_type = "CREATE INDEX webhook_data_event_type_idx ON webhook_data(webhook_id, event_type)";
+    assert_eq!(
+        mirror_partition_index(event_type, table).as_deref(),
+        Some(concat!(
+            "CREATE INDEX IF NOT EXISTS webhook_data_2025_10_event_type_idx ",
+            "ON webhook_data_2025_10(webhook_id, event_type)"
+        ))
+    );
+    let initial = "CREATE INDEX IF NOT EXISTS webhook_data_webhook_id_idx ON webhook_data(webhook_id)";
+    assert_eq!(
+        mirror_partition_index(initial, table).as_deref(),
+        Some("CREATE INDEX IF NOT EXISTS webhook_data_2025_10_webhook_id_idx ON webhook_data_2025_10(webhook_id)")
+    );
+    assert!(mirror_partition_index("CREATE INDEX custom ON webhook_data(webhook_id)", table).is_none());
+}
+
+#[test]
+fn audit_bounds_reject_seconds_that_overflow_milliseconds() {
+    assert_eq!(audit_second_bounds(1_700_000_000), Some((1_700_000_000_000, 1_700_000_000_999)));
+    assert_eq!(audit_second_bounds(-1), Some((-1000, -1)));
+    assert_eq!(audit_second_bounds(i64::MAX / 1000 + 1), None);
+    assert_eq!(audit_second_bounds(i64::MIN), None);
+    assert_eq!(audit_second_bounds(i64::MAX / 1000), None, "the last millisecond doesn't fit either");
+    assert!(audit_second_bounds(i64::MAX / 1000 - 1).is_some());
+}
+
+#[test]
+fn token_lifetimes_are_bounded() {
+    let now_ms = 1_700_000_000_000;
+
+    assert_eq!(token_expires_at_ms(now_ms, 86_400), Some(now_ms + 86_400_000));
+    assert!(token_expires_at_ms(now_ms, MAX_TOKEN_EXPIRES_IN_SECONDS).is_some());
+    assert_eq!(token_expires_at_ms(now_ms, MAX_TOKEN_EXPIRES_IN_SECONDS + 1), None);
+    assert_eq!(token_expires_at_ms(now_ms, i64::MAX), None);
+    assert_eq!(token_expires_at_ms(now_ms, 0), None);
+    assert_eq!(token_expires_at_ms(now_ms, -60), None);
+    assert_eq!(token_expires_at_ms(i64::MAX, 1), None);
+}
+
+#[test]
+fn ulids_of_one_millisecond_differ_in_their_random_part() {
+    let timestamp_ms = 1_700_000_000_123;
+    let first = ulid(timestamp_ms);
+    let second = ulid(timestamp_ms);
+
+    assert_eq!(first.len(), 26);
+    assert_eq!(first[..10], second[..10], "the timestamp prefix is shared");
+    assert_ne!(first[10..], second[10..]);
+    assert!(ulid(timestamp_ms + 1)[..10] > first[..10]);
+}
+
+#[test]
+fn migrations_split_on_statement_boundaries_only() {
+    let sql = concat!(
+        "-- Notes; with a semicolon\n",
+        "CREATE TABLE notes (id TEXT PRIMARY KEY, body TEXT DEFAULT 'a; b -- c');\n",
+        "/* block; comment */ INSERT INTO notes (id, body) VALUES ('it''s; fine', \"x;y\");\n",
+        "CREATE TRIGGER notes_touch AFTER UPDATE ON notes BEGIN\n",
+        "  UPDATE notes SET body = CASE WHEN new.body IS NULL THEN '' ELSE new.body END WHERE id = new.id;\n",
+        "  DELETE FROM notes WHERE id = 'gone';\n",
+        "END;\n",
+        "CREATE INDEX idx_notes_body ON notes(body)",
+    );
+
+    let statements = migration_statements(sql);
+
+    assert_eq!(statements.len(), 4, "{:#?}", statements);
+    assert_eq!(statements[0], "CREATE TABLE notes (id TEXT PRIMARY KEY, body TEXT DEFAULT 'a; b -- c')");
+    assert_eq!(statements[1], "INSERT INTO notes (id, body) VALUES ('it''s; fine', \"x;y\")");
+    assert!(statements[2].starts_with("CREATE TRIGGER notes_touch") && statements[2].ends_with("END"));
+    assert!(statements[2].contains("DELETE FROM notes WHERE id = 'gone';"));
+    assert_eq!(statements[3], "CREATE INDEX idx_notes_body ON notes(body)");
+    assert_eq!(migration_statements("BEGIN TRANSACTION; SELECT 1; COMMIT;").len(), 3);
+}
+
+#[test]
+fn tail_streams_drop_live_lines_the_backlog_sent() {
+    let mut dedup = BacklogDedup::new(["cap_2".to_string()].into_iter().collect());
+
+    // The DO's lines may arrive split across chunks
+    assert_eq!(dedup.filter(br#"{"id":"cap_2","method":"POST"}"#), b"");
+    assert_eq!(dedup.filter(b"\n{\"id\":\"cap_3\""), b"");
+    assert_eq!(dedup.filter(b"}\n{\"id\":\"cap_4\"}\n"), b"{\"id\":\"cap_3\"}\n{\"id\":\"cap_4\"}\n");
+    // Each backlog ID is dropped once; afterwards lines pass through as sent
+    assert_eq!(dedup.filter(b"{\"id\":\"cap_2\"}\n"), b"{\"id\":\"cap_2\"}\n");
+    assert_eq!(dedup.filter(b"{\"id\":\"cap_5\""), b"{\"id\":\"cap_5\"");
+}
+
+#[test]
+fn api_timestamp_ages_that_overflow_are_null() {
+    let mut value = serde_json::json!({ "received_at_ms": i64::MIN, "created_at_ms": i64::MAX });
+    let options = TimeOptions::parse(None, Some("true")).unwrap();
+    timestamps::annotate(&mut value, &options, 1_700_000_100_000);
+
+    assert_eq!(value["received_at_age_ms"], serde_json::Value::Null);
+    assert_eq!(value["created_at_age_ms"], 1_700_000_100_000 - i64::MAX);
+}
@@ -0,0 +1,85 @@
+//! MQTT codec for the MQTT-over-WebSocket sink
+
+use webhook_ingestion::mqtt::{self, Malformed, Packet, Publish};
+
+fn string(value: &str) -> Vec<u8> {
+    let mut bytes = (value.len() as u16).to_be_bytes().to_vec();
+    bytes.extend(value.as_bytes());
+    bytes
+}
+
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    assert!(body.len() < 128);
+    let mut bytes = vec![header, body.len() as u8];
+    bytes.extend(body);
+    bytes
+}
+
+fn connect(level: u8, client_id: &str) -> Vec<u8> {
+    let mut body = string("MQTT");
+    body.extend([level, 0x02, 0x00, 0x3c]);
+    body.extend(string(client_id));
+    packet(0x10, &body)
+}
+
+#[test]
+fn decodes_connect() {
+    let packets = mqtt::decode_all(&connect(4, "sensor-1")).unwrap();
+
+    assert_eq!(packets, [Packet::Connect { protocol_level: 4, client_id: "sensor-1".to_string() }]);
+    assert_eq!(mqtt::connack(mqtt::CONNECT_ACCEPTED), [0x20, 0x02, 0x00, 0x00]);
+}
+
+#[test]
+fn decodes_publishes_and_acknowledges_by_qos() {
+    let mut qos1 = string("devices/1/temp");
+    qos1.extend([0x00, 0x07]);
+    qos1.extend(b"21.5");
+    let mut bytes = packet(0x33, &qos1);
+    let mut qos0 = string("devices/1/state");
+    qos0.extend(b"on");
+    bytes.extend(packet(0x30, &qos0));
+
+    let packets = mqtt::decode_all(&bytes).unwrap();
+
+    assert_eq!(
+        packets[0],
+        Packet::Publish(Publish {
+            topic: "devices/1/temp".to_string(),
+            qos: 1,
+            retain: true,
+            dup: false,
+            packet_id: Some(7),
+            payload: b"21.5".to_vec(),
+        })
+    );
+    assert_eq!(mqtt::response(&packets[0]), Some(vec![0x40, 0x02, 0x00, 0x07]));
+    assert!(matches!(&packets[1], Packet::Publish(publish) if publish.qos == 0 && publish.payload == b"on"));
+    assert_eq!(mqtt::response(&packets[1]), None);
+}
+
+#[test]
+fn refuses_subscriptions_and_answers_pings() {
+    let mut subscribe = vec![0x00, 0x09];
+    subscribe.extend(string("a/#"));
+    subscribe.push(0x01);
+    subscribe.extend(string("b/+"));
+    subscribe.push(0x00);
+
+    let packets = mqtt::decode_all(&[packet(0x82, &subscribe), vec![0xc0, 0x00]].concat()).unwrap();
+
+    assert_eq!(packets[0], Packet::Subscribe { packet_id: 9, filters: 2 });
+    assert_eq!(mqtt::response(&packets[0]), Some(vec![0x90, 0x04, 0x00, 0x09, 0x80, 0x80]));
+    assert_eq!(mqtt::response(&packets[1]), Some(vec![0xd0, 0x00]));
+}
+
+#[test]
+fn rejects_truncated_and_invalid_packets() {
+    let truncated = &connect(4, "sensor-1")[..6];
+    let bad_qos = packet(0x36, &string("t"));
+    let endless_length = [0x30, 0xff, 0xff, 0xff, 0xff, 0x01];
+
+    assert_eq!(mqtt::decode_all(truncated), Err(Malformed));
+    assert_eq!(mqtt::decode_all(&bad_qos), Err(Malformed));
+    assert_eq!(mqtt::decode_all(&endless_length), Err(Malformed));
+}
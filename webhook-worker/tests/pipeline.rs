@@ -0,0 +1,1579 @@
+//! Ingestion pipeline core, natively
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap};
+use webhook_ingestion::canonical;
+use webhook_ingestion::chain;
+use webhook_ingestion::config_history::{self, Snapshot};
+use webhook_ingestion::dashboard;
+use webhook_ingestion::fixtures::{self, Expect};
+use webhook_ingestion::github::GithubFields;
+use webhook_ingestion::charset::{self, Charset};
+use webhook_ingestion::local::*;
+use webhook_ingestion::backchannel_logout;
+use webhook_ingestion::oauth;
+use webhook_ingestion::pipeline::{self, CaptureMeta, FrameType, IncomingFrame, IncomingRequest};
+use webhook_ingestion::processing::{Processing, SignedUrl};
+use webhook_ingestion::scim::{self, Endpoint, ResourceType, ScimRequest};
+use webhook_ingestion::script::{self, Script};
+use webhook_ingestion::simulate::{self, Sample};
+use webhook_ingestion::stripe::{self, Change, Referenced};
+use webhook_ingestion::slack::{self, Kind, SlackRequest};
+use webhook_ingestion::twiml;
+use worker::Url;
+
+const UUID: &str = "0b9c9a4e-5c55-4d3a-8f5d-3f6b1b2c7d8e";
+const STAGING_UUID: &str = "5f0e4c1a-2b3d-4e5f-8a9b-0c1d2e3f4a5b";
+const NOW_MS: i64 = 1_760_000_000_123;
+const SECRET: &str = "whsec_test";
+
+fn request(method: &str, url: &str, headers: &[(&str, &str)], body: Option<&str>) -> IncomingRequest {
+    IncomingRequest {
+        method: method.to_string(),
+        url: Url::parse(url).unwrap(),
+        headers: headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+        body: body.map(str::to_string),
+        received_at_ms: NOW_MS,
+    }
+}
+
+fn capture_url(query: &str) -> String {
+    format!("https://hooks.example.com/w/{}{}", UUID, query)
+}
+
+fn route(event_type: &str, forward_url: Option<&str>) -> EventRoute {
+    EventRoute {
+        event_type: event_type.to_string(),
+        forward_url: forward_url.map(str::to_string),
+        response: None,
+        retention_days: None,
+    }
+}
+
+fn settings() -> WebhookSettings {
+    WebhookSettings {
+        config: WebhookConfig {
+            event_type: Some(FieldSource::Body("type".to_string())),
+            routes: vec![
+                route("invoice.*", Some("https://billing.example.com/hooks")),
+                route("customer.*", Some("https://staging.example.com/hooks")),
+            ],
+            ..WebhookConfig::default()
+        },
+        secret: Some("url-secret".to_string()),
+        version: 1,
+        environments: vec![Environment {
+            id: "env_1".to_string(),
+            name: "staging".to_string(),
+            uuid: STAGING_UUID.to_string(),
+            forward_url: Some("https://staging.example.com/hooks".to_string()),
+            created_at_ms: 0,
+        }],
+        jurisdiction: None,
+    }
+}
+
+fn hmac_hex(payload: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+    mac.update(payload.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[test]
+fn parses_bodies_and_indexed_headers() {
+    let incoming = request(
+        "POST",
+        &capture_url(""),
+        &[
+            ("content-type", "application/json; charset=utf-8"),
+            ("x-github-event", "push"),
+            ("idempotency-key", "key-1"),
+        ],
+        Some(r#"{"ref":"main"}"#),
+    );
+
+    let parsed = pipeline::parse(&incoming).unwrap();
+
+    assert_eq!(parsed.data, r#"{"ref":"main"}"#);
+    assert_eq!(parsed.size_bytes, 14);
+    assert_eq!(parsed.received_at, 1_760_000_000);
+    assert_eq!(parsed.indexed_headers.content_type.as_deref(), Some("application/json"));
+    assert_eq!(parsed.indexed_headers.event_type.as_deref(), Some("push"));
+    assert_eq!(parsed.indexed_headers.idempotency_key.as_deref(), Some("key-1"));
+}
+
+#[test]
+fn github_deliveries_fill_their_columns() {
+    let body = r#"{"action":"opened","installation":{"id":4242,"node_id":"MDIz"},
+        "repository":{"full_name":"octo-org/hello-world"}}"#;
+    let incoming = request(
+        "POST",
+        &capture_url(""),
+        &[
+            ("content-type", "application/json"),
+            ("x-github-event", "pull_request"),
+            ("x-github-delivery", "72d3162e-cc78-11e3-81ab-4c9367dc0958"),
+        ],
+        Some(body),
+    );
+    let parsed = pipeline::parse(&incoming).unwrap();
+    let record = pipeline::into_record(
+        parsed,
+        CaptureMeta {
+            id: "cap_1".to_string(),
+            webhook_id: "wh_1".to_string(),
+            sequence: None,
+            verification: None,
+            environment: None,
+        },
+    );
+
+    let stored = StoredRequest::from(&record);
+    assert_eq!(stored.github_event.as_deref(), Some("pull_request"));
+    assert_eq!(stored.github_delivery.as_deref(), Some("72d3162e-cc78-11e3-81ab-4c9367dc0958"));
+    assert_eq!(stored.github_installation_id.as_deref(), Some("4242"));
+    assert_eq!(stored.github_repository.as_deref(), Some("octo-org/hello-world"));
+    assert_eq!(stored.github_installation, None, "looked up through the API");
+
+    // Installation events carry their account; other senders get no GitHub columns
+    let installation = r#"{"action":"created","installation":{"id":7,"account":{"login":"octo-org"}}}"#;
+    let headers = HashMap::from([("x-github-event".to_string(), "installation".to_string())]);
+    assert_eq!(GithubFields::extract(&headers, installation).installation.as_deref(), Some("octo-org"));
+    assert_eq!(GithubFields::extract(&HashMap::new(), body), GithubFields::default());
+}
+
+#[test]
+fn stripe_cross_checks_list_what_changed_since_the_event() {
+    let event: serde_json::Value = serde_json::from_str(
+        r#"{"id":"evt_1","type":"payment_intent.processing","data":{"object":{"id":"pi_1",
+        "object":"payment_intent","status":"processing","amount":2000,"metadata":{"order":"42"}}}}"#,
+    )
+    .unwrap();
+    let referenced = Referenced::from_event(&event).unwrap();
+    assert_eq!(referenced.path(), "payment_intents/pi_1");
+    let session = Referenced {
+        object: "checkout.session".to_string(),
+        id: "cs_1".to_string(),
+    };
+    assert_eq!(session.path(), "checkout/sessions/cs_1");
+    let policy = Referenced {
+        object: "issuing.authorization".to_string(),
+        id: "iauth_1".to_string(),
+    };
+    assert_eq!(policy.path(), "issuing/authorizations/iauth_1");
+
+    let current = serde_json::json!({
+        "id": "pi_1",
+        "object": "payment_intent",
+        "status": "succeeded",
+        "amount": 2000,
+        "metadata": {},
+        "latest_charge": "ch_1",
+    });
+    let check = stripe::compare(&referenced, &event, &current, NOW_MS);
+    assert_eq!(
+        check.changes,
+        vec![
+            Change {
+                path: "latest_charge".to_string(),
+                event: None,
+                current: Some(serde_json::json!("ch_1")),
+            },
+            Change {
+                path: "metadata.order".to_string(),
+                event: Some(serde_json::json!("42")),
+                current: None,
+            },
+            Change {
+                path: "status".to_string(),
+                event: Some(serde_json::json!("processing")),
+                current: Some(serde_json::json!("succeeded")),
+            },
+        ]
+    );
+    assert!(!check.truncated);
+    assert!(stripe::diff(&current, &current).is_empty());
+
+    // Events without an object to look up are not cross-checked
+    assert_eq!(Referenced::from_event(&serde_json::json!({ "data": {} })), None);
+}
+
+#[test]
+fn twilio_webhooks_are_answered_with_twiml() {
+    let form = Some("application/x-www-form-urlencoded");
+    let config = TwimlConfig {
+        voice: Some("<Response><Say>Hello {{ From }}</Say></Response>".to_string()),
+        messaging: Some("<Response><Message>Got: {{Body}}{{Missing}}</Message></Response>".to_string()),
+    };
+
+    let sms = "MessageSid=SM1&From=%2B15551234567&Body=Tom+%26+Jerry+%3Cb%3E";
+    let response = twiml::respond(&config, form, sms).unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.content_type.as_deref(), Some("text/xml"));
+    assert_eq!(response.body, "<Response><Message>Got: Tom &amp; Jerry &lt;b&gt;</Message></Response>");
+
+    let call = "CallSid=CA1&From=%2B15551234567&CallStatus=ringing";
+    let response = twiml::respond(&config, form, call).unwrap();
+    assert_eq!(response.body, "<Response><Say>Hello +15551234567</Say></Response>");
+
+    // No template: an empty answer; status callbacks and other senders keep the default response
+    assert_eq!(twiml::respond(&TwimlConfig::default(), form, call).unwrap().body, twiml::EMPTY);
+    assert!(twiml::respond(&config, form, "MessageSid=SM1&MessageStatus=delivered").is_none());
+    assert!(twiml::respond(&config, Some("application/json"), r#"{"CallSid":"CA1"}"#).is_none());
+
+    let invalid = WebhookConfig {
+        twiml: Some(TwimlConfig {
+            voice: Some("Hello".to_string()),
+            messaging: None,
+        }),
+        ..WebhookConfig::default()
+    };
+    assert!(invalid.validate().is_some());
+}
+
+#[test]
+fn slack_commands_and_interactions_are_decoded_and_answered() {
+    let form = ("content-type", "application/x-www-form-urlencoded");
+    let command = "command=%2Fdeploy&text=api+%22v2%22&user_name=ada&team_id=T1\
+        &response_url=https%3A%2F%2Fhooks.slack.com%2Fcommands%2FT1%2F1%2Fabc";
+    let incoming = request("POST", &capture_url(""), &[form], Some(command));
+    let record = pipeline::into_record(
+        pipeline::parse(&incoming).unwrap(),
+        CaptureMeta {
+            id: "cap_1".to_string(),
+            webhook_id: "wh_1".to_string(),
+            sequence: None,
+            verification: None,
+            environment: None,
+        },
+    );
+    assert_eq!(record.indexed_headers.event_type.as_deref(), Some("/deploy"));
+    let decoded: serde_json::Value = serde_json::from_str(record.canonical_data.as_deref().unwrap()).unwrap();
+    assert_eq!(decoded["text"], "api \"v2\"");
+
+    let slack = SlackRequest::parse(Some(form.1), command).unwrap();
+    assert_eq!(slack.kind, Kind::Command);
+    assert_eq!(slack.response_url().unwrap().path(), "/commands/T1/1/abc");
+    let config = SlackConfig {
+        response: Some(r#"{"response_type": "ephemeral", "text": "Deploying {{text}} for {{user_name}}"}"#.to_string()),
+        follow_up: None,
+    };
+    let response = slack::respond(&config, &slack);
+    assert_eq!(response.content_type.as_deref(), Some("application/json"));
+    let message: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+    assert_eq!(message["text"], "Deploying api \"v2\" for ada");
+    assert_eq!(slack::respond(&SlackConfig::default(), &slack).body, "");
+
+    let payload = r#"{"type":"block_actions","user":{"name":"ada"},"actions":[{"action_id":"approve","value":"42"}],
+        "response_url":"https://attacker.example/hook"}"#;
+    let body: String = form_urlencoded::Serializer::new(String::new()).append_pair("payload", payload).finish();
+    let interaction = SlackRequest::parse(Some(form.1), &body).unwrap();
+    assert_eq!(interaction.kind, Kind::Interaction);
+    assert_eq!(interaction.event_type().as_deref(), Some("block_actions"));
+    assert_eq!(slack::render("{{user.name}}:{{actions.0.value}}", &interaction.fields), "ada:42");
+    assert_eq!(interaction.response_url(), None, "follow-ups only go to Slack");
+
+    assert_eq!(SlackRequest::parse(Some(form.1), "text=hello"), None);
+    let invalid = WebhookConfig {
+        slack: Some(SlackConfig {
+            response: Some("Deploying {{text}}".to_string()),
+            follow_up: None,
+        }),
+        ..WebhookConfig::default()
+    };
+    assert!(invalid.validate().is_some());
+}
+
+#[test]
+fn oauth_callbacks_never_store_the_code() {
+    let callback = format!("https://hooks.example.com/w/{}/oauth/callback?code=4%2F0Ab_secret&state=xyz", UUID);
+    let (stripped, code) = oauth::strip_secrets(&Url::parse(&callback).unwrap());
+    assert_eq!(code.as_deref(), Some("4/0Ab_secret"));
+    assert_eq!(oauth::event_type(&stripped), "oauth.callback");
+    let incoming = IncomingRequest {
+        method: "GET".to_string(),
+        url: stripped,
+        headers: HashMap::new(),
+        body: None,
+        received_at_ms: NOW_MS,
+    };
+    let parsed = pipeline::parse(&incoming).unwrap();
+    assert!(!parsed.data.contains("secret"));
+    let query: HashMap<String, String> = serde_json::from_str(&parsed.data).unwrap();
+    assert_eq!(query["code"], oauth::fingerprint("4/0Ab_secret"));
+    assert_eq!(query["state"], "xyz");
+
+    let client = OauthClient {
+        token_url: "https://oauth2.example.com/token".to_string(),
+        client_id: "client-1".to_string(),
+        client_secret: "env:OAUTH_CLIENT_SECRET".to_string(),
+        redirect_uri: None,
+    };
+    let redirect = oauth::redirect_uri(&client, &incoming.url);
+    assert_eq!(redirect, format!("https://hooks.example.com/w/{}/oauth/callback", UUID));
+
+    // The token endpoint's answer keeps its shape and public claims, never the tokens
+    let claims = r#"{"iss":"https://accounts.example.com","aud":"client-1","exp":1760003600,"sub":"1234",
+        "email":"ada@example.com"}"#;
+    let id_token = format!("eyJhbGciOiJSUzI1NiJ9.{}.c2ln", URL_SAFE_NO_PAD.encode(claims));
+    let response = serde_json::json!({
+        "access_token": "ya29.secret",
+        "token_type": "Bearer",
+        "expires_in": 3599,
+        "scope": "openid email",
+        "id_token": id_token,
+    });
+    let exchange = oauth::summarize(200, &response);
+    assert_eq!(exchange.issued, vec!["access_token", "id_token"]);
+    assert_eq!(exchange.expires_in, Some(3599));
+    let claims = exchange.claims.as_ref().unwrap();
+    assert_eq!(claims["aud"], "client-1");
+    assert_eq!(claims["email"], "[REDACTED]");
+    let stored = serde_json::to_string(&exchange).unwrap();
+    assert!(!stored.contains("ya29") && !stored.contains("ada@"));
+
+    let refusal = serde_json::json!({ "error": "invalid_grant", "error_description": "Bad code" });
+    let refused = oauth::summarize(400, &refusal);
+    assert_eq!(refused.error.as_deref(), Some("invalid_grant: Bad code"));
+    assert!(refused.issued.is_empty());
+
+    let mut redacted = WebhookConfig {
+        oauth: Some(OauthClient {
+            client_secret: "literal-secret".to_string(),
+            ..client.clone()
+        }),
+        ..WebhookConfig::default()
+    }
+    .redacted();
+    assert_eq!(redacted.oauth.as_ref().unwrap().client_secret, "********");
+    redacted.restore_secrets(&WebhookConfig {
+        oauth: Some(OauthClient {
+            client_secret: "literal-secret".to_string(),
+            ..client
+        }),
+        ..WebhookConfig::default()
+    });
+    assert_eq!(redacted.oauth.unwrap().client_secret, "literal-secret");
+}
+
+#[test]
+fn logout_tokens_are_checked_as_the_spec_requires() {
+    let jwt = |claims: serde_json::Value| {
+        format!("eyJhbGciOiJSUzI1NiJ9.{}.c2ln", URL_SAFE_NO_PAD.encode(claims.to_string()))
+    };
+    let mut claims = serde_json::json!({
+        "iss": "https://idp.example.com", "aud": ["client-1"], "iat": 1_760_000_000, "exp": 1_760_000_120,
+        "jti": "bWJq", "sid": "08a5019c", "events": { backchannel_logout::LOGOUT_EVENT: {} },
+    });
+    let form = |token: &str| format!("logout_token={}", token);
+    let content_type = Some("application/x-www-form-urlencoded");
+
+    let accepted = backchannel_logout::respond(content_type, &form(&jwt(claims.clone())), 1_760_000_060);
+    assert_eq!((accepted.status, accepted.body.as_str()), (200, ""));
+    let expired = backchannel_logout::respond(content_type, &form(&jwt(claims.clone())), 1_760_000_200);
+    assert_eq!(expired.status, 400);
+    assert!(expired.body.contains("invalid_request") && expired.body.contains("expired"));
+
+    claims["nonce"] = serde_json::json!("n-0S6");
+    let with_nonce = backchannel_logout::check(content_type, &form(&jwt(claims.clone())), 1_760_000_060);
+    assert!(with_nonce.unwrap_err().contains("nonce"));
+    claims.as_object_mut().unwrap().remove("nonce");
+    claims["events"] = serde_json::json!({});
+    assert!(backchannel_logout::check(content_type, &form(&jwt(claims)), 1_760_000_060).is_err());
+    assert!(backchannel_logout::check(content_type, "logout_token=not-a-jwt", 1_760_000_060).is_err());
+    assert!(backchannel_logout::check(Some("application/json"), "{}", 1_760_000_060).is_err());
+}
+
+#[test]
+fn scim_calls_are_answered_like_a_service_provider() {
+    let call = |method: &str, path: &str, body: &str| {
+        let url = Url::parse(&format!("https://hooks.example.com/w/{}/scim/v2{}", UUID, path)).unwrap();
+        ScimRequest::parse(&url, UUID, method, body).unwrap()
+    };
+    let mut users = Vec::new();
+
+    let create = call("POST", "/Users", r#"{"userName":"ada@example.com","name":{"givenName":"Ada"},"active":true}"#);
+    assert_eq!(create.endpoint, Endpoint::Collection(ResourceType::User));
+    assert_eq!(create.event_type(), "scim.user.create");
+    let (reply, change) = scim::handle(&create, &users, 1_760_000_000_000, "u-1");
+    assert_eq!(reply.status, 201);
+    let location = format!("https://hooks.example.com/w/{}/scim/v2/Users/u-1", UUID);
+    assert_eq!(reply.location, Some(location));
+    let body = reply.body.unwrap();
+    assert_eq!(body["id"], "u-1");
+    assert_eq!(body["meta"]["version"], "W/\"1\"");
+    assert_eq!(body["schemas"][0], ResourceType::User.schema());
+    let Some(scim::Change::Put(user)) = change else { panic!("expected a write") };
+    users.push(user);
+
+    // userName is unique, case-insensitively
+    let again = call("POST", "/Users", r#"{"userName":"ADA@example.com"}"#);
+    let (duplicate, change) = scim::handle(&again, &users, 0, "u-2");
+    assert_eq!((duplicate.status, change), (409, None));
+    assert_eq!(duplicate.body.unwrap()["scimType"], "uniqueness");
+
+    let by_name = call("GET", "/Users?filter=userName%20eq%20%22Ada%40example.com%22", "");
+    let listed = scim::handle(&by_name, &users, 0, "-").0;
+    assert_eq!(listed.body.as_ref().unwrap()["totalResults"], 1);
+    let missed = scim::handle(&call("GET", "/Users?filter=userName%20eq%20%22bob%22", ""), &users, 0, "-").0;
+    assert_eq!(missed.body.unwrap()["Resources"], serde_json::json!([]));
+    let unsupported = scim::handle(&call("GET", "/Users?filter=userName%20sw%20%22a%22", ""), &users, 0, "-").0;
+    assert_eq!(unsupported.status, 400);
+
+    let deactivate = r#"{"schemas":["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+        "Operations":[{"op":"Replace","path":"active","value":false},
+        {"op":"add","value":{"name.familyName":"Lovelace"}}]}"#;
+    let (patched, change) = scim::handle(&call("PATCH", "/Users/u-1", deactivate), &users, 1_760_000_100_000, "-");
+    assert_eq!(patched.status, 200);
+    assert_eq!(patched.etag.as_deref(), Some("W/\"2\""));
+    let body = patched.body.unwrap();
+    assert_eq!((body["active"].clone(), body["name"]["familyName"].clone()), (false.into(), "Lovelace".into()));
+    assert_eq!(body["name"]["givenName"], "Ada");
+    let Some(scim::Change::Put(user)) = change else { panic!("expected a write") };
+    users[0] = user;
+
+    let (gone, _) = scim::handle(&call("GET", "/Users/u-9", ""), &users, 0, "-");
+    assert_eq!(gone.status, 404);
+    let (deleted, change) = scim::handle(&call("DELETE", "/Users/u-1", ""), &users, 0, "-");
+    assert_eq!((deleted.status, deleted.body, change), (204, None, Some(scim::Change::Delete("u-1".to_string()))));
+
+    // Group members are added and removed by value filter
+    let group = call("POST", "/Groups", r#"{"displayName":"Admins","members":[{"value":"u-1"},{"value":"u-2"}]}"#);
+    let Some(scim::Change::Put(admins)) = scim::handle(&group, &[], 0, "g-1").1 else { panic!("expected a write") };
+    let remove = r#"{"schemas":["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+        "Operations":[{"op":"remove","path":"members[value eq \"u-1\"]"}]}"#;
+    let patch = call("PATCH", "/Groups/g-1", remove);
+    let Some(scim::Change::Put(admins)) = scim::handle(&patch, &[admins], 0, "-").1 else { panic!("expected a write") };
+    assert_eq!(admins.attributes["members"], serde_json::json!([{ "value": "u-2" }]));
+
+    let config = scim::handle(&call("GET", "/ServiceProviderConfig", ""), &[], 0, "-").0;
+    assert_eq!(config.body.unwrap()["patch"]["supported"], true);
+    let bulk = call("POST", "/Bulk", "{}");
+    assert_eq!(bulk.endpoint, Endpoint::Unknown);
+    let (response, headers) = scim::handle(&bulk, &[], 0, "-").0.into_response();
+    assert_eq!((response.status, response.content_type.as_deref()), (404, Some(scim::CONTENT_TYPE)));
+    assert!(headers.is_empty());
+    let other = Url::parse(&format!("https://hooks.example.com/w/{}/upload/a.txt", UUID)).unwrap();
+    assert!(ScimRequest::parse(&other, UUID, "GET", "").is_none());
+}
+
+#[test]
+fn headers_are_sanitized_before_storage() {
+    let raw = [
+        ("X-Delivery", "a"),
+        ("x-delivery", "b"),
+        ("Connection", "keep-alive, X-Hop"),
+        ("x-hop", "1"),
+        ("Transfer-Encoding", "chunked"),
+        ("Content-Type", " application/json "),
+    ]
+    .map(|(name, value)| (name.to_string(), value.to_string()));
+
+    let headers = pipeline::sanitize_headers(raw, &HeaderLimits::default()).unwrap();
+
+    assert_eq!(
+        headers,
+        HashMap::from([
+            ("x-delivery".to_string(), "a, b".to_string()),
+            ("content-type".to_string(), "application/json".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn oversized_header_sets_are_refused() {
+    let limits = HeaderLimits { max_count: 3, max_bytes: 64 };
+    let headers = |count: usize, value: &str| -> Vec<(String, String)> {
+        (0..count).map(|i| (format!("x-{}", i), value.to_string())).collect()
+    };
+
+    let status = |raw| pipeline::sanitize_headers(raw, &limits).err().map(|rejection| rejection.status);
+
+    assert_eq!(status(headers(3, "ok")), None);
+    assert_eq!(status(headers(4, "ok")), Some(431));
+    assert_eq!(status(headers(1, &"x".repeat(64))), Some(431));
+}
+
+#[test]
+fn grpc_web_trailer_frames_are_stored_separately() {
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+
+    let frame = |flag: u8, payload: &[u8]| {
+        let mut frame = vec![flag];
+        frame.extend((payload.len() as u32).to_be_bytes());
+        frame.extend(payload);
+        BASE64.encode(frame)
+    };
+    let body = frame(0x00, b"\x0a\x02hi") + &frame(0x80, b"grpc-status: 0\r\nGrpc-Message: OK\r\nx-trace: t1\r\n");
+    let grpc = request("POST", &capture_url(""), &[("content-type", "application/grpc-web-text+proto")], Some(&body));
+    let json = request("POST", &capture_url(""), &[("content-type", "application/json")], Some(&body));
+
+    let parsed = pipeline::parse(&grpc).unwrap();
+    let trailers: HashMap<String, String> = serde_json::from_str(parsed.trailers.as_deref().unwrap()).unwrap();
+
+    assert_eq!(parsed.data, body, "the body keeps the trailer frame for replays");
+    assert_eq!(trailers["grpc-status"], "0");
+    assert_eq!(trailers["grpc-message"], "OK");
+    assert_eq!(trailers["x-trace"], "t1");
+    assert_eq!(pipeline::parse(&json).unwrap().trailers, None);
+}
+
+#[test]
+fn socket_messages_record_frame_and_connection() {
+    let settings = settings();
+    let frame = IncomingFrame {
+        connection_id: "conn_1".to_string(),
+        method: pipeline::SOCKET_METHOD.to_string(),
+        headers: HashMap::from([("user-agent".to_string(), "pusher/1.0".to_string())]),
+        frame_type: FrameType::Text,
+        data: r#"{"type":"invoice.paid"}"#.to_string(),
+        received_at_ms: NOW_MS,
+    };
+
+    let mut parsed = pipeline::parse_frame(&frame).unwrap();
+    let applied = pipeline::apply(&mut parsed, UUID, &settings);
+    let record = pipeline::into_record(
+        parsed,
+        CaptureMeta {
+            id: "cap_1".to_string(),
+            webhook_id: "wh_1".to_string(),
+            sequence: None,
+            verification: None,
+            environment: applied.environment,
+        },
+    );
+
+    assert_eq!(record.method, pipeline::SOCKET_METHOD);
+    assert_eq!(record.data, frame.data);
+    assert_eq!(record.connection_id.as_deref(), Some("conn_1"));
+    assert_eq!(record.frame_type.as_deref(), Some("text"));
+    assert_eq!(record.indexed_headers.user_agent.as_deref(), Some("pusher/1.0"));
+    assert_eq!(record.indexed_headers.event_type.as_deref(), Some("invoice.paid"));
+}
+
+#[test]
+fn unreadable_bodies_store_an_empty_object() {
+    let parsed = pipeline::parse(&request("PUT", &capture_url(""), &[], None)).unwrap();
+
+    assert_eq!(parsed.data, "{}");
+}
+
+#[test]
+fn get_requests_store_query_params_without_url_signature() {
+    let incoming = request("GET", &capture_url("?order=42&exp=1900000000&sig=abcd"), &[], None);
+
+    let parsed = pipeline::parse(&incoming).unwrap();
+    let data: HashMap<String, String> = serde_json::from_str(&parsed.data).unwrap();
+
+    assert_eq!(data, HashMap::from([("order".to_string(), "42".to_string())]));
+}
+
+#[test]
+fn settings_override_extraction_and_pick_route_and_environment() {
+    let settings = settings();
+    let incoming = request(
+        "POST",
+        &capture_url(""),
+        &[("x-event-type", "ignored")],
+        Some(r#"{"type":"customer.created"}"#),
+    );
+    let mut parsed = pipeline::parse(&incoming).unwrap();
+
+    let applied = pipeline::apply(&mut parsed, STAGING_UUID, &settings);
+
+    assert_eq!(parsed.indexed_headers.event_type.as_deref(), Some("customer.created"));
+    assert_eq!(applied.environment.map(|environment| environment.name.as_str()), Some("staging"));
+    assert_eq!(applied.route.map(|route| route.event_type.as_str()), Some("customer.*"));
+    assert_eq!(applied.forward_targets(), ["https://staging.example.com/hooks"], "duplicate targets collapse");
+}
+
+#[test]
+fn unmatched_events_have_no_route() {
+    let settings = settings();
+    let mut parsed = pipeline::parse(&request("POST", &capture_url(""), &[], Some(r#"{"type":"charge"}"#))).unwrap();
+
+    let applied = pipeline::apply(&mut parsed, UUID, &settings);
+
+    assert!(applied.environment.is_none());
+    assert!(applied.route.is_none());
+    assert!(applied.forward_targets().is_empty());
+}
+
+#[test]
+fn signed_urls_are_checked() {
+    let mut settings = settings();
+    let now = NOW_MS / 1000;
+    let sig = sign("url-secret", UUID, now + 60);
+    let check = |query: &str, settings: &WebhookSettings| {
+        pipeline::check_signed_url(&Url::parse(&capture_url(query)).unwrap(), UUID, settings, now)
+            .map(|rejection| rejection.status)
+    };
+
+    assert_eq!(check(&format!("?exp={}&sig={}", now + 60, sig), &settings), None);
+    assert_eq!(check(&format!("?exp={}&sig={}", now + 61, sig), &settings), Some(403));
+    assert_eq!(check(&format!("?sig={}", sig), &settings), Some(400));
+    assert_eq!(check("", &settings), None);
+    settings.config.require_signed_urls = true;
+    assert_eq!(check("", &settings), Some(403));
+
+    let expired = sign("url-secret", UUID, now - 1);
+    assert_eq!(check(&format!("?exp={}&sig={}", now - 1, expired), &settings), Some(410));
+}
+
+#[test]
+fn upload_urls_are_bound_to_the_file() {
+    let settings = settings();
+    let now = NOW_MS / 1000;
+    let check = |filename: &str, query: String| {
+        let url = format!("https://hooks.example.com/w/{}/upload/{}{}", UUID, filename, query);
+        pipeline::check_upload_url(&Url::parse(&url).unwrap(), UUID, filename, &settings, now)
+            .map(|rejection| rejection.status)
+    };
+    let sig = sign_upload("url-secret", UUID, "orders.csv", now + 60);
+
+    assert_eq!(check("orders.csv", format!("?exp={}&sig={}", now + 60, sig)), None);
+    assert_eq!(check("refunds.csv", format!("?exp={}&sig={}", now + 60, sig)), Some(403));
+    assert_eq!(check("orders.csv", String::new()), Some(403));
+    let capture_sig = sign("url-secret", UUID, now + 60);
+    assert_eq!(check("orders.csv", format!("?exp={}&sig={}", now + 60, capture_sig)), Some(403));
+    let expired = sign_upload("url-secret", UUID, "orders.csv", now - 1);
+    assert_eq!(check("orders.csv", format!("?exp={}&sig={}", now - 1, expired)), Some(410));
+}
+
+#[test]
+fn uploads_are_captured_as_puts() {
+    assert!(pipeline::valid_upload_filename("orders_2026-05.csv"));
+    assert!(!pipeline::valid_upload_filename(".env"));
+    assert!(!pipeline::valid_upload_filename("a/b.csv"));
+    assert!(!pipeline::valid_upload_filename(""));
+
+    let url = Url::parse(&format!("https://hooks.example.com/w/{}/upload/orders.csv", UUID)).unwrap();
+    let headers = HashMap::from([("content-type".to_string(), "text/csv".to_string())]);
+    let key = "uploads/wh_1/01J/orders.csv";
+    let csv = pipeline::upload_request(url.clone(), headers.clone(), "orders.csv", key, b"id,total\n1,10\n", NOW_MS);
+    let parsed = pipeline::parse(&csv).unwrap();
+
+    assert_eq!(parsed.method, "PUT");
+    assert_eq!(parsed.data, "id,total\n1,10\n");
+    assert_eq!(parsed.headers["upload-key"], key);
+    assert_eq!(parsed.headers["upload-filename"], "orders.csv");
+    assert_eq!(parsed.headers["upload-size"], "14");
+
+    let binary = pipeline::upload_request(url, headers, "orders.csv", key, &[0xff, 0xfe, 0x00], NOW_MS);
+    let parsed = pipeline::parse(&binary).unwrap();
+    assert_eq!(parsed.data, "");
+    assert_eq!(parsed.headers["upload-size"], "3");
+}
+
+#[test]
+fn enforced_signatures_reject_failures_only() {
+    let body = r#"{"id":"evt_1"}"#;
+    let timestamp = NOW_MS / 1000;
+    let header = format!("t={},v1={}", timestamp, hmac_hex(&format!("{}.{}", timestamp, body)));
+    let mut settings = settings();
+    settings.config.signature = Some(SignatureConfig {
+        provider: SignatureProvider::Stripe,
+        secret: SECRET.to_string(),
+        tolerance_seconds: 300,
+        enforce: true,
+        previous_secret: None,
+        previous_expires_at_ms: None,
+        hmac: None,
+        paypal: None,
+    });
+    let config = settings.config.signature.clone().unwrap();
+    let url = Url::parse(&capture_url("")).unwrap();
+    let verify = |header: &str, now: i64| {
+        let headers = HashMap::from([("stripe-signature".to_string(), header.to_string())]);
+        verify_with_secret(SECRET, &config, &url, &headers, body, now)
+    };
+
+    let valid = verify(&header, timestamp);
+    let replayed = verify(&header, timestamp + 301);
+    let forged = verify(&format!("t={},v1={}", timestamp, hmac_hex("other")), timestamp);
+
+    assert_eq!(valid, Verification::Valid);
+    assert_eq!(replayed, Verification::ReplaySuspected);
+    assert_eq!(forged, Verification::Invalid);
+    assert_eq!(pipeline::signature_rejection(&settings, Some(valid)), None);
+    assert_eq!(pipeline::signature_rejection(&settings, Some(replayed)).map(|r| r.status), Some(400));
+    assert_eq!(pipeline::signature_rejection(&settings, Some(forged)).map(|r| r.status), Some(401));
+
+    settings.config.signature.as_mut().unwrap().enforce = false;
+    assert_eq!(pipeline::signature_rejection(&settings, Some(forged)), None);
+}
+
+#[test]
+fn rotated_secrets_verify_until_they_expire() {
+    let body = r#"{"id":"evt_1"}"#;
+    let timestamp = NOW_MS / 1000;
+    let header = format!("t={},v1={}", timestamp, hmac_hex(&format!("{}.{}", timestamp, body)));
+    let headers = HashMap::from([("stripe-signature".to_string(), header)]);
+    let url = Url::parse(&capture_url("")).unwrap();
+    let mut config = SignatureConfig {
+        provider: SignatureProvider::Stripe,
+        secret: SECRET.to_string(),
+        tolerance_seconds: 300,
+        enforce: true,
+        previous_secret: None,
+        previous_expires_at_ms: None,
+        hmac: None,
+        paypal: None,
+    };
+
+    config.rotate("whsec_next".to_string(), 3600, NOW_MS);
+    let during = config.active_secrets(NOW_MS + 1000).iter().map(|secret| secret.to_string()).collect::<Vec<_>>();
+    let after = config.active_secrets(NOW_MS + 3_600_000).iter().map(|secret| secret.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(config.secret, "whsec_next");
+    assert_eq!(config.previous_secret.as_deref(), Some(SECRET));
+    assert_eq!(during, vec!["whsec_next".to_string(), SECRET.to_string()]);
+    assert_eq!(after, vec!["whsec_next".to_string()]);
+    assert_eq!(verify_with_secrets(&during, &config, &url, &headers, body, timestamp), Verification::Valid);
+    assert_eq!(verify_with_secrets(&after, &config, &url, &headers, body, timestamp), Verification::Invalid);
+
+    let redacted = WebhookConfig { signature: Some(config.clone()), ..WebhookConfig::default() }.redacted();
+    assert_eq!(redacted.signature.unwrap().previous_secret.as_deref(), Some("********"));
+
+    config.rotate("whsec_third".to_string(), 0, NOW_MS);
+    assert_eq!(config.previous_secret, None);
+    assert_eq!(config.active_secrets(NOW_MS), vec!["whsec_third"]);
+}
+
+#[test]
+fn every_provider_has_a_registered_verifier() {
+    let providers: Vec<SignatureProvider> = signature_providers().iter().map(|info| info.provider).collect();
+    for provider in [
+        SignatureProvider::Stripe,
+        SignatureProvider::Slack,
+        SignatureProvider::Github,
+        SignatureProvider::Shopify,
+        SignatureProvider::Twilio,
+        SignatureProvider::Svix,
+        SignatureProvider::StandardWebhooks,
+        SignatureProvider::Paypal,
+        SignatureProvider::Adyen,
+        SignatureProvider::Square,
+        SignatureProvider::Hmac,
+    ] {
+        assert_eq!(providers.iter().filter(|registered| **registered == provider).count(), 1, "{:?}", provider);
+    }
+}
+
+#[test]
+fn signed_forwards_follow_standard_webhooks() {
+    let secret = "whsec_MfKQ9r8GKYqrTwjUPD8ILPZIo2LaLaSw";
+    let body = r#"{"type":"user.created"}"#;
+    let timestamp = NOW_MS / 1000;
+    let url = Url::parse("https://downstream.example.com/hooks").unwrap();
+    let config = SignatureConfig {
+        provider: SignatureProvider::StandardWebhooks,
+        secret: secret.to_string(),
+        tolerance_seconds: 300,
+        enforce: true,
+        previous_secret: None,
+        previous_expires_at_ms: None,
+        hmac: None,
+        paypal: None,
+    };
+
+    let signer = Signer { secret: secret.to_string(), id: "01JCAPTURE".to_string(), retries: 2 };
+    let inbound = HashMap::from([
+        ("content-type".to_string(), "application/json".to_string()),
+        ("webhook-id".to_string(), "msg_upstream".to_string()),
+    ]);
+    let headers = signed_headers(&inbound, &signer, timestamp, body);
+    assert_eq!(headers["webhook-id"], "01JCAPTURE", "the capture ID names the message");
+    assert_eq!(headers["webhook-timestamp"], timestamp.to_string());
+    assert_eq!(headers["webhook-signature"], sign_standard_webhook(secret, "01JCAPTURE", timestamp, body));
+    assert!(headers["webhook-signature"].starts_with("v1,"));
+    assert_eq!(headers["content-type"], "application/json");
+
+    // The receiving side of the spec accepts what the sending side produced
+    assert_eq!(verify_with_secret(secret, &config, &url, &headers, body, timestamp), Verification::Valid);
+    assert_eq!(verify_with_secret(secret, &config, &url, &headers, "{}", timestamp), Verification::Invalid);
+    assert_eq!(verify_with_secret(secret, &config, &url, &inbound, body, timestamp), Verification::Missing);
+
+    let answered = |status| ForwardOutcome { status, ..ForwardOutcome::default() };
+    assert!(is_retryable(&answered(None)), "no answer");
+    assert!(is_retryable(&answered(Some(503))));
+    assert!(is_retryable(&answered(Some(429))));
+    assert!(!is_retryable(&answered(Some(204))));
+    assert!(!is_retryable(&answered(Some(400))), "the target refused the message itself");
+
+    let too_many = WebhookConfig {
+        forward_signing: Some(ForwardSigning { secret: secret.to_string(), retries: 9 }),
+        ..WebhookConfig::default()
+    };
+    assert!(too_many.validate().is_some());
+}
+
+#[test]
+fn svix_and_generic_hmac_signatures_verify() {
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+
+    let body = r#"{"type":"invoice.paid"}"#;
+    let timestamp = NOW_MS / 1000;
+    let url = Url::parse(&capture_url("")).unwrap();
+    let config = |provider, hmac| SignatureConfig {
+        provider,
+        secret: SECRET.to_string(),
+        tolerance_seconds: 300,
+        enforce: true,
+        previous_secret: None,
+        previous_expires_at_ms: None,
+        hmac,
+        paypal: None,
+    };
+
+    // Svix keys are the base64 part of `whsec_...`
+    let key = b"svix signing key";
+    let secret = format!("whsec_{}", BASE64.encode(key));
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(format!("msg_1.{}.{}", timestamp, body).as_bytes());
+    let signature = BASE64.encode(mac.finalize().into_bytes());
+    let headers = HashMap::from([
+        ("svix-id".to_string(), "msg_1".to_string()),
+        ("svix-timestamp".to_string(), timestamp.to_string()),
+        ("svix-signature".to_string(), format!("v1,bm90IGl0 v1,{}", signature)),
+    ]);
+    let svix = config(SignatureProvider::Svix, None);
+    assert_eq!(verify_with_secret(&secret, &svix, &url, &headers, body, timestamp), Verification::Valid);
+    assert_eq!(verify_with_secret(&secret, &svix, &url, &headers, body, timestamp + 301), Verification::ReplaySuspected);
+    assert_eq!(verify_with_secret(&secret, &svix, &url, &headers, "{}", timestamp), Verification::Invalid);
+
+    let scheme = HmacScheme {
+        header: "X-Signature".to_string(),
+        algorithm: HmacAlgorithm::Sha256,
+        encoding: SignatureEncoding::Hex,
+        prefix: Some("sha256=".to_string()),
+        timestamp_header: None,
+    };
+    let generic = config(SignatureProvider::Hmac, Some(scheme));
+    let headers = HashMap::from([("x-signature".to_string(), format!("sha256={}", hmac_hex(body)))]);
+    assert_eq!(verify_with_secret(SECRET, &generic, &url, &headers, body, timestamp), Verification::Valid);
+    assert_eq!(verify_with_secret(SECRET, &generic, &url, &HashMap::new(), body, timestamp), Verification::Missing);
+
+    let unconfigured = WebhookConfig {
+        signature: Some(config(SignatureProvider::Hmac, None)),
+        ..WebhookConfig::default()
+    };
+    assert!(unconfigured.validate().is_some_and(|problem| problem.contains("hmac.header")));
+}
+
+#[test]
+fn payment_provider_signatures_verify() {
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+
+    let timestamp = NOW_MS / 1000;
+    let url = Url::parse(&capture_url("")).unwrap();
+    let config = |provider| SignatureConfig {
+        provider,
+        secret: SECRET.to_string(),
+        tolerance_seconds: 300,
+        enforce: true,
+        previous_secret: None,
+        previous_expires_at_ms: None,
+        hmac: None,
+        paypal: None,
+    };
+    let hmac = |key: &[u8], payload: &str| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+        mac.update(payload.as_bytes());
+        BASE64.encode(mac.finalize().into_bytes())
+    };
+
+    // Square signs the notification URL followed by the body
+    let body = r#"{"type":"payment.updated"}"#;
+    let square = config(SignatureProvider::Square);
+    let signed = HashMap::from([(
+        "x-square-hmacsha256-signature".to_string(),
+        hmac(SECRET.as_bytes(), &format!("{}{}", url, body)),
+    )]);
+    assert_eq!(verify_with_secret(SECRET, &square, &url, &signed, body, timestamp), Verification::Valid);
+    let elsewhere = Url::parse("https://other.example.com/hooks").unwrap();
+    assert_eq!(verify_with_secret(SECRET, &square, &elsewhere, &signed, body, timestamp), Verification::Invalid);
+
+    // Adyen signs each notification item's payment fields with a hex key
+    let key_hex = "44782DEF547AAA06C910C43932B1EB0C71FC68D9D0C057550C48EC2ACF6BA056";
+    let key: Vec<u8> = (0..key_hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&key_hex[i..i + 2], 16).unwrap())
+        .collect();
+    let signature = hmac(&key, "7914073381342284::TestMerchant:TestPayment-1407325143704:1130:EUR:AUTHORISATION:true");
+    let item = |signature: &str| {
+        serde_json::json!({ "live": "false", "notificationItems": [{ "NotificationRequestItem": {
+            "additionalData": { "hmacSignature": signature },
+            "amount": { "currency": "EUR", "value": 1130 },
+            "eventCode": "AUTHORISATION",
+            "merchantAccountCode": "TestMerchant",
+            "merchantReference": "TestPayment-1407325143704",
+            "pspReference": "7914073381342284",
+            "success": "true",
+        }}]})
+        .to_string()
+    };
+    let adyen = config(SignatureProvider::Adyen);
+    let none = HashMap::new();
+    assert_eq!(verify_with_secret(key_hex, &adyen, &url, &none, &item(&signature), timestamp), Verification::Valid);
+    let tampered = item(&signature).replace("1130", "9999");
+    assert_eq!(verify_with_secret(key_hex, &adyen, &url, &none, &tampered, timestamp), Verification::Invalid);
+    assert_eq!(verify_with_secret(key_hex, &adyen, &url, &none, body, timestamp), Verification::Missing);
+
+    // PayPal is asked through its API; offline the delivery cannot be decided
+    let app = PaypalApp { client_id: "client".to_string(), webhook_id: "WH-1".to_string(), sandbox: true };
+    let headers: HashMap<String, String> = [
+        ("paypal-auth-algo", "SHA256withRSA"),
+        ("paypal-cert-url", "https://api-m.sandbox.paypal.com/v1/notifications/certs/CERT-1"),
+        ("paypal-transmission-id", "69cd13f0-d67a-11e5-baa3-778b53f4ae55"),
+        ("paypal-transmission-sig", "lmI95Jx3Y9nhR5Sja"),
+        ("paypal-transmission-time", "2025-10-09T08:53:20Z"),
+    ]
+    .iter()
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect();
+    let event = r#"{"id":"WH-EVT","event_type":"PAYMENT.CAPTURE.COMPLETED"}"#;
+    let raw = paypal_verification_body(&app, &headers, event).unwrap();
+    assert!(raw.ends_with(&format!(r#""webhook_event":{}}}"#, event)), "the signed body is embedded as delivered");
+    let request: serde_json::Value = serde_json::from_str(&raw).unwrap();
+    assert_eq!(request["webhook_id"], "WH-1");
+    assert_eq!(request["transmission_id"], "69cd13f0-d67a-11e5-baa3-778b53f4ae55");
+    assert_eq!(request["webhook_event"]["event_type"], "PAYMENT.CAPTURE.COMPLETED");
+    assert_eq!(paypal_transmission_time(&headers), Some(1_760_000_000));
+    assert!(paypal_verification_body(&app, &HashMap::new(), event).is_none());
+    assert!(paypal_verification_body(&app, &headers, "not json").is_none());
+    assert!(paypal_plausible(&headers));
+    let mut forged = headers.clone();
+    forged.insert("paypal-cert-url".to_string(), "https://paypal.com.example.net/certs/CERT-1".to_string());
+    assert!(!paypal_plausible(&forged));
+    let mut forged = headers.clone();
+    forged.insert("paypal-auth-algo".to_string(), "none".to_string());
+    assert!(!paypal_plausible(&forged));
+    let paypal = config(SignatureProvider::Paypal);
+    assert_eq!(verify_with_secret(SECRET, &paypal, &url, &headers, event, timestamp), Verification::Missing);
+    let unconfigured = WebhookConfig { signature: Some(paypal), ..WebhookConfig::default() };
+    assert!(unconfigured.validate().is_some_and(|problem| problem.contains("paypal.webhook_id")));
+}
+
+#[test]
+fn oidc_keys_must_fit_the_token_algorithm() {
+    let keys = vec![
+        serde_json::json!({ "kid": "rsa", "kty": "RSA", "n": "AQAB", "e": "AQAB" }),
+        serde_json::json!({ "kid": "ec", "kty": "EC", "crv": "P-256", "x": "AA", "y": "AA", "use": "sig" }),
+        serde_json::json!({ "kid": "enc", "kty": "RSA", "n": "AQAB", "e": "AQAB", "use": "enc" }),
+        serde_json::json!({ "kid": "ps", "kty": "RSA", "n": "AQAB", "e": "AQAB", "alg": "PS256" }),
+    ];
+    let kid = |alg: &str, kid: Option<&str>| oidc_select_key(&keys, alg, kid).map(|key| key["kid"].clone());
+
+    assert_eq!(kid("RS256", Some("rsa")), Some("rsa".into()));
+    assert_eq!(kid("ES256", Some("ec")), Some("ec".into()));
+    assert_eq!(kid("ES256", None), Some("ec".into()));
+    assert_eq!(kid("ES256", Some("rsa")), None, "an EC token can't name an RSA key");
+    assert_eq!(kid("RS256", Some("ec")), None, "an RSA token can't name an EC key");
+    assert_eq!(kid("RS256", Some("enc")), None, "encryption keys don't verify tokens");
+    assert_eq!(kid("RS256", Some("ps")), None, "a key pinned to another alg is skipped");
+    assert_eq!(kid("RS256", Some("rotated")), None);
+    assert_eq!(kid("HS256", Some("rsa")), None);
+}
+
+#[test]
+fn builds_records_and_success_bodies() {
+    let settings = settings();
+    let incoming = request(
+        "POST",
+        &format!("https://hooks.example.com/w/{}", STAGING_UUID),
+        &[("content-type", "application/json")],
+        Some(r#"{"type":"invoice.paid"}"#),
+    );
+    let mut parsed = pipeline::parse(&incoming).unwrap();
+    let applied = pipeline::apply(&mut parsed, STAGING_UUID, &settings);
+
+    let record = pipeline::into_record(
+        parsed,
+        CaptureMeta {
+            id: "cap_1".to_string(),
+            webhook_id: "wh_1".to_string(),
+            sequence: Some(7),
+            verification: Some(Verification::Valid),
+            environment: applied.environment,
+        },
+    );
+    let body = pipeline::success_body(STAGING_UUID, &record);
+
+    assert_eq!(record.environment.as_deref(), Some("staging"));
+    assert_eq!(record.verification.as_deref(), Some("valid"));
+    assert_eq!(record.indexed_headers.event_type.as_deref(), Some("invoice.paid"));
+    assert_eq!(body["data_id"], "cap_1");
+    assert_eq!(body["sequence"], 7);
+    assert_eq!(body["received_at_ms"], NOW_MS);
+    assert_eq!(body["verification"], "valid");
+}
+
+#[test]
+fn chain_headers_trace_hops_and_stop_loops() {
+    assert_eq!(chain::target_uuid("webhook:7f3c-11aa"), Some("7f3c-11aa"));
+    assert_eq!(chain::target_uuid("webhook:"), None);
+    assert_eq!(chain::target_uuid("webhook:../admin"), None);
+    assert_eq!(chain::target_uuid("https://example.com"), None);
+
+    let first = chain::next(None, "a", "req_1", "b").unwrap();
+    assert_eq!(first, "a/req_1");
+    let second = chain::next(Some(&first), "b", "req_2", "c").unwrap();
+    assert_eq!(second, "a/req_1,b/req_2");
+    assert_eq!(chain::parse(&second)[1].capture_id, "req_2");
+
+    // Back to a webhook already on the chain
+    assert_eq!(chain::next(Some(&second), "c", "req_3", "a"), None);
+    assert_eq!(chain::next(None, "a", "req_1", "a"), None);
+
+    let mut hops: Option<String> = None;
+    for depth in 0..chain::MAX_DEPTH {
+        hops = chain::next(hops.as_deref(), &format!("w{}", depth), "req", "next");
+        assert!(hops.is_some());
+    }
+    assert_eq!(chain::next(hops.as_deref(), "last", "req", "next"), None);
+}
+
+#[test]
+fn hook_scripts_edit_the_record_and_decide_forwarding() {
+    let mut settings = settings();
+    settings.config.script = Some(
+        r#"
+        # Sandbox traffic is relabelled and never forwarded
+        if header("x-env") == "test" and not body("mode") == "live" {
+            set event_type = "sandbox." + body("type")
+            forward none
+        }
+        if event_type starts_with "invoice." {
+            forward "webhook:7f3c-11aa"
+        } else if event_type == "ping" {
+            respond 202 "pong" "text/plain"; stop
+        }
+        remove header("authorization")
+        "#
+        .to_string(),
+    );
+    assert_eq!(settings.config.validate(), None);
+
+    let incoming = request(
+        "POST",
+        &capture_url(""),
+        &[("authorization", "Bearer secret")],
+        Some(r#"{"type":"invoice.paid"}"#),
+    );
+    let mut parsed = pipeline::parse(&incoming).unwrap();
+    let applied = pipeline::apply(&mut parsed, UUID, &settings);
+    assert_eq!(applied.route.map(|route| route.event_type.as_str()), Some("invoice.*"));
+    assert_eq!(applied.forward_targets(), ["https://billing.example.com/hooks", "webhook:7f3c-11aa"]);
+    assert!(!parsed.headers.contains_key("authorization"));
+    assert!(!parsed.headers_json.contains("secret"));
+
+    let incoming = request("POST", &capture_url(""), &[("x-env", "test")], Some(r#"{"type":"invoice.paid"}"#));
+    let mut parsed = pipeline::parse(&incoming).unwrap();
+    let applied = pipeline::apply(&mut parsed, UUID, &settings);
+    assert_eq!(parsed.indexed_headers.event_type.as_deref(), Some("sandbox.invoice.paid"));
+    assert!(applied.route.is_none());
+    assert!(applied.forward_targets().is_empty());
+
+    let incoming = request("POST", &capture_url(""), &[("authorization", "x")], Some(r#"{"type":"ping"}"#));
+    let mut parsed = pipeline::parse(&incoming).unwrap();
+    let applied = pipeline::apply(&mut parsed, UUID, &settings);
+    let response = applied.response().unwrap();
+    assert_eq!((response.status, response.body.as_str()), (202, "pong"));
+    assert!(parsed.headers.contains_key("authorization"), "stop skips the rest");
+}
+
+#[test]
+fn hook_scripts_edit_json_bodies() {
+    let mut settings = settings();
+    settings.config.script = Some(
+        r#"
+        set body("meta.source") = "hook-" + method
+        remove body("card.number")
+        remove body("card.missing")
+        if body("meta.source") == "hook-POST" { set event_type = "edited" }
+        "#
+        .to_string(),
+    );
+    assert_eq!(settings.config.validate(), None);
+
+    let delivered = r#"{"type":"charge","card":{"number":"4242","brand":"visa"}}"#;
+    let incoming = request("POST", &capture_url(""), &[], Some(delivered));
+    let mut parsed = pipeline::parse(&incoming).unwrap();
+    let applied = pipeline::apply(&mut parsed, UUID, &settings);
+    let body: serde_json::Value = serde_json::from_str(&parsed.data).unwrap();
+    assert_eq!(body, serde_json::json!({"type": "charge", "card": {"brand": "visa"}, "meta": {"source": "hook-POST"}}));
+    assert_eq!(parsed.size_bytes as usize, parsed.data.len());
+    assert_eq!(applied.script.original_body.as_deref(), Some(delivered), "signatures check what was delivered");
+    assert_eq!(parsed.indexed_headers.event_type.as_deref(), Some("edited"), "later reads see the edit");
+    assert_eq!(applied.script.trail.body_set, ["meta.source"]);
+    assert_eq!(applied.script.trail.body_removed, ["card.number"]);
+
+    // Bodies that aren't JSON objects are left alone
+    let incoming = request("POST", &capture_url(""), &[], Some("plain text"));
+    let mut parsed = pipeline::parse(&incoming).unwrap();
+    let applied = pipeline::apply(&mut parsed, UUID, &settings);
+    assert_eq!(parsed.data, "plain text");
+    assert_eq!(applied.script.original_body, None);
+    let error = Script::parse("remove body(\"a..b\")").unwrap_err();
+    assert_eq!(error.to_string(), "line 1: body path must not have empty parts");
+}
+
+#[test]
+fn hook_script_errors_name_the_line() {
+    let error = |source: &str| Script::parse(source).unwrap_err().to_string();
+
+    assert_eq!(error("set event_type = \"a\"\nforward body(\"url\")"), "line 2: expected a string");
+    assert_eq!(error("forward \"ftp://example.com\""), "line 1: forward target must be an http(s) URL or webhook:{uuid}");
+    assert_eq!(error("if method == \"POST\" {\n  stop\n"), "line 2: expected '}'");
+    assert_eq!(error("respond 700"), "line 1: expected a status between 100 and 599");
+    assert_eq!(error("launch \"rockets\""), "line 1: unknown statement 'launch'");
+    assert_eq!(error(&"if method { ".repeat(20)), "line 1: nested too deeply");
+    assert!(Script::parse(&" ".repeat(script::MAX_SCRIPT_BYTES + 1)).is_err());
+}
+
+#[test]
+fn hook_scripts_are_parsed_once_per_isolate() {
+    let source = "set event_type = \"cached\"";
+    let first = Script::cached(source).unwrap();
+    let second = Script::cached(source).unwrap();
+
+    assert!(std::rc::Rc::ptr_eq(&first, &second));
+    assert_eq!(*first, Script::parse(source).unwrap());
+    assert!(Script::cached("launch \"rockets\"").is_none());
+}
+
+#[test]
+fn enforced_signature_failures_are_forwarded_nowhere() {
+    let mut settings = settings();
+    settings.config.signature = Some(SignatureConfig {
+        provider: SignatureProvider::Stripe,
+        secret: SECRET.to_string(),
+        tolerance_seconds: 300,
+        enforce: true,
+        previous_secret: None,
+        previous_expires_at_ms: None,
+        hmac: None,
+        paypal: None,
+    });
+    let incoming = request("POST", &capture_url(""), &[], Some(r#"{"type":"invoice.paid"}"#));
+    let mut parsed = pipeline::parse(&incoming).unwrap();
+    let applied = pipeline::apply(&mut parsed, UUID, &settings);
+    let billing = ["https://billing.example.com/hooks"];
+
+    assert_eq!(pipeline::forward_targets(&applied, &settings, Some(Verification::Valid)), billing);
+    for failure in [Verification::Invalid, Verification::Missing, Verification::ReplaySuspected] {
+        assert!(pipeline::forward_targets(&applied, &settings, Some(failure)).is_empty(), "{:?}", failure);
+        let processing = Processing::new(&applied, &settings, None, Some(failure));
+        assert!(processing.forwards.is_empty());
+    }
+    let mut monitored = settings.clone();
+    monitored.config.signature.as_mut().unwrap().enforce = false;
+    assert_eq!(pipeline::forward_targets(&applied, &monitored, Some(Verification::Invalid)), billing);
+}
+
+#[test]
+fn processing_trails_record_the_stages_that_ran() {
+    let mut settings = settings();
+    settings.config.script = Some("remove header(\"authorization\")\nif event_type == \"invoice.void\" { forward none }".to_string());
+    let incoming = request(
+        "POST",
+        &capture_url("?exp=1&sig=ab"),
+        &[("authorization", "Bearer x")],
+        Some(r#"{"type":"invoice.paid"}"#),
+    );
+    let mut parsed = pipeline::parse(&incoming).unwrap();
+    let applied = pipeline::apply(&mut parsed, UUID, &settings);
+
+    let processing = Processing::new(&applied, &settings, Some(SignedUrl::checked(&incoming.url)), None);
+    let trail: serde_json::Value = serde_json::from_str(&processing.to_json()).unwrap();
+    assert_eq!(trail["signed_url"], "verified");
+    assert_eq!(trail["signature"], serde_json::Value::Null);
+    assert_eq!(trail["extraction"]["event_type"], "body:type");
+    assert_eq!(trail["extraction"]["idempotency_key"], "default");
+    assert_eq!(trail["script"]["headers_removed"], serde_json::json!(["authorization"]));
+    assert_eq!(trail["script"]["forward_none"], false);
+    assert_eq!(trail["route"], "invoice.*");
+    assert_eq!(trail["forwards"], serde_json::json!(["https://billing.example.com/hooks"]));
+    assert_eq!(trail["response"], "default");
+
+    settings.config.script = None;
+    let unsigned = request("POST", &capture_url(""), &[], Some("{}"));
+    let mut parsed = pipeline::parse(&unsigned).unwrap();
+    let applied = pipeline::apply(&mut parsed, STAGING_UUID, &settings);
+    let processing = Processing::new(&applied, &settings, Some(SignedUrl::checked(&unsigned.url)), None);
+    assert_eq!(processing.signed_url, Some(SignedUrl::NotRequired));
+    assert_eq!(processing.environment.as_deref(), Some("staging"));
+    assert_eq!(processing.script, None);
+}
+
+#[test]
+fn bodies_are_normalized_to_utf8() {
+    // Declared Latin-1, including a Windows-1252 curly quote
+    let latin1 = charset::decode(b"caf\xe9 \x93hi\x94", Some("text/plain; charset=ISO-8859-1")).unwrap();
+    assert_eq!(latin1.text, "caf\u{e9} \u{201c}hi\u{201d}");
+    assert_eq!((latin1.charset, latin1.transcoded), (Charset::Windows1252, true));
+
+    // A BOM wins over the declared charset
+    let utf16 = charset::decode(b"\xff\xfe{\0}\0", Some("application/json; charset=utf-8")).unwrap();
+    assert_eq!((utf16.text.as_str(), utf16.charset), ("{}", Charset::Utf16Le));
+
+    let plain = charset::decode("{\"name\":\"Zo\u{eb}\"}".as_bytes(), Some("application/json")).unwrap();
+    assert_eq!((plain.charset, plain.transcoded), (Charset::Utf8, false));
+
+    // Undeclared non-UTF-8 text falls back to Windows-1252; binary stays binary
+    let guessed = charset::decode(b"na\xefve", Some("text/csv")).unwrap();
+    assert_eq!((guessed.text.as_str(), guessed.charset), ("na\u{ef}ve", Charset::Windows1252));
+    assert!(charset::decode(b"\x89PNG\r\n\x1a\n", None).is_none());
+    assert!(charset::decode(&[0xde, 0xad, 0xbe, 0xef], Some("application/octet-stream")).is_none());
+}
+
+#[test]
+fn json_bodies_get_a_canonical_copy() {
+    let sent = "{ \"b\": [ {\"z\": 1, \"a\": null} ],\n  \"a\": \"x\" }";
+    let reordered = r#"{"a":"x","b":[{"a":null,"z":1}]}"#;
+    assert_eq!(canonical::canonicalize(sent).as_deref(), Some(reordered));
+    assert_eq!(canonical::canonicalize(reordered), canonical::canonicalize(sent));
+    assert_eq!(canonical::pretty(reordered).unwrap().lines().count(), 9);
+
+    // Scalars, non-JSON and invalid JSON are left alone
+    assert_eq!(canonical::canonicalize("42"), None);
+    assert_eq!(canonical::canonicalize("a=1&b=2"), None);
+    assert_eq!(canonical::canonicalize("{\"a\":"), None);
+
+    let mut parsed = pipeline::parse(&IncomingRequest {
+        method: "POST".to_string(),
+        url: Url::parse("https://hooks.example.com/w/abc").unwrap(),
+        headers: HashMap::new(),
+        body: Some(sent.to_string()),
+        received_at_ms: 1_700_000_000_000,
+    })
+    .unwrap();
+    pipeline::apply(&mut parsed, "abc", &WebhookSettings::default());
+    let record = pipeline::into_record(
+        parsed,
+        CaptureMeta {
+            id: "capture".to_string(),
+            webhook_id: "webhook".to_string(),
+            sequence: None,
+            verification: None,
+            environment: None,
+        },
+    );
+    assert_eq!(record.data, sent);
+    assert_eq!(record.canonical_data.as_deref(), Some(reordered));
+}
+
+#[test]
+fn correlation_ids_are_extracted_where_configured() {
+    let mut settings = settings();
+    let frame = IncomingFrame {
+        connection_id: "conn_1".to_string(),
+        method: pipeline::SOCKET_METHOD.to_string(),
+        headers: HashMap::from([("x-correlation-id".to_string(), " req-42 ".to_string())]),
+        frame_type: FrameType::Text,
+        data: r#"{"type":"invoice.paid","meta":{"trace_id":"trace-7"}}"#.to_string(),
+        received_at_ms: NOW_MS,
+    };
+    let correlation = |settings: &WebhookSettings| {
+        let mut parsed = pipeline::parse_frame(&frame).unwrap();
+        pipeline::apply(&mut parsed, UUID, settings);
+        parsed.indexed_headers.correlation_id
+    };
+
+    // Nothing is extracted unless the webhook says where to look
+    assert_eq!(correlation(&settings), None);
+    settings.config.correlation_id = Some(FieldSource::Header("X-Correlation-Id".to_string()));
+    assert_eq!(correlation(&settings).as_deref(), Some("req-42"));
+    settings.config.correlation_id = Some(FieldSource::Body("meta.trace_id".to_string()));
+    assert_eq!(correlation(&settings).as_deref(), Some("trace-7"));
+
+    let mut parsed = pipeline::parse_frame(&frame).unwrap();
+    let applied = pipeline::apply(&mut parsed, UUID, &settings);
+    let meta = CaptureMeta {
+        id: "cap_1".to_string(),
+        webhook_id: "wh_1".to_string(),
+        sequence: None,
+        verification: None,
+        environment: applied.environment,
+    };
+    let stored = StoredRequest::from(&pipeline::into_record(parsed, meta));
+    assert_eq!(stored.correlation_id.as_deref(), Some("trace-7"));
+}
+
+#[test]
+fn config_versions_diff_by_path_with_secrets_masked() {
+    let signature = SignatureConfig {
+        provider: SignatureProvider::Stripe,
+        secret: SECRET.to_string(),
+        tolerance_seconds: 300,
+        enforce: false,
+        previous_secret: None,
+        previous_expires_at_ms: None,
+        hmac: None,
+        paypal: None,
+    };
+    let snapshot = |version, config| Snapshot {
+        version,
+        config,
+        actor: Some("api_token".to_string()),
+        created_at_ms: Some(NOW_MS),
+    };
+    let first = snapshot(1, WebhookConfig { signature: Some(signature.clone()), ..WebhookConfig::default() });
+    let second = snapshot(
+        2,
+        WebhookConfig {
+            signature: Some(SignatureConfig { secret: "whsec_other".to_string(), tolerance_seconds: 600, ..signature }),
+            retention_days: Some(7),
+            ..WebhookConfig::default()
+        },
+    );
+
+    // A changed secret is masked on both sides, so only the other values show
+    let changes = second.changes(Some(&first));
+    let paths: Vec<&str> = changes.iter().map(|change| change.path.as_str()).collect();
+    assert_eq!(paths, vec!["retention_days", "signature.tolerance_seconds"]);
+    assert_eq!((changes[0].before.clone(), changes[0].after.clone()), (None, Some(serde_json::json!(7))));
+    assert!(!serde_json::to_string(&second.changes(None)).unwrap().contains(SECRET));
+
+    // Unset and null are the same; arrays compare as a whole
+    let before = serde_json::json!({"a": null, "list": [1, 2], "nested": {"x": 1}});
+    let after = serde_json::json!({"list": [1, 3], "nested": {"x": 1, "y": null}});
+    let changes = config_history::diff(&before, &after);
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].path, "list");
+    assert!(config_history::diff(&after, &after).is_empty());
+}
+
+#[test]
+fn simulated_deliveries_show_the_would_be_outcome() {
+    let mut settings = settings();
+    settings.config.script = Some("remove header(\"authorization\")".to_string());
+    let sample: Sample = serde_json::from_value(serde_json::json!({
+        "headers": {"Authorization": "Bearer x", "Content-Type": "application/json"},
+        "body": {"type": "invoice.paid"},
+    }))
+    .unwrap();
+    let url = Url::parse(&capture_url("")).unwrap();
+
+    let outcome = simulate::run(&sample, &url, "wh_1", &settings, NOW_MS).unwrap();
+    assert!(outcome.accepted);
+    let processing = outcome.processing.unwrap();
+    assert_eq!(processing.route.as_deref(), Some("invoice.*"));
+    assert_eq!(processing.forwards, vec!["https://billing.example.com/hooks"]);
+    let capture = outcome.capture.unwrap();
+    assert_eq!((capture.id.as_str(), capture.event_type.as_deref()), (simulate::CAPTURE_ID, Some("invoice.paid")));
+    assert!(!capture.headers.contains("authorization"));
+    assert_eq!(outcome.response.status, 200);
+
+    // Environments are picked by name; a route's response is what the sender would get
+    settings.config.routes[1].response = Some(CustomResponse {
+        status: 202,
+        body: "queued".to_string(),
+        content_type: None,
+        headers: BTreeMap::new(),
+    });
+    let staging = Sample {
+        environment: Some("staging".to_string()),
+        body: Some(serde_json::json!(r#"{"type": "customer.created"}"#)),
+        ..sample.clone()
+    };
+    let outcome = simulate::run(&staging, &url, "wh_1", &settings, NOW_MS).unwrap();
+    assert_eq!(outcome.processing.unwrap().environment.as_deref(), Some("staging"));
+    assert_eq!((outcome.response.status, outcome.response.body.as_str()), (202, "queued"));
+    let unknown = Sample { environment: Some("prod".to_string()), ..sample.clone() };
+    assert!(simulate::run(&unknown, &url, "wh_1", &settings, NOW_MS).is_err());
+
+    // Refusals stop the simulation where ingestion would
+    settings.config.require_signed_urls = true;
+    let outcome = simulate::run(&sample, &url, "wh_1", &settings, NOW_MS).unwrap();
+    assert!(!outcome.accepted && outcome.capture.is_none());
+    assert_eq!(outcome.response.status, 403);
+}
+
+#[test]
+fn fixtures_replay_captures_and_report_unmet_expectations() {
+    let settings = settings();
+    let url = Url::parse(&capture_url("")).unwrap();
+    let sample: Sample = serde_json::from_value(serde_json::json!({
+        "headers": {"Content-Type": "application/json"},
+        "body": {"type": "invoice.paid"},
+    }))
+    .unwrap();
+    let capture = simulate::run(&sample, &url, "wh_1", &settings, NOW_MS).unwrap().capture.unwrap();
+
+    // A saved capture runs again as it arrived
+    let fixture = fixtures::sample_from_capture(&capture);
+    assert_eq!(fixture.method, "POST");
+    let outcome = simulate::run(&fixture, &url, "wh_1", &settings, NOW_MS).unwrap();
+    let expect = Expect {
+        stored: Some(true),
+        forwards: Some(vec!["https://billing.example.com/hooks".to_string()]),
+        status: Some(200),
+        event_type: Some("invoice.paid".to_string()),
+        route: Some("invoice.*".to_string()),
+        ..Expect::default()
+    };
+    assert!(fixtures::check(&expect, &outcome).is_empty());
+
+    // Each unmet expectation is reported with what happened instead
+    let expect = Expect {
+        forwarded: Some(false),
+        status: Some(202),
+        ..expect
+    };
+    let failures = fixtures::check(&expect, &outcome);
+    let fields: Vec<&str> = failures.iter().map(|failure| failure.field.as_str()).collect();
+    assert_eq!(fields, vec!["forwarded", "status"]);
+    assert_eq!(failures[1].actual, serde_json::json!(200));
+
+    // Bodiless captures keep their query parameters
+    let get = Sample {
+        method: "GET".to_string(),
+        body: None,
+        query: Some("b=2&a=1".to_string()),
+        ..sample
+    };
+    let capture = simulate::run(&get, &url, "wh_1", &settings, NOW_MS).unwrap().capture.unwrap();
+    let fixture = fixtures::sample_from_capture(&capture);
+    assert_eq!((fixture.body, fixture.query.as_deref()), (None, Some("a=1&b=2")));
+    assert!(fixtures::is_valid_name("invoice_paid-1") && !fixtures::is_valid_name("no spaces"));
+}
+
+#[test]
+fn dashboard_previews_run_the_worker_logic_on_json() {
+    let body = r#"{"id":"evt_1"}"#;
+    let timestamp = NOW_MS / 1000;
+    let header = format!("t={},v1={}", timestamp, hmac_hex(&format!("{}.{}", timestamp, body)));
+    let preview = |signature: serde_json::Value, secret: Option<&str>| {
+        let preview = serde_json::json!({
+            "signature": signature,
+            "secret": secret,
+            "headers": {"Stripe-Signature": header},
+            "body": body,
+            "now": timestamp,
+        });
+        dashboard::verify_signature(&preview.to_string())
+    };
+    let stripe = serde_json::json!({"provider": "stripe", "secret": SECRET});
+    assert_eq!(preview(stripe, None).as_deref(), Ok("valid"));
+    assert_eq!(preview(serde_json::json!({"provider": "stripe", "secret": "other"}), None).as_deref(), Ok("invalid"));
+    // Worker secrets can't be read client-side; a pasted one is used instead
+    let from_env = serde_json::json!({"provider": "stripe", "secret": "env:STRIPE_SECRET"});
+    assert!(preview(from_env.clone(), None).is_err());
+    assert_eq!(preview(from_env, Some(SECRET)).as_deref(), Ok("valid"));
+
+    let shape = dashboard::infer_shape(r#"{"id": "evt_1", "data": {"amount": 5}}"#).unwrap();
+    assert_eq!(shape, r#"{"data":["object"],"data.amount":["integer"],"id":["string"]}"#);
+    let renamed = dashboard::infer_shape(r#"{"id": 1, "data": {"total": 5}}"#).unwrap();
+    let diff: serde_json::Value = serde_json::from_str(&dashboard::diff_shapes(&shape, &renamed).unwrap()).unwrap();
+    assert_eq!(diff["added"], serde_json::json!(["data.total"]));
+    assert_eq!(diff["removed"], serde_json::json!(["data.amount"]));
+    assert_eq!(diff["type_changed"][0]["path"], "id");
+    assert!(dashboard::infer_shape("\"text\"").is_err());
+
+    let changes = dashboard::diff_json(r#"{"a": {"b": 1}}"#, r#"{"a": {"b": 2}}"#).unwrap();
+    assert_eq!(changes, r#"[{"path":"a.b","before":1,"after":2}]"#);
+}
+
+#[test]
+fn response_rules_pick_the_first_matching_answer_or_the_default() {
+    let mut settings = settings();
+    settings.config.routes[0].response = Some(CustomResponse {
+        status: 202,
+        body: "queued".to_string(),
+        content_type: None,
+        headers: BTreeMap::new(),
+    });
+    let answer = |status: u16, body: &str| CustomResponse {
+        status,
+        body: body.to_string(),
+        content_type: None,
+        headers: BTreeMap::new(),
+    };
+    settings.config.response_rules = Some(ResponseRules {
+        rules: vec![
+            ResponseRule {
+                method: None,
+                event_type: Some("invoice.*".to_string()),
+                when: vec![Condition {
+                    field: FieldSource::Body("data.id".to_string()),
+                    equals: Some("in_missing".to_string()),
+                }],
+                response: CustomResponse {
+                    headers: BTreeMap::from([("Retry-After".to_string(), "30".to_string())]),
+                    ..answer(404, "no such invoice")
+                },
+            },
+            ResponseRule {
+                method: Some("put".to_string()),
+                event_type: None,
+                when: vec![Condition {
+                    field: FieldSource::Header("X-Mode".to_string()),
+                    equals: None,
+                }],
+                response: answer(409, "conflict"),
+            },
+        ],
+        default: Some(answer(200, "ok")),
+    });
+    assert_eq!(settings.config.validate(), None);
+    let url = Url::parse(&capture_url("")).unwrap();
+    let run = |method: &str, headers: &[(&str, &str)], body: &str| {
+        let sample = Sample {
+            method: method.to_string(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            body: Some(serde_json::json!(body)),
+            ..Sample::default()
+        };
+        let outcome = simulate::run(&sample, &url, "wh_1", &settings, NOW_MS).unwrap();
+        (outcome.processing.unwrap().response, outcome.response)
+    };
+
+    // Rules are checked in order and beat the route's response
+    let (kind, response) = run("POST", &[], r#"{"type": "invoice.paid", "data": {"id": "in_missing"}}"#);
+    assert_eq!((kind, response.status, response.body.as_str()), ("rule", 404, "no such invoice"));
+    assert_eq!(response.headers.get("Retry-After").map(String::as_str), Some("30"));
+    let (kind, response) = run("PUT", &[("x-mode", "strict")], r#"{"type": "customer.created"}"#);
+    assert_eq!((kind, response.status), ("rule", 409));
+
+    // Unmatched deliveries get the route's response, then the default
+    let (kind, response) = run("POST", &[], r#"{"type": "invoice.paid", "data": {"id": "in_1"}}"#);
+    assert_eq!((kind, response.status), ("route", 202));
+    let (kind, response) = run("PUT", &[], r#"{"type": "customer.created"}"#);
+    assert_eq!((kind, response.status, response.body.as_str()), ("rule_default", 200, "ok"));
+
+    let rules = settings.config.response_rules.as_mut().unwrap();
+    rules.rules[1].response.headers.insert("Bad Header".to_string(), "x".to_string());
+    let problem = settings.config.validate().unwrap();
+    assert!(problem.starts_with("Invalid response for response rule 1"), "{}", problem);
+}
+
+#[test]
+fn event_times_that_overflow_milliseconds_are_ignored() {
+    let headers = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    };
+
+    assert_eq!(extract_event_time(&headers(&[("webhook-timestamp", "1700000000")])), Some(1_700_000_000_000));
+    assert_eq!(extract_event_time(&headers(&[("webhook-timestamp", &i64::MAX.to_string())])), None);
+    assert_eq!(extract_event_time(&headers(&[("stripe-signature", "t=9223372036854775807,v1=ab")])), None);
+    let fallback = headers(&[("svix-timestamp", "9223372036854776"), ("x-timestamp", "1700000000")]);
+    assert_eq!(extract_event_time(&fallback), Some(1_700_000_000_000));
+}
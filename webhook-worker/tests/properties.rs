@@ -0,0 +1,306 @@
+//! Property tests for the parsing paths that see hostile input: content-type
+//! detection, query parsing, field extraction, signature and signed URL
+//! verification, and secret redaction
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use proptest::prelude::*;
+use sha2::Sha256;
+use std::collections::HashMap;
+use webhook_ingestion::local::*;
+use webhook_ingestion::pipeline::{self, IncomingRequest};
+use worker::Url;
+
+const UUID: &str = "0b9c9a4e-5c55-4d3a-8f5d-3f6b1b2c7d8e";
+
+fn incoming(method: &str, url: Url, headers: HashMap<String, String>, body: Option<String>) -> IncomingRequest {
+    IncomingRequest {
+        method: method.to_string(),
+        url,
+        headers,
+        body,
+        received_at_ms: 1_760_000_000_000,
+    }
+}
+
+fn capture_url() -> Url {
+    Url::parse(&format!("https://hooks.example.com/w/{}", UUID)).unwrap()
+}
+
+fn hmac(secret: &str, payload: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(payload);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn signature_config(provider: SignatureProvider, secret: &str) -> SignatureConfig {
+    SignatureConfig {
+        provider,
+        secret: secret.to_string(),
+        tolerance_seconds: 300,
+        enforce: true,
+        previous_secret: None,
+        previous_expires_at_ms: None,
+        hmac: None,
+        paypal: None,
+    }
+}
+
+fn providers() -> impl Strategy<Value = SignatureProvider> {
+    prop_oneof![
+        Just(SignatureProvider::Stripe),
+        Just(SignatureProvider::Slack),
+        Just(SignatureProvider::Github),
+        Just(SignatureProvider::Shopify),
+        Just(SignatureProvider::Twilio),
+        Just(SignatureProvider::Svix),
+        Just(SignatureProvider::StandardWebhooks),
+        Just(SignatureProvider::Paypal),
+        Just(SignatureProvider::Adyen),
+        Just(SignatureProvider::Square),
+        Just(SignatureProvider::Hmac),
+    ]
+}
+
+/// Header maps mixing arbitrary names with the ones verifiers and extractors read
+fn headers() -> impl Strategy<Value = HashMap<String, String>> {
+    let name = prop_oneof![
+        Just("content-type".to_string()),
+        Just("stripe-signature".to_string()),
+        Just("x-slack-signature".to_string()),
+        Just("x-slack-request-timestamp".to_string()),
+        Just("x-hub-signature-256".to_string()),
+        Just("x-shopify-hmac-sha256".to_string()),
+        Just("x-twilio-signature".to_string()),
+        Just("svix-id".to_string()),
+        Just("svix-timestamp".to_string()),
+        Just("svix-signature".to_string()),
+        Just("webhook-id".to_string()),
+        Just("webhook-timestamp".to_string()),
+        Just("webhook-signature".to_string()),
+        Just("x-square-hmacsha256-signature".to_string()),
+        Just("paypal-transmission-sig".to_string()),
+        Just("x-github-event".to_string()),
+        Just("date".to_string()),
+        "[a-z-]{1,20}",
+    ];
+    proptest::collection::hash_map(name, any::<String>(), 0..12)
+}
+
+proptest! {
+    #[test]
+    fn content_type_is_a_bare_lowercase_media_type(value in any::<String>()) {
+        let headers = HashMap::from([("content-type".to_string(), value)]);
+        let indexed = IndexedHeaders::extract(&headers);
+
+        if let Some(content_type) = indexed.content_type {
+            prop_assert!(!content_type.is_empty());
+            prop_assert!(!content_type.contains(';'));
+            prop_assert_eq!(content_type.trim(), content_type.as_str());
+            prop_assert_eq!(content_type.to_ascii_lowercase(), content_type);
+        }
+    }
+
+    #[test]
+    fn content_type_parameters_are_ignored(media in "[A-Za-z]{1,10}/[A-Za-z+.-]{1,15}", params in ";[ -~]{0,40}") {
+        let with = HashMap::from([("content-type".to_string(), format!("{}{}", media, params))]);
+        let without = HashMap::from([("content-type".to_string(), media.clone())]);
+
+        prop_assert_eq!(IndexedHeaders::extract(&with).content_type, IndexedHeaders::extract(&without).content_type);
+    }
+
+    #[test]
+    fn arbitrary_headers_parse(headers in headers()) {
+        let parsed = pipeline::parse(&incoming("POST", capture_url(), headers.clone(), None)).unwrap();
+        let roundtrip: HashMap<String, String> = serde_json::from_str(&parsed.headers_json).unwrap();
+
+        prop_assert_eq!(roundtrip, headers);
+    }
+
+    #[test]
+    fn bodies_are_stored_verbatim(body in any::<String>(), method in prop_oneof![Just("POST"), Just("PUT"), Just("PATCH")]) {
+        let parsed = pipeline::parse(&incoming(method, capture_url(), HashMap::new(), Some(body.clone()))).unwrap();
+
+        prop_assert_eq!(parsed.size_bytes as usize, body.len());
+        prop_assert_eq!(parsed.data, body);
+    }
+
+    #[test]
+    fn query_strings_parse_to_json_objects(query in any::<String>()) {
+        let mut url = capture_url();
+        url.set_query(Some(&query));
+
+        let parsed = pipeline::parse(&incoming("GET", url, HashMap::new(), None)).unwrap();
+        let data: HashMap<String, String> = serde_json::from_str(&parsed.data).unwrap();
+
+        prop_assert_eq!(parsed.size_bytes as usize, parsed.data.len());
+        prop_assert!(data.len() <= query.split('&').count());
+    }
+
+    #[test]
+    fn url_signature_params_are_never_stored(pairs in proptest::collection::vec(("[a-z]{1,8}", "[ -~]{0,16}"), 0..8)) {
+        let mut url = capture_url();
+        {
+            let mut query = url.query_pairs_mut();
+            for (key, value) in &pairs {
+                query.append_pair(key, value);
+            }
+            query.append_pair("exp", "1900000000").append_pair("sig", "00ff");
+        }
+
+        let parsed = pipeline::parse(&incoming("GET", url, HashMap::new(), None)).unwrap();
+        let data: HashMap<String, String> = serde_json::from_str(&parsed.data).unwrap();
+
+        prop_assert!(!data.contains_key("exp"));
+        prop_assert!(!data.contains_key("sig"));
+    }
+
+    #[test]
+    fn field_extraction_never_panics(path in "[a-z.]{0,20}", body in any::<String>(), headers in headers()) {
+        let body_value = FieldSource::Body(path.clone()).extract(&headers, &body);
+        let header_value = FieldSource::Header(path).extract(&headers, &body);
+
+        prop_assert!(body_value.is_none_or(|value| !value.is_empty()));
+        prop_assert!(header_value.is_none_or(|value| !value.is_empty()));
+    }
+
+    #[test]
+    fn body_paths_find_nested_values(keys in proptest::collection::vec("[a-z]{1,8}", 1..5), leaf in "[ -~]{1,30}") {
+        let document = keys.iter().rev().fold(serde_json::Value::String(leaf.clone()), |value, key| {
+            serde_json::json!({ key.as_str(): value })
+        });
+
+        let extracted = FieldSource::Body(keys.join(".")).extract(&HashMap::new(), &document.to_string());
+
+        prop_assert_eq!(extracted, Some(leaf));
+    }
+
+    #[test]
+    fn arbitrary_signatures_never_verify(
+        provider in providers(),
+        headers in headers(),
+        body in any::<String>(),
+        now in 0i64..4_000_000_000,
+    ) {
+        let config = signature_config(provider, "whsec_property");
+
+        let verification = verify_with_secret("whsec_property", &config, &capture_url(), &headers, &body, now);
+
+        prop_assert_ne!(verification, Verification::Valid);
+    }
+
+    #[test]
+    fn github_signatures_verify_only_the_signed_body(secret in "[ -~]{1,40}", body in any::<String>(), extra in any::<char>()) {
+        let config = signature_config(SignatureProvider::Github, &secret);
+        let signed = |body: &str| {
+            let header = format!("sha256={}", hex(&hmac(&secret, body.as_bytes())));
+            HashMap::from([("x-hub-signature-256".to_string(), header)])
+        };
+        let tampered = format!("{}{}", body, extra);
+
+        let valid = verify_with_secret(&secret, &config, &capture_url(), &signed(&body), &body, 0);
+        let invalid = verify_with_secret(&secret, &config, &capture_url(), &signed(&body), &tampered, 0);
+
+        prop_assert_eq!(valid, Verification::Valid);
+        prop_assert_eq!(invalid, Verification::Invalid);
+    }
+
+    #[test]
+    fn shopify_signatures_verify_only_the_signed_body(secret in "[ -~]{1,40}", body in any::<String>(), extra in any::<char>()) {
+        let config = signature_config(SignatureProvider::Shopify, &secret);
+        let header = BASE64.encode(hmac(&secret, body.as_bytes()));
+        let headers = HashMap::from([("x-shopify-hmac-sha256".to_string(), header)]);
+        let tampered = format!("{}{}", body, extra);
+
+        let valid = verify_with_secret(&secret, &config, &capture_url(), &headers, &body, 0);
+        let invalid = verify_with_secret(&secret, &config, &capture_url(), &headers, &tampered, 0);
+
+        prop_assert_eq!(valid, Verification::Valid);
+        prop_assert_eq!(invalid, Verification::Invalid);
+    }
+
+    #[test]
+    fn stripe_timestamps_outside_tolerance_are_replays(
+        body in any::<String>(),
+        timestamp in 1_000_000_000i64..2_000_000_000,
+        skew in -1_000i64..1_000,
+    ) {
+        let secret = "whsec_property";
+        let config = signature_config(SignatureProvider::Stripe, secret);
+        let signature = hex(&hmac(secret, format!("{}.{}", timestamp, body).as_bytes()));
+        let headers = HashMap::from([("stripe-signature".to_string(), format!("t={},v1={}", timestamp, signature))]);
+
+        let verification = verify_with_secret(secret, &config, &capture_url(), &headers, &body, timestamp + skew);
+
+        let expected = if skew.abs() > 300 { Verification::ReplaySuspected } else { Verification::Valid };
+        prop_assert_eq!(verification, expected);
+    }
+
+    #[test]
+    fn signed_urls_verify_until_expiry(secret in "[ -~]{1,40}", exp in 0i64..4_000_000_000, now in 0i64..4_000_000_000) {
+        let mut settings = WebhookSettings { secret: Some(secret.clone()), ..WebhookSettings::default() };
+        settings.config.require_signed_urls = true;
+        let mut url = capture_url();
+        url.query_pairs_mut()
+            .append_pair("exp", &exp.to_string())
+            .append_pair("sig", &sign(&secret, UUID, exp));
+
+        let rejection = pipeline::check_signed_url(&url, UUID, &settings, now);
+
+        prop_assert_eq!(rejection.map(|rejection| rejection.status), (exp < now).then_some(410));
+    }
+
+    #[test]
+    fn arbitrary_url_signatures_are_rejected(exp in any::<String>(), sig in any::<String>()) {
+        let settings = WebhookSettings { secret: Some("url-secret".to_string()), ..WebhookSettings::default() };
+        let mut url = capture_url();
+        url.query_pairs_mut().append_pair("exp", &exp).append_pair("sig", &sig);
+
+        let rejection = pipeline::check_signed_url(&url, UUID, &settings, 0);
+
+        prop_assert!(rejection.is_some_and(|rejection| matches!(rejection.status, 400 | 403)));
+    }
+
+    #[test]
+    fn redaction_hides_literal_secrets(secret in any::<String>(), provider in providers()) {
+        let config = WebhookConfig {
+            signature: Some(signature_config(provider, &secret)),
+            forward_signing: Some(ForwardSigning { secret: secret.clone(), retries: 2 }),
+            ..WebhookConfig::default()
+        };
+
+        let redacted = config.redacted();
+        let exported = serde_json::to_string(&redacted).unwrap();
+        let redacted_secret = &redacted.signature.as_ref().unwrap().secret;
+        let redacted_signing = &redacted.forward_signing.as_ref().unwrap().secret;
+
+        if secret.starts_with("env:") {
+            prop_assert_eq!(redacted_secret, &secret);
+            prop_assert_eq!(redacted_signing, &secret);
+        } else {
+            prop_assert_eq!(redacted_secret.as_str(), "********");
+            prop_assert_eq!(redacted_signing.as_str(), "********");
+            let leaked = secret.len() >= 4 && exported.contains(&serde_json::to_string(&secret).unwrap());
+            prop_assert!(!leaked);
+        }
+    }
+
+    #[test]
+    fn redacted_secrets_restore_to_the_original(secret in any::<String>(), provider in providers()) {
+        let config = WebhookConfig {
+            signature: Some(signature_config(provider, &secret)),
+            forward_signing: Some(ForwardSigning { secret: secret.clone(), retries: 0 }),
+            ..WebhookConfig::default()
+        };
+
+        let mut restored: WebhookConfig = serde_json::from_str(&serde_json::to_string(&config.redacted()).unwrap()).unwrap();
+        restored.restore_secrets(&config);
+
+        prop_assert_eq!(restored, config);
+    }
+}